@@ -8,6 +8,7 @@
 use std::borrow::Cow;
 use std::ffi::OsStr;
 use std::fs::File;
+use std::io::BufReader;
 use std::io::Cursor;
 use std::io::Read;
 use std::io::Write;
@@ -17,6 +18,8 @@ use std::path::Path;
 use std::path::PathBuf;
 use std::str::FromStr;
 
+#[cfg(feature = "container")]
+use anyhow::Context;
 use argh::FromArgs;
 use cros_codecs::codec::h264::parser::Nalu as H264Nalu;
 use cros_codecs::codec::h265::parser::Nalu as H265Nalu;
@@ -26,17 +29,31 @@ use cros_codecs::decoder::stateless::vp8::Vp8;
 use cros_codecs::decoder::stateless::vp9::Vp9;
 use cros_codecs::decoder::stateless::StatelessDecoder;
 use cros_codecs::decoder::stateless::StatelessVideoDecoder;
+use cros_codecs::decoder::timestamp::TimestampMode;
 use cros_codecs::decoder::BlockingMode;
 use cros_codecs::decoder::DecodedHandle;
 use cros_codecs::decoder::StreamInfo;
 use cros_codecs::multiple_desc_type;
+use cros_codecs::utils::compare::ReferenceComparator;
+#[cfg(feature = "container")]
+use cros_codecs::utils::container::mp4::Codec;
+#[cfg(feature = "container")]
+use cros_codecs::utils::container::mp4::Mp4Reader;
+#[cfg(feature = "container")]
+use cros_codecs::utils::container::ts::TsIterator;
+use cros_codecs::utils::probe::probe;
 use cros_codecs::utils::simple_playback_loop;
+use cros_codecs::utils::simple_playback_loop_memfd_frames;
 use cros_codecs::utils::simple_playback_loop_owned_frames;
-use cros_codecs::utils::simple_playback_loop_userptr_frames;
+use cros_codecs::utils::simple_playback_loop_user_frames;
+use cros_codecs::utils::y4m::Y4mHeader;
+use cros_codecs::utils::y4m::Y4mReader;
+use cros_codecs::utils::y4m::Y4mWriter;
 use cros_codecs::utils::DmabufFrame;
 use cros_codecs::utils::IvfIterator;
+use cros_codecs::utils::MemfdFrame;
 use cros_codecs::utils::NalIterator;
-use cros_codecs::utils::UserPtrFrame;
+use cros_codecs::utils::UserFrame;
 use cros_codecs::DecodedFormat;
 use cros_codecs::Fourcc;
 use cros_codecs::FrameLayout;
@@ -47,13 +64,14 @@ use matroska_demuxer::MatroskaFile;
 
 // Our buffer descriptor type.
 //
-// We support buffers which memory is managed by the backend, or imported from user memory or a
-// PRIME buffer.
+// We support buffers which memory is managed by the backend, or imported from user memory, a
+// memfd or a PRIME buffer.
 multiple_desc_type! {
     enum BufferDescriptor {
         Managed(()),
         Dmabuf(DmabufFrame),
-        User(UserPtrFrame),
+        User(UserFrame),
+        Memfd(MemfdFrame),
     }
 }
 
@@ -139,6 +157,7 @@ enum FrameMemoryType {
     Managed,
     Prime,
     User,
+    Memfd,
 }
 
 impl FromStr for FrameMemoryType {
@@ -149,7 +168,8 @@ impl FromStr for FrameMemoryType {
             "managed" => Ok(FrameMemoryType::Managed),
             "prime" => Ok(FrameMemoryType::Prime),
             "user" => Ok(FrameMemoryType::User),
-            _ => Err("unrecognized memory type. Valid values: managed, prime, user"),
+            "memfd" => Ok(FrameMemoryType::Memfd),
+            _ => Err("unrecognized memory type. Valid values: managed, prime, user, memfd"),
         }
     }
 }
@@ -213,7 +233,8 @@ struct Args {
     #[argh(positional)]
     input: PathBuf,
 
-    /// output file to write the decoded frames to
+    /// output file to write the decoded frames to. Frames are written as Y4M if the file name
+    /// ends with ".y4m", and as raw data otherwise.
     #[argh(option)]
     output: Option<PathBuf>,
 
@@ -229,7 +250,7 @@ struct Args {
     #[argh(option, default = "DecodedFormat::I420")]
     output_format: DecodedFormat,
 
-    /// origin of the memory for decoded buffers (managed, prime or user). Default: managed.
+    /// origin of the memory for decoded buffers (managed, prime, user or memfd). Default: managed.
     #[argh(option, default = "FrameMemoryType::Managed")]
     frame_memory: FrameMemoryType,
 
@@ -245,10 +266,77 @@ struct Args {
     /// frame)
     #[argh(option)]
     compute_md5: Option<Md5Computation>,
+
+    /// whether to display the presentation timestamp and duration of each frame, in units of the
+    /// timescale of the input container, or in microseconds for other inputs
+    #[argh(switch)]
+    print_timestamps: bool,
+
+    /// reference file to compare the decoded frames against, either a Y4M file if its name ends
+    /// with ".y4m", or raw frames of the output format. The differences are displayed for each
+    /// frame that does not match.
+    #[argh(option)]
+    reference: Option<PathBuf>,
+}
+
+/// Iterator over the encoded frames of an input file.
+type FrameIterator<'a> = Box<dyn Iterator<Item = Cow<'a, [u8]>> + 'a>;
+
+/// Timescale of the frame timestamps for inputs that do not have one, i.e. microseconds.
+const DEFAULT_TIMESCALE: u64 = 1_000_000;
+
+/// Returns an iterator over the frames of `input` if it is an MP4 file or an MPEG-2 transport
+/// stream, along with the timescale of the container. For MP4 files, the first video track in
+/// `format` is used.
+#[cfg(feature = "container")]
+fn create_container_frame_iterator(
+    input: &[u8],
+    format: EncodedFormat,
+) -> anyhow::Result<Option<(FrameIterator<'_>, u64)>> {
+    if input.get(4..8) == Some(&b"ftyp"[..]) {
+        let codec = match format {
+            EncodedFormat::H264 => Codec::H264,
+            EncodedFormat::H265 => Codec::H265,
+            EncodedFormat::VP9 => Codec::Vp9,
+            EncodedFormat::VP8 => anyhow::bail!("VP8 is not supported in MP4 files"),
+        };
+
+        let reader = Mp4Reader::new(input).context("error parsing MP4 file")?;
+        let track = reader
+            .tracks()
+            .iter()
+            .find(|track| track.codec() == codec)
+            .ok_or_else(|| anyhow::anyhow!("no {:?} video track in MP4 file", format))?;
+        let packets = reader
+            .packets(track)
+            .collect::<anyhow::Result<Vec<_>>>()
+            .context("error extracting MP4 samples")?;
+
+        Ok(Some((
+            Box::new(packets.into_iter().map(|p| Cow::Owned(p.data))),
+            u64::from(track.timescale),
+        )))
+    } else if input.first() == Some(&0x47) && input.get(188) == Some(&0x47) {
+        // Timestamps of transport streams are expressed in units of a 90 kHz clock.
+        Ok(Some((
+            Box::new(TsIterator::new(input).map(|au| Cow::Owned(au.data))),
+            90_000,
+        )))
+    } else {
+        Ok(None)
+    }
+}
+
+#[cfg(not(feature = "container"))]
+fn create_container_frame_iterator(
+    _: &[u8],
+    _: EncodedFormat,
+) -> anyhow::Result<Option<(FrameIterator<'_>, u64)>> {
+    Ok(None)
 }
 
 /// Detects the container type (IVF or MKV) and returns the corresponding frame iterator.
-fn create_vpx_frame_iterator(input: &[u8]) -> Box<dyn Iterator<Item = Cow<[u8]>> + '_> {
+fn create_vpx_frame_iterator(input: &[u8]) -> FrameIterator<'_> {
     if input.starts_with(&[0x1a, 0x45, 0xdf, 0xa3]) {
         Box::new(MkvFrameIterator::new(input).unwrap().map(Cow::Owned))
     } else {
@@ -301,7 +389,7 @@ fn main() {
     };
 
     let gbm = match args.frame_memory {
-        FrameMemoryType::Managed | FrameMemoryType::User => None,
+        FrameMemoryType::Managed | FrameMemoryType::User | FrameMemoryType::Memfd => None,
         FrameMemoryType::Prime => {
             /// A simple wrapper for a GBM device node.
             pub struct GbmDevice(std::fs::File);
@@ -336,10 +424,21 @@ fn main() {
     };
 
     let display = libva::Display::open().expect("failed to open libva display");
+    let (container_frame_iter, timescale) =
+        match create_container_frame_iterator(&input, args.input_format) {
+            Ok(Some((frame_iter, timescale))) => (Some(frame_iter), timescale),
+            Ok(None) => (None, DEFAULT_TIMESCALE),
+            Err(e) => {
+                eprintln!("error reading input file: {:#}", e);
+                std::process::exit(1);
+            }
+        };
+
     let (mut decoder, frame_iter) = match args.input_format {
         EncodedFormat::H264 => {
-            let frame_iter = Box::new(NalIterator::<H264Nalu<_>>::new(&input).map(Cow::Borrowed))
-                as Box<dyn Iterator<Item = Cow<[u8]>>>;
+            let frame_iter = container_frame_iter.unwrap_or_else(|| {
+                Box::new(NalIterator::<H264Nalu<_>>::new(&input).map(Cow::Borrowed))
+            });
 
             let decoder = Box::new(StatelessDecoder::<H264, _>::new_vaapi(
                 display,
@@ -349,7 +448,8 @@ fn main() {
             (decoder, frame_iter)
         }
         EncodedFormat::VP8 => {
-            let frame_iter = create_vpx_frame_iterator(&input);
+            let frame_iter =
+                container_frame_iter.unwrap_or_else(|| create_vpx_frame_iterator(&input));
 
             let decoder = Box::new(StatelessDecoder::<Vp8, _>::new_vaapi(
                 display,
@@ -359,7 +459,8 @@ fn main() {
             (decoder, frame_iter)
         }
         EncodedFormat::VP9 => {
-            let frame_iter = create_vpx_frame_iterator(&input);
+            let frame_iter =
+                container_frame_iter.unwrap_or_else(|| create_vpx_frame_iterator(&input));
 
             let decoder = Box::new(StatelessDecoder::<Vp9, _>::new_vaapi(
                 display,
@@ -369,8 +470,9 @@ fn main() {
             (decoder, frame_iter)
         }
         EncodedFormat::H265 => {
-            let frame_iter = Box::new(NalIterator::<H265Nalu<_>>::new(&input).map(Cow::Borrowed))
-                as Box<dyn Iterator<Item = Cow<[u8]>>>;
+            let frame_iter = container_frame_iter.unwrap_or_else(|| {
+                Box::new(NalIterator::<H265Nalu<_>>::new(&input).map(Cow::Borrowed))
+            });
 
             let decoder = Box::new(StatelessDecoder::<H265, _>::new_vaapi(
                 display,
@@ -384,9 +486,31 @@ fn main() {
     let mut md5_context = md5::Context::new();
     let mut output_filename_idx = 0;
 
+    // The Y4M header is written along with the first frame, once its size is known. The other
+    // stream parameters are only available if the input can be probed.
+    let write_y4m = !args.multiple_output_files
+        && args
+            .output
+            .as_ref()
+            .is_some_and(|p| p.extension() == Some(OsStr::new("y4m")));
+    let probe_result = probe(&input).ok();
+    let mut y4m_writer: Option<Y4mWriter<File>> = None;
+
+    // Raw reference frames can only be read once their size is known.
+    let mut comparator: Option<ReferenceComparator<BufReader<File>>> = None;
+    let mut mismatched_frames = 0;
+
     let mut on_new_frame = |handle: Box<dyn DecodedHandle<Descriptor = _>>| {
-        if args.output.is_some() || args.compute_md5.is_some() {
+        if args.print_timestamps {
+            match handle.duration() {
+                Some(duration) => println!("pts {} duration {}", handle.timestamp(), duration),
+                None => println!("pts {}", handle.timestamp()),
+            }
+        }
+
+        if args.output.is_some() || args.compute_md5.is_some() || args.reference.is_some() {
             handle.sync().unwrap();
+            let display_resolution = handle.display_resolution();
             let picture = handle.dyn_picture();
             let mut handle = picture.dyn_mappable_handle().unwrap();
             let buffer_size = handle.image_size();
@@ -406,6 +530,26 @@ fn main() {
                 output
                     .write_all(&frame_data)
                     .expect("failed to write to output file");
+            } else if write_y4m {
+                let writer = y4m_writer.get_or_insert_with(|| {
+                    let header = match &probe_result {
+                        Some(result) => Y4mHeader {
+                            size: display_resolution,
+                            ..Y4mHeader::from_probe(result, args.output_format)
+                        },
+                        None => Y4mHeader::new(args.output_format, display_resolution),
+                    };
+                    Y4mWriter::new(output.take().unwrap(), header)
+                        .expect("failed to write Y4M header")
+                });
+                assert_eq!(
+                    writer.header().size,
+                    display_resolution,
+                    "Y4M files cannot change resolution"
+                );
+                writer
+                    .write_frame(&frame_data)
+                    .expect("failed to write to output file");
             } else if let Some(output) = &mut output {
                 output
                     .write_all(&frame_data)
@@ -417,6 +561,30 @@ fn main() {
                 Some(Md5Computation::Frame) => println!("{:x}", md5::compute(&frame_data)),
                 Some(Md5Computation::Stream) => md5_context.consume(&frame_data),
             }
+
+            if let Some(reference) = &args.reference {
+                let comparator = comparator.get_or_insert_with(|| {
+                    let file = BufReader::new(
+                        File::open(reference).expect("error opening reference file"),
+                    );
+                    if reference.extension() == Some(OsStr::new("y4m")) {
+                        ReferenceComparator::from_y4m(
+                            Y4mReader::new(file).expect("error reading Y4M reference header"),
+                        )
+                    } else {
+                        ReferenceComparator::from_raw(file, args.output_format, display_resolution)
+                    }
+                });
+
+                let frame_num = comparator.frames_compared();
+                let metrics = comparator
+                    .compare_next(&frame_data, args.output_format, display_resolution)
+                    .expect("error comparing against reference");
+                if !metrics.is_identical() {
+                    mismatched_frames += 1;
+                    println!("frame {}: {}", frame_num, metrics);
+                }
+            }
         }
     };
 
@@ -440,20 +608,35 @@ fn main() {
                 .into_iter()
                 .map(BufferDescriptor::Dmabuf)
                 .collect(),
-                FrameMemoryType::User => {
-                    simple_playback_loop_userptr_frames(stream_info, nb_frames)?
+                FrameMemoryType::User => simple_playback_loop_user_frames(stream_info, nb_frames)?
+                    .into_iter()
+                    .map(BufferDescriptor::User)
+                    .collect(),
+                FrameMemoryType::Memfd => {
+                    simple_playback_loop_memfd_frames(stream_info, nb_frames)?
                         .into_iter()
-                        .map(BufferDescriptor::User)
+                        .map(BufferDescriptor::Memfd)
                         .collect()
                 }
             })
         },
         args.output_format,
         blocking_mode,
+        // Packets are given in decode order, so the presentation timestamps are derived from the
+        // stream itself.
+        TimestampMode::Synthesize { timescale },
     )
     .expect("error during playback loop");
 
     if let Some(Md5Computation::Stream) = args.compute_md5 {
         println!("{:x}", md5_context.compute());
     }
+
+    if let Some(comparator) = comparator {
+        println!(
+            "{} of {} frames differ from the reference",
+            mismatched_frames,
+            comparator.frames_compared()
+        );
+    }
 }
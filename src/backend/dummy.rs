@@ -13,6 +13,7 @@ use crate::decoder::DecodedHandle;
 use crate::decoder::DynHandle;
 use crate::decoder::FramePool;
 use crate::decoder::MappableHandle;
+use crate::decoder::MappedFrame;
 use crate::decoder::StreamInfo;
 use crate::DecodedFormat;
 use crate::Resolution;
@@ -28,6 +29,10 @@ impl MappableHandle for BackendHandle {
     fn image_size(&mut self) -> usize {
         1
     }
+
+    fn map(&mut self) -> anyhow::Result<MappedFrame<'_>> {
+        Err(anyhow::anyhow!("dummy frames have no data to map"))
+    }
 }
 
 impl<'a> DynHandle for std::cell::Ref<'a, BackendHandle> {
@@ -38,12 +43,14 @@ impl<'a> DynHandle for std::cell::Ref<'a, BackendHandle> {
 
 pub struct Handle {
     pub handle: Rc<RefCell<BackendHandle>>,
+    pub timestamp: u64,
 }
 
 impl Clone for Handle {
     fn clone(&self) -> Self {
         Self {
             handle: Rc::clone(&self.handle),
+            timestamp: self.timestamp,
         }
     }
 }
@@ -60,7 +67,7 @@ impl DecodedHandle for Handle {
     }
 
     fn timestamp(&self) -> u64 {
-        0
+        self.timestamp
     }
 
     fn dyn_picture<'a>(&'a self) -> Box<dyn DynHandle + 'a> {
@@ -126,7 +133,8 @@ impl<M> FramePool<M> for Backend {
 
 impl<FormatInfo> StatelessDecoderBackend<FormatInfo> for Backend {
     type Handle = Handle;
-    type Picture = ();
+    /// Timestamp of the picture.
+    type Picture = u64;
 
     fn try_format(&mut self, _: &FormatInfo, _: DecodedFormat) -> anyhow::Result<()> {
         Ok(())
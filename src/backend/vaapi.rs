@@ -13,8 +13,6 @@ use std::rc::Rc;
 
 use anyhow::anyhow;
 use anyhow::Context as AnyhowContext;
-use byteorder::ByteOrder;
-use byteorder::LittleEndian;
 use libva::Config;
 use libva::Context;
 use libva::Display;
@@ -36,14 +34,19 @@ use crate::decoder::DecodedHandle as DecodedHandleTrait;
 use crate::decoder::DynHandle;
 use crate::decoder::FramePool;
 use crate::decoder::MappableHandle;
+use crate::decoder::MappedFrame;
 use crate::decoder::StreamInfo;
-use crate::i4xx_copy;
-use crate::nv12_copy;
+use crate::utils::convert::convert;
+use crate::utils::convert::Dither;
+use crate::utils::convert::PixelFormat;
 use crate::utils::DmabufFrame;
+use crate::utils::MemfdFrame;
+use crate::utils::UserFrame;
 use crate::utils::UserPtrFrame;
-use crate::y410_to_i410;
 use crate::DecodedFormat;
 use crate::Fourcc;
+use crate::FrameLayout;
+use crate::PlaneLayout;
 use crate::Resolution;
 
 pub(crate) use surface_pool::PooledSurface;
@@ -802,8 +805,6 @@ impl<'a> MappableHandle for Image<'a> {
         let image_inner = self.image();
 
         let display_resolution = self.display_resolution();
-        let width = display_resolution.0 as usize;
-        let height = display_resolution.1 as usize;
 
         if buffer.len() != image_size {
             return Err(anyhow!(
@@ -813,66 +814,31 @@ impl<'a> MappableHandle for Image<'a> {
             ));
         }
 
-        let pitches = image_inner.pitches.map(|x| x as usize);
-        let offsets = image_inner.offsets.map(|x| x as usize);
+        let src_format = PixelFormat::from_fourcc(Fourcc::from(image_inner.format.fourcc))
+            .ok_or(StatelessBackendError::UnsupportedFormat)?;
+        let dst_format = PixelFormat::from(DecodedFormat::try_from(&image_inner.format)?);
+        let resolution = Resolution::from(display_resolution);
+
+        let src_layout = FrameLayout {
+            format: (src_format.fourcc(), 0),
+            size: resolution,
+            planes: (0..image_inner.num_planes as usize)
+                .map(|i| PlaneLayout {
+                    buffer_index: 0,
+                    offset: image_inner.offsets[i] as usize,
+                    stride: image_inner.pitches[i] as usize,
+                })
+                .collect(),
+        };
 
-        match image_inner.format.fourcc {
-            libva::constants::VA_FOURCC_NV12 => {
-                nv12_copy(self.as_ref(), buffer, width, height, pitches, offsets);
-            }
-            libva::constants::VA_FOURCC_I420 => {
-                i4xx_copy(
-                    self.as_ref(),
-                    buffer,
-                    width,
-                    height,
-                    pitches,
-                    offsets,
-                    (true, true),
-                );
-            }
-            libva::constants::VA_FOURCC_422H => {
-                i4xx_copy(
-                    self.as_ref(),
-                    buffer,
-                    width,
-                    height,
-                    pitches,
-                    offsets,
-                    (true, false),
-                );
-            }
-            libva::constants::VA_FOURCC_444P => {
-                i4xx_copy(
-                    self.as_ref(),
-                    buffer,
-                    width,
-                    height,
-                    pitches,
-                    offsets,
-                    (false, false),
-                );
-            }
-            libva::constants::VA_FOURCC_P010 => {
-                p01x_to_i01x(self.as_ref(), buffer, 10, width, height, pitches, offsets);
-            }
-            libva::constants::VA_FOURCC_P012 => {
-                p01x_to_i01x(self.as_ref(), buffer, 12, width, height, pitches, offsets);
-            }
-            libva::constants::VA_FOURCC_Y210 => {
-                y21x_to_i21x(self.as_ref(), buffer, 10, width, height, pitches, offsets);
-            }
-            libva::constants::VA_FOURCC_Y212 => {
-                y21x_to_i21x(self.as_ref(), buffer, 12, width, height, pitches, offsets);
-            }
-            libva::constants::VA_FOURCC_Y410 => {
-                y410_to_i410(self.as_ref(), buffer, width, height, pitches, offsets);
-            }
-            libva::constants::VA_FOURCC_Y412 => {
-                y412_to_i412(self.as_ref(), buffer, width, height, pitches, offsets);
-            }
-            _ => return Err(StatelessBackendError::UnsupportedFormat.into()),
-        }
+        // Images already in the requested format, like NV12 ones, are copied line by line.
+        convert(
+            self.as_ref(),
+            &src_layout,
+            buffer,
+            &dst_format.packed_layout(resolution),
+            Dither::None,
+        )?;
 
         Ok(())
     }
@@ -886,6 +852,24 @@ impl<'a> MappableHandle for Image<'a> {
             display_resolution.1 as usize,
         )
     }
+
+    fn map(&mut self) -> anyhow::Result<MappedFrame<'_>> {
+        let image_inner = self.image();
+        let fourcc = Fourcc::from(image_inner.format.fourcc);
+        let pitches = image_inner.pitches.map(|x| x as usize);
+        let offsets = image_inner.offsets.map(|x| x as usize);
+        // Borrow the data of the image itself rather than `self`, which `AsRef` would resolve
+        // to through its blanket implementation for `&mut T`.
+        let data = <Image as AsRef<[u8]>>::as_ref(self);
+
+        MappedFrame::new(
+            data,
+            fourcc,
+            Resolution::from(self.display_resolution()),
+            &pitches,
+            &offsets,
+        )
+    }
 }
 
 impl TryFrom<&libva::VAImageFormat> for DecodedFormat {
@@ -895,6 +879,8 @@ impl TryFrom<&libva::VAImageFormat> for DecodedFormat {
         match value.fourcc {
             libva::constants::VA_FOURCC_I420 => Ok(DecodedFormat::I420),
             libva::constants::VA_FOURCC_NV12 => Ok(DecodedFormat::NV12),
+            libva::constants::VA_FOURCC_422H => Ok(DecodedFormat::I422),
+            libva::constants::VA_FOURCC_444P => Ok(DecodedFormat::I444),
             libva::constants::VA_FOURCC_P010 => Ok(DecodedFormat::I010),
             libva::constants::VA_FOURCC_P012 => Ok(DecodedFormat::I012),
             libva::constants::VA_FOURCC_Y210 => Ok(DecodedFormat::I210),
@@ -1072,219 +1058,69 @@ where
     }
 }
 
-/// Copies `src` into `dst` removing all padding and converting from biplanar to triplanar format.
-///
-/// `useful_pixels` is the number of useful pixels in each sample, e.g. `10` for `P010`, `12` for
-/// `P012`, etc.
-///
-/// This function is VAAPI-specific because of the unusual the source pixels are laid out: VAAPI
-/// writes the `useful_pixels` MSBs, but software generally expects the LSBs to contain the data.
-fn p01x_to_i01x(
-    src: &[u8],
-    dst: &mut [u8],
-    useful_pixels: usize,
-    width: usize,
-    height: usize,
-    strides: [usize; 3],
-    offsets: [usize; 3],
-) {
-    let sample_shift = 16 - useful_pixels;
-
-    // Copy Y.
-    //
-    // VAAPI's Y samples are two byte little endian with the bottom six bits ignored. We need to
-    // convert that to two byte little endian with top 6 bits ignored.
-
-    let src_y_lines = src[offsets[0]..]
-        .chunks(strides[0])
-        .map(|line| &line[..width * 2]);
-    let dst_y_lines = dst.chunks_mut(width * 2);
-
-    for (src_line, dst_line) in src_y_lines.zip(dst_y_lines).take(height) {
-        for (src_y, dst_y) in src_line.chunks(2).zip(dst_line.chunks_mut(2)) {
-            LittleEndian::write_u16(dst_y, LittleEndian::read_u16(src_y) >> sample_shift);
-        }
+/// Returns the attribute describing a frame with `layout` in user memory, made of `data_size`
+/// bytes spread over `buffers`.
+fn user_ptr_surface_attribute(
+    layout: &FrameLayout,
+    data_size: usize,
+    buffers: &mut [*mut u8],
+) -> libva::VASurfaceAttribExternalBuffers {
+    let mut pitches = [0; 4];
+    let mut offsets = [0; 4];
+    for (i, plane) in layout.planes.iter().take(4).enumerate() {
+        pitches[i] = plane.stride as u32;
+        offsets[i] = plane.offset as u32;
     }
 
-    let dst_u_offset = width * 2 * height;
-
-    // Align width and height to 2 for UV plane.
-    let width = if width % 2 == 1 { width + 1 } else { width };
-    let height = if height % 2 == 1 { height + 1 } else { height };
-    // 1 sample per 4 pixels, but we have two components per line so width remains as-is.
-    let height = height / 2;
-
-    let dst_u_size = width * height;
-
-    // Copy U and V and deinterleave into different planes.
-    //
-    // We need to perform the same bit shift as luma, but also to de-interleave the data.
-    let src_uv_lines = src[offsets[1]..]
-        .chunks(strides[1])
-        .map(|line| &line[..width * 2]);
-    let (dst_u_plane, dst_v_plane) = dst[dst_u_offset..].split_at_mut(dst_u_size);
-    let dst_u_lines = dst_u_plane.chunks_mut(width);
-    let dst_v_lines = dst_v_plane.chunks_mut(width);
-    for (src_line, (dst_u_line, dst_v_line)) in
-        src_uv_lines.zip(dst_u_lines.zip(dst_v_lines)).take(height)
-    {
-        for ((src_u, src_v), (dst_u, dst_v)) in src_line
-            .chunks(4)
-            .map(|chunk| (&chunk[0..2], &chunk[2..4]))
-            .zip(dst_u_line.chunks_mut(2).zip(dst_v_line.chunks_mut(2)))
-        {
-            LittleEndian::write_u16(dst_u, LittleEndian::read_u16(src_u) >> sample_shift);
-            LittleEndian::write_u16(dst_v, LittleEndian::read_u16(src_v) >> sample_shift);
-        }
+    libva::VASurfaceAttribExternalBuffers {
+        pixel_format: layout.format.0.into(),
+        width: layout.size.width,
+        height: layout.size.height,
+        data_size: data_size as u32,
+        num_planes: layout.planes.len() as u32,
+        pitches,
+        offsets,
+        buffers: buffers.as_mut_ptr() as *mut _,
+        num_buffers: buffers.len() as u32,
+        flags: 0,
+        private_data: std::ptr::null_mut(),
     }
 }
 
-/// Copies `src` into `dst` as I21x, removing all padding and changing the layout from packed to
-/// triplanar.
-///
-/// `useful_pixels` is the number of useful pixels in each sample, e.g. `10` for `Y210` or `16` for
-/// `Y216`.
-///
-/// This function is VAAPI-specific because of the unusual the source pixels are laid out: VAAPI
-/// writes the `useful_pixels` MSBs, but software generally expects the LSBs to contain the data.
-///
-/// WARNING: this function could not be tested for lack of supporting hardware.
-fn y21x_to_i21x(
-    src: &[u8],
-    dst: &mut [u8],
-    useful_pixels: usize,
-    width: usize,
-    height: usize,
-    strides: [usize; 3],
-    offsets: [usize; 3],
-) {
-    let sample_shift = 16 - useful_pixels;
-    // Align width to 2 for U and V planes and divide by 2.
-    // This should not be necessary as the sampling method requires that width is a multiple of 2
-    // to begin with.
-    let uv_width = if width % 2 == 1 { width + 1 } else { width } / 2;
-
-    // YUYV representation, i.e. 4 16-bit words per two Y samples meaning we have 4 * width bytes
-    // of data per line.
-    let src_lines = src[offsets[0]..]
-        .chunks(strides[0])
-        .map(|line| &line[..width * 4]);
-
-    let dst_y_size = width * 2 * height;
-    let dst_u_size = uv_width * 2 * height;
-
-    let (dst_y_plane, dst_uv_planes) = dst.split_at_mut(dst_y_size);
-    let (dst_u_plane, dst_v_plane) = dst_uv_planes.split_at_mut(dst_u_size);
-    let dst_y_lines = dst_y_plane.chunks_mut(width * 2);
-    let dst_u_lines = dst_u_plane.chunks_mut(uv_width * 2);
-    let dst_v_lines = dst_v_plane.chunks_mut(uv_width * 2);
-
-    for (src_line, (dst_y_line, (dst_u_line, dst_v_line))) in src_lines
-        .zip(dst_y_lines.zip(dst_u_lines.zip(dst_v_lines)))
-        .take(height)
-    {
-        for (src, (dst_y, (dst_u, dst_v))) in src_line.chunks(8).zip(
-            dst_y_line
-                .chunks_mut(4)
-                .zip(dst_u_line.chunks_mut(2).zip(dst_v_line.chunks_mut(2))),
-        ) {
-            let y0 = LittleEndian::read_u16(&src[0..2]) >> sample_shift;
-            let u = LittleEndian::read_u16(&src[2..4]) >> sample_shift;
-            let y1 = LittleEndian::read_u16(&src[4..6]) >> sample_shift;
-            let v = LittleEndian::read_u16(&src[6..8]) >> sample_shift;
-
-            LittleEndian::write_u16(&mut dst_y[0..2], y0);
-            LittleEndian::write_u16(&mut dst_y[2..4], y1);
-            LittleEndian::write_u16(dst_u, u);
-            LittleEndian::write_u16(dst_v, v);
-        }
+impl libva::ExternalBufferDescriptor for UserPtrFrame {
+    const MEMORY_TYPE: libva::MemoryType = libva::MemoryType::UserPtr;
+    type DescriptorAttribute = libva::VASurfaceAttribExternalBuffers;
+
+    fn va_surface_attribute(&mut self) -> Self::DescriptorAttribute {
+        user_ptr_surface_attribute(&self.layout, self.mem_layout.size(), &mut self.buffers)
     }
 }
 
-/// Copies `src` into `dst` as I412, removing all padding and changing the layout from packed to
-/// triplanar. Also drops the alpha channel.
-///
-/// This function is VAAPI-specific because the samples need to be rolled somehow...
-fn y412_to_i412(
-    src: &[u8],
-    dst: &mut [u8],
-    width: usize,
-    height: usize,
-    strides: [usize; 3],
-    offsets: [usize; 3],
-) {
-    let src_lines = src[offsets[0]..]
-        .chunks(strides[0])
-        .map(|line| &line[..width * 8]);
-
-    let dst_y_size = width * 2 * height;
-    let dst_u_size = width * 2 * height;
-
-    let (dst_y_plane, dst_uv_planes) = dst.split_at_mut(dst_y_size);
-    let (dst_u_plane, dst_v_plane) = dst_uv_planes.split_at_mut(dst_u_size);
-    let dst_y_lines = dst_y_plane.chunks_mut(width * 2);
-    let dst_u_lines = dst_u_plane.chunks_mut(width * 2);
-    let dst_v_lines = dst_v_plane.chunks_mut(width * 2);
-
-    for (src_line, (dst_y_line, (dst_u_line, dst_v_line))) in src_lines
-        .zip(dst_y_lines.zip(dst_u_lines.zip(dst_v_lines)))
-        .take(height)
-    {
-        for (src, (dst_y, (dst_u, dst_v))) in src_line.chunks(8).zip(
-            dst_y_line
-                .chunks_mut(2)
-                .zip(dst_u_line.chunks_mut(2).zip(dst_v_line.chunks_mut(2))),
-        ) {
-            let y = LittleEndian::read_u16(&src[2..4]);
-            let u = LittleEndian::read_u16(&src[0..2]);
-            let v = LittleEndian::read_u16(&src[4..6]);
-            // Why is that rotate_right neeed??
-            LittleEndian::write_u16(dst_y, y.rotate_right(4));
-            LittleEndian::write_u16(dst_u, u.rotate_right(4));
-            LittleEndian::write_u16(dst_v, v.rotate_right(4));
-        }
+impl libva::ExternalBufferDescriptor for UserFrame {
+    const MEMORY_TYPE: libva::MemoryType = libva::MemoryType::UserPtr;
+    type DescriptorAttribute = libva::VASurfaceAttribExternalBuffers;
+
+    fn va_surface_attribute(&mut self) -> Self::DescriptorAttribute {
+        // The frame is a single buffer, so the pointer to it serves as a one-element array.
+        user_ptr_surface_attribute(
+            &self.buffer.layout,
+            self.mem_layout.size(),
+            std::slice::from_mut(&mut self.buffer.ptr),
+        )
     }
 }
 
-impl libva::ExternalBufferDescriptor for UserPtrFrame {
+/// Memfd frames are imported through their mapping, as VAAPI cannot take a memfd directly.
+impl libva::ExternalBufferDescriptor for MemfdFrame {
     const MEMORY_TYPE: libva::MemoryType = libva::MemoryType::UserPtr;
     type DescriptorAttribute = libva::VASurfaceAttribExternalBuffers;
 
     fn va_surface_attribute(&mut self) -> Self::DescriptorAttribute {
-        let pitches = self
-            .layout
-            .planes
-            .iter()
-            .map(|p| p.stride as u32)
-            .chain(std::iter::repeat(0))
-            .take(4)
-            .collect::<Vec<_>>()
-            .try_into()
-            .unwrap();
-        let offsets = self
-            .layout
-            .planes
-            .iter()
-            .map(|p| p.offset as u32)
-            .chain(std::iter::repeat(0))
-            .take(4)
-            .collect::<Vec<_>>()
-            .try_into()
-            .unwrap();
-
-        libva::VASurfaceAttribExternalBuffers {
-            pixel_format: self.layout.format.0.into(),
-            width: self.layout.size.width,
-            height: self.layout.size.height,
-            data_size: self.mem_layout.size() as u32,
-            num_planes: self.layout.planes.len() as u32,
-            pitches,
-            offsets,
-            buffers: self.buffers.as_mut_ptr() as *mut _,
-            num_buffers: self.buffers.len() as u32,
-            flags: 0,
-            private_data: std::ptr::null_mut(),
-        }
+        user_ptr_surface_attribute(
+            &self.buffer.layout,
+            self.map_size,
+            std::slice::from_mut(&mut self.buffer.ptr),
+        )
     }
 }
 
@@ -5,5 +5,7 @@
 pub mod dpb;
 pub mod nalu;
 pub mod nalu_reader;
+pub mod nalu_writer;
 pub mod parser;
 pub mod picture;
+pub mod synthesizer;
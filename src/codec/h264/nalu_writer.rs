@@ -0,0 +1,230 @@
+// Copyright 2023 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+use std::io::Write;
+
+use anyhow::anyhow;
+
+/// A bit writer for h264 bitstreams, i.e. the counterpart of
+/// [`NaluReader`](crate::codec::h264::nalu_reader::NaluReader). It inserts
+/// emulation-prevention bytes if requested, and can write stop bits.
+pub struct NaluWriter<W: Write> {
+    /// Where the complete bytes are written to.
+    inner: W,
+    /// Bits of the byte currently being written, starting from the MSB.
+    curr_byte: u8,
+    /// Number of bits written into `curr_byte`.
+    num_bits_in_curr_byte: usize,
+    /// Number of consecutive zero bytes written so far, used in epb insertion.
+    num_zero_bytes: usize,
+    /// Whether emulation-prevention bytes should be inserted.
+    ep_enabled: bool,
+    /// Number of bits written so far, not counting the emulation-prevention bytes.
+    num_bits_written: usize,
+}
+
+impl<W: Write> NaluWriter<W> {
+    /// Creates a new writer outputting to `inner`. If `ep_enabled` is set, emulation-prevention
+    /// bytes are inserted as needed so the output can be used as the payload of a NAL unit.
+    pub fn new(inner: W, ep_enabled: bool) -> Self {
+        Self {
+            inner,
+            curr_byte: 0,
+            num_bits_in_curr_byte: 0,
+            num_zero_bytes: 0,
+            ep_enabled,
+            num_bits_written: 0,
+        }
+    }
+
+    /// Write a single bit to the stream.
+    pub fn write_bit(&mut self, bit: bool) -> anyhow::Result<()> {
+        self.write_bits(u32::from(bit), 1)
+    }
+
+    /// Write the `num_bits` LSBs of `value` to the stream, up to 32 bits.
+    pub fn write_bits<U: Into<u64>>(&mut self, value: U, num_bits: usize) -> anyhow::Result<()> {
+        if num_bits > 32 {
+            return Err(anyhow!("Overflow: more than 32 bits written at once"));
+        }
+
+        let value = value.into();
+        if num_bits < 64 && value >> num_bits != 0 {
+            return Err(anyhow!("Value {} does not fit in {} bits", value, num_bits));
+        }
+
+        for i in (0..num_bits).rev() {
+            self.curr_byte |= (((value >> i) & 1) as u8) << (7 - self.num_bits_in_curr_byte);
+            self.num_bits_in_curr_byte += 1;
+
+            if self.num_bits_in_curr_byte == 8 {
+                self.flush_curr_byte()?;
+            }
+        }
+
+        self.num_bits_written += num_bits;
+
+        Ok(())
+    }
+
+    /// Write `value` as an unsigned Exp-Golomb code. Like for the reader, `value` must not exceed
+    /// 2^31 - 1.
+    pub fn write_ue<U: Into<u32>>(&mut self, value: U) -> anyhow::Result<()> {
+        let value = value.into();
+        if value > i32::MAX as u32 {
+            return Err(anyhow!("Value {} cannot be Exp-Golomb coded", value));
+        }
+
+        let value = value + 1;
+        let num_bits = (u32::BITS - value.leading_zeros()) as usize;
+
+        // `num_bits - 1` leading zeros followed by `value + 1` itself.
+        self.write_bits(0u32, num_bits - 1)?;
+        self.write_bits(value, num_bits)
+    }
+
+    /// Write `value` as a signed Exp-Golomb code.
+    pub fn write_se<U: Into<i32>>(&mut self, value: U) -> anyhow::Result<()> {
+        let value = value.into();
+        let abs = u64::from(value.unsigned_abs());
+        let mapped = if value > 0 { 2 * abs - 1 } else { 2 * abs };
+
+        self.write_ue(u32::try_from(mapped)?)
+    }
+
+    /// Write the stop bit and the alignment zero bits ending a RBSP.
+    pub fn write_rbsp_trailing_bits(&mut self) -> anyhow::Result<()> {
+        self.write_bit(true)?;
+        while !self.is_aligned() {
+            self.write_bit(false)?;
+        }
+
+        Ok(())
+    }
+
+    /// Whether the stream is currently at a byte boundary.
+    pub fn is_aligned(&self) -> bool {
+        self.num_bits_in_curr_byte == 0
+    }
+
+    /// Returns the number of bits written so far, not counting the emulation-prevention bytes.
+    pub fn num_bits_written(&self) -> usize {
+        self.num_bits_written
+    }
+
+    /// Returns the underlying writer. The stream must be byte-aligned.
+    pub fn into_inner(self) -> anyhow::Result<W> {
+        if !self.is_aligned() {
+            return Err(anyhow!("Stream is not byte-aligned"));
+        }
+
+        Ok(self.inner)
+    }
+
+    fn flush_curr_byte(&mut self) -> anyhow::Result<()> {
+        let byte = self.curr_byte;
+
+        if self.ep_enabled && self.num_zero_bytes >= 2 && byte <= 0x03 {
+            // Insert an epb to prevent a start code from being emulated.
+            self.inner.write_all(&[0x03])?;
+            self.num_zero_bytes = 0;
+        }
+
+        self.inner.write_all(&[byte])?;
+
+        if byte == 0 {
+            self.num_zero_bytes += 1;
+        } else {
+            self.num_zero_bytes = 0;
+        }
+
+        self.curr_byte = 0;
+        self.num_bits_in_curr_byte = 0;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NaluWriter;
+    use crate::codec::h264::nalu_reader::NaluReader;
+
+    #[test]
+    fn write_bits() {
+        let mut writer = NaluWriter::new(vec![], false);
+        writer.write_bit(false).unwrap();
+        writer.write_bits(0x02u8, 8).unwrap();
+        writer.write_bits(0x2345_6789u32, 31).unwrap();
+        writer.write_rbsp_trailing_bits().unwrap();
+        assert_eq!(writer.num_bits_written(), 48);
+
+        assert_eq!(
+            writer.into_inner().unwrap(),
+            [0x01, 0x23, 0x45, 0x67, 0x89, 0x80]
+        );
+    }
+
+    #[test]
+    fn value_overflow() {
+        let mut writer = NaluWriter::new(vec![], false);
+        assert!(writer.write_bits(0x100u32, 8).is_err());
+        assert!(writer.write_bits(0u64, 33).is_err());
+        assert!(writer.write_ue(1u32 << 31).is_err());
+        assert!(writer.write_se(i32::MIN).is_err());
+
+        writer.write_bit(true).unwrap();
+        assert!(writer.into_inner().is_err());
+    }
+
+    #[test]
+    fn exp_golomb() {
+        const UE: [u32; 8] = [0, 1, 2, 3, 7, 8, 255, i32::MAX as u32];
+        const SE: [i32; 7] = [0, 1, -1, 2, -2, 1000, -1000];
+
+        let mut writer = NaluWriter::new(vec![], true);
+        for ue in UE {
+            writer.write_ue(ue).unwrap();
+        }
+        for se in SE {
+            writer.write_se(se).unwrap();
+        }
+        writer.write_rbsp_trailing_bits().unwrap();
+        let data = writer.into_inner().unwrap();
+
+        // 0 is coded as '1', 1 as '010' and 2 as '011'.
+        assert_eq!(data[0] >> 1, 0b1010011);
+
+        let mut reader = NaluReader::new(&data);
+        for ue in UE {
+            assert_eq!(reader.read_ue::<u32>().unwrap(), ue);
+        }
+        for se in SE {
+            assert_eq!(reader.read_se::<i32>().unwrap(), se);
+        }
+        assert!(!reader.has_more_rsbp_data());
+    }
+
+    #[test]
+    fn emulation_prevention() {
+        const RBSP: [u8; 9] = [0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04];
+
+        let mut writer = NaluWriter::new(vec![], true);
+        for byte in RBSP {
+            writer.write_bits(byte, 8).unwrap();
+        }
+        let data = writer.into_inner().unwrap();
+
+        assert_eq!(
+            data,
+            [0x00, 0x00, 0x03, 0x01, 0x00, 0x00, 0x03, 0x00, 0x00, 0x03, 0x00, 0x04]
+        );
+
+        let mut reader = NaluReader::new(&data);
+        for byte in RBSP {
+            assert_eq!(reader.read_bits::<u8>(8).unwrap(), byte);
+        }
+        assert_eq!(reader.num_epb(), 3);
+    }
+}
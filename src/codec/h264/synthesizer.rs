@@ -0,0 +1,927 @@
+// Copyright 2023 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+//! Serialization of H.264 syntax structures into NAL units.
+//!
+//! This is the counterpart of the [parser](crate::codec::h264::parser): [`Synthesizer`] turns the
+//! structures produced by the parser (or built by an encoder) back into Annex B NAL units.
+
+use std::io::Write;
+
+use anyhow::anyhow;
+
+use crate::codec::h264::nalu_writer::NaluWriter;
+use crate::codec::h264::parser::HrdParams;
+use crate::codec::h264::parser::NaluType;
+use crate::codec::h264::parser::Pps;
+use crate::codec::h264::parser::PredWeightTable;
+use crate::codec::h264::parser::RefPicListModification;
+use crate::codec::h264::parser::SliceHeader;
+use crate::codec::h264::parser::Sps;
+use crate::codec::h264::parser::VuiParams;
+
+/// Profiles for which the SPS contains the chroma format, bit depths and scaling matrices.
+const HIGH_PROFILES: [u8; 13] = [100, 110, 122, 244, 44, 83, 86, 118, 128, 138, 139, 134, 135];
+
+/// Writes the syntax structure `T` as a NAL unit.
+///
+/// The NAL unit is prefixed with a 4 bytes start code, and emulation-prevention bytes are inserted
+/// into its payload if `ep_enabled` is set.
+pub struct Synthesizer<'a, T, W: Write> {
+    writer: NaluWriter<W>,
+    data: &'a T,
+}
+
+impl<'a, T, W: Write> Synthesizer<'a, T, W> {
+    /// Write the start code of the NAL unit into `writer` and prepare to write its content.
+    fn new(mut writer: W, data: &'a T, ep_enabled: bool) -> anyhow::Result<Self> {
+        // Emulation prevention does not apply to the start code.
+        writer.write_all(&[0x00, 0x00, 0x00, 0x01])?;
+
+        Ok(Self {
+            writer: NaluWriter::new(writer, ep_enabled),
+            data,
+        })
+    }
+
+    /// Write the NAL unit header.
+    fn nalu_header(&mut self, ref_idc: u8, nalu_type: NaluType) -> anyhow::Result<()> {
+        // forbidden_zero_bit
+        self.writer.write_bit(false)?;
+        self.writer.write_bits(ref_idc, 2)?;
+        self.writer.write_bits(nalu_type as u8, 5)
+    }
+
+    /// Write `value` using `num_bits` bits, checking that it fits.
+    fn bits<U: Into<u64>>(&mut self, value: U, num_bits: usize) -> anyhow::Result<()> {
+        self.writer.write_bits(value, num_bits)
+    }
+
+    fn bit(&mut self, bit: bool) -> anyhow::Result<()> {
+        self.writer.write_bit(bit)
+    }
+
+    fn ue<U: Into<u32>>(&mut self, value: U) -> anyhow::Result<()> {
+        self.writer.write_ue(value)
+    }
+
+    fn se<U: Into<i32>>(&mut self, value: U) -> anyhow::Result<()> {
+        self.writer.write_se(value)
+    }
+
+    /// Write `scaling_list` (7.3.2.1.1.1). The list is always coded explicitly, with its trailing
+    /// repeated values omitted.
+    fn scaling_list(&mut self, scaling_list: &[u8]) -> anyhow::Result<()> {
+        let mut last_scale = 8u8;
+
+        for (j, &scale) in scaling_list.iter().enumerate() {
+            // A next_scale of 0 repeats the last value until the end of the list, but means that
+            // the default list is in use if found first.
+            if j > 0 && scaling_list[j..].iter().all(|&s| s == last_scale) {
+                self.se(delta_scale(last_scale, 0))?;
+                return Ok(());
+            }
+
+            self.se(delta_scale(last_scale, scale))?;
+            last_scale = scale;
+        }
+
+        Ok(())
+    }
+
+    fn hrd_parameters(&mut self, hrd: &HrdParams) -> anyhow::Result<()> {
+        self.ue(hrd.cpb_cnt_minus1)?;
+        self.bits(hrd.bit_rate_scale, 4)?;
+        self.bits(hrd.cpb_size_scale, 4)?;
+
+        for i in 0..=usize::from(hrd.cpb_cnt_minus1) {
+            self.ue(hrd.bit_rate_value_minus1[i])?;
+            self.ue(hrd.cpb_size_value_minus1[i])?;
+            self.bit(hrd.cbr_flag[i])?;
+        }
+
+        self.bits(hrd.initial_cpb_removal_delay_length_minus1, 5)?;
+        self.bits(hrd.cpb_removal_delay_length_minus1, 5)?;
+        self.bits(hrd.dpb_output_delay_length_minus1, 5)?;
+        self.bits(hrd.time_offset_length, 5)
+    }
+
+    fn vui_parameters(&mut self, vui: &VuiParams) -> anyhow::Result<()> {
+        self.bit(vui.aspect_ratio_info_present_flag)?;
+        if vui.aspect_ratio_info_present_flag {
+            self.bits(vui.aspect_ratio_idc, 8)?;
+            if vui.aspect_ratio_idc == 255 {
+                self.bits(vui.sar_width, 16)?;
+                self.bits(vui.sar_height, 16)?;
+            }
+        }
+
+        self.bit(vui.overscan_info_present_flag)?;
+        if vui.overscan_info_present_flag {
+            self.bit(vui.overscan_appropriate_flag)?;
+        }
+
+        self.bit(vui.video_signal_type_present_flag)?;
+        if vui.video_signal_type_present_flag {
+            self.bits(vui.video_format, 3)?;
+            self.bit(vui.video_full_range_flag)?;
+            self.bit(vui.colour_description_present_flag)?;
+            if vui.colour_description_present_flag {
+                self.bits(vui.colour_primaries, 8)?;
+                self.bits(vui.transfer_characteristics, 8)?;
+                self.bits(vui.matrix_coefficients, 8)?;
+            }
+        }
+
+        self.bit(vui.chroma_loc_info_present_flag)?;
+        if vui.chroma_loc_info_present_flag {
+            self.ue(vui.chroma_sample_loc_type_top_field)?;
+            self.ue(vui.chroma_sample_loc_type_bottom_field)?;
+        }
+
+        self.bit(vui.timing_info_present_flag)?;
+        if vui.timing_info_present_flag {
+            if vui.num_units_in_tick == 0 || vui.time_scale == 0 {
+                return Err(anyhow!(
+                    "num_units_in_tick and time_scale must be greater than 0 (E.2.1)"
+                ));
+            }
+
+            self.bits(vui.num_units_in_tick, 32)?;
+            self.bits(vui.time_scale, 32)?;
+            self.bit(vui.fixed_frame_rate_flag)?;
+        }
+
+        self.bit(vui.nal_hrd_parameters_present_flag)?;
+        if vui.nal_hrd_parameters_present_flag {
+            self.hrd_parameters(&vui.nal_hrd_parameters)?;
+        }
+
+        self.bit(vui.vcl_hrd_parameters_present_flag)?;
+        if vui.vcl_hrd_parameters_present_flag {
+            self.hrd_parameters(&vui.vcl_hrd_parameters)?;
+        }
+
+        if vui.nal_hrd_parameters_present_flag || vui.vcl_hrd_parameters_present_flag {
+            self.bit(vui.low_delay_hrd_flag)?;
+        }
+
+        self.bit(vui.pic_struct_present_flag)?;
+        self.bit(vui.bitstream_restriction_flag)?;
+        if vui.bitstream_restriction_flag {
+            self.bit(vui.motion_vectors_over_pic_boundaries_flag)?;
+            self.ue(vui.max_bytes_per_pic_denom)?;
+            self.ue(vui.max_bits_per_mb_denom)?;
+            self.ue(vui.log2_max_mv_length_horizontal)?;
+            self.ue(vui.log2_max_mv_length_vertical)?;
+            self.ue(vui.max_num_reorder_frames)?;
+            self.ue(vui.max_dec_frame_buffering)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the `delta_scale` going from `last_scale` to `next_scale`, wrapped to the range of the
+/// syntax element (7.4.2.1.1.1).
+fn delta_scale(last_scale: u8, next_scale: u8) -> i32 {
+    let delta = i32::from(next_scale) - i32::from(last_scale);
+    (delta + 128).rem_euclid(256) - 128
+}
+
+impl<'a, W: Write> Synthesizer<'a, Sps, W> {
+    /// Write `sps` as a SPS NAL unit with `nal_ref_idc` equal to `ref_idc` into `writer`.
+    pub fn synthesize(
+        ref_idc: u8,
+        sps: &'a Sps,
+        writer: W,
+        ep_enabled: bool,
+    ) -> anyhow::Result<()> {
+        let mut s = Self::new(writer, sps, ep_enabled)?;
+
+        s.nalu_header(ref_idc, NaluType::Sps)?;
+        s.seq_parameter_set_data()?;
+        s.writer.write_rbsp_trailing_bits()?;
+        s.writer.into_inner()?;
+
+        Ok(())
+    }
+
+    fn seq_parameter_set_data(&mut self) -> anyhow::Result<()> {
+        let sps = self.data;
+
+        self.bits(sps.profile_idc, 8)?;
+        self.bit(sps.constraint_set0_flag)?;
+        self.bit(sps.constraint_set1_flag)?;
+        self.bit(sps.constraint_set2_flag)?;
+        self.bit(sps.constraint_set3_flag)?;
+        self.bit(sps.constraint_set4_flag)?;
+        self.bit(sps.constraint_set5_flag)?;
+        // reserved_zero_2bits
+        self.bits(0u8, 2)?;
+        self.bits(sps.level_idc as u8, 8)?;
+        self.ue(sps.seq_parameter_set_id)?;
+
+        if HIGH_PROFILES.contains(&sps.profile_idc) {
+            self.ue(sps.chroma_format_idc)?;
+            if sps.chroma_format_idc == 3 {
+                self.bit(sps.separate_colour_plane_flag)?;
+            }
+
+            self.ue(sps.bit_depth_luma_minus8)?;
+            self.ue(sps.bit_depth_chroma_minus8)?;
+            self.bit(sps.qpprime_y_zero_transform_bypass_flag)?;
+            self.bit(sps.seq_scaling_matrix_present_flag)?;
+
+            if sps.seq_scaling_matrix_present_flag {
+                for list in &sps.scaling_lists_4x4 {
+                    // seq_scaling_list_present_flag
+                    self.bit(true)?;
+                    self.scaling_list(list)?;
+                }
+
+                let num_8x8 = if sps.chroma_format_idc != 3 { 2 } else { 6 };
+                for list in &sps.scaling_lists_8x8[..num_8x8] {
+                    self.bit(true)?;
+                    self.scaling_list(list)?;
+                }
+            }
+        } else if sps.chroma_format_idc != 1 {
+            return Err(anyhow!(
+                "Profile {} only supports the 4:2:0 chroma format",
+                sps.profile_idc
+            ));
+        }
+
+        self.ue(sps.log2_max_frame_num_minus4)?;
+        self.ue(sps.pic_order_cnt_type)?;
+
+        if sps.pic_order_cnt_type == 0 {
+            self.ue(sps.log2_max_pic_order_cnt_lsb_minus4)?;
+        } else if sps.pic_order_cnt_type == 1 {
+            self.bit(sps.delta_pic_order_always_zero_flag)?;
+            self.se(sps.offset_for_non_ref_pic)?;
+            self.se(sps.offset_for_top_to_bottom_field)?;
+            self.ue(sps.num_ref_frames_in_pic_order_cnt_cycle)?;
+
+            for offset in
+                &sps.offset_for_ref_frame[..usize::from(sps.num_ref_frames_in_pic_order_cnt_cycle)]
+            {
+                self.se(*offset)?;
+            }
+        }
+
+        self.ue(sps.max_num_ref_frames)?;
+        self.bit(sps.gaps_in_frame_num_value_allowed_flag)?;
+        self.ue(sps.pic_width_in_mbs_minus1)?;
+        self.ue(sps.pic_height_in_map_units_minus1)?;
+        self.bit(sps.frame_mbs_only_flag)?;
+        if !sps.frame_mbs_only_flag {
+            self.bit(sps.mb_adaptive_frame_field_flag)?;
+        }
+
+        self.bit(sps.direct_8x8_inference_flag)?;
+        self.bit(sps.frame_cropping_flag)?;
+        if sps.frame_cropping_flag {
+            self.ue(sps.frame_crop_left_offset)?;
+            self.ue(sps.frame_crop_right_offset)?;
+            self.ue(sps.frame_crop_top_offset)?;
+            self.ue(sps.frame_crop_bottom_offset)?;
+        }
+
+        self.bit(sps.vui_parameters_present_flag)?;
+        if sps.vui_parameters_present_flag {
+            self.vui_parameters(&sps.vui_parameters)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a, W: Write> Synthesizer<'a, Pps, W> {
+    /// Write `pps` as a PPS NAL unit with `nal_ref_idc` equal to `ref_idc` into `writer`.
+    pub fn synthesize(
+        ref_idc: u8,
+        pps: &'a Pps,
+        writer: W,
+        ep_enabled: bool,
+    ) -> anyhow::Result<()> {
+        let mut s = Self::new(writer, pps, ep_enabled)?;
+
+        s.nalu_header(ref_idc, NaluType::Pps)?;
+        s.pic_parameter_set_rbsp()?;
+        s.writer.write_rbsp_trailing_bits()?;
+        s.writer.into_inner()?;
+
+        Ok(())
+    }
+
+    fn pic_parameter_set_rbsp(&mut self) -> anyhow::Result<()> {
+        let pps = self.data;
+        let sps = &pps.sps;
+
+        if pps.num_slice_groups_minus1 > 0 {
+            return Err(anyhow!("Slice groups are not supported"));
+        }
+
+        self.ue(pps.pic_parameter_set_id)?;
+        self.ue(pps.seq_parameter_set_id)?;
+        self.bit(pps.entropy_coding_mode_flag)?;
+        self.bit(pps.bottom_field_pic_order_in_frame_present_flag)?;
+        self.ue(pps.num_slice_groups_minus1)?;
+        self.ue(pps.num_ref_idx_l0_default_active_minus1)?;
+        self.ue(pps.num_ref_idx_l1_default_active_minus1)?;
+        self.bit(pps.weighted_pred_flag)?;
+        self.bits(pps.weighted_bipred_idc, 2)?;
+        self.se(pps.pic_init_qp_minus26)?;
+        self.se(pps.pic_init_qs_minus26)?;
+        self.se(pps.chroma_qp_index_offset)?;
+        self.bit(pps.deblocking_filter_control_present_flag)?;
+        self.bit(pps.constrained_intra_pred_flag)?;
+        self.bit(pps.redundant_pic_cnt_present_flag)?;
+
+        // The trailing syntax elements can be omitted if they have their inferred values.
+        if pps.transform_8x8_mode_flag
+            || pps.pic_scaling_matrix_present_flag
+            || pps.second_chroma_qp_index_offset != pps.chroma_qp_index_offset
+        {
+            self.bit(pps.transform_8x8_mode_flag)?;
+            self.bit(pps.pic_scaling_matrix_present_flag)?;
+
+            if pps.pic_scaling_matrix_present_flag {
+                for list in &pps.scaling_lists_4x4 {
+                    // pic_scaling_list_present_flag
+                    self.bit(true)?;
+                    self.scaling_list(list)?;
+                }
+
+                if pps.transform_8x8_mode_flag {
+                    let num_8x8 = if sps.chroma_format_idc != 3 { 2 } else { 6 };
+                    for list in &pps.scaling_lists_8x8[..num_8x8] {
+                        self.bit(true)?;
+                        self.scaling_list(list)?;
+                    }
+                }
+            }
+
+            self.se(pps.second_chroma_qp_index_offset)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a, W: Write> Synthesizer<'a, SliceHeader, W> {
+    /// Write `header` as the beginning of a slice NAL unit into `writer`. `pps` is the PPS the
+    /// slice refers to, `ref_idc` the `nal_ref_idc` of the NAL unit and `idr` whether it belongs
+    /// to an IDR picture.
+    ///
+    /// The header is followed by RBSP trailing bits, so the NAL unit can be parsed on its own.
+    /// Returns the size in bits of the NAL unit header and slice header, excluding the start code
+    /// and emulation-prevention bytes, i.e. the offset at which the slice data must be inserted.
+    pub fn synthesize(
+        header: &'a SliceHeader,
+        pps: &Pps,
+        ref_idc: u8,
+        idr: bool,
+        writer: W,
+        ep_enabled: bool,
+    ) -> anyhow::Result<usize> {
+        if idr && ref_idc == 0 {
+            return Err(anyhow!("IDR pictures must be reference pictures"));
+        }
+
+        let mut s = Self::new(writer, header, ep_enabled)?;
+
+        let nalu_type = if idr {
+            NaluType::SliceIdr
+        } else {
+            NaluType::Slice
+        };
+        s.nalu_header(ref_idc, nalu_type)?;
+        s.slice_header(pps, ref_idc, idr)?;
+
+        let header_bit_size = s.writer.num_bits_written();
+        s.writer.write_rbsp_trailing_bits()?;
+        s.writer.into_inner()?;
+
+        Ok(header_bit_size)
+    }
+
+    fn slice_header(&mut self, pps: &Pps, ref_idc: u8, idr: bool) -> anyhow::Result<()> {
+        let hdr = self.data;
+        let sps = &pps.sps;
+
+        self.ue(hdr.first_mb_in_slice)?;
+        self.ue(hdr.slice_type as u8)?;
+        self.ue(hdr.pic_parameter_set_id)?;
+
+        if sps.separate_colour_plane_flag {
+            self.bits(hdr.colour_plane_id, 2)?;
+        }
+
+        self.bits(
+            hdr.frame_num,
+            usize::from(sps.log2_max_frame_num_minus4) + 4,
+        )?;
+
+        if !sps.frame_mbs_only_flag {
+            self.bit(hdr.field_pic_flag)?;
+            if hdr.field_pic_flag {
+                self.bit(hdr.bottom_field_flag)?;
+            }
+        }
+
+        if idr {
+            self.ue(hdr.idr_pic_id)?;
+        }
+
+        if sps.pic_order_cnt_type == 0 {
+            self.bits(
+                hdr.pic_order_cnt_lsb,
+                usize::from(sps.log2_max_pic_order_cnt_lsb_minus4) + 4,
+            )?;
+
+            if pps.bottom_field_pic_order_in_frame_present_flag && !hdr.field_pic_flag {
+                self.se(hdr.delta_pic_order_cnt_bottom)?;
+            }
+        }
+
+        if sps.pic_order_cnt_type == 1 && !sps.delta_pic_order_always_zero_flag {
+            self.se(hdr.delta_pic_order_cnt[0])?;
+            if pps.bottom_field_pic_order_in_frame_present_flag && !hdr.field_pic_flag {
+                self.se(hdr.delta_pic_order_cnt[1])?;
+            }
+        }
+
+        if pps.redundant_pic_cnt_present_flag {
+            self.ue(hdr.redundant_pic_cnt)?;
+        }
+
+        if hdr.slice_type.is_b() {
+            self.bit(hdr.direct_spatial_mv_pred_flag)?;
+        }
+
+        if hdr.slice_type.is_p() || hdr.slice_type.is_sp() || hdr.slice_type.is_b() {
+            self.bit(hdr.num_ref_idx_active_override_flag)?;
+            if hdr.num_ref_idx_active_override_flag {
+                self.ue(hdr.num_ref_idx_l0_active_minus1)?;
+                if hdr.slice_type.is_b() {
+                    self.ue(hdr.num_ref_idx_l1_active_minus1)?;
+                }
+            }
+        }
+
+        if !hdr.slice_type.is_i() && !hdr.slice_type.is_si() {
+            self.bit(hdr.ref_pic_list_modification_flag_l0)?;
+            if hdr.ref_pic_list_modification_flag_l0 {
+                self.ref_pic_list_modification(&hdr.ref_pic_list_modification_l0)?;
+            }
+        }
+
+        if hdr.slice_type.is_b() {
+            self.bit(hdr.ref_pic_list_modification_flag_l1)?;
+            if hdr.ref_pic_list_modification_flag_l1 {
+                self.ref_pic_list_modification(&hdr.ref_pic_list_modification_l1)?;
+            }
+        }
+
+        if (pps.weighted_pred_flag && (hdr.slice_type.is_p() || hdr.slice_type.is_sp()))
+            || (pps.weighted_bipred_idc == 1 && hdr.slice_type.is_b())
+        {
+            self.pred_weight_table(sps)?;
+        }
+
+        if ref_idc != 0 {
+            self.dec_ref_pic_marking(idr)?;
+        }
+
+        if pps.entropy_coding_mode_flag && !hdr.slice_type.is_i() && !hdr.slice_type.is_si() {
+            self.ue(hdr.cabac_init_idc)?;
+        }
+
+        self.se(hdr.slice_qp_delta)?;
+
+        if hdr.slice_type.is_sp() || hdr.slice_type.is_si() {
+            if hdr.slice_type.is_sp() {
+                self.bit(hdr.sp_for_switch_flag)?;
+            }
+
+            self.se(hdr.slice_qs_delta)?;
+        }
+
+        if pps.deblocking_filter_control_present_flag {
+            self.ue(hdr.disable_deblocking_filter_idc)?;
+            if hdr.disable_deblocking_filter_idc != 1 {
+                self.se(hdr.slice_alpha_c0_offset_div2)?;
+                self.se(hdr.slice_beta_offset_div2)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn ref_pic_list_modification(
+        &mut self,
+        modifications: &[RefPicListModification],
+    ) -> anyhow::Result<()> {
+        for modification in modifications {
+            match modification.modification_of_pic_nums_idc {
+                0 | 1 => {
+                    self.ue(modification.modification_of_pic_nums_idc)?;
+                    self.ue(modification.abs_diff_pic_num_minus1)?;
+                }
+                2 => {
+                    self.ue(2u8)?;
+                    self.ue(modification.long_term_pic_num)?;
+                }
+                // The list is terminated below.
+                3 => break,
+                idc => return Err(anyhow!("Unsupported modification_of_pic_nums_idc {}", idc)),
+            }
+        }
+
+        // modification_of_pic_nums_idc equal to 3 ends the loop.
+        self.ue(3u8)
+    }
+
+    fn pred_weight_table(&mut self, sps: &Sps) -> anyhow::Result<()> {
+        let hdr = self.data;
+        let pt: &PredWeightTable = &hdr.pred_weight_table;
+
+        self.ue(pt.luma_log2_weight_denom)?;
+        if sps.chroma_array_type != 0 {
+            self.ue(pt.chroma_log2_weight_denom)?;
+        }
+
+        let default_luma_weight = 1i16 << pt.luma_log2_weight_denom;
+        let default_chroma_weight = 1i16 << pt.chroma_log2_weight_denom;
+
+        for i in 0..=usize::from(hdr.num_ref_idx_l0_active_minus1) {
+            // The weights are only coded when they differ from their inferred values.
+            let luma_weight_l0_flag =
+                pt.luma_weight_l0[i] != default_luma_weight || pt.luma_offset_l0[i] != 0;
+            self.bit(luma_weight_l0_flag)?;
+            if luma_weight_l0_flag {
+                self.se(pt.luma_weight_l0[i])?;
+                self.se(pt.luma_offset_l0[i])?;
+            }
+
+            if sps.chroma_array_type != 0 {
+                let chroma_weight_l0_flag = (0..2).any(|j| {
+                    pt.chroma_weight_l0[i][j] != default_chroma_weight
+                        || pt.chroma_offset_l0[i][j] != 0
+                });
+                self.bit(chroma_weight_l0_flag)?;
+                if chroma_weight_l0_flag {
+                    for j in 0..2 {
+                        self.se(pt.chroma_weight_l0[i][j])?;
+                        self.se(pt.chroma_offset_l0[i][j])?;
+                    }
+                }
+            }
+        }
+
+        if hdr.slice_type.is_b() {
+            for i in 0..=usize::from(hdr.num_ref_idx_l1_active_minus1) {
+                let luma_weight_l1_flag =
+                    pt.luma_weight_l1[i] != default_luma_weight || pt.luma_offset_l1[i] != 0;
+                self.bit(luma_weight_l1_flag)?;
+                if luma_weight_l1_flag {
+                    self.se(pt.luma_weight_l1[i])?;
+                    self.se(pt.luma_offset_l1[i])?;
+                }
+
+                if sps.chroma_array_type != 0 {
+                    let chroma_weight_l1_flag = (0..2).any(|j| {
+                        pt.chroma_weight_l1[i][j] != default_chroma_weight
+                            || pt.chroma_offset_l1[i][j] != 0
+                    });
+                    self.bit(chroma_weight_l1_flag)?;
+                    if chroma_weight_l1_flag {
+                        for j in 0..2 {
+                            self.se(pt.chroma_weight_l1[i][j])?;
+                            self.se(pt.chroma_offset_l1[i][j])?;
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn dec_ref_pic_marking(&mut self, idr: bool) -> anyhow::Result<()> {
+        let rpm = &self.data.dec_ref_pic_marking;
+
+        if idr {
+            self.bit(rpm.no_output_of_prior_pics_flag)?;
+            self.bit(rpm.long_term_reference_flag)?;
+            return Ok(());
+        }
+
+        self.bit(rpm.adaptive_ref_pic_marking_mode_flag)?;
+        if !rpm.adaptive_ref_pic_marking_mode_flag {
+            return Ok(());
+        }
+
+        for marking in rpm.inner.iter() {
+            let op = marking.memory_management_control_operation;
+            // The list is terminated below.
+            if op == 0 {
+                break;
+            }
+
+            self.ue(op)?;
+
+            if op == 1 || op == 3 {
+                self.ue(marking.difference_of_pic_nums_minus1)?;
+            }
+
+            if op == 2 {
+                self.ue(marking.long_term_pic_num)?;
+            }
+
+            if op == 3 || op == 6 {
+                self.ue(marking.long_term_frame_idx)?;
+            }
+
+            if op == 4 {
+                self.ue(u32::try_from(marking.max_long_term_frame_idx_plus1)?)?;
+            }
+        }
+
+        // memory_management_control_operation equal to 0 ends the loop.
+        self.ue(0u8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::codec::h264::parser::HrdParams;
+    use crate::codec::h264::parser::Nalu;
+    use crate::codec::h264::parser::NaluType;
+    use crate::codec::h264::parser::Parser;
+    use crate::codec::h264::parser::Pps;
+    use crate::codec::h264::parser::RefPicListModification;
+    use crate::codec::h264::parser::RefPicMarkingInner;
+    use crate::codec::h264::parser::SliceHeader;
+    use crate::codec::h264::parser::Sps;
+    use crate::codec::h264::parser::VuiParams;
+    use crate::codec::h264::synthesizer::Synthesizer;
+
+    const STREAMS: [&[u8]; 6] = [
+        include_bytes!("test_data/test-25fps.h264"),
+        include_bytes!("test_data/test-25fps-interlaced.h264"),
+        include_bytes!("test_data/64x64-I.h264"),
+        include_bytes!("test_data/64x64-I-P.h264"),
+        include_bytes!("test_data/64x64-I-P-B-P.h264"),
+        include_bytes!("test_data/64x64-I-P-B-P-high.h264"),
+    ];
+
+    fn synthesize_sps(sps: &Sps, parser: &mut Parser) -> Sps {
+        let mut buf = vec![];
+        Synthesizer::<Sps, _>::synthesize(3, sps, &mut buf, true).unwrap();
+        let nalu = Nalu::next(&mut Cursor::new(buf.as_slice())).unwrap();
+        assert_eq!(nalu.header().nalu_type(), NaluType::Sps);
+
+        (**parser.parse_sps(&nalu).unwrap()).clone()
+    }
+
+    fn synthesize_pps(pps: &Pps, parser: &mut Parser) -> Pps {
+        let mut buf = vec![];
+        Synthesizer::<Pps, _>::synthesize(3, pps, &mut buf, true).unwrap();
+        let nalu = Nalu::next(&mut Cursor::new(buf.as_slice())).unwrap();
+        assert_eq!(nalu.header().nalu_type(), NaluType::Pps);
+
+        parser.parse_pps(&nalu).unwrap().clone()
+    }
+
+    /// Synthesizes `header` and parses it back. Also checks that the returned size of the header
+    /// matches the one computed by the parser.
+    fn synthesize_slice_header(
+        header: &SliceHeader,
+        ref_idc: u8,
+        idr: bool,
+        parser: &Parser,
+    ) -> SliceHeader {
+        let pps = parser.get_pps(header.pic_parameter_set_id).unwrap();
+        let mut buf = vec![];
+        let header_bit_size =
+            Synthesizer::<SliceHeader, _>::synthesize(header, pps, ref_idc, idr, &mut buf, true)
+                .unwrap();
+        let nalu = Nalu::next(&mut Cursor::new(buf.as_slice())).unwrap();
+        assert_eq!(nalu.header().ref_idc(), ref_idc);
+        assert_eq!(nalu.header().idr_pic_flag(), idr);
+
+        let synthesized = parser.parse_slice_header(nalu).unwrap().header().clone();
+        assert_eq!(synthesized.header_bit_size, header_bit_size);
+
+        synthesized
+    }
+
+    /// Parses all the SPS, PPS and slice headers of each stream, synthesizes them and checks that
+    /// parsing them back gives the same result.
+    #[test]
+    fn round_trip_streams() {
+        for stream in STREAMS {
+            let mut cursor = Cursor::new(stream);
+            let mut parser = Parser::default();
+            let mut synth_parser = Parser::default();
+
+            while let Ok(nalu) = Nalu::next(&mut cursor) {
+                match nalu.header().nalu_type() {
+                    NaluType::Sps => {
+                        let sps = parser.parse_sps(&nalu).unwrap().clone();
+                        assert_eq!(*sps, synthesize_sps(&sps, &mut synth_parser));
+                    }
+                    NaluType::Pps => {
+                        let pps = parser.parse_pps(&nalu).unwrap().clone();
+                        assert_eq!(pps, synthesize_pps(&pps, &mut synth_parser));
+                    }
+                    NaluType::Slice | NaluType::SliceIdr => {
+                        let ref_idc = nalu.header().ref_idc();
+                        let idr = nalu.header().idr_pic_flag();
+                        let header = parser.parse_slice_header(nalu).unwrap().header().clone();
+                        let synthesized =
+                            synthesize_slice_header(&header, ref_idc, idr, &synth_parser);
+
+                        // The streams may signal slice_type with values 5 to 9, which the
+                        // parser does not keep, so the size of the headers can differ.
+                        assert_eq!(
+                            header,
+                            SliceHeader {
+                                header_bit_size: header.header_bit_size,
+                                n_emulation_prevention_bytes: header.n_emulation_prevention_bytes,
+                                ..synthesized
+                            }
+                        );
+                    }
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    /// Checks the syntax elements that are not used by the test streams.
+    #[test]
+    fn round_trip_optional_syntax() {
+        let mut cursor = Cursor::new(STREAMS[4]);
+        let mut parser = Parser::default();
+        let mut p_slice = None;
+
+        while let Ok(nalu) = Nalu::next(&mut cursor) {
+            match nalu.header().nalu_type() {
+                NaluType::Sps => {
+                    parser.parse_sps(&nalu).unwrap();
+                }
+                NaluType::Pps => {
+                    parser.parse_pps(&nalu).unwrap();
+                }
+                NaluType::Slice if nalu.header().ref_idc() != 0 => {
+                    let slice = parser.parse_slice_header(nalu).unwrap();
+                    if slice.header().slice_type.is_p() {
+                        p_slice = Some(slice.header().clone());
+                        break;
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        let mut synth_parser = Parser::default();
+
+        // SPS with VUI, HRD and bitstream restriction.
+        let mut hrd = HrdParams {
+            cpb_cnt_minus1: 1,
+            bit_rate_scale: 4,
+            cpb_size_scale: 6,
+            initial_cpb_removal_delay_length_minus1: 23,
+            cpb_removal_delay_length_minus1: 15,
+            dpb_output_delay_length_minus1: 7,
+            time_offset_length: 24,
+            ..Default::default()
+        };
+        hrd.bit_rate_value_minus1[..2].copy_from_slice(&[1000, 2000]);
+        hrd.cpb_size_value_minus1[..2].copy_from_slice(&[3000, 4000]);
+        hrd.cbr_flag[0] = true;
+
+        let mut sps = (**parser.get_sps(0).unwrap()).clone();
+        sps.vui_parameters_present_flag = true;
+        sps.vui_parameters = VuiParams {
+            aspect_ratio_info_present_flag: true,
+            aspect_ratio_idc: 255,
+            sar_width: 4,
+            sar_height: 3,
+            video_signal_type_present_flag: true,
+            video_format: 5,
+            video_full_range_flag: true,
+            colour_description_present_flag: true,
+            colour_primaries: 9,
+            transfer_characteristics: 16,
+            matrix_coefficients: 9,
+            timing_info_present_flag: true,
+            num_units_in_tick: 1001,
+            time_scale: 60000,
+            fixed_frame_rate_flag: true,
+            nal_hrd_parameters_present_flag: true,
+            nal_hrd_parameters: hrd,
+            low_delay_hrd_flag: true,
+            pic_struct_present_flag: true,
+            bitstream_restriction_flag: true,
+            motion_vectors_over_pic_boundaries_flag: true,
+            max_bytes_per_pic_denom: 2,
+            max_bits_per_mb_denom: 1,
+            log2_max_mv_length_horizontal: 15,
+            log2_max_mv_length_vertical: 15,
+            max_num_reorder_frames: 1,
+            max_dec_frame_buffering: 2,
+            ..Default::default()
+        };
+        assert_eq!(sps, synthesize_sps(&sps, &mut synth_parser));
+
+        // PPS with weighted prediction and chroma QP offsets.
+        let mut pps = (**parser.get_pps(0).unwrap()).clone();
+        pps.weighted_pred_flag = true;
+        pps.weighted_bipred_idc = 1;
+        pps.second_chroma_qp_index_offset = -2;
+        let synthesized = synthesize_pps(&pps, &mut synth_parser);
+        // The SPS of the PPS is the one synthesized above.
+        assert_eq!(*synthesized.sps, sps);
+        assert_eq!(
+            synthesized,
+            Pps {
+                sps: synthesized.sps.clone(),
+                ..pps
+            }
+        );
+
+        // P slice with list modifications, weights and adaptive reference marking.
+        let mut header = p_slice.unwrap();
+        header.num_ref_idx_active_override_flag = true;
+        header.num_ref_idx_l0_active_minus1 = 1;
+        header.ref_pic_list_modification_flag_l0 = true;
+        header.ref_pic_list_modification_l0 = vec![
+            RefPicListModification {
+                modification_of_pic_nums_idc: 0,
+                abs_diff_pic_num_minus1: 1,
+                ..Default::default()
+            },
+            RefPicListModification {
+                modification_of_pic_nums_idc: 2,
+                long_term_pic_num: 3,
+                ..Default::default()
+            },
+            RefPicListModification {
+                modification_of_pic_nums_idc: 3,
+                ..Default::default()
+            },
+        ];
+
+        let pwt = &mut header.pred_weight_table;
+        pwt.luma_log2_weight_denom = 5;
+        pwt.chroma_log2_weight_denom = 3;
+        pwt.luma_weight_l0[..2].copy_from_slice(&[32, 40]);
+        pwt.luma_offset_l0[..2].copy_from_slice(&[0, -3]);
+        pwt.chroma_weight_l0[..2].copy_from_slice(&[[8, 8], [6, 10]]);
+        pwt.chroma_offset_l0[..2].copy_from_slice(&[[0, 0], [1, -1]]);
+        for weight in &mut pwt.chroma_weight_l1[..=usize::from(header.num_ref_idx_l1_active_minus1)]
+        {
+            *weight = [8, 8];
+        }
+
+        header
+            .dec_ref_pic_marking
+            .adaptive_ref_pic_marking_mode_flag = true;
+        header.dec_ref_pic_marking.inner = vec![
+            RefPicMarkingInner {
+                memory_management_control_operation: 1,
+                difference_of_pic_nums_minus1: 2,
+                ..Default::default()
+            },
+            RefPicMarkingInner {
+                memory_management_control_operation: 4,
+                max_long_term_frame_idx_plus1: 2,
+                ..Default::default()
+            },
+            RefPicMarkingInner {
+                memory_management_control_operation: 6,
+                long_term_frame_idx: 1,
+                ..Default::default()
+            },
+        ];
+
+        let synthesized = synthesize_slice_header(&header, 2, false, &synth_parser);
+        assert_eq!(
+            synthesized,
+            SliceHeader {
+                header_bit_size: synthesized.header_bit_size,
+                n_emulation_prevention_bytes: synthesized.n_emulation_prevention_bytes,
+                ..header
+            }
+        );
+    }
+}
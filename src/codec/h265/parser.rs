@@ -45,17 +45,17 @@ const MAX_SHORT_TERM_REF_PIC_SETS: usize = 65;
 const MAX_LONG_TERM_REF_PIC_SETS: usize = 32;
 
 // From table 7-5.
-const DEFAULT_SCALING_LIST_0: [u8; 16] = [16; 16];
+pub(crate) const DEFAULT_SCALING_LIST_0: [u8; 16] = [16; 16];
 
 // From Table 7-6.
-const DEFAULT_SCALING_LIST_1: [u8; 64] = [
+pub(crate) const DEFAULT_SCALING_LIST_1: [u8; 64] = [
     16, 16, 16, 16, 16, 16, 16, 16, 16, 16, 17, 16, 17, 16, 17, 18, 17, 18, 18, 17, 18, 21, 19, 20,
     21, 20, 19, 21, 24, 22, 22, 24, 24, 22, 22, 24, 25, 25, 27, 30, 27, 25, 25, 29, 31, 35, 35, 31,
     29, 36, 41, 44, 41, 36, 47, 54, 54, 47, 65, 70, 65, 88, 88, 115,
 ];
 
 // From Table 7-6.
-const DEFAULT_SCALING_LIST_2: [u8; 64] = [
+pub(crate) const DEFAULT_SCALING_LIST_2: [u8; 64] = [
     16, 16, 16, 16, 16, 16, 16, 16, 16, 16, 17, 17, 17, 17, 17, 18, 18, 18, 18, 18, 18, 20, 20, 20,
     20, 20, 20, 20, 24, 24, 24, 24, 24, 24, 24, 24, 25, 25, 25, 25, 25, 25, 25, 28, 28, 28, 28, 28,
     28, 33, 33, 33, 33, 33, 41, 41, 41, 41, 54, 54, 54, 71, 71, 91,
@@ -264,25 +264,25 @@ pub enum Profile {
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Vps {
     /// Identifies the VPS for reference by other syntax elements.
-    video_parameter_set_id: u8,
+    pub video_parameter_set_id: u8,
     /// If vps_base_layer_internal_flag is equal to 1 and
     /// vps_base_layer_available_flag is equal to 1, the base layer is present
     /// in the bitstream.
-    base_layer_internal_flag: bool,
+    pub base_layer_internal_flag: bool,
     /// See `base_layer_internal_flag`.
-    base_layer_available_flag: bool,
+    pub base_layer_available_flag: bool,
     /// Plus 1 specifies the maximum allowed number of layers in each CVS
     /// referring to the VPS.
-    max_layers_minus1: u8,
+    pub max_layers_minus1: u8,
     /// Plus 1 specifies the maximum number of temporal sub-layers that may be
     /// present in each CVS referring to the VPS.
-    max_sub_layers_minus1: u8,
+    pub max_sub_layers_minus1: u8,
     /// When vps_max_sub_layers_minus1 is greater than 0, specifies whether
     /// inter prediction is additionally restricted for CVSs referring to the
     /// VPS.
-    temporal_id_nesting_flag: bool,
+    pub temporal_id_nesting_flag: bool,
     /// ProfileTierLevel() data.
-    profile_tier_level: ProfileTierLevel,
+    pub profile_tier_level: ProfileTierLevel,
     /// When true, specifies that `vps_max_dec_pic_buffering_minus1[ i ]`,
     /// `vps_max_num_reorder_pics[ i ]` and `vps_max_latency_increase_plus1[ i ]`
     /// are present for vps_max_sub_layers_ minus1 + 1 sub-layers.
@@ -291,33 +291,33 @@ pub struct Vps {
     /// vps_max_num_reorder_pics[ vps_max_sub_ layers_minus1 ] and
     /// `vps_max_latency_increase_plus1[ vps_max_sub_layers_minus1 ]` apply to all
     /// sub-layers
-    sub_layer_ordering_info_present_flag: bool,
+    pub sub_layer_ordering_info_present_flag: bool,
     /// `max_dec_pic_buffering_minus1[i]` plus 1 specifies the maximum required
     /// size of the decoded picture buffer for the CVS in units of picture
     /// storage buffers when HighestTid is equal to i.
-    max_dec_pic_buffering_minus1: [u32; 7],
+    pub max_dec_pic_buffering_minus1: [u32; 7],
     /// Indicates the maximum allowed number of pictures with PicOutputFlag
     /// equal to 1 that can precede any picture with PicOutputFlag equal to 1 in
     /// the CVS in decoding order and follow that picture with PicOutputFlag
     /// equal to 1 in output order when HighestTid is equal to i.
-    max_num_reorder_pics: [u32; 7],
+    pub max_num_reorder_pics: [u32; 7],
     /// When true, `max_latency_increase_plus1[i]` is used to compute the value of
     /// `VpsMaxLatencyPictures[ i ]`, which specifies the maximum number of
     /// pictures with PicOutputFlag equal to 1 that can precede any picture with
     /// PicOutputFlag equal to 1 in the CVS in output order and follow that
     /// picture with PicOutputFlag equal to 1 in decoding order when HighestTid
     /// is equal to i.
-    max_latency_increase_plus1: [u32; 7],
+    pub max_latency_increase_plus1: [u32; 7],
     /// Specifies the maximum allowed value of nuh_layer_id of all NAL units in
     /// each CVS referring to the VPS.
-    max_layer_id: u8,
+    pub max_layer_id: u8,
     /// num_layer_sets_minus1 plus 1 specifies the number of layer sets that are
     /// specified by the VPS.
-    num_layer_sets_minus1: u32,
+    pub num_layer_sets_minus1: u32,
     /// When true, specifies that num_units_in_tick, time_scale,
     /// poc_proportional_to_timing_flag and num_hrd_parameters are present in
     /// the VPS.
-    timing_info_present_flag: bool,
+    pub timing_info_present_flag: bool,
     /// The number of time units of a clock operating at the frequency
     /// vps_time_scale Hz that corresponds to one increment (called a clock
     /// tick) of a clock tick counter. The value of vps_num_units_in_tick shall
@@ -327,11 +327,11 @@ pub struct Vps {
     /// vps_time_scale may be equal to 27 000 000 and vps_num_units_in_tick may
     /// be equal to 1 080 000, and consequently a clock tick may be 0.04
     /// seconds.
-    num_units_in_tick: u32,
+    pub num_units_in_tick: u32,
     /// The number of time units that pass in one second. For example, a time
     /// coordinate system that measures time using a 27 MHz clock has a
     /// vps_time_scale of 27 000 000.
-    time_scale: u32,
+    pub time_scale: u32,
     /// When true, indicates that the picture order count value for each picture
     /// in the CVS that is not the first picture in the CVS, in decoding order,
     /// is proportional to the output time of the picture relative to the output
@@ -340,17 +340,17 @@ pub struct Vps {
     /// first picture in the CVS, in decoding order, may or may not be
     /// proportional to the output time of the picture relative to the output
     /// time of the first picture in the CVS.
-    poc_proportional_to_timing_flag: bool,
+    pub poc_proportional_to_timing_flag: bool,
     /// num_ticks_poc_diff_one_minus1 plus 1 specifies the number of clock ticks
     /// corresponding to a difference of picture order count values equal to 1.
-    num_ticks_poc_diff_one_minus1: u32,
+    pub num_ticks_poc_diff_one_minus1: u32,
     /// Specifies the number of hrd_parameters( ) syntax structures present in
     /// the VPS RBSP before the vps_extension_flag syntax element.
-    num_hrd_parameters: u32,
+    pub num_hrd_parameters: u32,
     /// `hrd_layer_set_idx[ i ]` specifies the index, into the list of layer sets
     /// specified by the VPS, of the layer set to which the i-th hrd_parameters(
     /// ) syntax structure in the VPS applies.
-    hrd_layer_set_idx: Vec<u16>,
+    pub hrd_layer_set_idx: Vec<u16>,
     /// `cprms_present_flag[ i ]` equal to true specifies that the HRD parameters
     /// that are common for all sub-layers are present in the i-th
     /// hrd_parameters( ) syntax structure in the VPS. `cprms_present_flag[ i ]`
@@ -359,9 +359,9 @@ pub struct Vps {
     /// structure in the VPS and are derived to be the same as the ( i − 1 )-th
     /// hrd_parameters( ) syntax structure in the VPS. `cprms_present_flag[ 0 ]`
     /// is inferred to be equal to true.
-    cprms_present_flag: Vec<bool>,
+    pub cprms_present_flag: Vec<bool>,
     /// The hrd_parameters() data.
-    hrd_parameters: Vec<HrdParams>,
+    pub hrd_parameters: Vec<HrdParams>,
     /// When false, specifies that no vps_extension_data_flag syntax elements
     /// are present in the VPS RBSP syntax structure. When true, specifies that
     /// there are vps_extension_data_flag syntax elements present in the VPS
@@ -369,7 +369,7 @@ pub struct Vps {
     /// Annex A but not supporting the INBLD capability specified in Annex F
     /// shall ignore all data that follow the value 1 for vps_extension_flag in
     /// a VPS NAL unit.
-    extension_flag: bool,
+    pub extension_flag: bool,
 }
 
 impl Vps {
@@ -501,21 +501,21 @@ pub struct ProfileTierLevel {
     /// Specifies the context for the interpretation of general_profile_idc and
     /// `general_profile_compatibility_flag[ j ]` for all values of j in the range
     /// of 0 to 31, inclusive.
-    general_profile_space: u8,
+    pub general_profile_space: u8,
     /// Specifies the tier context for the interpretation of general_level_idc
     /// as specified in Annex A.
-    general_tier_flag: bool,
+    pub general_tier_flag: bool,
     /// When general_profile_space is equal to 0, indicates a profile to which
     /// the CVS conforms as specified in Annex A. Bitstreams shall not contain
     /// values of general_profile_idc other than those specified in Annex A.
     /// Other values of general_profile_idc are reserved for future use by ITU-T
     /// | ISO/IEC.
-    general_profile_idc: u8,
+    pub general_profile_idc: u8,
     /// `general_profile_compatibility_flag[ j ]` equal to true, when
     /// general_profile_space is false, indicates that the CVS conforms to the
     /// profile indicated by general_profile_idc equal to j as specified in
     /// Annex A.
-    general_profile_compatibility_flag: [bool; 32],
+    pub general_profile_compatibility_flag: [bool; 32],
     /// general_progressive_source_flag and general_interlaced_source_flag are
     /// interpreted as follows:
     ///
@@ -535,9 +535,9 @@ pub struct ProfileTierLevel {
     /// general_interlaced_source_flag is true), the source scan type of each
     /// picture in the CVS is indicated at the picture level using the syntax
     /// element source_scan_type in a picture timing SEI message.
-    general_progressive_source_flag: bool,
+    pub general_progressive_source_flag: bool,
     /// See `general_progressive_source_flag`.
-    general_interlaced_source_flag: bool,
+    pub general_interlaced_source_flag: bool,
     /// If true, specifies that there are no frame packing arrangement SEI
     /// messages, segmented rectangular frame packing arrangement SEI messages,
     /// equirectangular projection SEI messages, or cubemap projection SEI
@@ -546,82 +546,82 @@ pub struct ProfileTierLevel {
     /// rectangular frame packing arrangement SEI messages, equirectangular
     /// projection SEI messages, or cubemap projection SEI messages present in
     /// the CVS.
-    general_non_packed_constraint_flag: bool,
+    pub general_non_packed_constraint_flag: bool,
     /// When true, specifies that field_seq_flag is false. When false, indicates
     /// that field_seq_flag may or may not be false.
-    general_frame_only_constraint_flag: bool,
+    pub general_frame_only_constraint_flag: bool,
     /// See Annex A.
-    general_max_12bit_constraint_flag: bool,
+    pub general_max_12bit_constraint_flag: bool,
     /// See Annex A.
-    general_max_10bit_constraint_flag: bool,
+    pub general_max_10bit_constraint_flag: bool,
     /// See Annex A.
-    general_max_8bit_constraint_flag: bool,
+    pub general_max_8bit_constraint_flag: bool,
     /// See Annex A.
-    general_max_422chroma_constraint_flag: bool,
+    pub general_max_422chroma_constraint_flag: bool,
     /// See Annex A.
-    general_max_420chroma_constraint_flag: bool,
+    pub general_max_420chroma_constraint_flag: bool,
     /// See Annex A.
-    general_max_monochrome_constraint_flag: bool,
+    pub general_max_monochrome_constraint_flag: bool,
     /// See Annex A.
-    general_intra_constraint_flag: bool,
+    pub general_intra_constraint_flag: bool,
     /// See Annex A.
-    general_lower_bit_rate_constraint_flag: bool,
+    pub general_lower_bit_rate_constraint_flag: bool,
     /// See Annex A.
-    general_max_14bit_constraint_flag: bool,
+    pub general_max_14bit_constraint_flag: bool,
     /// See Annex A.
-    general_one_picture_only_constraint_flag: bool,
+    pub general_one_picture_only_constraint_flag: bool,
     /// When true, specifies that the INBLD capability as specified in Annex F
     /// is required for decoding of the layer to which the profile_tier_level( )
     /// syntax structure applies. When false, specifies that the INBLD
     /// capability as specified in Annex F is not required for decoding of the
     /// layer to which the profile_tier_level( ) syntax structure applies.
-    general_inbld_flag: bool,
+    pub general_inbld_flag: bool,
     /// Indicates a level to which the CVS conforms as specified in Annex A.
-    general_level_idc: Level,
+    pub general_level_idc: Level,
     /// Sub-layer syntax element.
-    sub_layer_profile_present_flag: [bool; 6],
+    pub sub_layer_profile_present_flag: [bool; 6],
     /// Sub-layer syntax element.
-    sub_layer_level_present_flag: [bool; 6],
+    pub sub_layer_level_present_flag: [bool; 6],
     /// Sub-layer syntax element.
-    sub_layer_profile_space: [u8; 6],
+    pub sub_layer_profile_space: [u8; 6],
     /// Sub-layer syntax element.
-    sub_layer_tier_flag: [bool; 6],
+    pub sub_layer_tier_flag: [bool; 6],
     /// Sub-layer syntax element.
-    sub_layer_profile_idc: [u8; 6],
+    pub sub_layer_profile_idc: [u8; 6],
     /// Sub-layer syntax element.
-    sub_layer_profile_compatibility_flag: [[bool; 32]; 6],
+    pub sub_layer_profile_compatibility_flag: [[bool; 32]; 6],
     /// Sub-layer syntax element.
-    sub_layer_progressive_source_flag: [bool; 6],
+    pub sub_layer_progressive_source_flag: [bool; 6],
     /// Sub-layer syntax element.
-    sub_layer_interlaced_source_flag: [bool; 6],
+    pub sub_layer_interlaced_source_flag: [bool; 6],
     /// Sub-layer syntax element.
-    sub_layer_non_packed_constraint_flag: [bool; 6],
+    pub sub_layer_non_packed_constraint_flag: [bool; 6],
     /// Sub-layer syntax element.
-    sub_layer_frame_only_constraint_flag: [bool; 6],
+    pub sub_layer_frame_only_constraint_flag: [bool; 6],
     /// Sub-layer syntax element.
-    sub_layer_max_12bit_constraint_flag: [bool; 6],
+    pub sub_layer_max_12bit_constraint_flag: [bool; 6],
     /// Sub-layer syntax element.
-    sub_layer_max_10bit_constraint_flag: [bool; 6],
+    pub sub_layer_max_10bit_constraint_flag: [bool; 6],
     /// Sub-layer syntax element.
-    sub_layer_max_8bit_constraint_flag: [bool; 6],
+    pub sub_layer_max_8bit_constraint_flag: [bool; 6],
     /// Sub-layer syntax element.
-    sub_layer_max_422chroma_constraint_flag: [bool; 6],
+    pub sub_layer_max_422chroma_constraint_flag: [bool; 6],
     /// Sub-layer syntax element.
-    sub_layer_max_420chroma_constraint_flag: [bool; 6],
+    pub sub_layer_max_420chroma_constraint_flag: [bool; 6],
     /// Sub-layer syntax element.
-    sub_layer_max_monochrome_constraint_flag: [bool; 6],
+    pub sub_layer_max_monochrome_constraint_flag: [bool; 6],
     /// Sub-layer syntax element.
-    sub_layer_intra_constraint_flag: [bool; 6],
+    pub sub_layer_intra_constraint_flag: [bool; 6],
     /// Sub-layer syntax element.
-    sub_layer_one_picture_only_constraint_flag: [bool; 6],
+    pub sub_layer_one_picture_only_constraint_flag: [bool; 6],
     /// Sub-layer syntax element.
-    sub_layer_lower_bit_rate_constraint_flag: [bool; 6],
+    pub sub_layer_lower_bit_rate_constraint_flag: [bool; 6],
     /// Sub-layer syntax element.
-    sub_layer_max_14bit_constraint_flag: [bool; 6],
+    pub sub_layer_max_14bit_constraint_flag: [bool; 6],
     /// Sub-layer syntax element.
-    sub_layer_inbld_flag: [bool; 6],
+    pub sub_layer_inbld_flag: [bool; 6],
     /// Sub-layer syntax element.
-    sub_layer_level_idc: [Level; 6],
+    pub sub_layer_level_idc: [Level; 6],
 }
 
 impl ProfileTierLevel {
@@ -818,15 +818,15 @@ impl ProfileTierLevel {
 
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub struct SpsRangeExtension {
-    transform_skip_rotation_enabled_flag: bool,
-    transform_skip_context_enabled_flag: bool,
-    implicit_rdpcm_enabled_flag: bool,
-    explicit_rdpcm_enabled_flag: bool,
-    extended_precision_processing_flag: bool,
-    intra_smoothing_disabled_flag: bool,
-    high_precision_offsets_enabled_flag: bool,
-    persistent_rice_adaptation_enabled_flag: bool,
-    cabac_bypass_alignment_enabled_flag: bool,
+    pub transform_skip_rotation_enabled_flag: bool,
+    pub transform_skip_context_enabled_flag: bool,
+    pub implicit_rdpcm_enabled_flag: bool,
+    pub explicit_rdpcm_enabled_flag: bool,
+    pub extended_precision_processing_flag: bool,
+    pub intra_smoothing_disabled_flag: bool,
+    pub high_precision_offsets_enabled_flag: bool,
+    pub persistent_rice_adaptation_enabled_flag: bool,
+    pub cabac_bypass_alignment_enabled_flag: bool,
 }
 
 impl SpsRangeExtension {
@@ -873,34 +873,34 @@ pub struct SpsSccExtension {
     /// reference picture list of a slice of the picture itself.  When not set,
     /// specifies that a picture in the CVS is never included in a reference
     /// picture list of a slice of the picture itself.
-    curr_pic_ref_enabled_flag: bool,
+    pub curr_pic_ref_enabled_flag: bool,
     /// When set, specifies that the decoding process for palette mode may be
     /// used for intra blocks. When not set, specifies that the decoding process
     /// for palette mode is not applied.
-    palette_mode_enabled_flag: bool,
+    pub palette_mode_enabled_flag: bool,
     /// Specifies the maximum allowed palette size.
-    palette_max_size: u8,
+    pub palette_max_size: u8,
     /// Specifies the difference between the maximum allowed palette predictor
     /// size and the maximum allowed palette size.
-    delta_palette_max_predictor_size: u8,
+    pub delta_palette_max_predictor_size: u8,
     /// When set, specifies that the sequence palette predictors are initialized
     /// using the sps_palette_predictor_initializers. When not set, specifies
     /// that the entries in the sequence palette predictor are initialized to 0.
-    palette_predictor_initializers_present_flag: bool,
+    pub palette_predictor_initializers_present_flag: bool,
     /// num_palette_predictor_initializers_minus1 plus 1 specifies the number of
     /// entries in the sequence palette predictor initializer.
-    num_palette_predictor_initializer_minus1: u8,
+    pub num_palette_predictor_initializer_minus1: u8,
     /// `palette_predictor_initializer[ comp ][ i ]` specifies the value of the
     /// comp-th component of the i-th palette entry in the SPS that is used to
     /// initialize the array PredictorPaletteEntries.
-    palette_predictor_initializer: [[u32; 128]; 3],
+    pub palette_predictor_initializer: [[u32; 128]; 3],
     /// Controls the presence and inference of the use_integer_mv_flag that
     /// specifies the resolution of motion vectors for inter prediction.
-    motion_vector_resolution_control_idc: u8,
+    pub motion_vector_resolution_control_idc: u8,
     /// When set, specifies that the intra boundary filtering process is
     /// unconditionally disabled for intra prediction.  If not set, specifies
     /// that the intra boundary filtering process may be used.
-    intra_boundary_filtering_disabled_flag: bool,
+    pub intra_boundary_filtering_disabled_flag: bool,
 }
 
 impl SpsSccExtension {
@@ -961,135 +961,135 @@ impl Default for SpsSccExtension {
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub struct Sps {
     /// Specifies the value of the vps_video_parameter_set_id of the active VPS.
-    video_parameter_set_id: u8,
+    pub video_parameter_set_id: u8,
     /// `max_sub_layers_minus1` plus 1 specifies the maximum number of temporal
     /// sub-layers that may be present in each CVS referring to the SPS.
-    max_sub_layers_minus1: u8,
+    pub max_sub_layers_minus1: u8,
     /// When sps_max_sub_layers_minus1 is greater than 0, specifies whether
     /// inter prediction is additionally restricted for CVSs referring to the
     /// SPS.
-    temporal_id_nesting_flag: bool,
+    pub temporal_id_nesting_flag: bool,
     /// profile_tier_level() data.
-    profile_tier_level: ProfileTierLevel,
+    pub profile_tier_level: ProfileTierLevel,
     /// Provides an identifier for the SPS for reference by other syntax
     /// elements.
-    seq_parameter_set_id: u8,
+    pub seq_parameter_set_id: u8,
     /// Specifies the chroma sampling relative to the luma sampling as specified
     /// in clause 6.2.
-    chroma_format_idc: u8,
+    pub chroma_format_idc: u8,
     /// When true, specifies that the three colour components of the 4:4:4
     /// chroma format are coded separately. When false, specifies that the
     /// colour components are not coded separately.
-    separate_colour_plane_flag: bool,
+    pub separate_colour_plane_flag: bool,
     /// Specifies the width of each decoded picture in units of luma samples.
-    pic_width_in_luma_samples: u16,
+    pub pic_width_in_luma_samples: u16,
     /// Specifies the height of each decoded picture in units of luma samples.
-    pic_height_in_luma_samples: u16,
+    pub pic_height_in_luma_samples: u16,
     /// When true, indicates that the conformance cropping window offset
     /// parameters follow next in the SPS. When false, indicates that the
     /// conformance cropping window offset parameters are not present.
-    conformance_window_flag: bool,
+    pub conformance_window_flag: bool,
     /* if conformance_window_flag */
     /// Specify the samples of the pictures in the CVS that are output from the
     /// decoding process, in terms of a rectangular region specified in picture
     /// coordinates for output.
-    conf_win_left_offset: u32,
-    conf_win_right_offset: u32,
-    conf_win_top_offset: u32,
-    conf_win_bottom_offset: u32,
+    pub conf_win_left_offset: u32,
+    pub conf_win_right_offset: u32,
+    pub conf_win_top_offset: u32,
+    pub conf_win_bottom_offset: u32,
 
     /// Specifies the bit depth of the samples of the luma array BitDepthY and
     /// the value of the luma quantization parameter range offset QpBdOffsetY.
-    bit_depth_luma_minus8: u8,
+    pub bit_depth_luma_minus8: u8,
     /// Specifies the bit depth of the samples of the chroma arrays BitDepthC
     /// and the value of the chroma quantization parameter range offset
     /// QpBdOffsetC.
-    bit_depth_chroma_minus8: u8,
+    pub bit_depth_chroma_minus8: u8,
     /// Specifies the value of the variable MaxPicOrderCntLsb that is used in
     /// the decoding process for picture order count.
-    log2_max_pic_order_cnt_lsb_minus4: u8,
+    pub log2_max_pic_order_cnt_lsb_minus4: u8,
     /// When true, specifies that `max_dec_pic_buffering_minus1[ i ]`,
     /// `max_num_reorder_pics[ i ]` and `max_latency_increase_plus1[ i ]` are
     /// present for max_sub_layers_minus1 + 1 sub- layers. When false, specifies
     /// that the values of `max_dec_pic_ buffering_minus1[ max_sub_layers_minus1
     /// ]`, `max_num_reorder_pics[ max_sub_layers_minus1 ]` and max_
     /// `latency_increase_plus1[ max_sub_layers_minus1 ]` apply to all sub-layers.
-    sub_layer_ordering_info_present_flag: bool,
+    pub sub_layer_ordering_info_present_flag: bool,
     /// `max_dec_pic_buffering_minus1[ i ]` plus 1 specifies the maximum required
     /// size of the decoded picture buffer for the CVS in units of picture
     /// storage buffers when HighestTid is equal to i.
-    max_dec_pic_buffering_minus1: [u8; 7],
+    pub max_dec_pic_buffering_minus1: [u8; 7],
     /// `max_num_reorder_pics[ i ]` indicates the maximum allowed number of
     /// pictures with PicOutputFlag equal to 1 that can precede any picture with
     /// PicOutputFlag equal to 1 in the CVS in decoding order and follow that
     /// picture with PicOutputFlag equal to 1 in output order when HighestTid is
     /// equal to i.
-    max_num_reorder_pics: [u8; 7],
+    pub max_num_reorder_pics: [u8; 7],
     /// `max_latency_increase_plus1[ i ]` not equal to 0 is used to compute the
     /// value of `SpsMaxLatencyPictures[ i ]`, which specifies the maximum number
     /// of pictures with PicOutputFlag equal to 1 that can precede any picture
     /// with PicOutputFlag equal to 1 in the CVS in output order and follow that
     /// picture with PicOutputFlag equal to 1 in decoding order when HighestTid
     /// is equal to i.
-    max_latency_increase_plus1: [u8; 7],
+    pub max_latency_increase_plus1: [u8; 7],
     /// min_luma_coding_block_size_minus3 plus 3 specifies the minimum luma
     /// coding block size.
-    log2_min_luma_coding_block_size_minus3: u8,
+    pub log2_min_luma_coding_block_size_minus3: u8,
     /// Specifies the difference between the maximum and minimum luma coding
     /// block size.
-    log2_diff_max_min_luma_coding_block_size: u8,
+    pub log2_diff_max_min_luma_coding_block_size: u8,
     /// min_luma_transform_block_size_minus2 plus 2 specifies the minimum luma
     /// transform block size.
-    log2_min_luma_transform_block_size_minus2: u8,
+    pub log2_min_luma_transform_block_size_minus2: u8,
     /// Specifies the difference between the maximum and minimum luma transform
     /// block size.
-    log2_diff_max_min_luma_transform_block_size: u8,
+    pub log2_diff_max_min_luma_transform_block_size: u8,
     /// Specifies the maximum hierarchy depth for transform units of coding
     /// units coded in inter prediction mode.
-    max_transform_hierarchy_depth_inter: u8,
+    pub max_transform_hierarchy_depth_inter: u8,
     /// Specifies the maximum hierarchy depth for transform units of coding
     /// units coded in intra prediction mode.
-    max_transform_hierarchy_depth_intra: u8,
+    pub max_transform_hierarchy_depth_intra: u8,
     /// When true, specifies that a scaling list is used for the scaling process
     /// for transform coefficients. When false, specifies that scaling list is
     /// not used for the scaling process for transform coefficients.
-    scaling_list_enabled_flag: bool,
+    pub scaling_list_enabled_flag: bool,
     /* if scaling_list_enabled_flag */
     /// When true, specifies that the scaling_list_data( ) syntax structure is
     /// present in the SPS. When false, specifies that the scaling_list_data( )
     /// syntax structure is not present in the SPS.
-    scaling_list_data_present_flag: bool,
+    pub scaling_list_data_present_flag: bool,
     /// The scaling_list_data() syntax data.
-    scaling_list: ScalingLists,
+    pub scaling_list: ScalingLists,
     /// When true, specifies that asymmetric motion partitions, i.e., PartMode
     /// equal to PART_2NxnU, PART_2NxnD, PART_nLx2N or PART_nRx2N, may be used
     /// in CTBs. When false, specifies that asymmetric motion partitions cannot
     /// be used in CTBs.
-    amp_enabled_flag: bool,
+    pub amp_enabled_flag: bool,
     /// When true, specifies that the sample adaptive offset process is applied
     /// to the reconstructed picture after the deblocking filter process.  When
     /// false, specifies that the sample adaptive offset process is not applied
     /// to the reconstructed picture after the deblocking filter process.
-    sample_adaptive_offset_enabled_flag: bool,
+    pub sample_adaptive_offset_enabled_flag: bool,
     /// When false, specifies that PCM-related syntax
     /// (pcm_sample_bit_depth_luma_minus1, pcm_sample_ bit_depth_chroma_minus1,
     /// log2_min_pcm_luma_coding_block_size_minus3, log2_diff_max_min_pcm_luma_
     /// coding_block_size, pcm_loop_filter_disabled_flag, pcm_flag,
     /// pcm_alignment_zero_bit syntax elements and pcm_sample( ) syntax
     /// structure) is not present in the CVS.
-    pcm_enabled_flag: bool,
+    pub pcm_enabled_flag: bool,
 
     /* if pcm_enabled_flag */
-    pcm_sample_bit_depth_luma_minus1: u8,
+    pub pcm_sample_bit_depth_luma_minus1: u8,
     /// Specifies the number of bits used to represent each of PCM sample values
     /// of the luma component.
-    pcm_sample_bit_depth_chroma_minus1: u8,
+    pub pcm_sample_bit_depth_chroma_minus1: u8,
     /// Specifies the number of bits used to represent each of PCM sample values
     /// of the chroma components.
-    log2_min_pcm_luma_coding_block_size_minus3: u8,
+    pub log2_min_pcm_luma_coding_block_size_minus3: u8,
     /// Specifies the difference between the maximum and minimum size of coding
     /// blocks with pcm_flag equal to true.
-    log2_diff_max_min_pcm_luma_coding_block_size: u8,
+    pub log2_diff_max_min_pcm_luma_coding_block_size: u8,
     /// Specifies whether the loop filter process is disabled on reconstructed
     /// samples in a coding unit with pcm_flag equal to true as follows:
     ///
@@ -1101,89 +1101,89 @@ pub struct Sps {
     /// deblocking filter and sample adaptive offset filter processes on the
     /// reconstructed samples in a coding unit with pcm_flag set are not
     /// disabled.
-    pcm_loop_filter_disabled_flag: bool,
+    pub pcm_loop_filter_disabled_flag: bool,
     /// Specifies the number of st_ref_pic_set( ) syntax structures included in
     /// the SPS.
-    num_short_term_ref_pic_sets: u8,
+    pub num_short_term_ref_pic_sets: u8,
     /// the st_ref_pic_set() data.
-    short_term_ref_pic_set: Vec<ShortTermRefPicSet>,
+    pub short_term_ref_pic_set: Vec<ShortTermRefPicSet>,
     /// If unset, specifies that no long-term reference picture is used for
     /// inter prediction of any coded picture in the CVS.
     /// If set, specifies that long-term reference pictures may be used for
     /// inter prediction of one or more coded pictures in the CVS.
-    long_term_ref_pics_present_flag: bool,
+    pub long_term_ref_pics_present_flag: bool,
 
     /* if long_term_ref_pics_present_flag */
     /// Specifies the number of candidate long-term reference pictures that are
     /// specified in the SPS.
-    num_long_term_ref_pics_sps: u8,
+    pub num_long_term_ref_pics_sps: u8,
     /// `lt_ref_pic_poc_lsb_sps[ i ]` specifies the picture order count modulo
     /// MaxPicOrderCntLsb of the i-th candidate long-term reference picture
     /// specified in the SPS.
-    lt_ref_pic_poc_lsb_sps: [u32; MAX_LONG_TERM_REF_PIC_SETS],
+    pub lt_ref_pic_poc_lsb_sps: [u32; MAX_LONG_TERM_REF_PIC_SETS],
     /// `used_by_curr_pic_lt_sps_flag[ i ]` equal to false specifies that the i-th
     /// candidate long-term reference picture specified in the SPS is not used
     /// for reference by a picture that includes in its long-term reference
     /// picture set (RPS) the i-th candidate long-term reference picture
     /// specified in the SPS.
-    used_by_curr_pic_lt_sps_flag: [bool; MAX_LONG_TERM_REF_PIC_SETS],
+    pub used_by_curr_pic_lt_sps_flag: [bool; MAX_LONG_TERM_REF_PIC_SETS],
     /// When set, specifies that slice_temporal_mvp_enabled_flag is present in
     /// the slice headers of non-IDR pictures in the CVS. When not set,
     /// specifies that slice_temporal_mvp_enabled_flag is not present in slice
     /// headers and that temporal motion vector predictors are not used in the
     /// CVS.
-    temporal_mvp_enabled_flag: bool,
+    pub temporal_mvp_enabled_flag: bool,
     /// When set, specifies that bi-linear interpolation is conditionally used
     /// in the intraprediction filtering process in the CVS as specified in
     /// clause 8.4.4.2.3.
-    strong_intra_smoothing_enabled_flag: bool,
+    pub strong_intra_smoothing_enabled_flag: bool,
     /// When set, specifies that the vui_parameters( ) syntax structure as
     /// specified in Annex E is present. When not set, specifies that the
     /// vui_parameters( ) syntax structure as specified in Annex E is not
     /// present.
-    vui_parameters_present_flag: bool,
+    pub vui_parameters_present_flag: bool,
     /// The vui_parameters() data.
-    vui_parameters: VuiParams,
+    pub vui_parameters: VuiParams,
     /// When set, specifies that the syntax elements sps_range_extension_flag,
     /// sps_multilayer_extension_flag, sps_3d_extension_flag,
     /// sps_scc_extension_flag, and sps_extension_4bits are present in the SPS
     /// RBSP syntax structure. When not set, specifies that these syntax
     /// elements are not present.
-    extension_present_flag: bool,
+    pub extension_present_flag: bool,
 
-    range_extension_flag: bool,
+    pub range_extension_flag: bool,
     /// The sps_range_extension() data.
-    range_extension: SpsRangeExtension,
+    pub range_extension: SpsRangeExtension,
     /// When set, specifies that the sps_scc_extension( ) syntax structure is
     /// present in the SPS RBSP syntax structure. When not set, specifies that
     /// this syntax structure is not present
-    scc_extension_flag: bool,
+    pub scc_extension_flag: bool,
     /// The sps_scc_extension() data.
-    scc_extension: SpsSccExtension,
+    pub scc_extension: SpsSccExtension,
 
     // Internal H265 variables. Computed from the bitstream.
     /// Equivalent to MinCbLog2SizeY in the specification.
-    min_cb_log2_size_y: u32,
+    pub min_cb_log2_size_y: u32,
     /// Equivalent to CtbLog2SizeY in the specification.
-    ctb_log2_size_y: u32,
+    pub ctb_log2_size_y: u32,
     /// Equivalent to CtbSizeY in the specification.
-    ctb_size_y: u32,
+    pub ctb_size_y: u32,
     /// Equivalent to PicHeightInCtbsY in the specification.
-    pic_height_in_ctbs_y: u32,
+    pub pic_height_in_ctbs_y: u32,
     /// Equivalent to PicWidthInCtbsY in the specification.
-    pic_width_in_ctbs_y: u32,
+    pub pic_width_in_ctbs_y: u32,
     /// Equivalent to PicSizeInCtbsY in the specification.
-    pic_size_in_ctbs_y: u32,
+    pub pic_size_in_ctbs_y: u32,
     /// Equivalent to ChromaArrayType in the specification.
-    chroma_array_type: u8,
+    pub chroma_array_type: u8,
     /// Equivalent to WpOffsetHalfRangeY in the specification.
-    wp_offset_half_range_y: u32,
+    pub wp_offset_half_range_y: u32,
     /// Equivalent to WpOffsetHalfRangeC in the specification.
-    wp_offset_half_range_c: u32,
+    pub wp_offset_half_range_c: u32,
     /// Equivalent to MaxTbLog2SizeY in the specification.
-    max_tb_log2_size_y: u32,
+    pub max_tb_log2_size_y: u32,
     /// Equivalent to PicSizeInSamplesY in the specification.
-    pic_size_in_samples_y: u32,
+    pub pic_size_in_samples_y: u32,
 }
 
 impl Sps {
@@ -1512,45 +1512,45 @@ pub struct PpsSccExtension {
     /// in a reference picture list of a slice of the picture itself.  If not
     /// set, specifies that a picture referring to the PPS is never included in
     /// a reference picture list of a slice of the picture itself.
-    curr_pic_ref_enabled_flag: bool,
+    pub curr_pic_ref_enabled_flag: bool,
     /// When set, specifies that an adaptive colour transform may be applied to
     /// the residual in the decoding process. When not set, specifies that
     /// adaptive colour transform is not applied to the residual.
-    residual_adaptive_colour_transform_enabled_flag: bool,
+    pub residual_adaptive_colour_transform_enabled_flag: bool,
     /// When set, specifies that slice_act_y_qp_offset, slice_act_cb_qp_offset,
     /// slice_act_cr_qp_offset are present in the slice header.  When not set,
     /// specifies that slice_act_y_qp_offset, slice_act_cb_qp_offset,
     /// slice_act_cr_qp_offset are not present in the slice header.
-    slice_act_qp_offsets_present_flag: bool,
+    pub slice_act_qp_offsets_present_flag: bool,
     /// See the specificartion for more details.
-    act_y_qp_offset_plus5: i8,
+    pub act_y_qp_offset_plus5: i8,
     /// See the specificartion for more details.
-    act_cb_qp_offset_plus5: i8,
+    pub act_cb_qp_offset_plus5: i8,
     /// See the specificartion for more details.
-    act_cr_qp_offset_plus3: i8,
+    pub act_cr_qp_offset_plus3: i8,
     /// When set, specifies that the palette predictor initializers used for the
     /// pictures referring to the PPS are derived based on the palette predictor
     /// initializers specified by the PPS. If not set, specifies that the
     /// palette predictor initializers used for the pictures referring to the
     /// PPS are inferred to be equal to those specified by the active SPS.
-    palette_predictor_initializers_present_flag: bool,
+    pub palette_predictor_initializers_present_flag: bool,
     /// Specifies the number of entries in the picture palette predictor
     /// initializer.
-    num_palette_predictor_initializers: u8,
+    pub num_palette_predictor_initializers: u8,
     /// When set, specifies that the pictures that refer to this PPS are
     /// monochrome. If not set, specifies that the pictures that refer to this
     /// PPS have multiple components.
-    monochrome_palette_flag: bool,
+    pub monochrome_palette_flag: bool,
     /// luma_bit_depth_entry_minus8 plus 8 specifies the bit depth of the luma
     /// component of the entries of the palette predictor initializer.
-    luma_bit_depth_entry_minus8: u8,
+    pub luma_bit_depth_entry_minus8: u8,
     /// chroma_bit_depth_entry_minus8 plus 8 specifies the bit depth of the
     /// chroma components of the entries of the palette predictor initializer.
-    chroma_bit_depth_entry_minus8: u8,
+    pub chroma_bit_depth_entry_minus8: u8,
     /// `pps_palette_predictor_initializer[ comp ][ i ]` specifies the value of
     /// the comp-th component of the i-th palette entry in the PPS that is used
     /// to initialize the array PredictorPaletteEntries.
-    palette_predictor_initializer: [[u8; 128]; 3],
+    pub palette_predictor_initializer: [[u8; 128]; 3],
 }
 
 impl PpsSccExtension {
@@ -1630,34 +1630,34 @@ pub struct PpsRangeExtension {
     /// log2_max_transform_skip_block_size_minus2 is inferred to be equal to 0.
     /// When present, the value of log2_max_transform_skip_block_size_minus2
     /// shall be less than or equal to MaxTbLog2SizeY − 2.
-    log2_max_transform_skip_block_size_minus2: u32,
+    pub log2_max_transform_skip_block_size_minus2: u32,
     /// When set, specifies that log2_res_scale_abs_plus1 and
     /// res_scale_sign_flag may be present in the transform unit syntax for
     /// pictures referring to the PPS. When not set, specifies that
     /// log2_res_scale_abs_plus1 and res_scale_sign_flag are not present for
     /// pictures referring to the PPS.
-    cross_component_prediction_enabled_flag: bool,
+    pub cross_component_prediction_enabled_flag: bool,
     /// When set, specifies that the cu_chroma_qp_offset_flag may be present in
     /// the transform unit syntax. When not set, specifies that the
     /// cu_chroma_qp_offset_flag is not present in the transform unit syntax.
-    chroma_qp_offset_list_enabled_flag: bool,
+    pub chroma_qp_offset_list_enabled_flag: bool,
     /// Specifies the difference between the luma CTB size and the minimum luma
     /// coding block size of coding units that convey cu_chroma_qp_offset_flag.
-    diff_cu_chroma_qp_offset_depth: u32,
+    pub diff_cu_chroma_qp_offset_depth: u32,
     /// chroma_qp_offset_list_len_minus1 plus 1 specifies the number of
     /// `cb_qp_offset_list[ i ]` and `cr_qp_offset_list[ i ]` syntax elements that
     /// are present in the PPS.
-    chroma_qp_offset_list_len_minus1: u32,
+    pub chroma_qp_offset_list_len_minus1: u32,
     /// Specify offsets used in the derivation of Qp′Cb and Qp′Cr, respectively.
-    cb_qp_offset_list: [i32; 6],
+    pub cb_qp_offset_list: [i32; 6],
     /// Specify offsets used in the derivation of Qp′Cb and Qp′Cr, respectively.
-    cr_qp_offset_list: [i32; 6],
+    pub cr_qp_offset_list: [i32; 6],
     /// The base 2 logarithm of the scaling parameter that is used to scale
     /// sample adaptive offset (SAO) offset values for luma samples.
-    log2_sao_offset_scale_luma: u32,
+    pub log2_sao_offset_scale_luma: u32,
     /// The base 2 logarithm of the scaling parameter that is used to scale SAO
     /// offset values for chroma samples.
-    log2_sao_offset_scale_chroma: u32,
+    pub log2_sao_offset_scale_chroma: u32,
 }
 
 impl PpsRangeExtension {
@@ -1702,92 +1702,92 @@ impl PpsRangeExtension {
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Pps {
     /// Identifies the PPS for reference by other syntax elements.
-    pic_parameter_set_id: u8,
+    pub pic_parameter_set_id: u8,
     /// Specifies the value of sps_seq_parameter_set_id for the active SPS.
-    seq_parameter_set_id: u8,
+    pub seq_parameter_set_id: u8,
     /// When set, specifies the presence of the syntax element
     /// dependent_slice_segment_flag in the slice segment headers for coded
     /// pictures referring to the PPS. When not set, specifies the absence of
     /// the syntax element dependent_slice_segment_flag in the slice segment
     /// headers for coded pictures referring to the PPS.
-    dependent_slice_segments_enabled_flag: bool,
+    pub dependent_slice_segments_enabled_flag: bool,
     /// When set, indicates that the pic_output_flag syntax element is present
     /// in the associated slice headers. When not set, indicates that the
     /// pic_output_flag syntax element is not present in the associated slice
     /// headers.
-    output_flag_present_flag: bool,
+    pub output_flag_present_flag: bool,
     /// Specifies the number of extra slice header bits that are present in the
     /// slice header RBSP for coded pictures referring to the PPS.
-    num_extra_slice_header_bits: u8,
+    pub num_extra_slice_header_bits: u8,
     /// When not set, specifies that sign bit hiding is disabled. Whens set,
     /// specifies that sign bit hiding is enabled.
-    sign_data_hiding_enabled_flag: bool,
+    pub sign_data_hiding_enabled_flag: bool,
     /// When set, specifies that cabac_init_flag is present in slice headers
     /// referring to the PPS. When not set, specifies that cabac_init_flag is
     /// not present in slice headers referring to the PPS.
-    cabac_init_present_flag: bool,
+    pub cabac_init_present_flag: bool,
     /// Specifies the inferred value of num_ref_idx_l0_active_minus1 for P and B
     /// slices with num_ref_idx_active_override_flag not set.
-    num_ref_idx_l0_default_active_minus1: u8,
+    pub num_ref_idx_l0_default_active_minus1: u8,
     /// Specifies the inferred value of num_ref_idx_l1_active_minus1 for B
     /// slices with num_ref_idx_active_override_flag not set.
-    num_ref_idx_l1_default_active_minus1: u8,
+    pub num_ref_idx_l1_default_active_minus1: u8,
     /// init_qp_minus26 plus 26 specifies the initial value of SliceQpY for each
     /// slice referring to the PPS. The initial value of SliceQpY is modified at
     /// the slice segment layer when a non-zero value of slice_qp_delta is
     /// decoded.
-    init_qp_minus26: i8,
+    pub init_qp_minus26: i8,
     /// When not set, specifies that intra prediction allows usage of residual
     /// data and decoded samples of neighbouring coding blocks coded using
     /// either intra or inter prediction modes. When set, specifies constrained
     /// intra prediction, in which case intra prediction only uses residual data
     /// and decoded samples from neighbouring coding blocks coded using intra
     /// prediction modes.
-    constrained_intra_pred_flag: bool,
+    pub constrained_intra_pred_flag: bool,
     /// When set, specifies that transform_skip_flag may be present in the
     /// residual coding syntax. When not set, specifies that transform_skip_flag
     /// is not present in the residual coding syntax.
-    transform_skip_enabled_flag: bool,
+    pub transform_skip_enabled_flag: bool,
     /// When set, specifies that the diff_cu_qp_delta_depth syntax element is
     /// present in the PPS and that cu_qp_delta_abs may be present in the
     /// transform unit syntax and the palette syntax. When not set, specifies
     /// that the diff_cu_qp_delta_depth syntax element is not present in the PPS
     /// and that cu_qp_delta_abs is not present in the transform unit syntax and
     /// the palette syntax.
-    cu_qp_delta_enabled_flag: bool,
+    pub cu_qp_delta_enabled_flag: bool,
 
     /*if cu_qp_delta_enabled_flag */
     /// Specifies the difference between the luma CTB size and the minimum luma
     /// coding block size of coding units that convey cu_qp_delta_abs and
     /// cu_qp_delta_sign_flag.
-    diff_cu_qp_delta_depth: u8,
+    pub diff_cu_qp_delta_depth: u8,
     /// Specifies the offsets to the luma quantization parameter Qp′Y used for
     /// deriving Qp′Cb and Qp′Cr, respectively.
-    cb_qp_offset: i8,
+    pub cb_qp_offset: i8,
     /// Specifies the offsets to the luma quantization parameter Qp′Y used for
     /// deriving Qp′Cb and Qp′Cr, respectively.
-    cr_qp_offset: i8,
+    pub cr_qp_offset: i8,
     /// When set, indicates that the slice_cb_qp_offset and slice_cr_qp_offset
     /// syntax elements are present in the associated slice headers.  When not
     /// set, indicates that these syntax elements are not present in the
     /// associated slice headers. When ChromaArrayType is equal to 0,
     /// pps_slice_chroma_qp_offsets_present_flag shall be equal to 0
-    slice_chroma_qp_offsets_present_flag: bool,
+    pub slice_chroma_qp_offsets_present_flag: bool,
     /// When not set, specifies that weighted prediction is not applied to P
     /// slices. When set, specifies that weighted prediction is applied to P
     /// slices.
-    weighted_pred_flag: bool,
+    pub weighted_pred_flag: bool,
     /// When not set, specifies that the default weighted prediction is applied
     /// to B slices. When set, specifies that weighted prediction is applied to
     /// B slices.
-    weighted_bipred_flag: bool,
+    pub weighted_bipred_flag: bool,
     /// When set, specifies that `cu_transquant_bypass_flag` is present, When
     /// not set, specifies that `cu_transquant_bypass_flag` is not present.
-    transquant_bypass_enabled_flag: bool,
+    pub transquant_bypass_enabled_flag: bool,
     /// When set, specifies that there is more than one tile in each picture
     /// referring to the PPS. When not set, specifies that there is only one
     /// tile in each picture referring to the PPS.
-    tiles_enabled_flag: bool,
+    pub tiles_enabled_flag: bool,
     /// When set, specifies that a specific synchronization process for context
     /// variables, and when applicable, Rice parameter initialization states and
     /// palette predictor variables, is invoked before decoding the CTU which
@@ -1806,117 +1806,117 @@ pub struct Pps {
     /// variables, is required to be invoked after decoding the CTU which
     /// includes the second CTB of a row of CTBs in each tile in each picture
     /// referring to the PPS.
-    entropy_coding_sync_enabled_flag: bool,
+    pub entropy_coding_sync_enabled_flag: bool,
     /// num_tile_columns_minus1 plus 1 specifies the number of tile columns
     /// partitioning the picture.
-    num_tile_columns_minus1: u8,
+    pub num_tile_columns_minus1: u8,
     /// num_tile_rows_minus1 plus 1 specifies the number of tile rows
     /// partitioning the picture.
-    num_tile_rows_minus1: u8,
+    pub num_tile_rows_minus1: u8,
     /// When set, specifies that tile column boundaries and likewise tile row
     /// boundaries are distributed uniformly across the picture.  When not set,
     /// specifies that tile column boundaries and likewise tile row boundaries
     /// are not distributed uniformly across the picture but signalled
     /// explicitly using the syntax elements `column_width_minus1[ i ]` and
     /// `row_height_minus1[ i ]`.
-    uniform_spacing_flag: bool,
+    pub uniform_spacing_flag: bool,
     /// `column_width_minus1[ i ]` plus 1 specifies the width of the i-th tile
     /// column in units of CTBs.
-    column_width_minus1: [u32; 19],
+    pub column_width_minus1: [u32; 19],
     /// `row_height_minus1[ i ]` plus 1 specifies the height of the i-th tile row
     /// in units of CTBs.
-    row_height_minus1: [u32; 21],
+    pub row_height_minus1: [u32; 21],
     /// When set, specifies that in-loop filtering operations may be performed
     /// across tile boundaries in pictures referring to the PPS.  When not set,
     /// specifies that in-loop filtering operations are not performed across
     /// tile boundaries in pictures referring to the PPS. The in-loop filtering
     /// operations include the deblocking filter and sample adaptive offset
     /// filter operations.
-    loop_filter_across_tiles_enabled_flag: bool,
+    pub loop_filter_across_tiles_enabled_flag: bool,
     /// When set, specifies that in-loop filtering operations may be performed
     /// across left and upper boundaries of slices referring to the PPS.  When
     /// not set, specifies that in-loop filtering operations are not performed
     /// across left and upper boundaries of slices referring to the PPS. The in-
     /// loop filtering operations include the deblocking filter and sample
     /// adaptive offset filter operations.
-    loop_filter_across_slices_enabled_flag: bool,
+    pub loop_filter_across_slices_enabled_flag: bool,
     /// When set, specifies the presence of deblocking filter control syntax
     /// elements in the PPS. When not set, specifies the absence of deblocking
     /// filter control syntax elements in the PPS.
-    deblocking_filter_control_present_flag: bool,
+    pub deblocking_filter_control_present_flag: bool,
     /// When set, specifies the presence of deblocking_filter_override_flag in
     /// the slice headers for pictures referring to the PPS.  When not set,
     /// specifies the absence of deblocking_filter_override_flag in the slice
     /// headers for pictures referring to the PPS.
-    deblocking_filter_override_enabled_flag: bool,
+    pub deblocking_filter_override_enabled_flag: bool,
     /// When set, specifies that the operation of deblocking filter is not
     /// applied for slices referring to the PPS in which
     /// slice_deblocking_filter_disabled_flag is not present.  When not set,
     /// specifies that the operation of the deblocking filter is applied for
     /// slices referring to the PPS in which
     /// slice_deblocking_filter_disabled_flag is not present.
-    deblocking_filter_disabled_flag: bool,
+    pub deblocking_filter_disabled_flag: bool,
     /// Specify the default deblocking parameter offsets for β and tC (divided
     /// by 2) that are applied for slices referring to the PPS, unless the
     /// default deblocking parameter offsets are overridden by the deblocking
     /// parameter offsets present in the slice headers of the slices referring
     /// to the PPS.
-    beta_offset_div2: i8,
+    pub beta_offset_div2: i8,
     /// Specify the default deblocking parameter offsets for β and tC (divided
     /// by 2) that are applied for slices referring to the PPS, unless the
     /// default deblocking parameter offsets are overridden by the deblocking
     /// parameter offsets present in the slice headers of the slices referring
     /// to the PPS.
-    tc_offset_div2: i8,
+    pub tc_offset_div2: i8,
     /// When set, specifies that the scaling list data used for the pictures
     /// referring to the PPS are derived based on the scaling lists specified by
     /// the active SPS and the scaling lists specified by the PPS.
     /// pps_scaling_list_data_present_flag equal to 0 specifies that the scaling
     /// list data used for the pictures referring to the PPS are inferred to be
     /// equal to those specified by the active SPS.
-    scaling_list_data_present_flag: bool,
+    pub scaling_list_data_present_flag: bool,
     /// The scaling list data.
-    scaling_list: ScalingLists,
+    pub scaling_list: ScalingLists,
     /// When set, specifies that the syntax structure
     /// ref_pic_lists_modification( ) is present in the slice segment header.
     /// When not set, specifies that the syntax structure
     /// ref_pic_lists_modification( ) is not present in the slice segment header
-    lists_modification_present_flag: bool,
+    pub lists_modification_present_flag: bool,
     /// log2_parallel_merge_level_minus2 plus 2 specifies the value of the
     /// variable Log2ParMrgLevel, which is used in the derivation process for
     /// luma motion vectors for merge mode as specified in clause 8.5.3.2.2 and
     /// the derivation process for spatial merging candidates as specified in
     /// clause 8.5.3.2.3.
-    log2_parallel_merge_level_minus2: u8,
+    pub log2_parallel_merge_level_minus2: u8,
     /// When not set, specifies that no slice segment header extension syntax
     /// elements are present in the slice segment headers for coded pictures
     /// referring to the PPS. When set, specifies that slice segment header
     /// extension syntax elements are present in the slice segment headers for
     /// coded pictures referring to the PPS.
-    slice_segment_header_extension_present_flag: bool,
+    pub slice_segment_header_extension_present_flag: bool,
     /// When set, specifies that the syntax elements pps_range_extension_flag,
     /// pps_multilayer_extension_flag, pps_3d_extension_flag,
     /// pps_scc_extension_flag, and pps_extension_4bits are present in the
     /// picture parameter set RBSP syntax structure. When not set, specifies
     /// that these syntax elements are not present.
-    extension_present_flag: bool,
+    pub extension_present_flag: bool,
     /// When setspecifies that the pps_range_extension( ) syntax structure is
     /// present in the PPS RBSP syntax structure. When not set, specifies that
     /// this syntax structure is not present.
-    range_extension_flag: bool,
+    pub range_extension_flag: bool,
     /// The range extension data.
-    range_extension: PpsRangeExtension,
+    pub range_extension: PpsRangeExtension,
 
-    scc_extension_flag: bool,
+    pub scc_extension_flag: bool,
     /// The SCC extension data.
-    scc_extension: PpsSccExtension,
+    pub scc_extension: PpsSccExtension,
 
     // Internal variables.
     /// Equivalent to QpBdOffsetY in the specification.
-    qp_bd_offset_y: u32,
+    pub qp_bd_offset_y: u32,
 
     /// The nuh_temporal_id_plus1 - 1 of the associated NALU.
-    temporal_id: u8,
+    pub temporal_id: u8,
 }
 
 impl Pps {
@@ -2162,18 +2162,18 @@ impl Default for Pps {
 pub struct ScalingLists {
     /// plus 8 specifies the value of the variable `ScalingFactor[ 2 ][ matrixId
     /// ] [ 0 ][ 0 ]` for the scaling list for the 16x16 size.
-    scaling_list_dc_coef_minus8_16x16: [i16; 6],
+    pub scaling_list_dc_coef_minus8_16x16: [i16; 6],
     /// plus 8 specifies the value of the variable `ScalingFactor[ 3 ][ matrixId
     /// ][ 0 ][ 0 ]` for the scaling list for the 32x32 size.
-    scaling_list_dc_coef_minus8_32x32: [i16; 6],
+    pub scaling_list_dc_coef_minus8_32x32: [i16; 6],
     /// The 4x4 scaling list.
-    scaling_list_4x4: [[u8; 16]; 6],
+    pub scaling_list_4x4: [[u8; 16]; 6],
     /// The 8x8 scaling list.
-    scaling_list_8x8: [[u8; 64]; 6],
+    pub scaling_list_8x8: [[u8; 64]; 6],
     /// The 16x16 scaling list.
-    scaling_list_16x16: [[u8; 64]; 6],
+    pub scaling_list_16x16: [[u8; 64]; 6],
     /// The 32x32 scaling list.
-    scaling_list_32x32: [[u8; 64]; 6],
+    pub scaling_list_32x32: [[u8; 64]; 6],
 }
 
 impl ScalingLists {
@@ -2220,19 +2220,19 @@ pub struct RefPicListModification {
     /// Whenset, indicates that reference picture list 0 is specified explicitly
     /// by a list of `list_entry_l0[ i ]` values.  When not set, indicates that
     /// reference picture list 0 is determined implicitly.
-    ref_pic_list_modification_flag_l0: bool,
+    pub ref_pic_list_modification_flag_l0: bool,
     /// `list_entry_l0[ i ]` specifies the index of the reference picture in
     /// RefPicListTemp0 to be placed at the current position of reference
     /// picture list 0.
-    list_entry_l0: Vec<u32>,
+    pub list_entry_l0: Vec<u32>,
     /// Whenset, indicates that reference picture list 1 is specified explicitly
     /// by a list of `list_entry_l1[ i ]` values.  When not set, indicates that
     /// reference picture list 1 is determined implicitly.
-    ref_pic_list_modification_flag_l1: bool,
+    pub ref_pic_list_modification_flag_l1: bool,
     /// `list_entry_l1[ i ]` specifies the index of the reference picture in
     /// RefPicListTemp1 to be placed at the current position of reference
     /// picture list 1.
-    list_entry_l1: Vec<u32>,
+    pub list_entry_l1: Vec<u32>,
 }
 
 impl RefPicListModification {
@@ -2256,35 +2256,35 @@ impl RefPicListModification {
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub struct PredWeightTable {
     /// The base 2 logarithm of the denominator for all luma weighting factors.
-    luma_log2_weight_denom: u8,
+    pub luma_log2_weight_denom: u8,
     /// The difference of the base 2 logarithm of the denominator for all chroma
     /// weighting factors.
-    delta_chroma_log2_weight_denom: i8,
+    pub delta_chroma_log2_weight_denom: i8,
     /// `luma_weight_l0_flag[ i ]` set specifies that weighting factors for the
     /// luma component of list 0 prediction using `RefPicList0[ i ]` are present.
     /// `luma_weight_l0_flag[ i ]` not set specifies that these weighting factors
     /// are not present.
-    luma_weight_l0_flag: [bool; 15],
+    pub luma_weight_l0_flag: [bool; 15],
     /// `chroma_weight_l0_flag[ i ]` set specifies that weighting factors for the
     /// chroma prediction values of list 0 prediction using `RefPicList0[ i ]` are
     /// present. `chroma_weight_l0_flag[ i ]` not set specifies that these
     /// weighting factors are not present.
-    chroma_weight_l0_flag: [bool; 15],
+    pub chroma_weight_l0_flag: [bool; 15],
     /// `delta_luma_weight_l0[ i ]` is the difference of the weighting factor
     /// applied to the luma prediction value for list 0 prediction using
     /// `RefPicList0[ i ]`.
-    delta_luma_weight_l0: [i8; 15],
+    pub delta_luma_weight_l0: [i8; 15],
     /// `luma_offset_l0[ i ]` is the additive offset applied to the luma
     /// prediction value for list 0 prediction using `RefPicList0[ i ]`.
-    luma_offset_l0: [i8; 15],
+    pub luma_offset_l0: [i8; 15],
     /// `delta_chroma_weight_l0[ i ][ j ]` is the difference of the weighting
     /// factor applied to the chroma prediction values for list 0 prediction
     /// using `RefPicList0[ i ]` with j equal to 0 for Cb and j equal to 1 for Cr.
-    delta_chroma_weight_l0: [[i8; 2]; 15],
+    pub delta_chroma_weight_l0: [[i8; 2]; 15],
     /// `delta_chroma_offset_l0[ i ][ j ]` is the difference of the additive
     /// offset applied to the chroma prediction values for list 0 prediction
     /// using `RefPicList0[ i ]` with j equal to 0 for Cb and j equal to 1 for Cr.
-    delta_chroma_offset_l0: [[i16; 2]; 15],
+    pub delta_chroma_offset_l0: [[i16; 2]; 15],
 
     // `luma_weight_l1_flag[ i ]`, `chroma_weight_l1_flag[ i ]`,
     // `delta_luma_weight_l1[ i ]`, `luma_offset_l1[ i ]`, delta_chroma_weight_l1[ i
@@ -2293,17 +2293,17 @@ pub struct PredWeightTable {
     // `delta_luma_weight_l0[ i ]`, `luma_offset_l0[ i ]`, `delta_chroma_weight_l0[ i
     // ][ j ]` and `delta_chroma_offset_l0[ i ][ j ]`, respectively, with `l0`, `L0`,
     // `list 0` and `List0` replaced by `l1`, `L1`, `list 1` and `List1`, respectively.
-    luma_weight_l1_flag: [bool; 15],
-    chroma_weight_l1_flag: [bool; 15],
-    delta_luma_weight_l1: [i8; 15],
-    luma_offset_l1: [i8; 15],
+    pub luma_weight_l1_flag: [bool; 15],
+    pub chroma_weight_l1_flag: [bool; 15],
+    pub delta_luma_weight_l1: [i8; 15],
+    pub luma_offset_l1: [i8; 15],
 
-    delta_chroma_weight_l1: [[i8; 2]; 15],
-    delta_chroma_offset_l1: [[i16; 2]; 15],
+    pub delta_chroma_weight_l1: [[i8; 2]; 15],
+    pub delta_chroma_offset_l1: [[i16; 2]; 15],
 
     // Calculated.
     /// Same as ChromaLog2WeightDenom in the specification.
-    chroma_log2_weight_denom: u8,
+    pub chroma_log2_weight_denom: u8,
 }
 
 impl PredWeightTable {
@@ -2373,35 +2373,35 @@ pub struct ShortTermRefPicSet {
     /// When set, specifies that the stRpsIdx-th candidate short-term RPS is
     /// predicted from another candidate short-term RPS, which is referred to as
     /// the source candidate short-term RPS.
-    inter_ref_pic_set_prediction_flag: bool,
+    pub inter_ref_pic_set_prediction_flag: bool,
     /// delta_idx_minus1 plus 1 specifies the difference between the value of
     /// stRpsIdx and the index, into the list of the candidate short-term RPSs
     /// specified in the SPS, of the source candidate short-term RPS.
-    delta_idx_minus1: u8,
+    pub delta_idx_minus1: u8,
     /// delta_rps_sign and abs_delta_rps_minus1 together specify the value of
     /// the variable deltaRps.
-    delta_rps_sign: bool,
+    pub delta_rps_sign: bool,
     /// delta_rps_sign and abs_delta_rps_minus1 together specify the value of
     /// the variable deltaRps.
-    abs_delta_rps_minus1: u16,
+    pub abs_delta_rps_minus1: u16,
     /// specifies the number of entries in the stRpsIdx-th candidate short-term
     /// RPS that have picture order count values less than the picture order
     /// count value of the current picture.
-    num_negative_pics: u8,
+    pub num_negative_pics: u8,
     /// specifies the number of entries in the stRpsIdx-th candidate short-term
     /// RPS that have picture order count values greater than the picture order
     /// count value of the current picture.
-    num_positive_pics: u8,
+    pub num_positive_pics: u8,
     /// Same as UsedByCurrPicS0 in the specification.
-    used_by_curr_pic_s0: [bool; MAX_SHORT_TERM_REF_PIC_SETS],
+    pub used_by_curr_pic_s0: [bool; MAX_SHORT_TERM_REF_PIC_SETS],
     /// Same as UsedByCurrPicS1 in the specification.
-    used_by_curr_pic_s1: [bool; MAX_SHORT_TERM_REF_PIC_SETS],
+    pub used_by_curr_pic_s1: [bool; MAX_SHORT_TERM_REF_PIC_SETS],
     /// Same as DeltaPocS0 in the specification.
-    delta_poc_s0: [i32; MAX_SHORT_TERM_REF_PIC_SETS],
+    pub delta_poc_s0: [i32; MAX_SHORT_TERM_REF_PIC_SETS],
     /// Same as DeltaPocS1 in the specification.
-    delta_poc_s1: [i32; MAX_SHORT_TERM_REF_PIC_SETS],
+    pub delta_poc_s1: [i32; MAX_SHORT_TERM_REF_PIC_SETS],
     /// Same as NumDeltaPocs in the specification.
-    num_delta_pocs: u32,
+    pub num_delta_pocs: u32,
 }
 
 impl ShortTermRefPicSet {
@@ -2504,34 +2504,34 @@ pub struct SliceHeader {
     /// When set, specifies that the slice segment is the first slice segment of
     /// the picture in decoding order. When not set, specifies that the slice
     /// segment is not the first slice segment of the picture in decoding order.
-    first_slice_segment_in_pic_flag: bool,
+    pub first_slice_segment_in_pic_flag: bool,
     /// Affects the output of previously-decoded pictures in the decoded picture
     /// buffer after the decoding of an IDR or a BLA picture that is not the
     /// first picture in the bitstream as specified in Annex C.
-    no_output_of_prior_pics_flag: bool,
+    pub no_output_of_prior_pics_flag: bool,
     /// Specifies the value of pps_pic_parameter_set_id for the PPS in use.
-    pic_parameter_set_id: u8,
+    pub pic_parameter_set_id: u8,
     /// When set, specifies that the value of each slice segment header syntax
     /// element that is not present is inferred to be equal to the value of the
     /// corresponding slice segment header syntax element in the slice header.
-    dependent_slice_segment_flag: bool,
+    pub dependent_slice_segment_flag: bool,
     /// Specifies the address of the first CTB in the slice segment, in CTB
     /// raster scan of a picture.
-    segment_address: u32,
+    pub segment_address: u32,
     /// Specifies the coding type of the slice according to Table 7-7.
-    type_: SliceType,
+    pub type_: SliceType,
     /// Affects the decoded picture output and removal processes as specified in
     /// Annex C.
-    pic_output_flag: bool,
+    pub pic_output_flag: bool,
     /// Specifies the colour plane associated with the current slice RBSP when
     /// separate_colour_plane_flag is set. The value of colour_plane_id shall be
     /// in the range of 0 to 2, inclusive. colour_plane_id values 0, 1 and 2
     /// correspond to the Y, Cb and Cr planes, respectively.
-    colour_plane_id: u8,
+    pub colour_plane_id: u8,
     /// Specifies the picture order count modulo MaxPicOrderCntLsb for the
     /// current picture. The length of the slice_pic_order_cnt_lsb syntax
     /// element is log2_max_pic_order_cnt_lsb_minus4 + 4 bits.
-    pic_order_cnt_lsb: u16,
+    pub pic_order_cnt_lsb: u16,
     /// When set, specifies that the short-term RPS of the current picture is
     /// derived based on one of the st_ref_pic_set( ) syntax structures in the
     /// active SPS that is identified by the syntax element
@@ -2539,155 +2539,155 @@ pub struct SliceHeader {
     /// that the short-term RPS of the current picture is derived based on the
     /// st_ref_pic_set( ) syntax structure that is directly included in the
     /// slice headers of the current picture.
-    short_term_ref_pic_set_sps_flag: bool,
+    pub short_term_ref_pic_set_sps_flag: bool,
     /// The st_ref_pic_set() data.
-    short_term_ref_pic_set: ShortTermRefPicSet,
+    pub short_term_ref_pic_set: ShortTermRefPicSet,
     /// Specifies the index, into the list of the st_ref_pic_set( ) syntax
     /// structures included in the active SPS, of the st_ref_pic_set( ) syntax
     /// structure that is used for derivation of the short-term RPS of the
     /// current picture.
-    short_term_ref_pic_set_idx: u8,
+    pub short_term_ref_pic_set_idx: u8,
     /// Specifies the number of entries in the long-term RPS of the current
     /// picture that are derived based on the candidate long-term reference
     /// pictures specified in the active SPS.
-    num_long_term_sps: u8,
+    pub num_long_term_sps: u8,
     /// Specifies the number of entries in the long-term RPS of the current
     /// picture that are directly signalled in the slice header.
-    num_long_term_pics: u8,
+    pub num_long_term_pics: u8,
     /// `lt_idx_sps[ i ]` specifies an index, into the list of candidate long-term
     /// reference pictures specified in the active SPS, of the i-th entry in the
     /// long-term RPS of the current picture.
-    lt_idx_sps: [u8; 16],
+    pub lt_idx_sps: [u8; 16],
     /// Same as PocLsbLt in the specification.
-    poc_lsb_lt: [u32; 16],
+    pub poc_lsb_lt: [u32; 16],
     /// Same as UsedByCurrPicLt in the specification.
-    used_by_curr_pic_lt: [bool; 16],
+    pub used_by_curr_pic_lt: [bool; 16],
     /// When set, specifies that that `delta_poc_msb_cycle_lt[i]` is present.
-    delta_poc_msb_present_flag: [bool; 16],
+    pub delta_poc_msb_present_flag: [bool; 16],
     /// Same as DeltaPocMsbCycleLt in the specification.
-    delta_poc_msb_cycle_lt: [u32; 16],
+    pub delta_poc_msb_cycle_lt: [u32; 16],
     /// Specifies whether temporal motion vector predictors can be used for
     /// inter prediction. If slice_temporal_mvp_enabled_flag is not set, the
     /// syntax elements of the current picture shall be constrained such that no
     /// temporal motion vector predictor is used in decoding of the current
     /// picture. Otherwise (slice_temporal_mvp_enabled_flag is set), temporal
     /// motion vector predictors may be used in decoding of the current picture.
-    temporal_mvp_enabled_flag: bool,
+    pub temporal_mvp_enabled_flag: bool,
     /// When set, specifies that SAO is enabled for the luma component in the
     /// current slice; slice_sao_luma_flag not set specifies that SAO is
     /// disabled for the luma component in the current slice.
-    sao_luma_flag: bool,
+    pub sao_luma_flag: bool,
     /// When set, specifies that SAO is enabled for the chroma component in the
     /// current slice; When not set, specifies that SAO is disabled for the
     /// chroma component in the current slice.
-    sao_chroma_flag: bool,
+    pub sao_chroma_flag: bool,
     /// When set, specifies that the syntax element num_ref_idx_l0_active_minus1
     /// is present for P and B slices and that the syntax element
     /// num_ref_idx_l1_active_minus1 is present for B slices. When not set,
     /// specifies that the syntax elements num_ref_idx_l0_active_minus1 and
     /// num_ref_idx_l1_active_minus1 are not present.
-    num_ref_idx_active_override_flag: bool,
+    pub num_ref_idx_active_override_flag: bool,
     /// Specifies the maximum reference index for
     /// reference picture list 0 that may be used to decode the slice.
-    num_ref_idx_l0_active_minus1: u8,
+    pub num_ref_idx_l0_active_minus1: u8,
     /// Specifies the maximum reference index for reference picture list 1 that
     /// may be used to decode the slice.
-    num_ref_idx_l1_active_minus1: u8,
+    pub num_ref_idx_l1_active_minus1: u8,
     /// The RefPicListModification data.
-    ref_pic_list_modification: RefPicListModification,
+    pub ref_pic_list_modification: RefPicListModification,
     /// When set, indicates that the mvd_coding( x0, y0, 1 ) syntax structure is
     /// not parsed and `MvdL1[ x0 ]`[ y0 `][ compIdx ]` is set equal to 0 for
     /// compIdx = 0..1. When not set, indicates that the mvd_coding( x0, y0, 1 )
     /// syntax structure is parsed.
-    mvd_l1_zero_flag: bool,
+    pub mvd_l1_zero_flag: bool,
     /// Specifies the method for determining the initialization table used in
     /// the initialization process for context variables.
-    cabac_init_flag: bool,
+    pub cabac_init_flag: bool,
     /// When set, specifies that the collocated picture used for temporal motion
     /// vector prediction is derived from reference picture list 0.  When not
     /// set, specifies that the collocated picture used for temporal motion
     /// vector prediction is derived from reference picture list 1.
-    collocated_from_l0_flag: bool,
+    pub collocated_from_l0_flag: bool,
     /// Specifies the reference index of the collocated picture used for
     /// temporal motion vector prediction.
-    collocated_ref_idx: u8,
+    pub collocated_ref_idx: u8,
     /// The PredWeightTable data.
-    pred_weight_table: PredWeightTable,
+    pub pred_weight_table: PredWeightTable,
     /// Specifies the maximum number of merging motion vector prediction (MVP)
     /// candidates supported in the slice subtracted from 5.
-    five_minus_max_num_merge_cand: u8,
+    pub five_minus_max_num_merge_cand: u8,
     /// Specifies that the resolution of motion vectors for inter prediction in
     /// the current slice is integer. When not set, specifies
     /// that the resolution of motion vectors for inter prediction in the
     /// current slice that refer to pictures other than the current picture is
     /// fractional with quarter-sample precision in units of luma samples.
-    use_integer_mv_flag: bool,
+    pub use_integer_mv_flag: bool,
     /// Specifies the initial value of QpY to be used for the coding blocks in
     /// the slice until modified by the value of CuQpDeltaVal in the coding unit
     /// layer.
-    qp_delta: i8,
+    pub qp_delta: i8,
     /// Specifies a difference to be added to the value of pps_cb_qp_offset when
     /// determining the value of the Qp′Cb quantization parameter.
-    cb_qp_offset: i8,
+    pub cb_qp_offset: i8,
     /// Specifies a difference to be added to the value of pps_cb_qr_offset when
     /// determining the value of the Qp′Cr quantization parameter.
-    cr_qp_offset: i8,
+    pub cr_qp_offset: i8,
     /// Specifies offsets to the quantization parameter values qP derived in
     /// clause 8.6.2 for luma, Cb, and Cr components, respectively.
-    slice_act_y_qp_offset: i8,
+    pub slice_act_y_qp_offset: i8,
     /// Specifies offsets to the quantization parameter values qP derived in
     /// clause 8.6.2 for luma, Cb, and Cr components, respectively.
-    slice_act_cb_qp_offset: i8,
+    pub slice_act_cb_qp_offset: i8,
     /// Specifies offsets to the quantization parameter values qP derived in
     /// clause 8.6.2 for luma, Cb, and Cr components, respectively.
-    slice_act_cr_qp_offset: i8,
+    pub slice_act_cr_qp_offset: i8,
     /// When set, specifies that the cu_chroma_qp_offset_flag may be present in
     /// the transform unit syntax. When not set, specifies that the
     /// cu_chroma_qp_offset_flag is not present in the transform unit syntax.
-    cu_chroma_qp_offset_enabled_flag: bool,
+    pub cu_chroma_qp_offset_enabled_flag: bool,
     /// When set, specifies that deblocking parameters are present in the slice
     /// header. When not set, specifies that deblocking parameters are not
     /// present in the slice header.
-    deblocking_filter_override_flag: bool,
+    pub deblocking_filter_override_flag: bool,
     /// When set, specifies that the operation of the deblocking filter is not
     /// applied for the current slice. When not set, specifies that the
     /// operation of the deblocking filter is applied for the current slice.
-    deblocking_filter_disabled_flag: bool,
+    pub deblocking_filter_disabled_flag: bool,
     /// Specifies the deblocking parameter offsets for β and tC (divided by 2)
     /// for the current slice.
-    beta_offset_div2: i8,
+    pub beta_offset_div2: i8,
     /// Specifies the deblocking parameter offsets for β and tC (divided by 2)
     /// for the current slice.
-    tc_offset_div2: i8,
+    pub tc_offset_div2: i8,
     /// When set, specifies that in-loop filtering operations may be performed
     /// across the left and upper boundaries of the current slice.  When not
     /// set, specifies that in-loop operations are not performed across left and
     /// upper boundaries of the current slice. The in-loop filtering operations
     /// include the deblocking filter and sample adaptive offset filter.
-    loop_filter_across_slices_enabled_flag: bool,
+    pub loop_filter_across_slices_enabled_flag: bool,
     /// Specifies the number of `entry_point_offset_minus1[ i ]` syntax elements
     /// in the slice header.
-    num_entry_point_offsets: u32,
+    pub num_entry_point_offsets: u32,
     /// offset_len_minus1 plus 1 specifies the length, in bits, of the
     /// `entry_point_offset_minus1[ i ]` syntax elements.
-    offset_len_minus1: u8,
+    pub offset_len_minus1: u8,
     /// `entry_point_offset_minus1[ i ]` plus 1 specifies the i-th entry point
     /// offset in bytes, and is represented by offset_len_minus1 plus 1 bits.
     /// The slice segment data that follow the slice segment header consists of
     /// num_entry_point_offsets + 1 subsets, with subset index values ranging
     /// from 0 to num_entry_point_offsets, inclusive. See the specification for
     /// more details.
-    entry_point_offset_minus1: [u32; 32],
+    pub entry_point_offset_minus1: [u32; 32],
     /// Same as NumPicTotalCurr in the specification.
-    num_pic_total_curr: u32,
+    pub num_pic_total_curr: u32,
     // Size of slice_header() in bits.
-    header_bit_size: u32,
+    pub header_bit_size: u32,
     // Number of emulation prevention bytes (EPB) in this slice_header().
-    n_emulation_prevention_bytes: u32,
+    pub n_emulation_prevention_bytes: u32,
     /// Same as CurrRpsIdx in the specification.
-    curr_rps_idx: u8,
+    pub curr_rps_idx: u8,
     /// Number of bits taken by st_ref_pic_set minus Emulation Prevention Bytes.
-    st_rps_bits: u32,
+    pub st_rps_bits: u32,
 }
 
 impl SliceHeader {
@@ -3037,21 +3037,21 @@ pub struct SublayerHrdParameters {
     /// `bit_rate_value_minus1[ i ]` (together with bit_rate_scale) specifies the
     /// maximum input bit rate for the i-th CPB when the CPB operates at the
     /// access unit level
-    bit_rate_value_minus1: [u32; 32],
+    pub bit_rate_value_minus1: [u32; 32],
     /// `cpb_size_value_minus1[ i ]` is used together with cpb_size_scale to
     /// specify the i-th CPB size when the CPB operates at the access unit
     /// level.
-    cpb_size_value_minus1: [u32; 32],
+    pub cpb_size_value_minus1: [u32; 32],
     /// `cpb_size_du_value_minus1[ i ]` is used together with cpb_size_du_scale to
     /// specify the i-th CPB size when the CPB operates at sub-picture level.
-    cpb_size_du_value_minus1: [u32; 32],
+    pub cpb_size_du_value_minus1: [u32; 32],
     /// `bit_rate_du_value_minus1[ i ]` (together with bit_rate_scale) specifies
     /// the maximum input bit rate for the i-th CPB when the CPB operates at the
     /// sub-picture level.
-    bit_rate_du_value_minus1: [u32; 32],
+    pub bit_rate_du_value_minus1: [u32; 32],
     /// `cbr_flag[ i ]` not set specifies that to decode this CVS by the HRD using
     /// the i-th CPB specification.
-    cbr_flag: [bool; 32],
+    pub cbr_flag: [bool; 32],
 }
 
 impl SublayerHrdParameters {
@@ -3078,27 +3078,27 @@ pub struct HrdParams {
     /// bitstream conformance point) are present in the hrd_parameters( ) syntax
     /// structure. When not set, specifies that NAL HRD parameters are not
     /// present in the hrd_parameters( ) syntax structure.
-    nal_hrd_parameters_present_flag: bool,
+    pub nal_hrd_parameters_present_flag: bool,
     /// When set, specifies that VCL HRD parameters (pertaining to the Type I
     /// bitstream conformance point) are present in the hrd_parameters( ) syntax
     /// structure. When not set, specifies that VCL HRD parameters are not
     /// present in the hrd_parameters( ) syntax structure.
-    vcl_hrd_parameters_present_flag: bool,
+    pub vcl_hrd_parameters_present_flag: bool,
     /// When set, specifies that sub-picture level HRD parameters are present
     /// and the HRD may operate at access unit level or sub-picture level. When
     /// not set, specifies that sub-picture level HRD parameters are not present
     /// and the HRD operates at access unit level.
-    sub_pic_hrd_params_present_flag: bool,
+    pub sub_pic_hrd_params_present_flag: bool,
     /// Used to specify the clock sub-tick. A clock sub-tick is the minimum
     /// interval of time that can be represented in the coded data when
     /// sub_pic_hrd_params_present_flag is set.
-    tick_divisor_minus2: u8,
+    pub tick_divisor_minus2: u8,
     /// du_cpb_removal_delay_increment_length_minus1 plus 1 specifies the
     /// length, in bits, of the `du_cpb_removal_delay_increment_minus1[ i ]` and
     /// du_common_cpb_removal_delay_increment_minus1 syntax elements of the
     /// picture timing SEI message and the du_spt_cpb_removal_delay_increment
     /// syntax element in the decoding unit information SEI message.
-    du_cpb_removal_delay_increment_length_minus1: u8,
+    pub du_cpb_removal_delay_increment_length_minus1: u8,
     /// When set, specifies that sub-picture level CPB removal delay parameters
     /// are present in picture timing SEI messages and no decoding unit
     /// information SEI message is available (in the CVS or provided through
@@ -3106,65 +3106,65 @@ pub struct HrdParams {
     /// specifies that sub-picture level CPB removal delay parameters are
     /// present in decoding unit information SEI messages and picture timing SEI
     /// messages do not include sub-picture level CPB removal delay parameters.
-    sub_pic_cpb_params_in_pic_timing_sei_flag: bool,
+    pub sub_pic_cpb_params_in_pic_timing_sei_flag: bool,
     /// dpb_output_delay_du_length_minus1 plus 1 specifies the length, in bits,
     /// of the pic_dpb_output_du_delay syntax element in the picture timing SEI
     /// message and the pic_spt_dpb_output_du_delay syntax element in the
     /// decoding unit information SEI message.
-    dpb_output_delay_du_length_minus1: u8,
+    pub dpb_output_delay_du_length_minus1: u8,
     /// Together with `bit_rate_value_minus1[ i ]`, specifies the maximum input
     /// bit rate of the i-th CPB.
-    bit_rate_scale: u8,
+    pub bit_rate_scale: u8,
     /// Together with `cpb_size_du_value_minus1[ i ]`, specifies the CPB size of
     /// the i-th CPB when the CPB operates at sub-picture level.
-    cpb_size_scale: u8,
+    pub cpb_size_scale: u8,
     /// Together with `cpb_size_du_value_minus1[ i ]`, specifies the CPB size of
     /// the i-th CPB when the CPB operates at sub-picture level.
-    cpb_size_du_scale: u8,
+    pub cpb_size_du_scale: u8,
     /// initial_cpb_removal_delay_length_minus1 plus 1 specifies the length, in
     /// bits, of the `nal_initial_cpb_removal_delay[ i ]`,
     /// `nal_initial_cpb_removal_offset[ i ]`, `vcl_initial_cpb_removal_delay[ i ]`
     /// and `vcl_initial_cpb_removal_offset[ i ]` syntax elements of the buffering
     /// period SEI message.
-    initial_cpb_removal_delay_length_minus1: u8,
+    pub initial_cpb_removal_delay_length_minus1: u8,
     /// au_cpb_removal_delay_length_minus1 plus 1 specifies the length, in bits,
     /// of the cpb_delay_offset syntax element in the buffering period SEI
     /// message and the au_cpb_removal_delay_minus1 syntax element in the
     /// picture timing SEI message.
-    au_cpb_removal_delay_length_minus1: u8,
+    pub au_cpb_removal_delay_length_minus1: u8,
     /// dpb_output_delay_length_minus1 plus 1 specifies the length, in bits, of
     /// the dpb_delay_offset syntax element in the buffering period SEI message
     /// and the pic_dpb_output_delay syntax element in the picture timing SEI
     /// message.
-    dpb_output_delay_length_minus1: u8,
+    pub dpb_output_delay_length_minus1: u8,
     /// `fixed_pic_rate_general_flag[ i ]` set indicates that, when HighestTid is
     /// equal to i, the temporal distance between the HRD output times of
     /// consecutive pictures in output order is constrained as specified in the
     /// specification. `fixed_pic_rate_general_flag[ i ]` not set indicates that
     /// this constraint may not apply.
-    fixed_pic_rate_general_flag: [bool; 7],
+    pub fixed_pic_rate_general_flag: [bool; 7],
     /// `fixed_pic_rate_within_cvs_flag[ i ]` set indicates that, when HighestTid
     /// is equal to i, the temporal distance between the HRD output times of
     /// consecutive pictures in output order is constrained as specified in the
     /// specification. `fixed_pic_rate_within_cvs_flag[ i ]` not set indicates
     /// that this constraint may not apply.
-    fixed_pic_rate_within_cvs_flag: [bool; 7],
+    pub fixed_pic_rate_within_cvs_flag: [bool; 7],
     /// `elemental_duration_in_tc_minus1[ i ]` plus 1 (when present) specifies,
     /// when HighestTid is equal to i, the temporal distance, in clock ticks,
     /// between the elemental units that specify the HRD output times of
     /// consecutive pictures in output order as specified in the specification.
-    elemental_duration_in_tc_minus1: [u32; 7],
+    pub elemental_duration_in_tc_minus1: [u32; 7],
     /// `low_delay_hrd_flag[ i ]` specifies the HRD operational mode, when
     /// HighestTid is equal to i, as specified in Annex C or clause F.13.
-    low_delay_hrd_flag: [bool; 7],
+    pub low_delay_hrd_flag: [bool; 7],
     /// `cpb_cnt_minus1[ i ]` plus 1 specifies the number of alternative CPB
     /// specifications in the bitstream of the CVS when HighestTid is equal to
     /// i.
-    cpb_cnt_minus1: [u32; 7],
+    pub cpb_cnt_minus1: [u32; 7],
     /// The NAL HRD data.
-    nal_hrd: [SublayerHrdParameters; 7],
+    pub nal_hrd: [SublayerHrdParameters; 7],
     /// The VCL HRD data.
-    vcl_hrd: [SublayerHrdParameters; 7],
+    pub vcl_hrd: [SublayerHrdParameters; 7],
 }
 
 impl HrdParams {
@@ -3254,7 +3254,7 @@ impl Default for HrdParams {
         Self {
             initial_cpb_removal_delay_length_minus1: 23,
             au_cpb_removal_delay_length_minus1: 23,
-            dpb_output_delay_du_length_minus1: 23,
+            dpb_output_delay_length_minus1: 23,
             nal_hrd_parameters_present_flag: Default::default(),
             vcl_hrd_parameters_present_flag: Default::default(),
             sub_pic_hrd_params_present_flag: Default::default(),
@@ -3264,7 +3264,7 @@ impl Default for HrdParams {
             bit_rate_scale: Default::default(),
             cpb_size_scale: Default::default(),
             cpb_size_du_scale: Default::default(),
-            dpb_output_delay_length_minus1: Default::default(),
+            dpb_output_delay_du_length_minus1: Default::default(),
             fixed_pic_rate_general_flag: Default::default(),
             fixed_pic_rate_within_cvs_flag: Default::default(),
             elemental_duration_in_tc_minus1: Default::default(),
@@ -3280,112 +3280,112 @@ impl Default for HrdParams {
 pub struct VuiParams {
     /// When set, specifies that aspect_ratio_idc is present.  When not set,
     /// specifies that aspect_ratio_idc is not present.
-    aspect_ratio_info_present_flag: bool,
+    pub aspect_ratio_info_present_flag: bool,
     /// Specifies the value of the sample aspect ratio of the luma samples.
-    aspect_ratio_idc: u32,
+    pub aspect_ratio_idc: u32,
     /// Indicates the horizontal size of the sample aspect ratio (in arbitrary
     /// units).
-    sar_width: u32,
+    pub sar_width: u32,
     /// Indicates the vertical size of the sample aspect ratio (in arbitrary
     /// units).
-    sar_height: u32,
+    pub sar_height: u32,
     /// When set, specifies that the overscan_appropriate_flag is present. When
     /// not set, the preferred display method for the video signal is
     /// unspecified.
-    overscan_info_present_flag: bool,
+    pub overscan_info_present_flag: bool,
     /// When set indicates that the cropped decoded pictures output are suitable
     /// for display using overscan. When not set, indicates that the cropped
     /// decoded pictures output contain visually important information in the
     /// entire region out to the edges of the conformance cropping window of the
     /// picture, such that the cropped decoded pictures output should not be
     /// displayed using overscan.
-    overscan_appropriate_flag: bool,
+    pub overscan_appropriate_flag: bool,
     /// When set, specifies that video_format, video_full_range_flag and
     /// colour_description_present_flag are present.  When not set, specify that
     /// video_format, video_full_range_flag and colour_description_present_flag
     /// are not present.
-    video_signal_type_present_flag: bool,
+    pub video_signal_type_present_flag: bool,
     /// Indicates the representation of the pictures as specified in Table E.2,
     /// before being coded in accordance with this Specification.
-    video_format: u8,
+    pub video_format: u8,
     /// Indicates the black level and range of the luma and chroma signals as
     /// derived from E′Y, E′PB, and E′PR or E′R, E′G, and E′B real-valued
     /// component signals.
-    video_full_range_flag: bool,
+    pub video_full_range_flag: bool,
     /// When set, specifies that colour_primaries, transfer_characteristics, and
     /// matrix_coeffs are present. When not set, specifies that
     /// colour_primaries, transfer_characteristics, and matrix_coeffs are not
     /// present.
-    colour_description_present_flag: bool,
+    pub colour_description_present_flag: bool,
     /// Indicates the chromaticity coordinates of the source primaries as
     /// specified in Table E.3 in terms of the CIE 1931 definition of x and y as
     /// specified in ISO 11664-1.
-    colour_primaries: u32,
+    pub colour_primaries: u32,
     /// See table E.4 in the specification.
-    transfer_characteristics: u32,
+    pub transfer_characteristics: u32,
     /// Describes the matrix coefficients used in deriving luma and chroma
     /// signals from the green, blue, and red, or Y, Z, and X primaries, as
     /// specified in Table E.5.
-    matrix_coeffs: u32,
+    pub matrix_coeffs: u32,
     /// When true, specifies that chroma_sample_loc_type_top_field and
     /// chroma_sample_loc_type_bottom_field are present. When false, specifies
     /// that chroma_sample_loc_type_top_field and
     /// chroma_sample_loc_type_bottom_field are not present.
-    chroma_loc_info_present_flag: bool,
+    pub chroma_loc_info_present_flag: bool,
     /// See the specification for more details.
-    chroma_sample_loc_type_top_field: u32,
+    pub chroma_sample_loc_type_top_field: u32,
     /// See the specification for more details.
-    chroma_sample_loc_type_bottom_field: u32,
+    pub chroma_sample_loc_type_bottom_field: u32,
     /// When true, indicates that the value of all decoded chroma samples is
     /// equal to 1 << ( BitDepthC − 1 ). When false, provides no indication of
     /// decoded chroma sample values.
-    neutral_chroma_indication_flag: bool,
+    pub neutral_chroma_indication_flag: bool,
     /// When true, indicates that the CVS conveys pictures that represent
     /// fields, and specifies that a picture timing SEI message shall be present
     /// in every access unit of the current CVS. When false, indicates that the
     /// CVS conveys pictures that represent frames and that a picture timing SEI
     /// message may or may not be present in any access unit of the current CVS.
-    field_seq_flag: bool,
+    pub field_seq_flag: bool,
     /// When true, specifies that picture timing SEI messages are present for
     /// every picture and include the pic_struct, source_scan_type and
     /// duplicate_flag syntax elements. When false, specifies that the
     /// pic_struct syntax element is not present in picture timing SEI messages.
-    frame_field_info_present_flag: bool,
+    pub frame_field_info_present_flag: bool,
     /// When true, indicates that the default display window parameters follow
     /// next in the VUI. When false, indicates that the default display window
     /// parameters are not present.
-    default_display_window_flag: bool,
+    pub default_display_window_flag: bool,
     /// Specifies the samples of the pictures in the CVS that are within the
     /// default display window, in terms of a rectangular region specified in
     /// picture coordinates for display.
-    def_disp_win_left_offset: u32,
+    pub def_disp_win_left_offset: u32,
     /// Specifies the samples of the pictures in the CVS that are within the
     /// default display window, in terms of a rectangular region specified in
     /// picture coordinates for display.
-    def_disp_win_right_offset: u32,
+    pub def_disp_win_right_offset: u32,
     /// Specifies the samples of the pictures in the CVS that are within the
     /// default display window, in terms of a rectangular region specified in
     /// picture coordinates for display.
-    def_disp_win_top_offset: u32,
+    pub def_disp_win_top_offset: u32,
     /// Specifies the samples of the pictures in the CVS that are within the
     /// default display window, in terms of a rectangular region specified in
     /// picture coordinates for display.
-    def_disp_win_bottom_offset: u32,
+    pub def_disp_win_bottom_offset: u32,
     /// When set, specifies that vui_num_units_in_tick, vui_time_scale,
     /// vui_poc_proportional_to_timing_flag and vui_hrd_parameters_present_flag
     /// are present in the vui_parameters( ) syntax structure.  When not set,
     /// specifies that vui_num_units_in_tick, vui_time_scale,
     /// vui_poc_proportional_to_timing_flag and vui_hrd_parameters_present_flag
     /// are not present in the vui_parameters( ) syntax structure
-    timing_info_present_flag: bool,
+    pub timing_info_present_flag: bool,
     /// The number of time units of a clock operating at the frequency
     /// vui_time_scale Hz that corresponds to one increment (called a clock
     /// tick) of a clock tick counter.
-    num_units_in_tick: u32,
+    pub num_units_in_tick: u32,
     /// Is the number of time units that pass in one second. For example, a time
     /// coordinate system that measures time using a 27 MHz clock has a
     /// vui_time_scale of 27 000 000.
-    time_scale: u32,
+    pub time_scale: u32,
     /// When set, indicates that the picture order count value for each picture
     /// in the CVS that is not the first picture in the CVS, in decoding order,
     /// is proportional to the output time of the picture relative to the output
@@ -3394,58 +3394,58 @@ pub struct VuiParams {
     /// first picture in the CVS, in decoding order, may or may not be
     /// proportional to the output time of the picture relative to the output
     /// time of the first picture in the CVS.
-    poc_proportional_to_timing_flag: bool,
+    pub poc_proportional_to_timing_flag: bool,
     /// vui_num_ticks_poc_diff_one_minus1 plus 1 specifies the number of clock
     /// ticks corresponding to a difference of picture order count values equal
     /// to 1.
-    num_ticks_poc_diff_one_minus1: u32,
+    pub num_ticks_poc_diff_one_minus1: u32,
     /// When set, specifies that the syntax structure hrd_parameters( ) is
     /// present in the vui_parameters( ) syntax structure.  When not set,
     /// specifies that the syntax structure hrd_parameters( ) is not present in
     /// the vui_parameters( ) syntax structure.
-    hrd_parameters_present_flag: bool,
+    pub hrd_parameters_present_flag: bool,
     /// The hrd_parameters() data.
-    hrd: HrdParams,
+    pub hrd: HrdParams,
     /// When set, specifies that the bitstream restriction parameters for the
     /// CVS are present. When not set, specifies that the bitstream restriction
     /// parameters for the CVS are not present.
-    bitstream_restriction_flag: bool,
+    pub bitstream_restriction_flag: bool,
     /// When set, indicates that each PPS that is active in the CVS has the same
     /// value of the syntax elements num_tile_columns_minus1,
     /// num_tile_rows_minus1, uniform_spacing_flag, `column_width_minus1[ i ]`,
     /// `row_height_minus1[ i ]` and loop_filter_across_tiles_enabled_flag, when
     /// present. When not set, indicates that tiles syntax elements in different
     /// PPSs may or may not have the same value
-    tiles_fixed_structure_flag: bool,
+    pub tiles_fixed_structure_flag: bool,
     /// When not set, indicates that no sample outside the picture boundaries
     /// and no sample at a fractional sample position for which the sample value
     /// is derived using one or more samples outside the picture boundaries is
     /// used for inter prediction of any sample.  When set, indicates that one
     /// or more samples outside the picture boundaries may be used in inter
     /// prediction.
-    motion_vectors_over_pic_boundaries_flag: bool,
+    pub motion_vectors_over_pic_boundaries_flag: bool,
     /// When set, indicates that all P and B slices (when present) that belong
     /// to the same picture have an identical reference picture list 0 and that
     /// all B slices (when present) that belong to the same picture have an
     /// identical reference picture list 1.
-    restricted_ref_pic_lists_flag: bool,
+    pub restricted_ref_pic_lists_flag: bool,
     /// When not equal to 0, establishes a bound on the maximum possible size of
     /// distinct coded spatial segmentation regions in the pictures of the CVS.
-    min_spatial_segmentation_idc: u32,
+    pub min_spatial_segmentation_idc: u32,
     /// Indicates a number of bytes not exceeded by the sum of the sizes of the
     /// VCL NAL units associated with any coded picture in the CVS.
-    max_bytes_per_pic_denom: u32,
+    pub max_bytes_per_pic_denom: u32,
     /// Indicates an upper bound for the number of coded bits of coding_unit( )
     /// data for anycoding block in any picture of the CVS.
-    max_bits_per_min_cu_denom: u32,
+    pub max_bits_per_min_cu_denom: u32,
     /// Indicate the maximum absolute value of a decoded horizontal and vertical
     /// motion vector component, respectively, in quarter luma sample units, for
     /// all pictures in the CVS.
-    log2_max_mv_length_horizontal: u32,
+    pub log2_max_mv_length_horizontal: u32,
     /// Indicate the maximum absolute value of a decoded horizontal and vertical
     /// motion vector component, respectively, in quarter luma sample units, for
     /// all pictures in the CVS.
-    log2_max_mv_length_vertical: u32,
+    pub log2_max_mv_length_vertical: u32,
 }
 
 impl VuiParams {
@@ -3921,7 +3921,7 @@ impl Parser {
         }
 
         for i in 0..sps_max_sub_layers_minus_1 as usize {
-            if ptl.sub_layer_level_present_flag[i] {
+            if ptl.sub_layer_profile_present_flag[i] {
                 ptl.sub_layer_profile_space[i] = r.read_bits(2)?;
                 ptl.sub_layer_tier_flag[i] = r.read_bit()?;
                 ptl.sub_layer_profile_idc[i] = r.read_bits(5)?;
@@ -4003,12 +4003,12 @@ impl Parser {
                 } else {
                     r.skip_bits(1)?;
                 }
+            }
 
-                if ptl.sub_layer_level_present_flag[i] {
-                    let level: u8 = r.read_bits(8)?;
-                    ptl.sub_layer_level_idc[i] =
-                        Level::n(level).with_context(|| format!("Unsupported level {}", level))?;
-                }
+            if ptl.sub_layer_level_present_flag[i] {
+                let level: u8 = r.read_bits(8)?;
+                ptl.sub_layer_level_idc[i] =
+                    Level::n(level).with_context(|| format!("Unsupported level {}", level))?;
             }
         }
         Ok(())
@@ -4355,9 +4355,13 @@ impl Parser {
 
         for i in 0..=max_num_sublayers_minus1 as usize {
             hrd.fixed_pic_rate_general_flag[i] = r.read_bit()?;
-            if !hrd.fixed_pic_rate_general_flag[i] {
-                hrd.fixed_pic_rate_within_cvs_flag[i] = r.read_bit()?;
-            }
+            // When fixed_pic_rate_general_flag[i] is equal to 1, the value of
+            // fixed_pic_rate_within_cvs_flag[i] is inferred to be equal to 1.
+            hrd.fixed_pic_rate_within_cvs_flag[i] = if !hrd.fixed_pic_rate_general_flag[i] {
+                r.read_bit()?
+            } else {
+                true
+            };
             if hrd.fixed_pic_rate_within_cvs_flag[i] {
                 hrd.elemental_duration_in_tc_minus1[i] = r.read_ue_max(2047)?;
             } else {
@@ -4709,21 +4713,23 @@ impl Parser {
         sps.extension_present_flag = r.read_bit()?;
         if sps.extension_present_flag {
             sps.range_extension_flag = r.read_bit()?;
+            let multilayer_extension_flag = r.read_bit()?;
+            let three_d_extension_flag = r.read_bit()?;
+            sps.scc_extension_flag = r.read_bit()?;
+            r.skip_bits(4)?; // sps_extension_4bits
+
             if sps.range_extension_flag {
                 Self::parse_sps_range_extension(&mut sps, &mut r)?;
             }
 
-            let multilayer_extension_flag = r.read_bit()?;
             if multilayer_extension_flag {
                 return Err(anyhow!("Multilayer extension not supported."));
             }
 
-            let three_d_extension_flag = r.read_bit()?;
             if three_d_extension_flag {
                 return Err(anyhow!("3D extension not supported."));
             }
 
-            sps.scc_extension_flag = r.read_bit()?;
             if sps.scc_extension_flag {
                 Self::parse_sps_scc_extension(&mut sps, &mut r)?;
             }
@@ -4839,7 +4845,7 @@ impl Parser {
         }
 
         let bit_depth_y = sps.bit_depth_luma_minus8 + 8;
-        let max = u32::from(std::cmp::max(0, bit_depth_y - 10));
+        let max = u32::from(bit_depth_y.saturating_sub(10));
 
         rext.log2_sao_offset_scale_luma = r.read_ue_max(max)?;
         rext.log2_sao_offset_scale_chroma = r.read_ue_max(max)?;
@@ -4993,27 +4999,26 @@ impl Parser {
         pps.extension_present_flag = r.read_bit()?;
         if pps.extension_present_flag {
             pps.range_extension_flag = r.read_bit()?;
+            let multilayer_extension_flag = r.read_bit()?;
+            let three_d_extension_flag = r.read_bit()?;
+            pps.scc_extension_flag = r.read_bit()?;
+            r.skip_bits(4)?; // pps_extension_4bits
 
             if pps.range_extension_flag {
                 Self::parse_pps_range_extension(&mut pps, sps, &mut r)?;
             }
 
-            let multilayer_extension_flag = r.read_bit()?;
             if multilayer_extension_flag {
                 return Err(anyhow!("Multilayer extension is not supported"));
             }
 
-            let three_d_extension_flag = r.read_bit()?;
             if three_d_extension_flag {
                 return Err(anyhow!("3D extension is not supported"));
             }
 
-            pps.scc_extension_flag = r.read_bit()?;
             if pps.scc_extension_flag {
                 Self::parse_pps_scc_extension(&mut pps, sps, &mut r)?;
             }
-
-            r.skip_bits(4)?; // pps_extension_4bits
         }
 
         pps.temporal_id = nalu.header().temporal_id_plus1() - 1;
@@ -5083,7 +5088,7 @@ impl Parser {
                 pwt.luma_weight_l1_flag[i] = r.read_bit()?;
             }
 
-            if sps.chroma_format_idc != 0 {
+            if sps.chroma_array_type != 0 {
                 for i in 0..=usize::from(hdr.num_ref_idx_l1_active_minus1) {
                     pwt.chroma_weight_l1_flag[i] = r.read_bit()?;
                 }
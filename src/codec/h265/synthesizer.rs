@@ -0,0 +1,1989 @@
+// Copyright 2023 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+//! Serialization of H.265 syntax structures into NAL units.
+//!
+//! This is the counterpart of the [parser](crate::codec::h265::parser): [`Synthesizer`] turns the
+//! VPS, SPS, PPS and slice segment headers produced by the parser (or built by an encoder) back
+//! into Annex B NAL units. Values derived by the parser, like `Sps::ctb_size_y` or
+//! `SliceHeader::num_pic_total_curr`, are ignored and recomputed from the syntax elements when
+//! needed.
+
+use std::io::Write;
+
+use anyhow::anyhow;
+
+use crate::codec::h264::nalu_writer::NaluWriter;
+use crate::codec::h265::parser::HrdParams;
+use crate::codec::h265::parser::NaluType;
+use crate::codec::h265::parser::Pps;
+use crate::codec::h265::parser::PredWeightTable;
+use crate::codec::h265::parser::ProfileTierLevel;
+use crate::codec::h265::parser::ScalingLists;
+use crate::codec::h265::parser::ShortTermRefPicSet;
+use crate::codec::h265::parser::SliceHeader;
+use crate::codec::h265::parser::Sps;
+use crate::codec::h265::parser::SublayerHrdParameters;
+use crate::codec::h265::parser::Vps;
+use crate::codec::h265::parser::VuiParams;
+use crate::codec::h265::parser::DEFAULT_SCALING_LIST_0;
+use crate::codec::h265::parser::DEFAULT_SCALING_LIST_1;
+use crate::codec::h265::parser::DEFAULT_SCALING_LIST_2;
+
+/// Writes the syntax structure `T` as a NAL unit.
+///
+/// The NAL unit is prefixed with a 4 bytes start code, and emulation-prevention bytes are inserted
+/// into its payload if `ep_enabled` is set. Only the base layer is supported, i.e. `nuh_layer_id`
+/// is always 0.
+pub struct Synthesizer<'a, T, W: Write> {
+    writer: NaluWriter<W>,
+    data: &'a T,
+}
+
+/// The profile-related fields of `profile_tier_level()`, which have the same layout for the
+/// general profile and for the sub-layers.
+struct ProfileFlags<'b> {
+    profile_space: u8,
+    tier_flag: bool,
+    profile_idc: u8,
+    profile_compatibility_flag: &'b [bool; 32],
+    /// The progressive, interlaced, non-packed and frame-only flags.
+    source_flags: [bool; 4],
+    /// The 12bit, 10bit, 8bit, 422chroma, 420chroma, monochrome, intra, one-picture-only, lower
+    /// bit rate and 14bit constraint flags.
+    constraint_flags: [bool; 10],
+    inbld_flag: bool,
+}
+
+impl<'b> ProfileFlags<'b> {
+    fn general(ptl: &'b ProfileTierLevel) -> Self {
+        Self {
+            profile_space: ptl.general_profile_space,
+            tier_flag: ptl.general_tier_flag,
+            profile_idc: ptl.general_profile_idc,
+            profile_compatibility_flag: &ptl.general_profile_compatibility_flag,
+            source_flags: [
+                ptl.general_progressive_source_flag,
+                ptl.general_interlaced_source_flag,
+                ptl.general_non_packed_constraint_flag,
+                ptl.general_frame_only_constraint_flag,
+            ],
+            constraint_flags: [
+                ptl.general_max_12bit_constraint_flag,
+                ptl.general_max_10bit_constraint_flag,
+                ptl.general_max_8bit_constraint_flag,
+                ptl.general_max_422chroma_constraint_flag,
+                ptl.general_max_420chroma_constraint_flag,
+                ptl.general_max_monochrome_constraint_flag,
+                ptl.general_intra_constraint_flag,
+                ptl.general_one_picture_only_constraint_flag,
+                ptl.general_lower_bit_rate_constraint_flag,
+                ptl.general_max_14bit_constraint_flag,
+            ],
+            inbld_flag: ptl.general_inbld_flag,
+        }
+    }
+
+    fn sub_layer(ptl: &'b ProfileTierLevel, i: usize) -> Self {
+        Self {
+            profile_space: ptl.sub_layer_profile_space[i],
+            tier_flag: ptl.sub_layer_tier_flag[i],
+            profile_idc: ptl.sub_layer_profile_idc[i],
+            profile_compatibility_flag: &ptl.sub_layer_profile_compatibility_flag[i],
+            source_flags: [
+                ptl.sub_layer_progressive_source_flag[i],
+                ptl.sub_layer_interlaced_source_flag[i],
+                ptl.sub_layer_non_packed_constraint_flag[i],
+                ptl.sub_layer_frame_only_constraint_flag[i],
+            ],
+            constraint_flags: [
+                ptl.sub_layer_max_12bit_constraint_flag[i],
+                ptl.sub_layer_max_10bit_constraint_flag[i],
+                ptl.sub_layer_max_8bit_constraint_flag[i],
+                ptl.sub_layer_max_422chroma_constraint_flag[i],
+                ptl.sub_layer_max_420chroma_constraint_flag[i],
+                ptl.sub_layer_max_monochrome_constraint_flag[i],
+                ptl.sub_layer_intra_constraint_flag[i],
+                ptl.sub_layer_one_picture_only_constraint_flag[i],
+                ptl.sub_layer_lower_bit_rate_constraint_flag[i],
+                ptl.sub_layer_max_14bit_constraint_flag[i],
+            ],
+            inbld_flag: ptl.sub_layer_inbld_flag[i],
+        }
+    }
+
+    /// Whether the profile is, or is compatible with, one of `profiles`.
+    fn is_any_of(&self, profiles: &[u8]) -> bool {
+        profiles
+            .iter()
+            .any(|&p| self.profile_idc == p || self.profile_compatibility_flag[usize::from(p)])
+    }
+}
+
+/// Returns `Ceil(Log2(value))`, i.e. the number of bits used to code indices lower than `value`.
+fn ceil_log2(value: u32) -> usize {
+    (u32::BITS - value.saturating_sub(1).leading_zeros()) as usize
+}
+
+/// Returns the difference going from `last` to `next`, wrapped to the range of the
+/// `scaling_list_delta_coef` syntax element (7.4.5).
+fn delta_coef(last: u8, next: u8) -> i32 {
+    let delta = i32::from(next) - i32::from(last);
+    (delta + 128).rem_euclid(256) - 128
+}
+
+impl<'a, T, W: Write> Synthesizer<'a, T, W> {
+    /// Write the start code of the NAL unit into `writer` and prepare to write its content.
+    fn new(mut writer: W, data: &'a T, ep_enabled: bool) -> anyhow::Result<Self> {
+        // Emulation prevention does not apply to the start code.
+        writer.write_all(&[0x00, 0x00, 0x00, 0x01])?;
+
+        Ok(Self {
+            writer: NaluWriter::new(writer, ep_enabled),
+            data,
+        })
+    }
+
+    /// Write the NAL unit header (7.3.1.2).
+    fn nalu_header(&mut self, nalu_type: NaluType, temporal_id: u8) -> anyhow::Result<()> {
+        if temporal_id > 6 {
+            return Err(anyhow!("Invalid temporal id {}", temporal_id));
+        }
+
+        // forbidden_zero_bit
+        self.bit(false)?;
+        self.bits(nalu_type as u8, 6)?;
+        // nuh_layer_id
+        self.bits(0u8, 6)?;
+        self.bits(temporal_id + 1, 3)
+    }
+
+    /// Write the RBSP trailing bits and flush the NAL unit.
+    fn finish(mut self) -> anyhow::Result<()> {
+        self.writer.write_rbsp_trailing_bits()?;
+        self.writer.into_inner()?;
+
+        Ok(())
+    }
+
+    fn bits<U: Into<u64>>(&mut self, value: U, num_bits: usize) -> anyhow::Result<()> {
+        self.writer.write_bits(value, num_bits)
+    }
+
+    fn bit(&mut self, bit: bool) -> anyhow::Result<()> {
+        self.writer.write_bit(bit)
+    }
+
+    fn ue<U: Into<u32>>(&mut self, value: U) -> anyhow::Result<()> {
+        self.writer.write_ue(value)
+    }
+
+    fn se<U: Into<i32>>(&mut self, value: U) -> anyhow::Result<()> {
+        self.writer.write_se(value)
+    }
+
+    /// Write `num_bits` reserved zero bits.
+    fn zero_bits(&mut self, mut num_bits: usize) -> anyhow::Result<()> {
+        while num_bits > 0 {
+            let n = std::cmp::min(num_bits, 32);
+            self.bits(0u32, n)?;
+            num_bits -= n;
+        }
+
+        Ok(())
+    }
+
+    /// Write the profile part of `profile_tier_level()` for the general profile or a sub-layer.
+    fn profile(&mut self, p: &ProfileFlags) -> anyhow::Result<()> {
+        self.bits(p.profile_space, 2)?;
+        self.bit(p.tier_flag)?;
+        self.bits(p.profile_idc, 5)?;
+        for &flag in p.profile_compatibility_flag {
+            self.bit(flag)?;
+        }
+        for flag in p.source_flags {
+            self.bit(flag)?;
+        }
+
+        if p.is_any_of(&[4, 5, 6, 7, 8, 9, 10, 11]) {
+            for &flag in &p.constraint_flags[..9] {
+                self.bit(flag)?;
+            }
+
+            if p.is_any_of(&[5, 9, 10, 11]) {
+                self.bit(p.constraint_flags[9])?;
+                self.zero_bits(33)?;
+            } else {
+                self.zero_bits(34)?;
+            }
+        } else if p.is_any_of(&[2]) {
+            self.zero_bits(7)?;
+            self.bit(p.constraint_flags[7])?;
+            self.zero_bits(35)?;
+        } else {
+            self.zero_bits(43)?;
+        }
+
+        if p.is_any_of(&[1, 2, 3, 4, 5, 9, 11]) {
+            self.bit(p.inbld_flag)
+        } else {
+            // reserved_zero_bit
+            self.bit(false)
+        }
+    }
+
+    /// Write `profile_tier_level()` (7.3.3).
+    fn profile_tier_level(
+        &mut self,
+        ptl: &ProfileTierLevel,
+        profile_present_flag: bool,
+        max_sub_layers_minus1: u8,
+    ) -> anyhow::Result<()> {
+        if profile_present_flag {
+            self.profile(&ProfileFlags::general(ptl))?;
+        }
+
+        self.bits(ptl.general_level_idc as u8, 8)?;
+
+        let max_sub_layers_minus1 = usize::from(max_sub_layers_minus1);
+        for i in 0..max_sub_layers_minus1 {
+            self.bit(ptl.sub_layer_profile_present_flag[i])?;
+            self.bit(ptl.sub_layer_level_present_flag[i])?;
+        }
+
+        if max_sub_layers_minus1 > 0 {
+            // reserved_zero_2bits
+            self.zero_bits(2 * (8 - max_sub_layers_minus1))?;
+        }
+
+        for i in 0..max_sub_layers_minus1 {
+            if ptl.sub_layer_profile_present_flag[i] {
+                self.profile(&ProfileFlags::sub_layer(ptl, i))?;
+            }
+
+            if ptl.sub_layer_level_present_flag[i] {
+                self.bits(ptl.sub_layer_level_idc[i] as u8, 8)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write `scaling_list_data()` (7.3.4). Lists equal to their default value are signaled as
+    /// such, and the other ones are coded explicitly.
+    fn scaling_list_data(&mut self, sl: &ScalingLists) -> anyhow::Result<()> {
+        for size_id in 0..4 {
+            let step = if size_id == 3 { 3 } else { 1 };
+
+            for matrix_id in (0..6).step_by(step) {
+                let (list, default, dc_coef_minus8): (&[u8], &[u8], _) = match size_id {
+                    0 => (
+                        &sl.scaling_list_4x4[matrix_id],
+                        &DEFAULT_SCALING_LIST_0,
+                        None,
+                    ),
+                    _ => {
+                        let (list, dc_coef_minus8) = match size_id {
+                            1 => (&sl.scaling_list_8x8[matrix_id], None),
+                            2 => (
+                                &sl.scaling_list_16x16[matrix_id],
+                                Some(sl.scaling_list_dc_coef_minus8_16x16[matrix_id]),
+                            ),
+                            _ => (
+                                &sl.scaling_list_32x32[matrix_id],
+                                Some(sl.scaling_list_dc_coef_minus8_32x32[matrix_id]),
+                            ),
+                        };
+
+                        let default = if matrix_id < 3 {
+                            &DEFAULT_SCALING_LIST_1
+                        } else {
+                            &DEFAULT_SCALING_LIST_2
+                        };
+
+                        (list, default, dc_coef_minus8)
+                    }
+                };
+
+                // The DC coefficient of the default lists is 16.
+                if list == default && dc_coef_minus8.unwrap_or(8) == 8 {
+                    // scaling_list_pred_mode_flag
+                    self.bit(false)?;
+                    // scaling_list_pred_matrix_id_delta
+                    self.ue(0u32)?;
+                    continue;
+                }
+
+                self.bit(true)?;
+                let mut next_coef = 8u8;
+                if let Some(dc_coef_minus8) = dc_coef_minus8 {
+                    self.se(dc_coef_minus8)?;
+                    next_coef = u8::try_from(dc_coef_minus8 + 8)?;
+                }
+
+                for &coef in list {
+                    self.se(delta_coef(next_coef, coef))?;
+                    next_coef = coef;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write `st_ref_pic_set(st_rps_idx)` (7.3.7).
+    ///
+    /// If `st` is predicted from another set, `used_by_curr_pic_flag` and `use_delta_flag` are
+    /// recovered by matching the pictures of the reference set with those of `st`.
+    fn short_term_ref_pic_set(
+        &mut self,
+        st: &ShortTermRefPicSet,
+        st_rps_idx: u8,
+        sps: &Sps,
+    ) -> anyhow::Result<()> {
+        if st_rps_idx != 0 {
+            self.bit(st.inter_ref_pic_set_prediction_flag)?;
+        }
+
+        let num_negative_pics = usize::from(st.num_negative_pics);
+        let num_positive_pics = usize::from(st.num_positive_pics);
+
+        if st.inter_ref_pic_set_prediction_flag && st_rps_idx != 0 {
+            if st_rps_idx == sps.num_short_term_ref_pic_sets {
+                self.ue(st.delta_idx_minus1)?;
+            }
+
+            self.bit(st.delta_rps_sign)?;
+            self.ue(st.abs_delta_rps_minus1)?;
+
+            let ref_rps_idx = st_rps_idx
+                .checked_sub(st.delta_idx_minus1 + 1)
+                .ok_or_else(|| anyhow!("Invalid delta_idx_minus1 {}", st.delta_idx_minus1))?;
+            let ref_st = sps
+                .short_term_ref_pic_set
+                .get(usize::from(ref_rps_idx))
+                .ok_or_else(|| anyhow!("Invalid ref_rps_idx {}", ref_rps_idx))?;
+
+            let delta_rps =
+                (1 - 2 * i32::from(st.delta_rps_sign)) * (i32::from(st.abs_delta_rps_minus1) + 1);
+
+            let ref_negative = &ref_st.delta_poc_s0[..usize::from(ref_st.num_negative_pics)];
+            let ref_positive = &ref_st.delta_poc_s1[..usize::from(ref_st.num_positive_pics)];
+            let s0 = &st.delta_poc_s0[..num_negative_pics];
+            let s1 = &st.delta_poc_s1[..num_positive_pics];
+
+            // The candidate pictures are those of the reference set, followed by the reference
+            // picture itself, all shifted by delta_rps.
+            let mut num_matched = 0;
+            for d_poc in ref_negative
+                .iter()
+                .chain(ref_positive)
+                .map(|d| d + delta_rps)
+                .chain(std::iter::once(delta_rps))
+            {
+                let used = if d_poc < 0 {
+                    s0.iter()
+                        .position(|&d| d == d_poc)
+                        .map(|i| st.used_by_curr_pic_s0[i])
+                } else if d_poc > 0 {
+                    s1.iter()
+                        .position(|&d| d == d_poc)
+                        .map(|i| st.used_by_curr_pic_s1[i])
+                } else {
+                    None
+                };
+
+                match used {
+                    Some(used) => {
+                        num_matched += 1;
+                        // used_by_curr_pic_flag
+                        self.bit(used)?;
+                        if !used {
+                            // use_delta_flag
+                            self.bit(true)?;
+                        }
+                    }
+                    None => {
+                        self.bit(false)?;
+                        self.bit(false)?;
+                    }
+                }
+            }
+
+            if num_matched != num_negative_pics + num_positive_pics {
+                return Err(anyhow!(
+                    "Short-term RPS {} cannot be predicted from RPS {}",
+                    st_rps_idx,
+                    ref_rps_idx
+                ));
+            }
+        } else {
+            self.ue(st.num_negative_pics)?;
+            self.ue(st.num_positive_pics)?;
+
+            let mut prev = 0;
+            for i in 0..num_negative_pics {
+                let delta_poc_s0_minus1 = u32::try_from(prev - st.delta_poc_s0[i] - 1)
+                    .map_err(|_| anyhow!("delta_poc_s0 must be strictly decreasing"))?;
+                self.ue(delta_poc_s0_minus1)?;
+                self.bit(st.used_by_curr_pic_s0[i])?;
+                prev = st.delta_poc_s0[i];
+            }
+
+            let mut prev = 0;
+            for i in 0..num_positive_pics {
+                let delta_poc_s1_minus1 = u32::try_from(st.delta_poc_s1[i] - prev - 1)
+                    .map_err(|_| anyhow!("delta_poc_s1 must be strictly increasing"))?;
+                self.ue(delta_poc_s1_minus1)?;
+                self.bit(st.used_by_curr_pic_s1[i])?;
+                prev = st.delta_poc_s1[i];
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write `sub_layer_hrd_parameters()` (E.2.3).
+    fn sub_layer_hrd_parameters(
+        &mut self,
+        h: &SublayerHrdParameters,
+        cpb_cnt: usize,
+        sub_pic_hrd_params_present_flag: bool,
+    ) -> anyhow::Result<()> {
+        for i in 0..cpb_cnt {
+            self.ue(h.bit_rate_value_minus1[i])?;
+            self.ue(h.cpb_size_value_minus1[i])?;
+            if sub_pic_hrd_params_present_flag {
+                self.ue(h.cpb_size_du_value_minus1[i])?;
+                self.ue(h.bit_rate_du_value_minus1[i])?;
+            }
+            self.bit(h.cbr_flag[i])?;
+        }
+
+        Ok(())
+    }
+
+    /// Write `hrd_parameters()` (E.2.2).
+    fn hrd_parameters(
+        &mut self,
+        hrd: &HrdParams,
+        common_inf_present_flag: bool,
+        max_sub_layers_minus1: u8,
+    ) -> anyhow::Result<()> {
+        if common_inf_present_flag {
+            self.bit(hrd.nal_hrd_parameters_present_flag)?;
+            self.bit(hrd.vcl_hrd_parameters_present_flag)?;
+            if hrd.nal_hrd_parameters_present_flag || hrd.vcl_hrd_parameters_present_flag {
+                self.bit(hrd.sub_pic_hrd_params_present_flag)?;
+                if hrd.sub_pic_hrd_params_present_flag {
+                    self.bits(hrd.tick_divisor_minus2, 8)?;
+                    self.bits(hrd.du_cpb_removal_delay_increment_length_minus1, 5)?;
+                    self.bit(hrd.sub_pic_cpb_params_in_pic_timing_sei_flag)?;
+                    self.bits(hrd.dpb_output_delay_du_length_minus1, 5)?;
+                }
+
+                self.bits(hrd.bit_rate_scale, 4)?;
+                self.bits(hrd.cpb_size_scale, 4)?;
+                if hrd.sub_pic_hrd_params_present_flag {
+                    self.bits(hrd.cpb_size_du_scale, 4)?;
+                }
+
+                self.bits(hrd.initial_cpb_removal_delay_length_minus1, 5)?;
+                self.bits(hrd.au_cpb_removal_delay_length_minus1, 5)?;
+                self.bits(hrd.dpb_output_delay_length_minus1, 5)?;
+            }
+        }
+
+        for i in 0..=usize::from(max_sub_layers_minus1) {
+            self.bit(hrd.fixed_pic_rate_general_flag[i])?;
+            // fixed_pic_rate_within_cvs_flag is inferred to be 1 if fixed_pic_rate_general_flag
+            // is set.
+            let fixed_pic_rate_within_cvs_flag =
+                hrd.fixed_pic_rate_general_flag[i] || hrd.fixed_pic_rate_within_cvs_flag[i];
+            if !hrd.fixed_pic_rate_general_flag[i] {
+                self.bit(fixed_pic_rate_within_cvs_flag)?;
+            }
+
+            if fixed_pic_rate_within_cvs_flag {
+                self.ue(hrd.elemental_duration_in_tc_minus1[i])?;
+            } else {
+                self.bit(hrd.low_delay_hrd_flag[i])?;
+            }
+
+            if !hrd.low_delay_hrd_flag[i] {
+                self.ue(hrd.cpb_cnt_minus1[i])?;
+            }
+
+            let cpb_cnt = usize::try_from(hrd.cpb_cnt_minus1[i])? + 1;
+            if hrd.nal_hrd_parameters_present_flag {
+                self.sub_layer_hrd_parameters(
+                    &hrd.nal_hrd[i],
+                    cpb_cnt,
+                    hrd.sub_pic_hrd_params_present_flag,
+                )?;
+            }
+
+            if hrd.vcl_hrd_parameters_present_flag {
+                self.sub_layer_hrd_parameters(
+                    &hrd.vcl_hrd[i],
+                    cpb_cnt,
+                    hrd.sub_pic_hrd_params_present_flag,
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write `vui_parameters()` (E.2.1).
+    fn vui_parameters(&mut self, vui: &VuiParams, max_sub_layers_minus1: u8) -> anyhow::Result<()> {
+        self.bit(vui.aspect_ratio_info_present_flag)?;
+        if vui.aspect_ratio_info_present_flag {
+            self.bits(vui.aspect_ratio_idc, 8)?;
+            if vui.aspect_ratio_idc == 255 {
+                self.bits(vui.sar_width, 16)?;
+                self.bits(vui.sar_height, 16)?;
+            }
+        }
+
+        self.bit(vui.overscan_info_present_flag)?;
+        if vui.overscan_info_present_flag {
+            self.bit(vui.overscan_appropriate_flag)?;
+        }
+
+        self.bit(vui.video_signal_type_present_flag)?;
+        if vui.video_signal_type_present_flag {
+            self.bits(vui.video_format, 3)?;
+            self.bit(vui.video_full_range_flag)?;
+            self.bit(vui.colour_description_present_flag)?;
+            if vui.colour_description_present_flag {
+                self.bits(vui.colour_primaries, 8)?;
+                self.bits(vui.transfer_characteristics, 8)?;
+                self.bits(vui.matrix_coeffs, 8)?;
+            }
+        }
+
+        self.bit(vui.chroma_loc_info_present_flag)?;
+        if vui.chroma_loc_info_present_flag {
+            self.ue(vui.chroma_sample_loc_type_top_field)?;
+            self.ue(vui.chroma_sample_loc_type_bottom_field)?;
+        }
+
+        self.bit(vui.neutral_chroma_indication_flag)?;
+        self.bit(vui.field_seq_flag)?;
+        self.bit(vui.frame_field_info_present_flag)?;
+        self.bit(vui.default_display_window_flag)?;
+        if vui.default_display_window_flag {
+            self.ue(vui.def_disp_win_left_offset)?;
+            self.ue(vui.def_disp_win_right_offset)?;
+            self.ue(vui.def_disp_win_top_offset)?;
+            self.ue(vui.def_disp_win_bottom_offset)?;
+        }
+
+        self.bit(vui.timing_info_present_flag)?;
+        if vui.timing_info_present_flag {
+            self.bits(vui.num_units_in_tick, 32)?;
+            self.bits(vui.time_scale, 32)?;
+            self.bit(vui.poc_proportional_to_timing_flag)?;
+            if vui.poc_proportional_to_timing_flag {
+                self.ue(vui.num_ticks_poc_diff_one_minus1)?;
+            }
+
+            self.bit(vui.hrd_parameters_present_flag)?;
+            if vui.hrd_parameters_present_flag {
+                self.hrd_parameters(&vui.hrd, true, max_sub_layers_minus1)?;
+            }
+        }
+
+        self.bit(vui.bitstream_restriction_flag)?;
+        if vui.bitstream_restriction_flag {
+            self.bit(vui.tiles_fixed_structure_flag)?;
+            self.bit(vui.motion_vectors_over_pic_boundaries_flag)?;
+            self.bit(vui.restricted_ref_pic_lists_flag)?;
+            self.ue(vui.min_spatial_segmentation_idc)?;
+            self.ue(vui.max_bytes_per_pic_denom)?;
+            self.ue(vui.max_bits_per_min_cu_denom)?;
+            self.ue(vui.log2_max_mv_length_horizontal)?;
+            self.ue(vui.log2_max_mv_length_vertical)?;
+        }
+
+        Ok(())
+    }
+
+    /// Write the `*_sub_layer_ordering_info_present_flag` syntax element and the DPB sizes that
+    /// follow it, which have the same layout in the VPS and SPS.
+    fn sub_layer_ordering_info<U: Into<u32> + Copy>(
+        &mut self,
+        present_flag: bool,
+        max_sub_layers_minus1: u8,
+        max_dec_pic_buffering_minus1: &[U],
+        max_num_reorder_pics: &[U],
+        max_latency_increase_plus1: &[U],
+    ) -> anyhow::Result<()> {
+        self.bit(present_flag)?;
+
+        let start = if present_flag {
+            0
+        } else {
+            max_sub_layers_minus1
+        };
+
+        for i in usize::from(start)..=usize::from(max_sub_layers_minus1) {
+            self.ue(max_dec_pic_buffering_minus1[i])?;
+            self.ue(max_num_reorder_pics[i])?;
+            self.ue(max_latency_increase_plus1[i])?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a, W: Write> Synthesizer<'a, Vps, W> {
+    /// Write `vps` as a VPS NAL unit into `writer`.
+    ///
+    /// The layer sets are not kept by the parser, so only VPSs with a single layer set can be
+    /// written.
+    pub fn synthesize(vps: &'a Vps, writer: W, ep_enabled: bool) -> anyhow::Result<()> {
+        let mut s = Self::new(writer, vps, ep_enabled)?;
+
+        s.nalu_header(NaluType::VpsNut, 0)?;
+        s.video_parameter_set()?;
+        s.finish()
+    }
+
+    fn video_parameter_set(&mut self) -> anyhow::Result<()> {
+        let vps = self.data;
+
+        self.bits(vps.video_parameter_set_id, 4)?;
+        self.bit(vps.base_layer_internal_flag)?;
+        self.bit(vps.base_layer_available_flag)?;
+        self.bits(vps.max_layers_minus1, 6)?;
+        self.bits(vps.max_sub_layers_minus1, 3)?;
+        self.bit(vps.temporal_id_nesting_flag)?;
+        // vps_reserved_0xffff_16bits
+        self.bits(0xffffu16, 16)?;
+
+        self.profile_tier_level(&vps.profile_tier_level, true, vps.max_sub_layers_minus1)?;
+
+        self.sub_layer_ordering_info(
+            vps.sub_layer_ordering_info_present_flag,
+            vps.max_sub_layers_minus1,
+            &vps.max_dec_pic_buffering_minus1,
+            &vps.max_num_reorder_pics,
+            &vps.max_latency_increase_plus1,
+        )?;
+
+        self.bits(vps.max_layer_id, 6)?;
+        if vps.num_layer_sets_minus1 != 0 {
+            return Err(anyhow!(
+                "Writing {} layer sets is not supported",
+                vps.num_layer_sets_minus1 + 1
+            ));
+        }
+        self.ue(vps.num_layer_sets_minus1)?;
+
+        self.bit(vps.timing_info_present_flag)?;
+        if vps.timing_info_present_flag {
+            self.bits(vps.num_units_in_tick, 32)?;
+            self.bits(vps.time_scale, 32)?;
+            self.bit(vps.poc_proportional_to_timing_flag)?;
+            if vps.poc_proportional_to_timing_flag {
+                self.ue(vps.num_ticks_poc_diff_one_minus1)?;
+            }
+
+            let num_hrd_parameters = usize::try_from(vps.num_hrd_parameters)?;
+            if vps.hrd_layer_set_idx.len() < num_hrd_parameters
+                || vps.cprms_present_flag.len() < num_hrd_parameters
+                || vps.hrd_parameters.len() < num_hrd_parameters
+            {
+                return Err(anyhow!(
+                    "Missing parameters for {} HRDs",
+                    vps.num_hrd_parameters
+                ));
+            }
+
+            self.ue(vps.num_hrd_parameters)?;
+            for i in 0..num_hrd_parameters {
+                self.ue(vps.hrd_layer_set_idx[i])?;
+                // cprms_present_flag[0] is inferred to be 1.
+                let cprms_present_flag = i == 0 || vps.cprms_present_flag[i];
+                if i > 0 {
+                    self.bit(cprms_present_flag)?;
+                }
+
+                self.hrd_parameters(
+                    &vps.hrd_parameters[i],
+                    cprms_present_flag,
+                    vps.max_sub_layers_minus1,
+                )?;
+            }
+        }
+
+        self.bit(vps.extension_flag)
+    }
+}
+
+impl<'a, W: Write> Synthesizer<'a, Sps, W> {
+    /// Write `sps` as a SPS NAL unit into `writer`.
+    pub fn synthesize(sps: &'a Sps, writer: W, ep_enabled: bool) -> anyhow::Result<()> {
+        let mut s = Self::new(writer, sps, ep_enabled)?;
+
+        s.nalu_header(NaluType::SpsNut, 0)?;
+        s.seq_parameter_set()?;
+        s.finish()
+    }
+
+    fn seq_parameter_set(&mut self) -> anyhow::Result<()> {
+        let sps = self.data;
+
+        self.bits(sps.video_parameter_set_id, 4)?;
+        self.bits(sps.max_sub_layers_minus1, 3)?;
+        self.bit(sps.temporal_id_nesting_flag)?;
+        self.profile_tier_level(&sps.profile_tier_level, true, sps.max_sub_layers_minus1)?;
+
+        self.ue(sps.seq_parameter_set_id)?;
+        self.ue(sps.chroma_format_idc)?;
+        if sps.chroma_format_idc == 3 {
+            self.bit(sps.separate_colour_plane_flag)?;
+        }
+
+        self.ue(sps.pic_width_in_luma_samples)?;
+        self.ue(sps.pic_height_in_luma_samples)?;
+        self.bit(sps.conformance_window_flag)?;
+        if sps.conformance_window_flag {
+            self.ue(sps.conf_win_left_offset)?;
+            self.ue(sps.conf_win_right_offset)?;
+            self.ue(sps.conf_win_top_offset)?;
+            self.ue(sps.conf_win_bottom_offset)?;
+        }
+
+        self.ue(sps.bit_depth_luma_minus8)?;
+        self.ue(sps.bit_depth_chroma_minus8)?;
+        self.ue(sps.log2_max_pic_order_cnt_lsb_minus4)?;
+
+        self.sub_layer_ordering_info(
+            sps.sub_layer_ordering_info_present_flag,
+            sps.max_sub_layers_minus1,
+            &sps.max_dec_pic_buffering_minus1,
+            &sps.max_num_reorder_pics,
+            &sps.max_latency_increase_plus1,
+        )?;
+
+        self.ue(sps.log2_min_luma_coding_block_size_minus3)?;
+        self.ue(sps.log2_diff_max_min_luma_coding_block_size)?;
+        self.ue(sps.log2_min_luma_transform_block_size_minus2)?;
+        self.ue(sps.log2_diff_max_min_luma_transform_block_size)?;
+        self.ue(sps.max_transform_hierarchy_depth_inter)?;
+        self.ue(sps.max_transform_hierarchy_depth_intra)?;
+
+        self.bit(sps.scaling_list_enabled_flag)?;
+        if sps.scaling_list_enabled_flag {
+            self.bit(sps.scaling_list_data_present_flag)?;
+            if sps.scaling_list_data_present_flag {
+                self.scaling_list_data(&sps.scaling_list)?;
+            }
+        }
+
+        self.bit(sps.amp_enabled_flag)?;
+        self.bit(sps.sample_adaptive_offset_enabled_flag)?;
+        self.bit(sps.pcm_enabled_flag)?;
+        if sps.pcm_enabled_flag {
+            self.bits(sps.pcm_sample_bit_depth_luma_minus1, 4)?;
+            self.bits(sps.pcm_sample_bit_depth_chroma_minus1, 4)?;
+            self.ue(sps.log2_min_pcm_luma_coding_block_size_minus3)?;
+            self.ue(sps.log2_diff_max_min_pcm_luma_coding_block_size)?;
+            self.bit(sps.pcm_loop_filter_disabled_flag)?;
+        }
+
+        self.ue(sps.num_short_term_ref_pic_sets)?;
+        for i in 0..sps.num_short_term_ref_pic_sets {
+            let st = sps
+                .short_term_ref_pic_set
+                .get(usize::from(i))
+                .ok_or_else(|| anyhow!("Missing short-term RPS {}", i))?;
+            self.short_term_ref_pic_set(st, i, sps)?;
+        }
+
+        self.bit(sps.long_term_ref_pics_present_flag)?;
+        if sps.long_term_ref_pics_present_flag {
+            self.ue(sps.num_long_term_ref_pics_sps)?;
+            for i in 0..usize::from(sps.num_long_term_ref_pics_sps) {
+                self.bits(
+                    sps.lt_ref_pic_poc_lsb_sps[i],
+                    usize::from(sps.log2_max_pic_order_cnt_lsb_minus4) + 4,
+                )?;
+                self.bit(sps.used_by_curr_pic_lt_sps_flag[i])?;
+            }
+        }
+
+        self.bit(sps.temporal_mvp_enabled_flag)?;
+        self.bit(sps.strong_intra_smoothing_enabled_flag)?;
+
+        self.bit(sps.vui_parameters_present_flag)?;
+        if sps.vui_parameters_present_flag {
+            self.vui_parameters(&sps.vui_parameters, sps.max_sub_layers_minus1)?;
+        }
+
+        self.bit(sps.extension_present_flag)?;
+        if sps.extension_present_flag {
+            self.bit(sps.range_extension_flag)?;
+            // sps_multilayer_extension_flag and sps_3d_extension_flag
+            self.bit(false)?;
+            self.bit(false)?;
+            self.bit(sps.scc_extension_flag)?;
+            // sps_extension_4bits
+            self.bits(0u8, 4)?;
+
+            if sps.range_extension_flag {
+                self.sps_range_extension()?;
+            }
+
+            if sps.scc_extension_flag {
+                self.sps_scc_extension()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn sps_range_extension(&mut self) -> anyhow::Result<()> {
+        let ext = &self.data.range_extension;
+
+        self.bit(ext.transform_skip_rotation_enabled_flag)?;
+        self.bit(ext.transform_skip_context_enabled_flag)?;
+        self.bit(ext.implicit_rdpcm_enabled_flag)?;
+        self.bit(ext.explicit_rdpcm_enabled_flag)?;
+        self.bit(ext.extended_precision_processing_flag)?;
+        self.bit(ext.intra_smoothing_disabled_flag)?;
+        self.bit(ext.high_precision_offsets_enabled_flag)?;
+        self.bit(ext.persistent_rice_adaptation_enabled_flag)?;
+        self.bit(ext.cabac_bypass_alignment_enabled_flag)
+    }
+
+    fn sps_scc_extension(&mut self) -> anyhow::Result<()> {
+        let sps = self.data;
+        let scc = &sps.scc_extension;
+
+        self.bit(scc.curr_pic_ref_enabled_flag)?;
+        self.bit(scc.palette_mode_enabled_flag)?;
+        if scc.palette_mode_enabled_flag {
+            self.ue(scc.palette_max_size)?;
+            self.ue(scc.delta_palette_max_predictor_size)?;
+            self.bit(scc.palette_predictor_initializers_present_flag)?;
+            if scc.palette_predictor_initializers_present_flag {
+                self.ue(scc.num_palette_predictor_initializer_minus1)?;
+
+                let num_comps = if sps.chroma_format_idc == 0 { 1 } else { 3 };
+                for comp in 0..num_comps {
+                    let num_bits = if comp == 0 {
+                        sps.bit_depth_luma_minus8 + 8
+                    } else {
+                        sps.bit_depth_chroma_minus8 + 8
+                    };
+
+                    for i in 0..=usize::from(scc.num_palette_predictor_initializer_minus1) {
+                        self.bits(
+                            scc.palette_predictor_initializer[comp][i],
+                            usize::from(num_bits),
+                        )?;
+                    }
+                }
+            }
+        }
+
+        self.bits(scc.motion_vector_resolution_control_idc, 2)?;
+        self.bit(scc.intra_boundary_filtering_disabled_flag)
+    }
+}
+
+impl<'a, W: Write> Synthesizer<'a, Pps, W> {
+    /// Write `pps` as a PPS NAL unit into `writer`. The temporal id of the NAL unit is
+    /// `pps.temporal_id`.
+    pub fn synthesize(pps: &'a Pps, writer: W, ep_enabled: bool) -> anyhow::Result<()> {
+        let mut s = Self::new(writer, pps, ep_enabled)?;
+
+        s.nalu_header(NaluType::PpsNut, pps.temporal_id)?;
+        s.pic_parameter_set()?;
+        s.finish()
+    }
+
+    fn pic_parameter_set(&mut self) -> anyhow::Result<()> {
+        let pps = self.data;
+
+        self.ue(pps.pic_parameter_set_id)?;
+        self.ue(pps.seq_parameter_set_id)?;
+        self.bit(pps.dependent_slice_segments_enabled_flag)?;
+        self.bit(pps.output_flag_present_flag)?;
+        self.bits(pps.num_extra_slice_header_bits, 3)?;
+        self.bit(pps.sign_data_hiding_enabled_flag)?;
+        self.bit(pps.cabac_init_present_flag)?;
+        self.ue(pps.num_ref_idx_l0_default_active_minus1)?;
+        self.ue(pps.num_ref_idx_l1_default_active_minus1)?;
+        self.se(pps.init_qp_minus26)?;
+        self.bit(pps.constrained_intra_pred_flag)?;
+        self.bit(pps.transform_skip_enabled_flag)?;
+        self.bit(pps.cu_qp_delta_enabled_flag)?;
+        if pps.cu_qp_delta_enabled_flag {
+            self.ue(pps.diff_cu_qp_delta_depth)?;
+        }
+
+        self.se(pps.cb_qp_offset)?;
+        self.se(pps.cr_qp_offset)?;
+        self.bit(pps.slice_chroma_qp_offsets_present_flag)?;
+        self.bit(pps.weighted_pred_flag)?;
+        self.bit(pps.weighted_bipred_flag)?;
+        self.bit(pps.transquant_bypass_enabled_flag)?;
+        self.bit(pps.tiles_enabled_flag)?;
+        self.bit(pps.entropy_coding_sync_enabled_flag)?;
+
+        if pps.tiles_enabled_flag {
+            self.ue(pps.num_tile_columns_minus1)?;
+            self.ue(pps.num_tile_rows_minus1)?;
+            self.bit(pps.uniform_spacing_flag)?;
+            if !pps.uniform_spacing_flag {
+                for &width in &pps.column_width_minus1[..usize::from(pps.num_tile_columns_minus1)] {
+                    self.ue(width)?;
+                }
+
+                for &height in &pps.row_height_minus1[..usize::from(pps.num_tile_rows_minus1)] {
+                    self.ue(height)?;
+                }
+            }
+
+            self.bit(pps.loop_filter_across_tiles_enabled_flag)?;
+        }
+
+        self.bit(pps.loop_filter_across_slices_enabled_flag)?;
+        self.bit(pps.deblocking_filter_control_present_flag)?;
+        if pps.deblocking_filter_control_present_flag {
+            self.bit(pps.deblocking_filter_override_enabled_flag)?;
+            self.bit(pps.deblocking_filter_disabled_flag)?;
+            if !pps.deblocking_filter_disabled_flag {
+                self.se(pps.beta_offset_div2)?;
+                self.se(pps.tc_offset_div2)?;
+            }
+        }
+
+        self.bit(pps.scaling_list_data_present_flag)?;
+        if pps.scaling_list_data_present_flag {
+            self.scaling_list_data(&pps.scaling_list)?;
+        }
+
+        self.bit(pps.lists_modification_present_flag)?;
+        self.ue(pps.log2_parallel_merge_level_minus2)?;
+        self.bit(pps.slice_segment_header_extension_present_flag)?;
+
+        self.bit(pps.extension_present_flag)?;
+        if pps.extension_present_flag {
+            self.bit(pps.range_extension_flag)?;
+            // pps_multilayer_extension_flag and pps_3d_extension_flag
+            self.bit(false)?;
+            self.bit(false)?;
+            self.bit(pps.scc_extension_flag)?;
+            // pps_extension_4bits
+            self.bits(0u8, 4)?;
+
+            if pps.range_extension_flag {
+                self.pps_range_extension()?;
+            }
+
+            if pps.scc_extension_flag {
+                self.pps_scc_extension()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn pps_range_extension(&mut self) -> anyhow::Result<()> {
+        let pps = self.data;
+        let ext = &pps.range_extension;
+
+        if pps.transform_skip_enabled_flag {
+            self.ue(ext.log2_max_transform_skip_block_size_minus2)?;
+        }
+
+        self.bit(ext.cross_component_prediction_enabled_flag)?;
+        self.bit(ext.chroma_qp_offset_list_enabled_flag)?;
+        if ext.chroma_qp_offset_list_enabled_flag {
+            self.ue(ext.diff_cu_chroma_qp_offset_depth)?;
+            self.ue(ext.chroma_qp_offset_list_len_minus1)?;
+            for i in 0..=usize::try_from(ext.chroma_qp_offset_list_len_minus1)? {
+                self.se(ext.cb_qp_offset_list[i])?;
+                self.se(ext.cr_qp_offset_list[i])?;
+            }
+        }
+
+        self.ue(ext.log2_sao_offset_scale_luma)?;
+        self.ue(ext.log2_sao_offset_scale_chroma)
+    }
+
+    fn pps_scc_extension(&mut self) -> anyhow::Result<()> {
+        let scc = &self.data.scc_extension;
+
+        self.bit(scc.curr_pic_ref_enabled_flag)?;
+        self.bit(scc.residual_adaptive_colour_transform_enabled_flag)?;
+        if scc.residual_adaptive_colour_transform_enabled_flag {
+            self.bit(scc.slice_act_qp_offsets_present_flag)?;
+            self.se(scc.act_y_qp_offset_plus5)?;
+            self.se(scc.act_cb_qp_offset_plus5)?;
+            self.se(scc.act_cr_qp_offset_plus3)?;
+        }
+
+        self.bit(scc.palette_predictor_initializers_present_flag)?;
+        if scc.palette_predictor_initializers_present_flag {
+            self.ue(scc.num_palette_predictor_initializers)?;
+            if scc.num_palette_predictor_initializers > 0 {
+                self.bit(scc.monochrome_palette_flag)?;
+                self.ue(scc.luma_bit_depth_entry_minus8)?;
+                if !scc.monochrome_palette_flag {
+                    self.ue(scc.chroma_bit_depth_entry_minus8)?;
+                }
+
+                let num_comps = if scc.monochrome_palette_flag { 1 } else { 3 };
+                for comp in 0..num_comps {
+                    let num_bits = if comp == 0 {
+                        scc.luma_bit_depth_entry_minus8 + 8
+                    } else {
+                        scc.chroma_bit_depth_entry_minus8 + 8
+                    };
+
+                    for i in 0..usize::from(scc.num_palette_predictor_initializers) {
+                        self.bits(
+                            scc.palette_predictor_initializer[comp][i],
+                            usize::from(num_bits),
+                        )?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a, W: Write> Synthesizer<'a, SliceHeader, W> {
+    /// Write `header` as the beginning of a slice segment NAL unit of type `nalu_type` and
+    /// temporal id `temporal_id` into `writer`. `sps` and `pps` are the parameter sets the slice
+    /// refers to.
+    ///
+    /// The header is followed by its `byte_alignment()`. Returns the size in bits of the NAL unit
+    /// header and slice segment header, excluding the start code and emulation-prevention bytes,
+    /// i.e. the offset at which the slice segment data must be inserted. Like in the parser, the
+    /// slice segment header extension is always empty.
+    pub fn synthesize(
+        header: &'a SliceHeader,
+        sps: &Sps,
+        pps: &Pps,
+        nalu_type: NaluType,
+        temporal_id: u8,
+        writer: W,
+        ep_enabled: bool,
+    ) -> anyhow::Result<usize> {
+        if nalu_type as u8 > NaluType::RsvVcl31 as u8 {
+            return Err(anyhow!("{:?} is not a slice NAL unit type", nalu_type));
+        }
+
+        let mut s = Self::new(writer, header, ep_enabled)?;
+
+        s.nalu_header(nalu_type, temporal_id)?;
+        s.slice_segment_header(sps, pps, nalu_type)?;
+
+        // byte_alignment() has the same layout as the RBSP trailing bits.
+        s.writer.write_rbsp_trailing_bits()?;
+        let header_bit_size = s.writer.num_bits_written();
+        s.writer.into_inner()?;
+
+        Ok(header_bit_size)
+    }
+
+    fn slice_segment_header(
+        &mut self,
+        sps: &Sps,
+        pps: &Pps,
+        nalu_type: NaluType,
+    ) -> anyhow::Result<()> {
+        let hdr = self.data;
+        let chroma_array_type = if sps.separate_colour_plane_flag {
+            0
+        } else {
+            sps.chroma_format_idc
+        };
+
+        self.bit(hdr.first_slice_segment_in_pic_flag)?;
+        if nalu_type.is_irap() {
+            self.bit(hdr.no_output_of_prior_pics_flag)?;
+        }
+
+        self.ue(hdr.pic_parameter_set_id)?;
+
+        let dependent_slice_segment_flag =
+            !hdr.first_slice_segment_in_pic_flag && hdr.dependent_slice_segment_flag;
+
+        if !hdr.first_slice_segment_in_pic_flag {
+            if pps.dependent_slice_segments_enabled_flag {
+                self.bit(hdr.dependent_slice_segment_flag)?;
+            }
+
+            // (7-10) to (7-13), (7-15), (7-17) and (7-19)
+            let ctb_log2_size_y = u32::from(sps.log2_min_luma_coding_block_size_minus3)
+                + 3
+                + u32::from(sps.log2_diff_max_min_luma_coding_block_size);
+            let ctb_size_y = 1u32 << ctb_log2_size_y;
+            let pic_width_in_ctbs_y = u32::from(sps.pic_width_in_luma_samples).div_ceil(ctb_size_y);
+            let pic_height_in_ctbs_y =
+                u32::from(sps.pic_height_in_luma_samples).div_ceil(ctb_size_y);
+
+            self.bits(
+                hdr.segment_address,
+                ceil_log2(pic_width_in_ctbs_y * pic_height_in_ctbs_y),
+            )?;
+        }
+
+        if !dependent_slice_segment_flag {
+            // slice_reserved_flag
+            self.zero_bits(usize::from(pps.num_extra_slice_header_bits))?;
+            self.ue(hdr.type_ as u8)?;
+
+            if pps.output_flag_present_flag {
+                self.bit(hdr.pic_output_flag)?;
+            }
+
+            if sps.separate_colour_plane_flag {
+                self.bits(hdr.colour_plane_id, 2)?;
+            }
+
+            if !nalu_type.is_idr() {
+                self.poc_and_ref_pic_sets(sps)?;
+
+                if sps.temporal_mvp_enabled_flag {
+                    self.bit(hdr.temporal_mvp_enabled_flag)?;
+                }
+            }
+
+            if sps.sample_adaptive_offset_enabled_flag {
+                self.bit(hdr.sao_luma_flag)?;
+                if chroma_array_type != 0 {
+                    self.bit(hdr.sao_chroma_flag)?;
+                }
+            }
+
+            if hdr.type_.is_p() || hdr.type_.is_b() {
+                self.inter_prediction(sps, pps, chroma_array_type)?;
+            }
+
+            self.se(hdr.qp_delta)?;
+            if pps.slice_chroma_qp_offsets_present_flag {
+                self.se(hdr.cb_qp_offset)?;
+                self.se(hdr.cr_qp_offset)?;
+            }
+
+            if pps.scc_extension.slice_act_qp_offsets_present_flag {
+                self.se(hdr.slice_act_y_qp_offset)?;
+                self.se(hdr.slice_act_cb_qp_offset)?;
+                self.se(hdr.slice_act_cr_qp_offset)?;
+            }
+
+            if pps.range_extension.chroma_qp_offset_list_enabled_flag {
+                self.bit(hdr.cu_chroma_qp_offset_enabled_flag)?;
+            }
+
+            let deblocking_filter_override_flag =
+                pps.deblocking_filter_override_enabled_flag && hdr.deblocking_filter_override_flag;
+            if pps.deblocking_filter_override_enabled_flag {
+                self.bit(deblocking_filter_override_flag)?;
+            }
+
+            let deblocking_filter_disabled_flag = if deblocking_filter_override_flag {
+                self.bit(hdr.deblocking_filter_disabled_flag)?;
+                if !hdr.deblocking_filter_disabled_flag {
+                    self.se(hdr.beta_offset_div2)?;
+                    self.se(hdr.tc_offset_div2)?;
+                }
+
+                hdr.deblocking_filter_disabled_flag
+            } else {
+                pps.deblocking_filter_disabled_flag
+            };
+
+            if pps.loop_filter_across_slices_enabled_flag
+                && (hdr.sao_luma_flag || hdr.sao_chroma_flag || !deblocking_filter_disabled_flag)
+            {
+                self.bit(hdr.loop_filter_across_slices_enabled_flag)?;
+            }
+        }
+
+        if pps.tiles_enabled_flag || pps.entropy_coding_sync_enabled_flag {
+            self.ue(hdr.num_entry_point_offsets)?;
+            if hdr.num_entry_point_offsets > 0 {
+                self.ue(hdr.offset_len_minus1)?;
+
+                let num_entry_point_offsets = usize::try_from(hdr.num_entry_point_offsets)?;
+                let offsets = hdr
+                    .entry_point_offset_minus1
+                    .get(..num_entry_point_offsets)
+                    .ok_or_else(|| anyhow!("Too many entry points: {}", num_entry_point_offsets))?;
+
+                for &offset in offsets {
+                    self.bits(offset, usize::from(hdr.offset_len_minus1) + 1)?;
+                }
+            }
+        }
+
+        if pps.slice_segment_header_extension_present_flag {
+            // slice_segment_header_extension_length
+            self.ue(0u32)?;
+        }
+
+        Ok(())
+    }
+
+    /// Write the POC LSBs and the short and long-term reference picture sets of a non-IDR
+    /// picture.
+    fn poc_and_ref_pic_sets(&mut self, sps: &Sps) -> anyhow::Result<()> {
+        let hdr = self.data;
+        let poc_lsb_bits = usize::from(sps.log2_max_pic_order_cnt_lsb_minus4) + 4;
+
+        self.bits(hdr.pic_order_cnt_lsb, poc_lsb_bits)?;
+        self.bit(hdr.short_term_ref_pic_set_sps_flag)?;
+        if !hdr.short_term_ref_pic_set_sps_flag {
+            self.short_term_ref_pic_set(
+                &hdr.short_term_ref_pic_set,
+                sps.num_short_term_ref_pic_sets,
+                sps,
+            )?;
+        } else if sps.num_short_term_ref_pic_sets > 1 {
+            self.bits(
+                hdr.short_term_ref_pic_set_idx,
+                ceil_log2(u32::from(sps.num_short_term_ref_pic_sets)),
+            )?;
+        }
+
+        if sps.long_term_ref_pics_present_flag {
+            if sps.num_long_term_ref_pics_sps > 0 {
+                self.ue(hdr.num_long_term_sps)?;
+            }
+            self.ue(hdr.num_long_term_pics)?;
+
+            let num_long_term_sps = usize::from(hdr.num_long_term_sps);
+            let num_long_term = num_long_term_sps + usize::from(hdr.num_long_term_pics);
+            if num_long_term > hdr.poc_lsb_lt.len() {
+                return Err(anyhow!("Too many long-term pictures: {}", num_long_term));
+            }
+
+            for i in 0..num_long_term {
+                if i < num_long_term_sps {
+                    if sps.num_long_term_ref_pics_sps > 1 {
+                        self.bits(
+                            hdr.lt_idx_sps[i],
+                            ceil_log2(u32::from(sps.num_long_term_ref_pics_sps)),
+                        )?;
+                    }
+                } else {
+                    self.bits(hdr.poc_lsb_lt[i], poc_lsb_bits)?;
+                    self.bit(hdr.used_by_curr_pic_lt[i])?;
+                }
+
+                self.bit(hdr.delta_poc_msb_present_flag[i])?;
+                if hdr.delta_poc_msb_present_flag[i] {
+                    // The header stores DeltaPocMsbCycleLt, which accumulates the values of
+                    // delta_poc_msb_cycle_lt (7-52).
+                    let prev = if i != 0 && i != num_long_term_sps {
+                        hdr.delta_poc_msb_cycle_lt[i - 1]
+                    } else {
+                        0
+                    };
+
+                    let delta_poc_msb_cycle_lt = hdr.delta_poc_msb_cycle_lt[i]
+                        .checked_sub(prev)
+                        .ok_or_else(|| anyhow!("Invalid delta_poc_msb_cycle_lt[{}]", i))?;
+                    self.ue(delta_poc_msb_cycle_lt)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write the syntax elements specific to P and B slices.
+    fn inter_prediction(
+        &mut self,
+        sps: &Sps,
+        pps: &Pps,
+        chroma_array_type: u8,
+    ) -> anyhow::Result<()> {
+        let hdr = self.data;
+
+        self.bit(hdr.num_ref_idx_active_override_flag)?;
+        let (num_ref_idx_l0_active_minus1, num_ref_idx_l1_active_minus1) =
+            if hdr.num_ref_idx_active_override_flag {
+                self.ue(hdr.num_ref_idx_l0_active_minus1)?;
+                if hdr.type_.is_b() {
+                    self.ue(hdr.num_ref_idx_l1_active_minus1)?;
+                }
+
+                (
+                    hdr.num_ref_idx_l0_active_minus1,
+                    hdr.num_ref_idx_l1_active_minus1,
+                )
+            } else {
+                (
+                    pps.num_ref_idx_l0_default_active_minus1,
+                    pps.num_ref_idx_l1_default_active_minus1,
+                )
+            };
+
+        if pps.lists_modification_present_flag {
+            let num_pic_total_curr = Self::num_pic_total_curr(hdr, sps, pps)?;
+            if num_pic_total_curr > 1 {
+                let num_bits = ceil_log2(num_pic_total_curr);
+                let rplm = &hdr.ref_pic_list_modification;
+
+                self.bit(rplm.ref_pic_list_modification_flag_l0)?;
+                if rplm.ref_pic_list_modification_flag_l0 {
+                    self.list_entries(&rplm.list_entry_l0, num_ref_idx_l0_active_minus1, num_bits)?;
+                }
+
+                if hdr.type_.is_b() {
+                    self.bit(rplm.ref_pic_list_modification_flag_l1)?;
+                    if rplm.ref_pic_list_modification_flag_l1 {
+                        self.list_entries(
+                            &rplm.list_entry_l1,
+                            num_ref_idx_l1_active_minus1,
+                            num_bits,
+                        )?;
+                    }
+                }
+            }
+        }
+
+        if hdr.type_.is_b() {
+            self.bit(hdr.mvd_l1_zero_flag)?;
+        }
+
+        if pps.cabac_init_present_flag {
+            self.bit(hdr.cabac_init_flag)?;
+        }
+
+        if hdr.temporal_mvp_enabled_flag {
+            if hdr.type_.is_b() {
+                self.bit(hdr.collocated_from_l0_flag)?;
+            }
+
+            if (hdr.collocated_from_l0_flag && num_ref_idx_l0_active_minus1 > 0)
+                || (!hdr.collocated_from_l0_flag && num_ref_idx_l1_active_minus1 > 0)
+            {
+                self.ue(hdr.collocated_ref_idx)?;
+            }
+        }
+
+        if (pps.weighted_pred_flag && hdr.type_.is_p())
+            || (pps.weighted_bipred_flag && hdr.type_.is_b())
+        {
+            self.pred_weight_table(
+                &hdr.pred_weight_table,
+                chroma_array_type,
+                num_ref_idx_l0_active_minus1,
+                num_ref_idx_l1_active_minus1,
+            )?;
+        }
+
+        self.ue(hdr.five_minus_max_num_merge_cand)?;
+        if sps.scc_extension.motion_vector_resolution_control_idc == 2 {
+            self.bit(hdr.use_integer_mv_flag)?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns `NumPicTotalCurr` (7-55).
+    fn num_pic_total_curr(hdr: &SliceHeader, sps: &Sps, pps: &Pps) -> anyhow::Result<u32> {
+        let rps = if hdr.short_term_ref_pic_set_sps_flag {
+            sps.short_term_ref_pic_set
+                .get(usize::from(hdr.short_term_ref_pic_set_idx))
+                .ok_or_else(|| anyhow!("Invalid RPS {}", hdr.short_term_ref_pic_set_idx))?
+        } else {
+            &hdr.short_term_ref_pic_set
+        };
+
+        let num_long_term =
+            usize::from(hdr.num_long_term_sps) + usize::from(hdr.num_long_term_pics);
+        let used = rps.used_by_curr_pic_s0[..usize::from(rps.num_negative_pics)]
+            .iter()
+            .chain(&rps.used_by_curr_pic_s1[..usize::from(rps.num_positive_pics)])
+            .chain(&hdr.used_by_curr_pic_lt[..num_long_term])
+            .filter(|&&used| used)
+            .count();
+
+        Ok(used as u32 + u32::from(pps.scc_extension.curr_pic_ref_enabled_flag))
+    }
+
+    fn list_entries(
+        &mut self,
+        entries: &[u32],
+        num_ref_idx_active_minus1: u8,
+        num_bits: usize,
+    ) -> anyhow::Result<()> {
+        let entries = entries
+            .get(..=usize::from(num_ref_idx_active_minus1))
+            .ok_or_else(|| anyhow!("Missing reference list modification entries"))?;
+
+        for &entry in entries {
+            self.bits(entry, num_bits)?;
+        }
+
+        Ok(())
+    }
+
+    /// Write `pred_weight_table()` (7.3.6.3).
+    fn pred_weight_table(
+        &mut self,
+        pwt: &PredWeightTable,
+        chroma_array_type: u8,
+        num_ref_idx_l0_active_minus1: u8,
+        num_ref_idx_l1_active_minus1: u8,
+    ) -> anyhow::Result<()> {
+        self.ue(pwt.luma_log2_weight_denom)?;
+        if chroma_array_type != 0 {
+            self.se(pwt.delta_chroma_log2_weight_denom)?;
+        }
+
+        let num_l0 = usize::from(num_ref_idx_l0_active_minus1) + 1;
+        for &flag in &pwt.luma_weight_l0_flag[..num_l0] {
+            self.bit(flag)?;
+        }
+
+        if chroma_array_type != 0 {
+            for &flag in &pwt.chroma_weight_l0_flag[..num_l0] {
+                self.bit(flag)?;
+            }
+        }
+
+        for i in 0..num_l0 {
+            if pwt.luma_weight_l0_flag[i] {
+                self.se(pwt.delta_luma_weight_l0[i])?;
+                self.se(pwt.luma_offset_l0[i])?;
+            }
+
+            if chroma_array_type != 0 && pwt.chroma_weight_l0_flag[i] {
+                for j in 0..2 {
+                    self.se(pwt.delta_chroma_weight_l0[i][j])?;
+                    self.se(pwt.delta_chroma_offset_l0[i][j])?;
+                }
+            }
+        }
+
+        if self.data.type_.is_b() {
+            let num_l1 = usize::from(num_ref_idx_l1_active_minus1) + 1;
+            for &flag in &pwt.luma_weight_l1_flag[..num_l1] {
+                self.bit(flag)?;
+            }
+
+            if chroma_array_type != 0 {
+                for &flag in &pwt.chroma_weight_l1_flag[..num_l1] {
+                    self.bit(flag)?;
+                }
+            }
+
+            for i in 0..num_l1 {
+                if pwt.luma_weight_l1_flag[i] {
+                    self.se(pwt.delta_luma_weight_l1[i])?;
+                    self.se(pwt.luma_offset_l1[i])?;
+                }
+
+                if chroma_array_type != 0 && pwt.chroma_weight_l1_flag[i] {
+                    for j in 0..2 {
+                        self.se(pwt.delta_chroma_weight_l1[i][j])?;
+                        self.se(pwt.delta_chroma_offset_l1[i][j])?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::codec::h265::parser::HrdParams;
+    use crate::codec::h265::parser::Level;
+    use crate::codec::h265::parser::Nalu;
+    use crate::codec::h265::parser::NaluType;
+    use crate::codec::h265::parser::Parser;
+    use crate::codec::h265::parser::Pps;
+    use crate::codec::h265::parser::ShortTermRefPicSet;
+    use crate::codec::h265::parser::SliceHeader;
+    use crate::codec::h265::parser::SliceType;
+    use crate::codec::h265::parser::Sps;
+    use crate::codec::h265::parser::Vps;
+    use crate::codec::h265::parser::VuiParams;
+    use crate::codec::h265::synthesizer::Synthesizer;
+
+    const STREAMS: [&[u8]; 6] = [
+        include_bytes!("test_data/bear.h265"),
+        include_bytes!("test_data/bbb.h265"),
+        include_bytes!("test_data/test-25fps.h265"),
+        include_bytes!("test_data/64x64-I.h265"),
+        include_bytes!("test_data/64x64-I-P.h265"),
+        include_bytes!("test_data/64x64-I-P-B-P.h265"),
+    ];
+
+    fn next_nalu(buf: &[u8], nalu_type: NaluType) -> Nalu<&[u8]> {
+        let nalu = Nalu::next(&mut Cursor::new(buf)).unwrap();
+        assert_eq!(nalu.header().nalu_type(), nalu_type);
+        nalu
+    }
+
+    fn synthesize_vps(vps: &Vps, parser: &mut Parser) -> Vps {
+        let mut buf = vec![];
+        Synthesizer::<Vps, _>::synthesize(vps, &mut buf, true).unwrap();
+
+        parser
+            .parse_vps(&next_nalu(&buf, NaluType::VpsNut))
+            .unwrap()
+            .clone()
+    }
+
+    fn synthesize_sps(sps: &Sps, parser: &mut Parser) -> Sps {
+        let mut buf = vec![];
+        Synthesizer::<Sps, _>::synthesize(sps, &mut buf, true).unwrap();
+
+        parser
+            .parse_sps(&next_nalu(&buf, NaluType::SpsNut))
+            .unwrap()
+            .clone()
+    }
+
+    fn synthesize_pps(pps: &Pps, parser: &mut Parser) -> Pps {
+        let mut buf = vec![];
+        Synthesizer::<Pps, _>::synthesize(pps, &mut buf, true).unwrap();
+
+        parser
+            .parse_pps(&next_nalu(&buf, NaluType::PpsNut))
+            .unwrap()
+            .clone()
+    }
+
+    /// Synthesizes `header` and parses it back. Also checks that the returned size of the header
+    /// matches the one computed by the parser.
+    fn synthesize_slice_header(
+        header: &SliceHeader,
+        nalu_type: NaluType,
+        temporal_id: u8,
+        parser: &mut Parser,
+    ) -> SliceHeader {
+        let pps = parser.get_pps(header.pic_parameter_set_id).unwrap();
+        let sps = parser.get_sps(pps.seq_parameter_set_id).unwrap();
+        let mut buf = vec![];
+        let header_bit_size = Synthesizer::<SliceHeader, _>::synthesize(
+            header,
+            sps,
+            pps,
+            nalu_type,
+            temporal_id,
+            &mut buf,
+            true,
+        )
+        .unwrap();
+
+        let nalu = next_nalu(&buf, nalu_type);
+        assert_eq!(nalu.header().temporal_id_plus1(), temporal_id + 1);
+
+        let synthesized = parser.parse_slice_header(nalu).unwrap().header().clone();
+        assert_eq!(synthesized.header_bit_size as usize, header_bit_size);
+
+        synthesized
+    }
+
+    /// Parses all the parameter sets and slice headers of each stream, synthesizes them and
+    /// checks that parsing them back gives the same result.
+    #[test]
+    fn round_trip_streams() {
+        for stream in STREAMS {
+            let mut cursor = Cursor::new(stream);
+            let mut parser = Parser::default();
+            let mut synth_parser = Parser::default();
+
+            while let Ok(nalu) = Nalu::next(&mut cursor) {
+                let nalu_type = nalu.header().nalu_type();
+                match nalu_type {
+                    NaluType::VpsNut => {
+                        let vps = parser.parse_vps(&nalu).unwrap().clone();
+                        assert_eq!(vps, synthesize_vps(&vps, &mut synth_parser));
+                    }
+                    NaluType::SpsNut => {
+                        let sps = parser.parse_sps(&nalu).unwrap().clone();
+                        assert_eq!(sps, synthesize_sps(&sps, &mut synth_parser));
+                    }
+                    NaluType::PpsNut => {
+                        let pps = parser.parse_pps(&nalu).unwrap().clone();
+                        assert_eq!(pps, synthesize_pps(&pps, &mut synth_parser));
+                    }
+                    _ if (nalu_type as u8) < NaluType::RsvVclN10 as u8
+                        || (NaluType::BlaWLp..=NaluType::CraNut).contains(&nalu_type) =>
+                    {
+                        let temporal_id = nalu.header().temporal_id_plus1() - 1;
+                        let header = parser.parse_slice_header(nalu).unwrap().header().clone();
+                        let synthesized = synthesize_slice_header(
+                            &header,
+                            nalu_type,
+                            temporal_id,
+                            &mut synth_parser,
+                        );
+
+                        assert_eq!(header, synthesized);
+                    }
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    /// Finds the first NAL unit of `nalu_type` in `stream` and parses it along with everything
+    /// that precedes it.
+    fn parse_until(stream: &[u8], nalu_type: NaluType, parser: &mut Parser) {
+        let mut cursor = Cursor::new(stream);
+        loop {
+            let nalu = Nalu::next(&mut cursor).unwrap();
+            let found = nalu.header().nalu_type() == nalu_type;
+            match nalu.header().nalu_type() {
+                NaluType::VpsNut => drop(parser.parse_vps(&nalu).unwrap()),
+                NaluType::SpsNut => drop(parser.parse_sps(&nalu).unwrap()),
+                NaluType::PpsNut => drop(parser.parse_pps(&nalu).unwrap()),
+                _ => (),
+            }
+
+            if found {
+                return;
+            }
+        }
+    }
+
+    /// Checks the syntax elements that are absent from the test streams.
+    #[test]
+    fn round_trip_optional_syntax() {
+        let mut parser = Parser::default();
+        parse_until(STREAMS[2], NaluType::PpsNut, &mut parser);
+        let mut synth_parser = Parser::default();
+
+        let mut hrd = HrdParams {
+            nal_hrd_parameters_present_flag: true,
+            vcl_hrd_parameters_present_flag: true,
+            sub_pic_hrd_params_present_flag: true,
+            tick_divisor_minus2: 40,
+            du_cpb_removal_delay_increment_length_minus1: 7,
+            sub_pic_cpb_params_in_pic_timing_sei_flag: true,
+            dpb_output_delay_du_length_minus1: 9,
+            bit_rate_scale: 2,
+            cpb_size_scale: 3,
+            cpb_size_du_scale: 4,
+            initial_cpb_removal_delay_length_minus1: 23,
+            au_cpb_removal_delay_length_minus1: 15,
+            dpb_output_delay_length_minus1: 4,
+            fixed_pic_rate_general_flag: [true, false, false, false, false, false, false],
+            fixed_pic_rate_within_cvs_flag: [true, true, false, false, false, false, false],
+            elemental_duration_in_tc_minus1: [1, 3, 0, 0, 0, 0, 0],
+            low_delay_hrd_flag: [false, false, true, false, false, false, false],
+            cpb_cnt_minus1: [1, 0, 0, 0, 0, 0, 0],
+            ..Default::default()
+        };
+        // Sub-layer 0 has two CPB specifications, the other ones only have one.
+        for (i, sub_layer) in hrd.nal_hrd[..3]
+            .iter_mut()
+            .chain(hrd.vcl_hrd[..3].iter_mut())
+            .enumerate()
+        {
+            let n = if i % 3 == 0 { 2 } else { 1 };
+            let i = i as u32;
+            sub_layer.bit_rate_value_minus1[..n].copy_from_slice(&[1000 + i, 2000 + i][..n]);
+            sub_layer.cpb_size_value_minus1[..n].copy_from_slice(&[3000 + i, 4000 + i][..n]);
+            sub_layer.cpb_size_du_value_minus1[..n].copy_from_slice(&[500 + i, 600 + i][..n]);
+            sub_layer.bit_rate_du_value_minus1[..n].copy_from_slice(&[700 + i, 800 + i][..n]);
+            sub_layer.cbr_flag[..n].copy_from_slice(&[i.is_multiple_of(2), true][..n]);
+        }
+
+        // A VPS with sub-layers, timing information and two sets of HRD parameters, the
+        // second one without the common information.
+        let mut vps = parser.get_vps(0).unwrap().clone();
+        vps.max_sub_layers_minus1 = 2;
+        vps.temporal_id_nesting_flag = false;
+        vps.sub_layer_ordering_info_present_flag = true;
+        vps.max_dec_pic_buffering_minus1[..3].copy_from_slice(&[2, 3, 4]);
+        vps.max_num_reorder_pics[..3].copy_from_slice(&[0, 1, 2]);
+        vps.max_latency_increase_plus1[..3].copy_from_slice(&[0, 5, 6]);
+        let ptl = &mut vps.profile_tier_level;
+        ptl.sub_layer_profile_present_flag[..2].copy_from_slice(&[true, false]);
+        ptl.sub_layer_level_present_flag[..2].copy_from_slice(&[true, true]);
+        ptl.sub_layer_profile_idc[0] = ptl.general_profile_idc;
+        ptl.sub_layer_profile_compatibility_flag[0][1] = true;
+        ptl.sub_layer_progressive_source_flag[0] = true;
+        ptl.sub_layer_frame_only_constraint_flag[0] = true;
+        ptl.sub_layer_level_idc[..2].copy_from_slice(&[Level::L2, Level::L3_1]);
+        vps.timing_info_present_flag = true;
+        vps.num_units_in_tick = 1;
+        vps.time_scale = 25;
+        vps.poc_proportional_to_timing_flag = true;
+        vps.num_ticks_poc_diff_one_minus1 = 1;
+        vps.num_hrd_parameters = 2;
+        vps.hrd_layer_set_idx = vec![0, 0];
+        vps.cprms_present_flag = vec![true, false];
+        let common_less_hrd = HrdParams {
+            fixed_pic_rate_general_flag: hrd.fixed_pic_rate_general_flag,
+            fixed_pic_rate_within_cvs_flag: hrd.fixed_pic_rate_within_cvs_flag,
+            elemental_duration_in_tc_minus1: hrd.elemental_duration_in_tc_minus1,
+            low_delay_hrd_flag: hrd.low_delay_hrd_flag,
+            cpb_cnt_minus1: hrd.cpb_cnt_minus1,
+            ..Default::default()
+        };
+        vps.hrd_parameters = vec![hrd.clone(), common_less_hrd];
+        assert_eq!(vps, synthesize_vps(&vps, &mut synth_parser));
+
+        // Inter predicted from the first set, shifted by -1.
+        let mut inter_st = ShortTermRefPicSet {
+            inter_ref_pic_set_prediction_flag: true,
+            delta_rps_sign: true,
+            num_negative_pics: 3,
+            num_positive_pics: 1,
+            num_delta_pocs: 4,
+            ..Default::default()
+        };
+        inter_st.delta_poc_s0[..3].copy_from_slice(&[-1, -2, -4]);
+        inter_st.used_by_curr_pic_s0[..3].copy_from_slice(&[true, false, true]);
+        inter_st.delta_poc_s1[0] = 1;
+        inter_st.used_by_curr_pic_s1[0] = true;
+
+        let mut explicit_st = ShortTermRefPicSet {
+            num_negative_pics: 2,
+            num_positive_pics: 1,
+            num_delta_pocs: 3,
+            ..Default::default()
+        };
+        explicit_st.delta_poc_s0[..2].copy_from_slice(&[-1, -3]);
+        explicit_st.used_by_curr_pic_s0[..2].copy_from_slice(&[true, true]);
+        explicit_st.delta_poc_s1[0] = 2;
+        explicit_st.used_by_curr_pic_s1[0] = true;
+
+        let mut sps = parser.get_sps(0).unwrap().clone();
+        sps.max_sub_layers_minus1 = vps.max_sub_layers_minus1;
+        sps.temporal_id_nesting_flag = false;
+        sps.profile_tier_level = vps.profile_tier_level.clone();
+        sps.sub_layer_ordering_info_present_flag = true;
+        sps.max_dec_pic_buffering_minus1[..3].copy_from_slice(&[3, 4, 5]);
+        sps.max_num_reorder_pics[..3].copy_from_slice(&[0, 1, 2]);
+        sps.max_latency_increase_plus1[..3].copy_from_slice(&[0, 5, 6]);
+        sps.scaling_list_enabled_flag = true;
+        sps.scaling_list_data_present_flag = true;
+        sps.scaling_list = parser.get_pps(0).unwrap().scaling_list.clone();
+        sps.scaling_list.scaling_list_4x4[1] = [16; 16];
+        sps.scaling_list.scaling_list_8x8[4][10] = 40;
+        sps.scaling_list.scaling_list_16x16[2] = [20; 64];
+        sps.scaling_list.scaling_list_dc_coef_minus8_16x16[2] = 4;
+        sps.scaling_list.scaling_list_32x32[3][63] = 90;
+        sps.scaling_list.scaling_list_dc_coef_minus8_32x32[3] = -7;
+        sps.amp_enabled_flag = true;
+        sps.sample_adaptive_offset_enabled_flag = true;
+        sps.num_short_term_ref_pic_sets = 2;
+        sps.short_term_ref_pic_set = vec![explicit_st.clone(), inter_st];
+        sps.long_term_ref_pics_present_flag = true;
+        sps.num_long_term_ref_pics_sps = 2;
+        sps.lt_ref_pic_poc_lsb_sps[..2].copy_from_slice(&[5, 9]);
+        sps.used_by_curr_pic_lt_sps_flag[..2].copy_from_slice(&[true, false]);
+        sps.temporal_mvp_enabled_flag = true;
+        sps.vui_parameters_present_flag = true;
+        sps.vui_parameters = VuiParams {
+            aspect_ratio_info_present_flag: true,
+            aspect_ratio_idc: 255,
+            sar_width: 4,
+            sar_height: 3,
+            overscan_info_present_flag: true,
+            overscan_appropriate_flag: true,
+            video_signal_type_present_flag: true,
+            video_format: 2,
+            video_full_range_flag: true,
+            colour_description_present_flag: true,
+            colour_primaries: 9,
+            transfer_characteristics: 16,
+            matrix_coeffs: 9,
+            chroma_loc_info_present_flag: true,
+            chroma_sample_loc_type_top_field: 2,
+            chroma_sample_loc_type_bottom_field: 2,
+            frame_field_info_present_flag: true,
+            default_display_window_flag: true,
+            def_disp_win_left_offset: 1,
+            def_disp_win_right_offset: 2,
+            def_disp_win_top_offset: 3,
+            def_disp_win_bottom_offset: 4,
+            timing_info_present_flag: true,
+            num_units_in_tick: 1001,
+            time_scale: 60000,
+            poc_proportional_to_timing_flag: true,
+            num_ticks_poc_diff_one_minus1: 0,
+            hrd_parameters_present_flag: true,
+            hrd,
+            bitstream_restriction_flag: true,
+            motion_vectors_over_pic_boundaries_flag: true,
+            restricted_ref_pic_lists_flag: true,
+            min_spatial_segmentation_idc: 4,
+            max_bytes_per_pic_denom: 2,
+            max_bits_per_min_cu_denom: 1,
+            log2_max_mv_length_horizontal: 15,
+            log2_max_mv_length_vertical: 15,
+            ..Default::default()
+        };
+        sps.extension_present_flag = true;
+        sps.range_extension_flag = true;
+        sps.range_extension.transform_skip_rotation_enabled_flag = true;
+        sps.range_extension.implicit_rdpcm_enabled_flag = true;
+        sps.range_extension.persistent_rice_adaptation_enabled_flag = true;
+        sps.scc_extension_flag = true;
+        let scc = &mut sps.scc_extension;
+        scc.palette_mode_enabled_flag = true;
+        scc.palette_max_size = 31;
+        scc.delta_palette_max_predictor_size = 32;
+        scc.palette_predictor_initializers_present_flag = true;
+        scc.num_palette_predictor_initializer_minus1 = 2;
+        for comp in 0..3 {
+            scc.palette_predictor_initializer[comp][..3].copy_from_slice(&[
+                comp as u32,
+                128,
+                255 - comp as u32,
+            ]);
+        }
+        scc.motion_vector_resolution_control_idc = 2;
+        scc.intra_boundary_filtering_disabled_flag = true;
+        assert_eq!(sps, synthesize_sps(&sps, &mut synth_parser));
+
+        let mut pps = parser.get_pps(0).unwrap().clone();
+        pps.dependent_slice_segments_enabled_flag = true;
+        pps.output_flag_present_flag = true;
+        pps.num_extra_slice_header_bits = 2;
+        pps.cabac_init_present_flag = true;
+        pps.cu_qp_delta_enabled_flag = true;
+        pps.diff_cu_qp_delta_depth = 1;
+        pps.slice_chroma_qp_offsets_present_flag = true;
+        pps.weighted_pred_flag = true;
+        pps.weighted_bipred_flag = true;
+        pps.tiles_enabled_flag = true;
+        pps.num_tile_columns_minus1 = 1;
+        pps.num_tile_rows_minus1 = 1;
+        pps.uniform_spacing_flag = false;
+        pps.column_width_minus1[..2].copy_from_slice(&[0, sps.pic_width_in_ctbs_y - 2]);
+        pps.row_height_minus1[..2].copy_from_slice(&[0, sps.pic_height_in_ctbs_y - 2]);
+        pps.loop_filter_across_tiles_enabled_flag = true;
+        pps.loop_filter_across_slices_enabled_flag = true;
+        pps.deblocking_filter_control_present_flag = true;
+        pps.deblocking_filter_override_enabled_flag = true;
+        pps.beta_offset_div2 = -2;
+        pps.tc_offset_div2 = 3;
+        pps.lists_modification_present_flag = true;
+        pps.slice_segment_header_extension_present_flag = true;
+        pps.extension_present_flag = true;
+        pps.range_extension_flag = true;
+        let range = &mut pps.range_extension;
+        range.chroma_qp_offset_list_enabled_flag = true;
+        range.diff_cu_chroma_qp_offset_depth = 1;
+        range.chroma_qp_offset_list_len_minus1 = 1;
+        range.cb_qp_offset_list[..2].copy_from_slice(&[-3, 4]);
+        range.cr_qp_offset_list[..2].copy_from_slice(&[5, -6]);
+        pps.scc_extension_flag = true;
+        let scc = &mut pps.scc_extension;
+        scc.residual_adaptive_colour_transform_enabled_flag = true;
+        scc.slice_act_qp_offsets_present_flag = true;
+        scc.act_y_qp_offset_plus5 = 2;
+        scc.act_cb_qp_offset_plus5 = 7;
+        scc.act_cr_qp_offset_plus3 = -1;
+        scc.palette_predictor_initializers_present_flag = true;
+        scc.num_palette_predictor_initializers = 2;
+        for comp in 0..3 {
+            scc.palette_predictor_initializer[comp][..2].copy_from_slice(&[comp as u8, 200]);
+        }
+        assert_eq!(pps, synthesize_pps(&pps, &mut synth_parser));
+
+        // A B slice with an explicit RPS, long-term pictures from both the SPS and the slice
+        // header, and pretty much every optional syntax element.
+        let mut header = SliceHeader {
+            first_slice_segment_in_pic_flag: false,
+            segment_address: 1,
+            type_: SliceType::B,
+            pic_output_flag: true,
+            pic_order_cnt_lsb: 12,
+            short_term_ref_pic_set_sps_flag: false,
+            short_term_ref_pic_set: explicit_st,
+            num_long_term_sps: 1,
+            num_long_term_pics: 1,
+            temporal_mvp_enabled_flag: true,
+            sao_luma_flag: true,
+            sao_chroma_flag: false,
+            num_ref_idx_active_override_flag: true,
+            num_ref_idx_l0_active_minus1: 2,
+            num_ref_idx_l1_active_minus1: 1,
+            mvd_l1_zero_flag: true,
+            cabac_init_flag: true,
+            collocated_from_l0_flag: false,
+            collocated_ref_idx: 1,
+            five_minus_max_num_merge_cand: 2,
+            use_integer_mv_flag: true,
+            qp_delta: -3,
+            cb_qp_offset: 2,
+            cr_qp_offset: -2,
+            slice_act_y_qp_offset: -4,
+            slice_act_cb_qp_offset: 1,
+            slice_act_cr_qp_offset: 3,
+            cu_chroma_qp_offset_enabled_flag: true,
+            deblocking_filter_override_flag: true,
+            deblocking_filter_disabled_flag: false,
+            beta_offset_div2: 1,
+            tc_offset_div2: -1,
+            loop_filter_across_slices_enabled_flag: true,
+            num_entry_point_offsets: 3,
+            offset_len_minus1: 9,
+            ..Default::default()
+        };
+        header.lt_idx_sps[0] = 1;
+        header.poc_lsb_lt[..2].copy_from_slice(&[9, 3]);
+        header.used_by_curr_pic_lt[..2].copy_from_slice(&[false, true]);
+        header.delta_poc_msb_present_flag[..2].copy_from_slice(&[true, true]);
+        header.delta_poc_msb_cycle_lt[..2].copy_from_slice(&[1, 2]);
+
+        let modification = &mut header.ref_pic_list_modification;
+        modification.ref_pic_list_modification_flag_l0 = true;
+        modification.list_entry_l0 = vec![3, 0, 1];
+        modification.ref_pic_list_modification_flag_l1 = true;
+        modification.list_entry_l1 = vec![2, 3];
+
+        let pwt = &mut header.pred_weight_table;
+        pwt.luma_log2_weight_denom = 5;
+        pwt.delta_chroma_log2_weight_denom = -2;
+        pwt.chroma_log2_weight_denom = 3;
+        pwt.luma_weight_l0_flag[..3].copy_from_slice(&[true, false, true]);
+        pwt.chroma_weight_l0_flag[..3].copy_from_slice(&[false, true, true]);
+        pwt.delta_luma_weight_l0[..3].copy_from_slice(&[3, 0, -7]);
+        pwt.luma_offset_l0[..3].copy_from_slice(&[-20, 0, 17]);
+        pwt.delta_chroma_weight_l0[1] = [4, -4];
+        pwt.delta_chroma_offset_l0[1] = [-100, 300];
+        pwt.delta_chroma_weight_l0[2] = [-1, 1];
+        pwt.delta_chroma_offset_l0[2] = [12, -12];
+        pwt.luma_weight_l1_flag[1] = true;
+        pwt.chroma_weight_l1_flag[0] = true;
+        pwt.delta_luma_weight_l1[1] = 10;
+        pwt.luma_offset_l1[1] = -1;
+        pwt.delta_chroma_weight_l1[0] = [2, 3];
+        pwt.delta_chroma_offset_l1[0] = [-5, 6];
+
+        header.entry_point_offset_minus1[..3].copy_from_slice(&[100, 1000, 1]);
+
+        // The parser derives these from the rest of the header.
+        header.num_pic_total_curr = 4;
+
+        let synthesized = synthesize_slice_header(&header, NaluType::TrailR, 1, &mut synth_parser);
+        header.header_bit_size = synthesized.header_bit_size;
+        header.n_emulation_prevention_bytes = synthesized.n_emulation_prevention_bytes;
+        header.st_rps_bits = synthesized.st_rps_bits;
+        header.curr_rps_idx = synthesized.curr_rps_idx;
+        assert_eq!(header, synthesized);
+    }
+}
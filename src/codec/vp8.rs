@@ -3,5 +3,7 @@
 // found in the LICENSE file.
 
 mod bool_decoder;
+pub mod bool_encoder;
 pub mod parser;
 mod probs;
+pub mod synthesizer;
@@ -0,0 +1,235 @@
+// Copyright 2023 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+//! A VP8 boolean encoder, the counterpart of the boolean decoder, following section 7.3 of RFC
+//! 6386.
+
+use thiserror::Error;
+
+/// Some bits are "encoded" with a 50/50 probability.
+const DEFAULT_PROBABILITY: u8 = 128;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum BoolEncoderError {
+    #[error("value {0} does not fit in {1} bits")]
+    ValueOutOfRange(i64, usize),
+}
+
+pub type BoolEncoderResult<T> = std::result::Result<T, BoolEncoderError>;
+
+/// The encoder state.
+pub struct BoolEncoder {
+    output: Vec<u8>,
+    range: u32,
+    bottom: u32,
+    /// Number of shifts before the next byte of `bottom` is output.
+    bit_count: i32,
+}
+
+impl Default for BoolEncoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BoolEncoder {
+    /// Creates a new instance.
+    pub fn new() -> Self {
+        Self {
+            output: Vec::new(),
+            range: 255,
+            bottom: 0,
+            bit_count: 24,
+        }
+    }
+
+    /// Propagates a carry into the bytes already output.
+    fn add_one_to_output(&mut self) {
+        for byte in self.output.iter_mut().rev() {
+            if *byte == 255 {
+                *byte = 0;
+            } else {
+                *byte += 1;
+                return;
+            }
+        }
+    }
+
+    /// Writes `bit` into the coded stream. The probability of the bit to be zero is
+    /// probability / 256.
+    fn write_bit(&mut self, bit: bool, probability: u8) {
+        let split = 1 + (((self.range - 1) * u32::from(probability)) >> 8);
+
+        if bit {
+            self.bottom += split;
+            self.range -= split;
+        } else {
+            self.range = split;
+        }
+
+        while self.range < 128 {
+            self.range <<= 1;
+
+            if self.bottom & (1 << 31) != 0 {
+                self.add_one_to_output();
+            }
+
+            self.bottom <<= 1;
+            self.bit_count -= 1;
+
+            if self.bit_count == 0 {
+                self.output.push((self.bottom >> 24) as u8);
+                self.bottom &= (1 << 24) - 1;
+                self.bit_count = 8;
+            }
+        }
+    }
+
+    /// Writes a "literal", that is, a `nbits`-wide unsigned value whose bits go high- to
+    /// low-order, with each bit encoded at probability 1/2.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if `nbits > 31`.
+    fn write_literal(&mut self, value: u32, nbits: usize) {
+        assert!(nbits <= 31);
+
+        for i in (0..nbits).rev() {
+            self.write_bit((value >> i) & 1 != 0, DEFAULT_PROBABILITY);
+        }
+    }
+
+    /// Writes a boolean with an even probability.
+    pub fn write_bool(&mut self, value: bool) {
+        self.write_bit(value, DEFAULT_PROBABILITY)
+    }
+
+    /// Writes a boolean into the coded stream. The probability of `value` to be false is
+    /// probability / 256, e.g., when probability is 0x80, the chance is 1/2 (i.e., 0x80 / 256).
+    pub fn write_bool_with_prob(&mut self, value: bool, probability: u8) {
+        self.write_bit(value, probability)
+    }
+
+    /// Writes `value` as a `nbits`-wide unsigned literal.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if `nbits > 31`.
+    pub fn write_uint<U: Into<u32>>(&mut self, value: U, nbits: usize) -> BoolEncoderResult<()> {
+        let value = value.into();
+
+        if u64::from(value) >= 1u64 << nbits {
+            return Err(BoolEncoderError::ValueOutOfRange(value.into(), nbits));
+        }
+
+        self.write_literal(value, nbits);
+        Ok(())
+    }
+
+    /// Writes `value` as a `nbits`-wide magnitude, followed by its sign bit.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if `nbits > 31`.
+    pub fn write_sint<U: Into<i32>>(&mut self, value: U, nbits: usize) -> BoolEncoderResult<()> {
+        let value = value.into();
+        let magnitude = value.unsigned_abs();
+
+        if u64::from(magnitude) >= 1u64 << nbits {
+            return Err(BoolEncoderError::ValueOutOfRange(value.into(), nbits));
+        }
+
+        self.write_literal(magnitude, nbits);
+        self.write_bool(value < 0);
+        Ok(())
+    }
+
+    /// Flushes the pending bits and returns the coded stream.
+    pub fn finish(mut self) -> Vec<u8> {
+        let mut c = self.bit_count;
+        let mut v = self.bottom;
+
+        if v & (1 << (32 - c)) != 0 {
+            self.add_one_to_output();
+        }
+
+        v <<= c & 7;
+        c >>= 3;
+        while c > 0 {
+            v <<= 8;
+            c -= 1;
+        }
+
+        for _ in 0..4 {
+            self.output.push((v >> 24) as u8);
+            v <<= 8;
+        }
+
+        self.output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::vp8::bool_decoder::BoolDecoder;
+
+    #[test]
+    fn encode_bools_with_parities_and_increasing_probabilities() {
+        // Same stream as the one the decoder is tested against.
+        const EXPECTED: [u8; 21] = [
+            0x00, 0x02, 0x08, 0x31, 0x8e, 0xca, 0xab, 0xe2, 0xc8, 0x31, 0x12, 0xb3, 0x2c, 0x19,
+            0x90, 0xc6, 0x6a, 0xeb, 0x17, 0x52, 0x30,
+        ];
+
+        let mut be = BoolEncoder::new();
+        for i in 0..100 {
+            be.write_bool_with_prob(i % 2 == 1, i as u8);
+        }
+
+        let data = be.finish();
+        assert_eq!(&data[..EXPECTED.len()], &EXPECTED);
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut be = BoolEncoder::new();
+
+        // Long runs of likely values, which make the encoder propagate carries.
+        for i in 0..2000u32 {
+            be.write_bool_with_prob(i % 97 != 0, (i % 255 + 1) as u8);
+        }
+        be.write_uint(0x5au8, 7).unwrap();
+        be.write_uint(0x7fffffffu32, 31).unwrap();
+        be.write_sint(-9i8, 4).unwrap();
+        be.write_sint(63i8, 6).unwrap();
+        be.write_bool(true);
+
+        let data = be.finish();
+        let mut bd = BoolDecoder::new(&data[..]);
+
+        for i in 0..2000u32 {
+            assert_eq!(bd.read_bool_with_prob((i % 255 + 1) as u8), Ok(i % 97 != 0));
+        }
+        assert_eq!(bd.read_uint::<u8>(7), Ok(0x5a));
+        assert_eq!(bd.read_uint::<u32>(31), Ok(0x7fffffff));
+        assert_eq!(bd.read_sint::<i8>(4), Ok(-9));
+        assert_eq!(bd.read_sint::<i8>(6), Ok(63));
+        assert_eq!(bd.read_bool(), Ok(true));
+    }
+
+    #[test]
+    fn value_out_of_range() {
+        let mut be = BoolEncoder::new();
+
+        assert_eq!(
+            be.write_uint(128u8, 7),
+            Err(BoolEncoderError::ValueOutOfRange(128, 7))
+        );
+        assert_eq!(
+            be.write_sint(-16i8, 4),
+            Err(BoolEncoderError::ValueOutOfRange(-16, 4))
+        );
+    }
+}
@@ -0,0 +1,608 @@
+// Copyright 2023 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+//! Serialization of VP8 frame headers.
+//!
+//! This is the counterpart of the [parser](crate::codec::vp8::parser): [`Synthesizer`] writes a
+//! [`Header`] as the uncompressed data chunk of a frame, followed by the first partition starting
+//! with the boolean-coded frame header, and the DCT partitions.
+
+use std::io::Write;
+
+use anyhow::anyhow;
+
+use crate::codec::vp8::bool_encoder::BoolEncoder;
+use crate::codec::vp8::parser::Header;
+use crate::codec::vp8::parser::MbLfAdjustments;
+use crate::codec::vp8::parser::Parser;
+use crate::codec::vp8::parser::QuantIndices;
+use crate::codec::vp8::parser::Segmentation;
+use crate::codec::vp8::probs::COEFF_UPDATE_PROBS;
+use crate::codec::vp8::probs::MV_UPDATE_PROBS;
+
+/// Writes a VP8 frame.
+///
+/// The frame header is coded first into the first partition, after which the per-macroblock data
+/// can be appended using [`Synthesizer::bool_encoder`]. [`Synthesizer::finish`] then lays out the
+/// whole frame.
+pub struct Synthesizer<'a> {
+    header: &'a Header,
+    encoder: BoolEncoder,
+}
+
+impl<'a> Synthesizer<'a> {
+    /// Codes the frame header of `header` (section 9.2 to 9.11 of RFC 6386).
+    ///
+    /// `segmentation` and `mb_lf_adjust` are the values in effect for the frame, and are written
+    /// according to their update flags. `previous` is a parser that has processed all the frames
+    /// preceding this one: the loop filter deltas and the probabilities are coded as updates of
+    /// the values it holds. It is not used for key frames, which reset that state.
+    pub fn new(
+        header: &'a Header,
+        segmentation: &Segmentation,
+        mb_lf_adjust: &MbLfAdjustments,
+        previous: &Parser,
+    ) -> anyhow::Result<Self> {
+        let default_parser;
+        let previous = if header.key_frame {
+            default_parser = Parser::default();
+            &default_parser
+        } else {
+            previous
+        };
+
+        let mut s = Self {
+            header,
+            encoder: BoolEncoder::new(),
+        };
+
+        s.frame_header(segmentation, mb_lf_adjust, previous)?;
+
+        Ok(s)
+    }
+
+    /// Returns the boolean encoder of the first partition, for the per-macroblock data that
+    /// follows the frame header.
+    pub fn bool_encoder(&mut self) -> &mut BoolEncoder {
+        &mut self.encoder
+    }
+
+    /// Writes the frame into `writer`: the uncompressed data chunk, the first partition, the
+    /// partition sizes and `dct_partitions`, of which there must be as many as signaled by the
+    /// header.
+    ///
+    /// Returns the size of the frame in bytes.
+    pub fn finish<W: Write>(
+        self,
+        dct_partitions: &[&[u8]],
+        mut writer: W,
+    ) -> anyhow::Result<usize> {
+        let header = self.header;
+
+        if dct_partitions.len() != header.num_dct_partitions() {
+            return Err(anyhow!(
+                "Header signals {} DCT partitions, got {}",
+                header.num_dct_partitions(),
+                dct_partitions.len()
+            ));
+        }
+
+        let first_partition = self.encoder.finish();
+        let first_part_size = u32::try_from(first_partition.len())?;
+        if first_part_size >= 1 << 19 {
+            return Err(anyhow!("First partition too large: {}", first_part_size));
+        }
+
+        if header.version > 7 {
+            return Err(anyhow!("Invalid version {}", header.version));
+        }
+
+        let frame_tag = u32::from(!header.key_frame)
+            | u32::from(header.version) << 1
+            | u32::from(header.show_frame) << 4
+            | first_part_size << 5;
+        writer.write_all(&frame_tag.to_le_bytes()[..3])?;
+        let mut size = 3;
+
+        if header.key_frame {
+            if header.width >= 1 << 14 || header.height >= 1 << 14 {
+                return Err(anyhow!(
+                    "Invalid frame size {}x{}",
+                    header.width,
+                    header.height
+                ));
+            }
+
+            if header.horiz_scale_code > 3 || header.vert_scale_code > 3 {
+                return Err(anyhow!(
+                    "Invalid scale codes {}, {}",
+                    header.horiz_scale_code,
+                    header.vert_scale_code
+                ));
+            }
+
+            let horiz_size_code = header.width | u16::from(header.horiz_scale_code) << 14;
+            let vert_size_code = header.height | u16::from(header.vert_scale_code) << 14;
+
+            writer.write_all(&[0x9d, 0x01, 0x2a])?;
+            writer.write_all(&horiz_size_code.to_le_bytes())?;
+            writer.write_all(&vert_size_code.to_le_bytes())?;
+            size += 7;
+        }
+
+        writer.write_all(&first_partition)?;
+        size += first_partition.len();
+
+        // The size of the last partition is implied by the size of the frame.
+        for partition in &dct_partitions[..dct_partitions.len() - 1] {
+            let partition_size = u32::try_from(partition.len())?;
+            if partition_size >= 1 << 24 {
+                return Err(anyhow!("DCT partition too large: {}", partition_size));
+            }
+
+            writer.write_all(&partition_size.to_le_bytes()[..3])?;
+            size += 3;
+        }
+
+        for partition in dct_partitions {
+            writer.write_all(partition)?;
+            size += partition.len();
+        }
+
+        Ok(size)
+    }
+
+    fn bool(&mut self, value: bool) {
+        self.encoder.write_bool(value)
+    }
+
+    fn uint<U: Into<u32>>(&mut self, value: U, nbits: usize) -> anyhow::Result<()> {
+        Ok(self.encoder.write_uint(value, nbits)?)
+    }
+
+    fn sint<U: Into<i32>>(&mut self, value: U, nbits: usize) -> anyhow::Result<()> {
+        Ok(self.encoder.write_sint(value, nbits)?)
+    }
+
+    /// Writes a flag telling whether `value` is present, followed by `value` if it is non-zero.
+    fn optional_sint<U: Into<i32>>(&mut self, value: U, nbits: usize) -> anyhow::Result<()> {
+        let value = value.into();
+
+        self.bool(value != 0);
+        if value != 0 {
+            self.sint(value, nbits)?;
+        }
+
+        Ok(())
+    }
+
+    fn update_segmentation(&mut self, seg: &Segmentation) -> anyhow::Result<()> {
+        self.bool(seg.segmentation_enabled);
+        if !seg.segmentation_enabled {
+            return Ok(());
+        }
+
+        self.bool(seg.update_mb_segmentation_map);
+        self.bool(seg.update_segment_feature_data);
+
+        if seg.update_segment_feature_data {
+            self.bool(seg.segment_feature_mode);
+
+            // Values that are not updated are reset to zero.
+            for &value in &seg.quantizer_update_value {
+                self.optional_sint(value, 7)?;
+            }
+
+            for &value in &seg.lf_update_value {
+                self.optional_sint(value, 6)?;
+            }
+        }
+
+        if seg.update_mb_segmentation_map {
+            // Probabilities that are not updated are reset to 255.
+            for &prob in &seg.segment_prob {
+                self.bool(prob != 255);
+                if prob != 255 {
+                    self.uint(prob, 8)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn mb_lf_adjustments(
+        &mut self,
+        adj: &MbLfAdjustments,
+        previous: &MbLfAdjustments,
+    ) -> anyhow::Result<()> {
+        self.bool(adj.loop_filter_adj_enable);
+        if !adj.loop_filter_adj_enable {
+            return Ok(());
+        }
+
+        self.bool(adj.mode_ref_lf_delta_update);
+        if !adj.mode_ref_lf_delta_update {
+            return Ok(());
+        }
+
+        // Deltas that are not updated keep their previous value.
+        let deltas = adj.ref_frame_delta.iter().chain(&adj.mb_mode_delta);
+        let previous_deltas = previous
+            .ref_frame_delta
+            .iter()
+            .chain(&previous.mb_mode_delta);
+
+        for (&delta, &previous_delta) in deltas.zip(previous_deltas) {
+            self.bool(delta != previous_delta);
+            if delta != previous_delta {
+                self.sint(delta, 6)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn quant_indices(&mut self, q: &QuantIndices) -> anyhow::Result<()> {
+        self.uint(q.y_ac_qi, 7)?;
+        self.optional_sint(q.y_dc_delta, 4)?;
+        self.optional_sint(q.y2_dc_delta, 4)?;
+        self.optional_sint(q.y2_ac_delta, 4)?;
+        self.optional_sint(q.uv_dc_delta, 4)?;
+        self.optional_sint(q.uv_ac_delta, 4)
+    }
+
+    fn token_prob_update(&mut self, previous: &[[[[u8; 11]; 3]; 8]; 4]) -> anyhow::Result<()> {
+        let probs = self.header.coeff_prob.iter().flatten().flatten().flatten();
+        let previous = previous.iter().flatten().flatten().flatten();
+        let update_probs = COEFF_UPDATE_PROBS.iter().flatten().flatten().flatten();
+
+        for ((&prob, &previous), &update_prob) in probs.zip(previous).zip(update_probs) {
+            self.encoder
+                .write_bool_with_prob(prob != previous, update_prob);
+            if prob != previous {
+                self.uint(prob, 8)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn mv_prob_update(&mut self, previous: &[[u8; 19]; 2]) -> anyhow::Result<()> {
+        let probs = self.header.mv_prob.iter().flatten();
+        let previous = previous.iter().flatten();
+        let update_probs = MV_UPDATE_PROBS.iter().flatten();
+
+        for ((&prob, &previous), &update_prob) in probs.zip(previous).zip(update_probs) {
+            self.encoder
+                .write_bool_with_prob(prob != previous, update_prob);
+            if prob != previous {
+                // Updated probabilities are coded on 7 bits, with 0 standing for 1.
+                let value = match prob {
+                    1 => 0,
+                    _ if prob % 2 == 0 => prob >> 1,
+                    _ => return Err(anyhow!("MV probability {} cannot be coded", prob)),
+                };
+
+                self.uint(value, 7)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn frame_header(
+        &mut self,
+        segmentation: &Segmentation,
+        mb_lf_adjust: &MbLfAdjustments,
+        previous: &Parser,
+    ) -> anyhow::Result<()> {
+        let header = self.header;
+
+        if header.key_frame {
+            self.bool(header.color_space);
+            self.bool(header.clamping_type);
+        }
+
+        self.update_segmentation(segmentation)?;
+
+        self.bool(header.filter_type);
+        self.uint(header.loop_filter_level, 6)?;
+        self.uint(header.sharpness_level, 3)?;
+
+        self.mb_lf_adjustments(mb_lf_adjust, previous.mb_lf_adjust())?;
+
+        self.uint(header.log2_nbr_of_dct_partitions, 2)?;
+
+        self.quant_indices(&header.quant_indices)?;
+
+        if header.key_frame {
+            self.bool(header.refresh_entropy_probs);
+        } else {
+            self.bool(header.refresh_golden_frame);
+            self.bool(header.refresh_alternate_frame);
+
+            if !header.refresh_golden_frame {
+                self.uint(header.copy_buffer_to_golden, 2)?;
+            }
+
+            if !header.refresh_alternate_frame {
+                self.uint(header.copy_buffer_to_alternate, 2)?;
+            }
+
+            self.bool(header.sign_bias_golden);
+            self.bool(header.sign_bias_alternate);
+            self.bool(header.refresh_entropy_probs);
+            self.bool(header.refresh_last);
+        }
+
+        self.token_prob_update(previous.coeff_prob())?;
+
+        self.bool(header.mb_no_coeff_skip);
+        if header.mb_no_coeff_skip {
+            self.uint(header.prob_skip_false, 8)?;
+        }
+
+        if !header.key_frame {
+            self.uint(header.prob_intra, 8)?;
+            self.uint(header.prob_last, 8)?;
+            self.uint(header.prob_golden, 8)?;
+
+            let mode_probs = &header.mode_probs;
+            let previous_mode_probs = previous.mode_probs();
+
+            let intra_16x16_prob_update_flag =
+                mode_probs.intra_16x16_prob != previous_mode_probs.intra_16x16_prob;
+            self.bool(intra_16x16_prob_update_flag);
+            if intra_16x16_prob_update_flag {
+                for &prob in &mode_probs.intra_16x16_prob {
+                    self.uint(prob, 8)?;
+                }
+            }
+
+            let intra_chroma_prob_update_flag =
+                mode_probs.intra_chroma_prob != previous_mode_probs.intra_chroma_prob;
+            self.bool(intra_chroma_prob_update_flag);
+            if intra_chroma_prob_update_flag {
+                for &prob in &mode_probs.intra_chroma_prob {
+                    self.uint(prob, 8)?;
+                }
+            }
+
+            self.mv_prob_update(previous.mv_prob())?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::codec::vp8::parser::Header;
+    use crate::codec::vp8::parser::MbLfAdjustments;
+    use crate::codec::vp8::parser::ModeProbs;
+    use crate::codec::vp8::parser::Parser;
+    use crate::codec::vp8::parser::QuantIndices;
+    use crate::codec::vp8::parser::Segmentation;
+    use crate::codec::vp8::probs::KF_UV_MODE_PROBS;
+    use crate::codec::vp8::probs::KF_Y_MODE_PROBS;
+    use crate::codec::vp8::synthesizer::Synthesizer;
+
+    const VP8_TEST_0_INTRA: &[u8] = include_bytes!("test_data/vp8-parser-test-0-intra.bin");
+    const VP8_TEST_0_INTER: &[u8] = include_bytes!("test_data/vp8-parser-test-0-inter.bin");
+
+    /// Writes a frame with `header` and `partitions`, using the state of `parser` as reference,
+    /// and parses it back with `parser`.
+    ///
+    /// Checks that the frame is parsed identically, apart from the sizes and boolean decoder
+    /// state that depend on the content of the first partition.
+    fn round_trip(
+        header: &Header,
+        segmentation: &Segmentation,
+        mb_lf_adjust: &MbLfAdjustments,
+        partitions: &[&[u8]],
+        parser: &mut Parser,
+    ) {
+        let mut buf = vec![];
+        let size = Synthesizer::new(header, segmentation, mb_lf_adjust, parser)
+            .unwrap()
+            .finish(partitions, &mut buf)
+            .unwrap();
+        assert_eq!(size, buf.len());
+
+        let frame = parser.parse_frame(&buf).unwrap();
+        assert_eq!(frame.as_ref(), &buf[..]);
+
+        let parsed = &frame.header;
+        for (i, partition) in partitions.iter().enumerate() {
+            assert_eq!(parsed.partition_size[i] as usize, partition.len());
+        }
+
+        let expected = Header {
+            data_chunk_size: parsed.data_chunk_size,
+            first_part_size: parsed.first_part_size,
+            partition_size: parsed.partition_size,
+            bd_range: parsed.bd_range,
+            bd_value: parsed.bd_value,
+            bd_count: parsed.bd_count,
+            header_size: parsed.header_size,
+            ..header.clone()
+        };
+        assert_eq!(parsed, &expected);
+        assert_eq!(parser.segmentation(), segmentation);
+        assert_eq!(parser.mb_lf_adjust(), mb_lf_adjust);
+    }
+
+    #[test]
+    fn round_trip_gst() {
+        for frame in [VP8_TEST_0_INTRA, VP8_TEST_0_INTER] {
+            let mut parser = Parser::default();
+            let previous = parser.clone();
+            let header = parser.parse_frame(frame).unwrap().header;
+            let segmentation = parser.segmentation().clone();
+            let mb_lf_adjust = parser.mb_lf_adjust().clone();
+
+            let mut synth_parser = previous;
+            round_trip(
+                &header,
+                &segmentation,
+                &mb_lf_adjust,
+                &[&[0x12, 0x34]],
+                &mut synth_parser,
+            );
+        }
+    }
+
+    #[test]
+    fn round_trip_sequence() {
+        let mut parser = Parser::default();
+
+        let segmentation = Segmentation {
+            segmentation_enabled: true,
+            update_mb_segmentation_map: true,
+            update_segment_feature_data: true,
+            segment_feature_mode: true,
+            quantizer_update_value: [0, 12, -127, 5],
+            lf_update_value: [-63, 0, 7, 1],
+            segment_prob: [255, 3, 128],
+        };
+        let mb_lf_adjust = MbLfAdjustments {
+            loop_filter_adj_enable: true,
+            mode_ref_lf_delta_update: true,
+            ref_frame_delta: [2, 0, -2, -2],
+            mb_mode_delta: [4, -2, 2, 4],
+        };
+
+        let mut header = Header {
+            key_frame: true,
+            version: 1,
+            show_frame: true,
+            width: 1920,
+            height: 1080,
+            horiz_scale_code: 1,
+            vert_scale_code: 2,
+            color_space: true,
+            clamping_type: true,
+            filter_type: true,
+            loop_filter_level: 40,
+            sharpness_level: 5,
+            log2_nbr_of_dct_partitions: 2,
+            quant_indices: QuantIndices {
+                y_ac_qi: 100,
+                y_dc_delta: -15,
+                y2_dc_delta: 0,
+                y2_ac_delta: 15,
+                uv_dc_delta: 3,
+                uv_ac_delta: -1,
+            },
+            refresh_entropy_probs: true,
+            refresh_last: true,
+            refresh_golden_frame: true,
+            refresh_alternate_frame: true,
+            coeff_prob: *parser.coeff_prob(),
+            mv_prob: *parser.mv_prob(),
+            mb_no_coeff_skip: true,
+            prob_skip_false: 200,
+            mode_probs: ModeProbs {
+                intra_16x16_prob: KF_Y_MODE_PROBS,
+                intra_chroma_prob: KF_UV_MODE_PROBS,
+            },
+            ..Default::default()
+        };
+        header.coeff_prob[0][1][2][3] = 1;
+        header.coeff_prob[3][7][2][10] = 255;
+        header.coeff_prob[1][0][0][0] = 0;
+
+        let partitions: [&[u8]; 4] = [&[1, 2, 3], &[], &[4; 300], &[5]];
+        round_trip(
+            &header,
+            &segmentation,
+            &mb_lf_adjust,
+            &partitions,
+            &mut parser,
+        );
+
+        // An inter frame updating the probabilities and some of the loop filter deltas, and
+        // keeping the segmentation.
+        let segmentation = Segmentation {
+            update_mb_segmentation_map: false,
+            update_segment_feature_data: false,
+            ..segmentation
+        };
+        let mb_lf_adjust = MbLfAdjustments {
+            ref_frame_delta: [2, 1, -2, -2],
+            mb_mode_delta: [4, -2, -63, 4],
+            ..mb_lf_adjust
+        };
+
+        // The size, scaling and colour information are only present in key frames.
+        header = Header {
+            key_frame: false,
+            show_frame: false,
+            width: 0,
+            height: 0,
+            horiz_scale_code: 0,
+            vert_scale_code: 0,
+            color_space: false,
+            clamping_type: false,
+            ..header
+        };
+        header.log2_nbr_of_dct_partitions = 0;
+        header.refresh_entropy_probs = false;
+        header.refresh_last = false;
+        header.refresh_golden_frame = false;
+        header.refresh_alternate_frame = false;
+        header.copy_buffer_to_golden = 2;
+        header.copy_buffer_to_alternate = 1;
+        header.sign_bias_golden = true;
+        header.sign_bias_alternate = true;
+        header.coeff_prob[2][2][2][2] = 42;
+        header.mv_prob[0][0] = 1;
+        header.mv_prob[1][18] = 254;
+        header.mb_no_coeff_skip = false;
+        header.prob_skip_false = 0;
+        header.prob_intra = 10;
+        header.prob_last = 20;
+        header.prob_golden = 30;
+        header.mode_probs = parser.mode_probs().clone();
+        header.mode_probs.intra_chroma_prob = [1, 2, 3];
+        round_trip(&header, &segmentation, &mb_lf_adjust, &[&[]], &mut parser);
+
+        // The previous frame did not refresh the probabilities, so they are coded relative to
+        // those of the key frame again.
+        header.coeff_prob = *parser.coeff_prob();
+        header.coeff_prob[0][0][0][0] = 9;
+        header.mode_probs = parser.mode_probs().clone();
+        header.refresh_golden_frame = true;
+        header.copy_buffer_to_golden = 0;
+        round_trip(&header, &segmentation, &mb_lf_adjust, &[&[7]], &mut parser);
+    }
+
+    #[test]
+    fn invalid_input() {
+        let header = Header {
+            key_frame: true,
+            log2_nbr_of_dct_partitions: 1,
+            ..Default::default()
+        };
+        let parser = Parser::default();
+        let new = || {
+            Synthesizer::new(&header, &Default::default(), &Default::default(), &parser).unwrap()
+        };
+
+        // Two partitions are expected.
+        assert!(new().finish(&[&[]], vec![]).is_err());
+        assert!(new().finish(&[&[], &[]], vec![]).is_ok());
+
+        // Odd MV probabilities cannot be coded.
+        let mut header = Header {
+            mv_prob: *parser.mv_prob(),
+            ..Default::default()
+        };
+        header.mv_prob[0][3] = 3;
+        assert!(
+            Synthesizer::new(&header, &Default::default(), &Default::default(), &parser).is_err()
+        );
+    }
+}
@@ -4,3 +4,4 @@
 
 pub mod lookups;
 pub mod parser;
+pub mod synthesizer;
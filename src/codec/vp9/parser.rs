@@ -524,6 +524,13 @@ pub struct Parser {
 }
 
 impl Parser {
+    /// Returns the width and height of the frame held in reference slot `idx`, as of the last
+    /// parsed frame.
+    pub(crate) fn reference_frame_size(&self, idx: usize) -> (u32, u32) {
+        let size = &self.reference_frame_sz[idx];
+        (size.width, size.height)
+    }
+
     fn parse_superframe_hdr(resource: impl AsRef<[u8]>) -> anyhow::Result<SuperframeHeader> {
         let bitstream = resource.as_ref();
 
@@ -896,10 +903,13 @@ impl Parser {
             seg.abs_or_delta_update = r.read_bool()?;
             for i in 0..MAX_SEGMENTS {
                 for j in 0..SEG_LVL_MAX {
+                    let mut feature_value = 0;
+
                     seg.feature_enabled[i][j] = r.read_bool()?;
                     if seg.feature_enabled[i][j] {
+                        // The magnitude is unsigned, the sign being coded separately.
                         let bits_to_read = SEGMENTATION_FEATURE_BITS[j];
-                        let mut feature_value = r.read_i16(bits_to_read)?;
+                        feature_value = r.read_u16(bits_to_read)? as i16;
 
                         if SEGMENTATION_FEATURE_SIGNED[j] {
                             let feature_sign = r.read_bool()?;
@@ -908,9 +918,9 @@ impl Parser {
                                 feature_value = -feature_value;
                             }
                         }
-
-                        seg.feature_data[i][j] = feature_value;
                     }
+
+                    seg.feature_data[i][j] = feature_value;
                 }
             }
         }
@@ -918,7 +928,7 @@ impl Parser {
         Ok(())
     }
 
-    fn calc_min_log2_tile_cols(sb64_cols: u32) -> u8 {
+    pub(crate) fn calc_min_log2_tile_cols(sb64_cols: u32) -> u8 {
         let mut min_log2 = 0;
 
         while (MAX_TILE_WIDTH_B64 << min_log2) < sb64_cols {
@@ -928,7 +938,7 @@ impl Parser {
         min_log2
     }
 
-    fn calc_max_log2_tile_cols(sb64_cols: u32) -> u8 {
+    pub(crate) fn calc_max_log2_tile_cols(sb64_cols: u32) -> u8 {
         let mut max_log2 = 1;
 
         while (sb64_cols >> max_log2) >= MIN_TILE_WIDTH_B64 {
@@ -0,0 +1,807 @@
+// Copyright 2023 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+//! Serialization of VP9 frame headers and superframes.
+//!
+//! This is the counterpart of the [parser](crate::codec::vp9::parser): [`Synthesizer`] writes a
+//! [`Header`] as the uncompressed header of a frame (section 6.2 of the VP9 specification), and
+//! [`pack_superframe`] gathers several frames into a single chunk (Annex B).
+
+use std::io::Write;
+
+use anyhow::anyhow;
+
+use crate::codec::h264::nalu_writer::NaluWriter;
+use crate::codec::vp9::parser::BitDepth;
+use crate::codec::vp9::parser::ColorSpace;
+use crate::codec::vp9::parser::FrameType;
+use crate::codec::vp9::parser::Header;
+use crate::codec::vp9::parser::InterpolationFilter;
+use crate::codec::vp9::parser::LoopFilterParams;
+use crate::codec::vp9::parser::Parser;
+use crate::codec::vp9::parser::Profile;
+use crate::codec::vp9::parser::QuantizationParams;
+use crate::codec::vp9::parser::SegmentationParams;
+use crate::codec::vp9::parser::FRAME_MARKER;
+use crate::codec::vp9::parser::MAX_FRAMES_IN_SUPERFRAME;
+use crate::codec::vp9::parser::MAX_SEGMENTS;
+use crate::codec::vp9::parser::REFS_PER_FRAME;
+use crate::codec::vp9::parser::SEG_LVL_MAX;
+use crate::codec::vp9::parser::SUPERFRAME_MARKER;
+use crate::codec::vp9::parser::SYNC_CODE;
+
+/// Number of bits of the magnitude of each segmentation feature.
+const SEGMENTATION_FEATURE_BITS: [usize; SEG_LVL_MAX] = [8, 6, 2, 0];
+/// Whether each segmentation feature is followed by a sign bit.
+const SEGMENTATION_FEATURE_SIGNED: [bool; SEG_LVL_MAX] = [true, true, false, false];
+
+/// Writes the uncompressed header of a VP9 frame.
+///
+/// The compressed header and the tile data are not covered and must be appended by the caller,
+/// the size of the former being given by `header_size_in_bytes`.
+pub struct Synthesizer<'a, W: Write> {
+    writer: NaluWriter<W>,
+    header: &'a Header,
+}
+
+impl<'a, W: Write> Synthesizer<'a, W> {
+    /// Writes the uncompressed header of `header` into `writer`, including its trailing bits.
+    ///
+    /// `previous` is a parser that has processed all the frames preceding this one. The frame
+    /// size of inter frames is coded by reference to the first of their reference frames that
+    /// has the same size, like libvpx does.
+    ///
+    /// Returns the size of the uncompressed header in bytes.
+    pub fn synthesize(header: &'a Header, previous: &Parser, writer: W) -> anyhow::Result<usize> {
+        let mut s = Self {
+            writer: NaluWriter::new(writer, false),
+            header,
+        };
+
+        s.uncompressed_header(previous)?;
+
+        while !s.writer.is_aligned() {
+            s.bit(false)?;
+        }
+
+        let size = s.writer.num_bits_written() / 8;
+        s.writer.into_inner()?;
+
+        Ok(size)
+    }
+
+    fn bits<U: Into<u64>>(&mut self, value: U, num_bits: usize) -> anyhow::Result<()> {
+        self.writer.write_bits(value, num_bits)
+    }
+
+    fn bit(&mut self, bit: bool) -> anyhow::Result<()> {
+        self.writer.write_bit(bit)
+    }
+
+    /// Write `value` as a `num_bits` magnitude followed by a sign bit.
+    fn signed<U: Into<i32>>(&mut self, value: U, num_bits: usize) -> anyhow::Result<()> {
+        let value = value.into();
+
+        self.bits(value.unsigned_abs(), num_bits)?;
+        self.bit(value < 0)
+    }
+
+    fn frame_sync_code(&mut self) -> anyhow::Result<()> {
+        self.bits(SYNC_CODE, 24)
+    }
+
+    fn color_config(&mut self) -> anyhow::Result<()> {
+        let hdr = self.header;
+
+        match (hdr.profile, hdr.bit_depth) {
+            (Profile::Profile0 | Profile::Profile1, BitDepth::Depth8) => (),
+            (Profile::Profile2 | Profile::Profile3, BitDepth::Depth10) => self.bit(false)?,
+            (Profile::Profile2 | Profile::Profile3, BitDepth::Depth12) => self.bit(true)?,
+            (profile, bit_depth) => {
+                return Err(anyhow!(
+                    "Bit depth {:?} is not supported by {:?}",
+                    bit_depth,
+                    profile
+                ))
+            }
+        }
+
+        self.bits(hdr.color_space as u32, 3)?;
+
+        let has_subsampling = matches!(hdr.profile, Profile::Profile1 | Profile::Profile3);
+
+        if !matches!(hdr.color_space, ColorSpace::CsSrgb) {
+            self.bits(hdr.color_range as u32, 1)?;
+
+            if has_subsampling {
+                self.bit(hdr.subsampling_x)?;
+                self.bit(hdr.subsampling_y)?;
+                // reserved_zero
+                self.bit(false)?;
+            }
+        } else if has_subsampling {
+            // reserved_zero
+            self.bit(false)?;
+        }
+
+        Ok(())
+    }
+
+    fn frame_size(&mut self) -> anyhow::Result<()> {
+        let hdr = self.header;
+
+        if !(1..=1 << 16).contains(&hdr.width) || !(1..=1 << 16).contains(&hdr.height) {
+            return Err(anyhow!("Invalid frame size {}x{}", hdr.width, hdr.height));
+        }
+
+        self.bits(hdr.width - 1, 16)?;
+        self.bits(hdr.height - 1, 16)
+    }
+
+    fn render_size(&mut self) -> anyhow::Result<()> {
+        let hdr = self.header;
+
+        self.bit(hdr.render_and_frame_size_different)?;
+        if hdr.render_and_frame_size_different {
+            if !(1..=1 << 16).contains(&hdr.render_width)
+                || !(1..=1 << 16).contains(&hdr.render_height)
+            {
+                return Err(anyhow!(
+                    "Invalid render size {}x{}",
+                    hdr.render_width,
+                    hdr.render_height
+                ));
+            }
+
+            self.bits(hdr.render_width - 1, 16)?;
+            self.bits(hdr.render_height - 1, 16)?;
+        }
+
+        Ok(())
+    }
+
+    fn frame_size_with_refs(&mut self, previous: &Parser) -> anyhow::Result<()> {
+        let hdr = self.header;
+        let mut found_ref = false;
+
+        for &idx in &hdr.ref_frame_idx {
+            found_ref = previous.reference_frame_size(usize::from(idx)) == (hdr.width, hdr.height);
+            self.bit(found_ref)?;
+
+            if found_ref {
+                break;
+            }
+        }
+
+        if !found_ref {
+            self.frame_size()?;
+        }
+
+        self.render_size()
+    }
+
+    fn interpolation_filter(&mut self) -> anyhow::Result<()> {
+        let literal = match self.header.interpolation_filter {
+            InterpolationFilter::Switchable => return self.bit(true),
+            InterpolationFilter::EightTapSmooth => 0u8,
+            InterpolationFilter::EightTap => 1,
+            InterpolationFilter::EightTapSharp => 2,
+            InterpolationFilter::Bilinear => 3,
+        };
+
+        self.bit(false)?;
+        self.bits(literal, 2)
+    }
+
+    fn loop_filter_params(&mut self, lf: &LoopFilterParams) -> anyhow::Result<()> {
+        self.bits(lf.level, 6)?;
+        self.bits(lf.sharpness, 3)?;
+
+        self.bit(lf.delta_enabled)?;
+        if !lf.delta_enabled {
+            return Ok(());
+        }
+
+        self.bit(lf.delta_update)?;
+        if !lf.delta_update {
+            return Ok(());
+        }
+
+        for (&update, &delta) in lf.update_ref_delta.iter().zip(&lf.ref_deltas) {
+            self.bit(update)?;
+            if update {
+                self.signed(delta, 6)?;
+            }
+        }
+
+        for (&update, &delta) in lf.update_mode_delta.iter().zip(&lf.mode_deltas) {
+            self.bit(update)?;
+            if update {
+                self.signed(delta, 6)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn delta_q(&mut self, delta: i8) -> anyhow::Result<()> {
+        self.bit(delta != 0)?;
+        if delta != 0 {
+            self.signed(delta, 4)?;
+        }
+
+        Ok(())
+    }
+
+    fn quantization_params(&mut self, quant: &QuantizationParams) -> anyhow::Result<()> {
+        self.bits(quant.base_q_idx, 8)?;
+        self.delta_q(quant.delta_q_y_dc)?;
+        self.delta_q(quant.delta_q_uv_dc)?;
+        self.delta_q(quant.delta_q_uv_ac)
+    }
+
+    /// Write a probability, which is only coded if it differs from 255.
+    fn prob(&mut self, prob: u8) -> anyhow::Result<()> {
+        self.bit(prob != 255)?;
+        if prob != 255 {
+            self.bits(prob, 8)?;
+        }
+
+        Ok(())
+    }
+
+    fn segmentation_params(&mut self, seg: &SegmentationParams) -> anyhow::Result<()> {
+        self.bit(seg.enabled)?;
+        if !seg.enabled {
+            return Ok(());
+        }
+
+        self.bit(seg.update_map)?;
+        if seg.update_map {
+            for &prob in &seg.tree_probs {
+                self.prob(prob)?;
+            }
+
+            self.bit(seg.temporal_update)?;
+            if seg.temporal_update {
+                for &prob in &seg.pred_probs {
+                    self.prob(prob)?;
+                }
+            }
+        }
+
+        self.bit(seg.update_data)?;
+        if !seg.update_data {
+            return Ok(());
+        }
+
+        self.bit(seg.abs_or_delta_update)?;
+        for i in 0..MAX_SEGMENTS {
+            for j in 0..SEG_LVL_MAX {
+                let enabled = seg.feature_enabled[i][j];
+                let value = seg.feature_data[i][j];
+
+                self.bit(enabled)?;
+                if !enabled {
+                    continue;
+                }
+
+                if value < 0 && !SEGMENTATION_FEATURE_SIGNED[j] {
+                    return Err(anyhow!(
+                        "Segment {} feature {} cannot be negative, got {}",
+                        i,
+                        j,
+                        value
+                    ));
+                }
+
+                self.bits(value.unsigned_abs(), SEGMENTATION_FEATURE_BITS[j])?;
+                if SEGMENTATION_FEATURE_SIGNED[j] {
+                    self.bit(value < 0)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn tile_info(&mut self) -> anyhow::Result<()> {
+        let hdr = self.header;
+        let sb64_cols = (((hdr.width + 7) >> 3) + 7) >> 3;
+        let min_log2_tile_cols = Parser::calc_min_log2_tile_cols(sb64_cols);
+        let max_log2_tile_cols = Parser::calc_max_log2_tile_cols(sb64_cols);
+
+        if !(min_log2_tile_cols..=max_log2_tile_cols).contains(&hdr.tile_cols_log2) {
+            return Err(anyhow!(
+                "tile_cols_log2 {} is out of range [{}, {}] for a width of {}",
+                hdr.tile_cols_log2,
+                min_log2_tile_cols,
+                max_log2_tile_cols,
+                hdr.width
+            ));
+        }
+
+        // increment_tile_cols_log2
+        for _ in min_log2_tile_cols..hdr.tile_cols_log2 {
+            self.bit(true)?;
+        }
+        if hdr.tile_cols_log2 < max_log2_tile_cols {
+            self.bit(false)?;
+        }
+
+        match hdr.tile_rows_log2 {
+            0 => self.bit(false),
+            1 => self.bits(0b10u8, 2),
+            2 => self.bits(0b11u8, 2),
+            n => Err(anyhow!("Invalid tile_rows_log2 {}", n)),
+        }
+    }
+
+    fn uncompressed_header(&mut self, previous: &Parser) -> anyhow::Result<()> {
+        let hdr = self.header;
+
+        self.bits(FRAME_MARKER, 2)?;
+
+        let profile = hdr.profile as u8;
+        self.bit(profile & 1 != 0)?;
+        self.bit(profile & 2 != 0)?;
+        if matches!(hdr.profile, Profile::Profile3) {
+            // reserved_zero
+            self.bit(false)?;
+        }
+
+        self.bit(hdr.show_existing_frame)?;
+        if hdr.show_existing_frame {
+            return self.bits(hdr.frame_to_show_map_idx, 3);
+        }
+
+        self.bits(hdr.frame_type as u8, 1)?;
+        self.bit(hdr.show_frame)?;
+        self.bit(hdr.error_resilient_mode)?;
+
+        if matches!(hdr.frame_type, FrameType::KeyFrame) {
+            self.frame_sync_code()?;
+            self.color_config()?;
+            self.frame_size()?;
+            self.render_size()?;
+        } else {
+            if !hdr.show_frame {
+                self.bit(hdr.intra_only)?;
+            } else if hdr.intra_only {
+                return Err(anyhow!("Intra-only frames cannot be shown directly"));
+            }
+
+            if !hdr.error_resilient_mode {
+                self.bits(hdr.reset_frame_context, 2)?;
+            }
+
+            if hdr.intra_only {
+                self.frame_sync_code()?;
+                if !matches!(hdr.profile, Profile::Profile0) {
+                    self.color_config()?;
+                }
+
+                self.bits(hdr.refresh_frame_flags, 8)?;
+                self.frame_size()?;
+                self.render_size()?;
+            } else {
+                self.bits(hdr.refresh_frame_flags, 8)?;
+
+                for i in 0..REFS_PER_FRAME {
+                    self.bits(hdr.ref_frame_idx[i], 3)?;
+                    self.bits(hdr.ref_frame_sign_bias[1 + i], 1)?;
+                }
+
+                self.frame_size_with_refs(previous)?;
+                self.bit(hdr.allow_high_precision_mv)?;
+                self.interpolation_filter()?;
+            }
+        }
+
+        if !hdr.error_resilient_mode {
+            self.bit(hdr.refresh_frame_context)?;
+            self.bit(hdr.frame_parallel_decoding_mode)?;
+        }
+
+        self.bits(hdr.frame_context_idx, 2)?;
+        self.loop_filter_params(&hdr.lf)?;
+        self.quantization_params(&hdr.quant)?;
+        self.segmentation_params(&hdr.seg)?;
+        self.tile_info()?;
+        self.bits(hdr.header_size_in_bytes, 16)
+    }
+}
+
+/// Packs `frames` into a superframe (Annex B), i.e. concatenates them and appends the index
+/// giving their sizes, so they can be sent to the decoder as a single chunk.
+///
+/// The frame sizes are coded with as few bytes as the largest frame allows.
+pub fn pack_superframe(frames: &[&[u8]]) -> anyhow::Result<Vec<u8>> {
+    if frames.is_empty() || frames.len() > MAX_FRAMES_IN_SUPERFRAME {
+        return Err(anyhow!(
+            "A superframe must contain between 1 and {} frames, got {}",
+            MAX_FRAMES_IN_SUPERFRAME,
+            frames.len()
+        ));
+    }
+
+    let max_size = frames.iter().map(|f| f.len()).max().unwrap_or(0);
+    let bytes_per_framesize = match max_size {
+        0..=0xff => 1,
+        0x100..=0xffff => 2,
+        0x10000..=0xffffff => 3,
+        0x1000000..=0xffffffff => 4,
+        _ => return Err(anyhow!("Frame too large for a superframe: {}", max_size)),
+    };
+
+    let marker = (SUPERFRAME_MARKER as u8) << 5
+        | ((bytes_per_framesize - 1) as u8) << 3
+        | (frames.len() - 1) as u8;
+
+    let mut data = frames.concat();
+    data.push(marker);
+    for frame in frames {
+        data.extend_from_slice(&(frame.len() as u32).to_le_bytes()[..bytes_per_framesize]);
+    }
+    data.push(marker);
+
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::vp9::parser::ColorRange;
+    use crate::utils::IvfIterator;
+
+    /// Rewrites the uncompressed header of each frame of `chunk`, checking that it is identical to
+    /// the original one, and returns the frames of `chunk` repacked into a new superframe along
+    /// with their headers.
+    fn rewrite_chunk(chunk: &[u8], parser: &mut Parser) -> (Vec<u8>, Vec<Header>) {
+        let frames = parser.clone().parse_chunk(chunk).unwrap();
+        let mut rewritten = vec![];
+        let mut headers = vec![];
+
+        for frame in &frames {
+            let data = frame.as_ref();
+            let hdr = &frame.header;
+
+            let mut buf = vec![];
+            let size = Synthesizer::synthesize(hdr, parser, &mut buf).unwrap();
+            assert_eq!(size, buf.len());
+            if !hdr.show_existing_frame {
+                assert_eq!(size, usize::from(hdr.uncompressed_header_size_in_bytes));
+            }
+            assert_eq!(buf, &data[..size]);
+
+            parser.parse_frame(data, 0, data.len()).unwrap();
+            buf.extend_from_slice(&data[size..]);
+            rewritten.push(buf);
+            headers.push(hdr.clone());
+        }
+
+        let rewritten = rewritten.iter().map(|f| f.as_slice()).collect::<Vec<_>>();
+        (pack_superframe(&rewritten).unwrap(), headers)
+    }
+
+    #[test]
+    fn round_trip_streams() {
+        const TEST_STREAMS: [&[u8]; 3] = [
+            include_bytes!("test_data/test-25fps.vp9"),
+            include_bytes!("test_data/vp90-2-10-show-existing-frame.vp9.ivf"),
+            include_bytes!("test_data/vp90-2-10-show-existing-frame2.vp9.ivf"),
+        ];
+        const TEST_SUPERFRAME: &[u8] = include_bytes!("test_data/vp9-superframe.bin");
+
+        let mut num_hidden_frames = 0;
+        let mut num_existing_frames = 0;
+
+        for stream in TEST_STREAMS {
+            let mut parser = Parser::default();
+            let mut reparser = Parser::default();
+
+            for packet in IvfIterator::new(stream) {
+                let (chunk, headers) = rewrite_chunk(packet, &mut parser);
+                let frames = reparser.parse_chunk(chunk.as_slice()).unwrap();
+
+                assert_eq!(frames.len(), headers.len());
+                for (frame, hdr) in frames.iter().zip(&headers) {
+                    assert_eq!(&frame.header, hdr);
+                    num_hidden_frames += usize::from(!hdr.show_frame && !hdr.show_existing_frame);
+                    num_existing_frames += usize::from(hdr.show_existing_frame);
+                }
+            }
+        }
+        assert!(num_hidden_frames > 0);
+        assert!(num_existing_frames > 0);
+
+        // The fixture is cut from the middle of a stream, so only its index can be checked.
+        let frames = Parser::default().parse_chunk(TEST_SUPERFRAME).unwrap();
+        let frames = frames.iter().map(|f| f.as_ref()).collect::<Vec<_>>();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(pack_superframe(&frames).unwrap(), TEST_SUPERFRAME);
+    }
+
+    /// Writes `hdr` and checks that parsing it with `parser` gives `hdr` back.
+    fn round_trip(hdr: &Header, parser: &mut Parser) -> Vec<u8> {
+        let mut buf = vec![];
+        let size = Synthesizer::synthesize(hdr, parser, &mut buf).unwrap();
+        assert_eq!(size, buf.len());
+
+        let parsed = parser.parse_frame(buf.as_slice(), 0, size).unwrap().header;
+        let mut expected = hdr.clone();
+        if !hdr.show_existing_frame {
+            expected.uncompressed_header_size_in_bytes = size as u16;
+        }
+        assert_eq!(parsed, expected);
+
+        buf
+    }
+
+    fn default_lf() -> LoopFilterParams {
+        LoopFilterParams {
+            level: 10,
+            delta_enabled: true,
+            delta_update: true,
+            ref_deltas: [1, 0, -1, -1],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn round_trip_optional_syntax() {
+        let mut parser = Parser::default();
+
+        // 4:4:4 key frame with tiles, quantizer deltas, and every segmentation feature.
+        let mut feature_enabled = [[false; SEG_LVL_MAX]; MAX_SEGMENTS];
+        let mut feature_data = [[0; SEG_LVL_MAX]; MAX_SEGMENTS];
+        for (segment, feature, value) in
+            [(0, 0, 200), (1, 1, -63), (2, 2, 3), (3, 3, 0), (7, 0, -255)]
+        {
+            feature_enabled[segment][feature] = true;
+            feature_data[segment][feature] = value;
+        }
+
+        let key_frame = Header {
+            profile: Profile::Profile1,
+            subsampling_x: false,
+            subsampling_y: false,
+            color_space: ColorSpace::Bt709,
+            color_range: ColorRange::FullSwing,
+            frame_type: FrameType::KeyFrame,
+            show_frame: true,
+            width: 1920,
+            height: 1080,
+            render_and_frame_size_different: true,
+            render_width: 1280,
+            render_height: 720,
+            refresh_frame_flags: 0xff,
+            refresh_frame_context: true,
+            frame_context_idx: 3,
+            lf: LoopFilterParams {
+                level: 63,
+                sharpness: 7,
+                update_ref_delta: [true, false, false, true],
+                ref_deltas: [-20, 0, -1, 33],
+                update_mode_delta: [false, true],
+                mode_deltas: [0, -5],
+                ..default_lf()
+            },
+            quant: QuantizationParams {
+                base_q_idx: 100,
+                delta_q_y_dc: -3,
+                delta_q_uv_dc: 7,
+                delta_q_uv_ac: -15,
+            },
+            seg: SegmentationParams {
+                enabled: true,
+                update_map: true,
+                tree_probs: [255, 10, 128, 255, 1, 254, 200],
+                pred_probs: [255; 3],
+                update_data: true,
+                abs_or_delta_update: true,
+                feature_enabled,
+                feature_data,
+                ..Default::default()
+            },
+            tile_cols_log2: 1,
+            tile_rows_log2: 2,
+            header_size_in_bytes: 0xabcd,
+            ..Default::default()
+        };
+        round_trip(&key_frame, &mut parser);
+
+        // Error resilient inter frame of a size none of its references have, which resets the
+        // segmentation features and loop filter deltas.
+        let inter_frame = Header {
+            frame_type: FrameType::InterFrame,
+            error_resilient_mode: true,
+            width: 640,
+            height: 360,
+            render_width: 640,
+            render_height: 360,
+            render_and_frame_size_different: false,
+            refresh_frame_flags: 0x02,
+            ref_frame_idx: [0, 3, 7],
+            allow_high_precision_mv: true,
+            interpolation_filter: InterpolationFilter::Switchable,
+            refresh_frame_context: false,
+            frame_parallel_decoding_mode: true,
+            frame_context_idx: 0,
+            lf: default_lf(),
+            seg: SegmentationParams {
+                enabled: true,
+                update_map: true,
+                tree_probs: [1, 2, 3, 4, 5, 6, 7],
+                temporal_update: true,
+                pred_probs: [255, 0, 100],
+                ..Default::default()
+            },
+            tile_cols_log2: 0,
+            tile_rows_log2: 1,
+            ..key_frame.clone()
+        };
+        round_trip(&inter_frame, &mut parser);
+
+        let show_existing = Header {
+            profile: Profile::Profile1,
+            show_existing_frame: true,
+            frame_to_show_map_idx: 1,
+            ..Default::default()
+        };
+        let buf = round_trip(&show_existing, &mut parser);
+        assert_eq!(buf, [0xa9]);
+
+        // Profile 0 intra-only frame, followed by an inter frame using its size.
+        let mut parser = Parser::default();
+        let intra_only = Header {
+            color_space: ColorSpace::Bt601,
+            subsampling_x: true,
+            subsampling_y: true,
+            frame_type: FrameType::InterFrame,
+            intra_only: true,
+            reset_frame_context: 2,
+            refresh_frame_flags: 0x21,
+            width: 352,
+            height: 288,
+            render_width: 352,
+            render_height: 288,
+            lf: default_lf(),
+            lossless: true,
+            ..Default::default()
+        };
+        round_trip(&intra_only, &mut parser);
+
+        let inter_frame = Header {
+            intra_only: false,
+            show_frame: true,
+            reset_frame_context: 0,
+            refresh_frame_flags: 0x01,
+            ref_frame_idx: [1, 5, 0],
+            ref_frame_sign_bias: [0, 1, 0, 1],
+            interpolation_filter: InterpolationFilter::EightTapSharp,
+            lf: LoopFilterParams {
+                delta_enabled: false,
+                ..default_lf()
+            },
+            quant: QuantizationParams {
+                base_q_idx: 255,
+                ..Default::default()
+            },
+            lossless: false,
+            ..intra_only.clone()
+        };
+        let buf = round_trip(&inter_frame, &mut parser);
+        // The size is taken from the second reference, instead of being coded explicitly.
+        let mut explicit = vec![];
+        Synthesizer::synthesize(&inter_frame, &Parser::default(), &mut explicit).unwrap();
+        assert!(explicit.len() >= buf.len() + 4);
+
+        // The reserved bits of profile 3.
+        let key_frame = Header {
+            profile: Profile::Profile3,
+            bit_depth: BitDepth::Depth10,
+            color_space: ColorSpace::CsSrgb,
+            color_range: ColorRange::FullSwing,
+            subsampling_x: false,
+            subsampling_y: false,
+            frame_type: FrameType::KeyFrame,
+            refresh_frame_flags: 0xff,
+            width: 65536,
+            height: 1,
+            render_width: 65536,
+            render_height: 1,
+            lf: default_lf(),
+            quant: QuantizationParams {
+                base_q_idx: 1,
+                ..Default::default()
+            },
+            tile_cols_log2: 4,
+            ..Default::default()
+        };
+        round_trip(&key_frame, &mut Parser::default());
+    }
+
+    #[test]
+    fn invalid_headers() {
+        let key_frame = Header {
+            frame_type: FrameType::KeyFrame,
+            width: 320,
+            height: 240,
+            ..Default::default()
+        };
+        let synthesize =
+            |hdr: &Header| Synthesizer::synthesize(hdr, &Parser::default(), &mut vec![]).is_err();
+
+        assert!(!synthesize(&key_frame));
+        for hdr in [
+            Header {
+                width: 0,
+                ..key_frame.clone()
+            },
+            Header {
+                bit_depth: BitDepth::Depth10,
+                ..key_frame.clone()
+            },
+            Header {
+                tile_cols_log2: 1,
+                ..key_frame.clone()
+            },
+            Header {
+                tile_rows_log2: 3,
+                ..key_frame.clone()
+            },
+            Header {
+                quant: QuantizationParams {
+                    delta_q_y_dc: 16,
+                    ..Default::default()
+                },
+                ..key_frame.clone()
+            },
+            Header {
+                lf: LoopFilterParams {
+                    level: 64,
+                    ..Default::default()
+                },
+                ..key_frame.clone()
+            },
+            Header {
+                seg: SegmentationParams {
+                    enabled: true,
+                    update_data: true,
+                    feature_enabled: [[true; SEG_LVL_MAX]; MAX_SEGMENTS],
+                    feature_data: [[0, 0, -1, 0]; MAX_SEGMENTS],
+                    ..Default::default()
+                },
+                ..key_frame.clone()
+            },
+            Header {
+                frame_type: FrameType::InterFrame,
+                show_frame: true,
+                intra_only: true,
+                ..key_frame.clone()
+            },
+        ] {
+            assert!(synthesize(&hdr), "{:?}", hdr);
+        }
+    }
+
+    #[test]
+    fn superframe_index() {
+        let small: &[u8] = &[0xaa; 3];
+        let large = vec![0x55u8; 0x10000];
+
+        let data = pack_superframe(&[small, small]).unwrap();
+        assert_eq!(&data[6..], [0xc1, 3, 3, 0xc1]);
+
+        let data = pack_superframe(&[small, &large, small]).unwrap();
+        assert_eq!(
+            &data[data.len() - 11..],
+            [0xd2, 3, 0, 0, 0, 0, 1, 3, 0, 0, 0xd2]
+        );
+
+        assert!(pack_superframe(&[]).is_err());
+        assert!(pack_superframe(&[small; MAX_FRAMES_IN_SUPERFRAME + 1]).is_err());
+    }
+}
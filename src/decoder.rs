@@ -10,10 +10,14 @@
 //! At the moment, only a [stateless] decoder interface is provided.
 
 pub mod stateless;
+pub mod timestamp;
 
 use std::collections::VecDeque;
 
+use anyhow::anyhow;
+
 use crate::DecodedFormat;
+use crate::Fourcc;
 use crate::Resolution;
 
 /// Trait for a pool of frames in a particular format.
@@ -109,6 +113,135 @@ pub trait MappableHandle {
 
     /// Returns the size of the `buffer` argument required to call `read` on this handle.
     fn image_size(&mut self) -> usize;
+
+    /// Maps the planes of `self` without copying them.
+    ///
+    /// Contrary to `read`, the planes are described as they are laid out in memory, including
+    /// their padding and packed formats like `P010` or `Y410`.
+    ///
+    /// Handles that cannot be mapped return an error, which is the default.
+    fn map(&mut self) -> anyhow::Result<MappedFrame<'_>> {
+        Err(anyhow!("mapping is not supported by this handle"))
+    }
+}
+
+/// A plane of a [`MappedFrame`].
+#[derive(Debug)]
+pub struct MappedPlane<'a> {
+    /// Data of the plane, from its first line to the end of its last one.
+    pub data: &'a [u8],
+    /// Offset of the plane from the start of the mapping.
+    pub offset: usize,
+    /// Distance in bytes between two lines of the plane.
+    pub stride: usize,
+    /// Number of significant bits of each sample.
+    pub bit_depth: u8,
+    /// Whether the plane is subsampled horizontally and vertically, i.e. has half the width or
+    /// the height of the frame, rounded up. Samples of packed formats like `Y210` share a plane
+    /// with the luma and are not considered subsampled.
+    pub subsampling: (bool, bool),
+}
+
+/// The planes of a decoded frame, mapped into the client's address space by
+/// [`MappableHandle::map`].
+#[derive(Debug)]
+pub struct MappedFrame<'a> {
+    /// Fourcc of the mapped frame, which defines the order and packing of the samples within
+    /// each plane, e.g. interleaved chroma for `NV12` or 16-bit samples with the significant bits
+    /// in the MSBs for `P010`.
+    pub fourcc: Fourcc,
+    /// Visible size of the frame, in pixels.
+    pub resolution: Resolution,
+    pub planes: Vec<MappedPlane<'a>>,
+}
+
+impl<'a> MappedFrame<'a> {
+    /// Describes the planes of a frame of format `fourcc` and size `resolution` mapped at `data`,
+    /// given the stride and offset of each plane.
+    ///
+    /// Supported formats are `NV12`, `I420`, `422H`, `444P`, `P010`, `P012`, `Y210`, `Y212`,
+    /// `Y410` and `Y412`.
+    pub fn new(
+        data: &'a [u8],
+        fourcc: Fourcc,
+        resolution: Resolution,
+        strides: &[usize],
+        offsets: &[usize],
+    ) -> anyhow::Result<Self> {
+        const FULL: (bool, bool) = (false, false);
+        const SUB_420: (bool, bool) = (true, true);
+        const SUB_422: (bool, bool) = (true, false);
+
+        // Subsampling of each plane, along with the number of pixels and bytes of its smallest
+        // horizontal group of samples.
+        type PlaneDesc = ((bool, bool), (usize, usize));
+        let (bit_depth, descs): (u8, &[PlaneDesc]) = match &<[u8; 4]>::from(fourcc) {
+            b"NV12" => (8, &[(FULL, (1, 1)), (SUB_420, (2, 2))]),
+            b"I420" => (8, &[(FULL, (1, 1)), (SUB_420, (2, 1)), (SUB_420, (2, 1))]),
+            b"422H" => (8, &[(FULL, (1, 1)), (SUB_422, (2, 1)), (SUB_422, (2, 1))]),
+            b"444P" => (8, &[(FULL, (1, 1)), (FULL, (1, 1)), (FULL, (1, 1))]),
+            b"P010" => (10, &[(FULL, (1, 2)), (SUB_420, (2, 4))]),
+            b"P012" => (12, &[(FULL, (1, 2)), (SUB_420, (2, 4))]),
+            b"Y210" => (10, &[(FULL, (2, 8))]),
+            b"Y212" => (12, &[(FULL, (2, 8))]),
+            b"Y410" => (10, &[(FULL, (1, 4))]),
+            b"Y412" => (12, &[(FULL, (1, 8))]),
+            _ => return Err(anyhow!("cannot map frames of format {}", fourcc)),
+        };
+
+        if strides.len() < descs.len() || offsets.len() < descs.len() {
+            return Err(anyhow!("{} frames have {} planes", fourcc, descs.len()));
+        }
+
+        let planes = descs
+            .iter()
+            .enumerate()
+            .map(|(i, &(subsampling, (group_width, group_size)))| {
+                let (offset, stride) = (offsets[i], strides[i]);
+                let height = if subsampling.1 {
+                    (resolution.height as usize).div_ceil(2)
+                } else {
+                    resolution.height as usize
+                };
+                let line_size = (resolution.width as usize).div_ceil(group_width) * group_size;
+
+                // The last line of the plane may not be padded up to the stride, so only its
+                // significant bytes need to be mapped.
+                let min_end = match height.checked_sub(1) {
+                    None => Some(offset),
+                    Some(padded_lines) => stride
+                        .checked_mul(padded_lines)
+                        .and_then(|size| size.checked_add(line_size))
+                        .and_then(|size| size.checked_add(offset)),
+                };
+                let padded_end = stride
+                    .checked_mul(height)
+                    .and_then(|size| size.checked_add(offset));
+                let data = match (min_end, padded_end) {
+                    (Some(min_end), Some(padded_end))
+                        if min_end <= padded_end && min_end <= data.len() =>
+                    {
+                        &data[offset..std::cmp::min(padded_end, data.len())]
+                    }
+                    _ => return Err(anyhow!("plane {} does not fit in the mapping", i)),
+                };
+
+                Ok(MappedPlane {
+                    data,
+                    offset,
+                    stride,
+                    bit_depth,
+                    subsampling,
+                })
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(Self {
+            fourcc,
+            resolution,
+            planes,
+        })
+    }
 }
 
 /// The handle type used by the decoder backend. The only requirement from implementors is that
@@ -125,6 +258,11 @@ pub trait DecodedHandle {
     /// Returns the timestamp of the picture.
     fn timestamp(&self) -> u64;
 
+    /// Returns the duration of the picture, in the same unit as its timestamp, if it is known.
+    fn duration(&self) -> Option<u64> {
+        None
+    }
+
     /// Returns the coded resolution at the time this handle was decoded.
     fn coded_resolution(&self) -> Resolution;
 
@@ -192,3 +330,95 @@ impl<'a, T> Iterator for &'a mut ReadyFramesQueue<T> {
         self.queue.pop_front()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn map_nv12() {
+        // 6x3 frame with 8-byte lines, the chroma plane starting right after the luma.
+        let data = [0u8; 8 * 3 + 8 * 2 - 2];
+        let frame = MappedFrame::new(
+            &data,
+            Fourcc::from(b"NV12"),
+            Resolution::from((6, 3)),
+            &[8, 8],
+            &[0, 24],
+        )
+        .unwrap();
+
+        assert_eq!(frame.planes.len(), 2);
+        assert_eq!(frame.planes[0].data.len(), 24);
+        assert_eq!(frame.planes[0].subsampling, (false, false));
+        assert_eq!(frame.planes[1].offset, 24);
+        assert_eq!(frame.planes[1].stride, 8);
+        assert_eq!(frame.planes[1].subsampling, (true, true));
+        // The last line is not padded.
+        assert_eq!(frame.planes[1].data.len(), 14);
+        assert!(frame.planes.iter().all(|plane| plane.bit_depth == 8));
+    }
+
+    #[test]
+    fn map_packed() {
+        let data = [0u8; 16 * 2];
+        let frame = MappedFrame::new(
+            &data,
+            Fourcc::from(b"Y410"),
+            Resolution::from((4, 2)),
+            &[16],
+            &[0],
+        )
+        .unwrap();
+
+        assert_eq!(frame.planes.len(), 1);
+        assert_eq!(frame.planes[0].bit_depth, 10);
+        assert_eq!(frame.planes[0].data.len(), 32);
+    }
+
+    #[test]
+    fn map_invalid() {
+        let data = [0u8; 64];
+        let resolution = Resolution::from((4, 4));
+
+        assert!(MappedFrame::new(&data, Fourcc::from(b"RGBA"), resolution, &[16], &[0]).is_err());
+        // Missing plane.
+        assert!(
+            MappedFrame::new(&data, Fourcc::from(b"I420"), resolution, &[4, 2], &[0, 16]).is_err()
+        );
+        // Plane beyond the end of the mapping.
+        assert!(
+            MappedFrame::new(&data, Fourcc::from(b"NV12"), resolution, &[4, 4], &[0, 80]).is_err()
+        );
+        // Last line of the chroma plane one byte short.
+        assert!(MappedFrame::new(
+            &data[..31],
+            Fourcc::from(b"NV12"),
+            resolution,
+            &[4, 4],
+            &[0, 24]
+        )
+        .is_err());
+        assert!(MappedFrame::new(
+            &data[..32],
+            Fourcc::from(b"NV12"),
+            resolution,
+            &[4, 4],
+            &[0, 24]
+        )
+        .is_ok());
+        // Lines overlapping.
+        assert!(
+            MappedFrame::new(&data, Fourcc::from(b"P010"), resolution, &[4, 8], &[0, 16]).is_err()
+        );
+        // Plane size overflowing.
+        assert!(MappedFrame::new(
+            &data,
+            Fourcc::from(b"NV12"),
+            resolution,
+            &[usize::MAX / 2, 4],
+            &[0, 16]
+        )
+        .is_err());
+    }
+}
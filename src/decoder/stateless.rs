@@ -18,6 +18,8 @@ pub mod vp9;
 
 use thiserror::Error;
 
+use crate::decoder::timestamp::TimestampMode;
+use crate::decoder::timestamp::TimestampTracker;
 use crate::decoder::BlockingMode;
 use crate::decoder::DecodedHandle;
 use crate::decoder::DecoderEvent;
@@ -220,6 +222,18 @@ pub trait StatelessVideoDecoder<M> {
 
     fn stream_info(&self) -> Option<&StreamInfo>;
 
+    /// Sets how the timestamps of the decoded frames are computed. Pictures that have already
+    /// been submitted may not get correct timestamps, so this should be called before decoding
+    /// starts.
+    ///
+    /// Decoders that cannot compute timestamps keep passing them through, which is what this
+    /// default implementation does.
+    fn set_timestamp_mode(&mut self, mode: TimestampMode) {
+        if mode != TimestampMode::Passthrough {
+            log::warn!("{:?} timestamps are not supported by this decoder", mode);
+        }
+    }
+
     /// Returns the next event, if there is any pending.
     fn next_event(&mut self) -> Option<DecoderEvent<M>>;
 }
@@ -265,6 +279,9 @@ where
 
     ready_queue: ReadyFramesQueue<B::Handle>,
 
+    /// Computes the timestamps of the frames taken from `ready_queue`.
+    timestamps: TimestampTracker,
+
     decoding_state: DecodingState<C::FormatInfo>,
 
     /// The backend used for hardware acceleration.
@@ -287,6 +304,7 @@ where
             coded_resolution: Default::default(),
             decoding_state: Default::default(),
             ready_queue: Default::default(),
+            timestamps: Default::default(),
             codec: Default::default(),
         }
     }
@@ -319,69 +337,5 @@ impl<C: StatelessCodec, B: StatelessDecoderBackend<C::FormatInfo>> private::Stat
     }
 }
 
-#[cfg(test)]
-pub(crate) mod tests {
-    use crate::decoder::stateless::StatelessVideoDecoder;
-    use crate::decoder::DecodedHandle;
-
-    /// Stream that can be used in tests, along with the CRC32 of all of its frames.
-    pub struct TestStream {
-        /// Bytestream to decode.
-        pub stream: &'static [u8],
-        /// Expected CRC for each frame, one per line.
-        pub crcs: &'static str,
-    }
-
-    /// Run the codec-specific `decoding_loop` on a `decoder` with a given `test`, linearly
-    /// decoding the stream until its end.
-    ///
-    /// If `check_crcs` is `true`, then the expected CRCs of the decoded images are compared
-    /// against the existing result. We may want to set this to false when using a decoder backend
-    /// that does not produce actual frames.
-    ///
-    /// `dump_yuv` will dump all the decoded frames into `/tmp/framexxx.yuv`. Set this to true in
-    /// order to debug the output of the test.
-    pub fn test_decode_stream<D, M, L>(
-        decoding_loop: L,
-        mut decoder: D,
-        test: &TestStream,
-        check_crcs: bool,
-        dump_yuv: bool,
-    ) where
-        D: StatelessVideoDecoder<M>,
-        L: Fn(
-            &mut D,
-            &[u8],
-            &mut dyn FnMut(Box<dyn DecodedHandle<Descriptor = M>>),
-        ) -> anyhow::Result<()>,
-    {
-        let mut crcs = test.crcs.lines().enumerate();
-
-        decoding_loop(&mut decoder, test.stream, &mut |handle| {
-            let (frame_num, expected_crc) = crcs.next().expect("decoded more frames than expected");
-
-            if check_crcs || dump_yuv {
-                handle.sync().unwrap();
-                let picture = handle.dyn_picture();
-                let mut backend_handle = picture.dyn_mappable_handle().unwrap();
-
-                let buffer_size = backend_handle.image_size();
-                let mut nv12 = vec![0; buffer_size];
-
-                backend_handle.read(&mut nv12).unwrap();
-
-                if dump_yuv {
-                    std::fs::write(format!("/tmp/frame{:03}.yuv", frame_num), &nv12).unwrap();
-                }
-
-                if check_crcs {
-                    let frame_crc = format!("{:08x}", crc32fast::hash(&nv12));
-                    assert_eq!(frame_crc, expected_crc, "at frame {}", frame_num);
-                }
-            }
-        })
-        .unwrap();
-
-        assert_eq!(crcs.next(), None, "decoded less frames than expected");
-    }
-}
+#[cfg(any(test, feature = "test-utils"))]
+pub mod tests;
@@ -38,6 +38,9 @@ use crate::decoder::stateless::StatelessDecoder;
 use crate::decoder::stateless::StatelessDecoderBackend;
 use crate::decoder::stateless::StatelessDecoderFormatNegotiator;
 use crate::decoder::stateless::StatelessVideoDecoder;
+use crate::decoder::timestamp::PictureTiming;
+use crate::decoder::timestamp::StreamClock;
+use crate::decoder::timestamp::TimestampMode;
 use crate::decoder::BlockingMode;
 use crate::decoder::DecodedHandle;
 use crate::decoder::DecoderEvent;
@@ -67,6 +70,36 @@ fn get_raster_from_zigzag_4x4(src: [u8; 16], dst: &mut [u8; 16]) {
     }
 }
 
+/// Returns the timing information of `pic` used to synthesize its timestamp.
+fn picture_timing(pic: &PictureData, sps: &Sps) -> PictureTiming {
+    let vui = &sps.vui_parameters;
+
+    // A clock tick is the duration of a field, so frames usually last two ticks and increment the
+    // POC by 2.
+    let clock = if sps.vui_parameters_present_flag && vui.timing_info_present_flag() {
+        StreamClock {
+            time_scale: vui.time_scale(),
+            frame_duration: 2 * u64::from(vui.num_units_in_tick()),
+            poc_duration: u64::from(vui.num_units_in_tick()),
+        }
+    } else {
+        StreamClock::fallback(2)
+    };
+
+    // Pictures with memory_management_control_operation 5 restart the POC at 0 once decoded.
+    let has_mmco_5 = pic
+        .ref_pic_marking
+        .inner()
+        .iter()
+        .any(|m| m.memory_management_control_operation() == 5);
+
+    PictureTiming {
+        poc: if has_mmco_5 { 0 } else { pic.pic_order_cnt },
+        new_sequence: has_mmco_5 || matches!(pic.is_idr, IsIdr::Yes { .. }),
+        clock,
+    }
+}
+
 /// Stateless backend methods specific to H.264.
 pub trait StatelessH264DecoderBackend: StatelessDecoderBackend<Rc<Sps>> {
     /// Called when a new SPS is parsed.
@@ -1501,7 +1534,11 @@ where
             self.backend
                 .new_field_picture(&pic, timestamp, &first_field.1)?
         } else {
-            self.backend.new_picture(&pic, timestamp)?
+            let backend_pic = self.backend.new_picture(&pic, timestamp)?;
+            // Second fields are output along with their first field.
+            self.timestamps
+                .picture_decoded(timestamp, Some(picture_timing(&pic, &pps.sps)));
+            backend_pic
         };
 
         self.backend.start_picture(
@@ -1900,7 +1937,7 @@ where
         // change event that will allow us to keep going.
         (&mut self.ready_queue)
             .next()
-            .map(|handle| DecoderEvent::FrameReady(Box::new(handle)))
+            .map(|handle| DecoderEvent::FrameReady(Box::new(self.timestamps.frame_output(handle))))
             .or_else(|| {
                 if let DecodingState::AwaitingFormat(sps) = &self.decoding_state {
                     Some(DecoderEvent::FormatChanged(Box::new(
@@ -1924,22 +1961,38 @@ where
     fn stream_info(&self) -> Option<&StreamInfo> {
         self.stream_info()
     }
+
+    fn set_timestamp_mode(&mut self, mode: TimestampMode) {
+        self.timestamps.set_mode(mode);
+    }
 }
 
-#[cfg(test)]
+#[cfg(any(test, feature = "test-utils"))]
 pub mod tests {
+    #[cfg(test)]
     use crate::codec::h264::parser::Nalu;
+    #[cfg(test)]
     use crate::decoder::stateless::h264::H264;
+    #[cfg(test)]
     use crate::decoder::stateless::tests::test_decode_stream;
     use crate::decoder::stateless::tests::TestStream;
+    #[cfg(test)]
     use crate::decoder::stateless::StatelessDecoder;
+    #[cfg(test)]
+    use crate::decoder::timestamp::TimestampMode;
+    #[cfg(test)]
     use crate::decoder::BlockingMode;
+    #[cfg(test)]
     use crate::utils::simple_playback_loop;
+    #[cfg(test)]
     use crate::utils::simple_playback_loop_owned_frames;
+    #[cfg(test)]
     use crate::utils::NalIterator;
+    #[cfg(test)]
     use crate::DecodedFormat;
 
     /// Run `test` using the dummy decoder, in both blocking and non-blocking modes.
+    #[cfg(test)]
     fn test_decoder_dummy(test: &TestStream, blocking_mode: BlockingMode) {
         let decoder = StatelessDecoder::<H264, _>::new_dummy(blocking_mode);
 
@@ -1952,6 +2005,7 @@ pub mod tests {
                     &mut simple_playback_loop_owned_frames,
                     DecodedFormat::NV12,
                     blocking_mode,
+                    TimestampMode::Passthrough,
                 )
             },
             decoder,
@@ -2019,6 +2073,32 @@ pub mod tests {
         test_decoder_dummy(&DECODE_64X64_PROGRESSIVE_I_P_B_P, BlockingMode::NonBlocking);
     }
 
+    #[test]
+    fn test_64x64_progressive_i_p_b_p_timestamps() {
+        let mut decoder = StatelessDecoder::<H264, _>::new_dummy(BlockingMode::Blocking);
+        let mut frames = Vec::new();
+
+        simple_playback_loop(
+            &mut decoder,
+            NalIterator::<Nalu<_>>::new(DECODE_64X64_PROGRESSIVE_I_P_B_P.stream),
+            &mut |handle| frames.push((handle.timestamp(), handle.duration())),
+            &mut simple_playback_loop_owned_frames,
+            DecodedFormat::NV12,
+            BlockingMode::Blocking,
+            TimestampMode::Synthesize {
+                timescale: 1_000_000,
+            },
+        )
+        .unwrap();
+
+        // The VUI specifies 30 frames per second. Timestamps are rounded from the exact stream
+        // time, so durations may vary by one unit but never drift.
+        assert_eq!(
+            frames,
+            vec![(0, Some(33333)), (33333, Some(33333)), (66666, Some(33334))]
+        );
+    }
+
     /// A 64x64 progressive byte-stream encoded I-P-B-P sequence to make it
     /// easier to it easier to spot errors on the libva trace.
     /// Also tests whether the decoder supports the high profile.
@@ -41,10 +41,10 @@ impl StatelessH264DecoderBackend for Backend {
     fn new_field_picture(
         &mut self,
         _: &PictureData,
-        _: u64,
+        timestamp: u64,
         _: &Self::Handle,
-    ) -> StatelessBackendResult<()> {
-        Ok(())
+    ) -> StatelessBackendResult<Self::Picture> {
+        Ok(timestamp)
     }
 
     fn decode_slice(
@@ -60,14 +60,19 @@ impl StatelessH264DecoderBackend for Backend {
         Ok(())
     }
 
-    fn submit_picture(&mut self, _: Self::Picture) -> StatelessBackendResult<Self::Handle> {
+    fn submit_picture(&mut self, picture: Self::Picture) -> StatelessBackendResult<Self::Handle> {
         Ok(Handle {
             handle: Rc::new(RefCell::new(Default::default())),
+            timestamp: picture,
         })
     }
 
-    fn new_picture(&mut self, _: &PictureData, _: u64) -> StatelessBackendResult<()> {
-        Ok(())
+    fn new_picture(
+        &mut self,
+        _: &PictureData,
+        timestamp: u64,
+    ) -> StatelessBackendResult<Self::Picture> {
+        Ok(timestamp)
     }
 }
 
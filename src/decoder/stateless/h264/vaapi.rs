@@ -593,6 +593,7 @@ mod tests {
     use crate::decoder::stateless::tests::test_decode_stream;
     use crate::decoder::stateless::tests::TestStream;
     use crate::decoder::stateless::StatelessDecoder;
+    use crate::decoder::timestamp::TimestampMode;
     use crate::decoder::BlockingMode;
     use crate::utils::simple_playback_loop;
     use crate::utils::simple_playback_loop_owned_frames;
@@ -617,6 +618,7 @@ mod tests {
                     &mut simple_playback_loop_owned_frames,
                     output_format,
                     blocking_mode,
+                    TimestampMode::Passthrough,
                 )
             },
             decoder,
@@ -34,6 +34,9 @@ use crate::decoder::stateless::StatelessDecoder;
 use crate::decoder::stateless::StatelessDecoderBackend;
 use crate::decoder::stateless::StatelessDecoderFormatNegotiator;
 use crate::decoder::stateless::StatelessVideoDecoder;
+use crate::decoder::timestamp::PictureTiming;
+use crate::decoder::timestamp::StreamClock;
+use crate::decoder::timestamp::TimestampMode;
 use crate::decoder::BlockingMode;
 use crate::decoder::DecodedHandle;
 use crate::decoder::DecoderEvent;
@@ -100,6 +103,35 @@ fn get_raster_from_up_right_diagonal_4x4(src: [u8; 16], dst: &mut [u8; 16]) {
     }
 }
 
+/// Returns the timing information of `pic`, used to synthesize its presentation timestamp.
+fn picture_timing(pic: &PictureData, sps: &Sps) -> PictureTiming {
+    let vui = sps.vui_parameters();
+
+    // Unlike H.264, a clock tick is the duration of a frame in H.265. The VUI may also specify
+    // how many ticks correspond to a POC increment.
+    let clock = if sps.vui_parameters_present_flag() && vui.timing_info_present_flag() {
+        let ticks_per_poc = if vui.poc_proportional_to_timing_flag() {
+            u64::from(vui.num_ticks_poc_diff_one_minus1()) + 1
+        } else {
+            1
+        };
+
+        StreamClock {
+            time_scale: vui.time_scale(),
+            frame_duration: u64::from(vui.num_units_in_tick()),
+            poc_duration: ticks_per_poc * u64::from(vui.num_units_in_tick()),
+        }
+    } else {
+        StreamClock::fallback(1)
+    };
+
+    PictureTiming {
+        poc: pic.pic_order_cnt_val,
+        new_sequence: pic.is_irap && pic.no_rasl_output_flag,
+        clock,
+    }
+}
+
 /// Stateless backend methods specific to H.265.
 pub trait StatelessH265DecoderBackend: StatelessDecoderBackend<Sps> {
     /// Called when a new SPS is parsed.
@@ -978,6 +1010,16 @@ where
 
         let mut backend_pic = self.backend.new_picture(&pic, timestamp)?;
 
+        if pic.pic_output_flag {
+            let sps = self
+                .codec
+                .parser
+                .get_sps(self.codec.cur_sps_id)
+                .context("Invalid SPS")?;
+            self.timestamps
+                .picture_decoded(timestamp, Some(picture_timing(&pic, sps)));
+        }
+
         self.backend.begin_picture(
             &mut backend_pic,
             &pic,
@@ -1297,7 +1339,7 @@ where
         // change event that will allow us to keep going.
         (&mut self.ready_queue)
             .next()
-            .map(|handle| DecoderEvent::FrameReady(Box::new(handle)))
+            .map(|handle| DecoderEvent::FrameReady(Box::new(self.timestamps.frame_output(handle))))
             .or_else(|| {
                 if let DecodingState::AwaitingFormat(sps) = &self.decoding_state {
                     Some(DecoderEvent::FormatChanged(Box::new(
@@ -1322,23 +1364,38 @@ where
     fn stream_info(&self) -> Option<&StreamInfo> {
         self.backend.stream_info()
     }
+
+    fn set_timestamp_mode(&mut self, mode: TimestampMode) {
+        self.timestamps.set_mode(mode);
+    }
 }
 
-#[cfg(test)]
+#[cfg(any(test, feature = "test-utils"))]
 pub mod tests {
-
+    #[cfg(test)]
     use crate::codec::h265::parser::Nalu;
+    #[cfg(test)]
     use crate::decoder::stateless::h265::H265;
+    #[cfg(test)]
     use crate::decoder::stateless::tests::test_decode_stream;
     use crate::decoder::stateless::tests::TestStream;
+    #[cfg(test)]
     use crate::decoder::stateless::StatelessDecoder;
+    #[cfg(test)]
+    use crate::decoder::timestamp::TimestampMode;
+    #[cfg(test)]
     use crate::decoder::BlockingMode;
+    #[cfg(test)]
     use crate::utils::simple_playback_loop;
+    #[cfg(test)]
     use crate::utils::simple_playback_loop_owned_frames;
+    #[cfg(test)]
     use crate::utils::NalIterator;
+    #[cfg(test)]
     use crate::DecodedFormat;
 
     /// Run `test` using the dummy decoder, in both blocking and non-blocking modes.
+    #[cfg(test)]
     fn test_decoder_dummy(test: &TestStream, blocking_mode: BlockingMode) {
         let decoder = StatelessDecoder::<H265, _>::new_dummy(blocking_mode);
 
@@ -1351,6 +1408,7 @@ pub mod tests {
                     &mut simple_playback_loop_owned_frames,
                     DecodedFormat::NV12,
                     blocking_mode,
+                    TimestampMode::Passthrough,
                 )
             },
             decoder,
@@ -1418,6 +1476,32 @@ pub mod tests {
         test_decoder_dummy(&DECODE_64X64_PROGRESSIVE_I_P_B_P, BlockingMode::NonBlocking);
     }
 
+    #[test]
+    fn test_64x64_progressive_i_p_b_p_timestamps() {
+        let mut decoder = StatelessDecoder::<H265, _>::new_dummy(BlockingMode::Blocking);
+        let mut frames = Vec::new();
+
+        simple_playback_loop(
+            &mut decoder,
+            NalIterator::<Nalu<_>>::new(DECODE_64X64_PROGRESSIVE_I_P_B_P.stream),
+            &mut |handle| frames.push((handle.timestamp(), handle.duration())),
+            &mut simple_playback_loop_owned_frames,
+            DecodedFormat::NV12,
+            BlockingMode::Blocking,
+            TimestampMode::Synthesize {
+                timescale: 1_000_000,
+            },
+        )
+        .unwrap();
+
+        // The VUI specifies 30 frames per second. Timestamps are rounded from the exact stream
+        // time, so durations may vary by one unit but never drift.
+        assert_eq!(
+            frames,
+            vec![(0, Some(33333)), (33333, Some(33333)), (66666, Some(33334))]
+        );
+    }
+
     /// Same as Chromium's test-25fps.h265
     pub const DECODE_TEST_25FPS: TestStream = TestStream {
         stream: include_bytes!("../../codec/h265/test_data/test-25fps.h265"),
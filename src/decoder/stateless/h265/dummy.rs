@@ -27,9 +27,9 @@ impl StatelessH265DecoderBackend for Backend {
     fn new_picture(
         &mut self,
         _: &crate::codec::h265::picture::PictureData,
-        _: u64,
+        timestamp: u64,
     ) -> crate::decoder::stateless::StatelessBackendResult<Self::Picture> {
-        Ok(())
+        Ok(timestamp)
     }
 
     fn begin_picture(
@@ -60,10 +60,11 @@ impl StatelessH265DecoderBackend for Backend {
 
     fn submit_picture(
         &mut self,
-        _: Self::Picture,
+        picture: Self::Picture,
     ) -> crate::decoder::stateless::StatelessBackendResult<Self::Handle> {
         Ok(Handle {
             handle: Rc::new(RefCell::new(Default::default())),
+            timestamp: picture,
         })
     }
 }
@@ -0,0 +1,383 @@
+// Copyright 2023 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+//! Harness for checking decoders against streams with known output.
+//!
+//! Besides the tests of this crate, this module is available to other crates through the
+//! `test-utils` feature, so any [`StatelessDecoderBackend`](super::StatelessDecoderBackend) can be
+//! checked against the test streams bundled in the `tests` module of each codec, or against
+//! external conformance suites using [`conformance`].
+
+pub mod conformance;
+
+use anyhow::anyhow;
+
+use crate::decoder::stateless::StatelessVideoDecoder;
+use crate::decoder::DecodedHandle;
+use crate::utils::convert::convert;
+use crate::utils::convert::Dither;
+use crate::utils::convert::PixelFormat;
+use crate::DecodedFormat;
+use crate::Resolution;
+
+/// Stream that can be used in tests, along with the CRC32 of all of its frames.
+pub struct TestStream {
+    /// Bytestream to decode.
+    pub stream: &'static [u8],
+    /// Expected CRC for each frame, one per line.
+    pub crcs: &'static str,
+}
+
+impl TestStream {
+    /// Returns the expected checksums of the frames of the stream.
+    pub fn expected_checksums(&self) -> ExpectedChecksums {
+        ExpectedChecksums::from_crc_list(self.crcs)
+    }
+}
+
+/// Run the codec-specific `decoding_loop` on a `decoder` with a given `test`, linearly
+/// decoding the stream until its end.
+///
+/// If `check_crcs` is `true`, then the expected CRCs of the decoded images are compared
+/// against the existing result. We may want to set this to false when using a decoder backend
+/// that does not produce actual frames.
+///
+/// `dump_yuv` will dump all the decoded frames into `/tmp/framexxx.yuv`. Set this to true in
+/// order to debug the output of the test.
+pub fn test_decode_stream<D, M, L>(
+    decoding_loop: L,
+    mut decoder: D,
+    test: &TestStream,
+    check_crcs: bool,
+    dump_yuv: bool,
+) where
+    D: StatelessVideoDecoder<M>,
+    L: Fn(
+        &mut D,
+        &[u8],
+        &mut dyn FnMut(Box<dyn DecodedHandle<Descriptor = M>>),
+    ) -> anyhow::Result<()>,
+{
+    let mut crcs = test.crcs.lines().enumerate();
+
+    decoding_loop(&mut decoder, test.stream, &mut |handle| {
+        let (frame_num, expected_crc) = crcs.next().expect("decoded more frames than expected");
+
+        if check_crcs || dump_yuv {
+            handle.sync().unwrap();
+            let picture = handle.dyn_picture();
+            let mut backend_handle = picture.dyn_mappable_handle().unwrap();
+
+            let buffer_size = backend_handle.image_size();
+            let mut nv12 = vec![0; buffer_size];
+
+            backend_handle.read(&mut nv12).unwrap();
+
+            if dump_yuv {
+                std::fs::write(format!("/tmp/frame{:03}.yuv", frame_num), &nv12).unwrap();
+            }
+
+            if check_crcs {
+                let frame_crc = format!("{:08x}", crc32fast::hash(&nv12));
+                assert_eq!(frame_crc, expected_crc, "at frame {}", frame_num);
+            }
+        }
+    })
+    .unwrap();
+
+    assert_eq!(crcs.next(), None, "decoded less frames than expected");
+}
+
+/// Returns `checksum` in lowercase if it is a valid hexadecimal MD5.
+fn parse_md5(checksum: &str) -> anyhow::Result<String> {
+    if checksum.len() != 32 || !checksum.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(anyhow!("invalid MD5 {:?}", checksum));
+    }
+
+    Ok(checksum.to_ascii_lowercase())
+}
+
+/// Checksums of the decoded frames of a stream, in hexadecimal.
+///
+/// Frames are checksummed as written by
+/// [`MappableHandle::read`](crate::decoder::MappableHandle::read), i.e. without padding. The MD5s
+/// of conformance suites are computed on planar frames, so
+/// [`ChecksumVerifier::check_decoded_frame`] converts `NV12` frames to `I420` before checking them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ExpectedChecksums {
+    /// CRC32 of each frame, as used by the test streams of this crate.
+    Crc32(Vec<String>),
+    /// MD5 of each frame, as used by the libvpx test vectors.
+    Md5(Vec<String>),
+    /// MD5 of all the frames one after the other, as used by the JVT and JCT-VC conformance
+    /// suites.
+    StreamMd5(String),
+}
+
+impl ExpectedChecksums {
+    /// Returns the checksums of `crcs`, which holds one CRC32 per line.
+    pub fn from_crc_list(crcs: &str) -> Self {
+        Self::Crc32(crcs.lines().map(str::to_owned).collect())
+    }
+
+    /// Parses `manifest` in the format of `md5sum`: one MD5 per frame and per line, optionally
+    /// followed by the name of the file the frame was dumped into.
+    pub fn from_md5_manifest(manifest: &str) -> anyhow::Result<Self> {
+        manifest
+            .lines()
+            .filter_map(|line| line.split_whitespace().next())
+            .map(parse_md5)
+            .collect::<anyhow::Result<_>>()
+            .map(Self::Md5)
+    }
+
+    /// Parses `manifest`, which holds the MD5 of the whole decoded stream, optionally followed by
+    /// the name of the file it was dumped into.
+    pub fn from_stream_md5(manifest: &str) -> anyhow::Result<Self> {
+        let checksum = manifest
+            .split_whitespace()
+            .next()
+            .ok_or_else(|| anyhow!("empty MD5 manifest"))?;
+
+        Ok(Self::StreamMd5(parse_md5(checksum)?))
+    }
+
+    /// Returns a verifier to feed the decoded frames to, in display order.
+    pub fn verifier(&self) -> ChecksumVerifier<'_> {
+        ChecksumVerifier {
+            expected: self,
+            frame_num: 0,
+            stream_md5: md5::Context::new(),
+        }
+    }
+}
+
+/// Checks decoded frames against [`ExpectedChecksums`], one after the other.
+pub struct ChecksumVerifier<'a> {
+    expected: &'a ExpectedChecksums,
+    frame_num: usize,
+    stream_md5: md5::Context,
+}
+
+impl<'a> ChecksumVerifier<'a> {
+    /// Checks the next decoded `frame`.
+    pub fn check_frame(&mut self, frame: &[u8]) -> anyhow::Result<()> {
+        let frame_num = self.frame_num;
+        self.frame_num += 1;
+
+        let (checksum, expected) = match self.expected {
+            ExpectedChecksums::Crc32(crcs) => (
+                format!("{:08x}", crc32fast::hash(frame)),
+                crcs.get(frame_num),
+            ),
+            ExpectedChecksums::Md5(md5s) => {
+                (format!("{:x}", md5::compute(frame)), md5s.get(frame_num))
+            }
+            ExpectedChecksums::StreamMd5(_) => {
+                self.stream_md5.consume(frame);
+                return Ok(());
+            }
+        };
+
+        match expected {
+            None => Err(anyhow!("decoded more frames than expected")),
+            Some(expected) if *expected != checksum => Err(anyhow!(
+                "checksum mismatch at frame {}: expected {}, got {}",
+                frame_num,
+                expected,
+                checksum
+            )),
+            Some(_) => Ok(()),
+        }
+    }
+
+    /// Checks the next decoded `frame` of `format` and `resolution`, as written by
+    /// [`MappableHandle::read`](crate::decoder::MappableHandle::read).
+    ///
+    /// `NV12` frames are converted to `I420` if the expected checksums are MD5s, which are
+    /// computed on planar frames.
+    pub fn check_decoded_frame(
+        &mut self,
+        frame: &[u8],
+        format: DecodedFormat,
+        resolution: Resolution,
+    ) -> anyhow::Result<()> {
+        let checksummed_format = match (self.expected, format) {
+            (ExpectedChecksums::Md5(_) | ExpectedChecksums::StreamMd5(_), DecodedFormat::NV12) => {
+                PixelFormat::from(DecodedFormat::I420)
+            }
+            _ => return self.check_frame(frame),
+        };
+
+        let mut converted = vec![0; checksummed_format.packed_size(resolution)];
+        convert(
+            frame,
+            &PixelFormat::from(format).packed_layout(resolution),
+            &mut converted,
+            &checksummed_format.packed_layout(resolution),
+            Dither::None,
+        )?;
+
+        self.check_frame(&converted)
+    }
+
+    /// Checks that all the expected frames have been decoded.
+    pub fn finish(self) -> anyhow::Result<()> {
+        let num_expected = match self.expected {
+            ExpectedChecksums::Crc32(checksums) | ExpectedChecksums::Md5(checksums) => {
+                checksums.len()
+            }
+            ExpectedChecksums::StreamMd5(expected) => {
+                let checksum = format!("{:x}", self.stream_md5.compute());
+                if checksum != *expected {
+                    return Err(anyhow!(
+                        "stream checksum mismatch after {} frames: expected {}, got {}",
+                        self.frame_num,
+                        expected,
+                        checksum
+                    ));
+                }
+                return Ok(());
+            }
+        };
+
+        if self.frame_num < num_expected {
+            return Err(anyhow!(
+                "decoded {} frames, expected {}",
+                self.frame_num,
+                num_expected
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Runs the codec-specific `decoding_loop` on `decoder` and `stream`, and checks the decoded
+/// frames against `expected`.
+///
+/// `format` is the format `decoding_loop` decodes frames into, which is needed to check them
+/// against checksums computed on another format. See
+/// [`ChecksumVerifier::check_decoded_frame`].
+///
+/// Unlike [`test_decode_stream`], mismatches are returned as errors so suites with known failures
+/// can be run to completion.
+pub fn verify_decode<D, M, L>(
+    decoding_loop: L,
+    decoder: &mut D,
+    stream: &[u8],
+    format: DecodedFormat,
+    expected: &ExpectedChecksums,
+) -> anyhow::Result<()>
+where
+    D: StatelessVideoDecoder<M> + ?Sized,
+    L: FnOnce(
+        &mut D,
+        &[u8],
+        &mut dyn FnMut(Box<dyn DecodedHandle<Descriptor = M>>),
+    ) -> anyhow::Result<()>,
+{
+    let mut verifier = expected.verifier();
+    // The first error is kept, as the frames following it will most likely fail as well.
+    let mut result = Ok(());
+
+    decoding_loop(decoder, stream, &mut |handle| {
+        if result.is_err() {
+            return;
+        }
+
+        result = handle.sync().and_then(|()| {
+            let picture = handle.dyn_picture();
+            let mut mappable = picture.dyn_mappable_handle()?;
+            let mut frame = vec![0; mappable.image_size()];
+            mappable.read(&mut frame)?;
+            verifier.check_decoded_frame(&frame, format, handle.display_resolution())
+        });
+    })?;
+
+    result?;
+    verifier.finish()
+}
+
+#[cfg(test)]
+mod checksum_tests {
+    use super::*;
+
+    const FRAMES: [&[u8]; 2] = [b"first frame", b"second frame"];
+
+    fn verify(expected: &ExpectedChecksums, frames: &[&[u8]]) -> anyhow::Result<()> {
+        let mut verifier = expected.verifier();
+        for frame in frames {
+            verifier.check_frame(frame)?;
+        }
+        verifier.finish()
+    }
+
+    #[test]
+    fn per_frame_checksums() {
+        let crcs = FRAMES
+            .map(|frame| format!("{:08x}\n", crc32fast::hash(frame)))
+            .concat();
+        let md5s = FRAMES
+            .iter()
+            .enumerate()
+            .map(|(i, frame)| format!("{:X}  frame-{:04}.i420\n", md5::compute(frame), i))
+            .collect::<String>();
+
+        for expected in [
+            ExpectedChecksums::from_crc_list(&crcs),
+            ExpectedChecksums::from_md5_manifest(&md5s).unwrap(),
+        ] {
+            assert!(verify(&expected, &FRAMES).is_ok());
+            assert!(verify(&expected, &FRAMES[..1]).is_err());
+            assert!(verify(&expected, &[FRAMES[0], FRAMES[0]]).is_err());
+            assert!(verify(&expected, &[FRAMES[0], FRAMES[1], FRAMES[1]]).is_err());
+        }
+
+        assert!(ExpectedChecksums::from_md5_manifest("0123 frame.i420").is_err());
+    }
+
+    #[test]
+    fn nv12_frames() {
+        // 4x2 NV12 frame and its I420 equivalent.
+        let resolution = Resolution::from((4, 2));
+        let nv12: &[u8] = &[1, 2, 3, 4, 5, 6, 7, 8, 10, 20, 11, 21];
+        let i420: &[u8] = &[1, 2, 3, 4, 5, 6, 7, 8, 10, 11, 20, 21];
+
+        let verify_nv12 = |expected: &ExpectedChecksums| {
+            let mut verifier = expected.verifier();
+            verifier.check_decoded_frame(nv12, DecodedFormat::NV12, resolution)?;
+            verifier.finish()
+        };
+
+        // MD5s are computed on the I420 frame.
+        let md5s = format!("{:x}  frame-0000.i420\n", md5::compute(i420));
+        assert!(verify_nv12(&ExpectedChecksums::from_md5_manifest(&md5s).unwrap()).is_ok());
+        let md5 = format!("{:x}", md5::compute(i420));
+        assert!(verify_nv12(&ExpectedChecksums::from_stream_md5(&md5).unwrap()).is_ok());
+        let md5 = format!("{:x}", md5::compute(nv12));
+        assert!(verify_nv12(&ExpectedChecksums::from_stream_md5(&md5).unwrap()).is_err());
+
+        // The CRCs of the test streams of this crate are computed on the frame as decoded.
+        let crc = format!("{:08x}", crc32fast::hash(nv12));
+        assert!(verify_nv12(&ExpectedChecksums::from_crc_list(&crc)).is_ok());
+
+        // Planar frames are checked as they are.
+        let expected = ExpectedChecksums::from_md5_manifest(&md5s).unwrap();
+        let mut verifier = expected.verifier();
+        assert!(verifier
+            .check_decoded_frame(i420, DecodedFormat::I420, resolution)
+            .is_ok());
+    }
+
+    #[test]
+    fn stream_checksum() {
+        let md5 = format!("{:x}", md5::compute(FRAMES.concat()));
+        let expected = ExpectedChecksums::from_stream_md5(&format!("{} *out.yuv\n", md5)).unwrap();
+
+        assert!(verify(&expected, &FRAMES).is_ok());
+        assert!(verify(&expected, &FRAMES[..1]).is_err());
+        assert!(ExpectedChecksums::from_stream_md5("").is_err());
+    }
+}
@@ -0,0 +1,430 @@
+// Copyright 2023 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+//! Running of conformance suites, e.g. the JVT, JCT-VC or libvpx test vectors.
+//!
+//! The vectors of a suite are found with [`load_suite`] from the directory it has been extracted
+//! into, then run by [`run_suite`] which reports the vectors that fail, taking the failures known
+//! to happen with a given backend into account.
+
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+use std::fmt::Display;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Context;
+
+use super::ExpectedChecksums;
+use super::TestStream;
+
+/// How the vectors of a conformance suite are stored on disk.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SuiteLayout {
+    /// JVT H.264 suites: streams named `<name>.{264,26l,avc,h264,jsv,jvt}`, with the MD5 of their
+    /// whole output in `<name>_yuv.md5` or `<name>.md5`.
+    Jvt,
+    /// JCT-VC H.265 suites: streams named `<name>.{bin,bit}`, with the MD5 of their whole output in
+    /// `<name>_yuv.md5` or `<name>.md5`.
+    JctVc,
+    /// libvpx test vectors: IVF streams named `<name>.ivf`, with the MD5 of each frame in
+    /// `<name>.ivf.md5`.
+    Libvpx,
+}
+
+impl SuiteLayout {
+    /// Extensions of the streams of the suite.
+    fn stream_extensions(self) -> &'static [&'static str] {
+        match self {
+            SuiteLayout::Jvt => &["264", "26l", "avc", "h264", "jsv", "jvt"],
+            SuiteLayout::JctVc => &["bin", "bit"],
+            SuiteLayout::Libvpx => &["ivf"],
+        }
+    }
+
+    /// Returns the expected checksums of the stream at `path`, or `None` if the suite provides
+    /// none.
+    fn expected_checksums(self, path: &Path) -> anyhow::Result<Option<ExpectedChecksums>> {
+        let read = |path: PathBuf| match std::fs::read_to_string(&path) {
+            Ok(manifest) => Ok(Some(manifest)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("failed to read {}", path.display())),
+        };
+
+        match self {
+            SuiteLayout::Jvt | SuiteLayout::JctVc => {
+                let stem = path.with_extension("");
+                let mut yuv_md5 = stem.clone().into_os_string();
+                yuv_md5.push("_yuv.md5");
+
+                let manifest = match read(yuv_md5.into())? {
+                    Some(manifest) => Some(manifest),
+                    None => read(stem.with_extension("md5"))?,
+                };
+                manifest
+                    .map(|manifest| ExpectedChecksums::from_stream_md5(&manifest))
+                    .transpose()
+            }
+            SuiteLayout::Libvpx => {
+                let mut md5 = path.to_owned().into_os_string();
+                md5.push(".md5");
+
+                read(md5.into())?
+                    .map(|manifest| ExpectedChecksums::from_md5_manifest(&manifest))
+                    .transpose()
+            }
+        }
+    }
+}
+
+/// Where the stream of a [`ConformanceVector`] is read from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StreamSource {
+    /// A file, only read when the vector is run.
+    File(PathBuf),
+    /// A stream built into the program, e.g. a [`TestStream`].
+    Static(&'static [u8]),
+}
+
+/// A stream along with the checksums of its decoded frames.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConformanceVector {
+    /// Name of the vector, used in reports and lists of expected failures.
+    pub name: String,
+    pub source: StreamSource,
+    pub expected: ExpectedChecksums,
+}
+
+impl ConformanceVector {
+    /// Returns a vector for `test`, one of the test streams bundled with this crate.
+    pub fn from_test_stream(name: &str, test: &TestStream) -> Self {
+        Self {
+            name: name.to_owned(),
+            source: StreamSource::Static(test.stream),
+            expected: test.expected_checksums(),
+        }
+    }
+
+    /// Returns the stream to decode.
+    pub fn stream(&self) -> anyhow::Result<Cow<'static, [u8]>> {
+        match &self.source {
+            StreamSource::File(path) => std::fs::read(path)
+                .map(Cow::Owned)
+                .with_context(|| format!("failed to read {}", path.display())),
+            StreamSource::Static(stream) => Ok(Cow::Borrowed(stream)),
+        }
+    }
+}
+
+/// Appends the files of `dir` and of its subdirectories to `files`.
+fn list_files(dir: &Path, files: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+    let entries =
+        std::fs::read_dir(dir).with_context(|| format!("failed to list {}", dir.display()))?;
+
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_dir() {
+            list_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the vectors of the suite with `layout` found in `dir` and its subdirectories, sorted by
+/// name.
+///
+/// Vectors are named after their stream file, without extension. Streams without checksums are
+/// skipped.
+pub fn load_suite(dir: &Path, layout: SuiteLayout) -> anyhow::Result<Vec<ConformanceVector>> {
+    let mut files = Vec::new();
+    list_files(dir, &mut files)?;
+
+    let mut vectors = Vec::new();
+    for path in files {
+        let is_stream = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| {
+                layout
+                    .stream_extensions()
+                    .contains(&extension.to_ascii_lowercase().as_str())
+            });
+        if !is_stream {
+            continue;
+        }
+
+        let expected = match layout.expected_checksums(&path)? {
+            Some(expected) => expected,
+            None => {
+                log::warn!("no checksums found for {}, skipping", path.display());
+                continue;
+            }
+        };
+
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        vectors.push(ConformanceVector {
+            name,
+            source: StreamSource::File(path),
+            expected,
+        });
+    }
+
+    vectors.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(vectors)
+}
+
+/// Names of the vectors known to fail with a given decoder.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ExpectedFailures(BTreeSet<String>);
+
+impl ExpectedFailures {
+    /// Parses `list`, which holds one vector name per line. Empty lines and text following a `#`
+    /// are ignored.
+    pub fn parse(list: &str) -> Self {
+        Self(
+            list.lines()
+                .map(|line| line.split('#').next().unwrap_or_default().trim())
+                .filter(|name| !name.is_empty())
+                .map(str::to_owned)
+                .collect(),
+        )
+    }
+
+    /// Whether the vector named `name` is expected to fail.
+    pub fn contains(&self, name: &str) -> bool {
+        self.0.contains(name)
+    }
+}
+
+/// Outcome of running a [`ConformanceVector`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VectorResult {
+    Pass,
+    /// The vector failed for the given reason.
+    Fail(String),
+    /// The vector failed for the given reason, as expected.
+    ExpectedFail(String),
+    /// The vector was expected to fail, but passed.
+    UnexpectedPass,
+}
+
+/// Results of [`run_suite`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SuiteReport {
+    /// Name and outcome of each vector, in the order they were run.
+    pub results: Vec<(String, VectorResult)>,
+}
+
+impl SuiteReport {
+    /// Returns the number of vectors with `result`, ignoring the reason of failures.
+    fn count(&self, result: fn(&VectorResult) -> bool) -> usize {
+        self.results.iter().filter(|(_, r)| result(r)).count()
+    }
+
+    /// Whether all vectors passed, save for the expected failures.
+    ///
+    /// Unexpected passes also make the suite fail, so lists of expected failures are kept up to
+    /// date.
+    pub fn is_success(&self) -> bool {
+        self.results
+            .iter()
+            .all(|(_, result)| matches!(result, VectorResult::Pass | VectorResult::ExpectedFail(_)))
+    }
+}
+
+impl Display for SuiteReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (name, result) in &self.results {
+            match result {
+                VectorResult::Fail(reason) => writeln!(f, "FAIL {}: {}", name, reason)?,
+                VectorResult::UnexpectedPass => writeln!(f, "UNEXPECTED PASS {}", name)?,
+                VectorResult::Pass | VectorResult::ExpectedFail(_) => (),
+            }
+        }
+
+        write!(
+            f,
+            "{} passed, {} failed, {} expected failures, {} unexpected passes",
+            self.count(|r| matches!(r, VectorResult::Pass)),
+            self.count(|r| matches!(r, VectorResult::Fail(_))),
+            self.count(|r| matches!(r, VectorResult::ExpectedFail(_))),
+            self.count(|r| matches!(r, VectorResult::UnexpectedPass)),
+        )
+    }
+}
+
+/// Runs each of `vectors` by calling `decode` with its stream and expected checksums, and reports
+/// the outcome of each run according to `expected_failures`.
+///
+/// `decode` is expected to create a new decoder and pass it to
+/// [`verify_decode`](super::verify_decode).
+pub fn run_suite<F>(
+    vectors: &[ConformanceVector],
+    expected_failures: &ExpectedFailures,
+    mut decode: F,
+) -> SuiteReport
+where
+    F: FnMut(&[u8], &ExpectedChecksums) -> anyhow::Result<()>,
+{
+    let results = vectors
+        .iter()
+        .map(|vector| {
+            let outcome = vector
+                .stream()
+                .and_then(|stream| decode(&stream, &vector.expected));
+
+            let result = match (outcome, expected_failures.contains(&vector.name)) {
+                (Ok(()), false) => VectorResult::Pass,
+                (Ok(()), true) => VectorResult::UnexpectedPass,
+                (Err(e), false) => VectorResult::Fail(format!("{:#}", e)),
+                (Err(e), true) => VectorResult::ExpectedFail(format!("{:#}", e)),
+            };
+            log::info!("{}: {:?}", vector.name, result);
+
+            (vector.name.clone(), result)
+        })
+        .collect();
+
+    SuiteReport { results }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Directory removed when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path =
+                std::env::temp_dir().join(format!("cros-codecs-{}-{}", name, std::process::id()));
+            std::fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn md5(data: &[u8]) -> String {
+        format!("{:x}", md5::compute(data))
+    }
+
+    #[test]
+    fn load_jct_vc_suite() {
+        let dir = TempDir::new("jct-vc");
+        let vector_dir = dir.0.join("AMP_A_Samsung_7");
+        std::fs::create_dir(&vector_dir).unwrap();
+        std::fs::write(vector_dir.join("AMP_A_Samsung_7.bit"), b"stream").unwrap();
+        std::fs::write(vector_dir.join("AMP_A_Samsung_7_yuv.md5"), md5(b"a")).unwrap();
+        std::fs::write(dir.0.join("CAINIT_A_SHARP_4.BIT"), b"stream").unwrap();
+        std::fs::write(dir.0.join("CAINIT_A_SHARP_4.md5"), md5(b"b")).unwrap();
+        // No checksums.
+        std::fs::write(dir.0.join("NUT_A_ericsson_5.bit"), b"stream").unwrap();
+        // Not a stream.
+        std::fs::write(dir.0.join("readme.txt"), b"text").unwrap();
+
+        let vectors = load_suite(&dir.0, SuiteLayout::JctVc).unwrap();
+        assert_eq!(
+            vectors,
+            [
+                ConformanceVector {
+                    name: "AMP_A_Samsung_7".into(),
+                    source: StreamSource::File(vector_dir.join("AMP_A_Samsung_7.bit")),
+                    expected: ExpectedChecksums::StreamMd5(md5(b"a")),
+                },
+                ConformanceVector {
+                    name: "CAINIT_A_SHARP_4".into(),
+                    source: StreamSource::File(dir.0.join("CAINIT_A_SHARP_4.BIT")),
+                    expected: ExpectedChecksums::StreamMd5(md5(b"b")),
+                },
+            ]
+        );
+        assert_eq!(vectors[0].stream().unwrap(), &b"stream"[..]);
+    }
+
+    #[test]
+    fn load_libvpx_suite() {
+        let dir = TempDir::new("libvpx");
+        std::fs::write(dir.0.join("vp90-2-00-quantizer-00.webm"), b"webm").unwrap();
+        std::fs::write(dir.0.join("vp90-2-00-quantizer-01.ivf"), b"ivf").unwrap();
+        std::fs::write(
+            dir.0.join("vp90-2-00-quantizer-01.ivf.md5"),
+            format!(
+                "{}  vp90-2-00-quantizer-01-352x288-0001.i420\n{}  vp90-2-00-quantizer-01-352x288-0002.i420\n",
+                md5(b"a"),
+                md5(b"b")
+            ),
+        )
+        .unwrap();
+
+        let vectors = load_suite(&dir.0, SuiteLayout::Libvpx).unwrap();
+        assert_eq!(vectors.len(), 1);
+        assert_eq!(vectors[0].name, "vp90-2-00-quantizer-01");
+        assert_eq!(
+            vectors[0].expected,
+            ExpectedChecksums::Md5(vec![md5(b"a"), md5(b"b")])
+        );
+
+        assert!(load_suite(&dir.0.join("missing"), SuiteLayout::Libvpx).is_err());
+    }
+
+    #[test]
+    fn expected_failures() {
+        let failures = ExpectedFailures::parse(
+            "# Unsupported.\nFRExt1_Panasonic_D\n\n  CAPAMA3_Sand_F # Crashes.\n",
+        );
+        assert!(failures.contains("FRExt1_Panasonic_D"));
+        assert!(failures.contains("CAPAMA3_Sand_F"));
+        assert!(!failures.contains("# Unsupported."));
+        assert!(!failures.contains(""));
+    }
+
+    #[test]
+    fn run() {
+        let vector = |name: &str, stream: &'static [u8]| ConformanceVector {
+            name: name.into(),
+            source: StreamSource::Static(stream),
+            expected: ExpectedChecksums::Md5(vec![md5(b"good")]),
+        };
+        let vectors = [
+            vector("pass", b"good"),
+            vector("fail", b"bad"),
+            vector("expected-fail", b"bad"),
+            vector("unexpected-pass", b"good"),
+        ];
+        let expected_failures = ExpectedFailures::parse("expected-fail\nunexpected-pass\n");
+
+        // Each stream is its only decoded frame.
+        let decode = |stream: &[u8], expected: &ExpectedChecksums| {
+            let mut verifier = expected.verifier();
+            verifier.check_frame(stream)?;
+            verifier.finish()
+        };
+
+        let report = run_suite(&vectors, &expected_failures, decode);
+        assert!(!report.is_success());
+        assert_eq!(report.results[0], ("pass".into(), VectorResult::Pass));
+        assert!(matches!(report.results[1].1, VectorResult::Fail(_)));
+        assert!(matches!(report.results[2].1, VectorResult::ExpectedFail(_)));
+        assert_eq!(report.results[3].1, VectorResult::UnexpectedPass);
+        assert!(report
+            .to_string()
+            .ends_with("1 passed, 1 failed, 1 expected failures, 1 unexpected passes"));
+
+        let report = run_suite(&vectors[..1], &Default::default(), decode);
+        assert!(report.is_success());
+    }
+}
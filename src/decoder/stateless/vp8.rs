@@ -20,6 +20,7 @@ use crate::decoder::stateless::StatelessDecoder;
 use crate::decoder::stateless::StatelessDecoderBackend;
 use crate::decoder::stateless::StatelessDecoderFormatNegotiator;
 use crate::decoder::stateless::StatelessVideoDecoder;
+use crate::decoder::timestamp::TimestampMode;
 use crate::decoder::BlockingMode;
 use crate::decoder::DecodedHandle;
 use crate::decoder::DecoderEvent;
@@ -195,6 +196,7 @@ where
             .update_references(&frame.header, &decoded_handle)?;
 
         if show_frame {
+            self.timestamps.picture_decoded(timestamp, None);
             self.ready_queue.push(decoded_handle);
         }
 
@@ -257,7 +259,7 @@ where
         // change event that will allow us to keep going.
         (&mut self.ready_queue)
             .next()
-            .map(|handle| DecoderEvent::FrameReady(Box::new(handle)))
+            .map(|handle| DecoderEvent::FrameReady(Box::new(self.timestamps.frame_output(handle))))
             .or_else(|| {
                 if let DecodingState::AwaitingFormat(hdr) = &self.decoding_state {
                     Some(DecoderEvent::FormatChanged(Box::new(
@@ -282,21 +284,36 @@ where
     fn stream_info(&self) -> Option<&StreamInfo> {
         self.backend.stream_info()
     }
+
+    fn set_timestamp_mode(&mut self, mode: TimestampMode) {
+        self.timestamps.set_mode(mode);
+    }
 }
 
-#[cfg(test)]
+#[cfg(any(test, feature = "test-utils"))]
 pub mod tests {
+    #[cfg(test)]
     use crate::decoder::stateless::tests::test_decode_stream;
     use crate::decoder::stateless::tests::TestStream;
+    #[cfg(test)]
     use crate::decoder::stateless::vp8::Vp8;
+    #[cfg(test)]
     use crate::decoder::stateless::StatelessDecoder;
+    #[cfg(test)]
+    use crate::decoder::timestamp::TimestampMode;
+    #[cfg(test)]
     use crate::decoder::BlockingMode;
+    #[cfg(test)]
     use crate::utils::simple_playback_loop;
+    #[cfg(test)]
     use crate::utils::simple_playback_loop_owned_frames;
+    #[cfg(test)]
     use crate::utils::IvfIterator;
+    #[cfg(test)]
     use crate::DecodedFormat;
 
     /// Run `test` using the dummy decoder, in both blocking and non-blocking modes.
+    #[cfg(test)]
     fn test_decoder_dummy(test: &TestStream, blocking_mode: BlockingMode) {
         let decoder = StatelessDecoder::<Vp8, _>::new_dummy(blocking_mode);
 
@@ -309,6 +326,7 @@ pub mod tests {
                     &mut simple_playback_loop_owned_frames,
                     DecodedFormat::NV12,
                     blocking_mode,
+                    TimestampMode::Passthrough,
                 )
             },
             decoder,
@@ -333,4 +351,29 @@ pub mod tests {
     fn test_25fps_nonblock() {
         test_decoder_dummy(&DECODE_TEST_25FPS, BlockingMode::NonBlocking);
     }
+
+    #[test]
+    fn test_25fps_timestamps() {
+        let mut decoder = StatelessDecoder::<Vp8, _>::new_dummy(BlockingMode::Blocking);
+        let mut frames = Vec::new();
+
+        simple_playback_loop(
+            &mut decoder,
+            IvfIterator::new(DECODE_TEST_25FPS.stream),
+            &mut |handle| frames.push((handle.timestamp(), handle.duration())),
+            &mut simple_playback_loop_owned_frames,
+            DecodedFormat::NV12,
+            BlockingMode::Blocking,
+            TimestampMode::Synthesize {
+                timescale: 1_000_000,
+            },
+        )
+        .unwrap();
+
+        // VP8 does not signal the frame rate, so frames are assumed to last 1/25th of a second.
+        assert_eq!(frames.len(), DECODE_TEST_25FPS.crcs.lines().count());
+        for (i, frame) in frames.into_iter().enumerate() {
+            assert_eq!(frame, (i as u64 * 40000, Some(40000)), "at frame {}", i);
+        }
+    }
 }
@@ -32,10 +32,11 @@ impl StatelessVp8DecoderBackend for Backend {
         _: &[u8],
         _: &Segmentation,
         _: &MbLfAdjustments,
-        _: u64,
+        timestamp: u64,
     ) -> StatelessBackendResult<Self::Handle> {
         Ok(Handle {
             handle: Rc::new(RefCell::new(Default::default())),
+            timestamp,
         })
     }
 }
@@ -310,6 +310,7 @@ mod tests {
     use crate::decoder::stateless::tests::test_decode_stream;
     use crate::decoder::stateless::tests::TestStream;
     use crate::decoder::stateless::StatelessDecoder;
+    use crate::decoder::timestamp::TimestampMode;
     use crate::decoder::BlockingMode;
     use crate::utils::simple_playback_loop;
     use crate::utils::simple_playback_loop_owned_frames;
@@ -337,6 +338,7 @@ mod tests {
                     &mut simple_playback_loop_owned_frames,
                     output_format,
                     blocking_mode,
+                    TimestampMode::Passthrough,
                 )
             },
             decoder,
@@ -25,6 +25,7 @@ use crate::decoder::stateless::StatelessDecoder;
 use crate::decoder::stateless::StatelessDecoderBackend;
 use crate::decoder::stateless::StatelessDecoderFormatNegotiator;
 use crate::decoder::stateless::StatelessVideoDecoder;
+use crate::decoder::timestamp::TimestampMode;
 use crate::decoder::BlockingMode;
 use crate::decoder::DecodedHandle;
 use crate::decoder::DecoderEvent;
@@ -178,6 +179,9 @@ where
 
         let show_existing_frame = frame.header.show_existing_frame;
         if frame.header.show_frame || show_existing_frame {
+            // Frames shown with show_existing_frame keep the timestamp they were decoded with, but
+            // are presented at the time of the frame showing them.
+            self.timestamps.picture_decoded(timestamp, None);
             self.ready_queue.push(decoded_handle);
         }
 
@@ -270,7 +274,7 @@ where
         // change event that will allow us to keep going.
         (&mut self.ready_queue)
             .next()
-            .map(|handle| DecoderEvent::FrameReady(Box::new(handle)))
+            .map(|handle| DecoderEvent::FrameReady(Box::new(self.timestamps.frame_output(handle))))
             .or_else(|| {
                 if let DecodingState::AwaitingFormat(hdr) = &self.decoding_state {
                     Some(DecoderEvent::FormatChanged(Box::new(
@@ -292,21 +296,36 @@ where
     fn stream_info(&self) -> Option<&StreamInfo> {
         self.backend.stream_info()
     }
+
+    fn set_timestamp_mode(&mut self, mode: TimestampMode) {
+        self.timestamps.set_mode(mode);
+    }
 }
 
-#[cfg(test)]
+#[cfg(any(test, feature = "test-utils"))]
 pub mod tests {
+    #[cfg(test)]
     use crate::decoder::stateless::tests::test_decode_stream;
     use crate::decoder::stateless::tests::TestStream;
+    #[cfg(test)]
     use crate::decoder::stateless::vp9::Vp9;
+    #[cfg(test)]
     use crate::decoder::stateless::StatelessDecoder;
+    #[cfg(test)]
+    use crate::decoder::timestamp::TimestampMode;
+    #[cfg(test)]
     use crate::decoder::BlockingMode;
+    #[cfg(test)]
     use crate::utils::simple_playback_loop;
+    #[cfg(test)]
     use crate::utils::simple_playback_loop_owned_frames;
+    #[cfg(test)]
     use crate::utils::IvfIterator;
+    #[cfg(test)]
     use crate::DecodedFormat;
 
     /// Run `test` using the dummy decoder, in both blocking and non-blocking modes.
+    #[cfg(test)]
     fn test_decoder_dummy(test: &TestStream, blocking_mode: BlockingMode) {
         let decoder = StatelessDecoder::<Vp9, _>::new_dummy(blocking_mode);
 
@@ -319,6 +338,7 @@ pub mod tests {
                     &mut simple_playback_loop_owned_frames,
                     DecodedFormat::NV12,
                     blocking_mode,
+                    TimestampMode::Passthrough,
                 )
             },
             decoder,
@@ -367,6 +387,35 @@ pub mod tests {
         );
     }
 
+    #[test]
+    fn show_existing_frame_timestamps() {
+        let mut decoder = StatelessDecoder::<Vp9, _>::new_dummy(BlockingMode::Blocking);
+        let mut frames = Vec::new();
+
+        simple_playback_loop(
+            &mut decoder,
+            IvfIterator::new(DECODE_TEST_25FPS_SHOW_EXISTING_FRAME.stream),
+            &mut |handle| frames.push((handle.timestamp(), handle.duration())),
+            &mut simple_playback_loop_owned_frames,
+            DecodedFormat::NV12,
+            BlockingMode::Blocking,
+            TimestampMode::Synthesize {
+                timescale: 1_000_000,
+            },
+        )
+        .unwrap();
+
+        // VP9 does not signal the frame rate, so frames are assumed to last 1/25th of a second.
+        // Frames shown again with show_existing_frame follow the previous ones like any other.
+        assert_eq!(
+            frames.len(),
+            DECODE_TEST_25FPS_SHOW_EXISTING_FRAME.crcs.lines().count()
+        );
+        for (i, frame) in frames.into_iter().enumerate() {
+            assert_eq!(frame, (i as u64 * 40000, Some(40000)), "at frame {}", i);
+        }
+    }
+
     pub const DECODE_TEST_25FPS_SHOW_EXISTING_FRAME2: TestStream = TestStream {
         stream: include_bytes!("../../codec/vp9/test_data/vp90-2-10-show-existing-frame2.vp9.ivf"),
         crcs: include_str!("../../codec/vp9/test_data/vp90-2-10-show-existing-frame2.vp9.ivf.crc"),
@@ -29,11 +29,12 @@ impl StatelessVp9DecoderBackend for Backend {
         _: &Header,
         _: &[Option<Self::Handle>; NUM_REF_FRAMES],
         _: &[u8],
-        _: u64,
+        timestamp: u64,
         _: &[Segmentation; MAX_SEGMENTS],
     ) -> StatelessBackendResult<Self::Handle> {
         Ok(Handle {
             handle: Rc::new(RefCell::new(Default::default())),
+            timestamp,
         })
     }
 }
@@ -314,6 +314,7 @@ mod tests {
     use crate::decoder::stateless::tests::test_decode_stream;
     use crate::decoder::stateless::tests::TestStream;
     use crate::decoder::stateless::vp9::Segmentation;
+    use crate::decoder::timestamp::TimestampMode;
     use crate::decoder::BlockingMode;
     use crate::utils::simple_playback_loop;
     use crate::utils::simple_playback_loop_owned_frames;
@@ -340,6 +341,7 @@ mod tests {
                     &mut simple_playback_loop_owned_frames,
                     output_format,
                     blocking_mode,
+                    TimestampMode::Passthrough,
                 )
             },
             decoder,
@@ -439,6 +441,7 @@ mod tests {
                     &mut simple_playback_loop_owned_frames,
                     DecodedFormat::NV12,
                     BlockingMode::Blocking,
+                    TimestampMode::Passthrough,
                 )
             },
             decoder,
@@ -466,6 +469,7 @@ mod tests {
                     &mut simple_playback_loop_owned_frames,
                     DecodedFormat::NV12,
                     BlockingMode::NonBlocking,
+                    TimestampMode::Passthrough,
                 )
             },
             decoder,
@@ -0,0 +1,403 @@
+// Copyright 2023 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+//! Presentation timestamps of decoded frames.
+//!
+//! Decoders receive their input in decode order, but output frames in presentation order. By
+//! default the timestamp passed to `decode` is attached to the frame decoded from that input,
+//! which is correct if the client passes presentation timestamps (e.g. the PTS of a container).
+//! [`TimestampMode`] allows the decoder to compute presentation timestamps itself when the client
+//! only knows the decode order timestamps, or no timestamps at all.
+
+use std::collections::BTreeMap;
+
+use crate::decoder::DecodedHandle;
+use crate::decoder::DynHandle;
+use crate::Resolution;
+
+/// Frame rate assumed when a stream does not signal its timing information.
+const DEFAULT_FRAME_RATE: u32 = 25;
+
+/// Maximum number of pictures we keep timing information for. Pictures that are decoded but never
+/// output (e.g. when an IDR has `no_output_of_prior_pics_flag` set) are forgotten after that.
+const MAX_PENDING_PICTURES: usize = 64;
+
+/// How the decoder computes the timestamps of the frames it outputs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TimestampMode {
+    /// Frames carry the timestamp that was passed to `decode` along with their data.
+    #[default]
+    Passthrough,
+    /// The timestamps passed to `decode` are in decode order (e.g. DTS, or a counter) and are
+    /// handed out again to the frames in presentation order, i.e. each output frame receives the
+    /// smallest timestamp not yet used.
+    Reorder,
+    /// Timestamps are synthesized from the timing information of the stream (VUI and picture order
+    /// count for H.264 and H.265) and expressed in units of `1 / timescale` seconds. Streams that
+    /// do not signal their timing are assumed to run at 25 frames per second.
+    ///
+    /// The timestamps passed to `decode` must be different for each frame, but are otherwise
+    /// ignored.
+    Synthesize { timescale: u64 },
+}
+
+/// Clock of a stream, as signaled in its VUI.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct StreamClock {
+    /// Number of clock ticks per second.
+    pub(crate) time_scale: u32,
+    /// Duration of a frame, in ticks.
+    pub(crate) frame_duration: u64,
+    /// Duration corresponding to an increment of the picture order count, in ticks.
+    pub(crate) poc_duration: u64,
+}
+
+impl StreamClock {
+    /// Returns the clock to use for streams without timing information, where each frame
+    /// increments the picture order count by `pocs_per_frame`.
+    pub(crate) fn fallback(pocs_per_frame: u32) -> Self {
+        Self {
+            time_scale: DEFAULT_FRAME_RATE * pocs_per_frame,
+            frame_duration: u64::from(pocs_per_frame),
+            poc_duration: 1,
+        }
+    }
+
+    /// Converts `ticks` into units of `1 / timescale` seconds.
+    fn to_timescale(self, ticks: u64, timescale: u64) -> u64 {
+        (u128::from(ticks) * u128::from(timescale) / u128::from(self.time_scale)) as u64
+    }
+}
+
+/// Timing information of a decoded picture, for codecs that reorder pictures.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct PictureTiming {
+    /// Picture order count.
+    pub(crate) poc: i32,
+    /// Whether the picture order count restarts with this picture, e.g. on IDR pictures.
+    pub(crate) new_sequence: bool,
+    pub(crate) clock: StreamClock,
+}
+
+/// A decoded picture waiting to be output.
+struct PendingPicture {
+    timing: Option<PictureTiming>,
+    /// Index of the picture order count sequence the picture belongs to.
+    sequence: u64,
+}
+
+/// State of the last output frame, used to synthesize the timestamp of the next one.
+struct LastOutput {
+    timing: Option<PictureTiming>,
+    sequence: u64,
+    /// Timestamp of the first frame of the sequence.
+    base: u64,
+    /// Time elapsed since the first frame of the sequence, in ticks of the clock.
+    offset: u64,
+    /// Timestamp right after the end of the frame.
+    end: u64,
+}
+
+/// Keeps track of decoded pictures until they are output, and computes their timestamps according
+/// to the current [`TimestampMode`].
+#[derive(Default)]
+pub(crate) struct TimestampTracker {
+    mode: TimestampMode,
+    /// Pictures decoded and not output yet, by input timestamp.
+    pending: BTreeMap<u64, PendingPicture>,
+    /// Index of the current picture order count sequence.
+    sequence: u64,
+    last_output: Option<LastOutput>,
+}
+
+impl TimestampTracker {
+    pub(crate) fn set_mode(&mut self, mode: TimestampMode) {
+        self.mode = mode;
+        self.pending.clear();
+        self.last_output = None;
+    }
+
+    /// Records that a picture that will be output has been decoded from the input submitted with
+    /// `timestamp`. `timing` is given by codecs that can output pictures in a different order than
+    /// they are decoded.
+    pub(crate) fn picture_decoded(&mut self, timestamp: u64, timing: Option<PictureTiming>) {
+        if self.mode == TimestampMode::Passthrough {
+            return;
+        }
+
+        if timing.is_some_and(|t| t.new_sequence) {
+            self.sequence += 1;
+        }
+
+        self.pending.insert(
+            timestamp,
+            PendingPicture {
+                timing,
+                sequence: self.sequence,
+            },
+        );
+
+        while self.pending.len() > MAX_PENDING_PICTURES {
+            self.pending.pop_first();
+        }
+    }
+
+    /// Returns `handle` along with its presentation timestamp and duration. Must be called on
+    /// frames in output order.
+    pub(crate) fn frame_output<H: DecodedHandle>(&mut self, handle: H) -> TimestampedHandle<H> {
+        let (timestamp, duration) = match self.mode {
+            TimestampMode::Passthrough => (handle.timestamp(), None),
+            TimestampMode::Reorder => match self.pending.pop_first() {
+                Some((timestamp, _)) => {
+                    let duration = self.pending.first_key_value().map(|(t, _)| t - timestamp);
+                    (timestamp, duration)
+                }
+                None => (handle.timestamp(), None),
+            },
+            TimestampMode::Synthesize { timescale } => {
+                // Frames we do not know, e.g. shown again by VP9's show_existing_frame, are
+                // matched with the oldest pending picture.
+                let picture = self
+                    .pending
+                    .remove(&handle.timestamp())
+                    .or_else(|| self.pending.pop_first().map(|(_, p)| p));
+                let (timestamp, duration) = self.synthesize(picture, timescale);
+                (timestamp, Some(duration))
+            }
+        };
+
+        TimestampedHandle {
+            handle,
+            timestamp,
+            duration,
+        }
+    }
+
+    /// Computes the timestamp and duration of the next output frame from its timing information.
+    fn synthesize(&mut self, picture: Option<PendingPicture>, timescale: u64) -> (u64, u64) {
+        let (timing, sequence) = match picture {
+            Some(picture) => (picture.timing, picture.sequence),
+            None => (None, self.sequence),
+        };
+        let clock = timing
+            .map(|t| t.clock)
+            .or_else(|| {
+                self.last_output
+                    .as_ref()
+                    .and_then(|l| l.timing.map(|t| t.clock))
+            })
+            .unwrap_or_else(|| StreamClock::fallback(1));
+
+        // Frames of the same sequence are spaced according to their picture order count. Other
+        // frames start right after the previous one.
+        let (base, offset) = match (&self.last_output, timing) {
+            (Some(last), Some(timing)) => match last.timing {
+                Some(last_timing)
+                    if last.sequence == sequence
+                        && last_timing.clock == clock
+                        && timing.poc > last_timing.poc =>
+                {
+                    let pocs = (timing.poc - last_timing.poc) as u64;
+                    (last.base, last.offset + pocs * clock.poc_duration)
+                }
+                _ => (last.end, 0),
+            },
+            (Some(last), None) => (last.end, 0),
+            (None, _) => (0, 0),
+        };
+
+        let timestamp = base + clock.to_timescale(offset, timescale);
+        let end = base + clock.to_timescale(offset + clock.frame_duration, timescale);
+
+        self.last_output = Some(LastOutput {
+            timing,
+            sequence,
+            base,
+            offset,
+            end,
+        });
+
+        (timestamp, end - timestamp)
+    }
+}
+
+/// A decoded frame along with its presentation timestamp and duration, as computed by a
+/// [`TimestampTracker`].
+pub(crate) struct TimestampedHandle<H> {
+    handle: H,
+    timestamp: u64,
+    duration: Option<u64>,
+}
+
+impl<H: DecodedHandle> DecodedHandle for TimestampedHandle<H> {
+    type Descriptor = H::Descriptor;
+
+    fn dyn_picture<'a>(&'a self) -> Box<dyn DynHandle + 'a> {
+        self.handle.dyn_picture()
+    }
+
+    fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    fn duration(&self) -> Option<u64> {
+        self.duration
+    }
+
+    fn coded_resolution(&self) -> Resolution {
+        self.handle.coded_resolution()
+    }
+
+    fn display_resolution(&self) -> Resolution {
+        self.handle.display_resolution()
+    }
+
+    fn is_ready(&self) -> bool {
+        self.handle.is_ready()
+    }
+
+    fn sync(&self) -> anyhow::Result<()> {
+        self.handle.sync()
+    }
+
+    fn resource(&self) -> std::cell::Ref<'_, Self::Descriptor> {
+        self.handle.resource()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::backend::dummy::Handle;
+
+    fn handle(timestamp: u64) -> Handle {
+        Handle {
+            handle: Rc::new(RefCell::new(Default::default())),
+            timestamp,
+        }
+    }
+
+    fn timing(poc: i32, new_sequence: bool, clock: StreamClock) -> Option<PictureTiming> {
+        Some(PictureTiming {
+            poc,
+            new_sequence,
+            clock,
+        })
+    }
+
+    /// Decodes pictures given as `(input timestamp, timing)` in decode order, then outputs them
+    /// in the order of `output` and returns their timestamps and durations.
+    fn run(
+        tracker: &mut TimestampTracker,
+        decoded: &[(u64, Option<PictureTiming>)],
+        output: &[u64],
+    ) -> Vec<(u64, Option<u64>)> {
+        for (timestamp, timing) in decoded {
+            tracker.picture_decoded(*timestamp, *timing);
+        }
+
+        output
+            .iter()
+            .map(|&t| {
+                let handle = tracker.frame_output(handle(t));
+                (handle.timestamp(), handle.duration())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn passthrough() {
+        let mut tracker = TimestampTracker::default();
+        let frames = run(&mut tracker, &[(0, None), (1, None), (2, None)], &[0, 2, 1]);
+        assert_eq!(frames, vec![(0, None), (2, None), (1, None)]);
+    }
+
+    #[test]
+    fn reorder() {
+        let mut tracker = TimestampTracker::default();
+        tracker.set_mode(TimestampMode::Reorder);
+
+        // I P B P, with DTS in decode order.
+        let decoded = [(0, None), (40, None), (80, None), (120, None)];
+        let frames = run(&mut tracker, &decoded, &[0, 80, 40, 120]);
+        assert_eq!(
+            frames,
+            vec![(0, Some(40)), (40, Some(40)), (80, Some(40)), (120, None)]
+        );
+    }
+
+    #[test]
+    fn synthesize() {
+        let mut tracker = TimestampTracker::default();
+        tracker.set_mode(TimestampMode::Synthesize { timescale: 1000 });
+
+        // 50 ticks per second, 2 ticks per frame, i.e. 25 fps.
+        let clock = StreamClock {
+            time_scale: 50,
+            frame_duration: 2,
+            poc_duration: 1,
+        };
+
+        // I P B P, then a new IDR with a leading picture.
+        let decoded = [
+            (0, timing(0, true, clock)),
+            (1, timing(4, false, clock)),
+            (2, timing(2, false, clock)),
+            (3, timing(6, false, clock)),
+            (4, timing(0, true, clock)),
+            (5, timing(-2, false, clock)),
+        ];
+        let frames = run(&mut tracker, &decoded, &[0, 2, 1, 3, 5, 4]);
+        assert_eq!(
+            frames,
+            vec![
+                (0, Some(40)),
+                (40, Some(40)),
+                (80, Some(40)),
+                (120, Some(40)),
+                (160, Some(40)),
+                (200, Some(40)),
+            ]
+        );
+    }
+
+    #[test]
+    fn synthesize_without_timing() {
+        let mut tracker = TimestampTracker::default();
+        tracker.set_mode(TimestampMode::Synthesize {
+            timescale: 1_000_000,
+        });
+
+        // Unknown frames are matched with the oldest pending picture.
+        let frames = run(&mut tracker, &[(10, None), (20, None)], &[10, 5, 30]);
+        assert_eq!(
+            frames,
+            vec![(0, Some(40000)), (40000, Some(40000)), (80000, Some(40000))]
+        );
+    }
+
+    #[test]
+    fn synthesize_ntsc() {
+        let mut tracker = TimestampTracker::default();
+        tracker.set_mode(TimestampMode::Synthesize { timescale: 90000 });
+
+        // 29.97 fps, which does not translate into an integer number of milliseconds.
+        let clock = StreamClock {
+            time_scale: 60000,
+            frame_duration: 2002,
+            poc_duration: 1001,
+        };
+        let decoded = (0..4)
+            .map(|i| (i, timing(i as i32 * 2, i == 0, clock)))
+            .collect::<Vec<_>>();
+        let frames = run(&mut tracker, &decoded, &[0, 1, 2, 3]);
+        assert_eq!(
+            frames.iter().map(|f| f.0).collect::<Vec<_>>(),
+            vec![0, 3003, 6006, 9009]
+        );
+    }
+}
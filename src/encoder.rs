@@ -0,0 +1,42 @@
+// Copyright 2023 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+//! Raw frame encoding.
+//!
+//! An encoder turns a sequence of raw frames into an encoded stream. This module provides encoders
+//! for various codecs and backends.
+//!
+//! At the moment, only a [stateless] encoder interface is provided. The [ratecontrol] module
+//! decides the quantization of frames to follow a bitrate.
+
+pub mod ratecontrol;
+pub mod stateless;
+
+/// Properties of a frame submitted for encoding, that are carried along to its coded output.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FrameMetadata {
+    /// Timestamp of the frame, returned unchanged along with its coded data.
+    pub timestamp: u64,
+    /// Whether the frame must be encoded as a key frame, regardless of its position in the group
+    /// of pictures.
+    pub force_keyframe: bool,
+}
+
+/// Coded data produced by the encoder for one frame.
+#[derive(Debug)]
+pub struct CodedBitstreamBuffer {
+    /// Metadata of the frame this data has been produced from.
+    pub metadata: FrameMetadata,
+    /// Whether the frame has been encoded as a key frame, i.e. decoding can start from it.
+    pub keyframe: bool,
+    /// The coded data.
+    pub bitstream: Vec<u8>,
+}
+
+/// Events that can be retrieved using the `next_event` method of an encoder.
+#[derive(Debug)]
+pub enum EncoderEvent {
+    /// The next frame in decoding order has been encoded.
+    FrameEncoded(CodedBitstreamBuffer),
+}
@@ -0,0 +1,761 @@
+// Copyright 2023 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+//! Rate control for encoders.
+//!
+//! [`RateController`] decides the quantization parameter of each frame so the produced stream
+//! follows a bitrate. Its constraints come from the coded picture buffer of the decoder, modeled as
+//! a leaky bucket like the hypothetical reference decoder of H.264 (Annex C): bits enter the
+//! buffer at a constant rate, and each frame leaves it at once when it is decoded, one frame
+//! interval after the previous one. A stream is compliant if a frame never leaves before all its
+//! bits have arrived (underflow), and, for constant bitrate streams, if the buffer never has to
+//! hold more bits than its size (overflow).
+//!
+//! The controller is codec-agnostic. Its rate model assumes the quantizer step size doubles every
+//! 6 QP, as it does in H.264 and H.265: other codecs should map their quantizer index accordingly.
+
+use anyhow::anyhow;
+
+/// Increase of the QP doubling the quantizer step size.
+const QP_PER_DOUBLING: f64 = 6.0;
+
+/// Number of frames over which the deviation of the buffer level from its target is corrected.
+const BUFFER_CORRECTION_FRAMES: f64 = 10.0;
+
+/// Number of frames over which the deviation from the average bitrate is corrected in VBR mode.
+const VBR_CORRECTION_FRAMES: f64 = 150.0;
+
+/// Fraction of the bits in the buffer a frame may use, so the following frames still get some.
+const MAX_BUFFER_USAGE: f64 = 0.75;
+
+/// Weight of the latest frame in the running estimates of the rate model.
+const MODEL_UPDATE_WEIGHT: f64 = 0.5;
+
+/// How the bitrate of the stream is controlled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RateControlMode {
+    /// Every frame uses a fixed QP, and the bitrate is not constrained.
+    ConstantQp {
+        /// QP of inter frames.
+        qp: u32,
+        /// QP of key frames.
+        keyframe_qp: u32,
+    },
+    /// The stream is sent at `bitrate` bits per second. Filler data must be added to frames that
+    /// are too small, as the decoder buffer of `buffer_size` bits would overflow otherwise.
+    ConstantBitrate { bitrate: u64, buffer_size: u64 },
+    /// The stream averages `bitrate` bits per second, with more bits given to complex frames.
+    /// It is sent at up to `max_bitrate` bits per second into a decoder buffer of `buffer_size`
+    /// bits.
+    VariableBitrate {
+        bitrate: u64,
+        max_bitrate: u64,
+        buffer_size: u64,
+    },
+}
+
+/// Configuration of a [`RateController`].
+#[derive(Clone, Debug)]
+pub struct RateControlConfig {
+    /// How the bitrate is controlled.
+    pub mode: RateControlMode,
+    /// Frame rate of the stream, as a `(numerator, denominator)` number of frames per second.
+    pub framerate: (u32, u32),
+    /// Smallest QP the controller may choose.
+    pub min_qp: u32,
+    /// Largest QP the controller may choose.
+    pub max_qp: u32,
+    /// QP of the first frame of each type, before the rate model has been fed with coded sizes.
+    /// It should be coarse enough for the first key frame to fit in half of the buffer.
+    pub initial_qp: u32,
+    /// Size of key frames relative to the average size of a frame.
+    pub keyframe_size_ratio: u32,
+    /// Whether inter frames may be skipped when even the largest QP would make them underflow the
+    /// decoder buffer.
+    pub frame_skipping: bool,
+}
+
+impl Default for RateControlConfig {
+    fn default() -> Self {
+        Self {
+            mode: RateControlMode::ConstantQp {
+                qp: 26,
+                keyframe_qp: 26,
+            },
+            framerate: (30, 1),
+            min_qp: 0,
+            max_qp: 51,
+            initial_qp: 26,
+            keyframe_size_ratio: 4,
+            frame_skipping: true,
+        }
+    }
+}
+
+impl RateControlConfig {
+    /// Checks that the configuration can be followed.
+    fn validate(&self) -> anyhow::Result<()> {
+        let (num, den) = self.framerate;
+        if num == 0 || den == 0 {
+            return Err(anyhow!("invalid frame rate {}/{}", num, den));
+        }
+        if self.min_qp > self.max_qp {
+            return Err(anyhow!(
+                "invalid QP range [{}, {}]",
+                self.min_qp,
+                self.max_qp
+            ));
+        }
+        if self.keyframe_size_ratio == 0 {
+            return Err(anyhow!("key frame size ratio must be at least 1"));
+        }
+
+        let qp_range = self.min_qp..=self.max_qp;
+        let (bitrate, max_bitrate, buffer_size) = match self.mode {
+            RateControlMode::ConstantQp { qp, keyframe_qp } => {
+                if !qp_range.contains(&qp) || !qp_range.contains(&keyframe_qp) {
+                    return Err(anyhow!(
+                        "QPs {} and {} must be in [{}, {}]",
+                        qp,
+                        keyframe_qp,
+                        self.min_qp,
+                        self.max_qp
+                    ));
+                }
+                return Ok(());
+            }
+            RateControlMode::ConstantBitrate {
+                bitrate,
+                buffer_size,
+            } => (bitrate, bitrate, buffer_size),
+            RateControlMode::VariableBitrate {
+                bitrate,
+                max_bitrate,
+                buffer_size,
+            } => (bitrate, max_bitrate, buffer_size),
+        };
+
+        if !qp_range.contains(&self.initial_qp) {
+            return Err(anyhow!(
+                "initial QP {} must be in [{}, {}]",
+                self.initial_qp,
+                self.min_qp,
+                self.max_qp
+            ));
+        }
+        if bitrate == 0 || max_bitrate < bitrate {
+            return Err(anyhow!(
+                "invalid bitrates {} (max {})",
+                bitrate,
+                max_bitrate
+            ));
+        }
+        // The buffer must be able to hold at least one average key frame.
+        let keyframe_bits =
+            bitrate as f64 * f64::from(den) / f64::from(num) * f64::from(self.keyframe_size_ratio);
+        if (buffer_size as f64) < keyframe_bits {
+            return Err(anyhow!(
+                "buffer of {} bits is too small for key frames of {} bits",
+                buffer_size,
+                keyframe_bits
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// What to do with a frame, as decided by [`RateController::begin_frame`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameDecision {
+    /// Encode the frame with the given QP. `target_bits` is the size the QP is expected to
+    /// produce, if the bitrate is constrained.
+    Encode { qp: u32, target_bits: Option<u64> },
+    /// Do not encode the frame, which would not fit in the decoder buffer.
+    Skip,
+}
+
+/// Running estimates for one type of frame.
+#[derive(Clone, Copy, Debug, Default)]
+struct RateModel {
+    /// Coded size of a frame of unit complexity with a unit quantizer step, i.e. the coded size
+    /// of a frame is `scale * complexity / qstep(qp)`.
+    scale: Option<f64>,
+    /// Sum of the complexities of the frames.
+    total_complexity: f64,
+    /// Number of frames the model has been updated with.
+    num_frames: u32,
+}
+
+impl RateModel {
+    fn update(&mut self, complexity: f64, scale: Option<f64>) {
+        self.total_complexity += complexity;
+        self.num_frames += 1;
+
+        if let Some(new) = scale {
+            self.scale = Some(match self.scale {
+                Some(scale) => scale + MODEL_UPDATE_WEIGHT * (new - scale),
+                None => new,
+            });
+        }
+    }
+
+    /// Returns the average complexity of the frames seen so far.
+    fn average_complexity(&self) -> Option<f64> {
+        (self.num_frames > 0).then(|| self.total_complexity / f64::from(self.num_frames))
+    }
+}
+
+/// A frame being encoded.
+#[derive(Clone, Copy, Debug)]
+struct CurrentFrame {
+    keyframe: bool,
+    complexity: f64,
+    qp: u32,
+}
+
+/// Returns the quantizer step size corresponding to `qp`, relative to the one of QP 0.
+fn qstep(qp: u32) -> f64 {
+    (f64::from(qp) / QP_PER_DOUBLING).exp2()
+}
+
+/// Decides the QP of each frame of a stream.
+///
+/// For each frame, [`begin_frame`] is called with the type of the frame and an estimate of its
+/// complexity, e.g. the sum of absolute differences with its prediction. It returns the QP to
+/// encode the frame with. Once the frame is encoded, [`end_frame`] is called with its size to
+/// update the model of the encoder and the state of the decoder buffer.
+///
+/// [`begin_frame`]: RateController::begin_frame
+/// [`end_frame`]: RateController::end_frame
+pub struct RateController {
+    config: RateControlConfig,
+    /// Bits of a frame at the average bitrate.
+    bits_per_frame: f64,
+    /// Bits entering the decoder buffer during a frame interval.
+    buffer_fill_per_frame: f64,
+    /// Size of the decoder buffer, in bits.
+    buffer_size: f64,
+    /// Number of bits in the decoder buffer right before the next frame is removed from it.
+    buffer_fullness: f64,
+    /// Bits that can be spent above the average bitrate in VBR mode, negative if the stream is
+    /// above its average bitrate.
+    vbr_budget: f64,
+    /// Models for key frames and inter frames.
+    models: [RateModel; 2],
+    current: Option<CurrentFrame>,
+}
+
+impl RateController {
+    /// Creates a new controller following `config`. The decoder buffer starts half full, i.e.
+    /// the decoder waits for half of its size to be received before removing the first frame.
+    pub fn new(config: RateControlConfig) -> anyhow::Result<Self> {
+        config.validate()?;
+
+        let (num, den) = config.framerate;
+        let frame_duration = f64::from(den) / f64::from(num);
+        let (bitrate, fill_rate, buffer_size) = match config.mode {
+            RateControlMode::ConstantQp { .. } => (0, 0, 0),
+            RateControlMode::ConstantBitrate {
+                bitrate,
+                buffer_size,
+            } => (bitrate, bitrate, buffer_size),
+            RateControlMode::VariableBitrate {
+                bitrate,
+                max_bitrate,
+                buffer_size,
+            } => (bitrate, max_bitrate, buffer_size),
+        };
+
+        Ok(Self {
+            config,
+            bits_per_frame: bitrate as f64 * frame_duration,
+            buffer_fill_per_frame: fill_rate as f64 * frame_duration,
+            buffer_size: buffer_size as f64,
+            buffer_fullness: buffer_size as f64 / 2.0,
+            vbr_budget: 0.0,
+            models: Default::default(),
+            current: None,
+        })
+    }
+
+    /// Returns the number of bits in the decoder buffer right before the next frame is removed
+    /// from it. This is zero in constant QP mode.
+    pub fn buffer_fullness(&self) -> u64 {
+        self.buffer_fullness.max(0.0) as u64
+    }
+
+    /// Returns the number of bits the next frame should use, given the type and complexity of
+    /// the frame.
+    fn target_bits(&self, keyframe: bool, complexity: f64) -> f64 {
+        let model = &self.models[usize::from(!keyframe)];
+        let mut target = self.bits_per_frame;
+        if keyframe {
+            target *= f64::from(self.config.keyframe_size_ratio);
+        }
+
+        match self.config.mode {
+            RateControlMode::ConstantQp { .. } => unreachable!(),
+            RateControlMode::ConstantBitrate { .. } => {
+                target +=
+                    (self.buffer_fullness - self.buffer_size / 2.0) / BUFFER_CORRECTION_FRAMES;
+            }
+            RateControlMode::VariableBitrate { .. } => {
+                // Frames more complex than average get more bits, up to the peak bitrate.
+                if let Some(average) = model.average_complexity() {
+                    let peak_ratio = self.buffer_fill_per_frame / self.bits_per_frame;
+                    target *= (complexity / average).clamp(1.0 / peak_ratio, peak_ratio);
+                }
+                target += self.vbr_budget / VBR_CORRECTION_FRAMES;
+            }
+        }
+
+        target.min(self.buffer_fullness * MAX_BUFFER_USAGE).max(1.0)
+    }
+
+    /// Returns the QP expected to code a frame of `complexity` in `bits` bits.
+    fn qp_for_bits(&self, keyframe: bool, complexity: f64, bits: f64) -> u32 {
+        let type_index = usize::from(!keyframe);
+        // Fall back to the model of the other type of frames if this one has none yet.
+        let scale = self.models[type_index]
+            .scale
+            .or(self.models[1 - type_index].scale);
+
+        let qp = match scale {
+            Some(scale) => (QP_PER_DOUBLING * (scale * complexity / bits).log2()).round(),
+            None => f64::from(self.config.initial_qp),
+        };
+
+        qp.clamp(f64::from(self.config.min_qp), f64::from(self.config.max_qp)) as u32
+    }
+
+    /// Returns the expected size of a frame coded with `qp`, if the model can tell.
+    fn predicted_bits(&self, keyframe: bool, complexity: f64, qp: u32) -> Option<f64> {
+        self.models[usize::from(!keyframe)]
+            .scale
+            .map(|scale| scale * complexity / qstep(qp))
+    }
+
+    /// Moves the decoder buffer to the removal time of the next frame, after the removal of a
+    /// frame of `bits` bits.
+    ///
+    /// Returns the number of filler bits that must be added to the frame to prevent the buffer
+    /// from overflowing.
+    fn advance(&mut self, bits: f64) -> u64 {
+        if matches!(self.config.mode, RateControlMode::ConstantQp { .. }) {
+            return 0;
+        }
+
+        self.buffer_fullness -= bits;
+        if self.buffer_fullness < 0.0 {
+            log::warn!("decoder buffer underflow by {} bits", -self.buffer_fullness);
+        }
+
+        self.vbr_budget += self.bits_per_frame - bits;
+        self.buffer_fullness += self.buffer_fill_per_frame;
+
+        let excess = self.buffer_fullness - self.buffer_size;
+        if excess <= 0.0 {
+            return 0;
+        }
+
+        self.buffer_fullness = self.buffer_size;
+        match self.config.mode {
+            RateControlMode::ConstantBitrate { .. } => {
+                // The stream must fill the channel, so the excess bits become filler data.
+                excess.ceil() as u64
+            }
+            // The transmission pauses while the buffer is full.
+            _ => 0,
+        }
+    }
+
+    /// Decides how to encode the next frame, which is a key frame if `keyframe` is set.
+    /// `complexity` is a positive estimate of how costly the frame is to code, in any unit as long
+    /// as it is consistent across frames.
+    ///
+    /// If the frame is to be encoded, [`end_frame`](Self::end_frame) must be called with its
+    /// coded size before the next call.
+    pub fn begin_frame(&mut self, keyframe: bool, complexity: f64) -> FrameDecision {
+        let complexity = if complexity > 0.0 { complexity } else { 1.0 };
+
+        if let RateControlMode::ConstantQp { qp, keyframe_qp } = self.config.mode {
+            let qp = if keyframe { keyframe_qp } else { qp };
+            self.current = Some(CurrentFrame {
+                keyframe,
+                complexity,
+                qp,
+            });
+            return FrameDecision::Encode {
+                qp,
+                target_bits: None,
+            };
+        }
+
+        if !keyframe && self.config.frame_skipping {
+            // Frames that would not fit even in a full buffer are encoded anyway, as waiting for
+            // the buffer to fill up would not help. Skipped frames cannot carry filler data
+            // either, so they must not make the buffer overflow.
+            let max_bits = self.buffer_fullness * MAX_BUFFER_USAGE;
+            let can_wait = self.buffer_fullness + self.buffer_fill_per_frame <= self.buffer_size;
+            let smallest = self.predicted_bits(keyframe, complexity, self.config.max_qp);
+            if can_wait
+                && smallest.is_some_and(|bits| {
+                    bits > max_bits && bits <= self.buffer_size * MAX_BUFFER_USAGE
+                })
+            {
+                log::debug!("skipping frame to avoid a decoder buffer underflow");
+                self.advance(0.0);
+                self.current = None;
+                return FrameDecision::Skip;
+            }
+        }
+
+        let target = self.target_bits(keyframe, complexity);
+        let qp = self.qp_for_bits(keyframe, complexity, target);
+        self.current = Some(CurrentFrame {
+            keyframe,
+            complexity,
+            qp,
+        });
+
+        FrameDecision::Encode {
+            qp,
+            target_bits: Some(target as u64),
+        }
+    }
+
+    /// Updates the controller with the size of the frame started by the last call to
+    /// [`begin_frame`](Self::begin_frame), in bits.
+    ///
+    /// Returns the number of bits of filler data to append to the frame, which is only non-zero
+    /// in constant bitrate mode.
+    pub fn end_frame(&mut self, coded_bits: u64) -> anyhow::Result<u64> {
+        let frame = self
+            .current
+            .take()
+            .ok_or_else(|| anyhow!("no frame is being encoded"))?;
+
+        // Empty frames tell nothing about the scale of the model.
+        let scale =
+            (coded_bits > 0).then(|| coded_bits as f64 * qstep(frame.qp) / frame.complexity);
+        self.models[usize::from(!frame.keyframe)].update(frame.complexity, scale);
+
+        Ok(self.advance(coded_bits as f64))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAMERATE: u32 = 30;
+
+    /// A synthetic encoder, producing frames of `scale * complexity / qstep(qp)` bits, within
+    /// +/- 20% of deterministic noise.
+    struct SyntheticEncoder {
+        keyframe_scale: f64,
+        inter_scale: f64,
+        seed: u32,
+    }
+
+    impl SyntheticEncoder {
+        fn new() -> Self {
+            Self {
+                keyframe_scale: 4e6,
+                inter_scale: 1e6,
+                seed: 1,
+            }
+        }
+
+        fn encode(&mut self, keyframe: bool, complexity: f64, qp: u32) -> u64 {
+            // xorshift32
+            self.seed ^= self.seed << 13;
+            self.seed ^= self.seed >> 17;
+            self.seed ^= self.seed << 5;
+            let noise = 0.8 + 0.4 * f64::from(self.seed) / f64::from(u32::MAX);
+
+            let scale = if keyframe {
+                self.keyframe_scale
+            } else {
+                self.inter_scale
+            };
+
+            (scale * complexity / qstep(qp) * noise) as u64
+        }
+    }
+
+    /// Outcome of a simulation.
+    #[derive(Debug, Default)]
+    struct Simulation {
+        /// Size of each frame including its filler data, or `None` if it was skipped.
+        sizes: Vec<Option<u64>>,
+        qps: Vec<u32>,
+    }
+
+    impl Simulation {
+        fn total_bits(&self) -> u64 {
+            self.sizes.iter().flatten().sum()
+        }
+
+        fn num_skipped(&self) -> usize {
+            self.sizes.iter().filter(|s| s.is_none()).count()
+        }
+
+        /// Checks the stream against a leaky bucket of `buffer_size` bits filled at `rate` bits
+        /// per second, starting half full.
+        fn check_buffer(&self, rate: u64, buffer_size: u64, cbr: bool) {
+            let fill = rate as f64 / f64::from(FRAMERATE);
+            let mut fullness = buffer_size as f64 / 2.0;
+
+            for (i, size) in self.sizes.iter().enumerate() {
+                fullness -= size.unwrap_or(0) as f64;
+                assert!(fullness >= 0.0, "underflow at frame {}", i);
+
+                fullness += fill;
+                if cbr {
+                    assert!(
+                        fullness <= buffer_size as f64 + 1.0,
+                        "overflow at frame {}",
+                        i
+                    );
+                } else {
+                    fullness = fullness.min(buffer_size as f64);
+                }
+            }
+        }
+    }
+
+    /// Runs `controller` over a trace of frame complexities, with a key frame every `gop_size`
+    /// frames.
+    fn simulate(
+        controller: &mut RateController,
+        complexities: &[f64],
+        gop_size: usize,
+    ) -> Simulation {
+        let mut encoder = SyntheticEncoder::new();
+        let mut simulation = Simulation::default();
+
+        for (i, &complexity) in complexities.iter().enumerate() {
+            let keyframe = i % gop_size == 0;
+
+            match controller.begin_frame(keyframe, complexity) {
+                FrameDecision::Encode { qp, .. } => {
+                    let bits = encoder.encode(keyframe, complexity, qp);
+                    let filler = controller.end_frame(bits).unwrap();
+                    simulation.sizes.push(Some(bits + filler));
+                    simulation.qps.push(qp);
+                }
+                FrameDecision::Skip => simulation.sizes.push(None),
+            }
+        }
+
+        simulation
+    }
+
+    /// A trace alternating between calm and busy scenes, with a scene change every 50 frames.
+    fn scene_trace(num_frames: usize) -> Vec<f64> {
+        (0..num_frames)
+            .map(|i| {
+                let base = if (i / 50) % 2 == 0 { 1.0 } else { 3.0 };
+                base * (1.0 + 0.3 * (i as f64 / 7.0).sin())
+            })
+            .collect()
+    }
+
+    fn config(mode: RateControlMode) -> RateControlConfig {
+        RateControlConfig {
+            mode,
+            framerate: (FRAMERATE, 1),
+            // Coarse enough for the first key frame to fit in half of the smallest buffer.
+            initial_qp: 40,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn constant_qp() {
+        let mut controller = RateController::new(config(RateControlMode::ConstantQp {
+            qp: 30,
+            keyframe_qp: 24,
+        }))
+        .unwrap();
+
+        let simulation = simulate(&mut controller, &scene_trace(60), 30);
+
+        assert_eq!(simulation.num_skipped(), 0);
+        for (i, &qp) in simulation.qps.iter().enumerate() {
+            assert_eq!(qp, if i % 30 == 0 { 24 } else { 30 });
+        }
+        assert_eq!(controller.buffer_fullness(), 0);
+    }
+
+    #[test]
+    fn constant_bitrate() {
+        const BITRATE: u64 = 500_000;
+        const BUFFER_SIZE: u64 = BITRATE;
+        const NUM_FRAMES: usize = 600;
+
+        let mut controller = RateController::new(config(RateControlMode::ConstantBitrate {
+            bitrate: BITRATE,
+            buffer_size: BUFFER_SIZE,
+        }))
+        .unwrap();
+
+        let simulation = simulate(&mut controller, &scene_trace(NUM_FRAMES), 60);
+        simulation.check_buffer(BITRATE, BUFFER_SIZE, true);
+        assert_eq!(simulation.num_skipped(), 0);
+
+        let bitrate = simulation.total_bits() * u64::from(FRAMERATE) / NUM_FRAMES as u64;
+        assert!(
+            bitrate.abs_diff(BITRATE) < BITRATE / 20,
+            "bitrate {}",
+            bitrate
+        );
+
+        // Busy scenes need a coarser quantization.
+        let average_qp = |range: std::ops::Range<usize>| {
+            let len = range.len() as u32;
+            simulation.qps[range].iter().sum::<u32>() / len
+        };
+        assert!(average_qp(60..100) > average_qp(10..50));
+    }
+
+    #[test]
+    fn variable_bitrate() {
+        const BITRATE: u64 = 500_000;
+        const MAX_BITRATE: u64 = 1_000_000;
+        const BUFFER_SIZE: u64 = MAX_BITRATE;
+        const NUM_FRAMES: usize = 600;
+
+        let mut controller = RateController::new(config(RateControlMode::VariableBitrate {
+            bitrate: BITRATE,
+            max_bitrate: MAX_BITRATE,
+            buffer_size: BUFFER_SIZE,
+        }))
+        .unwrap();
+
+        let simulation = simulate(&mut controller, &scene_trace(NUM_FRAMES), 60);
+        simulation.check_buffer(MAX_BITRATE, BUFFER_SIZE, false);
+        assert_eq!(simulation.num_skipped(), 0);
+
+        let bitrate = simulation.total_bits() * u64::from(FRAMERATE) / NUM_FRAMES as u64;
+        assert!(
+            bitrate.abs_diff(BITRATE) < BITRATE / 10,
+            "bitrate {}",
+            bitrate
+        );
+
+        // Busy scenes get more bits than calm ones.
+        let scene_bits =
+            |range: std::ops::Range<usize>| simulation.sizes[range].iter().flatten().sum::<u64>();
+        assert!(scene_bits(51..100) > scene_bits(1..50) * 3 / 2);
+    }
+
+    #[test]
+    fn frame_skipping() {
+        const BITRATE: u64 = 200_000;
+        const BUFFER_SIZE: u64 = BITRATE / 2;
+
+        // A scene too complex to fit in the bitrate even at the largest QP.
+        let complexities = (0..120)
+            .map(|i| if (30..90).contains(&i) { 20.0 } else { 1.0 })
+            .collect::<Vec<_>>();
+
+        for frame_skipping in [true, false] {
+            let mut controller = RateController::new(RateControlConfig {
+                frame_skipping,
+                ..config(RateControlMode::ConstantBitrate {
+                    bitrate: BITRATE,
+                    buffer_size: BUFFER_SIZE,
+                })
+            })
+            .unwrap();
+
+            let simulation = simulate(&mut controller, &complexities, 1000);
+
+            if frame_skipping {
+                simulation.check_buffer(BITRATE, BUFFER_SIZE, true);
+                assert!(simulation.num_skipped() > 0);
+                // The calm frames at the end are all encoded.
+                assert!(simulation.sizes[100..].iter().all(|s| s.is_some()));
+            } else {
+                assert_eq!(simulation.num_skipped(), 0);
+                assert!(simulation.qps[40..90].iter().all(|&qp| qp == 51));
+            }
+        }
+    }
+
+    #[test]
+    fn keyframe_budget() {
+        const BITRATE: u64 = 300_000;
+
+        let mut controller = RateController::new(config(RateControlMode::ConstantBitrate {
+            bitrate: BITRATE,
+            buffer_size: BITRATE,
+        }))
+        .unwrap();
+
+        let simulation = simulate(&mut controller, &[1.0; 300], 30);
+        simulation.check_buffer(BITRATE, BITRATE, true);
+
+        // Key frames get a larger share of the bitrate, paid for by the following frames.
+        let bits_per_frame = BITRATE / u64::from(FRAMERATE);
+        for gop in simulation.sizes.chunks(30).skip(1) {
+            let keyframe = gop[0].unwrap();
+            assert!(
+                keyframe > 2 * bits_per_frame,
+                "key frame of {} bits",
+                keyframe
+            );
+            let following = gop[1..6].iter().flatten().sum::<u64>();
+            assert!(following < 5 * bits_per_frame, "{} bits", following);
+        }
+    }
+
+    #[test]
+    fn end_frame_without_frame() {
+        let mut controller = RateController::new(Default::default()).unwrap();
+
+        assert!(controller.end_frame(1000).is_err());
+        controller.begin_frame(true, 1.0);
+        assert!(controller.end_frame(1000).is_ok());
+        assert!(controller.end_frame(1000).is_err());
+    }
+
+    #[test]
+    fn invalid_config() {
+        for config in [
+            RateControlConfig {
+                framerate: (30, 0),
+                ..Default::default()
+            },
+            RateControlConfig {
+                min_qp: 10,
+                max_qp: 5,
+                ..Default::default()
+            },
+            config(RateControlMode::ConstantQp {
+                qp: 52,
+                keyframe_qp: 26,
+            }),
+            config(RateControlMode::ConstantBitrate {
+                bitrate: 0,
+                buffer_size: 1000,
+            }),
+            config(RateControlMode::VariableBitrate {
+                bitrate: 1000,
+                max_bitrate: 500,
+                buffer_size: 1000,
+            }),
+            // Smaller than 4 average frames.
+            config(RateControlMode::ConstantBitrate {
+                bitrate: 30_000,
+                buffer_size: 3_000,
+            }),
+        ] {
+            assert!(RateController::new(config).is_err());
+        }
+    }
+}
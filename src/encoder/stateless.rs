@@ -0,0 +1,133 @@
+// Copyright 2023 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+//! Stateless encoders.
+//!
+//! Like for [stateless decoders](crate::decoder::stateless), stateless refers to the backend API
+//! targeted by these encoders: the encoder decides the structure of the stream, i.e. the type of
+//! each frame, which frames they reference and the content of their headers, and the backend only
+//! encodes the pictures it is given according to these parameters.
+//!
+//! Each codec provides its own encoder, which implements the [`StatelessEncoder`] trait on top of
+//! a codec-specific backend.
+
+pub mod h264;
+
+use std::collections::VecDeque;
+
+use thiserror::Error;
+
+use crate::decoder::stateless::StatelessBackendError;
+use crate::encoder::CodedBitstreamBuffer;
+use crate::encoder::EncoderEvent;
+use crate::encoder::FrameMetadata;
+
+/// Error returned by the methods of [`StatelessEncoder`].
+#[derive(Debug, Error)]
+pub enum EncodeError {
+    #[error("encoder error: {0}")]
+    EncoderError(#[from] anyhow::Error),
+    #[error("backend error: {0}")]
+    BackendError(#[from] StatelessBackendError),
+}
+
+/// Handle to the coded data of a frame, which may not be available yet if the backend encodes
+/// asynchronously.
+pub trait CodedHandle {
+    /// Returns `true` if the frame has been completely encoded.
+    fn is_ready(&self) -> bool;
+
+    /// Wait until the frame has been completely encoded.
+    fn sync(&self) -> anyhow::Result<()>;
+
+    /// Returns the coded data of the frame, waiting for it to be ready if needed.
+    fn bitstream(self) -> anyhow::Result<Vec<u8>>;
+}
+
+/// Common trait shared by all stateless encoder backends, providing codec-independent types.
+pub trait StatelessEncoderBackend {
+    /// Raw frame to be encoded, as submitted by the client.
+    type Picture;
+
+    /// Reconstructed version of an encoded frame. The encoder keeps it as long as the frame is
+    /// used as a reference by the following ones.
+    type Reconstructed;
+
+    /// Handle to the coded data of a frame.
+    type Coded: CodedHandle;
+}
+
+/// Stateless video encoder interface.
+///
+/// Frames are submitted in presentation order through [`encode`]. Depending on the structure of
+/// the stream, the encoder may hold some of them until the frames they reference have been
+/// submitted, so the coded data of a frame is not necessarily available right after submitting
+/// it. Coded data is returned in decoding order through [`next_event`].
+///
+/// The `P` generic parameter is the type of the frames accepted by the encoder.
+///
+/// [`encode`]: StatelessEncoder::encode
+/// [`next_event`]: StatelessEncoder::next_event
+pub trait StatelessEncoder<P> {
+    /// Submit `picture` for encoding. `metadata` is returned along with the coded data of the
+    /// frame.
+    fn encode(&mut self, metadata: FrameMetadata, picture: P) -> Result<(), EncodeError>;
+
+    /// Encode all the frames held by the encoder and wait for their coded data to be ready to be
+    /// retrieved via [`next_event`].
+    ///
+    /// The stream restarts with a key frame after draining.
+    ///
+    /// [`next_event`]: StatelessEncoder::next_event
+    fn drain(&mut self) -> Result<(), EncodeError>;
+
+    /// Returns the next event, if there is any pending.
+    fn next_event(&mut self) -> Result<Option<EncoderEvent>, EncodeError>;
+}
+
+/// A queue where the coded data of frames waits until it is ready, at which point it can be
+/// retrieved.
+struct OutputQueue<H: CodedHandle> {
+    /// Frames being encoded, in decoding order, along with whether they are key frames.
+    queue: VecDeque<(FrameMetadata, bool, H)>,
+}
+
+impl<H: CodedHandle> Default for OutputQueue<H> {
+    fn default() -> Self {
+        Self {
+            queue: Default::default(),
+        }
+    }
+}
+
+impl<H: CodedHandle> OutputQueue<H> {
+    /// Push the coded data of a frame to the back of the queue.
+    fn push(&mut self, metadata: FrameMetadata, keyframe: bool, coded: H) {
+        self.queue.push_back((metadata, keyframe, coded))
+    }
+
+    /// Wait until all the frames in the queue are ready.
+    fn sync(&self) -> anyhow::Result<()> {
+        self.queue.iter().try_for_each(|(_, _, coded)| coded.sync())
+    }
+
+    /// Returns the coded data of the frame at the front of the queue, if it is ready.
+    fn next_event(&mut self) -> Result<Option<EncoderEvent>, EncodeError> {
+        if !self
+            .queue
+            .front()
+            .is_some_and(|(_, _, coded)| coded.is_ready())
+        {
+            return Ok(None);
+        }
+
+        let (metadata, keyframe, coded) = self.queue.pop_front().unwrap();
+
+        Ok(Some(EncoderEvent::FrameEncoded(CodedBitstreamBuffer {
+            metadata,
+            keyframe,
+            bitstream: coded.bitstream()?,
+        })))
+    }
+}
@@ -0,0 +1,730 @@
+// Copyright 2023 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+//! Stateless H.264 encoder.
+//!
+//! The encoder produces a single slice per picture, and organizes the stream in groups of pictures
+//! starting with an IDR picture. Inside a group, an anchor picture (I or P) is produced every
+//! `ip_period` frames, and the frames between two anchors are encoded as non-reference B pictures
+//! predicted from both of them.
+//!
+//! Reference pictures are managed using the sliding window process, so the decoded picture buffer
+//! of the decoder always matches the one of the encoder without any explicit marking operation.
+
+#[cfg(test)]
+mod dummy;
+
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use anyhow::anyhow;
+
+use crate::codec::h264::parser::Level;
+use crate::codec::h264::parser::Pps;
+use crate::codec::h264::parser::Profile;
+use crate::codec::h264::parser::SliceHeader;
+use crate::codec::h264::parser::SliceType;
+use crate::codec::h264::parser::Sps;
+use crate::decoder::stateless::StatelessBackendResult;
+use crate::decoder::BlockingMode;
+use crate::encoder::stateless::CodedHandle;
+use crate::encoder::stateless::EncodeError;
+use crate::encoder::stateless::OutputQueue;
+use crate::encoder::stateless::StatelessEncoder;
+use crate::encoder::stateless::StatelessEncoderBackend;
+use crate::encoder::EncoderEvent;
+use crate::encoder::FrameMetadata;
+use crate::Resolution;
+
+/// Configuration of the H.264 encoder.
+#[derive(Clone, Debug)]
+pub struct EncoderConfig {
+    /// Resolution of the frames to encode.
+    pub resolution: Resolution,
+    /// Profile of the produced stream.
+    pub profile: Profile,
+    /// Level of the produced stream.
+    pub level: Level,
+    /// Number of frames in a group of pictures, i.e. distance between two IDR pictures.
+    pub idr_period: u32,
+    /// Distance between two anchor pictures. The frames in between are encoded as B pictures, so
+    /// 1 produces a stream without B pictures.
+    pub ip_period: u32,
+    /// Maximum number of reference pictures P pictures can be predicted from.
+    pub num_ref_frames: u32,
+    /// Quantization parameter used for all the pictures.
+    pub qp: u8,
+}
+
+impl Default for EncoderConfig {
+    fn default() -> Self {
+        Self {
+            resolution: Resolution::from((320, 240)),
+            profile: Profile::Main,
+            level: Level::L4,
+            idr_period: 30,
+            ip_period: 1,
+            num_ref_frames: 1,
+            qp: 26,
+        }
+    }
+}
+
+impl EncoderConfig {
+    /// Checks that the configuration can be used to produce a valid stream.
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.resolution.width == 0 || self.resolution.height == 0 {
+            return Err(anyhow!("invalid resolution {:?}", self.resolution));
+        }
+        if self.idr_period == 0 {
+            return Err(anyhow!("IDR period must be at least 1"));
+        }
+        if self.ip_period == 0 || self.ip_period > self.idr_period {
+            return Err(anyhow!(
+                "IP period {} must be between 1 and the IDR period",
+                self.ip_period
+            ));
+        }
+        if self.ip_period > 1 && self.profile == Profile::Baseline {
+            return Err(anyhow!(
+                "B pictures are not supported by the baseline profile"
+            ));
+        }
+        if !(1..=16).contains(&self.num_ref_frames) {
+            return Err(anyhow!(
+                "invalid number of reference frames {}",
+                self.num_ref_frames
+            ));
+        }
+        if self.qp > 51 {
+            return Err(anyhow!("invalid QP {}", self.qp));
+        }
+
+        Ok(())
+    }
+}
+
+/// A reference picture kept by the encoder.
+pub struct RefFrame<R> {
+    /// Reconstructed picture, as returned by the backend.
+    pub reconstructed: R,
+    /// Value of `frame_num` of the picture.
+    pub frame_num: u16,
+    /// Picture order count of the picture.
+    pub poc: i32,
+}
+
+/// Parameters of a picture to encode, as decided by the encoder.
+pub struct PictureParams<R> {
+    /// SPS in use for the picture. It must be sent along with IDR pictures.
+    pub sps: Rc<Sps>,
+    /// PPS in use for the picture. It must be sent along with IDR pictures.
+    pub pps: Rc<Pps>,
+    /// Header of the single slice making up the picture.
+    pub slice_header: SliceHeader,
+    /// Whether the picture is an IDR picture.
+    pub is_idr: bool,
+    /// Value of `nal_ref_idc` for the slice NAL unit. Zero if the picture is not used as a
+    /// reference.
+    pub nal_ref_idc: u8,
+    /// Picture order count of the picture.
+    pub poc: i32,
+    /// Reference picture list 0, limited to the active entries.
+    pub ref_list_0: Vec<Rc<RefFrame<R>>>,
+    /// Reference picture list 1, limited to the active entries.
+    pub ref_list_1: Vec<Rc<RefFrame<R>>>,
+}
+
+impl<R> PictureParams<R> {
+    /// Returns the quantization parameter of the slice.
+    pub fn slice_qp(&self) -> i32 {
+        26 + i32::from(self.pps.pic_init_qp_minus26) + i32::from(self.slice_header.slice_qp_delta)
+    }
+}
+
+/// Stateless backend methods specific to H.264.
+pub trait StatelessH264EncoderBackend: StatelessEncoderBackend {
+    /// Encode `picture` according to `params`, and return its reconstructed version along with
+    /// a handle to its coded data.
+    ///
+    /// The coded data must contain the slice NAL unit of the picture, preceded by the SPS and PPS
+    /// for IDR pictures.
+    fn encode_picture(
+        &mut self,
+        picture: Self::Picture,
+        params: &PictureParams<Self::Reconstructed>,
+    ) -> StatelessBackendResult<(Self::Reconstructed, Self::Coded)>;
+}
+
+/// Type of a picture in the group of pictures.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PictureType {
+    Idr,
+    P,
+    B,
+}
+
+/// A frame submitted by the client, waiting to be encoded.
+struct PendingFrame<P> {
+    metadata: FrameMetadata,
+    picture: P,
+    /// Position of the frame in presentation order, from the start of its group of pictures.
+    index: u32,
+}
+
+/// Returns the number of bits required to represent `n`, clamped to the range allowed for
+/// `frame_num` and `pic_order_cnt_lsb`.
+fn counter_bits(n: u32) -> u8 {
+    (u32::BITS - n.leading_zeros()).clamp(4, 16) as u8
+}
+
+/// Stateless H.264 encoder.
+pub struct H264Encoder<B: StatelessH264EncoderBackend> {
+    config: EncoderConfig,
+
+    /// Whether the encoder should block on encode operations.
+    blocking_mode: BlockingMode,
+
+    /// The backend used for hardware acceleration.
+    backend: B,
+
+    sps: Rc<Sps>,
+    pps: Rc<Pps>,
+
+    /// Frames waiting for the next anchor frame in order to be encoded as B pictures, in
+    /// presentation order.
+    pending: Vec<PendingFrame<B::Picture>>,
+
+    /// Reference pictures, in decoding order.
+    dpb: VecDeque<Rc<RefFrame<B::Reconstructed>>>,
+
+    /// Number of frames submitted since the last IDR picture, or `None` if the next frame must be
+    /// an IDR picture.
+    frames_in_gop: Option<u32>,
+
+    /// Value of `frame_num` for the next picture.
+    frame_num: u16,
+
+    /// Value of `idr_pic_id` for the next IDR picture.
+    idr_pic_id: u16,
+
+    output: OutputQueue<B::Coded>,
+}
+
+impl<B: StatelessH264EncoderBackend> H264Encoder<B> {
+    /// Creates a new encoder producing a stream following `config`, using `backend` to encode
+    /// the pictures.
+    pub fn new(
+        backend: B,
+        config: EncoderConfig,
+        blocking_mode: BlockingMode,
+    ) -> anyhow::Result<Self> {
+        config.validate()?;
+
+        let sps = Rc::new(Self::build_sps(&config));
+        let pps = Rc::new(Pps {
+            entropy_coding_mode_flag: config.profile != Profile::Baseline,
+            pic_init_qp_minus26: config.qp as i8 - 26,
+            deblocking_filter_control_present_flag: true,
+            sps: Rc::clone(&sps),
+            ..Default::default()
+        });
+
+        Ok(Self {
+            config,
+            blocking_mode,
+            backend,
+            sps,
+            pps,
+            pending: Default::default(),
+            dpb: Default::default(),
+            frames_in_gop: None,
+            frame_num: 0,
+            idr_pic_id: 0,
+            output: Default::default(),
+        })
+    }
+
+    /// Returns the SPS describing the stream produced by `config`.
+    fn build_sps(config: &EncoderConfig) -> Sps {
+        let width_mbs = config.resolution.width.div_ceil(16);
+        let height_mbs = config.resolution.height.div_ceil(16);
+        let width = width_mbs * 16;
+        let height = height_mbs * 16;
+
+        // B pictures need both of their surrounding anchors.
+        let max_num_ref_frames = if config.ip_period > 1 {
+            config.num_ref_frames.max(2)
+        } else {
+            config.num_ref_frames
+        };
+
+        Sps {
+            profile_idc: config.profile as u8,
+            // Only use the tools of the constrained baseline profile.
+            constraint_set1_flag: config.profile == Profile::Baseline,
+            level_idc: config.level,
+            chroma_format_idc: 1,
+            log2_max_frame_num_minus4: counter_bits(config.idr_period) - 4,
+            log2_max_pic_order_cnt_lsb_minus4: counter_bits(2 * config.idr_period) - 4,
+            max_num_ref_frames,
+            pic_width_in_mbs_minus1: width_mbs - 1,
+            pic_height_in_map_units_minus1: height_mbs - 1,
+            frame_mbs_only_flag: true,
+            direct_8x8_inference_flag: true,
+            frame_cropping_flag: width != config.resolution.width
+                || height != config.resolution.height,
+            // Cropping is done in units of 2 luma samples for 4:2:0 content.
+            frame_crop_right_offset: (width - config.resolution.width) / 2,
+            frame_crop_bottom_offset: (height - config.resolution.height) / 2,
+            chroma_array_type: 1,
+            width,
+            height,
+            crop_rect_width: config.resolution.width,
+            crop_rect_height: config.resolution.height,
+            ..Default::default()
+        }
+    }
+
+    /// Returns the SPS of the stream.
+    pub fn sps(&self) -> &Rc<Sps> {
+        &self.sps
+    }
+
+    /// Returns the PPS of the stream.
+    pub fn pps(&self) -> &Rc<Pps> {
+        &self.pps
+    }
+
+    /// Returns the reference picture lists of a picture of type `pic_type` and picture order
+    /// count `poc`, in their initial order (clause 8.2.4.2).
+    #[allow(clippy::type_complexity)]
+    fn ref_lists(
+        &self,
+        pic_type: PictureType,
+        poc: i32,
+    ) -> (
+        Vec<Rc<RefFrame<B::Reconstructed>>>,
+        Vec<Rc<RefFrame<B::Reconstructed>>>,
+    ) {
+        match pic_type {
+            PictureType::Idr => Default::default(),
+            // Frame numbers never wrap within a group of pictures, so the most recently encoded
+            // pictures have the highest PicNum.
+            PictureType::P => {
+                let ref_list_0 = self
+                    .dpb
+                    .iter()
+                    .rev()
+                    .take(self.config.num_ref_frames as usize)
+                    .cloned()
+                    .collect();
+
+                (ref_list_0, vec![])
+            }
+            PictureType::B => {
+                let mut before = self.dpb.iter().filter(|r| r.poc < poc).collect::<Vec<_>>();
+                let mut after = self.dpb.iter().filter(|r| r.poc > poc).collect::<Vec<_>>();
+                before.sort_by_key(|r| std::cmp::Reverse(r.poc));
+                after.sort_by_key(|r| r.poc);
+
+                let ref_list_0 = before
+                    .iter()
+                    .chain(&after)
+                    .copied()
+                    .cloned()
+                    .collect::<Vec<_>>();
+                let mut ref_list_1 = after
+                    .iter()
+                    .chain(&before)
+                    .copied()
+                    .cloned()
+                    .collect::<Vec<_>>();
+                if ref_list_1.len() > 1
+                    && ref_list_0
+                        .iter()
+                        .zip(&ref_list_1)
+                        .all(|(a, b)| Rc::ptr_eq(a, b))
+                {
+                    ref_list_1.swap(0, 1);
+                }
+
+                // Only use the closest picture in each direction.
+                (
+                    ref_list_0.into_iter().take(1).collect(),
+                    ref_list_1.into_iter().take(1).collect(),
+                )
+            }
+        }
+    }
+
+    /// Encode `frame` as a picture of type `pic_type`.
+    fn encode_frame(
+        &mut self,
+        frame: PendingFrame<B::Picture>,
+        pic_type: PictureType,
+    ) -> Result<(), EncodeError> {
+        let is_idr = pic_type == PictureType::Idr;
+        let is_reference = pic_type != PictureType::B;
+
+        if is_idr {
+            self.dpb.clear();
+            self.frame_num = 0;
+        }
+
+        let poc = 2 * frame.index as i32;
+        let (ref_list_0, ref_list_1) = self.ref_lists(pic_type, poc);
+        let max_pic_order_cnt_lsb = 1 << (self.sps.log2_max_pic_order_cnt_lsb_minus4 + 4);
+
+        let slice_header = SliceHeader {
+            slice_type: match pic_type {
+                PictureType::Idr => SliceType::I,
+                PictureType::P => SliceType::P,
+                PictureType::B => SliceType::B,
+            },
+            pic_parameter_set_id: self.pps.pic_parameter_set_id,
+            frame_num: self.frame_num,
+            idr_pic_id: if is_idr { self.idr_pic_id } else { 0 },
+            pic_order_cnt_lsb: (poc % max_pic_order_cnt_lsb) as u16,
+            direct_spatial_mv_pred_flag: pic_type == PictureType::B,
+            num_ref_idx_active_override_flag: !is_idr,
+            num_ref_idx_l0_active_minus1: ref_list_0.len().saturating_sub(1) as u8,
+            num_ref_idx_l1_active_minus1: ref_list_1.len().saturating_sub(1) as u8,
+            ..Default::default()
+        };
+
+        let params = PictureParams {
+            sps: Rc::clone(&self.sps),
+            pps: Rc::clone(&self.pps),
+            slice_header,
+            is_idr,
+            nal_ref_idc: match pic_type {
+                PictureType::Idr => 3,
+                PictureType::P => 2,
+                PictureType::B => 0,
+            },
+            poc,
+            ref_list_0,
+            ref_list_1,
+        };
+
+        log::debug!(
+            "Encoding frame {} as {:?} picture, frame_num {}",
+            frame.index,
+            pic_type,
+            self.frame_num
+        );
+
+        let (reconstructed, coded) = self.backend.encode_picture(frame.picture, &params)?;
+
+        if self.blocking_mode == BlockingMode::Blocking {
+            coded.sync()?;
+        }
+
+        self.output.push(frame.metadata, is_idr, coded);
+
+        if is_idr {
+            self.idr_pic_id = self.idr_pic_id.wrapping_add(1);
+        }
+
+        if is_reference {
+            // Sliding window reference picture marking (clause 8.2.5.3).
+            if self.dpb.len() == self.sps.max_num_ref_frames as usize {
+                self.dpb.pop_front();
+            }
+            self.dpb.push_back(Rc::new(RefFrame {
+                reconstructed,
+                frame_num: self.frame_num,
+                poc,
+            }));
+
+            let max_frame_num = 1 << (self.sps.log2_max_frame_num_minus4 + 4);
+            self.frame_num = ((u32::from(self.frame_num) + 1) % max_frame_num) as u16;
+        }
+
+        Ok(())
+    }
+
+    /// Encode an anchor picture and the B pictures that precede it in presentation order.
+    fn encode_mini_gop(
+        &mut self,
+        anchor: PendingFrame<B::Picture>,
+        anchor_type: PictureType,
+    ) -> Result<(), EncodeError> {
+        self.encode_frame(anchor, anchor_type)?;
+
+        for frame in std::mem::take(&mut self.pending) {
+            self.encode_frame(frame, PictureType::B)?;
+        }
+
+        Ok(())
+    }
+
+    /// Encode the frames waiting for their following anchor, using the last one as anchor.
+    fn encode_pending(&mut self) -> Result<(), EncodeError> {
+        match self.pending.pop() {
+            Some(anchor) => self.encode_mini_gop(anchor, PictureType::P),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<B: StatelessH264EncoderBackend> StatelessEncoder<B::Picture> for H264Encoder<B> {
+    fn encode(&mut self, metadata: FrameMetadata, picture: B::Picture) -> Result<(), EncodeError> {
+        let index = match self.frames_in_gop {
+            Some(index) if index < self.config.idr_period && !metadata.force_keyframe => index,
+            _ => {
+                // The IDR picture cannot be referenced by the frames preceding it, so close the
+                // current group of pictures.
+                self.encode_pending()?;
+                0
+            }
+        };
+        self.frames_in_gop = Some(index + 1);
+
+        let frame = PendingFrame {
+            metadata,
+            picture,
+            index,
+        };
+
+        if index == 0 {
+            self.encode_frame(frame, PictureType::Idr)
+        } else if index % self.config.ip_period == 0 {
+            self.encode_mini_gop(frame, PictureType::P)
+        } else {
+            self.pending.push(frame);
+            Ok(())
+        }
+    }
+
+    fn drain(&mut self) -> Result<(), EncodeError> {
+        self.encode_pending()?;
+        self.frames_in_gop = None;
+        self.output.sync()?;
+
+        Ok(())
+    }
+
+    fn next_event(&mut self) -> Result<Option<EncoderEvent>, EncodeError> {
+        self.output.next_event()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::stateless::h264::dummy::EncodedPicture;
+    use crate::encoder::CodedBitstreamBuffer;
+
+    /// Encodes `num_frames` frames with `config` and returns the pictures seen by the backend,
+    /// along with the output events.
+    fn encode(
+        config: EncoderConfig,
+        num_frames: u64,
+        keyframes: &[u64],
+    ) -> (Vec<EncodedPicture>, Vec<CodedBitstreamBuffer>) {
+        let mut encoder = H264Encoder::new_dummy(config, BlockingMode::NonBlocking).unwrap();
+        let mut output = Vec::new();
+        let mut poll = |encoder: &mut H264Encoder<_>| {
+            while let Some(EncoderEvent::FrameEncoded(buffer)) = encoder.next_event().unwrap() {
+                output.push(buffer);
+            }
+        };
+
+        for i in 0..num_frames {
+            let metadata = FrameMetadata {
+                timestamp: i,
+                force_keyframe: keyframes.contains(&i),
+            };
+            encoder.encode(metadata, i).unwrap();
+            poll(&mut encoder);
+        }
+        encoder.drain().unwrap();
+        poll(&mut encoder);
+
+        (encoder.backend.pictures, output)
+    }
+
+    /// Returns `(picture, slice_type, frame_num, poc)` for each picture of `pictures`.
+    fn structure(pictures: &[EncodedPicture]) -> Vec<(u64, SliceType, u16, i32)> {
+        pictures
+            .iter()
+            .map(|p| (p.picture, p.slice_type, p.frame_num, p.poc))
+            .collect()
+    }
+
+    #[test]
+    fn ip_only() {
+        let config = EncoderConfig {
+            idr_period: 4,
+            ..Default::default()
+        };
+        let (pictures, _) = encode(config, 6, &[]);
+
+        assert_eq!(
+            structure(&pictures),
+            vec![
+                (0, SliceType::I, 0, 0),
+                (1, SliceType::P, 1, 2),
+                (2, SliceType::P, 2, 4),
+                (3, SliceType::P, 3, 6),
+                (4, SliceType::I, 0, 0),
+                (5, SliceType::P, 1, 2),
+            ]
+        );
+        assert!(pictures.iter().skip(1).all(|p| p.ref_list_1.is_empty()));
+        assert_eq!(pictures[3].ref_list_0, vec![2]);
+        assert_eq!(pictures[5].ref_list_0, vec![4]);
+        // Consecutive IDR pictures must have different identifiers.
+        assert_ne!(pictures[0].idr_pic_id, pictures[4].idr_pic_id);
+    }
+
+    #[test]
+    fn b_frames() {
+        let config = EncoderConfig {
+            idr_period: 8,
+            ip_period: 3,
+            ..Default::default()
+        };
+        let (pictures, output) = encode(config, 8, &[]);
+
+        // The last frame has no following anchor and is encoded as a P picture when draining.
+        assert_eq!(
+            structure(&pictures),
+            vec![
+                (0, SliceType::I, 0, 0),
+                (3, SliceType::P, 1, 6),
+                (1, SliceType::B, 2, 2),
+                (2, SliceType::B, 2, 4),
+                (6, SliceType::P, 2, 12),
+                (4, SliceType::B, 3, 8),
+                (5, SliceType::B, 3, 10),
+                (7, SliceType::P, 3, 14),
+            ]
+        );
+        assert_eq!(
+            pictures.iter().map(|p| p.is_reference).collect::<Vec<_>>(),
+            vec![true, true, false, false, true, false, false, true]
+        );
+        assert_eq!(pictures[2].ref_list_0, vec![0]);
+        assert_eq!(pictures[2].ref_list_1, vec![3]);
+        assert_eq!(pictures[5].ref_list_0, vec![3]);
+        assert_eq!(pictures[5].ref_list_1, vec![6]);
+
+        // Coded data is returned in decoding order.
+        assert_eq!(
+            output
+                .iter()
+                .map(|b| b.metadata.timestamp)
+                .collect::<Vec<_>>(),
+            vec![0, 3, 1, 2, 6, 4, 5, 7]
+        );
+        assert!(output
+            .iter()
+            .all(|b| b.bitstream == b.metadata.timestamp.to_le_bytes()));
+    }
+
+    #[test]
+    fn multiple_references() {
+        let config = EncoderConfig {
+            num_ref_frames: 2,
+            ..Default::default()
+        };
+        let (pictures, _) = encode(config, 4, &[]);
+
+        assert_eq!(pictures[1].ref_list_0, vec![0]);
+        assert_eq!(pictures[2].ref_list_0, vec![1, 0]);
+        // The oldest reference has been evicted by the sliding window.
+        assert_eq!(pictures[3].ref_list_0, vec![2, 1]);
+    }
+
+    #[test]
+    fn forced_keyframe() {
+        let config = EncoderConfig {
+            ip_period: 2,
+            ..Default::default()
+        };
+        let (pictures, output) = encode(config, 5, &[4]);
+
+        // Frame 3 precedes the forced key frame and cannot reference it, so it becomes an anchor.
+        assert_eq!(
+            structure(&pictures),
+            vec![
+                (0, SliceType::I, 0, 0),
+                (2, SliceType::P, 1, 4),
+                (1, SliceType::B, 2, 2),
+                (3, SliceType::P, 2, 6),
+                (4, SliceType::I, 0, 0),
+            ]
+        );
+        assert_eq!(
+            output.iter().map(|b| b.keyframe).collect::<Vec<_>>(),
+            vec![true, false, false, false, true]
+        );
+    }
+
+    #[test]
+    fn drain_restarts_gop() {
+        let mut encoder =
+            H264Encoder::new_dummy(Default::default(), BlockingMode::Blocking).unwrap();
+
+        encoder.encode(Default::default(), 0).unwrap();
+        encoder.encode(Default::default(), 1).unwrap();
+        encoder.drain().unwrap();
+        encoder.encode(Default::default(), 2).unwrap();
+
+        assert_eq!(
+            encoder
+                .backend
+                .pictures
+                .iter()
+                .map(|p| p.is_idr)
+                .collect::<Vec<_>>(),
+            vec![true, false, true]
+        );
+    }
+
+    #[test]
+    fn sps() {
+        let config = EncoderConfig {
+            resolution: Resolution::from((100, 50)),
+            ..Default::default()
+        };
+        let encoder = H264Encoder::new_dummy(config, BlockingMode::Blocking).unwrap();
+
+        let sps = encoder.sps();
+        assert_eq!(sps.width, 112);
+        assert_eq!(sps.height, 64);
+        assert_eq!(
+            sps.visible_rectangle().max,
+            crate::codec::h264::parser::Point { x: 100, y: 50 }
+        );
+    }
+
+    #[test]
+    fn invalid_config() {
+        for config in [
+            EncoderConfig {
+                idr_period: 0,
+                ..Default::default()
+            },
+            EncoderConfig {
+                ip_period: 2,
+                profile: Profile::Baseline,
+                ..Default::default()
+            },
+            EncoderConfig {
+                num_ref_frames: 17,
+                ..Default::default()
+            },
+            EncoderConfig {
+                qp: 52,
+                ..Default::default()
+            },
+        ] {
+            assert!(H264Encoder::new_dummy(config, BlockingMode::Blocking).is_err());
+        }
+    }
+}
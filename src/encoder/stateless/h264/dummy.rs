@@ -0,0 +1,88 @@
+// Copyright 2023 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+//! This file contains a dummy backend whose only purpose is to let the encoder
+//! run so we can test it in isolation.
+//!
+//! Pictures are identified by a number, which is also used as their reconstructed picture and
+//! coded data. The backend records the parameters it receives for each picture so tests can
+//! check the decisions of the encoder.
+
+use crate::codec::h264::parser::SliceType;
+use crate::decoder::stateless::StatelessBackendResult;
+use crate::decoder::BlockingMode;
+use crate::encoder::stateless::h264::EncoderConfig;
+use crate::encoder::stateless::h264::H264Encoder;
+use crate::encoder::stateless::h264::PictureParams;
+use crate::encoder::stateless::h264::StatelessH264EncoderBackend;
+use crate::encoder::stateless::CodedHandle;
+use crate::encoder::stateless::StatelessEncoderBackend;
+
+/// Parameters of a picture, as received by the backend.
+#[derive(Debug)]
+pub(crate) struct EncodedPicture {
+    pub(crate) picture: u64,
+    pub(crate) slice_type: SliceType,
+    pub(crate) is_idr: bool,
+    pub(crate) is_reference: bool,
+    pub(crate) idr_pic_id: u16,
+    pub(crate) frame_num: u16,
+    pub(crate) poc: i32,
+    pub(crate) ref_list_0: Vec<u64>,
+    pub(crate) ref_list_1: Vec<u64>,
+}
+
+impl CodedHandle for Vec<u8> {
+    fn is_ready(&self) -> bool {
+        true
+    }
+
+    fn sync(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn bitstream(self) -> anyhow::Result<Vec<u8>> {
+        Ok(self)
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct Backend {
+    pub(crate) pictures: Vec<EncodedPicture>,
+}
+
+impl StatelessEncoderBackend for Backend {
+    type Picture = u64;
+    type Reconstructed = u64;
+    type Coded = Vec<u8>;
+}
+
+impl StatelessH264EncoderBackend for Backend {
+    fn encode_picture(
+        &mut self,
+        picture: Self::Picture,
+        params: &PictureParams<Self::Reconstructed>,
+    ) -> StatelessBackendResult<(Self::Reconstructed, Self::Coded)> {
+        self.pictures.push(EncodedPicture {
+            picture,
+            slice_type: params.slice_header.slice_type,
+            is_idr: params.is_idr,
+            is_reference: params.nal_ref_idc != 0,
+            idr_pic_id: params.slice_header.idr_pic_id,
+            frame_num: params.slice_header.frame_num,
+            poc: params.poc,
+            ref_list_0: params.ref_list_0.iter().map(|r| r.reconstructed).collect(),
+            ref_list_1: params.ref_list_1.iter().map(|r| r.reconstructed).collect(),
+        });
+
+        Ok((picture, picture.to_le_bytes().to_vec()))
+    }
+}
+
+impl H264Encoder<Backend> {
+    // Creates a new instance of the encoder using the dummy backend.
+    pub fn new_dummy(config: EncoderConfig, blocking_mode: BlockingMode) -> anyhow::Result<Self> {
+        Self::new(Backend::default(), config, blocking_mode)
+    }
+}
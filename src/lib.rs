@@ -14,18 +14,20 @@
 //! The [decoder] module contains decoders that can turn an encoded video stream into a sequence of
 //! decoded frames using the hardware acceleration available on the host.
 //!
+//! The [encoder] module contains encoders that can turn a sequence of raw frames into an encoded
+//! video stream, leveraging the same hardware acceleration.
+//!
 //! The [utils] module contains some useful code that is shared between different parts of this
 //! crate and didn't fit any of the modules above.
 
 pub mod backend;
 pub mod codec;
 pub mod decoder;
+pub mod encoder;
 pub mod utils;
 
 use std::str::FromStr;
 
-use byteorder::ByteOrder;
-use byteorder::LittleEndian;
 #[cfg(feature = "vaapi")]
 pub use libva;
 
@@ -184,7 +186,7 @@ impl FromStr for DecodedFormat {
 }
 
 /// Describes the layout of a plane within a frame.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct PlaneLayout {
     /// Index of the memory buffer the plane belongs to.
     pub buffer_index: usize,
@@ -198,7 +200,7 @@ pub struct PlaneLayout {
 ///
 /// A frame can be made of one or several memory buffers, each containing one or several planes.
 /// For a given frame, this structure defines where each plane can be found.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct FrameLayout {
     /// `(Fourcc, modifier)` tuple describing the arrangement of the planes.
     ///
@@ -346,76 +348,8 @@ pub fn i4xx_copy(
 /// padding. This is the minimum size of the destination buffer passed to `nv12_copy` or
 /// `i420_copy`.
 pub fn decoded_frame_size(format: DecodedFormat, width: usize, height: usize) -> usize {
-    match format {
-        DecodedFormat::I420 | DecodedFormat::NV12 => {
-            let u_size = width * height;
-            // U and V planes need to be aligned to 2.
-            let uv_size = ((width + 1) / 2) * ((height + 1) / 2) * 2;
-
-            u_size + uv_size
-        }
-        DecodedFormat::I422 => {
-            let u_size = width * height;
-            // U and V planes need to be aligned to 2.
-            let uv_size = ((width + 1) / 2) * ((height + 1) / 2) * 2 * 2;
-
-            u_size + uv_size
-        }
-        DecodedFormat::I444 => (width * height) * 3,
-        DecodedFormat::I010 | DecodedFormat::I012 => {
-            decoded_frame_size(DecodedFormat::I420, width, height) * 2
-        }
-        DecodedFormat::I210 | DecodedFormat::I212 => {
-            let u_size = width * height * 2;
-            // U and V planes need to be aligned to 2.
-            let uv_size = ((width + 1) / 2) * ((height + 1) / 2) * 2 * 2;
-
-            u_size + uv_size
-        }
-        DecodedFormat::I410 | DecodedFormat::I412 => (width * height * 2) * 3,
-    }
-}
-
-/// Copies `src` into `dst` as I410, removing all padding and changing the layout from packed to
-/// triplanar. Also drops the alpha channel.
-fn y410_to_i410(
-    src: &[u8],
-    dst: &mut [u8],
-    width: usize,
-    height: usize,
-    strides: [usize; 3],
-    offsets: [usize; 3],
-) {
-    let src_lines = src[offsets[0]..]
-        .chunks(strides[0])
-        .map(|line| &line[..width * 4]);
-
-    let dst_y_size = width * 2 * height;
-    let dst_u_size = width * 2 * height;
-
-    let (dst_y_plane, dst_uv_planes) = dst.split_at_mut(dst_y_size);
-    let (dst_u_plane, dst_v_plane) = dst_uv_planes.split_at_mut(dst_u_size);
-    let dst_y_lines = dst_y_plane.chunks_mut(width * 2);
-    let dst_u_lines = dst_u_plane.chunks_mut(width * 2);
-    let dst_v_lines = dst_v_plane.chunks_mut(width * 2);
-
-    for (src_line, (dst_y_line, (dst_u_line, dst_v_line))) in src_lines
-        .zip(dst_y_lines.zip(dst_u_lines.zip(dst_v_lines)))
-        .take(height)
-    {
-        for (src, (dst_y, (dst_u, dst_v))) in src_line.chunks(4).zip(
-            dst_y_line
-                .chunks_mut(2)
-                .zip(dst_u_line.chunks_mut(2).zip(dst_v_line.chunks_mut(2))),
-        ) {
-            let y = LittleEndian::read_u16(&[src[1] >> 2 | src[2] << 6, src[2] >> 2 & 0b11]);
-            let u = LittleEndian::read_u16(&[src[0], src[1] & 0b11]);
-            let v = LittleEndian::read_u16(&[src[2] >> 4 | src[3] << 4, src[3] >> 4 & 0b11]);
-            LittleEndian::write_u16(dst_y, y);
-            LittleEndian::write_u16(dst_u, u);
-            LittleEndian::write_u16(dst_v, v);
-        }
-    }
+    utils::convert::PixelFormat::from(format)
+        .packed_size(Resolution::from((width as u32, height as u32)))
 }
 
 #[cfg(test)]
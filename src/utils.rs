@@ -7,28 +7,88 @@
 //! This module is for anything that doesn't fit into the other top-level modules. Try not to add
 //! new code here unless it really doesn't belong anywhere else.
 
+pub mod codec_string;
+pub mod compare;
+#[cfg(feature = "container")]
+pub mod container;
+pub mod convert;
+pub mod index;
+pub mod probe;
+pub mod rewrite;
+pub mod rtp;
+pub mod scale;
+pub mod y4m;
+
+use std::fmt::Debug;
 use std::io::Cursor;
 use std::io::Seek;
 use std::marker::PhantomData;
+use std::num::NonZeroUsize;
+use std::os::fd::AsFd;
+use std::os::fd::AsRawFd;
+use std::os::fd::BorrowedFd;
+use std::os::fd::FromRawFd;
 use std::os::fd::OwnedFd;
 
 use bytes::Buf;
+use nix::fcntl::fcntl;
+use nix::fcntl::FcntlArg;
+use nix::fcntl::SealFlag;
+use nix::sys::memfd::memfd_create;
+use nix::sys::memfd::MemFdCreateFlag;
+use nix::sys::mman::mmap;
+use nix::sys::mman::munmap;
+use nix::sys::mman::MapFlags;
+use nix::sys::mman::ProtFlags;
+use nix::sys::stat::fstat;
 
+use crate::codec::h264::nalu::Header;
+use crate::codec::h264::nalu::Nalu;
 use crate::codec::h264::parser::Nalu as H264Nalu;
 use crate::codec::h265::parser::Nalu as H265Nalu;
 use crate::decoder::stateless::DecodeError;
 use crate::decoder::stateless::StatelessVideoDecoder;
+use crate::decoder::timestamp::TimestampMode;
 use crate::decoder::BlockingMode;
 use crate::decoder::DecodedHandle;
 use crate::decoder::DecoderEvent;
 use crate::decoder::StreamInfo;
+use crate::utils::convert::PixelFormat;
 use crate::DecodedFormat;
 use crate::Fourcc;
 use crate::FrameLayout;
 use crate::PlaneLayout;
 use crate::Resolution;
 
+/// Size of the IVF file header.
+const IVF_HEADER_SIZE: usize = 32;
+/// Size of the header preceding each frame of an IVF file.
+const IVF_FRAME_HEADER_SIZE: usize = 12;
+
+/// Header of an IVF file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IvfFileHeader {
+    /// Fourcc of the codec of the frames, e.g. `VP80` or `VP90`.
+    pub fourcc: [u8; 4],
+    pub width: u16,
+    pub height: u16,
+    /// Time base of the frame timestamps, in seconds, as a (numerator, denominator) pair.
+    pub time_base: (u32, u32),
+}
+
+/// A frame of an IVF file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IvfFrame<'a> {
+    /// Offset of the frame data in the file.
+    pub offset: usize,
+    /// Timestamp of the frame, in units of the time base of the file.
+    pub pts: u64,
+    pub data: &'a [u8],
+}
+
 /// Iterator over IVF packets.
+///
+/// Iteration stops at the first frame that is truncated.
 pub struct IvfIterator<'a> {
     cursor: Cursor<&'a [u8]>,
 }
@@ -38,24 +98,46 @@ impl<'a> IvfIterator<'a> {
         let mut cursor = Cursor::new(data);
 
         // Skip the IVH header entirely.
-        cursor.seek(std::io::SeekFrom::Start(32)).unwrap();
+        cursor
+            .seek(std::io::SeekFrom::Start(IVF_HEADER_SIZE as u64))
+            .unwrap();
 
         Self { cursor }
     }
-}
 
-impl<'a> Iterator for IvfIterator<'a> {
-    type Item = &'a [u8];
+    /// Returns the header of the file.
+    pub fn header(&self) -> anyhow::Result<IvfFileHeader> {
+        let data = *self.cursor.get_ref();
+        if data.len() < IVF_HEADER_SIZE || !data.starts_with(b"DKIF") {
+            anyhow::bail!("invalid IVF header");
+        }
 
-    fn next(&mut self) -> Option<Self::Item> {
+        let mut header = &data[8..IVF_HEADER_SIZE];
+        let mut fourcc = [0u8; 4];
+        header.copy_to_slice(&mut fourcc);
+        let width = header.get_u16_le();
+        let height = header.get_u16_le();
+        // The header stores the denominator first.
+        let time_base_den = header.get_u32_le();
+        let time_base_num = header.get_u32_le();
+
+        Ok(IvfFileHeader {
+            fourcc,
+            width,
+            height,
+            time_base: (time_base_num, time_base_den),
+        })
+    }
+
+    /// Returns the next frame along with its position in the file and timestamp.
+    pub fn next_frame(&mut self) -> Option<IvfFrame<'a>> {
         // Make sure we have a header.
-        if self.cursor.remaining() < 6 {
+        if self.cursor.remaining() < IVF_FRAME_HEADER_SIZE {
             return None;
         }
 
         let len = self.cursor.get_u32_le() as usize;
-        // Skip PTS.
-        let _ = self.cursor.get_u64_le();
+        let pts = self.cursor.get_u64_le();
 
         if self.cursor.remaining() < len {
             return None;
@@ -65,7 +147,19 @@ impl<'a> Iterator for IvfIterator<'a> {
         let _ = self.cursor.seek(std::io::SeekFrom::Current(len as i64));
         let end = self.cursor.position() as usize;
 
-        Some(&self.cursor.get_ref()[start..end])
+        Some(IvfFrame {
+            offset: start,
+            pts,
+            data: &self.cursor.get_ref()[start..end],
+        })
+    }
+}
+
+impl<'a> Iterator for IvfIterator<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_frame().map(|frame| frame.data)
     }
 }
 
@@ -106,7 +200,49 @@ impl<'a> Iterator for NalIterator<'a, H265Nalu<&[u8]>> {
     }
 }
 
+/// Calls `f` on each NAL unit of the Annex B stream `data`, in order.
+pub(crate) fn for_each_nalu<'a, U, F>(data: &'a [u8], mut f: F) -> anyhow::Result<()>
+where
+    U: Debug + Header,
+    F: FnMut(Nalu<&'a [u8], U>) -> anyhow::Result<()>,
+{
+    let mut cursor = Cursor::new(data);
+
+    loop {
+        // Only stop on a missing start code, so invalid NAL units are reported.
+        let pos = usize::try_from(cursor.position())?;
+        if !data[pos.min(data.len())..]
+            .windows(3)
+            .any(|window| window == [0x00, 0x00, 0x01])
+        {
+            return Ok(());
+        }
+
+        f(Nalu::next(&mut cursor)?)?;
+    }
+}
+
+/// Returns the NAL units of the Annex B stream `data`, without their start code.
+#[cfg(test)]
+pub(crate) fn annexb_nalus<U: Debug + Header>(data: &[u8]) -> Vec<&[u8]> {
+    let mut nalus = Vec::new();
+    for_each_nalu(data, |nalu: Nalu<&[u8], U>| {
+        let data = *nalu.data();
+        nalus.push(&data[nalu.offset()..nalu.offset() + nalu.size()]);
+        Ok(())
+    })
+    .unwrap();
+
+    nalus
+}
+
 /// Simple decoding loop that plays the stream once from start to finish.
+///
+/// Packets are submitted with their index as timestamp, and the decoded frames receive their
+/// timestamps according to `timestamp_mode`. With [`TimestampMode::Passthrough`], each frame
+/// carries the index of the packet it was decoded from, which is not its presentation order once
+/// frames are reordered; [`TimestampMode::Synthesize`] gives frames their presentation timestamp
+/// instead.
 pub fn simple_playback_loop<D, R, I, M>(
     decoder: &mut D,
     stream_iter: I,
@@ -114,12 +250,15 @@ pub fn simple_playback_loop<D, R, I, M>(
     allocate_new_frames: &mut dyn FnMut(&StreamInfo, usize) -> anyhow::Result<Vec<M>>,
     output_format: DecodedFormat,
     blocking_mode: BlockingMode,
+    timestamp_mode: TimestampMode,
 ) -> anyhow::Result<()>
 where
     D: StatelessVideoDecoder<M> + ?Sized,
     R: AsRef<[u8]>,
     I: Iterator<Item = R>,
 {
+    decoder.set_timestamp_mode(timestamp_mode);
+
     // Closure that drains all pending decoder events and calls `on_new_frame` on each
     // completed frame.
     let mut check_events = |decoder: &mut D| -> anyhow::Result<()> {
@@ -185,6 +324,41 @@ pub fn simple_playback_loop_owned_frames(
     Ok(vec![(); nb_frames])
 }
 
+/// Frame allocation callback that returns safe user-allocated memory for the frames.
+///
+/// Frames of any [`DecodedFormat`] can be allocated, using the layout constraints of
+/// [`FrameAlignment::default`].
+pub fn simple_playback_loop_user_frames(
+    stream_info: &StreamInfo,
+    nb_frames: usize,
+) -> anyhow::Result<Vec<UserFrame>> {
+    (0..nb_frames)
+        .map(|_| {
+            UserFrame::for_decoded_format(
+                stream_info.format,
+                stream_info.coded_resolution,
+                FrameAlignment::default(),
+            )
+        })
+        .collect()
+}
+
+/// Frame allocation callback that returns frames backed by sealed memfds.
+pub fn simple_playback_loop_memfd_frames(
+    stream_info: &StreamInfo,
+    nb_frames: usize,
+) -> anyhow::Result<Vec<MemfdFrame>> {
+    (0..nb_frames)
+        .map(|_| {
+            MemfdFrame::for_decoded_format(
+                stream_info.format,
+                stream_info.coded_resolution,
+                FrameAlignment::default(),
+            )
+        })
+        .collect()
+}
+
 /// Frame allocation callback that returns user-allocated memory for the frames.
 pub fn simple_playback_loop_userptr_frames(
     stream_info: &StreamInfo,
@@ -204,6 +378,9 @@ pub fn simple_playback_loop_userptr_frames(
 }
 
 /// A structure that holds user-allocated memory for a frame as well as its layout.
+///
+/// Only NV12 frames can be allocated this way. [`UserFrame`] supports every format and gives safe
+/// access to the decoded planes.
 #[derive(Debug)]
 pub struct UserPtrFrame {
     pub buffers: Vec<*mut u8>,
@@ -269,3 +446,653 @@ impl Drop for UserPtrFrame {
         }
     }
 }
+
+/// Alignment constraints applied when allocating a [`UserFrame`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameAlignment {
+    /// The width of the frame is rounded up to a multiple of this value.
+    pub width: u32,
+    /// The height of the frame is rounded up to a multiple of this value.
+    pub height: u32,
+    /// The stride of each plane is rounded up to a multiple of this many bytes.
+    pub stride: usize,
+}
+
+impl Default for FrameAlignment {
+    /// Returns the alignment expected by VAAPI for user memory.
+    fn default() -> Self {
+        Self {
+            width: 16,
+            height: 4,
+            stride: 64,
+        }
+    }
+}
+
+impl FrameAlignment {
+    /// Returns the layout of a frame of `format` with a resolution of at least `size` aligned
+    /// according to `self`, along with the size of its data.
+    fn layout(
+        &self,
+        format: PixelFormat,
+        size: Resolution,
+    ) -> anyhow::Result<(FrameLayout, usize)> {
+        if size.width == 0 || size.height == 0 {
+            anyhow::bail!("cannot allocate a frame of size {:?}", size);
+        }
+        if !self.stride.is_power_of_two() {
+            anyhow::bail!("stride alignment {} is not a power of two", self.stride);
+        }
+
+        let size = Resolution {
+            width: size.width.next_multiple_of(self.width),
+            height: size.height.next_multiple_of(self.height),
+        };
+
+        Ok((
+            format.aligned_layout(size, self.stride),
+            format.aligned_size(size, self.stride),
+        ))
+    }
+}
+
+/// Returns the format of the surface the hardware decodes into when `format` is requested.
+fn surface_format(format: DecodedFormat) -> PixelFormat {
+    match format {
+        DecodedFormat::I420 | DecodedFormat::NV12 => PixelFormat::Decoded(DecodedFormat::NV12),
+        DecodedFormat::I422 => PixelFormat::Decoded(DecodedFormat::I422),
+        DecodedFormat::I444 => PixelFormat::Decoded(DecodedFormat::I444),
+        DecodedFormat::I010 => PixelFormat::P010,
+        DecodedFormat::I012 => PixelFormat::P012,
+        DecodedFormat::I210 => PixelFormat::Y210,
+        DecodedFormat::I212 => PixelFormat::Y212,
+        DecodedFormat::I410 => PixelFormat::Y410,
+        DecodedFormat::I412 => PixelFormat::Y412,
+    }
+}
+
+/// Memory holding all the planes of a frame in a single buffer, shared by [`UserFrame`] and
+/// [`MemfdFrame`].
+///
+/// The memory is owned by the frame holding the buffer, which is responsible for releasing it.
+#[derive(Debug)]
+pub(crate) struct FrameBuffer {
+    pub(crate) ptr: *mut u8,
+    /// Byte range covered by each plane, in increasing order.
+    planes: Vec<std::ops::Range<usize>>,
+    pub(crate) layout: FrameLayout,
+}
+
+impl FrameBuffer {
+    /// Returns the byte range covered by each plane of `layout`.
+    ///
+    /// The layout may come from another process, so it is rejected unless its planes follow each
+    /// other in a single buffer without overlapping, and without their ends overflowing.
+    fn plane_ranges(layout: &FrameLayout) -> anyhow::Result<Vec<std::ops::Range<usize>>> {
+        let format = convert::layout_format(layout)?;
+        let plane_sizes = format.plane_sizes(layout.size);
+        if layout.planes.len() != plane_sizes.len() {
+            anyhow::bail!(
+                "expected {} planes for {:?}, got {}",
+                plane_sizes.len(),
+                format,
+                layout.planes.len()
+            );
+        }
+
+        let mut ranges: Vec<std::ops::Range<usize>> = Vec::with_capacity(plane_sizes.len());
+        for (plane, (line_size, lines)) in layout.planes.iter().zip(plane_sizes) {
+            let end = plane
+                .stride
+                .checked_mul(lines)
+                .and_then(|size| size.checked_add(plane.offset));
+            let prev_end = ranges.last().map_or(0, |range| range.end);
+            match end {
+                Some(end)
+                    if plane.buffer_index == 0
+                        && plane.stride >= line_size
+                        && plane.offset >= prev_end =>
+                {
+                    ranges.push(plane.offset..end)
+                }
+                _ => anyhow::bail!("invalid plane layout {:?}", plane),
+            }
+        }
+
+        Ok(ranges)
+    }
+
+    /// Returns the size of the frame data, excluding any padding at the end of the memory.
+    fn data_size(&self) -> usize {
+        self.planes.last().map_or(0, |range| range.end)
+    }
+
+    /// Returns the whole frame data, all planes included.
+    ///
+    /// # Safety
+    ///
+    /// Nothing else may write to the memory while the returned slice is alive.
+    unsafe fn as_bytes(&self) -> &[u8] {
+        // Safe because `ptr` points to at least `data_size` initialized bytes that live as long as
+        // the frame holding `self`.
+        unsafe { std::slice::from_raw_parts(self.ptr, self.data_size()) }
+    }
+
+    /// Returns the whole frame data, all planes included, for writing.
+    ///
+    /// # Safety
+    ///
+    /// Nothing else may access the memory while the returned slice is alive.
+    unsafe fn as_bytes_mut(&mut self) -> &mut [u8] {
+        // Safe because `ptr` points to at least `data_size` initialized bytes that live as long as
+        // the frame holding `self`.
+        unsafe { std::slice::from_raw_parts_mut(self.ptr, self.data_size()) }
+    }
+
+    /// Copies the data of plane `index` out of the memory without ever referencing it.
+    fn read_plane(&self, index: usize) -> Option<Vec<u8>> {
+        let range = self.planes.get(index)?.clone();
+        let mut data = vec![0u8; range.len()];
+        // Safe because `range` is within the `data_size` bytes pointed to by `ptr`, which cannot
+        // overlap with the newly allocated `data`.
+        unsafe {
+            std::ptr::copy_nonoverlapping(self.ptr.add(range.start), data.as_mut_ptr(), data.len())
+        };
+
+        Some(data)
+    }
+
+    /// Copies `data` into plane `index` without ever referencing the memory.
+    fn write_plane(&mut self, index: usize, data: &[u8]) -> anyhow::Result<()> {
+        let range = self
+            .planes
+            .get(index)
+            .ok_or_else(|| anyhow::anyhow!("frame has no plane {}", index))?
+            .clone();
+        if data.len() != range.len() {
+            anyhow::bail!(
+                "plane {} is {} bytes, got {}",
+                index,
+                range.len(),
+                data.len()
+            );
+        }
+
+        // Safe because `range` is within the `data_size` bytes pointed to by `ptr`, and `data`
+        // borrows memory of its own.
+        unsafe {
+            std::ptr::copy_nonoverlapping(data.as_ptr(), self.ptr.add(range.start), data.len())
+        };
+
+        Ok(())
+    }
+}
+
+// Safe because the memory is owned by the frame holding the buffer and only accessed through it.
+unsafe impl Send for FrameBuffer {}
+unsafe impl Sync for FrameBuffer {}
+
+/// A frame backed by memory owned by the user, with all its planes in a single buffer.
+///
+/// Unlike [`UserPtrFrame`], the memory is zero-initialized, released on drop, and only accessible
+/// through slices once decoding has completed.
+#[derive(Debug)]
+pub struct UserFrame {
+    pub(crate) buffer: FrameBuffer,
+    pub(crate) mem_layout: std::alloc::Layout,
+}
+
+impl UserFrame {
+    /// Alignment of the allocated memory, which must be page-aligned for VAAPI.
+    const MEM_ALIGNMENT: usize = 4096;
+
+    /// Allocates a frame of `format` with a resolution of at least `size`, aligned according to
+    /// `alignment`.
+    pub fn new(
+        format: PixelFormat,
+        size: Resolution,
+        alignment: FrameAlignment,
+    ) -> anyhow::Result<Self> {
+        let (layout, data_size) = alignment.layout(format, size)?;
+        let planes = FrameBuffer::plane_ranges(&layout)?;
+        let mem_layout = std::alloc::Layout::from_size_align(
+            data_size.next_multiple_of(Self::MEM_ALIGNMENT),
+            Self::MEM_ALIGNMENT,
+        )?;
+
+        // Safe because `mem_layout` has a non-zero size.
+        let ptr = unsafe { std::alloc::alloc_zeroed(mem_layout) };
+        if ptr.is_null() {
+            std::alloc::handle_alloc_error(mem_layout);
+        }
+
+        Ok(Self {
+            buffer: FrameBuffer {
+                ptr,
+                planes,
+                layout,
+            },
+            mem_layout,
+        })
+    }
+
+    /// Allocates a frame that a decoder can output `format` into, i.e. using the layout of the
+    /// surface format the hardware decodes to for `format`.
+    pub fn for_decoded_format(
+        format: DecodedFormat,
+        size: Resolution,
+        alignment: FrameAlignment,
+    ) -> anyhow::Result<Self> {
+        Self::new(surface_format(format), size, alignment)
+    }
+
+    /// Returns the layout of the frame.
+    pub fn layout(&self) -> &FrameLayout {
+        &self.buffer.layout
+    }
+
+    /// Returns the whole frame data, all planes included.
+    pub fn as_bytes(&self) -> &[u8] {
+        // Safe because the memory is exclusively owned by `self`.
+        unsafe { self.buffer.as_bytes() }
+    }
+
+    /// Returns the whole frame data, all planes included, for writing.
+    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
+        // Safe because the memory is exclusively owned by `self`, which we borrow mutably.
+        unsafe { self.buffer.as_bytes_mut() }
+    }
+
+    /// Returns the data of plane `index`, or `None` if the frame has no such plane.
+    pub fn plane(&self, index: usize) -> Option<&[u8]> {
+        let range = self.buffer.planes.get(index)?.clone();
+        Some(&self.as_bytes()[range])
+    }
+
+    /// Returns the data of plane `index` for writing, or `None` if the frame has no such plane.
+    pub fn plane_mut(&mut self, index: usize) -> Option<&mut [u8]> {
+        let range = self.buffer.planes.get(index)?.clone();
+        Some(&mut self.as_bytes_mut()[range])
+    }
+}
+
+impl Drop for UserFrame {
+    fn drop(&mut self) {
+        // Safe because we allocated the memory using `std::alloc::alloc_zeroed` with this layout.
+        unsafe { std::alloc::dealloc(self.buffer.ptr, self.mem_layout) }
+    }
+}
+
+/// A frame backed by a sealed `memfd`, with all its planes in a single buffer.
+///
+/// The memory can be shared with other processes by sending the file descriptor returned by
+/// [`MemfdFrame::try_clone_fd`] along with the [`FrameLayout`], and imported on the other side
+/// with [`MemfdFrame::from_fd`]. The size of the memfd is sealed, so neither side can truncate it
+/// while the other has it mapped.
+#[derive(Debug)]
+pub struct MemfdFrame {
+    fd: OwnedFd,
+    pub(crate) buffer: FrameBuffer,
+    /// Size of the mapping, which covers the whole memfd.
+    pub(crate) map_size: usize,
+}
+
+impl MemfdFrame {
+    /// Seals applied to the memfd of every frame.
+    const SEALS: SealFlag = SealFlag::F_SEAL_SHRINK
+        .union(SealFlag::F_SEAL_GROW)
+        .union(SealFlag::F_SEAL_SEAL);
+
+    /// Allocates a frame of `format` with a resolution of at least `size`, aligned according to
+    /// `alignment`.
+    pub fn new(
+        format: PixelFormat,
+        size: Resolution,
+        alignment: FrameAlignment,
+    ) -> anyhow::Result<Self> {
+        let (layout, data_size) = alignment.layout(format, size)?;
+        let planes = FrameBuffer::plane_ranges(&layout)?;
+        let page_size = nix::unistd::sysconf(nix::unistd::SysconfVar::PAGE_SIZE)?
+            .and_then(|size| usize::try_from(size).ok())
+            .unwrap_or(4096);
+        let map_size = data_size.next_multiple_of(page_size);
+
+        let fd = memfd_create(
+            c"cros-codecs-frame",
+            MemFdCreateFlag::MFD_CLOEXEC | MemFdCreateFlag::MFD_ALLOW_SEALING,
+        )?;
+        // Safe because `memfd_create` returned a valid file descriptor that nothing else owns.
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        nix::unistd::ftruncate(fd.as_raw_fd(), i64::try_from(map_size)?)?;
+        fcntl(fd.as_raw_fd(), FcntlArg::F_ADD_SEALS(Self::SEALS))?;
+
+        Self::map(fd, map_size, planes, layout)
+    }
+
+    /// Allocates a frame that a decoder can output `format` into, i.e. using the layout of the
+    /// surface format the hardware decodes to for `format`.
+    pub fn for_decoded_format(
+        format: DecodedFormat,
+        size: Resolution,
+        alignment: FrameAlignment,
+    ) -> anyhow::Result<Self> {
+        Self::new(surface_format(format), size, alignment)
+    }
+
+    /// Imports a frame allocated by [`MemfdFrame::new`], possibly in another process, from its
+    /// file descriptor and layout.
+    ///
+    /// Fails if the memfd can still be shrunk or is too small for `layout`, or if the planes of
+    /// `layout` overlap or are not in increasing order.
+    pub fn from_fd(fd: OwnedFd, layout: FrameLayout) -> anyhow::Result<Self> {
+        let seals = SealFlag::from_bits_truncate(fcntl(fd.as_raw_fd(), FcntlArg::F_GET_SEALS)?);
+        if !seals.contains(SealFlag::F_SEAL_SHRINK) {
+            anyhow::bail!("memfd can be shrunk while mapped");
+        }
+
+        let planes = FrameBuffer::plane_ranges(&layout)?;
+        let data_size = planes.last().map_or(0, |range| range.end);
+
+        let map_size = usize::try_from(fstat(fd.as_raw_fd())?.st_size)?;
+        if map_size < data_size {
+            anyhow::bail!(
+                "memfd is {} bytes but the layout requires {}",
+                map_size,
+                data_size
+            );
+        }
+
+        Self::map(fd, map_size, planes, layout)
+    }
+
+    /// Maps the `map_size` bytes of `fd` into memory.
+    fn map(
+        fd: OwnedFd,
+        map_size: usize,
+        planes: Vec<std::ops::Range<usize>>,
+        layout: FrameLayout,
+    ) -> anyhow::Result<Self> {
+        let length =
+            NonZeroUsize::new(map_size).ok_or_else(|| anyhow::anyhow!("cannot map empty memfd"))?;
+        // Safe because we create a new shared mapping of a file descriptor we own, which cannot be
+        // shrunk, and only access it through `self`.
+        let ptr = unsafe {
+            mmap(
+                None,
+                length,
+                ProtFlags::PROT_READ | ProtFlags::PROT_WRITE,
+                MapFlags::MAP_SHARED,
+                fd.as_raw_fd(),
+                0,
+            )?
+        };
+
+        Ok(Self {
+            fd,
+            buffer: FrameBuffer {
+                ptr: ptr as *mut u8,
+                planes,
+                layout,
+            },
+            map_size,
+        })
+    }
+
+    /// Returns the layout of the frame.
+    pub fn layout(&self) -> &FrameLayout {
+        &self.buffer.layout
+    }
+
+    /// Returns a new file descriptor to the memory of the frame, e.g. to send it to another
+    /// process.
+    pub fn try_clone_fd(&self) -> std::io::Result<OwnedFd> {
+        self.fd.try_clone()
+    }
+
+    /// Returns a copy of the data of plane `index`, or `None` if the frame has no such plane.
+    ///
+    /// Other processes holding the memfd may write to it concurrently, so its memory is only ever
+    /// copied and never exposed as a slice. A copy made during a concurrent write may contain a mix
+    /// of the old and new data.
+    pub fn read_plane(&self, index: usize) -> Option<Vec<u8>> {
+        self.buffer.read_plane(index)
+    }
+
+    /// Overwrites the whole plane `index` with `data`.
+    ///
+    /// Fails if the frame has no such plane, or if `data` is not exactly the size of the plane.
+    pub fn write_plane(&mut self, index: usize, data: &[u8]) -> anyhow::Result<()> {
+        self.buffer.write_plane(index, data)
+    }
+}
+
+impl AsFd for MemfdFrame {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+impl Drop for MemfdFrame {
+    fn drop(&mut self) {
+        // Safe because we mapped `map_size` bytes at `ptr` and nothing references them anymore.
+        if let Err(e) = unsafe { munmap(self.buffer.ptr as *mut _, self.map_size) } {
+            log::error!("failed to unmap memfd frame: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ivf_iterator() {
+        const STREAM: &[u8] = include_bytes!("codec/vp9/test_data/test-25fps.vp9");
+
+        let mut ivf = IvfIterator::new(STREAM);
+        assert_eq!(
+            ivf.header().unwrap(),
+            IvfFileHeader {
+                fourcc: *b"VP90",
+                width: 320,
+                height: 240,
+                time_base: (1, 1000),
+            }
+        );
+
+        let first = ivf.next_frame().unwrap();
+        assert_eq!(first.offset, 44);
+        assert_eq!(first.pts, 0);
+        let second = ivf.next_frame().unwrap();
+        assert_eq!(second.offset, first.offset + first.data.len() + 12);
+        assert_eq!(second.pts, 40);
+        assert_eq!(ivf.count(), 248);
+
+        // Iteration stops on truncated frames and headers.
+        assert_eq!(IvfIterator::new(&STREAM[..second.offset - 1]).count(), 1);
+        assert_eq!(IvfIterator::new(&STREAM[..second.offset - 6]).count(), 1);
+        assert!(IvfIterator::new(&STREAM[..16]).header().is_err());
+        assert!(IvfIterator::new(&[0u8; 32]).header().is_err());
+    }
+
+    #[test]
+    fn user_frame_layouts() {
+        let size = Resolution::from((320, 240));
+        let formats = [
+            DecodedFormat::I420,
+            DecodedFormat::NV12,
+            DecodedFormat::I422,
+            DecodedFormat::I444,
+            DecodedFormat::I010,
+            DecodedFormat::I012,
+            DecodedFormat::I210,
+            DecodedFormat::I212,
+            DecodedFormat::I410,
+            DecodedFormat::I412,
+        ];
+        // (fourcc, [(offset, stride)]) of each format above.
+        type Expected = (&'static [u8; 4], &'static [(usize, usize)]);
+        let expected: [Expected; 10] = [
+            (b"NV12", &[(0, 320), (76800, 320)]),
+            (b"NV12", &[(0, 320), (76800, 320)]),
+            (b"422H", &[(0, 320), (76800, 192), (122880, 192)]),
+            (b"444P", &[(0, 320), (76800, 320), (153600, 320)]),
+            (b"P010", &[(0, 640), (153600, 640)]),
+            (b"P012", &[(0, 640), (153600, 640)]),
+            (b"Y210", &[(0, 1280)]),
+            (b"Y212", &[(0, 1280)]),
+            (b"Y410", &[(0, 1280)]),
+            (b"Y412", &[(0, 2560)]),
+        ];
+
+        for (format, (fourcc, planes)) in formats.into_iter().zip(expected) {
+            let frame = UserFrame::for_decoded_format(format, size, Default::default()).unwrap();
+            let layout = frame.layout();
+            assert_eq!(<[u8; 4]>::from(layout.format.0), *fourcc, "{:?}", format);
+            assert_eq!(layout.format.1, 0);
+            assert_eq!(layout.size, size);
+            assert_eq!(
+                layout
+                    .planes
+                    .iter()
+                    .map(|p| (p.offset, p.stride))
+                    .collect::<Vec<_>>(),
+                planes,
+                "{:?}",
+                format
+            );
+            assert_eq!(frame.buffer.ptr as usize % UserFrame::MEM_ALIGNMENT, 0);
+        }
+    }
+
+    #[test]
+    fn user_frame_alignment() {
+        let alignment = FrameAlignment {
+            width: 32,
+            height: 16,
+            stride: 128,
+        };
+        let frame =
+            UserFrame::new(PixelFormat::P010, Resolution::from((100, 50)), alignment).unwrap();
+        let layout = frame.layout();
+
+        assert_eq!(layout.size, Resolution::from((128, 64)));
+        // 128 * 2 bytes per line, already a multiple of 128.
+        assert_eq!(layout.planes[0].stride, 256);
+        assert_eq!(layout.planes[1].offset, 256 * 64);
+        assert_eq!(layout.planes[1].stride, 256);
+        assert_eq!(frame.as_bytes().len(), 256 * 64 + 256 * 32);
+
+        let frame = UserFrame::new(
+            DecodedFormat::I420.into(),
+            Resolution::from((100, 50)),
+            FrameAlignment {
+                width: 1,
+                height: 1,
+                stride: 64,
+            },
+        )
+        .unwrap();
+        let strides = frame
+            .layout()
+            .planes
+            .iter()
+            .map(|p| p.stride)
+            .collect::<Vec<_>>();
+        assert_eq!(strides, [128, 64, 64]);
+
+        assert!(UserFrame::new(
+            DecodedFormat::NV12.into(),
+            Resolution::from((0, 16)),
+            Default::default()
+        )
+        .is_err());
+        assert!(UserFrame::new(
+            DecodedFormat::NV12.into(),
+            Resolution::from((16, 16)),
+            FrameAlignment {
+                stride: 48,
+                ..Default::default()
+            }
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn memfd_frame_sharing() {
+        let mut frame = MemfdFrame::for_decoded_format(
+            DecodedFormat::I010,
+            Resolution::from((64, 32)),
+            Default::default(),
+        )
+        .unwrap();
+        assert_eq!(<[u8; 4]>::from(frame.layout().format.0), *b"P010");
+        assert_eq!(frame.read_plane(1).unwrap().len(), 128 * 16);
+        assert!(frame.read_plane(2).is_none());
+        frame.write_plane(1, &[0x42; 128 * 16]).unwrap();
+        assert!(frame.write_plane(1, &[0x42; 128]).is_err());
+        assert!(frame.write_plane(2, &[]).is_err());
+
+        // Writes through one mapping are visible through the other.
+        let mut imported =
+            MemfdFrame::from_fd(frame.try_clone_fd().unwrap(), frame.layout().clone()).unwrap();
+        assert!(imported.read_plane(0).unwrap().iter().all(|&b| b == 0));
+        assert!(imported.read_plane(1).unwrap().iter().all(|&b| b == 0x42));
+        let mut luma = imported.read_plane(0).unwrap();
+        luma[0] = 1;
+        imported.write_plane(0, &luma).unwrap();
+        assert_eq!(frame.read_plane(0).unwrap()[0], 1);
+
+        // The size of the memfd is sealed.
+        assert!(nix::unistd::ftruncate(frame.as_fd().as_raw_fd(), 0).is_err());
+
+        // Layouts that do not fit in the memfd are rejected.
+        let mut layout = frame.layout().clone();
+        layout.planes[1].offset += frame.map_size;
+        assert!(MemfdFrame::from_fd(frame.try_clone_fd().unwrap(), layout).is_err());
+        let mut layout = frame.layout().clone();
+        layout.planes[0].stride = 64;
+        assert!(MemfdFrame::from_fd(frame.try_clone_fd().unwrap(), layout).is_err());
+        // The chroma plane has 16 lines, so this stride wraps its size around to 0.
+        let mut layout = frame.layout().clone();
+        layout.planes[1].stride = 1 << 60;
+        assert!(MemfdFrame::from_fd(frame.try_clone_fd().unwrap(), layout).is_err());
+        let mut layout = frame.layout().clone();
+        layout.planes[1].offset = usize::MAX;
+        assert!(MemfdFrame::from_fd(frame.try_clone_fd().unwrap(), layout).is_err());
+        // Planes must follow each other without overlapping.
+        let mut layout = frame.layout().clone();
+        layout.planes.swap(0, 1);
+        assert!(MemfdFrame::from_fd(frame.try_clone_fd().unwrap(), layout).is_err());
+        let mut layout = frame.layout().clone();
+        layout.planes[1].offset -= 128;
+        assert!(MemfdFrame::from_fd(frame.try_clone_fd().unwrap(), layout).is_err());
+
+        // So are memfds that can be shrunk.
+        let fd = memfd_create(c"unsealed", MemFdCreateFlag::MFD_CLOEXEC).unwrap();
+        // Safe because `memfd_create` returned a valid file descriptor that nothing else owns.
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        nix::unistd::ftruncate(fd.as_raw_fd(), frame.map_size as i64).unwrap();
+        assert!(MemfdFrame::from_fd(fd, frame.layout().clone()).is_err());
+    }
+
+    #[test]
+    fn user_frame_planes() {
+        let mut frame = UserFrame::for_decoded_format(
+            DecodedFormat::NV12,
+            Resolution::from((64, 32)),
+            Default::default(),
+        )
+        .unwrap();
+
+        assert!(frame.as_bytes().iter().all(|&b| b == 0));
+        assert_eq!(frame.plane(0).unwrap().len(), 64 * 32);
+        assert_eq!(frame.plane(1).unwrap().len(), 64 * 16);
+        assert!(frame.plane(2).is_none());
+
+        frame.plane_mut(1).unwrap().fill(0x80);
+        assert_eq!(frame.as_bytes()[64 * 32 - 1], 0);
+        assert_eq!(frame.as_bytes()[64 * 32], 0x80);
+        assert!(frame.plane(1).unwrap().iter().all(|&b| b == 0x80));
+    }
+}
@@ -0,0 +1,974 @@
+// Copyright 2023 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+//! RFC 6381 codec strings, as used in the `codecs` parameter of MIME types, DASH and HLS
+//! manifests, or `MediaSource.isTypeSupported()`.
+//!
+//! The strings can be built from the parsed headers of a stream, or parsed back into the profile
+//! and level constraints they carry in order to check them against the capabilities of a decoder.
+//! The formats are the ones of ISO/IEC 14496-15 annex E for H.264 and H.265, of the "VP Codec ISO
+//! Media File Format Binding" for VP9 and of the "AV1 Codec ISO Media File Format Binding" for AV1.
+
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::anyhow;
+
+use crate::codec::h264::parser::Sps as H264Sps;
+use crate::codec::h265::parser::ProfileTierLevel;
+use crate::codec::h265::parser::Sps as H265Sps;
+use crate::codec::vp9::parser::BitDepth;
+use crate::codec::vp9::parser::ColorRange;
+use crate::codec::vp9::parser::ColorSpace;
+use crate::codec::vp9::parser::Header as Vp9Header;
+
+/// VP9 levels (Annex A of the VP9 specification) with their maximum luma sample rate, luma
+/// picture size and luma picture breadth.
+const VP9_LEVELS: [(u8, u64, u32, u32); 14] = [
+    (10, 829_440, 36_864, 512),
+    (11, 2_764_800, 73_728, 768),
+    (20, 4_608_000, 122_880, 960),
+    (21, 9_216_000, 245_760, 1_344),
+    (30, 20_736_000, 552_960, 2_048),
+    (31, 36_864_000, 983_040, 2_752),
+    (40, 83_558_400, 2_228_224, 4_160),
+    (41, 160_432_128, 2_228_224, 4_160),
+    (50, 311_951_360, 8_912_896, 8_384),
+    (51, 588_251_136, 8_912_896, 8_384),
+    (52, 1_176_502_272, 8_912_896, 8_384),
+    (60, 1_176_502_272, 35_651_584, 16_832),
+    (61, 2_353_004_544, 35_651_584, 16_832),
+    (62, 4_706_009_088, 35_651_584, 16_832),
+];
+
+/// Parses the dot-separated field `field` of a codec string, using `radix`.
+fn parse_field<T: TryFrom<u32>>(field: Option<&str>, radix: u32) -> anyhow::Result<T> {
+    let field = field.ok_or_else(|| anyhow!("missing field in codec string"))?;
+    if field.is_empty() || !field.chars().all(|c| c.is_digit(radix)) {
+        return Err(anyhow!("invalid codec string field {:?}", field));
+    }
+
+    u32::from_str_radix(field, radix)
+        .ok()
+        .and_then(|value| T::try_from(value).ok())
+        .ok_or_else(|| anyhow!("codec string field {:?} is out of range", field))
+}
+
+/// Writes `fields` separated by dots, omitting the trailing ones that are equal to their value in
+/// `defaults`.
+fn write_optional_fields(
+    f: &mut fmt::Formatter,
+    fields: &[String],
+    defaults: &[&str],
+) -> fmt::Result {
+    let len = fields
+        .iter()
+        .zip(defaults)
+        .rposition(|(field, default)| field != default)
+        .map_or(0, |pos| pos + 1);
+
+    for field in &fields[..len] {
+        write!(f, ".{}", field)?;
+    }
+
+    Ok(())
+}
+
+/// Returns the H.273 colour primaries, transfer characteristics and matrix coefficients matching
+/// a VP9 color space.
+fn vp9_colour_description(color_space: ColorSpace, bit_depth: BitDepth) -> (u8, u8, u8) {
+    match color_space {
+        ColorSpace::Unknown | ColorSpace::Reserved2 => (2, 2, 2),
+        ColorSpace::Bt601 => (5, 6, 5),
+        ColorSpace::Bt709 => (1, 1, 1),
+        ColorSpace::Smpte170 => (6, 6, 6),
+        ColorSpace::Smpte240 => (7, 7, 7),
+        ColorSpace::Bt2020 => match bit_depth {
+            BitDepth::Depth12 => (9, 15, 9),
+            _ => (9, 14, 9),
+        },
+        ColorSpace::CsSrgb => (1, 13, 0),
+    }
+}
+
+/// Returns the lowest VP9 level allowing frames of `width`x`height` pixels at `frame_rate` frames
+/// per second, or `None` if the stream exceeds the limits of all levels.
+pub fn vp9_level(width: u32, height: u32, frame_rate: f64) -> Option<u8> {
+    let picture_size = u64::from(width) * u64::from(height);
+    let sample_rate = picture_size as f64 * frame_rate;
+
+    VP9_LEVELS
+        .iter()
+        .find(|(_, max_sample_rate, max_picture_size, max_breadth)| {
+            sample_rate <= *max_sample_rate as f64
+                && picture_size <= u64::from(*max_picture_size)
+                && width.max(height) <= *max_breadth
+        })
+        .map(|(level, ..)| *level)
+}
+
+/// Parameters of an `avc1` or `avc3` codec string.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct H264CodecString {
+    /// Whether the parameter sets are sent in the samples (`avc3`) rather than in the sample
+    /// description (`avc1`).
+    pub in_band_parameter_sets: bool,
+    pub profile_idc: u8,
+    /// `constraint_set0_flag` to `constraint_set5_flag` from the most significant bit, followed
+    /// by two reserved bits.
+    pub constraint_flags: u8,
+    pub level_idc: u8,
+}
+
+impl H264CodecString {
+    /// Creates the codec string of a stream using `sps`.
+    pub fn from_sps(sps: &H264Sps) -> Self {
+        let constraint_flags = [
+            sps.constraint_set0_flag,
+            sps.constraint_set1_flag,
+            sps.constraint_set2_flag,
+            sps.constraint_set3_flag,
+            sps.constraint_set4_flag,
+            sps.constraint_set5_flag,
+        ]
+        .iter()
+        .enumerate()
+        .fold(0, |flags, (i, &flag)| flags | (u8::from(flag) << (7 - i)));
+
+        Self {
+            in_band_parameter_sets: false,
+            profile_idc: sps.profile_idc,
+            constraint_flags,
+            level_idc: sps.level_idc as u8,
+        }
+    }
+
+    /// Returns the level multiplied by 10, so level 1b, which is signaled either with a
+    /// `level_idc` of 9 or with `constraint_set3_flag` (A.3.1), sorts between levels 1 and 1.1.
+    fn level(&self) -> u32 {
+        let constraint_set3_flag = self.constraint_flags & 0x10 != 0;
+        let is_level_1b = self.level_idc == 9
+            || (self.level_idc == 11
+                && constraint_set3_flag
+                && matches!(self.profile_idc, 66 | 77 | 88));
+
+        if is_level_1b {
+            105
+        } else {
+            u32::from(self.level_idc) * 10
+        }
+    }
+
+    /// Whether a decoder for `capability` can decode the stream. Constrained Baseline streams
+    /// are also accepted by Main and High profile decoders.
+    fn is_supported_by(&self, capability: &Self) -> bool {
+        let constrained_baseline = self.profile_idc == 66 && self.constraint_flags & 0x40 != 0;
+        let profile_ok = self.profile_idc == capability.profile_idc
+            || (constrained_baseline && matches!(capability.profile_idc, 77 | 100));
+
+        profile_ok && self.level() <= capability.level()
+    }
+
+    fn parse(mut fields: std::str::Split<char>) -> anyhow::Result<Self> {
+        let field = fields
+            .next()
+            .ok_or_else(|| anyhow!("missing profile and level in avc codec string"))?;
+        if field.len() != 6 || fields.next().is_some() {
+            return Err(anyhow!("invalid avc codec string field {:?}", field));
+        }
+
+        let value: u32 = parse_field(Some(field), 16)?;
+
+        Ok(Self {
+            in_band_parameter_sets: false,
+            profile_idc: (value >> 16) as u8,
+            constraint_flags: (value >> 8) as u8,
+            level_idc: value as u8,
+        })
+    }
+}
+
+impl fmt::Display for H264CodecString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let fourcc = if self.in_band_parameter_sets {
+            "avc3"
+        } else {
+            "avc1"
+        };
+
+        write!(
+            f,
+            "{}.{:02x}{:02x}{:02x}",
+            fourcc, self.profile_idc, self.constraint_flags, self.level_idc
+        )
+    }
+}
+
+/// Parameters of an `hvc1` or `hev1` codec string.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct H265CodecString {
+    /// Whether the parameter sets are sent in the samples (`hev1`) rather than in the sample
+    /// description (`hvc1`).
+    pub in_band_parameter_sets: bool,
+    pub general_profile_space: u8,
+    pub general_profile_idc: u8,
+    /// `general_profile_compatibility_flag[j]` is bit `j`.
+    pub general_profile_compatibility_flags: u32,
+    pub general_tier_flag: bool,
+    pub general_level_idc: u8,
+    /// The 48 bits of `profile_tier_level()` starting with `general_progressive_source_flag`.
+    pub general_constraint_indicator_flags: [u8; 6],
+}
+
+impl H265CodecString {
+    /// Creates the codec string of a stream using `ptl` as its general profile, tier and level.
+    pub fn from_profile_tier_level(ptl: &ProfileTierLevel) -> Self {
+        let profile_is = |profiles: &[u8]| {
+            profiles.iter().any(|&p| {
+                ptl.general_profile_idc == p
+                    || ptl.general_profile_compatibility_flag[usize::from(p)]
+            })
+        };
+
+        // The meaning of the flags depends on the profile, as in 7.3.3.
+        let mut flags = vec![
+            ptl.general_progressive_source_flag,
+            ptl.general_interlaced_source_flag,
+            ptl.general_non_packed_constraint_flag,
+            ptl.general_frame_only_constraint_flag,
+        ];
+        if profile_is(&[4, 5, 6, 7, 8, 9, 10, 11]) {
+            flags.extend([
+                ptl.general_max_12bit_constraint_flag,
+                ptl.general_max_10bit_constraint_flag,
+                ptl.general_max_8bit_constraint_flag,
+                ptl.general_max_422chroma_constraint_flag,
+                ptl.general_max_420chroma_constraint_flag,
+                ptl.general_max_monochrome_constraint_flag,
+                ptl.general_intra_constraint_flag,
+                ptl.general_one_picture_only_constraint_flag,
+                ptl.general_lower_bit_rate_constraint_flag,
+            ]);
+            if profile_is(&[5, 9, 10, 11]) {
+                flags.push(ptl.general_max_14bit_constraint_flag);
+            }
+        } else if profile_is(&[2]) {
+            flags.extend([false; 7]);
+            flags.push(ptl.general_one_picture_only_constraint_flag);
+        }
+        flags.resize(47, false);
+        flags.push(profile_is(&[1, 2, 3, 4, 5, 9, 11]) && ptl.general_inbld_flag);
+
+        let mut general_constraint_indicator_flags = [0u8; 6];
+        for (i, flag) in flags.into_iter().enumerate() {
+            general_constraint_indicator_flags[i / 8] |= u8::from(flag) << (7 - i % 8);
+        }
+
+        let general_profile_compatibility_flags = ptl
+            .general_profile_compatibility_flag
+            .iter()
+            .enumerate()
+            .fold(0, |flags, (j, &flag)| flags | (u32::from(flag) << j));
+
+        Self {
+            in_band_parameter_sets: false,
+            general_profile_space: ptl.general_profile_space,
+            general_profile_idc: ptl.general_profile_idc,
+            general_profile_compatibility_flags,
+            general_tier_flag: ptl.general_tier_flag,
+            general_level_idc: ptl.general_level_idc as u8,
+            general_constraint_indicator_flags,
+        }
+    }
+
+    /// Creates the codec string of a stream using `sps`.
+    pub fn from_sps(sps: &H265Sps) -> Self {
+        Self::from_profile_tier_level(&sps.profile_tier_level)
+    }
+
+    /// Whether a decoder for `capability` can decode the stream, i.e. whether the stream
+    /// conforms to the profile of the decoder, and its tier and level are not higher.
+    fn is_supported_by(&self, capability: &Self) -> bool {
+        let profile_ok = self.general_profile_space == capability.general_profile_space
+            && (self.general_profile_idc == capability.general_profile_idc
+                || capability.general_profile_idc < 32
+                    && self.general_profile_compatibility_flags
+                        & (1 << capability.general_profile_idc)
+                        != 0);
+
+        profile_ok
+            && self.general_tier_flag <= capability.general_tier_flag
+            && self.general_level_idc <= capability.general_level_idc
+    }
+
+    fn parse(mut fields: std::str::Split<char>) -> anyhow::Result<Self> {
+        let profile = fields
+            .next()
+            .ok_or_else(|| anyhow!("missing profile in hevc codec string"))?;
+        let (general_profile_space, profile) = match profile.chars().next() {
+            Some(c @ 'A'..='C') => (c as u8 - b'A' + 1, &profile[1..]),
+            _ => (0, profile),
+        };
+        let general_profile_idc = parse_field(Some(profile), 10)?;
+
+        let compatibility = fields.next();
+        if compatibility.is_some_and(|c| c.len() > 8) {
+            return Err(anyhow!("invalid profile compatibility {:?}", compatibility));
+        }
+        let general_profile_compatibility_flags = parse_field(compatibility, 16)?;
+
+        let level = fields
+            .next()
+            .ok_or_else(|| anyhow!("missing tier and level in hevc codec string"))?;
+        let general_tier_flag = match level.chars().next() {
+            Some('L') => false,
+            Some('H') => true,
+            _ => return Err(anyhow!("invalid tier {:?}", level)),
+        };
+        let general_level_idc = parse_field(Some(&level[1..]), 10)?;
+
+        let mut general_constraint_indicator_flags = [0u8; 6];
+        for (i, field) in fields.enumerate() {
+            if i >= 6 || field.len() > 2 {
+                return Err(anyhow!("invalid constraint flags {:?}", field));
+            }
+            general_constraint_indicator_flags[i] = parse_field(Some(field), 16)?;
+        }
+
+        Ok(Self {
+            in_band_parameter_sets: false,
+            general_profile_space,
+            general_profile_idc,
+            general_profile_compatibility_flags,
+            general_tier_flag,
+            general_level_idc,
+            general_constraint_indicator_flags,
+        })
+    }
+}
+
+impl fmt::Display for H265CodecString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let fourcc = if self.in_band_parameter_sets {
+            "hev1"
+        } else {
+            "hvc1"
+        };
+        let profile_space = ["", "A", "B", "C"][usize::from(self.general_profile_space & 0x3)];
+        let tier = if self.general_tier_flag { 'H' } else { 'L' };
+
+        // The compatibility flags are written in the reverse of their bitstream order, i.e. with
+        // general_profile_compatibility_flag[0] as the least significant bit.
+        write!(
+            f,
+            "{}.{}{}.{:X}.{}{}",
+            fourcc,
+            profile_space,
+            self.general_profile_idc,
+            self.general_profile_compatibility_flags,
+            tier,
+            self.general_level_idc
+        )?;
+
+        // Trailing zero bytes are omitted.
+        let flags = &self.general_constraint_indicator_flags;
+        let len = flags.iter().rposition(|&b| b != 0).map_or(0, |pos| pos + 1);
+        for byte in &flags[..len] {
+            write!(f, ".{:X}", byte)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Parameters of a `vp09` codec string.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Vp9CodecString {
+    pub profile: u8,
+    /// The level multiplied by 10, e.g. 31 for level 3.1.
+    pub level: u8,
+    pub bit_depth: u8,
+    /// 0 and 1 are 4:2:0 with the chroma samples between the luma rows or co-sited with the
+    /// top-left luma sample respectively, 2 is 4:2:2 and 3 is 4:4:4.
+    pub chroma_subsampling: u8,
+    pub colour_primaries: u8,
+    pub transfer_characteristics: u8,
+    pub matrix_coefficients: u8,
+    pub video_full_range_flag: bool,
+}
+
+impl Default for Vp9CodecString {
+    /// The values the optional fields take when omitted.
+    fn default() -> Self {
+        Self {
+            profile: 0,
+            level: 10,
+            bit_depth: 8,
+            chroma_subsampling: 1,
+            colour_primaries: 1,
+            transfer_characteristics: 1,
+            matrix_coefficients: 1,
+            video_full_range_flag: false,
+        }
+    }
+}
+
+impl Vp9CodecString {
+    /// Creates the codec string of a stream of level `level`, e.g. computed with [`vp9_level`],
+    /// using the colour configuration of `header`, which must be the header of a key frame or of
+    /// an intra-only frame.
+    ///
+    /// VP9 only signals the matrix coefficients, so the colour primaries and transfer
+    /// characteristics are the ones usually associated with them.
+    pub fn from_header(header: &Vp9Header, level: u8) -> anyhow::Result<Self> {
+        if !VP9_LEVELS.iter().any(|(l, ..)| *l == level) {
+            return Err(anyhow!("invalid VP9 level {}", level));
+        }
+
+        let chroma_subsampling = match (header.subsampling_x, header.subsampling_y) {
+            // VP9 does not signal the chroma sample position, use the one of libvpx.
+            (true, true) => 1,
+            (true, false) => 2,
+            (false, false) => 3,
+            (false, true) => return Err(anyhow!("4:4:0 subsampling has no codec string")),
+        };
+
+        let (colour_primaries, transfer_characteristics, matrix_coefficients) =
+            vp9_colour_description(header.color_space, header.bit_depth);
+
+        Ok(Self {
+            profile: header.profile as u8,
+            level,
+            bit_depth: header.bit_depth as u8,
+            chroma_subsampling,
+            colour_primaries,
+            transfer_characteristics,
+            matrix_coefficients,
+            video_full_range_flag: header.color_range == ColorRange::FullSwing,
+        })
+    }
+
+    fn is_supported_by(&self, capability: &Self) -> bool {
+        self.profile == capability.profile
+            && self.level <= capability.level
+            && self.bit_depth <= capability.bit_depth
+    }
+
+    fn parse(mut fields: std::str::Split<char>) -> anyhow::Result<Self> {
+        let profile = parse_field(fields.next(), 10)?;
+        let level = parse_field(fields.next(), 10)?;
+        let bit_depth = parse_field(fields.next(), 10)?;
+
+        let defaults = Self::default();
+        let mut optional = |default: u8| -> anyhow::Result<u8> {
+            match fields.next() {
+                Some(field) => parse_field(Some(field), 10),
+                None => Ok(default),
+            }
+        };
+
+        let parsed = Self {
+            profile,
+            level,
+            bit_depth,
+            chroma_subsampling: optional(defaults.chroma_subsampling)?,
+            colour_primaries: optional(defaults.colour_primaries)?,
+            transfer_characteristics: optional(defaults.transfer_characteristics)?,
+            matrix_coefficients: optional(defaults.matrix_coefficients)?,
+            video_full_range_flag: match optional(0)? {
+                0 => false,
+                1 => true,
+                flag => return Err(anyhow!("invalid video full range flag {}", flag)),
+            },
+        };
+
+        if fields.next().is_some() {
+            return Err(anyhow!("too many fields in vp09 codec string"));
+        }
+
+        Ok(parsed)
+    }
+}
+
+impl fmt::Display for Vp9CodecString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "vp09.{:02}.{:02}.{:02}",
+            self.profile, self.level, self.bit_depth
+        )?;
+
+        let fields = [
+            format!("{:02}", self.chroma_subsampling),
+            format!("{:02}", self.colour_primaries),
+            format!("{:02}", self.transfer_characteristics),
+            format!("{:02}", self.matrix_coefficients),
+            format!("{:02}", u8::from(self.video_full_range_flag)),
+        ];
+        write_optional_fields(f, &fields, &["01", "01", "01", "01", "00"])
+    }
+}
+
+/// Parameters of an `av01` codec string.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Av1CodecString {
+    pub seq_profile: u8,
+    /// `seq_level_idx` of the first operating point.
+    pub seq_level_idx: u8,
+    /// `seq_tier` of the first operating point.
+    pub seq_tier: bool,
+    pub bit_depth: u8,
+    pub mono_chrome: bool,
+    pub subsampling_x: bool,
+    pub subsampling_y: bool,
+    pub chroma_sample_position: u8,
+    pub colour_primaries: u8,
+    pub transfer_characteristics: u8,
+    pub matrix_coefficients: u8,
+    pub color_range: bool,
+}
+
+impl Default for Av1CodecString {
+    /// The values the optional fields take when omitted.
+    fn default() -> Self {
+        Self {
+            seq_profile: 0,
+            seq_level_idx: 0,
+            seq_tier: false,
+            bit_depth: 8,
+            mono_chrome: false,
+            subsampling_x: true,
+            subsampling_y: true,
+            chroma_sample_position: 0,
+            colour_primaries: 1,
+            transfer_characteristics: 1,
+            matrix_coefficients: 1,
+            color_range: false,
+        }
+    }
+}
+
+impl Av1CodecString {
+    fn is_supported_by(&self, capability: &Self) -> bool {
+        self.seq_profile == capability.seq_profile
+            && (self.seq_level_idx, self.seq_tier)
+                <= (capability.seq_level_idx, capability.seq_tier)
+            && self.bit_depth <= capability.bit_depth
+    }
+
+    fn parse(mut fields: std::str::Split<char>) -> anyhow::Result<Self> {
+        let mut parsed = Self {
+            seq_profile: parse_field(fields.next(), 10)?,
+            ..Default::default()
+        };
+
+        let level = fields
+            .next()
+            .ok_or_else(|| anyhow!("missing level in av01 codec string"))?;
+        let (level, tier) = level.split_at(level.len().saturating_sub(1));
+        parsed.seq_level_idx = parse_field(Some(level), 10)?;
+        parsed.seq_tier = match tier {
+            "M" => false,
+            "H" => true,
+            _ => return Err(anyhow!("invalid tier {:?}", tier)),
+        };
+        parsed.bit_depth = parse_field(fields.next(), 10)?;
+
+        let flag = |field: &str| match field {
+            "0" => Ok(false),
+            "1" => Ok(true),
+            _ => Err(anyhow!("invalid flag {:?} in av01 codec string", field)),
+        };
+
+        if let Some(field) = fields.next() {
+            parsed.mono_chrome = flag(field)?;
+        }
+        if let Some(field) = fields.next() {
+            if field.len() != 3 {
+                return Err(anyhow!("invalid chroma subsampling {:?}", field));
+            }
+            parsed.subsampling_x = flag(&field[0..1])?;
+            parsed.subsampling_y = flag(&field[1..2])?;
+            parsed.chroma_sample_position = parse_field(Some(&field[2..3]), 10)?;
+        }
+        if let Some(field) = fields.next() {
+            parsed.colour_primaries = parse_field(Some(field), 10)?;
+        }
+        if let Some(field) = fields.next() {
+            parsed.transfer_characteristics = parse_field(Some(field), 10)?;
+        }
+        if let Some(field) = fields.next() {
+            parsed.matrix_coefficients = parse_field(Some(field), 10)?;
+        }
+        if let Some(field) = fields.next() {
+            parsed.color_range = flag(field)?;
+        }
+
+        if fields.next().is_some() {
+            return Err(anyhow!("too many fields in av01 codec string"));
+        }
+
+        Ok(parsed)
+    }
+}
+
+impl fmt::Display for Av1CodecString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "av01.{}.{:02}{}.{:02}",
+            self.seq_profile,
+            self.seq_level_idx,
+            if self.seq_tier { 'H' } else { 'M' },
+            self.bit_depth
+        )?;
+
+        let fields = [
+            u8::from(self.mono_chrome).to_string(),
+            format!(
+                "{}{}{}",
+                u8::from(self.subsampling_x),
+                u8::from(self.subsampling_y),
+                self.chroma_sample_position
+            ),
+            format!("{:02}", self.colour_primaries),
+            format!("{:02}", self.transfer_characteristics),
+            format!("{:02}", self.matrix_coefficients),
+            u8::from(self.color_range).to_string(),
+        ];
+        write_optional_fields(f, &fields, &["0", "110", "01", "01", "01", "0"])
+    }
+}
+
+/// A codec string, see the [module documentation](self).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CodecString {
+    H264(H264CodecString),
+    H265(H265CodecString),
+    Vp9(Vp9CodecString),
+    Av1(Av1CodecString),
+}
+
+impl CodecString {
+    /// Whether a decoder able to decode streams described by `capability` can decode the stream
+    /// described by `self`, considering the profile, tier, level and bit depth.
+    pub fn is_supported_by(&self, capability: &Self) -> bool {
+        match (self, capability) {
+            (CodecString::H264(s), CodecString::H264(c)) => s.is_supported_by(c),
+            (CodecString::H265(s), CodecString::H265(c)) => s.is_supported_by(c),
+            (CodecString::Vp9(s), CodecString::Vp9(c)) => s.is_supported_by(c),
+            (CodecString::Av1(s), CodecString::Av1(c)) => s.is_supported_by(c),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for CodecString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CodecString::H264(s) => s.fmt(f),
+            CodecString::H265(s) => s.fmt(f),
+            CodecString::Vp9(s) => s.fmt(f),
+            CodecString::Av1(s) => s.fmt(f),
+        }
+    }
+}
+
+impl FromStr for CodecString {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        // Fields are sliced by byte offset.
+        if !s.is_ascii() {
+            return Err(anyhow!("invalid codec string {:?}", s));
+        }
+
+        let mut fields = s.split('.');
+
+        match fields.next() {
+            Some(fourcc @ ("avc1" | "avc3")) => Ok(CodecString::H264(H264CodecString {
+                in_band_parameter_sets: fourcc == "avc3",
+                ..H264CodecString::parse(fields)?
+            })),
+            Some(fourcc @ ("hvc1" | "hev1")) => Ok(CodecString::H265(H265CodecString {
+                in_band_parameter_sets: fourcc == "hev1",
+                ..H265CodecString::parse(fields)?
+            })),
+            Some("vp09") => Ok(CodecString::Vp9(Vp9CodecString::parse(fields)?)),
+            Some("av01") => Ok(CodecString::Av1(Av1CodecString::parse(fields)?)),
+            _ => Err(anyhow!("unsupported codec string {:?}", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::codec::h264::parser::Nalu as H264Nalu;
+    use crate::codec::h264::parser::NaluType as H264NaluType;
+    use crate::codec::h264::parser::Parser as H264Parser;
+    use crate::codec::h265::parser::Nalu as H265Nalu;
+    use crate::codec::h265::parser::NaluType as H265NaluType;
+    use crate::codec::h265::parser::Parser as H265Parser;
+    use crate::codec::vp9::parser::Parser as Vp9Parser;
+    use crate::codec::vp9::parser::Profile;
+    use crate::utils::IvfIterator;
+    use crate::utils::NalIterator;
+
+    fn parse(s: &str) -> CodecString {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn from_h264_sps() {
+        let stream = include_bytes!("../codec/h264/test_data/64x64-I-P-B-P-high.h264");
+        let mut parser = H264Parser::default();
+        let nalu = NalIterator::<H264Nalu<_>>::new(stream)
+            .map(|data| H264Nalu::next(&mut Cursor::new(data)).unwrap())
+            .find(|nalu| nalu.header().nalu_type() == H264NaluType::Sps)
+            .unwrap();
+        let sps = parser.parse_sps(&nalu).unwrap();
+
+        let codec_string = H264CodecString::from_sps(sps);
+        assert_eq!(codec_string.to_string(), "avc1.640014");
+        assert_eq!(parse("avc1.640014"), CodecString::H264(codec_string));
+    }
+
+    #[test]
+    fn from_h265_sps() {
+        let stream = include_bytes!("../codec/h265/test_data/bbb.h265");
+        let mut parser = H265Parser::default();
+        let nalu = NalIterator::<H265Nalu<_>>::new(stream)
+            .map(|data| H265Nalu::next(&mut Cursor::new(data)).unwrap())
+            .find(|nalu| nalu.header().nalu_type() == H265NaluType::SpsNut)
+            .unwrap();
+        let sps = parser.parse_sps(&nalu).unwrap();
+
+        // Main profile, also compatible with Main 10, level 2, progressive frames.
+        let codec_string = H265CodecString::from_sps(sps);
+        assert_eq!(codec_string.to_string(), "hvc1.1.6.L60.90");
+        assert_eq!(parse("hvc1.1.6.L60.90"), CodecString::H265(codec_string));
+    }
+
+    #[test]
+    fn from_vp9_header() {
+        let stream = include_bytes!("../codec/vp9/test_data/test-25fps.vp9");
+        let packet = IvfIterator::new(stream).next().unwrap();
+        let mut frames = Vp9Parser::default().parse_chunk(packet).unwrap();
+        let mut header = frames.remove(0).header;
+
+        let level = vp9_level(header.width, header.height, 25.0).unwrap();
+        assert_eq!((header.width, header.height, level), (320, 240, 20));
+
+        // The stream does not signal its color space.
+        let codec_string = Vp9CodecString::from_header(&header, level).unwrap();
+        assert_eq!(codec_string.to_string(), "vp09.00.20.08.01.02.02.02");
+        assert_eq!(
+            parse("vp09.00.20.08.01.02.02.02"),
+            CodecString::Vp9(codec_string)
+        );
+
+        header.color_space = ColorSpace::Bt709;
+        let codec_string = Vp9CodecString::from_header(&header, level).unwrap();
+        assert_eq!(codec_string.to_string(), "vp09.00.20.08");
+
+        header.profile = Profile::Profile2;
+        header.bit_depth = BitDepth::Depth10;
+        header.color_space = ColorSpace::Bt2020;
+        header.color_range = ColorRange::FullSwing;
+        let codec_string = Vp9CodecString::from_header(&header, 62).unwrap();
+        assert_eq!(codec_string.to_string(), "vp09.02.62.10.01.09.14.09.01");
+
+        header.subsampling_x = false;
+        assert!(Vp9CodecString::from_header(&header, 62).is_err());
+        assert!(Vp9CodecString::from_header(&header, 12).is_err());
+
+        assert_eq!(vp9_level(1920, 1080, 30.0), Some(40));
+        assert_eq!(vp9_level(1920, 1080, 60.0), Some(41));
+        assert_eq!(vp9_level(3840, 2160, 30.0), Some(50));
+        assert_eq!(vp9_level(4000, 100, 1.0), Some(40));
+        assert_eq!(vp9_level(16384, 16384, 120.0), None);
+    }
+
+    #[test]
+    fn parse_strings() {
+        assert_eq!(
+            parse("avc1.64001f"),
+            CodecString::H264(H264CodecString {
+                in_band_parameter_sets: false,
+                profile_idc: 100,
+                constraint_flags: 0,
+                level_idc: 31,
+            })
+        );
+        assert_eq!(
+            parse("avc3.42E01E"),
+            CodecString::H264(H264CodecString {
+                in_band_parameter_sets: true,
+                profile_idc: 66,
+                constraint_flags: 0xe0,
+                level_idc: 30,
+            })
+        );
+
+        assert_eq!(
+            parse("hvc1.1.6.L93.B0"),
+            CodecString::H265(H265CodecString {
+                in_band_parameter_sets: false,
+                general_profile_space: 0,
+                general_profile_idc: 1,
+                general_profile_compatibility_flags: 0x6,
+                general_tier_flag: false,
+                general_level_idc: 93,
+                general_constraint_indicator_flags: [0xb0, 0, 0, 0, 0, 0],
+            })
+        );
+        assert_eq!(
+            parse("hev1.A4.10.H153.9D.8"),
+            CodecString::H265(H265CodecString {
+                in_band_parameter_sets: true,
+                general_profile_space: 1,
+                general_profile_idc: 4,
+                general_profile_compatibility_flags: 0x10,
+                general_tier_flag: true,
+                general_level_idc: 153,
+                general_constraint_indicator_flags: [0x9d, 0x08, 0, 0, 0, 0],
+            })
+        );
+
+        assert_eq!(
+            parse("vp09.00.10.08"),
+            CodecString::Vp9(Vp9CodecString {
+                profile: 0,
+                level: 10,
+                bit_depth: 8,
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            parse("vp09.01.31.08.03"),
+            CodecString::Vp9(Vp9CodecString {
+                profile: 1,
+                level: 31,
+                bit_depth: 8,
+                chroma_subsampling: 3,
+                ..Default::default()
+            })
+        );
+
+        assert_eq!(
+            parse("av01.0.04M.08"),
+            CodecString::Av1(Av1CodecString {
+                seq_level_idx: 4,
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            parse("av01.2.15H.12.1.111.09.16.09.1"),
+            CodecString::Av1(Av1CodecString {
+                seq_profile: 2,
+                seq_level_idx: 15,
+                seq_tier: true,
+                bit_depth: 12,
+                mono_chrome: true,
+                subsampling_x: true,
+                subsampling_y: true,
+                chroma_sample_position: 1,
+                colour_primaries: 9,
+                transfer_characteristics: 16,
+                matrix_coefficients: 9,
+                color_range: true,
+            })
+        );
+
+        // The strings are written back in their shortest form.
+        for s in [
+            "avc1.64001f",
+            "avc3.42e01e",
+            "hvc1.1.6.L93.B0",
+            "hev1.A4.10.H153.9D.8",
+            "hvc1.2.4.L120",
+            "vp09.00.10.08",
+            "vp09.01.31.08.03",
+            "vp09.02.10.10.01.09.16.09.01",
+            "av01.0.04M.08",
+            "av01.1.08H.10.0.000",
+            "av01.2.15H.12.1.111.09.16.09.1",
+        ] {
+            assert_eq!(parse(s).to_string(), s);
+        }
+        assert_eq!(
+            parse("vp09.00.10.08.01.01.01.01.00").to_string(),
+            "vp09.00.10.08"
+        );
+        assert_eq!(parse("av01.0.04M.08.0.110.01").to_string(), "av01.0.04M.08");
+    }
+
+    #[test]
+    fn invalid_strings() {
+        for s in [
+            "",
+            "mp4a.40.2",
+            "avc1",
+            "avc1.64001",
+            "avc1.64001f.1",
+            "avc1.64001g",
+            "hvc1.1",
+            "hvc1.1.6",
+            "hvc1.1.6.X93",
+            "hvc1.1.123456789.L93",
+            "hvc1.1.6.L93.B0.0.0.0.0.0.0",
+            "hvc1.1.6.L93.100",
+            "vp09.00.10",
+            "vp09.00.10.08.01.01.01.01.02",
+            "vp09.00.10.08.01.01.01.01.00.00",
+            "vp09.00.1a.08",
+            "vp09.00.300.08",
+            "av01.0.04.08",
+            "av01.0.04M",
+            "av01.0.04M.08.2",
+            "av01.0.04M.08.0.11",
+            "av01.0.04M.08.0.110.01.01.01.0.0",
+            "av01.0.0\u{e9}M.08",
+        ] {
+            assert!(s.parse::<CodecString>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn capabilities() {
+        let supported =
+            |stream: &str, capability: &str| parse(stream).is_supported_by(&parse(capability));
+
+        assert!(supported("avc1.64001f", "avc1.640028"));
+        assert!(!supported("avc1.640028", "avc1.64001f"));
+        assert!(!supported("avc1.64001f", "avc1.4d0028"));
+        // Constrained Baseline can be decoded by High profile decoders, and level 1b is between
+        // levels 1 and 1.1.
+        assert!(supported("avc1.42e01e", "avc1.64001e"));
+        assert!(!supported("avc1.42001e", "avc1.64001e"));
+        assert!(supported("avc1.42f00b", "avc1.42c00b"));
+        assert!(!supported("avc1.42f00b", "avc1.42c00a"));
+
+        assert!(supported("hvc1.1.6.L93.B0", "hvc1.1.6.L120"));
+        assert!(supported("hvc1.1.6.L93.B0", "hvc1.2.4.L93"));
+        assert!(!supported("hvc1.2.4.L93", "hvc1.1.6.L93"));
+        assert!(!supported("hvc1.1.6.H93", "hvc1.1.6.L150"));
+        assert!(supported("hvc1.1.6.L93", "hev1.1.6.H93"));
+
+        assert!(supported("vp09.00.31.08", "vp09.00.41.10"));
+        assert!(!supported("vp09.00.41.08", "vp09.00.31.08"));
+        assert!(!supported("vp09.02.31.10", "vp09.00.31.10"));
+        assert!(!supported("vp09.02.31.10", "vp09.02.31.08"));
+
+        assert!(supported("av01.0.04M.08", "av01.0.08M.10"));
+        assert!(supported("av01.0.04H.08", "av01.0.05M.08"));
+        assert!(!supported("av01.0.04H.08", "av01.0.04M.08"));
+        assert!(!supported("av01.1.04M.08", "av01.0.08M.08"));
+
+        assert!(!supported("vp09.00.10.08", "av01.0.04M.08"));
+    }
+}
@@ -0,0 +1,506 @@
+// Copyright 2023 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+//! Measurement of the differences between decoded frames.
+//!
+//! Checksums only tell whether two frames are identical. The metrics computed here also tell by
+//! how much they differ and where, which helps telling a broken decoder from one that merely
+//! rounds differently from the reference.
+//!
+//! Frames are expected with the layout written by
+//! [`MappableHandle::read`](crate::decoder::MappableHandle::read), i.e.
+//! [`PixelFormat::packed_layout`].
+
+use std::fmt::Display;
+use std::io::ErrorKind;
+use std::io::Read;
+
+use anyhow::anyhow;
+use anyhow::Context;
+
+use crate::utils::convert;
+use crate::utils::convert::PixelFormat;
+use crate::utils::convert::Planar;
+use crate::utils::y4m::Y4mReader;
+use crate::DecodedFormat;
+use crate::Resolution;
+
+/// Size of the square windows over which the SSIM is computed.
+const SSIM_WINDOW: usize = 8;
+/// Distance between two consecutive SSIM windows, which overlap.
+const SSIM_STEP: usize = 4;
+
+/// Differences between two planes of the same size.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PlaneMetrics {
+    /// Peak signal-to-noise ratio in dB, infinite if the planes are identical.
+    pub psnr: f64,
+    /// Structural similarity index, between -1 and 1, 1 meaning that the planes are identical.
+    pub ssim: f64,
+    /// Largest absolute difference between two co-located samples.
+    pub max_abs_diff: u16,
+    /// Position of the first sample with the largest difference, as (x, y) coordinates in the
+    /// plane.
+    pub max_abs_diff_position: (usize, usize),
+}
+
+impl PlaneMetrics {
+    /// Computes the differences between the planes `a` and `b` of `width` samples per line and
+    /// `bit_depth` bits per sample.
+    fn new(a: &[u16], b: &[u16], width: usize, bit_depth: u8) -> Self {
+        let peak = f64::from((1u32 << bit_depth) - 1);
+
+        let mut squared_error = 0u64;
+        let mut max_abs_diff = 0;
+        let mut max_abs_diff_index = 0;
+        for (i, (&a, &b)) in a.iter().zip(b).enumerate() {
+            let diff = a.abs_diff(b);
+            squared_error += u64::from(diff) * u64::from(diff);
+            if diff > max_abs_diff {
+                max_abs_diff = diff;
+                max_abs_diff_index = i;
+            }
+        }
+
+        let psnr = match squared_error {
+            0 => f64::INFINITY,
+            _ => {
+                let mse = squared_error as f64 / a.len() as f64;
+                10.0 * (peak * peak / mse).log10()
+            }
+        };
+
+        Self {
+            psnr,
+            ssim: ssim(a, b, width, a.len() / width, peak),
+            max_abs_diff,
+            max_abs_diff_position: (max_abs_diff_index % width, max_abs_diff_index / width),
+        }
+    }
+}
+
+/// Returns the mean SSIM of the planes `a` and `b` of `width` by `height` samples of maximum value
+/// `peak`, computed over overlapping square windows.
+fn ssim(a: &[u16], b: &[u16], width: usize, height: usize, peak: f64) -> f64 {
+    let c1 = (0.01 * peak).powi(2);
+    let c2 = (0.03 * peak).powi(2);
+    // Planes smaller than a window are covered by a single one.
+    let (window_width, window_height) = (SSIM_WINDOW.min(width), SSIM_WINDOW.min(height));
+    let num_samples = (window_width * window_height) as f64;
+
+    let mut sum = 0.0;
+    let mut num_windows = 0;
+    for y in (0..=height - window_height).step_by(SSIM_STEP) {
+        for x in (0..=width - window_width).step_by(SSIM_STEP) {
+            let (mut sum_a, mut sum_b) = (0u64, 0u64);
+            let (mut sum_aa, mut sum_bb, mut sum_ab) = (0u64, 0u64, 0u64);
+
+            for line in y..y + window_height {
+                let start = line * width + x;
+                let lines = a[start..start + window_width]
+                    .iter()
+                    .zip(&b[start..start + window_width]);
+                for (&a, &b) in lines {
+                    let (a, b) = (u64::from(a), u64::from(b));
+                    sum_a += a;
+                    sum_b += b;
+                    sum_aa += a * a;
+                    sum_bb += b * b;
+                    sum_ab += a * b;
+                }
+            }
+
+            let mean_a = sum_a as f64 / num_samples;
+            let mean_b = sum_b as f64 / num_samples;
+            let var_a = sum_aa as f64 / num_samples - mean_a * mean_a;
+            let var_b = sum_bb as f64 / num_samples - mean_b * mean_b;
+            let covar = sum_ab as f64 / num_samples - mean_a * mean_b;
+
+            sum += ((2.0 * mean_a * mean_b + c1) * (2.0 * covar + c2))
+                / ((mean_a * mean_a + mean_b * mean_b + c1) * (var_a + var_b + c2));
+            num_windows += 1;
+        }
+    }
+
+    sum / f64::from(num_windows)
+}
+
+/// Differences between two frames.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrameMetrics {
+    /// Metrics of the Y, U and V planes.
+    pub planes: [PlaneMetrics; 3],
+}
+
+impl FrameMetrics {
+    /// Whether the frames are identical.
+    pub fn is_identical(&self) -> bool {
+        self.planes.iter().all(|plane| plane.max_abs_diff == 0)
+    }
+}
+
+impl Display for FrameMetrics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, (name, plane)) in ["Y", "U", "V"].iter().zip(&self.planes).enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(
+                f,
+                "{}: PSNR {:.2} dB, SSIM {:.4}, max diff {} at {:?}",
+                name, plane.psnr, plane.ssim, plane.max_abs_diff, plane.max_abs_diff_position
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads `frame`, of `format` and `size`, into its planes.
+fn read_frame(frame: &[u8], format: DecodedFormat, size: Resolution) -> anyhow::Result<Planar> {
+    let format = PixelFormat::from(format);
+
+    convert::read(
+        frame,
+        &format.packed_layout(size),
+        format,
+        size.width as usize,
+        size.height as usize,
+    )
+}
+
+/// Checks that `a` and `b` can be compared sample by sample.
+fn check_comparable(a: &Planar, b: &Planar) -> anyhow::Result<()> {
+    if (a.width, a.height) != (b.width, b.height) {
+        return Err(anyhow!(
+            "cannot compare frames of size {}x{} and {}x{}",
+            a.width,
+            a.height,
+            b.width,
+            b.height
+        ));
+    }
+
+    if a.bit_depth != b.bit_depth || a.subsampling != b.subsampling {
+        return Err(anyhow!(
+            "cannot compare frames of different bit depth or chroma subsampling"
+        ));
+    }
+
+    Ok(())
+}
+
+/// Computes the differences between `a` and `b`.
+fn compare_planar(a: &Planar, b: &Planar) -> anyhow::Result<FrameMetrics> {
+    check_comparable(a, b)?;
+
+    let (chroma_width, _) = a.chroma_size();
+    let widths = [a.width, chroma_width, chroma_width];
+
+    Ok(FrameMetrics {
+        planes: [0, 1, 2]
+            .map(|i| PlaneMetrics::new(&a.planes[i], &b.planes[i], widths[i], a.bit_depth)),
+    })
+}
+
+/// Computes the differences between the frames `a` and `b`, both of `format` and `size`.
+pub fn compare_frames(
+    a: &[u8],
+    b: &[u8],
+    format: DecodedFormat,
+    size: Resolution,
+) -> anyhow::Result<FrameMetrics> {
+    compare_planar(&read_frame(a, format, size)?, &read_frame(b, format, size)?)
+}
+
+/// Returns a frame of `format` and `size` showing where the frames `a` and `b`, of the same format
+/// and size, differ.
+///
+/// The luma of each pixel is the largest absolute difference between its samples in `a` and `b`,
+/// multiplied by `gain`, and the chroma is neutral. Identical frames thus give a black picture.
+pub fn diff_frame(
+    a: &[u8],
+    b: &[u8],
+    format: DecodedFormat,
+    size: Resolution,
+    gain: u16,
+) -> anyhow::Result<Vec<u8>> {
+    let a = read_frame(a, format, size)?;
+    let b = read_frame(b, format, size)?;
+    check_comparable(&a, &b)?;
+
+    let peak = (1u16 << a.bit_depth) - 1;
+    let (chroma_width, chroma_height) = a.chroma_size();
+    let (shift_x, shift_y) = (a.subsampling.0 as usize, a.subsampling.1 as usize);
+    let abs_diff =
+        |plane: usize, index: usize| a.planes[plane][index].abs_diff(b.planes[plane][index]);
+
+    let mut luma = Vec::with_capacity(a.width * a.height);
+    for y in 0..a.height {
+        for x in 0..a.width {
+            let chroma_index = (y >> shift_y) * chroma_width + (x >> shift_x);
+            let diff = abs_diff(0, y * a.width + x)
+                .max(abs_diff(1, chroma_index))
+                .max(abs_diff(2, chroma_index));
+            luma.push(diff.saturating_mul(gain).min(peak));
+        }
+    }
+
+    let neutral = vec![1 << (a.bit_depth - 1); chroma_width * chroma_height];
+    let diff = Planar {
+        planes: [luma, neutral.clone(), neutral],
+        ..a
+    };
+
+    let format = PixelFormat::from(format);
+    let mut frame = vec![0; format.packed_size(size)];
+    convert::write(&diff, &mut frame, &format.packed_layout(size), format)?;
+
+    Ok(frame)
+}
+
+/// Source of the reference frames of a [`ReferenceComparator`].
+enum ReferenceSource<R: Read> {
+    Y4m(Y4mReader<R>),
+    Raw {
+        reader: R,
+        format: DecodedFormat,
+        size: Resolution,
+    },
+}
+
+/// Compares decoded frames against the frames of a reference file, one after the other.
+pub struct ReferenceComparator<R: Read> {
+    source: ReferenceSource<R>,
+    /// Current reference frame.
+    frame: Vec<u8>,
+    frames_compared: usize,
+}
+
+impl<R: Read> ReferenceComparator<R> {
+    /// Compares against the frames of a Y4M file.
+    pub fn from_y4m(reader: Y4mReader<R>) -> Self {
+        Self {
+            source: ReferenceSource::Y4m(reader),
+            frame: Vec::new(),
+            frames_compared: 0,
+        }
+    }
+
+    /// Compares against a raw file made of frames of `format` and `size` without any padding, as
+    /// written by `ccdec`.
+    pub fn from_raw(reader: R, format: DecodedFormat, size: Resolution) -> Self {
+        Self {
+            source: ReferenceSource::Raw {
+                reader,
+                format,
+                size,
+            },
+            frame: Vec::new(),
+            frames_compared: 0,
+        }
+    }
+
+    /// Returns the number of frames compared so far.
+    pub fn frames_compared(&self) -> usize {
+        self.frames_compared
+    }
+
+    /// Reads the next reference frame into `self.frame`, and returns its format and size.
+    fn read_reference(&mut self) -> anyhow::Result<(DecodedFormat, Resolution)> {
+        let (read, format, size) = match &mut self.source {
+            ReferenceSource::Y4m(reader) => {
+                let read = reader.read_frame_into(&mut self.frame)?;
+                let header = reader.header();
+                (read, header.format, header.size)
+            }
+            ReferenceSource::Raw {
+                reader,
+                format,
+                size,
+            } => {
+                self.frame
+                    .resize(PixelFormat::from(*format).packed_size(*size), 0);
+                let read = match reader.read_exact(&mut self.frame) {
+                    Ok(()) => true,
+                    Err(e) if e.kind() == ErrorKind::UnexpectedEof => false,
+                    Err(e) => return Err(e).context("failed to read reference frame"),
+                };
+                (read, *format, *size)
+            }
+        };
+
+        if !read {
+            return Err(anyhow!(
+                "reference only has {} frames",
+                self.frames_compared
+            ));
+        }
+
+        Ok((format, size))
+    }
+
+    /// Compares `frame`, of `format` and `size`, against the next reference frame.
+    ///
+    /// The frames can be of different formats, e.g. NV12 and I420, as long as they have the same
+    /// bit depth and chroma subsampling.
+    pub fn compare_next(
+        &mut self,
+        frame: &[u8],
+        format: DecodedFormat,
+        size: Resolution,
+    ) -> anyhow::Result<FrameMetrics> {
+        let (ref_format, ref_size) = self.read_reference()?;
+        let metrics = compare_planar(
+            &read_frame(frame, format, size)?,
+            &read_frame(&self.frame, ref_format, ref_size)?,
+        )
+        .with_context(|| format!("failed to compare frame {}", self.frames_compared))?;
+        self.frames_compared += 1;
+
+        Ok(metrics)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::utils::y4m::Y4mHeader;
+    use crate::utils::y4m::Y4mWriter;
+
+    /// Returns an I420 frame of `size` with a gradient in each plane.
+    fn gradient(size: Resolution) -> Vec<u8> {
+        let format = PixelFormat::from(DecodedFormat::I420);
+        (0..format.packed_size(size))
+            .map(|i| (i % 251) as u8)
+            .collect()
+    }
+
+    #[test]
+    fn identical_frames() {
+        let size = Resolution::from((64, 32));
+        let frame = gradient(size);
+        let metrics = compare_frames(&frame, &frame, DecodedFormat::I420, size).unwrap();
+
+        assert!(metrics.is_identical());
+        for plane in &metrics.planes {
+            assert_eq!(plane.psnr, f64::INFINITY);
+            assert!((plane.ssim - 1.0).abs() < 1e-9);
+        }
+
+        let diff = diff_frame(&frame, &frame, DecodedFormat::I420, size, 16).unwrap();
+        assert!(diff[..64 * 32].iter().all(|&b| b == 0));
+        assert!(diff[64 * 32..].iter().all(|&b| b == 128));
+    }
+
+    #[test]
+    fn different_frames() {
+        let size = Resolution::from((64, 32));
+        let a = gradient(size);
+        let mut b = a.clone();
+        // Off by one everywhere in the luma plane, and by 10 in one sample of the V plane.
+        for sample in &mut b[..64 * 32] {
+            *sample ^= 1;
+        }
+        let v_offset = 64 * 32 + 32 * 16;
+        b[v_offset + 3 * 32 + 5] = b[v_offset + 3 * 32 + 5].wrapping_add(10);
+
+        let metrics = compare_frames(&a, &b, DecodedFormat::I420, size).unwrap();
+        assert!(!metrics.is_identical());
+
+        let [y, u, v] = metrics.planes;
+        // An error of 1 on every sample gives an MSE of 1.
+        assert!((y.psnr - 20.0 * 255f64.log10()).abs() < 1e-9);
+        assert!(y.ssim < 1.0 && y.ssim > 0.99);
+        assert_eq!(y.max_abs_diff, 1);
+        assert_eq!(y.max_abs_diff_position, (0, 0));
+        assert_eq!(u.psnr, f64::INFINITY);
+        assert_eq!(v.max_abs_diff, 10);
+        assert_eq!(v.max_abs_diff_position, (5, 3));
+        assert!(v.psnr > y.psnr);
+
+        // The luma of the diff shows where each plane differs.
+        let diff = diff_frame(&a, &b, DecodedFormat::I420, size, 4).unwrap();
+        assert_eq!(diff[0], 4);
+        assert_eq!(diff[6 * 64 + 10], 40);
+        assert_eq!(diff[7 * 64 + 11], 40);
+        assert_eq!(diff[8 * 64 + 10], 4);
+    }
+
+    #[test]
+    fn high_bit_depth() {
+        let size = Resolution::from((16, 16));
+        let format = PixelFormat::from(DecodedFormat::I010);
+        let a = vec![0; format.packed_size(size)];
+        let mut b = a.clone();
+        // Largest 10-bit value.
+        b[0..2].copy_from_slice(&1023u16.to_le_bytes());
+
+        let metrics = compare_frames(&a, &b, DecodedFormat::I010, size).unwrap();
+        assert_eq!(metrics.planes[0].max_abs_diff, 1023);
+
+        let diff = diff_frame(&a, &b, DecodedFormat::I010, size, 2).unwrap();
+        assert_eq!(&diff[0..2], &1023u16.to_le_bytes());
+        assert_eq!(&diff[256 * 2..256 * 2 + 2], &512u16.to_le_bytes());
+    }
+
+    #[test]
+    fn reference_comparator() {
+        let size = Resolution::from((32, 16));
+        let frames = [gradient(size), vec![0x40; gradient(size).len()]];
+
+        let mut writer =
+            Y4mWriter::new(Vec::new(), Y4mHeader::new(DecodedFormat::I420, size)).unwrap();
+        for frame in &frames {
+            writer.write_frame(frame).unwrap();
+        }
+        let y4m = writer.into_inner().unwrap();
+
+        // NV12 frames can be compared against an I420 reference.
+        let nv12 = PixelFormat::from(DecodedFormat::NV12);
+        let i420 = PixelFormat::from(DecodedFormat::I420);
+        let mut decoded = vec![0; nv12.packed_size(size)];
+        convert::convert(
+            &frames[0],
+            &i420.packed_layout(size),
+            &mut decoded,
+            &nv12.packed_layout(size),
+            Default::default(),
+        )
+        .unwrap();
+
+        let mut comparator =
+            ReferenceComparator::from_y4m(Y4mReader::new(Cursor::new(&y4m)).unwrap());
+        assert!(comparator
+            .compare_next(&decoded, DecodedFormat::NV12, size)
+            .unwrap()
+            .is_identical());
+        assert!(!comparator
+            .compare_next(&frames[0], DecodedFormat::I420, size)
+            .unwrap()
+            .is_identical());
+        assert_eq!(comparator.frames_compared(), 2);
+        assert!(comparator
+            .compare_next(&frames[0], DecodedFormat::I420, size)
+            .is_err());
+
+        let raw = frames.concat().repeat(2);
+        let mut comparator =
+            ReferenceComparator::from_raw(Cursor::new(&raw), DecodedFormat::I420, size);
+        assert!(comparator
+            .compare_next(&frames[0], DecodedFormat::I420, size)
+            .unwrap()
+            .is_identical());
+        // Frames of a different size or bit depth cannot be compared.
+        assert!(comparator
+            .compare_next(&frames[1], DecodedFormat::I420, Resolution::from((16, 32)))
+            .is_err());
+        let i010 = vec![0; PixelFormat::from(DecodedFormat::I010).packed_size(size)];
+        assert!(comparator
+            .compare_next(&i010, DecodedFormat::I010, size)
+            .is_err());
+    }
+}
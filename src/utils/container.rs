@@ -0,0 +1,90 @@
+// Copyright 2023 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+//! Demuxers extracting encoded video samples from container files.
+//!
+//! The demuxers of this module only take care of locating the video samples and their timing
+//! information, and of presenting them in the form expected by the stateless decoders (e.g. Annex
+//! B byte-stream for H.264 and H.265). They never look into the encoded data itself beyond what is
+//! needed for that purpose.
+
+pub mod mp4;
+pub mod ts;
+
+use anyhow::anyhow;
+
+/// Simple big-endian reader over a byte slice, returning an error instead of panicking when
+/// reading past the end of the data.
+pub(crate) struct ByteReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    /// Returns the number of bytes that are left to read.
+    pub(crate) fn remaining(&self) -> usize {
+        self.data.len() - self.pos
+    }
+
+    /// Returns the current read position from the start of the data.
+    pub(crate) fn position(&self) -> usize {
+        self.pos
+    }
+
+    pub(crate) fn read_bytes(&mut self, len: usize) -> anyhow::Result<&'a [u8]> {
+        if self.remaining() < len {
+            return Err(anyhow!(
+                "unexpected end of data: need {} bytes, only {} remaining",
+                len,
+                self.remaining()
+            ));
+        }
+
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+
+        Ok(bytes)
+    }
+
+    pub(crate) fn skip(&mut self, len: usize) -> anyhow::Result<()> {
+        self.read_bytes(len).map(|_| ())
+    }
+
+    pub(crate) fn read_u8(&mut self) -> anyhow::Result<u8> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    pub(crate) fn read_u16(&mut self) -> anyhow::Result<u16> {
+        let b = self.read_bytes(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    pub(crate) fn read_u24(&mut self) -> anyhow::Result<u32> {
+        let b = self.read_bytes(3)?;
+        Ok(u32::from_be_bytes([0, b[0], b[1], b[2]]))
+    }
+
+    pub(crate) fn read_u32(&mut self) -> anyhow::Result<u32> {
+        let b = self.read_bytes(4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    pub(crate) fn read_u64(&mut self) -> anyhow::Result<u64> {
+        let b = self.read_bytes(8)?;
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(b);
+        Ok(u64::from_be_bytes(bytes))
+    }
+
+    /// Returns all the data that has not been read yet, and moves the position to the end.
+    pub(crate) fn read_to_end(&mut self) -> &'a [u8] {
+        let bytes = &self.data[self.pos..];
+        self.pos = self.data.len();
+        bytes
+    }
+}
@@ -0,0 +1,1657 @@
+// Copyright 2023 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+//! ISO Base Media File Format (MP4) demuxer.
+//!
+//! [`Mp4Reader`] parses the `moov` box of a file as well as any movie fragment (`moof`) that
+//! follows, and builds the list of samples of every H.264, H.265, VP9 or AV1 video track it
+//! finds. Other tracks are ignored.
+//!
+//! The samples can then be extracted as [`Packet`]s, which are directly suitable as input for the
+//! stateless decoder of the corresponding codec: H.264 and H.265 samples are converted from the
+//! length-prefixed format used by MP4 into Annex B byte-stream format, and the parameter sets
+//! stored in the decoder configuration record are inserted before the first sample and every
+//! sync sample.
+//!
+//! Edit lists are not applied, so the timestamps are the raw media timestamps of the track.
+
+use anyhow::anyhow;
+use anyhow::Context;
+
+use crate::utils::container::ByteReader;
+
+/// Flag of the sample flags indicating that a sample is not a sync sample.
+const SAMPLE_IS_NON_SYNC_SAMPLE: u32 = 0x1_0000;
+
+/// Codec of a video track.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Codec {
+    H264,
+    H265,
+    Vp9,
+    Av1,
+}
+
+/// AVC decoder configuration record, as stored in the `avcC` box (ISO/IEC 14496-15 5.3.3.1).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AvcConfig {
+    /// Profile code as defined in ISO/IEC 14496-10.
+    pub profile_indication: u8,
+    /// Byte occurring between the profile_idc and level_idc in the SPS.
+    pub profile_compatibility: u8,
+    /// Level code as defined in ISO/IEC 14496-10.
+    pub level_indication: u8,
+    /// Length in bytes of the NALUnitLength field preceding each NAL unit of a sample.
+    pub nal_length_size: u8,
+    /// Sequence parameter set NAL units, without start code.
+    pub sps: Vec<Vec<u8>>,
+    /// Picture parameter set NAL units, without start code.
+    pub pps: Vec<Vec<u8>>,
+}
+
+impl AvcConfig {
+    fn parse(data: &[u8]) -> anyhow::Result<Self> {
+        let mut r = ByteReader::new(data);
+
+        let version = r.read_u8()?;
+        if version != 1 {
+            return Err(anyhow!("unsupported avcC version {}", version));
+        }
+
+        let profile_indication = r.read_u8()?;
+        let profile_compatibility = r.read_u8()?;
+        let level_indication = r.read_u8()?;
+        let nal_length_size = (r.read_u8()? & 0x3) + 1;
+
+        let num_sps = r.read_u8()? & 0x1f;
+        let sps = (0..num_sps)
+            .map(|_| read_u16_prefixed(&mut r))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let num_pps = r.read_u8()?;
+        let pps = (0..num_pps)
+            .map(|_| read_u16_prefixed(&mut r))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        // The extension present for high profiles only contains information that can also be
+        // found in the SPS, so we don't need it.
+
+        Ok(Self {
+            profile_indication,
+            profile_compatibility,
+            level_indication,
+            nal_length_size,
+            sps,
+            pps,
+        })
+    }
+}
+
+/// An array of NAL units of the same type in a [`HevcConfig`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HevcNalArray {
+    /// Whether all NAL units of this type are in the array and none are in the stream.
+    pub array_completeness: bool,
+    /// Type of the NAL units in the array (VPS, SPS, PPS or SEI).
+    pub nal_unit_type: u8,
+    /// NAL units of the array, without start code.
+    pub nalus: Vec<Vec<u8>>,
+}
+
+/// HEVC decoder configuration record, as stored in the `hvcC` box (ISO/IEC 14496-15 8.3.3.1).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HevcConfig {
+    pub general_profile_space: u8,
+    pub general_tier_flag: bool,
+    pub general_profile_idc: u8,
+    pub general_profile_compatibility_flags: u32,
+    /// The 48 bits of the general constraint indicator flags.
+    pub general_constraint_indicator_flags: u64,
+    pub general_level_idc: u8,
+    pub min_spatial_segmentation_idc: u16,
+    pub parallelism_type: u8,
+    pub chroma_format_idc: u8,
+    pub bit_depth_luma_minus8: u8,
+    pub bit_depth_chroma_minus8: u8,
+    /// Average frame rate in units of frames/(256 seconds), or 0 if unspecified.
+    pub avg_frame_rate: u16,
+    pub constant_frame_rate: u8,
+    pub num_temporal_layers: u8,
+    pub temporal_id_nested: bool,
+    /// Length in bytes of the NALUnitLength field preceding each NAL unit of a sample.
+    pub nal_length_size: u8,
+    /// Parameter sets and SEI NAL units.
+    pub arrays: Vec<HevcNalArray>,
+}
+
+impl HevcConfig {
+    fn parse(data: &[u8]) -> anyhow::Result<Self> {
+        let mut r = ByteReader::new(data);
+
+        let version = r.read_u8()?;
+        if version != 1 {
+            return Err(anyhow!("unsupported hvcC version {}", version));
+        }
+
+        let byte = r.read_u8()?;
+        let general_profile_space = byte >> 6;
+        let general_tier_flag = (byte >> 5) & 0x1 != 0;
+        let general_profile_idc = byte & 0x1f;
+        let general_profile_compatibility_flags = r.read_u32()?;
+        let general_constraint_indicator_flags =
+            (u64::from(r.read_u16()?) << 32) | u64::from(r.read_u32()?);
+        let general_level_idc = r.read_u8()?;
+        let min_spatial_segmentation_idc = r.read_u16()? & 0xfff;
+        let parallelism_type = r.read_u8()? & 0x3;
+        let chroma_format_idc = r.read_u8()? & 0x3;
+        let bit_depth_luma_minus8 = r.read_u8()? & 0x7;
+        let bit_depth_chroma_minus8 = r.read_u8()? & 0x7;
+        let avg_frame_rate = r.read_u16()?;
+
+        let byte = r.read_u8()?;
+        let constant_frame_rate = byte >> 6;
+        let num_temporal_layers = (byte >> 3) & 0x7;
+        let temporal_id_nested = (byte >> 2) & 0x1 != 0;
+        let nal_length_size = (byte & 0x3) + 1;
+
+        let num_arrays = r.read_u8()?;
+        let mut arrays = Vec::with_capacity(usize::from(num_arrays));
+        for _ in 0..num_arrays {
+            let byte = r.read_u8()?;
+            let num_nalus = r.read_u16()?;
+            let nalus = (0..num_nalus)
+                .map(|_| read_u16_prefixed(&mut r))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+
+            arrays.push(HevcNalArray {
+                array_completeness: byte >> 7 != 0,
+                nal_unit_type: byte & 0x3f,
+                nalus,
+            });
+        }
+
+        Ok(Self {
+            general_profile_space,
+            general_tier_flag,
+            general_profile_idc,
+            general_profile_compatibility_flags,
+            general_constraint_indicator_flags,
+            general_level_idc,
+            min_spatial_segmentation_idc,
+            parallelism_type,
+            chroma_format_idc,
+            bit_depth_luma_minus8,
+            bit_depth_chroma_minus8,
+            avg_frame_rate,
+            constant_frame_rate,
+            num_temporal_layers,
+            temporal_id_nested,
+            nal_length_size,
+            arrays,
+        })
+    }
+}
+
+/// VP codec configuration record, as stored in the `vpcC` box ("VP Codec ISO Media File Format
+/// Binding", version 1).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct VpcConfig {
+    pub profile: u8,
+    pub level: u8,
+    pub bit_depth: u8,
+    pub chroma_subsampling: u8,
+    pub video_full_range_flag: bool,
+    pub colour_primaries: u8,
+    pub transfer_characteristics: u8,
+    pub matrix_coefficients: u8,
+    /// Must be empty for VP9.
+    pub codec_initialization_data: Vec<u8>,
+}
+
+impl VpcConfig {
+    fn parse(data: &[u8]) -> anyhow::Result<Self> {
+        let mut r = ByteReader::new(data);
+
+        let (version, _) = read_full_box_header(&mut r)?;
+        if version != 1 {
+            return Err(anyhow!("unsupported vpcC version {}", version));
+        }
+
+        let profile = r.read_u8()?;
+        let level = r.read_u8()?;
+        let byte = r.read_u8()?;
+        let colour_primaries = r.read_u8()?;
+        let transfer_characteristics = r.read_u8()?;
+        let matrix_coefficients = r.read_u8()?;
+        let codec_initialization_data = read_u16_prefixed(&mut r)?;
+
+        Ok(Self {
+            profile,
+            level,
+            bit_depth: byte >> 4,
+            chroma_subsampling: (byte >> 1) & 0x7,
+            video_full_range_flag: byte & 0x1 != 0,
+            colour_primaries,
+            transfer_characteristics,
+            matrix_coefficients,
+            codec_initialization_data,
+        })
+    }
+}
+
+/// AV1 codec configuration record, as stored in the `av1C` box ("AV1 Codec ISO Media File Format
+/// Binding", section 2.3).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Av1Config {
+    pub seq_profile: u8,
+    pub seq_level_idx_0: u8,
+    pub seq_tier_0: bool,
+    pub high_bitdepth: bool,
+    pub twelve_bit: bool,
+    pub monochrome: bool,
+    pub chroma_subsampling_x: bool,
+    pub chroma_subsampling_y: bool,
+    pub chroma_sample_position: u8,
+    pub initial_presentation_delay_minus_one: Option<u8>,
+    /// Sequence header and metadata OBUs, in low overhead bitstream format.
+    pub config_obus: Vec<u8>,
+}
+
+impl Av1Config {
+    fn parse(data: &[u8]) -> anyhow::Result<Self> {
+        let mut r = ByteReader::new(data);
+
+        let byte = r.read_u8()?;
+        if byte >> 7 != 1 || byte & 0x7f != 1 {
+            return Err(anyhow!("invalid av1C marker or version {:#x}", byte));
+        }
+
+        let byte = r.read_u8()?;
+        let seq_profile = byte >> 5;
+        let seq_level_idx_0 = byte & 0x1f;
+
+        let byte = r.read_u8()?;
+        let flag = |shift: u8| (byte >> shift) & 0x1 != 0;
+
+        let delay = r.read_u8()?;
+        let initial_presentation_delay_minus_one = if (delay >> 4) & 0x1 != 0 {
+            Some(delay & 0xf)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            seq_profile,
+            seq_level_idx_0,
+            seq_tier_0: flag(7),
+            high_bitdepth: flag(6),
+            twelve_bit: flag(5),
+            monochrome: flag(4),
+            chroma_subsampling_x: flag(3),
+            chroma_subsampling_y: flag(2),
+            chroma_sample_position: byte & 0x3,
+            initial_presentation_delay_minus_one,
+            config_obus: r.read_to_end().to_vec(),
+        })
+    }
+}
+
+/// Decoder configuration of a track, taken from its sample description.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CodecConfig {
+    Avc(AvcConfig),
+    Hevc(HevcConfig),
+    Vp9(VpcConfig),
+    Av1(Av1Config),
+}
+
+impl CodecConfig {
+    /// Returns the codec this configuration is for.
+    pub fn codec(&self) -> Codec {
+        match self {
+            CodecConfig::Avc(_) => Codec::H264,
+            CodecConfig::Hevc(_) => Codec::H265,
+            CodecConfig::Vp9(_) => Codec::Vp9,
+            CodecConfig::Av1(_) => Codec::Av1,
+        }
+    }
+
+    /// Returns the size of the length field preceding each NAL unit in a sample, or `None` if
+    /// the samples of this codec are not made of NAL units.
+    pub fn nal_length_size(&self) -> Option<u8> {
+        match self {
+            CodecConfig::Avc(config) => Some(config.nal_length_size),
+            CodecConfig::Hevc(config) => Some(config.nal_length_size),
+            CodecConfig::Vp9(_) | CodecConfig::Av1(_) => None,
+        }
+    }
+
+    /// Returns the NAL units of the configuration record that need to be sent to the decoder
+    /// before the first sample.
+    fn parameter_sets(&self) -> Vec<&[u8]> {
+        match self {
+            CodecConfig::Avc(config) => config
+                .sps
+                .iter()
+                .chain(config.pps.iter())
+                .map(|n| n.as_slice())
+                .collect(),
+            CodecConfig::Hevc(config) => config
+                .arrays
+                .iter()
+                .flat_map(|a| a.nalus.iter())
+                .map(|n| n.as_slice())
+                .collect(),
+            CodecConfig::Vp9(_) | CodecConfig::Av1(_) => vec![],
+        }
+    }
+}
+
+/// A sample of a track, i.e. the encoded data of one frame.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Sample {
+    /// Offset of the sample data from the start of the file.
+    pub offset: u64,
+    /// Size of the sample data in bytes.
+    pub size: u32,
+    /// Decode timestamp, in units of the track's timescale.
+    pub dts: u64,
+    /// Presentation timestamp, in units of the track's timescale. Can be negative if the stream
+    /// uses negative composition offsets.
+    pub pts: i64,
+    /// Duration of the sample, in units of the track's timescale.
+    pub duration: u32,
+    /// Whether the sample is a sync sample, i.e. decoding can start from it.
+    pub is_sync: bool,
+}
+
+/// A video track of an MP4 file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Track {
+    /// ID of the track, as found in its `tkhd` box.
+    pub id: u32,
+    /// Number of time units per second for the timestamps of the track.
+    pub timescale: u32,
+    /// Width of the video as found in the sample description.
+    pub width: u16,
+    /// Height of the video as found in the sample description.
+    pub height: u16,
+    /// Decoder configuration record of the track.
+    pub config: CodecConfig,
+    /// Samples of the track, in decode order.
+    pub samples: Vec<Sample>,
+    /// Decode timestamp of the next sample, used to place fragments that lack a `tfdt` box.
+    next_dts: u64,
+    /// Default values for the samples of fragments, from the `trex` box.
+    trex: TrackExtends,
+}
+
+impl Track {
+    /// Returns the codec of the track.
+    pub fn codec(&self) -> Codec {
+        self.config.codec()
+    }
+}
+
+/// An encoded frame ready to be fed to a stateless decoder.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Packet {
+    /// Encoded data of the frame, in the format expected by the decoder.
+    pub data: Vec<u8>,
+    /// Decode timestamp, in units of the track's timescale.
+    pub dts: u64,
+    /// Presentation timestamp, in units of the track's timescale.
+    pub pts: i64,
+    /// Duration of the frame, in units of the track's timescale.
+    pub duration: u32,
+    /// Whether decoding can start from this frame.
+    pub is_sync: bool,
+}
+
+impl AsRef<[u8]> for Packet {
+    fn as_ref(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+/// Default sample values set by a `trex` box.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct TrackExtends {
+    default_sample_duration: u32,
+    default_sample_size: u32,
+    default_sample_flags: u32,
+}
+
+/// A box as found in the file.
+struct Mp4Box<'a> {
+    /// Four-character code of the box.
+    kind: [u8; 4],
+    /// Offset of the start of the box (i.e. of its header) from the start of the file.
+    start: u64,
+    /// Content of the box, after its header.
+    payload: &'a [u8],
+}
+
+/// Iterator over the boxes contained in a piece of data.
+struct BoxIterator<'a> {
+    reader: ByteReader<'a>,
+    /// Offset of the data from the start of the file.
+    base: u64,
+}
+
+impl<'a> BoxIterator<'a> {
+    fn new(data: &'a [u8], base: u64) -> Self {
+        Self {
+            reader: ByteReader::new(data),
+            base,
+        }
+    }
+
+    fn read_box(&mut self) -> anyhow::Result<Mp4Box<'a>> {
+        let start = self.base + self.reader.position() as u64;
+        let available = self.reader.remaining() as u64;
+
+        let size = self.reader.read_u32()?;
+        let mut kind = [0u8; 4];
+        kind.copy_from_slice(self.reader.read_bytes(4)?);
+
+        let (size, header_size) = match size {
+            // Box extends to the end of the data.
+            0 => (available, 8),
+            1 => (self.reader.read_u64()?, 16),
+            size => (u64::from(size), 8),
+        };
+
+        let header_size = if &kind == b"uuid" {
+            self.reader.skip(16)?;
+            header_size + 16
+        } else {
+            header_size
+        };
+
+        if size < header_size || size > available {
+            return Err(anyhow!(
+                "invalid size {} for box {}",
+                size,
+                String::from_utf8_lossy(&kind)
+            ));
+        }
+
+        let payload = self.reader.read_bytes((size - header_size) as usize)?;
+
+        Ok(Mp4Box {
+            kind,
+            start,
+            payload,
+        })
+    }
+}
+
+impl<'a> Iterator for BoxIterator<'a> {
+    type Item = anyhow::Result<Mp4Box<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.reader.remaining() == 0 {
+            return None;
+        }
+
+        let res = self.read_box();
+        if res.is_err() {
+            // Do not try to make sense of what follows an invalid box.
+            let _ = self.reader.read_to_end();
+        }
+
+        Some(res)
+    }
+}
+
+/// Returns the first child box of `data` with type `kind`, if any.
+fn find_box<'a>(data: &'a [u8], kind: &[u8; 4]) -> anyhow::Result<Option<Mp4Box<'a>>> {
+    for b in BoxIterator::new(data, 0) {
+        let b = b?;
+        if &b.kind == kind {
+            return Ok(Some(b));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Reads the version and flags of a full box.
+fn read_full_box_header(r: &mut ByteReader) -> anyhow::Result<(u8, u32)> {
+    let version = r.read_u8()?;
+    let flags = r.read_u24()?;
+
+    Ok((version, flags))
+}
+
+/// Reads a piece of data prefixed by its 16-bit length.
+fn read_u16_prefixed(r: &mut ByteReader) -> anyhow::Result<Vec<u8>> {
+    let len = r.read_u16()?;
+    Ok(r.read_bytes(usize::from(len))?.to_vec())
+}
+
+/// Reads a 32-bit (version 0) or 64-bit (version 1) value.
+fn read_versioned_u64(r: &mut ByteReader, version: u8) -> anyhow::Result<u64> {
+    if version == 1 {
+        r.read_u64()
+    } else {
+        r.read_u32().map(u64::from)
+    }
+}
+
+/// Checks that `count` samples of `size` bytes fit in a file of `file_size` bytes, so sample
+/// counts read from the file cannot make us allocate more memory than the file justifies. Empty
+/// samples are counted as one byte.
+fn check_sample_count(count: u32, size: u32, file_size: u64) -> anyhow::Result<()> {
+    if u64::from(count) * u64::from(size.max(1)) > file_size {
+        return Err(anyhow!(
+            "{} samples of {} bytes do not fit in the file",
+            count,
+            size
+        ));
+    }
+
+    Ok(())
+}
+
+/// Raw content of the sample tables of a `stbl` box.
+#[derive(Default)]
+struct SampleTables {
+    /// Sample sizes from `stsz`, or `None` if the box was not present.
+    sizes: Option<Vec<u32>>,
+    /// Chunk offsets from `stco` or `co64`.
+    chunk_offsets: Vec<u64>,
+    /// `(first_chunk, samples_per_chunk)` entries from `stsc`.
+    sample_to_chunk: Vec<(u32, u32)>,
+    /// `(sample_count, sample_delta)` entries from `stts`.
+    time_to_sample: Vec<(u32, u32)>,
+    /// `(sample_count, sample_offset)` entries from `ctts`.
+    composition_offsets: Vec<(u32, i32)>,
+    /// 1-based indices of the sync samples from `stss`, or `None` if all samples are sync
+    /// samples.
+    sync_samples: Option<Vec<u32>>,
+}
+
+impl SampleTables {
+    /// Parses the tables of `stbl`, the sample table of a track from a file of `file_size` bytes.
+    fn parse(stbl: &[u8], file_size: u64) -> anyhow::Result<Self> {
+        let mut tables = Self::default();
+
+        for b in BoxIterator::new(stbl, 0) {
+            let b = b?;
+            let mut r = ByteReader::new(b.payload);
+
+            match &b.kind {
+                b"stsz" => {
+                    read_full_box_header(&mut r)?;
+                    let sample_size = r.read_u32()?;
+                    let sample_count = r.read_u32()?;
+                    tables.sizes = Some(if sample_size != 0 {
+                        check_sample_count(sample_count, sample_size, file_size)?;
+                        vec![sample_size; sample_count as usize]
+                    } else {
+                        (0..sample_count)
+                            .map(|_| r.read_u32())
+                            .collect::<anyhow::Result<_>>()?
+                    });
+                }
+                b"stco" | b"co64" => {
+                    read_full_box_header(&mut r)?;
+                    let entry_count = r.read_u32()?;
+                    tables.chunk_offsets = (0..entry_count)
+                        .map(|_| {
+                            if &b.kind == b"co64" {
+                                r.read_u64()
+                            } else {
+                                r.read_u32().map(u64::from)
+                            }
+                        })
+                        .collect::<anyhow::Result<_>>()?;
+                }
+                b"stsc" => {
+                    read_full_box_header(&mut r)?;
+                    let entry_count = r.read_u32()?;
+                    tables.sample_to_chunk = (0..entry_count)
+                        .map(|_| {
+                            let first_chunk = r.read_u32()?;
+                            let samples_per_chunk = r.read_u32()?;
+                            let _sample_description_index = r.read_u32()?;
+                            Ok((first_chunk, samples_per_chunk))
+                        })
+                        .collect::<anyhow::Result<_>>()?;
+                }
+                b"stts" => {
+                    read_full_box_header(&mut r)?;
+                    let entry_count = r.read_u32()?;
+                    tables.time_to_sample = (0..entry_count)
+                        .map(|_| Ok((r.read_u32()?, r.read_u32()?)))
+                        .collect::<anyhow::Result<_>>()?;
+                }
+                b"ctts" => {
+                    read_full_box_header(&mut r)?;
+                    let entry_count = r.read_u32()?;
+                    // Version 0 offsets are unsigned, but many muxers use them as signed values
+                    // so we interpret them the same way as version 1.
+                    tables.composition_offsets = (0..entry_count)
+                        .map(|_| Ok((r.read_u32()?, r.read_u32()? as i32)))
+                        .collect::<anyhow::Result<_>>()?;
+                }
+                b"stss" => {
+                    read_full_box_header(&mut r)?;
+                    let entry_count = r.read_u32()?;
+                    tables.sync_samples = Some(
+                        (0..entry_count)
+                            .map(|_| r.read_u32())
+                            .collect::<anyhow::Result<_>>()?,
+                    );
+                }
+                _ => (),
+            }
+        }
+
+        Ok(tables)
+    }
+
+    /// Builds the list of samples described by the tables.
+    fn build_samples(&self) -> anyhow::Result<Vec<Sample>> {
+        let sizes = match &self.sizes {
+            Some(sizes) => sizes,
+            None => return Ok(vec![]),
+        };
+
+        let mut samples = sizes
+            .iter()
+            .map(|&size| Sample {
+                size,
+                is_sync: true,
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+
+        // Sample offsets.
+        let mut sample_iter = samples.iter_mut();
+        let mut stsc_idx = 0;
+        for (chunk_idx, &chunk_offset) in self.chunk_offsets.iter().enumerate() {
+            let chunk_num = chunk_idx as u32 + 1;
+            while stsc_idx + 1 < self.sample_to_chunk.len()
+                && self.sample_to_chunk[stsc_idx + 1].0 <= chunk_num
+            {
+                stsc_idx += 1;
+            }
+
+            let samples_per_chunk = self
+                .sample_to_chunk
+                .get(stsc_idx)
+                .map(|e| e.1)
+                .ok_or_else(|| anyhow!("missing sample to chunk table"))?;
+
+            let mut offset = chunk_offset;
+            for _ in 0..samples_per_chunk {
+                let sample = sample_iter
+                    .next()
+                    .ok_or_else(|| anyhow!("chunk {} has more samples than stsz", chunk_num))?;
+                sample.offset = offset;
+                offset = offset.checked_add(u64::from(sample.size)).ok_or_else(|| {
+                    anyhow!("chunk {} extends past the maximum offset", chunk_num)
+                })?;
+            }
+        }
+
+        if sample_iter.next().is_some() {
+            return Err(anyhow!("some samples are not part of any chunk"));
+        }
+
+        // Decode timestamps and durations.
+        let mut deltas = self
+            .time_to_sample
+            .iter()
+            .flat_map(|&(count, delta)| std::iter::repeat_n(delta, count as usize));
+        let mut dts = 0u64;
+        for sample in samples.iter_mut() {
+            let delta = deltas
+                .next()
+                .ok_or_else(|| anyhow!("time to sample table does not cover all samples"))?;
+            sample.dts = dts;
+            sample.pts = i64::try_from(dts)?;
+            sample.duration = delta;
+            dts = dts
+                .checked_add(u64::from(delta))
+                .ok_or_else(|| anyhow!("decode timestamp overflow"))?;
+        }
+
+        // Presentation timestamps.
+        let mut offsets = self
+            .composition_offsets
+            .iter()
+            .flat_map(|&(count, offset)| std::iter::repeat_n(offset, count as usize));
+        for (sample, offset) in samples.iter_mut().zip(&mut offsets) {
+            sample.pts = sample
+                .pts
+                .checked_add(i64::from(offset))
+                .ok_or_else(|| anyhow!("presentation timestamp overflow"))?;
+        }
+
+        // Sync samples.
+        if let Some(sync_samples) = &self.sync_samples {
+            for sample in samples.iter_mut() {
+                sample.is_sync = false;
+            }
+
+            for &num in sync_samples {
+                let sample = num
+                    .checked_sub(1)
+                    .and_then(|idx| samples.get_mut(idx as usize))
+                    .ok_or_else(|| anyhow!("invalid sync sample number {}", num))?;
+                sample.is_sync = true;
+            }
+        }
+
+        Ok(samples)
+    }
+}
+
+/// MP4 file reader.
+pub struct Mp4Reader<'a> {
+    data: &'a [u8],
+    tracks: Vec<Track>,
+}
+
+impl<'a> Mp4Reader<'a> {
+    /// Parses the MP4 file in `data` and builds the sample list of its video tracks.
+    pub fn new(data: &'a [u8]) -> anyhow::Result<Self> {
+        let mut reader = Self {
+            data,
+            tracks: vec![],
+        };
+
+        for b in BoxIterator::new(data, 0) {
+            let b = b?;
+            match &b.kind {
+                b"moov" => reader.parse_moov(b.payload)?,
+                b"moof" => reader.parse_moof(&b)?,
+                _ => (),
+            }
+        }
+
+        for track in &reader.tracks {
+            for sample in &track.samples {
+                let end = sample.offset.checked_add(u64::from(sample.size));
+                if end.is_none_or(|end| end > data.len() as u64) {
+                    return Err(anyhow!(
+                        "sample data of track {} is out of bounds (offset {}, size {})",
+                        track.id,
+                        sample.offset,
+                        sample.size
+                    ));
+                }
+            }
+        }
+
+        Ok(reader)
+    }
+
+    /// Returns the video tracks found in the file.
+    pub fn tracks(&self) -> &[Track] {
+        &self.tracks
+    }
+
+    /// Returns the track with ID `id`, if it exists.
+    pub fn track(&self, id: u32) -> Option<&Track> {
+        self.tracks.iter().find(|t| t.id == id)
+    }
+
+    /// Returns the raw data of `sample`, as stored in the file.
+    pub fn sample_data(&self, sample: &Sample) -> &'a [u8] {
+        // Bounds have been checked when building the sample list.
+        let start = sample.offset as usize;
+        &self.data[start..start + sample.size as usize]
+    }
+
+    /// Returns an iterator over the samples of `track`, converted into the format expected by
+    /// the stateless decoder of the track's codec.
+    pub fn packets<'b>(
+        &'b self,
+        track: &'b Track,
+    ) -> impl Iterator<Item = anyhow::Result<Packet>> + 'b {
+        track.samples.iter().enumerate().map(move |(i, sample)| {
+            let raw = self.sample_data(sample);
+
+            let data = match track.config.nal_length_size() {
+                Some(nal_length_size) => {
+                    let mut data = Vec::with_capacity(raw.len() + 64);
+                    if i == 0 || sample.is_sync {
+                        for nalu in track.config.parameter_sets() {
+                            data.extend_from_slice(&[0, 0, 0, 1]);
+                            data.extend_from_slice(nalu);
+                        }
+                    }
+
+                    length_prefixed_to_annexb(raw, nal_length_size, &mut data)
+                        .with_context(|| format!("while converting sample {}", i))?;
+                    data
+                }
+                None => raw.to_vec(),
+            };
+
+            Ok(Packet {
+                data,
+                dts: sample.dts,
+                pts: sample.pts,
+                duration: sample.duration,
+                is_sync: sample.is_sync,
+            })
+        })
+    }
+
+    fn parse_moov(&mut self, moov: &[u8]) -> anyhow::Result<()> {
+        let mut trex = vec![];
+
+        for b in BoxIterator::new(moov, 0) {
+            let b = b?;
+            match &b.kind {
+                b"trak" => {
+                    if let Some(track) = Self::parse_trak(b.payload, self.data.len() as u64)? {
+                        self.tracks.push(track);
+                    }
+                }
+                b"mvex" => {
+                    for b in BoxIterator::new(b.payload, 0) {
+                        let b = b?;
+                        if &b.kind != b"trex" {
+                            continue;
+                        }
+
+                        let mut r = ByteReader::new(b.payload);
+                        read_full_box_header(&mut r)?;
+                        let track_id = r.read_u32()?;
+                        let _default_sample_description_index = r.read_u32()?;
+                        trex.push((
+                            track_id,
+                            TrackExtends {
+                                default_sample_duration: r.read_u32()?,
+                                default_sample_size: r.read_u32()?,
+                                default_sample_flags: r.read_u32()?,
+                            },
+                        ));
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        for (track_id, defaults) in trex {
+            if let Some(track) = self.tracks.iter_mut().find(|t| t.id == track_id) {
+                track.trex = defaults;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Parses a `trak` box from a file of `file_size` bytes, returning `None` if it is not a
+    /// supported video track.
+    fn parse_trak(trak: &[u8], file_size: u64) -> anyhow::Result<Option<Track>> {
+        let tkhd = find_box(trak, b"tkhd")?.ok_or_else(|| anyhow!("track without tkhd"))?;
+        let mut r = ByteReader::new(tkhd.payload);
+        let (version, _) = read_full_box_header(&mut r)?;
+        // Skip creation and modification times.
+        r.skip(if version == 1 { 16 } else { 8 })?;
+        let id = r.read_u32()?;
+
+        let mdia = find_box(trak, b"mdia")?
+            .ok_or_else(|| anyhow!("track {} without mdia", id))?
+            .payload;
+
+        let hdlr = find_box(mdia, b"hdlr")?.ok_or_else(|| anyhow!("track {} without hdlr", id))?;
+        let mut r = ByteReader::new(hdlr.payload);
+        read_full_box_header(&mut r)?;
+        let _pre_defined = r.read_u32()?;
+        if r.read_bytes(4)? != b"vide" {
+            return Ok(None);
+        }
+
+        let mdhd = find_box(mdia, b"mdhd")?.ok_or_else(|| anyhow!("track {} without mdhd", id))?;
+        let mut r = ByteReader::new(mdhd.payload);
+        let (version, _) = read_full_box_header(&mut r)?;
+        r.skip(if version == 1 { 16 } else { 8 })?;
+        let timescale = r.read_u32()?;
+
+        let stbl = find_box(mdia, b"minf")?
+            .map(|minf| find_box(minf.payload, b"stbl"))
+            .transpose()?
+            .flatten()
+            .ok_or_else(|| anyhow!("track {} without stbl", id))?
+            .payload;
+
+        let stsd = find_box(stbl, b"stsd")?.ok_or_else(|| anyhow!("track {} without stsd", id))?;
+        let (width, height, config) = match Self::parse_stsd(stsd.payload)? {
+            Some(entry) => entry,
+            None => {
+                log::debug!("skipping track {} with unsupported codec", id);
+                return Ok(None);
+            }
+        };
+
+        let samples = SampleTables::parse(stbl, file_size)?
+            .build_samples()
+            .with_context(|| format!("while building sample list of track {}", id))?;
+        let next_dts = match samples.last() {
+            Some(s) => s
+                .dts
+                .checked_add(u64::from(s.duration))
+                .ok_or_else(|| anyhow!("decode timestamp overflow in track {}", id))?,
+            None => 0,
+        };
+
+        Ok(Some(Track {
+            id,
+            timescale,
+            width,
+            height,
+            config,
+            samples,
+            next_dts,
+            trex: Default::default(),
+        }))
+    }
+
+    /// Parses the first supported sample entry of a `stsd` box, returning its width, height and
+    /// decoder configuration.
+    fn parse_stsd(stsd: &[u8]) -> anyhow::Result<Option<(u16, u16, CodecConfig)>> {
+        let mut r = ByteReader::new(stsd);
+        read_full_box_header(&mut r)?;
+        let _entry_count = r.read_u32()?;
+
+        for entry in BoxIterator::new(r.read_to_end(), 0) {
+            let entry = entry?;
+            let config_kind = match &entry.kind {
+                b"avc1" | b"avc3" => b"avcC",
+                b"hvc1" | b"hev1" => b"hvcC",
+                b"vp09" => b"vpcC",
+                b"av01" => b"av1C",
+                _ => continue,
+            };
+
+            // VisualSampleEntry fields.
+            let mut r = ByteReader::new(entry.payload);
+            r.skip(24)?;
+            let width = r.read_u16()?;
+            let height = r.read_u16()?;
+            r.skip(50)?;
+
+            let config_box = find_box(r.read_to_end(), config_kind)?.ok_or_else(|| {
+                anyhow!(
+                    "missing {} box in {} sample entry",
+                    String::from_utf8_lossy(config_kind),
+                    String::from_utf8_lossy(&entry.kind)
+                )
+            })?;
+
+            let config = match config_kind {
+                b"avcC" => CodecConfig::Avc(AvcConfig::parse(config_box.payload)?),
+                b"hvcC" => CodecConfig::Hevc(HevcConfig::parse(config_box.payload)?),
+                b"vpcC" => CodecConfig::Vp9(VpcConfig::parse(config_box.payload)?),
+                _ => CodecConfig::Av1(Av1Config::parse(config_box.payload)?),
+            };
+
+            return Ok(Some((width, height, config)));
+        }
+
+        Ok(None)
+    }
+
+    fn parse_moof(&mut self, moof: &Mp4Box) -> anyhow::Result<()> {
+        for traf in BoxIterator::new(moof.payload, 0) {
+            let traf = traf?;
+            if &traf.kind != b"traf" {
+                continue;
+            }
+
+            self.parse_traf(traf.payload, moof.start)?;
+        }
+
+        Ok(())
+    }
+
+    fn parse_traf(&mut self, traf: &[u8], moof_start: u64) -> anyhow::Result<()> {
+        let file_size = self.data.len() as u64;
+        let tfhd = find_box(traf, b"tfhd")?.ok_or_else(|| anyhow!("traf without tfhd"))?;
+        let mut r = ByteReader::new(tfhd.payload);
+        let (_, flags) = read_full_box_header(&mut r)?;
+        let track_id = r.read_u32()?;
+
+        let track = match self.tracks.iter_mut().find(|t| t.id == track_id) {
+            Some(track) => track,
+            // Not a video track we are interested in.
+            None => return Ok(()),
+        };
+
+        let mut defaults = track.trex;
+        // In the absence of an explicit base data offset, we always use the start of the moof
+        // box. This is correct for the first traf and when default-base-is-moof is set, which
+        // covers the files produced by all common muxers.
+        let base_data_offset = if flags & 0x1 != 0 {
+            r.read_u64()?
+        } else {
+            moof_start
+        };
+        if flags & 0x2 != 0 {
+            let _sample_description_index = r.read_u32()?;
+        }
+        if flags & 0x8 != 0 {
+            defaults.default_sample_duration = r.read_u32()?;
+        }
+        if flags & 0x10 != 0 {
+            defaults.default_sample_size = r.read_u32()?;
+        }
+        if flags & 0x20 != 0 {
+            defaults.default_sample_flags = r.read_u32()?;
+        }
+
+        if let Some(tfdt) = find_box(traf, b"tfdt")? {
+            let mut r = ByteReader::new(tfdt.payload);
+            let (version, _) = read_full_box_header(&mut r)?;
+            track.next_dts = read_versioned_u64(&mut r, version)?;
+        }
+
+        let mut data_offset = base_data_offset;
+        for trun in BoxIterator::new(traf, 0) {
+            let trun = trun?;
+            if &trun.kind != b"trun" {
+                continue;
+            }
+
+            let mut r = ByteReader::new(trun.payload);
+            let (_, flags) = read_full_box_header(&mut r)?;
+            let sample_count = r.read_u32()?;
+            if flags & 0x1 != 0 {
+                let offset = r.read_u32()? as i32;
+                data_offset = base_data_offset
+                    .checked_add_signed(i64::from(offset))
+                    .ok_or_else(|| anyhow!("invalid trun data offset {}", offset))?;
+            }
+            let first_sample_flags = if flags & 0x4 != 0 {
+                Some(r.read_u32()?)
+            } else {
+                None
+            };
+            // Samples without any field in the box are only bounded by the size of the file.
+            if flags & 0xf00 == 0 {
+                check_sample_count(sample_count, defaults.default_sample_size, file_size)?;
+            }
+
+            for i in 0..sample_count {
+                let duration = if flags & 0x100 != 0 {
+                    r.read_u32()?
+                } else {
+                    defaults.default_sample_duration
+                };
+                let size = if flags & 0x200 != 0 {
+                    r.read_u32()?
+                } else {
+                    defaults.default_sample_size
+                };
+                let sample_flags = if flags & 0x400 != 0 {
+                    r.read_u32()?
+                } else {
+                    defaults.default_sample_flags
+                };
+                let sample_flags = match first_sample_flags {
+                    Some(first_sample_flags) if i == 0 => first_sample_flags,
+                    _ => sample_flags,
+                };
+                // Same as for ctts, treat version 0 offsets as signed.
+                let composition_offset = if flags & 0x800 != 0 {
+                    r.read_u32()? as i32
+                } else {
+                    0
+                };
+
+                let pts = i64::try_from(track.next_dts)
+                    .ok()
+                    .and_then(|dts| dts.checked_add(i64::from(composition_offset)))
+                    .ok_or_else(|| {
+                        anyhow!("presentation timestamp overflow in track {}", track_id)
+                    })?;
+
+                track.samples.push(Sample {
+                    offset: data_offset,
+                    size,
+                    dts: track.next_dts,
+                    pts,
+                    duration,
+                    is_sync: sample_flags & SAMPLE_IS_NON_SYNC_SAMPLE == 0,
+                });
+
+                data_offset = data_offset
+                    .checked_add(u64::from(size))
+                    .ok_or_else(|| anyhow!("trun data extends past the maximum offset"))?;
+                track.next_dts = track
+                    .next_dts
+                    .checked_add(u64::from(duration))
+                    .ok_or_else(|| anyhow!("decode timestamp overflow in track {}", track_id))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Converts a sample made of NAL units prefixed by their length into Annex B format, appending
+/// the result to `out`.
+fn length_prefixed_to_annexb(
+    mut data: &[u8],
+    nal_length_size: u8,
+    out: &mut Vec<u8>,
+) -> anyhow::Result<()> {
+    let nal_length_size = usize::from(nal_length_size);
+
+    while !data.is_empty() {
+        if data.len() < nal_length_size {
+            return Err(anyhow!("truncated NAL unit length"));
+        }
+
+        let len = data[..nal_length_size]
+            .iter()
+            .fold(0usize, |len, &b| (len << 8) | usize::from(b));
+        data = &data[nal_length_size..];
+
+        if data.len() < len {
+            return Err(anyhow!(
+                "NAL unit of length {} exceeds the remaining {} bytes of the sample",
+                len,
+                data.len()
+            ));
+        }
+
+        out.extend_from_slice(&[0, 0, 0, 1]);
+        out.extend_from_slice(&data[..len]);
+        data = &data[len..];
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::codec::h264::parser::NaluHeader;
+    use crate::utils::annexb_nalus;
+    use crate::utils::IvfIterator;
+
+    use super::*;
+
+    const H264_STREAM: &[u8] = include_bytes!("../../codec/h264/test_data/64x64-I-P-B-P.h264");
+    const VP9_STREAM: &[u8] = include_bytes!("../../codec/vp9/test_data/test-25fps.vp9");
+
+    fn mp4_box(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut b = Vec::with_capacity(payload.len() + 8);
+        b.extend_from_slice(&(payload.len() as u32 + 8).to_be_bytes());
+        b.extend_from_slice(kind);
+        b.extend_from_slice(payload);
+        b
+    }
+
+    fn full_box(kind: &[u8; 4], version: u8, flags: u32, payload: &[u8]) -> Vec<u8> {
+        let mut p = vec![version];
+        p.extend_from_slice(&flags.to_be_bytes()[1..]);
+        p.extend_from_slice(payload);
+        mp4_box(kind, &p)
+    }
+
+    /// Builds the payload of a sample table box: its entry count followed by the entries.
+    fn table<const N: usize>(entries: &[[u32; N]]) -> Vec<u8> {
+        let mut p = (entries.len() as u32).to_be_bytes().to_vec();
+        for v in entries.iter().flatten() {
+            p.extend_from_slice(&v.to_be_bytes());
+        }
+        p
+    }
+
+    /// Builds a `trak` box with the given handler, sample entry and sample table boxes.
+    fn trak(id: u32, handler: &[u8; 4], sample_entry: &[u8], tables: &[Vec<u8>]) -> Vec<u8> {
+        let mut tkhd = vec![0u8; 8];
+        tkhd.extend_from_slice(&id.to_be_bytes());
+        tkhd.resize(80, 0);
+
+        let mut hdlr = vec![0u8; 4];
+        hdlr.extend_from_slice(handler);
+        hdlr.resize(21, 0);
+
+        let mut mdhd = vec![0u8; 8];
+        mdhd.extend_from_slice(&25000u32.to_be_bytes());
+        mdhd.resize(20, 0);
+
+        let mut stsd = 1u32.to_be_bytes().to_vec();
+        stsd.extend_from_slice(sample_entry);
+
+        let mut stbl = full_box(b"stsd", 0, 0, &stsd);
+        for t in tables {
+            stbl.extend_from_slice(t);
+        }
+
+        let minf = mp4_box(b"stbl", &stbl);
+        let mut mdia = full_box(b"mdhd", 0, 0, &mdhd);
+        mdia.extend(full_box(b"hdlr", 0, 0, &hdlr));
+        mdia.extend(mp4_box(b"minf", &minf));
+
+        let mut trak = full_box(b"tkhd", 0, 0, &tkhd);
+        trak.extend(mp4_box(b"mdia", &mdia));
+
+        mp4_box(b"trak", &trak)
+    }
+
+    fn visual_sample_entry(kind: &[u8; 4], width: u16, height: u16, config: &[u8]) -> Vec<u8> {
+        let mut p = vec![0u8; 24];
+        p.extend_from_slice(&width.to_be_bytes());
+        p.extend_from_slice(&height.to_be_bytes());
+        p.resize(78, 0);
+        p.extend_from_slice(config);
+        mp4_box(kind, &p)
+    }
+
+    #[test]
+    fn h264_progressive() {
+        let nalus = annexb_nalus::<NaluHeader>(H264_STREAM);
+
+        // Put the parameter sets into avcC and make one length-prefixed sample per access unit.
+        let mut sps = vec![];
+        let mut pps = vec![];
+        let mut samples = vec![];
+        let mut sample = vec![];
+        let mut vcl_nalus = vec![];
+        for nalu in &nalus {
+            match nalu[0] & 0x1f {
+                7 => sps.push(nalu.to_vec()),
+                8 => pps.push(nalu.to_vec()),
+                nalu_type => {
+                    sample.extend_from_slice(&(nalu.len() as u32).to_be_bytes());
+                    sample.extend_from_slice(nalu);
+                    if nalu_type == 1 || nalu_type == 5 {
+                        vcl_nalus.push(nalu.to_vec());
+                        samples.push(std::mem::take(&mut sample));
+                    }
+                }
+            }
+        }
+        assert!(samples.len() >= 3);
+
+        let mut avcc = vec![
+            1,
+            sps[0][1],
+            sps[0][2],
+            sps[0][3],
+            0xff,
+            0xe0 | sps.len() as u8,
+        ];
+        for s in &sps {
+            avcc.extend_from_slice(&(s.len() as u16).to_be_bytes());
+            avcc.extend_from_slice(s);
+        }
+        avcc.push(pps.len() as u8);
+        for p in &pps {
+            avcc.extend_from_slice(&(p.len() as u16).to_be_bytes());
+            avcc.extend_from_slice(p);
+        }
+
+        // Layout: ftyp, mdat, moov. The first chunk holds two samples, the others one each.
+        let ftyp = mp4_box(b"ftyp", b"isom\0\0\0\0isomavc1");
+        let mdat = mp4_box(b"mdat", &samples.concat());
+        let mut chunk_offsets = vec![];
+        let mut offset = (ftyp.len() + 8) as u32;
+        for (i, s) in samples.iter().enumerate() {
+            if i != 1 {
+                chunk_offsets.push(offset);
+            }
+            offset += s.len() as u32;
+        }
+
+        let sizes = samples.iter().map(|s| [s.len() as u32]).collect::<Vec<_>>();
+        let chunk_offsets = chunk_offsets.into_iter().map(|o| [o]).collect::<Vec<_>>();
+        let mut stsz = vec![0u8; 4];
+        stsz.extend(table(&sizes));
+        let num_samples = samples.len() as u32;
+        let tables = [
+            full_box(b"stsz", 0, 0, &stsz),
+            full_box(b"stco", 0, 0, &table(&chunk_offsets)),
+            full_box(b"stsc", 0, 0, &table(&[[1, 2, 1], [2, 1, 1]])),
+            full_box(b"stts", 0, 0, &table(&[[num_samples, 1000]])),
+            full_box(
+                b"ctts",
+                0,
+                0,
+                &table(&[[1, 2000], [num_samples - 1, (-1000i32) as u32]]),
+            ),
+            full_box(b"stss", 0, 0, &table(&[[1]])),
+        ];
+
+        let mut moov = trak(
+            1,
+            b"vide",
+            &visual_sample_entry(b"avc1", 64, 64, &mp4_box(b"avcC", &avcc)),
+            &tables,
+        );
+        // An audio track that must be ignored.
+        moov.extend(trak(2, b"soun", &mp4_box(b"mp4a", &[0u8; 28]), &[]));
+        let moov = mp4_box(b"moov", &moov);
+
+        let file = [ftyp, mdat, moov].concat();
+        let reader = Mp4Reader::new(&file).unwrap();
+
+        assert_eq!(reader.tracks().len(), 1);
+        let track = reader.track(1).unwrap();
+        assert_eq!(track.codec(), Codec::H264);
+        assert_eq!((track.width, track.height), (64, 64));
+        assert_eq!(track.timescale, 25000);
+        match &track.config {
+            CodecConfig::Avc(config) => {
+                assert_eq!(config.nal_length_size, 4);
+                assert_eq!(config.sps, sps);
+                assert_eq!(config.pps, pps);
+            }
+            config => panic!("unexpected config {:?}", config),
+        }
+
+        let packets = reader
+            .packets(track)
+            .collect::<anyhow::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(packets.len(), samples.len());
+
+        for (i, packet) in packets.iter().enumerate() {
+            assert_eq!(packet.dts, i as u64 * 1000);
+            let expected_offset = if i == 0 { 2000 } else { -1000 };
+            assert_eq!(packet.pts, i as i64 * 1000 + expected_offset);
+            assert_eq!(packet.duration, 1000);
+            assert_eq!(packet.is_sync, i == 0);
+        }
+
+        // The first packet must start with the parameter sets, and the slices must come out
+        // untouched.
+        let first = annexb_nalus::<NaluHeader>(&packets[0].data);
+        assert_eq!(first[0], sps[0].as_slice());
+        assert_eq!(first[1], pps[0].as_slice());
+
+        let stream = packets
+            .iter()
+            .map(|p| p.data.clone())
+            .collect::<Vec<_>>()
+            .concat();
+        let out_vcl_nalus = annexb_nalus::<NaluHeader>(&stream)
+            .into_iter()
+            .filter(|n| matches!(n[0] & 0x1f, 1 | 5))
+            .collect::<Vec<_>>();
+        assert_eq!(out_vcl_nalus, vcl_nalus);
+    }
+
+    #[test]
+    fn vp9_fragmented() {
+        let frames = IvfIterator::new(VP9_STREAM).take(5).collect::<Vec<_>>();
+
+        let vpcc = full_box(b"vpcC", 1, 0, &[0, 10, 0x82, 1, 1, 1, 0, 0]);
+        let mut trex = 1u32.to_be_bytes().to_vec();
+        trex.extend_from_slice(&1u32.to_be_bytes());
+        // Default duration, size and flags (non-sync).
+        trex.extend_from_slice(&40u32.to_be_bytes());
+        trex.extend_from_slice(&0u32.to_be_bytes());
+        trex.extend_from_slice(&SAMPLE_IS_NON_SYNC_SAMPLE.to_be_bytes());
+
+        let mut moov = trak(
+            1,
+            b"vide",
+            &visual_sample_entry(b"vp09", 320, 240, &vpcc),
+            &[],
+        );
+        moov.extend(mp4_box(b"mvex", &full_box(b"trex", 0, 0, &trex)));
+        let moov = mp4_box(b"moov", &moov);
+
+        /// Builds a moof + mdat pair with the sample data placed right after the moof.
+        fn fragment(frames: &[&[u8]], tfdt: Option<u64>, first_sync: bool) -> Vec<u8> {
+            let tfhd = full_box(b"tfhd", 0, 0x2_0000, &1u32.to_be_bytes());
+            let tfdt = tfdt.map(|t| full_box(b"tfdt", 1, 0, &t.to_be_bytes()));
+
+            let build_trun = |data_offset: u32| {
+                let mut p = (frames.len() as u32).to_be_bytes().to_vec();
+                p.extend_from_slice(&data_offset.to_be_bytes());
+                if first_sync {
+                    p.extend_from_slice(&0u32.to_be_bytes());
+                }
+                for f in frames {
+                    p.extend_from_slice(&(f.len() as u32).to_be_bytes());
+                }
+                let flags = 0x1 | 0x200 | if first_sync { 0x4 } else { 0 };
+                full_box(b"trun", 0, flags, &p)
+            };
+
+            let build_moof = |data_offset: u32| {
+                let mut traf = tfhd.clone();
+                if let Some(tfdt) = &tfdt {
+                    traf.extend_from_slice(tfdt);
+                }
+                traf.extend(build_trun(data_offset));
+                let mut moof = full_box(b"mfhd", 0, 0, &1u32.to_be_bytes());
+                moof.extend(mp4_box(b"traf", &traf));
+                mp4_box(b"moof", &moof)
+            };
+
+            let moof_len = build_moof(0).len() as u32;
+            let mut out = build_moof(moof_len + 8);
+            out.extend(mp4_box(b"mdat", &frames.concat()));
+            out
+        }
+
+        let file = [
+            mp4_box(b"ftyp", b"iso6\0\0\0\0iso6vp09"),
+            moov,
+            fragment(&frames[..3], Some(1000), true),
+            // No tfdt, the timestamps must follow the previous fragment.
+            fragment(&frames[3..], None, false),
+        ]
+        .concat();
+
+        let reader = Mp4Reader::new(&file).unwrap();
+        let track = &reader.tracks()[0];
+        assert_eq!(track.codec(), Codec::Vp9);
+        match &track.config {
+            CodecConfig::Vp9(config) => {
+                assert_eq!(config.level, 10);
+                assert_eq!(config.bit_depth, 8);
+                assert_eq!(config.chroma_subsampling, 1);
+                assert!(!config.video_full_range_flag);
+            }
+            config => panic!("unexpected config {:?}", config),
+        }
+
+        let packets = reader
+            .packets(track)
+            .collect::<anyhow::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(packets.len(), frames.len());
+        for (i, (packet, frame)) in packets.iter().zip(frames.iter()).enumerate() {
+            assert_eq!(packet.data.as_slice(), *frame);
+            assert_eq!(packet.dts, 1000 + i as u64 * 40);
+            assert_eq!(packet.pts, packet.dts as i64);
+            assert_eq!(packet.duration, 40);
+            assert_eq!(packet.is_sync, i == 0);
+        }
+    }
+
+    #[test]
+    fn hvcc() {
+        let mut hvcc = vec![
+            1, 0x21, 0x60, 0, 0, 0, 0x90, 0, 0, 0, 0, 0, 93, 0xf0, 0, 0xfc, 0xfd, 0xfa, 0xfa, 0, 0,
+            0x0f,
+        ];
+        hvcc.push(2);
+        hvcc.extend_from_slice(&[0x80 | 32, 0, 1, 0, 2, 0x40, 0x01]);
+        hvcc.extend_from_slice(&[0x80 | 33, 0, 2, 0, 1, 0x42, 0, 1, 0x44]);
+
+        let config = HevcConfig::parse(&hvcc).unwrap();
+        assert_eq!(config.general_profile_space, 0);
+        assert!(config.general_tier_flag);
+        assert_eq!(config.general_profile_idc, 1);
+        assert_eq!(config.general_profile_compatibility_flags, 0x6000_0000);
+        assert_eq!(config.general_constraint_indicator_flags, 0x9000_0000_0000);
+        assert_eq!(config.general_level_idc, 93);
+        assert_eq!(config.chroma_format_idc, 1);
+        assert_eq!(config.bit_depth_luma_minus8, 2);
+        assert_eq!(config.bit_depth_chroma_minus8, 2);
+        assert_eq!(config.num_temporal_layers, 1);
+        assert!(config.temporal_id_nested);
+        assert_eq!(config.nal_length_size, 4);
+        assert_eq!(config.arrays.len(), 2);
+        assert_eq!(config.arrays[0].nal_unit_type, 32);
+        assert_eq!(config.arrays[0].nalus, vec![vec![0x40, 0x01]]);
+        assert_eq!(config.arrays[1].nal_unit_type, 33);
+        assert_eq!(config.arrays[1].nalus, vec![vec![0x42], vec![0x44]]);
+
+        let config = CodecConfig::Hevc(config);
+        assert_eq!(config.codec(), Codec::H265);
+        assert_eq!(
+            config.parameter_sets(),
+            vec![&[0x40, 0x01][..], &[0x42][..], &[0x44][..]]
+        );
+
+        // Truncated record.
+        assert!(HevcConfig::parse(&hvcc[..hvcc.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn av1c() {
+        let config = Av1Config::parse(&[0x81, 0x28, 0x4e, 0x13, 0x0a, 0x0b, 0x00]).unwrap();
+        assert_eq!(config.seq_profile, 1);
+        assert_eq!(config.seq_level_idx_0, 8);
+        assert!(!config.seq_tier_0);
+        assert!(config.high_bitdepth);
+        assert!(!config.twelve_bit);
+        assert!(!config.monochrome);
+        assert!(config.chroma_subsampling_x);
+        assert!(config.chroma_subsampling_y);
+        assert_eq!(config.chroma_sample_position, 2);
+        assert_eq!(config.initial_presentation_delay_minus_one, Some(3));
+        assert_eq!(config.config_obus, vec![0x0a, 0x0b, 0x00]);
+
+        assert!(Av1Config::parse(&[0x01, 0x28, 0x4e, 0x00]).is_err());
+    }
+
+    #[test]
+    fn co64_and_large_boxes() {
+        let build_file = |chunk_offset: u64| {
+            let mut stsz = 3u32.to_be_bytes().to_vec();
+            stsz.extend_from_slice(&2u32.to_be_bytes());
+            let mut co64 = 1u32.to_be_bytes().to_vec();
+            co64.extend_from_slice(&chunk_offset.to_be_bytes());
+
+            let tables = [
+                full_box(b"stsz", 0, 0, &stsz),
+                full_box(b"co64", 0, 0, &co64),
+                full_box(b"stsc", 0, 0, &table(&[[1, 2, 1]])),
+                full_box(b"stts", 0, 0, &table(&[[2, 512]])),
+            ];
+
+            let av1c = mp4_box(b"av1C", &[0x81, 0x00, 0x0c, 0x00]);
+            let moov = mp4_box(
+                b"moov",
+                &trak(
+                    7,
+                    b"vide",
+                    &visual_sample_entry(b"av01", 16, 16, &av1c),
+                    &tables,
+                ),
+            );
+
+            // An mdat using a 64-bit size, with its payload starting at offset 24.
+            let mut mdat = 1u32.to_be_bytes().to_vec();
+            mdat.extend_from_slice(b"mdat");
+            mdat.extend_from_slice(&22u64.to_be_bytes());
+            mdat.extend_from_slice(&[1, 2, 3, 4, 5, 6]);
+
+            [mp4_box(b"free", &[]), mdat, moov].concat()
+        };
+
+        let file = build_file(24);
+        let reader = Mp4Reader::new(&file).unwrap();
+        let track = reader.track(7).unwrap();
+        assert_eq!(track.codec(), Codec::Av1);
+        assert_eq!(track.samples.len(), 2);
+        assert_eq!(reader.sample_data(&track.samples[0]), &[1, 2, 3]);
+        assert_eq!(reader.sample_data(&track.samples[1]), &[4, 5, 6]);
+        assert_eq!(track.samples[1].dts, 512);
+        assert!(track.samples.iter().all(|s| s.is_sync));
+
+        // Sample data outside of the file must be rejected.
+        assert!(Mp4Reader::new(&build_file(1 << 32)).is_err());
+    }
+
+    #[test]
+    fn untrusted_counts_and_offsets() {
+        let build_file = |stsz: &[u8], chunk_offset: u64| {
+            let mut co64 = 1u32.to_be_bytes().to_vec();
+            co64.extend_from_slice(&chunk_offset.to_be_bytes());
+
+            let tables = [
+                full_box(b"stsz", 0, 0, stsz),
+                full_box(b"co64", 0, 0, &co64),
+                full_box(b"stsc", 0, 0, &table(&[[1, 2, 1]])),
+                full_box(b"stts", 0, 0, &table(&[[2, 1]])),
+            ];
+            let av1c = mp4_box(b"av1C", &[0x81, 0x00, 0x0c, 0x00]);
+
+            mp4_box(
+                b"moov",
+                &trak(
+                    1,
+                    b"vide",
+                    &visual_sample_entry(b"av01", 16, 16, &av1c),
+                    &tables,
+                ),
+            )
+        };
+        let constant_size = |size: u32, count: u32| {
+            let mut stsz = size.to_be_bytes().to_vec();
+            stsz.extend_from_slice(&count.to_be_bytes());
+            stsz
+        };
+
+        // Constant size samples that cannot fit in the file.
+        assert!(Mp4Reader::new(&build_file(&constant_size(1, u32::MAX), 0)).is_err());
+        // Sample offsets overflowing.
+        assert!(Mp4Reader::new(&build_file(&constant_size(2, 2), u64::MAX - 2)).is_err());
+
+        // trun with a huge sample count and no per-sample fields.
+        let mut trex = 1u32.to_be_bytes().to_vec();
+        trex.extend_from_slice(&[0, 0, 0, 1, 0, 0, 0, 40, 0, 0, 0, 1, 0, 0, 0, 0]);
+        let mut moov = trak(
+            1,
+            b"vide",
+            &visual_sample_entry(b"vp09", 16, 16, &full_box(b"vpcC", 1, 0, &[0; 8])),
+            &[],
+        );
+        moov.extend(mp4_box(b"mvex", &full_box(b"trex", 0, 0, &trex)));
+        let moov = mp4_box(b"moov", &moov);
+
+        let mut traf = full_box(b"tfhd", 0, 0x2_0000, &1u32.to_be_bytes());
+        traf.extend(full_box(b"tfdt", 1, 0, &(u64::MAX - 40).to_be_bytes()));
+        let build_moof = |sample_count: u32| {
+            let mut traf = traf.clone();
+            traf.extend(full_box(b"trun", 0, 0, &sample_count.to_be_bytes()));
+            mp4_box(b"moof", &mp4_box(b"traf", &traf))
+        };
+
+        assert!(Mp4Reader::new(&[moov.clone(), build_moof(u32::MAX)].concat()).is_err());
+        // Timestamps overflowing.
+        assert!(Mp4Reader::new(&[moov, build_moof(2)].concat()).is_err());
+    }
+
+    #[test]
+    fn invalid_box_size() {
+        let mut file = mp4_box(b"ftyp", b"isom");
+        file[3] = 0xff;
+        assert!(Mp4Reader::new(&file).is_err());
+
+        let mut file = mp4_box(b"ftyp", b"isom");
+        file[3] = 4;
+        assert!(Mp4Reader::new(&file).is_err());
+    }
+}
@@ -0,0 +1,213 @@
+#!/usr/bin/env python3
+# Copyright 2023 The ChromiumOS Authors
+# Use of this source code is governed by a BSD-style license that can be
+# found in the LICENSE file.
+
+"""Generates the MPEG-2 transport stream fixtures used by the TS demuxer tests.
+
+The elementary streams are taken from the H.264 and H.265 parser test data, split into access
+units, and muxed with one access unit per PES packet. See README.md for the content of each file.
+"""
+
+import os
+import struct
+
+HERE = os.path.dirname(os.path.abspath(__file__))
+CODEC_DATA = os.path.join(HERE, "..", "..", "..", "codec")
+
+VIDEO_PID = 0x100
+AUDIO_PID = 0x101
+PMT_PID = 0x1000
+
+
+def crc32_mpeg2(data):
+    crc = 0xFFFFFFFF
+    for byte in data:
+        crc ^= byte << 24
+        for _ in range(8):
+            crc = ((crc << 1) ^ 0x04C11DB7) if crc & 0x80000000 else crc << 1
+            crc &= 0xFFFFFFFF
+    return crc
+
+
+def split_nalus(stream):
+    """Returns the NAL units of an Annex B stream, including their start code."""
+    starts = []
+    i = 0
+    while True:
+        j = stream.find(b"\x00\x00\x01", i)
+        if j < 0:
+            break
+        starts.append(j - 1 if j > 0 and stream[j - 1] == 0 else j)
+        i = j + 3
+    starts.append(len(stream))
+    return [stream[starts[k] : starts[k + 1]] for k in range(len(starts) - 1)]
+
+
+def nalu_header(nalu):
+    return nalu[nalu.index(b"\x00\x00\x01") + 3 :]
+
+
+def split_access_units(stream, is_vcl, starts_au):
+    aus = []
+    current = b""
+    prev_vcl = False
+    for nalu in split_nalus(stream):
+        hdr = nalu_header(nalu)
+        vcl = is_vcl(hdr)
+        if current and prev_vcl and (not vcl or starts_au(hdr)):
+            aus.append(current)
+            current = b""
+        current += nalu
+        prev_vcl = vcl
+    aus.append(current)
+    return aus
+
+
+def h264_access_units(stream):
+    return split_access_units(
+        stream,
+        lambda h: (h[0] & 0x1F) in (1, 5),
+        # first_mb_in_slice == 0 is coded as a single '1' bit.
+        lambda h: h[1] & 0x80 != 0,
+    )
+
+
+def h265_access_units(stream):
+    return split_access_units(
+        stream,
+        lambda h: (h[0] >> 1) & 0x3F < 32,
+        # first_slice_segment_in_pic_flag.
+        lambda h: h[2] & 0x80 != 0,
+    )
+
+
+def section(table_id, table_id_ext, payload):
+    body = struct.pack(">HBBB", table_id_ext, 0xC1, 0, 0) + payload
+    length = len(body) + 4
+    data = struct.pack(">BH", table_id, 0xB000 | length) + body
+    return data + struct.pack(">I", crc32_mpeg2(data))
+
+
+def pat():
+    return section(0x00, 1, struct.pack(">HH", 1, 0xE000 | PMT_PID))
+
+
+def pmt(video_stream_type, program_info=b""):
+    payload = struct.pack(">HH", 0xE000 | VIDEO_PID, 0xF000 | len(program_info))
+    payload += program_info
+    payload += struct.pack(">BHH", 0x0F, 0xE000 | AUDIO_PID, 0xF000)
+    payload += struct.pack(">BHH", video_stream_type, 0xE000 | VIDEO_PID, 0xF000)
+    return section(0x02, 1, payload)
+
+
+def timestamp(prefix, ts):
+    return struct.pack(
+        ">BHH",
+        (prefix << 4) | (((ts >> 30) & 0x7) << 1) | 1,
+        (((ts >> 15) & 0x7FFF) << 1) | 1,
+        ((ts & 0x7FFF) << 1) | 1,
+    )
+
+
+def pes(stream_id, data, pts, dts, bounded):
+    header = timestamp(0x3, pts) + timestamp(0x1, dts)
+    body = struct.pack(">BBB", 0x80, 0xC0, len(header)) + header + data
+    length = len(body) if bounded else 0
+    return struct.pack(">3sBH", b"\x00\x00\x01", stream_id, length) + body
+
+
+class Muxer:
+    def __init__(self):
+        self.cc = {}
+        self.packets = []
+
+    def _packet(self, pid, pusi, payload, adaptation=b""):
+        """Writes a TS packet, `adaptation` being the adaptation field without its length."""
+        cc = self.cc.get(pid, 0)
+        self.cc[pid] = (cc + 1) & 0xF
+        room = 184 - len(payload)
+        if adaptation or room > 0:
+            # Fill the packet with stuffing bytes in the adaptation field.
+            field = b"" if room == 1 else (adaptation or b"\x00").ljust(room - 1, b"\xff")
+            header = struct.pack(">BHB", 0x47, (0x4000 if pusi else 0) | pid, 0x30 | cc)
+            packet = header + bytes([len(field)]) + field + payload
+        else:
+            header = struct.pack(">BHB", 0x47, (0x4000 if pusi else 0) | pid, 0x10 | cc)
+            packet = header + payload
+        assert len(packet) == 188, len(packet)
+        self.packets.append(packet)
+
+    def write(self, pid, data, pusi_prefix=b"", random_access=False, pcr=None):
+        """Splits `data` into TS packets, the first one having the PUSI flag set."""
+        data = pusi_prefix + data
+        first = True
+        while data:
+            adaptation = b""
+            if first and (random_access or pcr is not None):
+                flags = (0x40 if random_access else 0) | (0x10 if pcr is not None else 0)
+                adaptation = bytes([flags])
+                if pcr is not None:
+                    base = pcr // 300
+                    ext = pcr % 300
+                    adaptation += struct.pack(">IH", base >> 1, ((base & 1) << 15) | 0x7E00 | ext)
+            max_payload = 184 - (1 + len(adaptation) if adaptation else 0)
+            chunk = data[:max_payload]
+            self._packet(pid, first, chunk, adaptation)
+            data = data[len(chunk) :]
+            first = False
+
+    def write_section(self, pid, section):
+        self.write(pid, section, pusi_prefix=b"\x00")
+
+
+def mux(access_units, stream_type, bounded, program_info=b""):
+    m = Muxer()
+    m.write_section(0, pat())
+    m.write_section(PMT_PID, pmt(stream_type, program_info))
+    for i, au in enumerate(access_units):
+        dts = 900000 + i * 3600
+        pts = dts + 3600
+        m.write(
+            VIDEO_PID,
+            pes(0xE0, au, pts, dts, bounded),
+            random_access=(i == 0),
+            pcr=dts * 300,
+        )
+        # Some audio in between, which must be ignored.
+        m.write(AUDIO_PID, pes(0xC0, b"\xff\xf1" + b"\x00" * 30, pts, pts, True))
+    return m.packets
+
+
+def main():
+    with open(os.path.join(CODEC_DATA, "h264", "test_data", "64x64-I-P-B-P.h264"), "rb") as f:
+        h264 = h264_access_units(f.read())
+    with open(os.path.join(CODEC_DATA, "h265", "test_data", "64x64-I-P-B-P.h265"), "rb") as f:
+        h265 = h265_access_units(f.read())
+
+    packets = mux(h264, 0x1B, bounded=False)
+    with open(os.path.join(HERE, "64x64-I-P-B-P.h264.ts"), "wb") as f:
+        f.write(b"".join(packets))
+
+    # Large enough program info to make the PMT span two TS packets.
+    program_info = b"".join(bytes([0x05, 4]) + b"TEST" for _ in range(40))
+    packets = mux(h265, 0x24, bounded=True, program_info=program_info)
+    with open(os.path.join(HERE, "64x64-I-P-B-P.h265.ts"), "wb") as f:
+        f.write(b"".join(packets))
+
+    # Drop the second TS packet of the second access unit, and duplicate the first packet of the
+    # third one.
+    packets = mux(h264, 0x1B, bounded=False)
+    starts = [
+        i
+        for i, p in enumerate(packets)
+        if p[1] & 0x40 and ((p[1] & 0x1F) << 8 | p[2]) == VIDEO_PID
+    ]
+    dup = packets[starts[2]]
+    packets = packets[: starts[1] + 1] + packets[starts[1] + 2 : starts[2]] + [dup] + packets[starts[2] :]
+    with open(os.path.join(HERE, "64x64-I-P-B-P-cc-error.h264.ts"), "wb") as f:
+        f.write(b"".join(packets))
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,765 @@
+// Copyright 2023 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+//! MPEG-2 transport stream (ISO/IEC 13818-1) demuxer.
+//!
+//! [`TsDemuxer`] follows the PAT and PMT to find the first H.264 or H.265 stream of the first
+//! program, and reassembles its PES packets into access units that can be fed directly to the
+//! corresponding stateless decoder. Following common broadcast practice, each PES packet is
+//! expected to contain exactly one access unit.
+//!
+//! Data is pushed in chunks of arbitrary size as it is received, and the demuxer reports its
+//! findings through [`TsEvent`]s. Lost packets are detected using the continuity counter: the
+//! PES packet being reassembled is then dropped, a [`TsEvent::Discontinuity`] is emitted, and the
+//! next access unit is marked as following a discontinuity so the client can reset its decoder.
+
+use std::collections::HashMap;
+use std::collections::VecDeque;
+
+use anyhow::anyhow;
+
+use crate::utils::container::ByteReader;
+
+const TS_PACKET_SIZE: usize = 188;
+const SYNC_BYTE: u8 = 0x47;
+const PAT_PID: u16 = 0x0000;
+const NULL_PID: u16 = 0x1fff;
+
+const TABLE_ID_PAT: u8 = 0x00;
+const TABLE_ID_PMT: u8 = 0x02;
+
+/// Computes the CRC used by PSI sections (CRC-32/MPEG-2).
+fn crc32_mpeg2(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+
+    for &byte in data {
+        crc ^= u32::from(byte) << 24;
+        for _ in 0..8 {
+            crc = if crc & 0x8000_0000 != 0 {
+                (crc << 1) ^ 0x04c1_1db7
+            } else {
+                crc << 1
+            };
+        }
+    }
+
+    crc
+}
+
+/// Video stream types supported by the demuxer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StreamType {
+    H264,
+    H265,
+}
+
+impl StreamType {
+    fn from_stream_type(stream_type: u8) -> Option<Self> {
+        match stream_type {
+            0x1b => Some(StreamType::H264),
+            0x24 => Some(StreamType::H265),
+            _ => None,
+        }
+    }
+}
+
+/// An access unit extracted from a PES packet.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AccessUnit {
+    /// Annex B encoded data of the access unit.
+    pub data: Vec<u8>,
+    /// Presentation timestamp in 90 kHz units, if present in the PES header.
+    pub pts: Option<u64>,
+    /// Decode timestamp in 90 kHz units. Equal to the PTS if the PES header does not specify it.
+    pub dts: Option<u64>,
+    /// Whether the random access indicator was set on the first TS packet of the access unit.
+    pub random_access: bool,
+    /// Whether some data was lost between the previous access unit and this one.
+    pub discontinuity: bool,
+}
+
+impl AsRef<[u8]> for AccessUnit {
+    fn as_ref(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+/// Events produced by [`TsDemuxer`].
+#[derive(Debug, PartialEq, Eq)]
+pub enum TsEvent {
+    /// A new video stream has been selected, either for the first time or because the PMT
+    /// changed. The decoder must be prepared for the new stream.
+    StreamChanged { pid: u16, stream_type: StreamType },
+    /// Video data has been lost. The decoder should be reset, and the next access unit will have
+    /// its `discontinuity` member set.
+    Discontinuity,
+    /// A complete access unit has been received.
+    AccessUnit(AccessUnit),
+}
+
+/// A PES packet being reassembled.
+struct PesBuffer {
+    data: Vec<u8>,
+    random_access: bool,
+}
+
+/// Parses the header of a PES packet, returning its PTS, DTS and payload.
+fn parse_pes(data: &[u8]) -> anyhow::Result<(Option<u64>, Option<u64>, &[u8])> {
+    let mut r = ByteReader::new(data);
+
+    if r.read_u24()? != 0x000001 {
+        return Err(anyhow!("invalid PES start code"));
+    }
+    let _stream_id = r.read_u8()?;
+    let _pes_packet_length = r.read_u16()?;
+
+    let flags = r.read_u16()?;
+    if flags >> 14 != 0b10 {
+        return Err(anyhow!("unexpected PES header marker bits {:#x}", flags));
+    }
+    let header_data_length = usize::from(r.read_u8()?);
+    let mut header = ByteReader::new(r.read_bytes(header_data_length)?);
+
+    let read_timestamp = |r: &mut ByteReader| -> anyhow::Result<u64> {
+        let b = r.read_bytes(5)?;
+        Ok((u64::from(b[0] >> 1) & 0x7) << 30
+            | u64::from(b[1]) << 22
+            | u64::from(b[2] >> 1) << 15
+            | u64::from(b[3]) << 7
+            | u64::from(b[4] >> 1))
+    };
+
+    let (pts, dts) = match (flags >> 6) & 0x3 {
+        0b10 => {
+            let pts = read_timestamp(&mut header)?;
+            (Some(pts), Some(pts))
+        }
+        0b11 => (
+            Some(read_timestamp(&mut header)?),
+            Some(read_timestamp(&mut header)?),
+        ),
+        _ => (None, None),
+    };
+
+    Ok((pts, dts, r.read_to_end()))
+}
+
+/// Push-based MPEG-2 transport stream demuxer.
+pub struct TsDemuxer {
+    /// Data that has been pushed but not processed yet.
+    pending: Vec<u8>,
+    /// PID of the PMT of the selected program.
+    pmt_pid: Option<u16>,
+    /// PID and type of the selected video stream.
+    video: Option<(u16, StreamType)>,
+    /// Last continuity counter seen for each PID we are interested in.
+    continuity: HashMap<u16, u8>,
+    /// PSI sections being reassembled, by PID.
+    sections: HashMap<u16, Vec<u8>>,
+    /// PES packet being reassembled, or `None` if we are waiting for the start of the next one.
+    pes: Option<PesBuffer>,
+    /// Whether video data has been lost since the last access unit.
+    discontinuity: bool,
+    events: VecDeque<TsEvent>,
+}
+
+impl Default for TsDemuxer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TsDemuxer {
+    pub fn new() -> Self {
+        Self {
+            pending: Vec::new(),
+            pmt_pid: None,
+            video: None,
+            continuity: HashMap::new(),
+            sections: HashMap::new(),
+            pes: None,
+            discontinuity: false,
+            events: VecDeque::new(),
+        }
+    }
+
+    /// Pushes transport stream data into the demuxer. `data` does not need to be aligned to
+    /// packet boundaries.
+    pub fn push(&mut self, data: &[u8]) {
+        self.pending.extend_from_slice(data);
+
+        // A position is considered to be the start of a packet if it starts with a sync byte,
+        // and so does the next packet if we have received it.
+        let is_packet_start = |data: &[u8], pos: usize| {
+            data[pos] == SYNC_BYTE
+                && data
+                    .get(pos + TS_PACKET_SIZE)
+                    .is_none_or(|&b| b == SYNC_BYTE)
+        };
+
+        let mut pos = 0;
+        while self.pending.len() - pos >= TS_PACKET_SIZE {
+            if !is_packet_start(&self.pending, pos) {
+                // Lost synchronization, look for the next packet start.
+                let skip = (pos + 1..self.pending.len())
+                    .find(|&i| is_packet_start(&self.pending, i))
+                    .unwrap_or(self.pending.len())
+                    - pos;
+                log::warn!("skipping {} bytes to find the next TS packet", skip);
+                pos += skip;
+                continue;
+            }
+
+            let mut packet = [0u8; TS_PACKET_SIZE];
+            packet.copy_from_slice(&self.pending[pos..pos + TS_PACKET_SIZE]);
+            self.process_packet(&packet);
+            pos += TS_PACKET_SIZE;
+        }
+
+        self.pending.drain(..pos);
+    }
+
+    /// Signals the end of the stream, so the last PES packet is emitted as an access unit.
+    pub fn flush(&mut self) {
+        self.emit_pes();
+        self.pending.clear();
+    }
+
+    /// Returns the next pending event, if any.
+    pub fn next_event(&mut self) -> Option<TsEvent> {
+        self.events.pop_front()
+    }
+
+    /// Returns the PID and type of the currently selected video stream.
+    pub fn video_stream(&self) -> Option<(u16, StreamType)> {
+        self.video
+    }
+
+    fn process_packet(&mut self, packet: &[u8; TS_PACKET_SIZE]) {
+        let transport_error = packet[1] & 0x80 != 0;
+        let pusi = packet[1] & 0x40 != 0;
+        let pid = (u16::from(packet[1] & 0x1f) << 8) | u16::from(packet[2]);
+        let adaptation_field_control = (packet[3] >> 4) & 0x3;
+        let continuity_counter = packet[3] & 0xf;
+
+        let video_pid = self.video.map(|(pid, _)| pid);
+        if pid == NULL_PID
+            || (pid != PAT_PID && Some(pid) != self.pmt_pid && Some(pid) != video_pid)
+        {
+            return;
+        }
+
+        if transport_error {
+            log::warn!("transport error indicator set on PID {:#x}", pid);
+            self.data_lost(pid);
+            return;
+        }
+
+        let mut payload_start = 4;
+        let mut discontinuity_indicator = false;
+        let mut random_access = false;
+        if adaptation_field_control & 0x2 != 0 {
+            let length = usize::from(packet[4]);
+            if length > 0 {
+                discontinuity_indicator = packet[5] & 0x80 != 0;
+                random_access = packet[5] & 0x40 != 0;
+            }
+            payload_start = 5 + length;
+        }
+
+        if adaptation_field_control & 0x1 == 0 {
+            return;
+        }
+
+        // An adaptation field followed by a payload cannot fill the whole packet.
+        if payload_start >= TS_PACKET_SIZE {
+            log::warn!("invalid adaptation field length on PID {:#x}", pid);
+            self.data_lost(pid);
+            return;
+        }
+
+        match self.continuity.insert(pid, continuity_counter) {
+            Some(last) if !discontinuity_indicator => {
+                if continuity_counter == last {
+                    // Duplicate packet.
+                    return;
+                } else if continuity_counter != (last + 1) & 0xf {
+                    log::warn!(
+                        "continuity counter error on PID {:#x}: expected {}, got {}",
+                        pid,
+                        (last + 1) & 0xf,
+                        continuity_counter
+                    );
+                    self.data_lost(pid);
+                }
+            }
+            _ => (),
+        }
+
+        let payload = &packet[payload_start..];
+        if Some(pid) == video_pid {
+            self.process_pes_payload(pusi, payload, random_access);
+        } else {
+            self.process_section_payload(pid, pusi, payload);
+        }
+    }
+
+    /// Drops any partial data for `pid` after some of its packets have been lost.
+    fn data_lost(&mut self, pid: u16) {
+        if Some(pid) == self.video.map(|(pid, _)| pid) {
+            self.pes = None;
+            if !self.discontinuity {
+                self.discontinuity = true;
+                self.events.push_back(TsEvent::Discontinuity);
+            }
+        } else {
+            self.sections.remove(&pid);
+        }
+    }
+
+    fn process_pes_payload(&mut self, pusi: bool, payload: &[u8], random_access: bool) {
+        if pusi {
+            self.emit_pes();
+            self.pes = Some(PesBuffer {
+                data: payload.to_vec(),
+                random_access,
+            });
+        } else if let Some(pes) = &mut self.pes {
+            pes.data.extend_from_slice(payload);
+        } else {
+            // Waiting for the start of the next PES packet.
+            return;
+        }
+
+        // Emit bounded PES packets as soon as they are complete.
+        let complete = match &mut self.pes {
+            Some(pes) if pes.data.len() >= 6 => {
+                let length = usize::from(u16::from_be_bytes([pes.data[4], pes.data[5]]));
+                if length != 0 && pes.data.len() >= 6 + length {
+                    pes.data.truncate(6 + length);
+                    true
+                } else {
+                    false
+                }
+            }
+            _ => false,
+        };
+
+        if complete {
+            self.emit_pes();
+        }
+    }
+
+    /// Turns the PES packet being reassembled into an access unit.
+    fn emit_pes(&mut self) {
+        let pes = match self.pes.take() {
+            Some(pes) => pes,
+            None => return,
+        };
+
+        match parse_pes(&pes.data) {
+            Ok((pts, dts, payload)) => {
+                self.events.push_back(TsEvent::AccessUnit(AccessUnit {
+                    data: payload.to_vec(),
+                    pts,
+                    dts,
+                    random_access: pes.random_access,
+                    discontinuity: std::mem::take(&mut self.discontinuity),
+                }));
+            }
+            Err(e) => {
+                log::warn!("dropping invalid PES packet: {:#}", e);
+                if !self.discontinuity {
+                    self.discontinuity = true;
+                    self.events.push_back(TsEvent::Discontinuity);
+                }
+            }
+        }
+    }
+
+    fn process_section_payload(&mut self, pid: u16, pusi: bool, payload: &[u8]) {
+        let mut payload = payload;
+
+        if pusi {
+            let pointer_field = match payload.first() {
+                Some(&pointer_field) => usize::from(pointer_field),
+                None => {
+                    log::warn!("missing pointer field on PID {:#x}", pid);
+                    self.sections.remove(&pid);
+                    return;
+                }
+            };
+            if 1 + pointer_field > payload.len() {
+                log::warn!("invalid pointer field on PID {:#x}", pid);
+                self.sections.remove(&pid);
+                return;
+            }
+
+            // The bytes before the pointed position end the previous section.
+            if let Some(buffer) = self.sections.get_mut(&pid) {
+                buffer.extend_from_slice(&payload[1..1 + pointer_field]);
+                self.process_sections(pid);
+            }
+
+            self.sections.insert(pid, Vec::new());
+            payload = &payload[1 + pointer_field..];
+        }
+
+        if let Some(buffer) = self.sections.get_mut(&pid) {
+            buffer.extend_from_slice(payload);
+            self.process_sections(pid);
+        }
+    }
+
+    /// Processes all the complete sections accumulated for `pid`.
+    fn process_sections(&mut self, pid: u16) {
+        loop {
+            let buffer = match self.sections.get_mut(&pid) {
+                Some(buffer) => buffer,
+                None => return,
+            };
+
+            // 0xff is stuffing after the last section.
+            if buffer.first() == Some(&0xff) {
+                self.sections.remove(&pid);
+                return;
+            }
+
+            if buffer.len() < 3 {
+                return;
+            }
+
+            let section_length = (usize::from(buffer[1] & 0xf) << 8) | usize::from(buffer[2]);
+            if buffer.len() < 3 + section_length {
+                return;
+            }
+
+            let section = buffer.drain(..3 + section_length).collect::<Vec<_>>();
+            if let Err(e) = self.process_section(pid, &section) {
+                log::warn!("ignoring invalid section on PID {:#x}: {:#}", pid, e);
+            }
+        }
+    }
+
+    fn process_section(&mut self, pid: u16, section: &[u8]) -> anyhow::Result<()> {
+        if crc32_mpeg2(section) != 0 {
+            return Err(anyhow!("CRC mismatch"));
+        }
+
+        let mut r = ByteReader::new(&section[..section.len() - 4]);
+        let table_id = r.read_u8()?;
+        let _section_length = r.read_u16()?;
+        let _table_id_extension = r.read_u16()?;
+        let current_next_indicator = r.read_u8()? & 0x1 != 0;
+        let _section_number = r.read_u8()?;
+        let _last_section_number = r.read_u8()?;
+
+        if !current_next_indicator {
+            return Ok(());
+        }
+
+        match (pid, table_id) {
+            (PAT_PID, TABLE_ID_PAT) => self.process_pat(r),
+            (_, TABLE_ID_PMT) if Some(pid) == self.pmt_pid => self.process_pmt(r),
+            _ => Ok(()),
+        }
+    }
+
+    fn process_pat(&mut self, mut r: ByteReader) -> anyhow::Result<()> {
+        while r.remaining() > 0 {
+            let program_number = r.read_u16()?;
+            let pid = r.read_u16()? & 0x1fff;
+
+            // Program 0 is the network PID.
+            if program_number == 0 {
+                continue;
+            }
+
+            if self.pmt_pid != Some(pid) {
+                if let Some(pmt_pid) = self.pmt_pid {
+                    self.sections.remove(&pmt_pid);
+                    self.continuity.remove(&pmt_pid);
+                }
+                self.pmt_pid = Some(pid);
+            }
+
+            return Ok(());
+        }
+
+        Err(anyhow!("no program in PAT"))
+    }
+
+    fn process_pmt(&mut self, mut r: ByteReader) -> anyhow::Result<()> {
+        let _pcr_pid = r.read_u16()? & 0x1fff;
+        let program_info_length = r.read_u16()? & 0xfff;
+        r.skip(usize::from(program_info_length))?;
+
+        let mut video = None;
+        while r.remaining() > 0 {
+            let stream_type = r.read_u8()?;
+            let pid = r.read_u16()? & 0x1fff;
+            let es_info_length = r.read_u16()? & 0xfff;
+            r.skip(usize::from(es_info_length))?;
+
+            if let Some(stream_type) = StreamType::from_stream_type(stream_type) {
+                video = Some((pid, stream_type));
+                break;
+            }
+        }
+
+        if video == self.video {
+            return Ok(());
+        }
+
+        if let Some((pid, _)) = self.video {
+            self.continuity.remove(&pid);
+        }
+        self.pes = None;
+        self.video = video;
+
+        match video {
+            Some((pid, stream_type)) => {
+                self.events
+                    .push_back(TsEvent::StreamChanged { pid, stream_type });
+                Ok(())
+            }
+            None => Err(anyhow!("no supported video stream in PMT")),
+        }
+    }
+}
+
+/// Iterator over the access units of a complete transport stream.
+///
+/// Other events are not reported, but discontinuities can still be detected using the
+/// `discontinuity` member of [`AccessUnit`].
+pub struct TsIterator<'a> {
+    data: &'a [u8],
+    demuxer: TsDemuxer,
+    flushed: bool,
+}
+
+impl<'a> TsIterator<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            demuxer: TsDemuxer::new(),
+            flushed: false,
+        }
+    }
+}
+
+impl<'a> Iterator for TsIterator<'a> {
+    type Item = AccessUnit;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            while let Some(event) = self.demuxer.next_event() {
+                if let TsEvent::AccessUnit(au) = event {
+                    return Some(au);
+                }
+            }
+
+            if !self.data.is_empty() {
+                let len = std::cmp::min(self.data.len(), TS_PACKET_SIZE);
+                self.demuxer.push(&self.data[..len]);
+                self.data = &self.data[len..];
+            } else if !self.flushed {
+                self.demuxer.flush();
+                self.flushed = true;
+            } else {
+                return None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const H264_TS: &[u8] = include_bytes!("test_data/64x64-I-P-B-P.h264.ts");
+    const H265_TS: &[u8] = include_bytes!("test_data/64x64-I-P-B-P.h265.ts");
+    const H264_CC_ERROR_TS: &[u8] = include_bytes!("test_data/64x64-I-P-B-P-cc-error.h264.ts");
+    const H264_STREAM: &[u8] = include_bytes!("../../codec/h264/test_data/64x64-I-P-B-P.h264");
+    const H265_STREAM: &[u8] = include_bytes!("../../codec/h265/test_data/64x64-I-P-B-P.h265");
+
+    /// Pushes `data` into a new demuxer by chunks of `chunk_size` bytes and returns all the
+    /// events it produced.
+    fn demux(data: &[u8], chunk_size: usize) -> Vec<TsEvent> {
+        let mut demuxer = TsDemuxer::new();
+        let mut events = vec![];
+
+        for chunk in data.chunks(chunk_size) {
+            demuxer.push(chunk);
+            while let Some(event) = demuxer.next_event() {
+                events.push(event);
+            }
+        }
+
+        demuxer.flush();
+        while let Some(event) = demuxer.next_event() {
+            events.push(event);
+        }
+
+        events
+    }
+
+    fn access_units(events: Vec<TsEvent>) -> Vec<AccessUnit> {
+        events
+            .into_iter()
+            .filter_map(|e| match e {
+                TsEvent::AccessUnit(au) => Some(au),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Checks the events produced by the demuxer for one of our error-free test streams.
+    fn check_stream(ts: &[u8], es: &[u8], stream_type: StreamType, chunk_size: usize) {
+        let mut events = demux(ts, chunk_size);
+
+        assert_eq!(
+            events.remove(0),
+            TsEvent::StreamChanged {
+                pid: 0x100,
+                stream_type
+            }
+        );
+
+        let aus = access_units(events);
+        assert_eq!(aus.len(), 3);
+        for (i, au) in aus.iter().enumerate() {
+            let dts = 900000 + i as u64 * 3600;
+            assert_eq!(au.dts, Some(dts));
+            assert_eq!(au.pts, Some(dts + 3600));
+            assert_eq!(au.random_access, i == 0);
+            assert!(!au.discontinuity);
+        }
+
+        // The access units put back together must give the original stream.
+        let data = aus.iter().map(|au| au.data.clone()).collect::<Vec<_>>();
+        assert_eq!(data.concat(), es);
+    }
+
+    #[test]
+    fn h264() {
+        check_stream(H264_TS, H264_STREAM, StreamType::H264, TS_PACKET_SIZE);
+    }
+
+    #[test]
+    fn h265() {
+        check_stream(H265_TS, H265_STREAM, StreamType::H265, TS_PACKET_SIZE);
+    }
+
+    #[test]
+    fn unaligned_chunks() {
+        check_stream(H264_TS, H264_STREAM, StreamType::H264, 7);
+        check_stream(H265_TS, H265_STREAM, StreamType::H265, 1000);
+    }
+
+    #[test]
+    fn resync() {
+        let mut ts = vec![0x12, 0x47, 0x00, 0x34];
+        ts.extend_from_slice(H264_TS);
+        check_stream(&ts, H264_STREAM, StreamType::H264, TS_PACKET_SIZE);
+    }
+
+    #[test]
+    fn continuity_error() {
+        let expected = access_units(demux(H264_TS, TS_PACKET_SIZE));
+        let mut events = demux(H264_CC_ERROR_TS, TS_PACKET_SIZE).into_iter();
+
+        assert!(matches!(
+            events.next(),
+            Some(TsEvent::StreamChanged { pid: 0x100, .. })
+        ));
+        assert_eq!(
+            events.next(),
+            Some(TsEvent::AccessUnit(expected[0].clone()))
+        );
+        // The second access unit is incomplete and must be dropped.
+        assert_eq!(events.next(), Some(TsEvent::Discontinuity));
+        // The third one is complete, despite its first packet being duplicated.
+        assert_eq!(
+            events.next(),
+            Some(TsEvent::AccessUnit(AccessUnit {
+                discontinuity: true,
+                ..expected[2].clone()
+            }))
+        );
+        assert_eq!(events.next(), None);
+    }
+
+    #[test]
+    fn invalid_crc() {
+        let mut ts = H264_TS.to_vec();
+        // Corrupt the CRC of the PAT, which ends the first packet.
+        ts[TS_PACKET_SIZE - 1] ^= 0xff;
+
+        let mut demuxer = TsDemuxer::new();
+        demuxer.push(&ts);
+        demuxer.flush();
+        assert_eq!(demuxer.next_event(), None);
+        assert_eq!(demuxer.video_stream(), None);
+    }
+
+    #[test]
+    fn adaptation_field_filling_packet() {
+        // PAT packet starting a section, with an adaptation field leaving no room for its payload.
+        let mut packet = vec![0x47, 0x40, 0x00, 0x30, (TS_PACKET_SIZE - 5) as u8, 0x00];
+        packet.resize(TS_PACKET_SIZE, 0xff);
+
+        let mut ts = packet.clone();
+        ts.extend_from_slice(H264_TS);
+        check_stream(&ts, H264_STREAM, StreamType::H264, TS_PACKET_SIZE);
+
+        let mut demuxer = TsDemuxer::new();
+        demuxer.process_section_payload(0, true, &[]);
+        assert_eq!(demuxer.next_event(), None);
+    }
+
+    #[test]
+    fn iterator() {
+        let aus = TsIterator::new(H264_CC_ERROR_TS).collect::<Vec<_>>();
+        assert_eq!(aus.len(), 2);
+        assert!(!aus[0].discontinuity);
+        assert!(aus[1].discontinuity);
+    }
+
+    #[test]
+    fn pes_timestamps() {
+        // PTS-only header with a 33-bit timestamp.
+        let pts = 0x1_2345_6789u64;
+        let pes = [
+            0x00,
+            0x00,
+            0x01,
+            0xe0,
+            0x00,
+            0x00,
+            0x80,
+            0x80,
+            0x05,
+            0x21 | ((pts >> 29) & 0xe) as u8,
+            (pts >> 22) as u8,
+            ((pts >> 14) & 0xfe) as u8 | 1,
+            (pts >> 7) as u8,
+            ((pts << 1) & 0xfe) as u8 | 1,
+            0xaa,
+        ];
+
+        let (parsed_pts, dts, payload) = parse_pes(&pes).unwrap();
+        assert_eq!(parsed_pts, Some(pts));
+        assert_eq!(dts, Some(pts));
+        assert_eq!(payload, &[0xaa]);
+
+        assert!(parse_pes(&pes[1..]).is_err());
+        assert!(parse_pes(&pes[..12]).is_err());
+    }
+
+    #[test]
+    fn crc() {
+        assert_eq!(crc32_mpeg2(b"123456789"), 0x0376_e6e7);
+    }
+}
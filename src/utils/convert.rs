@@ -0,0 +1,1510 @@
+// Copyright 2023 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+//! Software conversion between pixel formats.
+//!
+//! [`convert`] reads a frame whose planes are laid out as described by a [`FrameLayout`] and writes
+//! it using another layout, possibly of a different [`PixelFormat`]. Chroma is resampled when the
+//! subsampling differs, and samples are scaled when the bit depth differs, optionally with
+//! dithering when it is reduced.
+//!
+//! Frames go through an intermediate planar representation with 16 bits per sample, which makes
+//! any conversion possible at the cost of some speed.
+
+pub mod rgb;
+pub mod tonemap;
+
+use anyhow::anyhow;
+use byteorder::ByteOrder;
+use byteorder::LittleEndian;
+
+use crate::DecodedFormat;
+use crate::Fourcc;
+use crate::FrameLayout;
+use crate::PlaneLayout;
+use crate::Resolution;
+
+/// 4x4 Bayer matrix used for ordered dithering.
+const BAYER_4X4: [[u32; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// Pixel formats that can be converted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PixelFormat {
+    /// Format returned by the decoders, laid out as described by [`DecodedFormat`].
+    Decoded(DecodedFormat),
+    /// One Y and one interleaved UV plane, 4:2:2 sampling, 8 bits per sample.
+    Nv16,
+    /// One Y and one interleaved UV plane, 4:2:0 sampling, 16 bits per sample, LE. Only the 10
+    /// MSBs are used.
+    P010,
+    /// One Y and one interleaved UV plane, 4:2:0 sampling, 16 bits per sample, LE. Only the 12
+    /// MSBs are used.
+    P012,
+    /// Single plane of Y0 U Y1 V samples for each pair of pixels, 4:2:2 sampling, 16 bits per
+    /// sample, LE. Only the 10 MSBs are used.
+    Y210,
+    /// Single plane of Y0 U Y1 V samples for each pair of pixels, 4:2:2 sampling, 16 bits per
+    /// sample, LE. Only the 12 MSBs are used.
+    Y212,
+    /// Single plane of 32-bit LE words per pixel, holding 10-bit U, Y and V samples from the LSBs,
+    /// followed by 2 bits of alpha.
+    Y410,
+    /// Single plane of U Y V A samples for each pixel, 16 bits per sample, LE. Only the 12 MSBs
+    /// are used.
+    Y412,
+}
+
+/// How samples are arranged into planes.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Packing {
+    /// One plane per component.
+    Planar,
+    /// One Y plane and one plane of interleaved U and V samples.
+    SemiPlanar,
+    /// Y0 U Y1 V samples for each pair of pixels.
+    Yuyv,
+    /// 32-bit words with 10-bit U, Y and V samples and a 2-bit alpha.
+    Y410,
+    /// U Y V A samples for each pixel.
+    Uyva,
+}
+
+/// Memory representation of a pixel format.
+pub(crate) struct FormatInfo {
+    pub(crate) packing: Packing,
+    pub(crate) bit_depth: u8,
+    /// Size of each sample in bytes. Not meaningful for `Packing::Y410`.
+    pub(crate) sample_size: usize,
+    /// Whether the data of 16-bit samples is in their MSBs rather than their LSBs.
+    pub(crate) msb_aligned: bool,
+    /// Whether chroma is subsampled horizontally and vertically.
+    pub(crate) subsampling: (bool, bool),
+}
+
+impl PixelFormat {
+    /// Returns the pixel format corresponding to `fourcc`, if it is supported.
+    ///
+    /// The planar formats of [`DecodedFormat`] use the fourccs of VA-API for their 8-bit
+    /// variants (`I420`, `422H` and `444P`), and their own name for the 16-bit ones, e.g. `I010`.
+    pub fn from_fourcc(fourcc: Fourcc) -> Option<Self> {
+        let format = match &<[u8; 4]>::from(fourcc) {
+            b"I420" | b"YU12" => Self::Decoded(DecodedFormat::I420),
+            b"NV12" => Self::Decoded(DecodedFormat::NV12),
+            b"422H" | b"YU16" => Self::Decoded(DecodedFormat::I422),
+            b"444P" | b"YU24" => Self::Decoded(DecodedFormat::I444),
+            b"I010" => Self::Decoded(DecodedFormat::I010),
+            b"I012" => Self::Decoded(DecodedFormat::I012),
+            b"I210" => Self::Decoded(DecodedFormat::I210),
+            b"I212" => Self::Decoded(DecodedFormat::I212),
+            b"I410" => Self::Decoded(DecodedFormat::I410),
+            b"I412" => Self::Decoded(DecodedFormat::I412),
+            b"NV16" => Self::Nv16,
+            b"P010" => Self::P010,
+            b"P012" => Self::P012,
+            b"Y210" => Self::Y210,
+            b"Y212" => Self::Y212,
+            b"Y410" => Self::Y410,
+            b"Y412" => Self::Y412,
+            _ => return None,
+        };
+
+        Some(format)
+    }
+
+    /// Returns the fourcc of this format, as recognized by [`PixelFormat::from_fourcc`].
+    pub fn fourcc(self) -> Fourcc {
+        Fourcc::from(match self {
+            Self::Decoded(DecodedFormat::I420) => b"I420",
+            Self::Decoded(DecodedFormat::NV12) => b"NV12",
+            Self::Decoded(DecodedFormat::I422) => b"422H",
+            Self::Decoded(DecodedFormat::I444) => b"444P",
+            Self::Decoded(DecodedFormat::I010) => b"I010",
+            Self::Decoded(DecodedFormat::I012) => b"I012",
+            Self::Decoded(DecodedFormat::I210) => b"I210",
+            Self::Decoded(DecodedFormat::I212) => b"I212",
+            Self::Decoded(DecodedFormat::I410) => b"I410",
+            Self::Decoded(DecodedFormat::I412) => b"I412",
+            Self::Nv16 => b"NV16",
+            Self::P010 => b"P010",
+            Self::P012 => b"P012",
+            Self::Y210 => b"Y210",
+            Self::Y212 => b"Y212",
+            Self::Y410 => b"Y410",
+            Self::Y412 => b"Y412",
+        })
+    }
+
+    pub(crate) fn info(self) -> FormatInfo {
+        let (packing, bit_depth, subsampling) = match self {
+            Self::Decoded(DecodedFormat::I420) => (Packing::Planar, 8, (true, true)),
+            Self::Decoded(DecodedFormat::NV12) => (Packing::SemiPlanar, 8, (true, true)),
+            Self::Decoded(DecodedFormat::I422) => (Packing::Planar, 8, (true, false)),
+            Self::Decoded(DecodedFormat::I444) => (Packing::Planar, 8, (false, false)),
+            Self::Decoded(DecodedFormat::I010) => (Packing::Planar, 10, (true, true)),
+            Self::Decoded(DecodedFormat::I012) => (Packing::Planar, 12, (true, true)),
+            Self::Decoded(DecodedFormat::I210) => (Packing::Planar, 10, (true, false)),
+            Self::Decoded(DecodedFormat::I212) => (Packing::Planar, 12, (true, false)),
+            Self::Decoded(DecodedFormat::I410) => (Packing::Planar, 10, (false, false)),
+            Self::Decoded(DecodedFormat::I412) => (Packing::Planar, 12, (false, false)),
+            Self::Nv16 => (Packing::SemiPlanar, 8, (true, false)),
+            Self::P010 => (Packing::SemiPlanar, 10, (true, true)),
+            Self::P012 => (Packing::SemiPlanar, 12, (true, true)),
+            Self::Y210 => (Packing::Yuyv, 10, (true, false)),
+            Self::Y212 => (Packing::Yuyv, 12, (true, false)),
+            Self::Y410 => (Packing::Y410, 10, (false, false)),
+            Self::Y412 => (Packing::Uyva, 12, (false, false)),
+        };
+
+        FormatInfo {
+            packing,
+            bit_depth,
+            sample_size: if bit_depth > 8 { 2 } else { 1 },
+            // Only the formats of DecodedFormat store their data in the LSBs.
+            msb_aligned: !matches!(self, Self::Decoded(_)),
+            subsampling,
+        }
+    }
+
+    /// Returns the size in bytes and number of lines of each plane of a frame of this format and
+    /// of size `size`, without any padding.
+    pub(crate) fn plane_sizes(self, size: Resolution) -> Vec<(usize, usize)> {
+        let info = self.info();
+        let (width, height) = (size.width as usize, size.height as usize);
+        let (chroma_width, chroma_height) = chroma_size(width, height, info.subsampling);
+        let sample_size = info.sample_size;
+
+        match info.packing {
+            Packing::Planar => vec![
+                (width * sample_size, height),
+                (chroma_width * sample_size, chroma_height),
+                (chroma_width * sample_size, chroma_height),
+            ],
+            Packing::SemiPlanar => vec![
+                (width * sample_size, height),
+                (2 * chroma_width * sample_size, chroma_height),
+            ],
+            Packing::Yuyv => vec![(chroma_width * 4 * sample_size, height)],
+            Packing::Y410 => vec![(width * 4, height)],
+            Packing::Uyva => vec![(width * 4 * sample_size, height)],
+        }
+    }
+
+    /// Returns the layout of a frame of this format and of size `size` without any padding, the
+    /// planes following each other in a single buffer. This is the layout written by
+    /// [`MappableHandle::read`](crate::decoder::MappableHandle::read).
+    pub fn packed_layout(self, size: Resolution) -> FrameLayout {
+        self.aligned_layout(size, 1)
+    }
+
+    /// Returns the size of the buffer needed to hold a frame of this format and of size `size`
+    /// with the layout returned by [`PixelFormat::packed_layout`].
+    pub fn packed_size(self, size: Resolution) -> usize {
+        self.aligned_size(size, 1)
+    }
+
+    /// Returns the layout of a frame of this format and of size `size` in a single buffer, with
+    /// the stride of every plane rounded up to a multiple of `stride_alignment` bytes.
+    ///
+    /// `stride_alignment` must be a power of two.
+    pub fn aligned_layout(self, size: Resolution, stride_alignment: usize) -> FrameLayout {
+        let mut offset = 0;
+        FrameLayout {
+            format: (self.fourcc(), 0),
+            size,
+            planes: self
+                .plane_sizes(size)
+                .into_iter()
+                .map(|(line_size, lines)| {
+                    let stride = line_size.next_multiple_of(stride_alignment);
+                    let plane = PlaneLayout {
+                        buffer_index: 0,
+                        offset,
+                        stride,
+                    };
+                    offset += stride * lines;
+                    plane
+                })
+                .collect(),
+        }
+    }
+
+    /// Returns the size of the buffer needed to hold a frame of this format and of size `size`
+    /// with the layout returned by [`PixelFormat::aligned_layout`].
+    pub fn aligned_size(self, size: Resolution, stride_alignment: usize) -> usize {
+        self.plane_sizes(size)
+            .into_iter()
+            .map(|(line_size, lines)| line_size.next_multiple_of(stride_alignment) * lines)
+            .sum()
+    }
+}
+
+impl From<DecodedFormat> for PixelFormat {
+    fn from(format: DecodedFormat) -> Self {
+        Self::Decoded(format)
+    }
+}
+
+/// Dithering applied when reducing the bit depth of samples.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Dither {
+    /// Samples are rounded to the nearest value.
+    #[default]
+    None,
+    /// Samples are rounded up or down following a 4x4 Bayer matrix, which avoids banding on
+    /// smooth gradients.
+    Ordered,
+}
+
+/// Returns the size of the chroma planes of a `width`x`height` frame with `subsampling`.
+pub(crate) fn chroma_size(
+    width: usize,
+    height: usize,
+    subsampling: (bool, bool),
+) -> (usize, usize) {
+    (
+        if subsampling.0 {
+            width.div_ceil(2)
+        } else {
+            width
+        },
+        if subsampling.1 {
+            height.div_ceil(2)
+        } else {
+            height
+        },
+    )
+}
+
+/// A frame with one plane per component and 16 bits per sample.
+pub(crate) struct Planar {
+    pub(crate) width: usize,
+    pub(crate) height: usize,
+    pub(crate) bit_depth: u8,
+    pub(crate) subsampling: (bool, bool),
+    /// Y, U and V planes, without padding.
+    pub(crate) planes: [Vec<u16>; 3],
+}
+
+impl Planar {
+    pub(crate) fn chroma_size(&self) -> (usize, usize) {
+        chroma_size(self.width, self.height, self.subsampling)
+    }
+
+    /// Resamples the chroma planes to `subsampling`, averaging samples when reducing the
+    /// resolution and duplicating them when increasing it.
+    pub(crate) fn resample_chroma(&mut self, subsampling: (bool, bool)) {
+        let (width, height) = self.chroma_size();
+        let (new_width, new_height) = chroma_size(self.width, self.height, subsampling);
+
+        for plane in &mut self.planes[1..] {
+            let mut resampled = Vec::with_capacity(new_width * new_height);
+
+            for y in 0..new_height {
+                let lines = match new_height.cmp(&height) {
+                    std::cmp::Ordering::Less => [2 * y, std::cmp::min(2 * y + 1, height - 1)],
+                    std::cmp::Ordering::Equal => [y, y],
+                    std::cmp::Ordering::Greater => [y / 2, y / 2],
+                };
+
+                for x in 0..new_width {
+                    let columns = match new_width.cmp(&width) {
+                        std::cmp::Ordering::Less => [2 * x, std::cmp::min(2 * x + 1, width - 1)],
+                        std::cmp::Ordering::Equal => [x, x],
+                        std::cmp::Ordering::Greater => [x / 2, x / 2],
+                    };
+
+                    let sum: u32 = lines
+                        .iter()
+                        .flat_map(|&line| columns.iter().map(move |&col| (line, col)))
+                        .map(|(line, col)| u32::from(plane[line * width + col]))
+                        .sum();
+                    resampled.push(((sum + 2) / 4) as u16);
+                }
+            }
+
+            *plane = resampled;
+        }
+
+        self.subsampling = subsampling;
+    }
+
+    /// Scales all samples to `bit_depth`.
+    fn set_bit_depth(&mut self, bit_depth: u8, dither: Dither) {
+        let (chroma_width, _) = self.chroma_size();
+        let widths = [self.width, chroma_width, chroma_width];
+
+        for (plane, width) in self.planes.iter_mut().zip(widths) {
+            if bit_depth >= self.bit_depth {
+                let shift = bit_depth - self.bit_depth;
+                plane.iter_mut().for_each(|sample| *sample <<= shift);
+                continue;
+            }
+
+            let shift = u32::from(self.bit_depth - bit_depth);
+            let max = (1u32 << bit_depth) - 1;
+
+            for (i, sample) in plane.iter_mut().enumerate() {
+                let bias = match dither {
+                    Dither::None => 1 << (shift - 1),
+                    // Threshold of (n + 0.5) / 16 of the quantization step.
+                    Dither::Ordered => {
+                        ((2 * BAYER_4X4[(i / width) % 4][(i % width) % 4] + 1) << shift) / 32
+                    }
+                };
+
+                *sample = std::cmp::min((u32::from(*sample) + bias) >> shift, max) as u16;
+            }
+        }
+
+        self.bit_depth = bit_depth;
+    }
+}
+
+/// Returns the lines of plane `index` of `layout` in `data`, each of them `line_size` bytes long.
+fn lines<'a>(
+    data: &'a [u8],
+    layout: &FrameLayout,
+    index: usize,
+    line_size: usize,
+    num_lines: usize,
+) -> anyhow::Result<impl Iterator<Item = &'a [u8]>> {
+    let plane = check_plane(data.len(), layout, index, line_size, num_lines)?;
+
+    Ok(data[plane.offset..]
+        .chunks(plane.stride)
+        .map(move |line| &line[..line_size])
+        .take(num_lines))
+}
+
+/// Mutable version of [`lines`].
+fn lines_mut<'a>(
+    data: &'a mut [u8],
+    layout: &FrameLayout,
+    index: usize,
+    line_size: usize,
+    num_lines: usize,
+) -> anyhow::Result<impl Iterator<Item = &'a mut [u8]>> {
+    let plane = check_plane(data.len(), layout, index, line_size, num_lines)?;
+
+    Ok(data[plane.offset..]
+        .chunks_mut(plane.stride)
+        .map(move |line| &mut line[..line_size])
+        .take(num_lines))
+}
+
+/// Checks that plane `index` of `layout` holds `num_lines` lines of `line_size` bytes within a
+/// buffer of `data_len` bytes.
+fn check_plane(
+    data_len: usize,
+    layout: &FrameLayout,
+    index: usize,
+    line_size: usize,
+    num_lines: usize,
+) -> anyhow::Result<&PlaneLayout> {
+    let plane = layout
+        .planes
+        .get(index)
+        .ok_or_else(|| anyhow!("{} layout has no plane {}", layout.format.0, index))?;
+
+    if plane.buffer_index != 0 {
+        return Err(anyhow!("only single-buffer layouts are supported"));
+    }
+
+    if plane.stride < line_size {
+        return Err(anyhow!(
+            "stride of plane {} is {} but lines take {} bytes",
+            index,
+            plane.stride,
+            line_size
+        ));
+    }
+
+    if num_lines == 0 {
+        return Ok(plane);
+    }
+
+    // Layouts can come from anywhere, so the end of the plane must not overflow.
+    let end = plane
+        .stride
+        .checked_mul(num_lines - 1)
+        .and_then(|size| size.checked_add(plane.offset))
+        .and_then(|size| size.checked_add(line_size))
+        .ok_or_else(|| anyhow!("plane {} of layout {:?} overflows", index, layout))?;
+    if end > data_len {
+        return Err(anyhow!(
+            "plane {} ends at {} but the buffer is {} bytes",
+            index,
+            end,
+            data_len
+        ));
+    }
+
+    Ok(plane)
+}
+
+/// Reads the sample at index `i` of `line`, returning it with its data in the LSBs.
+fn read_sample(line: &[u8], i: usize, info: &FormatInfo) -> u16 {
+    if info.sample_size == 1 {
+        u16::from(line[i])
+    } else if info.msb_aligned {
+        LittleEndian::read_u16(&line[2 * i..]) >> (16 - info.bit_depth)
+    } else {
+        LittleEndian::read_u16(&line[2 * i..])
+    }
+}
+
+/// Writes `sample`, whose data is in the LSBs, at index `i` of `line`.
+fn write_sample(line: &mut [u8], i: usize, info: &FormatInfo, sample: u16) {
+    if info.sample_size == 1 {
+        line[i] = sample as u8;
+    } else if info.msb_aligned {
+        LittleEndian::write_u16(&mut line[2 * i..], sample << (16 - info.bit_depth));
+    } else {
+        LittleEndian::write_u16(&mut line[2 * i..], sample);
+    }
+}
+
+/// Reads the top-left `width`x`height` pixels of the frame of `format` in `data`.
+pub(crate) fn read(
+    data: &[u8],
+    layout: &FrameLayout,
+    format: PixelFormat,
+    width: usize,
+    height: usize,
+) -> anyhow::Result<Planar> {
+    let info = format.info();
+    let (chroma_width, chroma_height) = chroma_size(width, height, info.subsampling);
+    let sample_size = info.sample_size;
+    let mut planes: [Vec<u16>; 3] = Default::default();
+
+    match info.packing {
+        Packing::Planar => {
+            for (i, plane) in planes.iter_mut().enumerate() {
+                let (w, h) = if i == 0 {
+                    (width, height)
+                } else {
+                    (chroma_width, chroma_height)
+                };
+
+                for line in lines(data, layout, i, w * sample_size, h)? {
+                    plane.extend((0..w).map(|x| read_sample(line, x, &info)));
+                }
+            }
+        }
+        Packing::SemiPlanar => {
+            for line in lines(data, layout, 0, width * sample_size, height)? {
+                planes[0].extend((0..width).map(|x| read_sample(line, x, &info)));
+            }
+
+            let uv_line_size = 2 * chroma_width * sample_size;
+            for line in lines(data, layout, 1, uv_line_size, chroma_height)? {
+                for x in 0..chroma_width {
+                    planes[1].push(read_sample(line, 2 * x, &info));
+                    planes[2].push(read_sample(line, 2 * x + 1, &info));
+                }
+            }
+        }
+        Packing::Yuyv => {
+            for line in lines(data, layout, 0, chroma_width * 4 * sample_size, height)? {
+                for x in 0..chroma_width {
+                    planes[0].push(read_sample(line, 4 * x, &info));
+                    // The second luma sample of the last pair is padding for odd widths.
+                    if 2 * x + 1 < width {
+                        planes[0].push(read_sample(line, 4 * x + 2, &info));
+                    }
+                    planes[1].push(read_sample(line, 4 * x + 1, &info));
+                    planes[2].push(read_sample(line, 4 * x + 3, &info));
+                }
+            }
+        }
+        Packing::Y410 => {
+            for line in lines(data, layout, 0, width * 4, height)? {
+                for word in line.chunks(4).map(LittleEndian::read_u32) {
+                    planes[0].push(((word >> 10) & 0x3ff) as u16);
+                    planes[1].push((word & 0x3ff) as u16);
+                    planes[2].push(((word >> 20) & 0x3ff) as u16);
+                }
+            }
+        }
+        Packing::Uyva => {
+            for line in lines(data, layout, 0, width * 4 * sample_size, height)? {
+                for x in 0..width {
+                    planes[1].push(read_sample(line, 4 * x, &info));
+                    planes[0].push(read_sample(line, 4 * x + 1, &info));
+                    planes[2].push(read_sample(line, 4 * x + 2, &info));
+                }
+            }
+        }
+    }
+
+    Ok(Planar {
+        width,
+        height,
+        bit_depth: info.bit_depth,
+        subsampling: info.subsampling,
+        planes,
+    })
+}
+
+/// Writes `frame` into `data` as `format`. `frame` must already have the bit depth and
+/// subsampling of `format`.
+pub(crate) fn write(
+    frame: &Planar,
+    data: &mut [u8],
+    layout: &FrameLayout,
+    format: PixelFormat,
+) -> anyhow::Result<()> {
+    let info = format.info();
+    let (width, height) = (frame.width, frame.height);
+    let (chroma_width, chroma_height) = frame.chroma_size();
+    let sample_size = info.sample_size;
+    let [y_plane, u_plane, v_plane] = &frame.planes;
+
+    match info.packing {
+        Packing::Planar => {
+            for (i, plane) in frame.planes.iter().enumerate() {
+                let (w, h) = if i == 0 {
+                    (width, height)
+                } else {
+                    (chroma_width, chroma_height)
+                };
+
+                for (line, samples) in
+                    lines_mut(data, layout, i, w * sample_size, h)?.zip(plane.chunks(w))
+                {
+                    for (x, &sample) in samples.iter().enumerate() {
+                        write_sample(line, x, &info, sample);
+                    }
+                }
+            }
+        }
+        Packing::SemiPlanar => {
+            for (line, samples) in
+                lines_mut(data, layout, 0, width * sample_size, height)?.zip(y_plane.chunks(width))
+            {
+                for (x, &sample) in samples.iter().enumerate() {
+                    write_sample(line, x, &info, sample);
+                }
+            }
+
+            let uv_line_size = 2 * chroma_width * sample_size;
+            for (line, (u, v)) in lines_mut(data, layout, 1, uv_line_size, chroma_height)?.zip(
+                u_plane
+                    .chunks(chroma_width)
+                    .zip(v_plane.chunks(chroma_width)),
+            ) {
+                for x in 0..chroma_width {
+                    write_sample(line, 2 * x, &info, u[x]);
+                    write_sample(line, 2 * x + 1, &info, v[x]);
+                }
+            }
+        }
+        Packing::Yuyv => {
+            let line_size = chroma_width * 4 * sample_size;
+            for (y, line) in lines_mut(data, layout, 0, line_size, height)?.enumerate() {
+                let luma = &y_plane[y * width..][..width];
+                let (u, v) = (&u_plane[y * chroma_width..], &v_plane[y * chroma_width..]);
+
+                for x in 0..chroma_width {
+                    // Repeat the last luma sample of odd widths as padding.
+                    let y1 = luma[std::cmp::min(2 * x + 1, width - 1)];
+
+                    write_sample(line, 4 * x, &info, luma[2 * x]);
+                    write_sample(line, 4 * x + 1, &info, u[x]);
+                    write_sample(line, 4 * x + 2, &info, y1);
+                    write_sample(line, 4 * x + 3, &info, v[x]);
+                }
+            }
+        }
+        Packing::Y410 => {
+            for (y, line) in lines_mut(data, layout, 0, width * 4, height)?.enumerate() {
+                for (x, word) in line.chunks_mut(4).enumerate() {
+                    let i = y * width + x;
+                    // Alpha is set to opaque.
+                    let value = u32::from(u_plane[i])
+                        | u32::from(y_plane[i]) << 10
+                        | u32::from(v_plane[i]) << 20
+                        | 0b11 << 30;
+                    LittleEndian::write_u32(word, value);
+                }
+            }
+        }
+        Packing::Uyva => {
+            let line_size = width * 4 * sample_size;
+            let alpha = (1 << info.bit_depth) - 1;
+
+            for (y, line) in lines_mut(data, layout, 0, line_size, height)?.enumerate() {
+                for x in 0..width {
+                    let i = y * width + x;
+                    write_sample(line, 4 * x, &info, u_plane[i]);
+                    write_sample(line, 4 * x + 1, &info, y_plane[i]);
+                    write_sample(line, 4 * x + 2, &info, v_plane[i]);
+                    write_sample(line, 4 * x + 3, &info, alpha);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the pixel format of `layout`.
+pub(crate) fn layout_format(layout: &FrameLayout) -> anyhow::Result<PixelFormat> {
+    let (fourcc, modifier) = layout.format;
+
+    if modifier != 0 {
+        return Err(anyhow!("only linear layouts can be converted"));
+    }
+
+    PixelFormat::from_fourcc(fourcc).ok_or_else(|| anyhow!("unsupported pixel format {}", fourcc))
+}
+
+/// Copies the lines of each plane of the `format` frame in `src`, laid out according to
+/// `src_layout`, into `dst` according to `dst_layout`.
+fn copy(
+    src: &[u8],
+    src_layout: &FrameLayout,
+    dst: &mut [u8],
+    dst_layout: &FrameLayout,
+    format: PixelFormat,
+) -> anyhow::Result<()> {
+    for (index, (line_size, num_lines)) in
+        format.plane_sizes(dst_layout.size).into_iter().enumerate()
+    {
+        let src_lines = lines(src, src_layout, index, line_size, num_lines)?;
+        let dst_lines = lines_mut(dst, dst_layout, index, line_size, num_lines)?;
+        for (dst_line, src_line) in dst_lines.zip(src_lines) {
+            dst_line.copy_from_slice(src_line);
+        }
+    }
+
+    Ok(())
+}
+
+/// Converts the frame in `src`, laid out according to `src_layout`, into `dst` according to
+/// `dst_layout`.
+///
+/// Only the top-left part of the source frame with the size of `dst_layout` is converted, so
+/// frames can be cropped to their visible rectangle in the process. `dither` is used if the
+/// destination format has a lower bit depth than the source.
+pub fn convert(
+    src: &[u8],
+    src_layout: &FrameLayout,
+    dst: &mut [u8],
+    dst_layout: &FrameLayout,
+    dither: Dither,
+) -> anyhow::Result<()> {
+    let src_format = layout_format(src_layout)?;
+    let dst_format = layout_format(dst_layout)?;
+    let size = dst_layout.size;
+
+    if !src_layout.size.can_contain(size) {
+        return Err(anyhow!(
+            "cannot convert a {:?} frame into a {:?} one",
+            src_layout.size,
+            size
+        ));
+    }
+
+    // Frames that keep their format only need their lines copied.
+    if src_format == dst_format {
+        return copy(src, src_layout, dst, dst_layout, dst_format);
+    }
+
+    let mut frame = read(
+        src,
+        src_layout,
+        src_format,
+        size.width as usize,
+        size.height as usize,
+    )?;
+
+    let dst_info = dst_format.info();
+    frame.resample_chroma(dst_info.subsampling);
+    frame.set_bit_depth(dst_info.bit_depth, dither);
+
+    write(&frame, dst, dst_layout, dst_format)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every supported format.
+    const FORMATS: [PixelFormat; 17] = [
+        PixelFormat::Decoded(DecodedFormat::I420),
+        PixelFormat::Decoded(DecodedFormat::NV12),
+        PixelFormat::Decoded(DecodedFormat::I422),
+        PixelFormat::Decoded(DecodedFormat::I444),
+        PixelFormat::Decoded(DecodedFormat::I010),
+        PixelFormat::Decoded(DecodedFormat::I012),
+        PixelFormat::Decoded(DecodedFormat::I210),
+        PixelFormat::Decoded(DecodedFormat::I212),
+        PixelFormat::Decoded(DecodedFormat::I410),
+        PixelFormat::Decoded(DecodedFormat::I412),
+        PixelFormat::Nv16,
+        PixelFormat::P010,
+        PixelFormat::P012,
+        PixelFormat::Y210,
+        PixelFormat::Y212,
+        PixelFormat::Y410,
+        PixelFormat::Y412,
+    ];
+
+    /// Converts `src`, a packed frame of `src_format`, into a packed frame of `dst_format`.
+    fn convert_packed(
+        src: &[u8],
+        src_format: PixelFormat,
+        dst_format: PixelFormat,
+        size: Resolution,
+        dither: Dither,
+    ) -> Vec<u8> {
+        let mut dst = vec![0u8; dst_format.packed_size(size)];
+        convert(
+            src,
+            &src_format.packed_layout(size),
+            &mut dst,
+            &dst_format.packed_layout(size),
+            dither,
+        )
+        .unwrap();
+
+        dst
+    }
+
+    fn u16_le(samples: &[u16]) -> Vec<u8> {
+        samples.iter().flat_map(|s| s.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn fourccs() {
+        for format in FORMATS {
+            assert_eq!(PixelFormat::from_fourcc(format.fourcc()), Some(format));
+        }
+
+        assert_eq!(PixelFormat::from_fourcc(Fourcc::from(b"RGBA")), None);
+    }
+
+    #[test]
+    fn packed_sizes() {
+        let size = Resolution::from((5, 3));
+        let expected = [
+            // 15 luma samples and two 3x2 chroma planes.
+            (PixelFormat::Decoded(DecodedFormat::I420), 15 + 2 * 6),
+            (PixelFormat::Decoded(DecodedFormat::NV12), 15 + 2 * 6),
+            (PixelFormat::Decoded(DecodedFormat::I422), 15 + 2 * 9),
+            (PixelFormat::Decoded(DecodedFormat::I444), 3 * 15),
+            (PixelFormat::Decoded(DecodedFormat::I010), 2 * (15 + 2 * 6)),
+            (PixelFormat::Decoded(DecodedFormat::I212), 2 * (15 + 2 * 9)),
+            (PixelFormat::Decoded(DecodedFormat::I410), 2 * 3 * 15),
+            (PixelFormat::P010, 2 * (15 + 2 * 6)),
+            // 3 lines of 3 Y0 U Y1 V groups.
+            (PixelFormat::Y210, 3 * 3 * 4 * 2),
+            (PixelFormat::Y410, 15 * 4),
+            (PixelFormat::Y412, 15 * 4 * 2),
+        ];
+
+        for (format, size_bytes) in expected {
+            assert_eq!(format.packed_size(size), size_bytes, "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn p010_to_i010() {
+        let size = Resolution::from((2, 2));
+        let p010 = u16_le(&[
+            // Y
+            0x0040, 0x0080, 0xffc0, 0x8000, // UV
+            0x1000, 0x2000,
+        ]);
+        let i010 = u16_le(&[0x001, 0x002, 0x3ff, 0x200, 0x040, 0x080]);
+
+        let dst = convert_packed(
+            &p010,
+            PixelFormat::P010,
+            DecodedFormat::I010.into(),
+            size,
+            Dither::None,
+        );
+        assert_eq!(dst, i010);
+
+        let dst = convert_packed(
+            &i010,
+            DecodedFormat::I010.into(),
+            PixelFormat::P010,
+            size,
+            Dither::None,
+        );
+        assert_eq!(dst, p010);
+    }
+
+    #[test]
+    fn nv12_to_i420_with_padding() {
+        // 3x3 NV12 frame with 4-byte lines and a line of padding between the planes.
+        #[rustfmt::skip]
+        let nv12 = [
+            1, 2, 3, 0,
+            4, 5, 6, 0,
+            7, 8, 9, 0,
+            0, 0, 0, 0,
+            10, 20, 11, 21,
+            12, 22, 13, 23,
+        ];
+        let layout = FrameLayout {
+            format: (Fourcc::from(b"NV12"), 0),
+            size: Resolution::from((3, 3)),
+            planes: vec![
+                PlaneLayout {
+                    buffer_index: 0,
+                    offset: 0,
+                    stride: 4,
+                },
+                PlaneLayout {
+                    buffer_index: 0,
+                    offset: 16,
+                    stride: 4,
+                },
+            ],
+        };
+
+        let i420_format = PixelFormat::Decoded(DecodedFormat::I420);
+        let mut dst = vec![0u8; i420_format.packed_size(layout.size)];
+        convert(
+            &nv12,
+            &layout,
+            &mut dst,
+            &i420_format.packed_layout(layout.size),
+            Dither::None,
+        )
+        .unwrap();
+        assert_eq!(
+            dst,
+            [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 20, 21, 22, 23]
+        );
+
+        // Crop to 2x2 while converting.
+        let size = Resolution::from((2, 2));
+        let mut dst = vec![0u8; i420_format.packed_size(size)];
+        convert(
+            &nv12,
+            &layout,
+            &mut dst,
+            &i420_format.packed_layout(size),
+            Dither::None,
+        )
+        .unwrap();
+        assert_eq!(dst, [1, 2, 4, 5, 10, 20]);
+    }
+
+    #[test]
+    fn same_format_copy() {
+        // 4x3 frame of every format, with garbage in the padding and in the unused bits of the
+        // samples, which copies preserve.
+        let size = Resolution::from((4, 3));
+        for format in FORMATS {
+            let padded_layout = format.aligned_layout(size, 64);
+            let padded = (0..format.aligned_size(size, 64))
+                .map(|i| (i * 7 + 3) as u8)
+                .collect::<Vec<_>>();
+
+            let mut packed = vec![0u8; format.packed_size(size)];
+            let packed_layout = format.packed_layout(size);
+            convert(
+                &padded,
+                &padded_layout,
+                &mut packed,
+                &packed_layout,
+                Dither::None,
+            )
+            .unwrap();
+
+            let mut expected = Vec::new();
+            for (index, (line_size, num_lines)) in format.plane_sizes(size).into_iter().enumerate()
+            {
+                let plane = &padded_layout.planes[index];
+                for line in 0..num_lines {
+                    let start = plane.offset + line * plane.stride;
+                    expected.extend_from_slice(&padded[start..start + line_size]);
+                }
+            }
+            assert_eq!(packed, expected, "{:?}", format);
+        }
+
+        // Frames are cropped like when converting.
+        #[rustfmt::skip]
+        let nv12 = [
+            1, 2, 3, 0,
+            4, 5, 6, 0,
+            7, 8, 9, 0,
+            0, 0, 0, 0,
+            10, 20, 11, 21,
+            12, 22, 13, 23,
+        ];
+        let nv12_format = PixelFormat::Decoded(DecodedFormat::NV12);
+        let mut layout = nv12_format.aligned_layout(Resolution::from((3, 3)), 4);
+        layout.planes[1].offset = 16;
+        let size = Resolution::from((2, 2));
+        let mut dst = vec![0u8; nv12_format.packed_size(size)];
+        convert(
+            &nv12,
+            &layout,
+            &mut dst,
+            &nv12_format.packed_layout(size),
+            Dither::None,
+        )
+        .unwrap();
+        assert_eq!(dst, [1, 2, 4, 5, 10, 20]);
+    }
+
+    #[test]
+    fn packed_formats() {
+        let size = Resolution::from((2, 1));
+        let i410 = u16_le(&[
+            // Y
+            0x100, 0x3ff, // U
+            0x001, 0x002, // V
+            0x200, 0x000,
+        ]);
+
+        let y410 = convert_packed(
+            &i410,
+            DecodedFormat::I410.into(),
+            PixelFormat::Y410,
+            size,
+            Dither::None,
+        );
+        assert_eq!(
+            y410,
+            [
+                0x001 | 0x100 << 10 | 0x200 << 20 | 0b11 << 30,
+                0x002 | 0x3ff << 10
+            ]
+            .iter()
+            .flat_map(|word: &u32| (word | 0b11 << 30).to_le_bytes())
+            .collect::<Vec<_>>()
+        );
+
+        // Y210 averages the chroma of both pixels.
+        let y210 = convert_packed(
+            &i410,
+            DecodedFormat::I410.into(),
+            PixelFormat::Y210,
+            size,
+            Dither::None,
+        );
+        assert_eq!(
+            y210,
+            u16_le(&[0x100 << 6, 0x002 << 6, 0x3ff << 6, 0x100 << 6])
+        );
+
+        let y412 = convert_packed(
+            &i410,
+            DecodedFormat::I410.into(),
+            PixelFormat::Y412,
+            size,
+            Dither::None,
+        );
+        assert_eq!(
+            y412,
+            u16_le(&[
+                0x004 << 4,
+                0x400 << 4,
+                0x800 << 4,
+                0xfff << 4,
+                0x008 << 4,
+                0xffc << 4,
+                0x000,
+                0xfff << 4
+            ])
+        );
+
+        for packed in [PixelFormat::Y410, PixelFormat::Y412] {
+            let src = convert_packed(
+                &i410,
+                DecodedFormat::I410.into(),
+                packed,
+                size,
+                Dither::None,
+            );
+            let dst = convert_packed(&src, packed, DecodedFormat::I410.into(), size, Dither::None);
+            assert_eq!(dst, i410);
+        }
+    }
+
+    /// The per-format helpers that read back VA-API images before this module existed, kept to
+    /// check that the conversions give byte-exact results on images laid out by VA-API.
+    mod vaapi_readback {
+        use byteorder::ByteOrder;
+        use byteorder::LittleEndian;
+
+        pub(super) fn p01x_to_i01x(
+            src: &[u8],
+            dst: &mut [u8],
+            useful_pixels: usize,
+            width: usize,
+            height: usize,
+            strides: [usize; 3],
+            offsets: [usize; 3],
+        ) {
+            let sample_shift = 16 - useful_pixels;
+
+            let src_y_lines = src[offsets[0]..]
+                .chunks(strides[0])
+                .map(|line| &line[..width * 2]);
+            let dst_y_lines = dst.chunks_mut(width * 2);
+
+            for (src_line, dst_line) in src_y_lines.zip(dst_y_lines).take(height) {
+                for (src_y, dst_y) in src_line.chunks(2).zip(dst_line.chunks_mut(2)) {
+                    LittleEndian::write_u16(dst_y, LittleEndian::read_u16(src_y) >> sample_shift);
+                }
+            }
+
+            let dst_u_offset = width * 2 * height;
+
+            let width = if width % 2 == 1 { width + 1 } else { width };
+            let height = if height % 2 == 1 { height + 1 } else { height };
+            let height = height / 2;
+
+            let dst_u_size = width * height;
+
+            let src_uv_lines = src[offsets[1]..]
+                .chunks(strides[1])
+                .map(|line| &line[..width * 2]);
+            let (dst_u_plane, dst_v_plane) = dst[dst_u_offset..].split_at_mut(dst_u_size);
+            let dst_u_lines = dst_u_plane.chunks_mut(width);
+            let dst_v_lines = dst_v_plane.chunks_mut(width);
+            for (src_line, (dst_u_line, dst_v_line)) in
+                src_uv_lines.zip(dst_u_lines.zip(dst_v_lines)).take(height)
+            {
+                for ((src_u, src_v), (dst_u, dst_v)) in src_line
+                    .chunks(4)
+                    .map(|chunk| (&chunk[0..2], &chunk[2..4]))
+                    .zip(dst_u_line.chunks_mut(2).zip(dst_v_line.chunks_mut(2)))
+                {
+                    LittleEndian::write_u16(dst_u, LittleEndian::read_u16(src_u) >> sample_shift);
+                    LittleEndian::write_u16(dst_v, LittleEndian::read_u16(src_v) >> sample_shift);
+                }
+            }
+        }
+
+        pub(super) fn y21x_to_i21x(
+            src: &[u8],
+            dst: &mut [u8],
+            useful_pixels: usize,
+            width: usize,
+            height: usize,
+            strides: [usize; 3],
+            offsets: [usize; 3],
+        ) {
+            let sample_shift = 16 - useful_pixels;
+            let uv_width = if width % 2 == 1 { width + 1 } else { width } / 2;
+
+            let src_lines = src[offsets[0]..]
+                .chunks(strides[0])
+                .map(|line| &line[..width * 4]);
+
+            let dst_y_size = width * 2 * height;
+            let dst_u_size = uv_width * 2 * height;
+
+            let (dst_y_plane, dst_uv_planes) = dst.split_at_mut(dst_y_size);
+            let (dst_u_plane, dst_v_plane) = dst_uv_planes.split_at_mut(dst_u_size);
+            let dst_y_lines = dst_y_plane.chunks_mut(width * 2);
+            let dst_u_lines = dst_u_plane.chunks_mut(uv_width * 2);
+            let dst_v_lines = dst_v_plane.chunks_mut(uv_width * 2);
+
+            for (src_line, (dst_y_line, (dst_u_line, dst_v_line))) in src_lines
+                .zip(dst_y_lines.zip(dst_u_lines.zip(dst_v_lines)))
+                .take(height)
+            {
+                for (src, (dst_y, (dst_u, dst_v))) in src_line.chunks(8).zip(
+                    dst_y_line
+                        .chunks_mut(4)
+                        .zip(dst_u_line.chunks_mut(2).zip(dst_v_line.chunks_mut(2))),
+                ) {
+                    let y0 = LittleEndian::read_u16(&src[0..2]) >> sample_shift;
+                    let u = LittleEndian::read_u16(&src[2..4]) >> sample_shift;
+                    let y1 = LittleEndian::read_u16(&src[4..6]) >> sample_shift;
+                    let v = LittleEndian::read_u16(&src[6..8]) >> sample_shift;
+
+                    LittleEndian::write_u16(&mut dst_y[0..2], y0);
+                    LittleEndian::write_u16(&mut dst_y[2..4], y1);
+                    LittleEndian::write_u16(dst_u, u);
+                    LittleEndian::write_u16(dst_v, v);
+                }
+            }
+        }
+
+        pub(super) fn y410_to_i410(
+            src: &[u8],
+            dst: &mut [u8],
+            width: usize,
+            height: usize,
+            strides: [usize; 3],
+            offsets: [usize; 3],
+        ) {
+            let src_lines = src[offsets[0]..]
+                .chunks(strides[0])
+                .map(|line| &line[..width * 4]);
+
+            let dst_y_size = width * 2 * height;
+            let dst_u_size = width * 2 * height;
+
+            let (dst_y_plane, dst_uv_planes) = dst.split_at_mut(dst_y_size);
+            let (dst_u_plane, dst_v_plane) = dst_uv_planes.split_at_mut(dst_u_size);
+            let dst_y_lines = dst_y_plane.chunks_mut(width * 2);
+            let dst_u_lines = dst_u_plane.chunks_mut(width * 2);
+            let dst_v_lines = dst_v_plane.chunks_mut(width * 2);
+
+            for (src_line, (dst_y_line, (dst_u_line, dst_v_line))) in src_lines
+                .zip(dst_y_lines.zip(dst_u_lines.zip(dst_v_lines)))
+                .take(height)
+            {
+                for (src, (dst_y, (dst_u, dst_v))) in src_line.chunks(4).zip(
+                    dst_y_line
+                        .chunks_mut(2)
+                        .zip(dst_u_line.chunks_mut(2).zip(dst_v_line.chunks_mut(2))),
+                ) {
+                    let y =
+                        LittleEndian::read_u16(&[src[1] >> 2 | src[2] << 6, src[2] >> 2 & 0b11]);
+                    let u = LittleEndian::read_u16(&[src[0], src[1] & 0b11]);
+                    let v =
+                        LittleEndian::read_u16(&[src[2] >> 4 | src[3] << 4, src[3] >> 4 & 0b11]);
+                    LittleEndian::write_u16(dst_y, y);
+                    LittleEndian::write_u16(dst_u, u);
+                    LittleEndian::write_u16(dst_v, v);
+                }
+            }
+        }
+
+        pub(super) fn y412_to_i412(
+            src: &[u8],
+            dst: &mut [u8],
+            width: usize,
+            height: usize,
+            strides: [usize; 3],
+            offsets: [usize; 3],
+        ) {
+            let src_lines = src[offsets[0]..]
+                .chunks(strides[0])
+                .map(|line| &line[..width * 8]);
+
+            let dst_y_size = width * 2 * height;
+            let dst_u_size = width * 2 * height;
+
+            let (dst_y_plane, dst_uv_planes) = dst.split_at_mut(dst_y_size);
+            let (dst_u_plane, dst_v_plane) = dst_uv_planes.split_at_mut(dst_u_size);
+            let dst_y_lines = dst_y_plane.chunks_mut(width * 2);
+            let dst_u_lines = dst_u_plane.chunks_mut(width * 2);
+            let dst_v_lines = dst_v_plane.chunks_mut(width * 2);
+
+            for (src_line, (dst_y_line, (dst_u_line, dst_v_line))) in src_lines
+                .zip(dst_y_lines.zip(dst_u_lines.zip(dst_v_lines)))
+                .take(height)
+            {
+                for (src, (dst_y, (dst_u, dst_v))) in src_line.chunks(8).zip(
+                    dst_y_line
+                        .chunks_mut(2)
+                        .zip(dst_u_line.chunks_mut(2).zip(dst_v_line.chunks_mut(2))),
+                ) {
+                    let y = LittleEndian::read_u16(&src[2..4]);
+                    let u = LittleEndian::read_u16(&src[0..2]);
+                    let v = LittleEndian::read_u16(&src[4..6]);
+                    LittleEndian::write_u16(dst_y, y.rotate_right(4));
+                    LittleEndian::write_u16(dst_u, u.rotate_right(4));
+                    LittleEndian::write_u16(dst_v, v.rotate_right(4));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn vaapi_readback() {
+        type Readback = fn(&[u8], &mut [u8], usize, usize, [usize; 3], [usize; 3]);
+        // Format, format read back into, strides, offsets, significant bits of the samples and
+        // previous readback helper.
+        type Case = (
+            PixelFormat,
+            DecodedFormat,
+            [usize; 3],
+            [usize; 3],
+            u16,
+            Readback,
+        );
+
+        // Odd height, with padding at the end of each line and between the planes as VA-API
+        // lays them out.
+        let size = Resolution::from((6, 5));
+        let (width, height) = (size.width as usize, size.height as usize);
+        let cases: [Case; 6] = [
+            (
+                PixelFormat::P010,
+                DecodedFormat::I010,
+                [16, 16, 0],
+                [0, 96, 0],
+                0xffc0,
+                |src, dst, w, h, strides, offsets| {
+                    vaapi_readback::p01x_to_i01x(src, dst, 10, w, h, strides, offsets)
+                },
+            ),
+            (
+                PixelFormat::P012,
+                DecodedFormat::I012,
+                [32, 32, 0],
+                [32, 256, 0],
+                0xfff0,
+                |src, dst, w, h, strides, offsets| {
+                    vaapi_readback::p01x_to_i01x(src, dst, 12, w, h, strides, offsets)
+                },
+            ),
+            (
+                PixelFormat::Y210,
+                DecodedFormat::I210,
+                [32, 0, 0],
+                [0, 0, 0],
+                0xffc0,
+                |src, dst, w, h, strides, offsets| {
+                    vaapi_readback::y21x_to_i21x(src, dst, 10, w, h, strides, offsets)
+                },
+            ),
+            (
+                PixelFormat::Y212,
+                DecodedFormat::I212,
+                [64, 0, 0],
+                [64, 0, 0],
+                0xfff0,
+                |src, dst, w, h, strides, offsets| {
+                    vaapi_readback::y21x_to_i21x(src, dst, 12, w, h, strides, offsets)
+                },
+            ),
+            (
+                PixelFormat::Y410,
+                DecodedFormat::I410,
+                [32, 0, 0],
+                [0, 0, 0],
+                0xffff,
+                vaapi_readback::y410_to_i410,
+            ),
+            (
+                PixelFormat::Y412,
+                DecodedFormat::I412,
+                [64, 0, 0],
+                [0, 0, 0],
+                0xfff0,
+                vaapi_readback::y412_to_i412,
+            ),
+        ];
+
+        // Deterministic pseudo-random samples.
+        let mut seed = 0x1234_5678u32;
+        let mut next_sample = || {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (seed >> 8) as u16
+        };
+
+        for (format, decoded_format, strides, offsets, sample_mask, readback) in cases {
+            let layout = FrameLayout {
+                format: (format.fourcc(), 0),
+                size,
+                planes: format
+                    .plane_sizes(size)
+                    .iter()
+                    .enumerate()
+                    .map(|(i, _)| PlaneLayout {
+                        buffer_index: 0,
+                        offset: offsets[i],
+                        stride: strides[i],
+                    })
+                    .collect(),
+            };
+            let src_size = format
+                .plane_sizes(size)
+                .iter()
+                .enumerate()
+                .map(|(i, (_, lines))| offsets[i] + strides[i] * lines)
+                .max()
+                .unwrap();
+            let src = (0..src_size / 2)
+                .flat_map(|_| (next_sample() & sample_mask).to_le_bytes())
+                .collect::<Vec<_>>();
+
+            let mut expected = vec![0u8; PixelFormat::from(decoded_format).packed_size(size)];
+            readback(&src, &mut expected, width, height, strides, offsets);
+
+            let mut dst = vec![0u8; expected.len()];
+            convert(
+                &src,
+                &layout,
+                &mut dst,
+                &PixelFormat::from(decoded_format).packed_layout(size),
+                Dither::None,
+            )
+            .unwrap();
+            assert_eq!(dst, expected, "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn chroma_resampling() {
+        let size = Resolution::from((2, 2));
+        #[rustfmt::skip]
+        let i444 = [
+            // Y
+            0, 1, 2, 3,
+            // U
+            10, 20, 30, 41,
+            // V
+            0, 0, 255, 255,
+        ];
+
+        let i420 = convert_packed(
+            &i444,
+            DecodedFormat::I444.into(),
+            DecodedFormat::I420.into(),
+            size,
+            Dither::None,
+        );
+        assert_eq!(i420, [0, 1, 2, 3, 25, 128]);
+
+        let i422 = convert_packed(
+            &i444,
+            DecodedFormat::I444.into(),
+            DecodedFormat::I422.into(),
+            size,
+            Dither::None,
+        );
+        assert_eq!(i422, [0, 1, 2, 3, 15, 36, 0, 255]);
+
+        let i444 = convert_packed(
+            &i420,
+            DecodedFormat::I420.into(),
+            DecodedFormat::I444.into(),
+            size,
+            Dither::None,
+        );
+        assert_eq!(i444, [0, 1, 2, 3, 25, 25, 25, 25, 128, 128, 128, 128]);
+    }
+
+    #[test]
+    fn bit_depth_reduction() {
+        let size = Resolution::from((4, 4));
+        let format = PixelFormat::Decoded(DecodedFormat::I410);
+        // A flat frame half-way between two 8-bit values.
+        let i410 = u16_le(&[0x202; 4 * 4 * 3]);
+
+        let rounded = convert_packed(
+            &i410,
+            format,
+            DecodedFormat::I444.into(),
+            size,
+            Dither::None,
+        );
+        assert!(rounded.iter().all(|&sample| sample == 0x81));
+
+        // Dithering alternates between both values so that the average is preserved.
+        let dithered = convert_packed(
+            &i410,
+            format,
+            DecodedFormat::I444.into(),
+            size,
+            Dither::Ordered,
+        );
+        let luma = &dithered[..16];
+        assert_eq!(luma.iter().filter(|&&sample| sample == 0x80).count(), 8);
+        assert_eq!(luma.iter().filter(|&&sample| sample == 0x81).count(), 8);
+
+        // Values are clamped.
+        let white = u16_le(&[0x3ff; 4 * 4 * 3]);
+        let dst = convert_packed(
+            &white,
+            format,
+            DecodedFormat::I444.into(),
+            size,
+            Dither::Ordered,
+        );
+        assert!(dst.iter().all(|&sample| sample == 0xff));
+    }
+
+    #[test]
+    fn all_formats() {
+        // A 4:4:4 12-bit frame with odd dimensions and the same chroma for each 2x2 block, so it
+        // survives subsampling.
+        let size = Resolution::from((5, 3));
+        let (width, height) = (5, 3);
+        let mut samples = Vec::new();
+        samples.extend((0..width * height).map(|i| (i * 257 % 4096) as u16 & !0xf));
+        for c in 1..3 {
+            samples.extend((0..width * height).map(|i| {
+                let (x, y) = (i % width, i / width);
+                ((x / 2 + 3 * (y / 2) + c * 7) * 128) as u16
+            }));
+        }
+        let i412 = u16_le(&samples);
+        let src_format = PixelFormat::Decoded(DecodedFormat::I412);
+
+        for format in FORMATS {
+            let converted = convert_packed(&i412, src_format, format, size, Dither::None);
+            let back = convert_packed(&converted, format, src_format, size, Dither::None);
+
+            // 8-bit formats only keep the 8 MSBs, and 10-bit ones the 10 MSBs.
+            let mask = match format.info().bit_depth {
+                8 => !0xf,
+                10 => !0x3,
+                _ => !0,
+            };
+            let expected = samples.iter().map(|s| s & mask).collect::<Vec<_>>();
+            assert_eq!(back, u16_le(&expected), "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn invalid_layouts() {
+        let size = Resolution::from((4, 4));
+        let format = PixelFormat::Decoded(DecodedFormat::NV12);
+        let src = vec![0u8; format.packed_size(size)];
+        let mut dst = vec![0u8; format.packed_size(size)];
+        let layout = format.packed_layout(size);
+
+        // Destination too small.
+        assert!(convert(&src, &layout, &mut dst[1..], &layout, Dither::None).is_err());
+
+        // Destination larger than the source.
+        let larger = format.packed_layout(Resolution::from((4, 6)));
+        let mut larger_dst = vec![0u8; format.packed_size(larger.size)];
+        assert!(convert(&src, &layout, &mut larger_dst, &larger, Dither::None).is_err());
+
+        // Unsupported format and tiled layout.
+        let mut rgba = format.packed_layout(size);
+        rgba.format.0 = Fourcc::from(b"RGBA");
+        assert!(convert(&src, &rgba, &mut dst, &layout, Dither::None).is_err());
+        let mut tiled = format.packed_layout(size);
+        tiled.format.1 = 1;
+        assert!(convert(&src, &tiled, &mut dst, &layout, Dither::None).is_err());
+
+        // Stride shorter than a line.
+        let mut short_stride = format.packed_layout(size);
+        short_stride.planes[0].stride = 2;
+        assert!(convert(&src, &short_stride, &mut dst, &layout, Dither::None).is_err());
+
+        // Planes whose end overflows, through either the general or the copying path.
+        let i420 = PixelFormat::Decoded(DecodedFormat::I420);
+        let mut i420_dst = vec![0u8; i420.packed_size(size)];
+        let mut huge_stride = format.packed_layout(size);
+        huge_stride.planes[1].stride = usize::MAX / 2;
+        let mut huge_offset = format.packed_layout(size);
+        huge_offset.planes[1].offset = usize::MAX - 1;
+        for overflowing in [huge_stride, huge_offset] {
+            assert!(convert(&src, &overflowing, &mut dst, &layout, Dither::None).is_err());
+            assert!(convert(&src, &layout, &mut dst, &overflowing, Dither::None).is_err());
+            let i420_layout = i420.packed_layout(size);
+            assert!(convert(
+                &src,
+                &overflowing,
+                &mut i420_dst,
+                &i420_layout,
+                Dither::None
+            )
+            .is_err());
+        }
+    }
+}
@@ -0,0 +1,886 @@
+// Copyright 2023 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+//! Conversion of YUV frames to RGB.
+//!
+//! [`yuv_to_rgb`] uses the matrix, range and chroma siting of a [`ColorDescription`], which can be
+//! obtained from the parameter sets or frame headers of the stream, or from
+//! [`probe`](crate::utils::probe::probe).
+//!
+//! 8-bit 4:2:0 frames converted to 8-bit RGB use fixed-point arithmetic and read the source planes
+//! directly. Other frames go through the planar representation of the parent module and are
+//! converted using floating-point arithmetic.
+
+use anyhow::anyhow;
+
+use crate::codec::h264::parser::Sps as H264Sps;
+use crate::codec::h265::parser::Sps as H265Sps;
+use crate::codec::vp9::parser::ColorRange;
+use crate::codec::vp9::parser::ColorSpace;
+use crate::codec::vp9::parser::Header as Vp9Header;
+use crate::utils::convert::chroma_size;
+use crate::utils::convert::layout_format;
+use crate::utils::convert::lines;
+use crate::utils::convert::read;
+use crate::utils::convert::Packing;
+use crate::utils::convert::PixelFormat;
+use crate::utils::convert::Planar;
+use crate::DecodedFormat;
+use crate::FrameLayout;
+use crate::Resolution;
+
+/// Matrix used to derive the luma and chroma signals from RGB.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MatrixCoefficients {
+    /// The samples are G, B and R instead of Y, U and V.
+    Identity,
+    /// ITU-R BT.601, also used for SMPTE 170M and BT.470.
+    Bt601,
+    /// ITU-R BT.709.
+    Bt709,
+    /// SMPTE 240M.
+    Smpte240,
+    /// ITU-R BT.2020 non-constant luminance.
+    Bt2020,
+}
+
+impl MatrixCoefficients {
+    /// Returns the matrix corresponding to the `matrix_coefficients` value of H.264 and H.265
+    /// (table E-5 of H.264), if it is supported.
+    pub fn from_h26x(matrix_coefficients: u32) -> Option<Self> {
+        match matrix_coefficients {
+            0 => Some(Self::Identity),
+            1 => Some(Self::Bt709),
+            5 | 6 => Some(Self::Bt601),
+            7 => Some(Self::Smpte240),
+            // The constant luminance variant is approximated by the non-constant one.
+            9 | 10 => Some(Self::Bt2020),
+            _ => None,
+        }
+    }
+
+    /// Returns the matrix to assume for a stream of size `resolution` that does not signal one:
+    /// BT.709 for HD content, BT.601 otherwise.
+    pub fn guess(resolution: Resolution) -> Self {
+        if resolution.height >= 720 {
+            Self::Bt709
+        } else {
+            Self::Bt601
+        }
+    }
+
+    /// Returns the `(Kr, Kb)` constants of the matrix, or `None` for `Identity`.
+    pub(super) fn kr_kb(self) -> Option<(f32, f32)> {
+        match self {
+            Self::Identity => None,
+            Self::Bt601 => Some((0.299, 0.114)),
+            Self::Bt709 => Some((0.2126, 0.0722)),
+            Self::Smpte240 => Some((0.212, 0.087)),
+            Self::Bt2020 => Some((0.2627, 0.0593)),
+        }
+    }
+}
+
+/// Transfer function of the samples, i.e. how they relate to light intensity.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TransferFunction {
+    /// Gamma-based transfer of standard dynamic range content, e.g. BT.709 or sRGB.
+    #[default]
+    Sdr,
+    /// SMPTE ST 2084 perceptual quantizer, used by HDR10.
+    Pq,
+    /// ARIB STD-B67 hybrid log-gamma.
+    Hlg,
+}
+
+impl TransferFunction {
+    /// Returns the transfer function corresponding to the `transfer_characteristics` value of
+    /// H.264 and H.265 (table E-4 of H.264). Values other than PQ and HLG are considered SDR.
+    pub fn from_h26x(transfer_characteristics: u32) -> Self {
+        match transfer_characteristics {
+            16 => Self::Pq,
+            18 => Self::Hlg,
+            _ => Self::Sdr,
+        }
+    }
+}
+
+/// Position of the chroma samples relative to the luma samples of subsampled frames, named after
+/// the luma sample they are closest to (figure E-1 of H.264).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ChromaSiting {
+    /// Co-sited horizontally, between two lines vertically, as in MPEG-2.
+    #[default]
+    Left,
+    /// Between the four luma samples, as in MPEG-1 and JPEG.
+    Center,
+    /// Co-sited with the top-left luma sample.
+    TopLeft,
+    /// Between two luma samples horizontally, co-sited with the top line vertically.
+    Top,
+    /// Co-sited with the bottom-left luma sample.
+    BottomLeft,
+    /// Between two luma samples horizontally, co-sited with the bottom line vertically.
+    Bottom,
+}
+
+impl ChromaSiting {
+    /// Returns the siting corresponding to the `chroma_sample_loc_type` value of H.264 and H.265.
+    pub fn from_loc_type(chroma_sample_loc_type: u32) -> Option<Self> {
+        match chroma_sample_loc_type {
+            0 => Some(Self::Left),
+            1 => Some(Self::Center),
+            2 => Some(Self::TopLeft),
+            3 => Some(Self::Top),
+            4 => Some(Self::BottomLeft),
+            5 => Some(Self::Bottom),
+            _ => None,
+        }
+    }
+
+    /// Returns the horizontal and vertical offsets of the first chroma sample from the first luma
+    /// sample, in half luma samples.
+    fn offsets(self) -> (u32, u32) {
+        match self {
+            Self::Left => (0, 1),
+            Self::Center => (1, 1),
+            Self::TopLeft => (0, 0),
+            Self::Top => (1, 0),
+            Self::BottomLeft => (0, 2),
+            Self::Bottom => (1, 2),
+        }
+    }
+}
+
+/// How the samples of a stream are to be interpreted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ColorDescription {
+    pub matrix: MatrixCoefficients,
+    pub transfer: TransferFunction,
+    /// Whether samples use the full range of values rather than the "studio" range, e.g. 16 to
+    /// 235 for 8-bit luma.
+    pub full_range: bool,
+    pub chroma_siting: ChromaSiting,
+}
+
+impl ColorDescription {
+    /// Builds the description of a stream of size `resolution` from the video signal type fields
+    /// of its VUI, using defaults for the ones that are not present or not supported.
+    pub fn from_vui(
+        matrix_coefficients: Option<u32>,
+        transfer_characteristics: Option<u32>,
+        full_range: bool,
+        chroma_sample_loc_type: Option<u32>,
+        resolution: Resolution,
+    ) -> Self {
+        Self {
+            matrix: matrix_coefficients
+                .and_then(MatrixCoefficients::from_h26x)
+                .unwrap_or_else(|| MatrixCoefficients::guess(resolution)),
+            transfer: transfer_characteristics
+                .map(TransferFunction::from_h26x)
+                .unwrap_or_default(),
+            full_range,
+            chroma_siting: chroma_sample_loc_type
+                .and_then(ChromaSiting::from_loc_type)
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Returns the description signaled by an H.264 SPS.
+    pub fn from_h264_sps(sps: &H264Sps) -> Self {
+        let vui = &sps.vui_parameters;
+        let present = sps.vui_parameters_present_flag;
+        let signal_type = present && vui.video_signal_type_present_flag;
+        let colour_description = signal_type && vui.colour_description_present_flag;
+
+        Self::from_vui(
+            colour_description.then_some(u32::from(vui.matrix_coefficients)),
+            colour_description.then_some(u32::from(vui.transfer_characteristics)),
+            signal_type && vui.video_full_range_flag,
+            (present && vui.chroma_loc_info_present_flag)
+                .then_some(u32::from(vui.chroma_sample_loc_type_top_field)),
+            Resolution::from((sps.width, sps.height)),
+        )
+    }
+
+    /// Returns the description signaled by an H.265 SPS.
+    pub fn from_h265_sps(sps: &H265Sps) -> Self {
+        let vui = &sps.vui_parameters;
+        let present = sps.vui_parameters_present_flag;
+        let signal_type = present && vui.video_signal_type_present_flag;
+        let colour_description = signal_type && vui.colour_description_present_flag;
+
+        Self::from_vui(
+            colour_description.then_some(vui.matrix_coeffs),
+            colour_description.then_some(vui.transfer_characteristics),
+            signal_type && vui.video_full_range_flag,
+            (present && vui.chroma_loc_info_present_flag)
+                .then_some(vui.chroma_sample_loc_type_top_field),
+            Resolution::from((u32::from(sps.width()), u32::from(sps.height()))),
+        )
+    }
+
+    /// Returns the description signaled by the header of a VP9 key or intra-only frame. VP9 does
+    /// not signal the chroma siting, so it is assumed to be [`ChromaSiting::Left`]. Neither does
+    /// it signal the transfer function, which must be taken from the container for HDR content.
+    pub fn from_vp9_header(header: &Vp9Header) -> Self {
+        let matrix = match header.color_space {
+            ColorSpace::Bt601 | ColorSpace::Smpte170 => MatrixCoefficients::Bt601,
+            ColorSpace::Bt709 => MatrixCoefficients::Bt709,
+            ColorSpace::Smpte240 => MatrixCoefficients::Smpte240,
+            ColorSpace::Bt2020 => MatrixCoefficients::Bt2020,
+            ColorSpace::CsSrgb => MatrixCoefficients::Identity,
+            ColorSpace::Unknown | ColorSpace::Reserved2 => {
+                MatrixCoefficients::guess(Resolution::from((header.width, header.height)))
+            }
+        };
+
+        Self {
+            matrix,
+            transfer: TransferFunction::Sdr,
+            // sRGB always uses the full range.
+            full_range: header.color_range == ColorRange::FullSwing
+                || header.color_space == ColorSpace::CsSrgb,
+            chroma_siting: ChromaSiting::Left,
+        }
+    }
+
+    /// Returns the description of VP8 streams, which always use BT.601 with the studio range.
+    pub fn vp8() -> Self {
+        Self {
+            matrix: MatrixCoefficients::Bt601,
+            transfer: TransferFunction::Sdr,
+            full_range: false,
+            chroma_siting: ChromaSiting::Left,
+        }
+    }
+}
+
+/// RGB formats that YUV frames can be converted to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RgbFormat {
+    /// R, G and B bytes for each pixel.
+    Rgb24,
+    /// R, G, B and A bytes for each pixel, with an opaque alpha.
+    Rgba,
+    /// B, G, R and A bytes for each pixel, with an opaque alpha.
+    Bgra,
+    /// R, G and B planes of native-endian `f32` values between 0.0 and 1.0.
+    PlanarF32,
+}
+
+impl RgbFormat {
+    /// Returns the number of bytes taken by a frame of size `size` in this format, without
+    /// padding.
+    pub fn frame_size(self, size: Resolution) -> usize {
+        let pixels = size.width as usize * size.height as usize;
+
+        match self {
+            Self::Rgb24 => pixels * 3,
+            Self::Rgba | Self::Bgra => pixels * 4,
+            Self::PlanarF32 => pixels * 3 * std::mem::size_of::<f32>(),
+        }
+    }
+
+    /// Writes the 8-bit `rgb` values of pixel `i` of an 8-bit format into `dst`.
+    fn write_u8(self, dst: &mut [u8], i: usize, [r, g, b]: [u8; 3]) {
+        match self {
+            Self::Rgb24 => dst[3 * i..][..3].copy_from_slice(&[r, g, b]),
+            Self::Rgba => dst[4 * i..][..4].copy_from_slice(&[r, g, b, 0xff]),
+            Self::Bgra => dst[4 * i..][..4].copy_from_slice(&[b, g, r, 0xff]),
+            Self::PlanarF32 => unreachable!(),
+        }
+    }
+}
+
+/// Two chroma samples to interpolate, and their weights out of 4.
+type Taps = [(usize, u32); 2];
+
+/// Returns the taps to interpolate the chroma of the luma sample at `pos`. `len` is the number of
+/// chroma samples, and `offset` the offset of the first chroma sample from the first luma sample,
+/// in half luma samples.
+fn taps(pos: usize, len: usize, subsampled: bool, offset: u32) -> Taps {
+    if !subsampled {
+        return [(pos, 4), (pos, 0)];
+    }
+
+    // Position of the luma sample in quarters of chroma samples.
+    let pos = 2 * pos as i64 - i64::from(offset);
+    let first = pos.div_euclid(4);
+    let weight = pos.rem_euclid(4) as u32;
+    let clamp = |i: i64| i.clamp(0, len as i64 - 1) as usize;
+
+    [(clamp(first), 4 - weight), (clamp(first + 1), weight)]
+}
+
+/// Returns the chroma taps for each column and each line of a `width`x`height` frame.
+fn frame_taps(
+    width: usize,
+    height: usize,
+    subsampling: (bool, bool),
+    siting: ChromaSiting,
+) -> (Vec<Taps>, Vec<Taps>) {
+    let (chroma_width, chroma_height) = chroma_size(width, height, subsampling);
+    let (h_offset, v_offset) = siting.offsets();
+
+    (
+        (0..width)
+            .map(|x| taps(x, chroma_width, subsampling.0, h_offset))
+            .collect(),
+        (0..height)
+            .map(|y| taps(y, chroma_height, subsampling.1, v_offset))
+            .collect(),
+    )
+}
+
+/// Coefficients turning normalized Y, Pb and Pr values into RGB.
+struct Matrix {
+    /// Offset and range of the luma samples.
+    y_offset: f32,
+    y_range: f32,
+    /// Offset and range of the chroma samples.
+    c_offset: f32,
+    c_range: f32,
+    /// Contributions of Pr to R, Pb and Pr to G, and Pb to B, or `None` for the identity matrix.
+    coefficients: Option<[f32; 4]>,
+}
+
+impl Matrix {
+    fn new(color: &ColorDescription, bit_depth: u8) -> Self {
+        let scale = (1u32 << (bit_depth - 8)) as f32;
+        let max = ((1u32 << bit_depth) - 1) as f32;
+        let (y_offset, y_range, c_offset, c_range) = if color.full_range {
+            (0.0, max, (1u32 << (bit_depth - 1)) as f32, max)
+        } else {
+            (16.0 * scale, 219.0 * scale, 128.0 * scale, 224.0 * scale)
+        };
+
+        let coefficients = color.matrix.kr_kb().map(|(kr, kb)| {
+            let kg = 1.0 - kr - kb;
+            [
+                2.0 * (1.0 - kr),
+                2.0 * kb * (1.0 - kb) / kg,
+                2.0 * kr * (1.0 - kr) / kg,
+                2.0 * (1.0 - kb),
+            ]
+        });
+
+        Self {
+            y_offset,
+            y_range,
+            c_offset,
+            c_range,
+            coefficients,
+        }
+    }
+
+    /// Returns the R, G and B values between 0.0 and 1.0 of a pixel with samples `y`, `u` and
+    /// `v`.
+    fn to_rgb(&self, y: f32, u: f32, v: f32) -> [f32; 3] {
+        let normalize_y = |sample: f32| (sample - self.y_offset) / self.y_range;
+
+        let rgb = match self.coefficients {
+            // All the components use the range of luma.
+            None => [normalize_y(v), normalize_y(y), normalize_y(u)],
+            Some([r_v, g_u, g_v, b_u]) => {
+                let y = normalize_y(y);
+                let u = (u - self.c_offset) / self.c_range;
+                let v = (v - self.c_offset) / self.c_range;
+
+                [y + r_v * v, y - g_u * u - g_v * v, y + b_u * u]
+            }
+        };
+
+        rgb.map(|c| c.clamp(0.0, 1.0))
+    }
+}
+
+/// Calls `f` with the index and the R, G and B values between 0.0 and 1.0 of each pixel of
+/// `frame`, in raster order, using floating-point arithmetic.
+pub(super) fn for_each_rgb_pixel<F: FnMut(usize, [f32; 3])>(
+    frame: &Planar,
+    color: &ColorDescription,
+    mut f: F,
+) {
+    let (width, height) = (frame.width, frame.height);
+    let (chroma_width, _) = frame.chroma_size();
+    let (column_taps, line_taps) =
+        frame_taps(width, height, frame.subsampling, color.chroma_siting);
+    let matrix = Matrix::new(color, frame.bit_depth);
+    let [y_plane, u_plane, v_plane] = &frame.planes;
+
+    for (y, line_taps) in line_taps.iter().enumerate() {
+        for (x, column_taps) in column_taps.iter().enumerate() {
+            let interpolate = |plane: &[u16]| {
+                let mut sum = 0;
+                for &(line, line_weight) in line_taps {
+                    for &(column, column_weight) in column_taps {
+                        sum += line_weight
+                            * column_weight
+                            * u32::from(plane[line * chroma_width + column]);
+                    }
+                }
+                sum as f32 / 16.0
+            };
+
+            let i = y * width + x;
+            f(
+                i,
+                matrix.to_rgb(
+                    f32::from(y_plane[i]),
+                    interpolate(u_plane),
+                    interpolate(v_plane),
+                ),
+            );
+        }
+    }
+}
+
+/// Converts `frame` to RGB using floating-point arithmetic.
+fn planar_to_rgb(frame: &Planar, dst: &mut [u8], dst_format: RgbFormat, color: &ColorDescription) {
+    let plane_size = frame.width * frame.height * std::mem::size_of::<f32>();
+
+    for_each_rgb_pixel(frame, color, |i, rgb| {
+        if dst_format == RgbFormat::PlanarF32 {
+            for (c, value) in rgb.iter().enumerate() {
+                dst[c * plane_size + 4 * i..][..4].copy_from_slice(&value.to_ne_bytes());
+            }
+        } else {
+            dst_format.write_u8(dst, i, rgb.map(|c| (c * 255.0).round() as u8));
+        }
+    });
+}
+
+/// Number of fractional bits of the fixed-point coefficients.
+const FIXED_POINT_BITS: u32 = 16;
+
+/// Converts the 8-bit 4:2:0 frame in `src` to 8-bit RGB using fixed-point arithmetic.
+fn yuv420_8bit_to_rgb(
+    src: &[u8],
+    src_layout: &FrameLayout,
+    semi_planar: bool,
+    dst: &mut [u8],
+    dst_format: RgbFormat,
+    color: &ColorDescription,
+) -> anyhow::Result<()> {
+    let (width, height) = (
+        src_layout.size.width as usize,
+        src_layout.size.height as usize,
+    );
+    let (chroma_width, chroma_height) = chroma_size(width, height, (true, true));
+    let (column_taps, line_taps) = frame_taps(width, height, (true, true), color.chroma_siting);
+
+    let y_lines = lines(src, src_layout, 0, width, height)?.collect::<Vec<_>>();
+    // U and V samples, and the distance between two samples of the same component.
+    let (u_lines, v_lines, step) = if semi_planar {
+        let uv_lines = lines(src, src_layout, 1, 2 * chroma_width, chroma_height)?;
+        let (u, v): (Vec<_>, Vec<_>) = uv_lines.map(|line| (line, &line[1..])).unzip();
+        (u, v, 2)
+    } else {
+        let u = lines(src, src_layout, 1, chroma_width, chroma_height)?.collect();
+        let v = lines(src, src_layout, 2, chroma_width, chroma_height)?.collect();
+        (u, v, 1)
+    };
+
+    let matrix = Matrix::new(color, 8);
+    let fixed = |value: f32, bits: u32| (value * (1 << bits) as f32).round() as i32;
+    let y_offset = matrix.y_offset as i32;
+    let y_mul = fixed(255.0 / matrix.y_range, FIXED_POINT_BITS);
+    // The interpolated chroma samples are 16 times their value, so their coefficients have 4
+    // fractional bits less.
+    let c_offset = 16 * matrix.c_offset as i32;
+    let [r_v, g_u, g_v, b_u] = matrix
+        .coefficients
+        .ok_or_else(|| anyhow!("the identity matrix has no fixed-point path"))?
+        .map(|c| fixed(255.0 * c / matrix.c_range, FIXED_POINT_BITS - 4));
+    let round = 1 << (FIXED_POINT_BITS - 1);
+    let to_u8 = |value: i32| (value >> FIXED_POINT_BITS).clamp(0, 255) as u8;
+
+    let mut u_line = vec![0u32; chroma_width];
+    let mut v_line = vec![0u32; chroma_width];
+
+    for (y, &[(line0, weight0), (line1, weight1)]) in line_taps.iter().enumerate() {
+        // Interpolate vertically once per line.
+        for x in 0..chroma_width {
+            let interpolate = |lines: &[&[u8]]| {
+                weight0 * u32::from(lines[line0][step * x])
+                    + weight1 * u32::from(lines[line1][step * x])
+            };
+            u_line[x] = interpolate(&u_lines);
+            v_line[x] = interpolate(&v_lines);
+        }
+
+        for (x, &[(column0, weight0), (column1, weight1)]) in column_taps.iter().enumerate() {
+            let u = (weight0 * u_line[column0] + weight1 * u_line[column1]) as i32 - c_offset;
+            let v = (weight0 * v_line[column0] + weight1 * v_line[column1]) as i32 - c_offset;
+            let luma = (i32::from(y_lines[y][x]) - y_offset) * y_mul + round;
+
+            dst_format.write_u8(
+                dst,
+                y * width + x,
+                [
+                    to_u8(luma + r_v * v),
+                    to_u8(luma - g_u * u - g_v * v),
+                    to_u8(luma + b_u * u),
+                ],
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Converts the YUV frame in `src`, laid out according to `src_layout`, into `dst` as
+/// `dst_format`, interpreting its samples according to `color`.
+///
+/// The whole frame described by `src_layout` is converted, and written into `dst` without
+/// padding.
+pub fn yuv_to_rgb(
+    src: &[u8],
+    src_layout: &FrameLayout,
+    dst: &mut [u8],
+    dst_format: RgbFormat,
+    color: &ColorDescription,
+) -> anyhow::Result<()> {
+    let src_format = layout_format(src_layout)?;
+    let size = src_layout.size;
+
+    let dst_size = dst_format.frame_size(size);
+    if dst.len() < dst_size {
+        return Err(anyhow!(
+            "destination buffer is {} bytes but {} are needed",
+            dst.len(),
+            dst_size
+        ));
+    }
+
+    let info = src_format.info();
+    if info.bit_depth == 8
+        && info.subsampling == (true, true)
+        && dst_format != RgbFormat::PlanarF32
+        && color.matrix != MatrixCoefficients::Identity
+    {
+        let semi_planar = info.packing == Packing::SemiPlanar;
+        return yuv420_8bit_to_rgb(src, src_layout, semi_planar, dst, dst_format, color);
+    }
+
+    let frame = read(
+        src,
+        src_layout,
+        src_format,
+        size.width as usize,
+        size.height as usize,
+    )?;
+    planar_to_rgb(&frame, dst, dst_format, color);
+
+    Ok(())
+}
+
+/// Converts a frame of `format` and size `size` returned by
+/// [`MappableHandle::read`](crate::decoder::MappableHandle::read) to RGB.
+pub fn decoded_to_rgb(
+    src: &[u8],
+    format: DecodedFormat,
+    size: Resolution,
+    dst: &mut [u8],
+    dst_format: RgbFormat,
+    color: &ColorDescription,
+) -> anyhow::Result<()> {
+    let layout = PixelFormat::from(format).packed_layout(size);
+    yuv_to_rgb(src, &layout, dst, dst_format, color)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::convert::write;
+
+    const BT601_LIMITED: ColorDescription = ColorDescription {
+        matrix: MatrixCoefficients::Bt601,
+        transfer: TransferFunction::Sdr,
+        full_range: false,
+        chroma_siting: ChromaSiting::Left,
+    };
+
+    /// Converts a 4x4 frame of `format` with constant `y`, `u` and `v` samples to RGB24 and
+    /// returns its first pixel.
+    fn convert_flat(
+        format: DecodedFormat,
+        [y, u, v]: [u16; 3],
+        color: &ColorDescription,
+    ) -> [u8; 3] {
+        let size = Resolution::from((4, 4));
+        let format = PixelFormat::from(format);
+        let info = format.info();
+        let (chroma_width, chroma_height) = chroma_size(4, 4, info.subsampling);
+        let frame = Planar {
+            width: 4,
+            height: 4,
+            bit_depth: info.bit_depth,
+            subsampling: info.subsampling,
+            planes: [
+                vec![y; 16],
+                vec![u; chroma_width * chroma_height],
+                vec![v; chroma_width * chroma_height],
+            ],
+        };
+
+        let layout = format.packed_layout(size);
+        let mut src = vec![0u8; format.packed_size(size)];
+        write(&frame, &mut src, &layout, format).unwrap();
+
+        let mut dst = vec![0u8; RgbFormat::Rgb24.frame_size(size)];
+        yuv_to_rgb(&src, &layout, &mut dst, RgbFormat::Rgb24, color).unwrap();
+        assert!(dst.chunks(3).all(|pixel| pixel == &dst[..3]));
+
+        [dst[0], dst[1], dst[2]]
+    }
+
+    fn assert_close(actual: [u8; 3], expected: [u8; 3]) {
+        assert!(
+            actual
+                .iter()
+                .zip(expected)
+                .all(|(&a, e)| (i16::from(a) - i16::from(e)).abs() <= 1),
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn reference_colors() {
+        let bt709_full = ColorDescription {
+            matrix: MatrixCoefficients::Bt709,
+            transfer: TransferFunction::Sdr,
+            full_range: true,
+            chroma_siting: ChromaSiting::Left,
+        };
+
+        // (samples, expected RGB) for BT.601 studio range and BT.709 full range.
+        let bt601_colors = [
+            ([16, 128, 128], [0, 0, 0]),
+            ([235, 128, 128], [255, 255, 255]),
+            ([81, 90, 240], [255, 0, 0]),
+            ([145, 54, 34], [0, 255, 0]),
+            ([41, 240, 110], [0, 0, 255]),
+        ];
+        let bt709_colors = [
+            ([0, 128, 128], [0, 0, 0]),
+            ([255, 128, 128], [255, 255, 255]),
+            ([54, 99, 255], [255, 0, 0]),
+            ([182, 30, 12], [0, 255, 0]),
+            ([18, 255, 116], [0, 0, 255]),
+        ];
+
+        for format in [
+            DecodedFormat::I420,
+            DecodedFormat::NV12,
+            DecodedFormat::I444,
+        ] {
+            for (samples, rgb) in bt601_colors {
+                assert_close(convert_flat(format, samples, &BT601_LIMITED), rgb);
+            }
+            for (samples, rgb) in bt709_colors {
+                assert_close(convert_flat(format, samples, &bt709_full), rgb);
+            }
+        }
+
+        // 10-bit samples are 4 times larger.
+        for (samples, rgb) in bt601_colors {
+            assert_close(
+                convert_flat(DecodedFormat::I010, samples.map(|s| s << 2), &BT601_LIMITED),
+                rgb,
+            );
+        }
+
+        // Out of range values are clamped.
+        assert_eq!(
+            convert_flat(DecodedFormat::I420, [255, 128, 128], &BT601_LIMITED),
+            [255, 255, 255]
+        );
+        assert_eq!(
+            convert_flat(DecodedFormat::I420, [0, 128, 128], &BT601_LIMITED),
+            [0, 0, 0]
+        );
+
+        let identity = ColorDescription {
+            matrix: MatrixCoefficients::Identity,
+            transfer: TransferFunction::Sdr,
+            full_range: true,
+            chroma_siting: ChromaSiting::Left,
+        };
+        assert_eq!(
+            convert_flat(DecodedFormat::I444, [10, 20, 30], &identity),
+            [30, 10, 20]
+        );
+    }
+
+    #[test]
+    fn fixed_point_matches_floating_point() {
+        let size = Resolution::from((6, 4));
+        let format = PixelFormat::Decoded(DecodedFormat::I420);
+        let src = (0..format.packed_size(size))
+            .map(|i| (i * 37 % 256) as u8)
+            .collect::<Vec<_>>();
+        let layout = format.packed_layout(size);
+
+        for siting in [
+            ChromaSiting::Left,
+            ChromaSiting::Center,
+            ChromaSiting::Bottom,
+        ] {
+            let color = ColorDescription {
+                chroma_siting: siting,
+                ..BT601_LIMITED
+            };
+
+            let mut fixed = vec![0u8; RgbFormat::Rgb24.frame_size(size)];
+            yuv_to_rgb(&src, &layout, &mut fixed, RgbFormat::Rgb24, &color).unwrap();
+
+            let frame = read(&src, &layout, format, 6, 4).unwrap();
+            let mut float = vec![0u8; RgbFormat::Rgb24.frame_size(size)];
+            planar_to_rgb(&frame, &mut float, RgbFormat::Rgb24, &color);
+
+            for (a, b) in fixed.iter().zip(&float) {
+                assert!((i16::from(*a) - i16::from(*b)).abs() <= 1, "{:?}", siting);
+            }
+        }
+    }
+
+    #[test]
+    fn chroma_siting() {
+        // Luma positions 0 and 1 of a line with chroma samples 0 and 64.
+        let interpolate = |siting: ChromaSiting, pos: usize| {
+            taps(pos, 2, true, siting.offsets().0)
+                .iter()
+                .map(|&(i, weight)| weight * [0, 64][i])
+                .sum::<u32>()
+                / 4
+        };
+
+        // Co-sited samples are used as is for even positions and averaged for odd ones.
+        assert_eq!(interpolate(ChromaSiting::Left, 0), 0);
+        assert_eq!(interpolate(ChromaSiting::Left, 1), 32);
+        assert_eq!(interpolate(ChromaSiting::Left, 2), 64);
+        // Centered samples are a quarter of a chroma sample away from each luma sample.
+        assert_eq!(interpolate(ChromaSiting::Center, 0), 0);
+        assert_eq!(interpolate(ChromaSiting::Center, 1), 16);
+        assert_eq!(interpolate(ChromaSiting::Center, 2), 48);
+        assert_eq!(interpolate(ChromaSiting::Center, 3), 64);
+
+        // Vertical siting.
+        assert_eq!(
+            taps(0, 2, true, ChromaSiting::TopLeft.offsets().1),
+            [(0, 4), (1, 0)]
+        );
+        assert_eq!(
+            taps(1, 2, true, ChromaSiting::Left.offsets().1),
+            [(0, 3), (1, 1)]
+        );
+        assert_eq!(
+            taps(1, 2, true, ChromaSiting::Bottom.offsets().1),
+            [(0, 4), (1, 0)]
+        );
+        assert_eq!(
+            taps(2, 2, true, ChromaSiting::Bottom.offsets().1),
+            [(0, 2), (1, 2)]
+        );
+    }
+
+    #[test]
+    fn output_formats() {
+        let size = Resolution::from((2, 2));
+        // BT.601 studio range red.
+        let src = [81, 81, 81, 81, 90, 240];
+
+        let mut rgba = [0u8; 16];
+        decoded_to_rgb(
+            &src,
+            DecodedFormat::I420,
+            size,
+            &mut rgba,
+            RgbFormat::Rgba,
+            &BT601_LIMITED,
+        )
+        .unwrap();
+        assert_close([rgba[0], rgba[1], rgba[2]], [255, 0, 0]);
+        assert_eq!(rgba[3], 0xff);
+
+        let mut bgra = [0u8; 16];
+        decoded_to_rgb(
+            &src,
+            DecodedFormat::I420,
+            size,
+            &mut bgra,
+            RgbFormat::Bgra,
+            &BT601_LIMITED,
+        )
+        .unwrap();
+        assert_eq!(
+            rgba.chunks(4).next(),
+            Some(&[bgra[2], bgra[1], bgra[0], 0xff][..])
+        );
+
+        let mut float = vec![0u8; RgbFormat::PlanarF32.frame_size(size)];
+        decoded_to_rgb(
+            &src,
+            DecodedFormat::I420,
+            size,
+            &mut float,
+            RgbFormat::PlanarF32,
+            &BT601_LIMITED,
+        )
+        .unwrap();
+        let float = float
+            .chunks(4)
+            .map(|c| f32::from_ne_bytes(c.try_into().unwrap()))
+            .collect::<Vec<_>>();
+        // R plane, then G and B.
+        assert!(float[..4].iter().all(|&r| r > 0.99));
+        assert!(float[4..].iter().all(|&c| c < 0.01));
+
+        // Destination too small.
+        assert!(decoded_to_rgb(
+            &src,
+            DecodedFormat::I420,
+            size,
+            &mut rgba[1..],
+            RgbFormat::Rgba,
+            &BT601_LIMITED,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn color_descriptions() {
+        assert_eq!(
+            ColorDescription::from_vui(
+                Some(9),
+                Some(16),
+                true,
+                Some(2),
+                Resolution::from((320, 240))
+            ),
+            ColorDescription {
+                matrix: MatrixCoefficients::Bt2020,
+                transfer: TransferFunction::Pq,
+                full_range: true,
+                chroma_siting: ChromaSiting::TopLeft,
+            }
+        );
+
+        // Unspecified matrices depend on the resolution.
+        assert_eq!(
+            ColorDescription::from_vui(Some(2), Some(2), false, None, Resolution::from((320, 240))),
+            BT601_LIMITED
+        );
+        assert_eq!(
+            ColorDescription::from_vui(None, None, false, None, Resolution::from((1280, 720)))
+                .matrix,
+            MatrixCoefficients::Bt709
+        );
+    }
+}
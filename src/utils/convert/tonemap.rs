@@ -0,0 +1,645 @@
+// Copyright 2023 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+//! Tone mapping of HDR frames to SDR.
+//!
+//! [`ToneMapper::tone_map`] takes a frame using the PQ (HDR10) or HLG transfer function and BT.2020
+//! primaries, and produces an 8-bit frame using the BT.709 matrix, primaries and transfer
+//! function, suitable for SDR displays and thumbnails.
+//!
+//! Samples are converted to linear light in cd/m², the luminance of each pixel is compressed into
+//! the range of the SDR display using a [`ToneMapOperator`], and the colors that BT.709 cannot
+//! represent are brought back into its gamut according to a [`GamutMapping`].
+
+use anyhow::anyhow;
+
+use crate::utils::convert::layout_format;
+use crate::utils::convert::read;
+use crate::utils::convert::rgb::for_each_rgb_pixel;
+use crate::utils::convert::rgb::ColorDescription;
+use crate::utils::convert::rgb::MatrixCoefficients;
+use crate::utils::convert::rgb::TransferFunction;
+use crate::utils::convert::write;
+use crate::utils::convert::Packing;
+use crate::utils::convert::Planar;
+use crate::FrameLayout;
+
+/// Payload type of the mastering display colour volume SEI message of H.265.
+pub const SEI_MASTERING_DISPLAY_COLOUR_VOLUME: u32 = 137;
+/// Payload type of the content light level information SEI message of H.265.
+pub const SEI_CONTENT_LIGHT_LEVEL_INFO: u32 = 144;
+
+/// Peak luminance in cd/m² assumed for HLG content, and for PQ content without metadata.
+const DEFAULT_HDR_PEAK: f32 = 1000.0;
+/// Luminance in cd/m² of the peak white of a reference SDR display.
+const DEFAULT_SDR_PEAK: f32 = 100.0;
+
+/// Luminance coefficients of BT.2020 and BT.709.
+const BT2020_LUMA: [f32; 3] = [0.2627, 0.6780, 0.0593];
+const BT709_LUMA: [f32; 3] = [0.2126, 0.7152, 0.0722];
+
+/// Conversion of linear RGB from BT.2020 to BT.709 primaries (BT.2407).
+const BT2020_TO_BT709: [[f32; 3]; 3] = [
+    [1.6605, -0.5876, -0.0728],
+    [-0.1246, 1.1329, -0.0083],
+    [-0.0182, -0.1006, 1.1187],
+];
+
+/// Constants of the PQ transfer function (SMPTE ST 2084).
+const PQ_M1: f32 = 2610.0 / 16384.0;
+const PQ_M2: f32 = 2523.0 / 4096.0 * 128.0;
+const PQ_C1: f32 = 3424.0 / 4096.0;
+const PQ_C2: f32 = 2413.0 / 4096.0 * 32.0;
+const PQ_C3: f32 = 2392.0 / 4096.0 * 32.0;
+/// Luminance in cd/m² of a PQ signal of 1.0.
+const PQ_PEAK: f32 = 10000.0;
+
+/// Constants of the HLG transfer function (BT.2100).
+const HLG_A: f32 = 0.178_832_77;
+const HLG_B: f32 = 0.284_668_92;
+const HLG_C: f32 = 0.559_910_7;
+/// System gamma of the HLG OOTF for a 1000 cd/m² display.
+const HLG_GAMMA: f32 = 1.2;
+
+/// Exponent of the BT.1886 EOTF of SDR displays.
+const BT1886_GAMMA: f32 = 2.4;
+
+/// Returns the luminance in cd/m² of the PQ signal `e`.
+fn pq_eotf(e: f32) -> f32 {
+    let p = e.max(0.0).powf(1.0 / PQ_M2);
+    ((p - PQ_C1).max(0.0) / (PQ_C2 - PQ_C3 * p)).powf(1.0 / PQ_M1) * PQ_PEAK
+}
+
+/// Returns the PQ signal of the luminance `l` in cd/m².
+fn pq_inverse_eotf(l: f32) -> f32 {
+    let y = (l / PQ_PEAK).clamp(0.0, 1.0).powf(PQ_M1);
+    ((PQ_C1 + PQ_C2 * y) / (1.0 + PQ_C3 * y)).powf(PQ_M2)
+}
+
+/// Returns the relative scene light between 0.0 and 1.0 of the HLG signal `e`.
+fn hlg_inverse_oetf(e: f32) -> f32 {
+    if e <= 0.5 {
+        e * e / 3.0
+    } else {
+        (((e - HLG_C) / HLG_A).exp() + HLG_B) / 12.0
+    }
+}
+
+/// Metadata of the mastering display of a stream, from its mastering display colour volume SEI
+/// message.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MasteringDisplay {
+    /// Peak luminance of the display, in cd/m².
+    pub max_luminance: f32,
+    /// Black level of the display, in cd/m².
+    pub min_luminance: f32,
+}
+
+impl MasteringDisplay {
+    /// Parses the payload of a mastering display colour volume SEI message (D.2.28 of H.265).
+    pub fn from_sei_payload(payload: &[u8]) -> anyhow::Result<Self> {
+        // Primaries and white point come first, as 8 16-bit values.
+        let luminance = payload
+            .get(16..24)
+            .ok_or_else(|| anyhow!("mastering display colour volume payload is too short"))?;
+        let read = |i: usize| u32::from_be_bytes(luminance[i..i + 4].try_into().unwrap());
+
+        // Both luminances are in units of 0.0001 cd/m².
+        Ok(Self {
+            max_luminance: read(0) as f32 / 10000.0,
+            min_luminance: read(4) as f32 / 10000.0,
+        })
+    }
+}
+
+/// Light level of the content of a stream, from its content light level information SEI message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ContentLightLevel {
+    /// Luminance of the brightest pixel of the stream, in cd/m².
+    pub max_content_light_level: u16,
+    /// Average luminance of the brightest frame of the stream, in cd/m².
+    pub max_frame_average_light_level: u16,
+}
+
+impl ContentLightLevel {
+    /// Parses the payload of a content light level information SEI message (D.2.35 of H.265).
+    pub fn from_sei_payload(payload: &[u8]) -> anyhow::Result<Self> {
+        match payload {
+            [a, b, c, d, ..] => Ok(Self {
+                max_content_light_level: u16::from_be_bytes([*a, *b]),
+                max_frame_average_light_level: u16::from_be_bytes([*c, *d]),
+            }),
+            _ => Err(anyhow!("content light level payload is too short")),
+        }
+    }
+}
+
+/// Static HDR metadata of a stream, used to know the range of luminance to compress.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct HdrMetadata {
+    pub mastering_display: Option<MasteringDisplay>,
+    pub content_light_level: Option<ContentLightLevel>,
+}
+
+impl HdrMetadata {
+    /// Returns the peak luminance of PQ content in cd/m², preferring the measured content light
+    /// level over the capabilities of the mastering display.
+    fn pq_peak(&self) -> f32 {
+        let max_cll = self
+            .content_light_level
+            .map(|cll| f32::from(cll.max_content_light_level))
+            .filter(|&l| l > 0.0);
+        let mastering_peak = self
+            .mastering_display
+            .map(|display| display.max_luminance)
+            .filter(|&l| l > 0.0);
+
+        max_cll.or(mastering_peak).unwrap_or(DEFAULT_HDR_PEAK)
+    }
+}
+
+/// Curves used to compress the luminance of HDR content.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ToneMapOperator {
+    /// The filmic curve of John Hable, with a strong contrast in the highlights.
+    Hable,
+    /// The EETF of ITU-R BT.2390, which leaves the darker part of the image untouched and rolls
+    /// off the highlights in the PQ domain.
+    #[default]
+    Bt2390,
+    /// The extended Reinhard curve, mapping the peak luminance of the content to the peak of the
+    /// display.
+    Reinhard,
+}
+
+/// How colors outside of the BT.709 gamut are handled.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GamutMapping {
+    /// Each component is clamped on its own, which can shift the hue of saturated colors.
+    Clip,
+    /// Colors are desaturated towards the gray of the same luminance until they fit.
+    #[default]
+    Desaturate,
+}
+
+/// Curve of the filmic operator of John Hable.
+fn hable(x: f32) -> f32 {
+    const A: f32 = 0.15;
+    const B: f32 = 0.50;
+    const C: f32 = 0.10;
+    const D: f32 = 0.20;
+    const E: f32 = 0.02;
+    const F: f32 = 0.30;
+
+    (x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F) - E / F
+}
+
+/// Converts HDR frames to SDR.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ToneMapper {
+    pub operator: ToneMapOperator,
+    pub gamut_mapping: GamutMapping,
+    /// Metadata of PQ streams. HLG streams are always considered to peak at 1000 cd/m².
+    pub metadata: HdrMetadata,
+    /// Peak luminance of the SDR display, in cd/m².
+    pub target_peak: f32,
+}
+
+impl Default for ToneMapper {
+    fn default() -> Self {
+        Self {
+            operator: Default::default(),
+            gamut_mapping: Default::default(),
+            metadata: Default::default(),
+            target_peak: DEFAULT_SDR_PEAK,
+        }
+    }
+}
+
+impl ToneMapper {
+    /// Returns the tone-mapped luminance in cd/m² of `l`, for content whose luminance is between
+    /// `black` and `peak`.
+    fn map_luminance(&self, l: f32, black: f32, peak: f32) -> f32 {
+        let target = self.target_peak;
+        if peak <= target {
+            return l.min(target);
+        }
+
+        match self.operator {
+            ToneMapOperator::Reinhard => {
+                let (x, w) = (l / target, peak / target);
+                target * x * (1.0 + x / (w * w)) / (1.0 + x)
+            }
+            ToneMapOperator::Hable => target * hable(l / target) / hable(peak / target),
+            ToneMapOperator::Bt2390 => {
+                // Work with PQ values normalized to the range of the content.
+                let (pq_black, pq_peak) = (pq_inverse_eotf(black), pq_inverse_eotf(peak));
+                let normalize = |l: f32| (pq_inverse_eotf(l) - pq_black) / (pq_peak - pq_black);
+                let max_lum = normalize(target);
+                let min_lum = normalize(0.0).max(0.0);
+
+                let e1 = normalize(l).clamp(0.0, 1.0);
+                let knee = 1.5 * max_lum - 0.5;
+                let e2 = if e1 < knee {
+                    e1
+                } else {
+                    // Hermite spline rolling off from the knee to the target peak.
+                    let t = (e1 - knee) / (1.0 - knee);
+                    let (t2, t3) = (t * t, t * t * t);
+                    (2.0 * t3 - 3.0 * t2 + 1.0) * knee
+                        + (t3 - 2.0 * t2 + t) * (1.0 - knee)
+                        + (-2.0 * t3 + 3.0 * t2) * max_lum
+                };
+                let e3 = e2 + min_lum * (1.0 - e2).powi(4);
+
+                pq_eotf(e3 * (pq_peak - pq_black) + pq_black).min(target)
+            }
+        }
+    }
+
+    /// Brings the linear BT.709 `rgb` color into the [0.0, 1.0] range.
+    fn map_gamut(&self, rgb: [f32; 3]) -> [f32; 3] {
+        let rgb = match self.gamut_mapping {
+            GamutMapping::Clip => rgb,
+            GamutMapping::Desaturate => {
+                let luma = dot(BT709_LUMA, rgb).clamp(0.0, 1.0);
+                // Largest fraction of the chroma that keeps all components in range.
+                let t = rgb.iter().fold(1.0f32, |t, &c| {
+                    if c < 0.0 {
+                        t.min(luma / (luma - c))
+                    } else if c > 1.0 {
+                        t.min((1.0 - luma) / (c - luma))
+                    } else {
+                        t
+                    }
+                });
+
+                if t < 1.0 {
+                    rgb.map(|c| luma + t * (c - luma))
+                } else {
+                    rgb
+                }
+            }
+        };
+
+        rgb.map(|c| c.clamp(0.0, 1.0))
+    }
+
+    /// Tone maps the HDR frame in `src`, laid out according to `src_layout` and described by
+    /// `color`, into `dst` according to `dst_layout`.
+    ///
+    /// `color` must use the PQ or HLG transfer function, and `dst_layout` an 8-bit planar or
+    /// semi-planar format. As with [`convert`](super::convert), only the top-left part of the
+    /// source frame with the size of `dst_layout` is converted.
+    pub fn tone_map(
+        &self,
+        src: &[u8],
+        src_layout: &FrameLayout,
+        color: &ColorDescription,
+        dst: &mut [u8],
+        dst_layout: &FrameLayout,
+    ) -> anyhow::Result<()> {
+        let src_format = layout_format(src_layout)?;
+        let dst_format = layout_format(dst_layout)?;
+        let dst_info = dst_format.info();
+        if dst_info.bit_depth != 8
+            || !matches!(dst_info.packing, Packing::Planar | Packing::SemiPlanar)
+        {
+            return Err(anyhow!(
+                "cannot tone map into {}, an 8-bit planar format is needed",
+                dst_layout.format.0
+            ));
+        }
+
+        let (black, peak) = match color.transfer {
+            TransferFunction::Pq => (
+                self.metadata
+                    .mastering_display
+                    .map(|display| display.min_luminance)
+                    .unwrap_or_default(),
+                self.metadata.pq_peak(),
+            ),
+            TransferFunction::Hlg => (0.0, DEFAULT_HDR_PEAK),
+            TransferFunction::Sdr => {
+                return Err(anyhow!("only PQ and HLG frames can be tone mapped"))
+            }
+        };
+
+        let size = dst_layout.size;
+        if !src_layout.size.can_contain(size) {
+            return Err(anyhow!(
+                "cannot tone map a {:?} frame into a {:?} one",
+                src_layout.size,
+                size
+            ));
+        }
+
+        let (width, height) = (size.width as usize, size.height as usize);
+        let frame = read(src, src_layout, src_format, width, height)?;
+
+        let mut planes: [Vec<u16>; 3] = Default::default();
+        for plane in &mut planes {
+            plane.reserve(width * height);
+        }
+
+        for_each_rgb_pixel(&frame, color, |_, rgb| {
+            // Display light in cd/m², with BT.2020 primaries.
+            let linear = match color.transfer {
+                TransferFunction::Hlg => {
+                    let scene = rgb.map(hlg_inverse_oetf);
+                    let scene_luma = dot(BT2020_LUMA, scene);
+                    let gain = DEFAULT_HDR_PEAK * scene_luma.powf(HLG_GAMMA - 1.0);
+                    scene.map(|c| c * gain)
+                }
+                _ => rgb.map(pq_eotf),
+            };
+
+            // Compress the luminance and scale the components alike to preserve the hue.
+            let luma = dot(BT2020_LUMA, linear);
+            let ratio = if luma > 0.0 {
+                self.map_luminance(luma, black, peak) / luma / self.target_peak
+            } else {
+                0.0
+            };
+            let linear = BT2020_TO_BT709.map(|row| dot(row, linear) * ratio);
+
+            let [r, g, b] = self.map_gamut(linear).map(|c| c.powf(1.0 / BT1886_GAMMA));
+
+            // BT.709 studio range YUV.
+            let y = dot(BT709_LUMA, [r, g, b]);
+            let u = (b - y) / (2.0 * (1.0 - BT709_LUMA[2]));
+            let v = (r - y) / (2.0 * (1.0 - BT709_LUMA[0]));
+            let quantize = |value: f32| value.round().clamp(0.0, 255.0) as u16;
+
+            planes[0].push(quantize(16.0 + 219.0 * y));
+            planes[1].push(quantize(128.0 + 224.0 * u));
+            planes[2].push(quantize(128.0 + 224.0 * v));
+        });
+
+        let mut sdr = Planar {
+            width,
+            height,
+            bit_depth: 8,
+            subsampling: (false, false),
+            planes,
+        };
+        sdr.resample_chroma(dst_info.subsampling);
+
+        write(&sdr, dst, dst_layout, dst_format)
+    }
+
+    /// Returns the color description of the frames produced by [`ToneMapper::tone_map`].
+    pub fn output_color() -> ColorDescription {
+        ColorDescription {
+            matrix: MatrixCoefficients::Bt709,
+            transfer: TransferFunction::Sdr,
+            full_range: false,
+            chroma_siting: Default::default(),
+        }
+    }
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::convert::rgb::ChromaSiting;
+    use crate::utils::convert::PixelFormat;
+    use crate::DecodedFormat;
+    use crate::Resolution;
+
+    const PQ_BT2020: ColorDescription = ColorDescription {
+        matrix: MatrixCoefficients::Bt2020,
+        transfer: TransferFunction::Pq,
+        full_range: false,
+        chroma_siting: ChromaSiting::Left,
+    };
+
+    /// Returns the 10-bit studio range code of the normalized luma or chroma value `value`.
+    fn code_10bit(value: f32, chroma: bool) -> u16 {
+        if chroma {
+            (512.0 + 896.0 * value).round() as u16
+        } else {
+            (64.0 + 876.0 * value).round() as u16
+        }
+    }
+
+    /// Tone maps a flat 4x4 I010 frame of linear BT.2020 `rgb` in cd/m² into I444, and returns
+    /// its first pixel.
+    fn tone_map_flat(tone_mapper: &ToneMapper, rgb: [f32; 3], color: &ColorDescription) -> [u8; 3] {
+        let encoded = match color.transfer {
+            TransferFunction::Hlg => rgb.map(|c| {
+                // Inverse of the OOTF for gray colors, then the OETF.
+                let e = (c / DEFAULT_HDR_PEAK).powf(1.0 / HLG_GAMMA);
+                if e <= 1.0 / 12.0 {
+                    (3.0 * e).sqrt()
+                } else {
+                    HLG_A * (12.0 * e - HLG_B).ln() + HLG_C
+                }
+            }),
+            _ => rgb.map(pq_inverse_eotf),
+        };
+
+        let y = dot(BT2020_LUMA, encoded);
+        let u = (encoded[2] - y) / (2.0 * (1.0 - BT2020_LUMA[2]));
+        let v = (encoded[0] - y) / (2.0 * (1.0 - BT2020_LUMA[0]));
+
+        let size = Resolution::from((4, 4));
+        let src_format = PixelFormat::from(DecodedFormat::I010);
+        let mut src = Vec::new();
+        for (sample, count) in [
+            (code_10bit(y, false), 16),
+            (code_10bit(u, true), 4),
+            (code_10bit(v, true), 4),
+        ] {
+            for _ in 0..count {
+                src.extend_from_slice(&sample.to_le_bytes());
+            }
+        }
+
+        let dst_format = PixelFormat::from(DecodedFormat::I444);
+        let mut dst = vec![0u8; dst_format.packed_size(size)];
+        tone_mapper
+            .tone_map(
+                &src,
+                &src_format.packed_layout(size),
+                color,
+                &mut dst,
+                &dst_format.packed_layout(size),
+            )
+            .unwrap();
+
+        [dst[0], dst[16], dst[32]]
+    }
+
+    #[test]
+    fn transfer_functions() {
+        for l in [0.0, 0.1, 1.0, 100.0, 1000.0, 10000.0] {
+            let e = pq_inverse_eotf(l);
+            assert!((pq_eotf(e) - l).abs() <= l * 1e-3 + 1e-4, "{}", l);
+        }
+        assert!((pq_inverse_eotf(100.0) - 0.508).abs() < 1e-3);
+        assert!((pq_inverse_eotf(1000.0) - 0.752).abs() < 1e-3);
+
+        assert_eq!(hlg_inverse_oetf(0.0), 0.0);
+        assert!((hlg_inverse_oetf(0.5) - 1.0 / 12.0).abs() < 1e-6);
+        assert!((hlg_inverse_oetf(1.0) - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn operators() {
+        for operator in [
+            ToneMapOperator::Hable,
+            ToneMapOperator::Bt2390,
+            ToneMapOperator::Reinhard,
+        ] {
+            let tone_mapper = ToneMapper {
+                operator,
+                ..Default::default()
+            };
+
+            assert!(tone_mapper.map_luminance(0.0, 0.0, 1000.0) < 0.01);
+            let peak = tone_mapper.map_luminance(1000.0, 0.0, 1000.0);
+            assert!((95.0..=100.0).contains(&peak), "{:?} {}", operator, peak);
+
+            // The curves are monotonic.
+            let mut previous = 0.0;
+            for l in (1..=1000).map(|l| l as f32) {
+                let mapped = tone_mapper.map_luminance(l, 0.0, 1000.0);
+                assert!(mapped >= previous, "{:?} {}", operator, l);
+                previous = mapped;
+            }
+
+            // SDR content is left untouched.
+            assert_eq!(tone_mapper.map_luminance(50.0, 0.0, 100.0), 50.0);
+        }
+
+        // BT.2390 does not change dark regions.
+        let tone_mapper = ToneMapper::default();
+        assert!((tone_mapper.map_luminance(10.0, 0.0, 1000.0) - 10.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn metadata() {
+        // Primaries and white point of P3 D65, then 1000 and 0.005 cd/m².
+        let mastering = [
+            0x33, 0xc2, 0x86, 0xc4, 0x1d, 0x4c, 0x0b, 0xb8, 0x84, 0xd0, 0x3e, 0x80, 0x3d, 0x13,
+            0x40, 0x42, 0x00, 0x98, 0x96, 0x80, 0x00, 0x00, 0x00, 0x32,
+        ];
+        let mastering = MasteringDisplay::from_sei_payload(&mastering).unwrap();
+        assert_eq!(mastering.max_luminance, 1000.0);
+        assert_eq!(mastering.min_luminance, 0.005);
+        assert!(MasteringDisplay::from_sei_payload(&[0; 20]).is_err());
+
+        let cll = ContentLightLevel::from_sei_payload(&[0x02, 0x58, 0x00, 0xc8]).unwrap();
+        assert_eq!(cll.max_content_light_level, 600);
+        assert_eq!(cll.max_frame_average_light_level, 200);
+        assert!(ContentLightLevel::from_sei_payload(&[0x02]).is_err());
+
+        let mut metadata = HdrMetadata::default();
+        assert_eq!(metadata.pq_peak(), 1000.0);
+        metadata.mastering_display = Some(MasteringDisplay {
+            max_luminance: 4000.0,
+            min_luminance: 0.0,
+        });
+        assert_eq!(metadata.pq_peak(), 4000.0);
+        metadata.content_light_level = Some(cll);
+        assert_eq!(metadata.pq_peak(), 600.0);
+    }
+
+    #[test]
+    fn tone_map_frames() {
+        let hlg_bt2020 = ColorDescription {
+            transfer: TransferFunction::Hlg,
+            ..PQ_BT2020
+        };
+
+        for color in [PQ_BT2020, hlg_bt2020] {
+            for operator in [
+                ToneMapOperator::Hable,
+                ToneMapOperator::Bt2390,
+                ToneMapOperator::Reinhard,
+            ] {
+                let tone_mapper = ToneMapper {
+                    operator,
+                    ..Default::default()
+                };
+
+                let black = tone_map_flat(&tone_mapper, [0.0; 3], &color);
+                assert_eq!(black, [16, 128, 128], "{:?} {:?}", color.transfer, operator);
+
+                // The peak of the content is mapped to the SDR white.
+                let white = tone_map_flat(&tone_mapper, [1000.0; 3], &color);
+                assert!(white[0] >= 232, "{:?} {:?}", color.transfer, operator);
+                assert!(white[1].abs_diff(128) <= 1 && white[2].abs_diff(128) <= 1);
+
+                // Gray stays gray and in between.
+                let gray = tone_map_flat(&tone_mapper, [50.0; 3], &color);
+                assert!((40..200).contains(&gray[0]), "{:?}", gray);
+                assert!(gray[1].abs_diff(128) <= 1 && gray[2].abs_diff(128) <= 1);
+            }
+        }
+    }
+
+    #[test]
+    fn gamut_mapping() {
+        // Pure BT.2020 green is out of the BT.709 gamut.
+        let green = BT2020_TO_BT709.map(|row| dot(row, [0.0, 0.5, 0.0]));
+        assert!(green.iter().any(|&c| c < 0.0));
+
+        let clip = ToneMapper {
+            gamut_mapping: GamutMapping::Clip,
+            ..Default::default()
+        };
+        let clipped = clip.map_gamut(green);
+        assert_eq!(clipped[0], 0.0);
+        // Clipping the negative components makes the color brighter.
+        assert!(dot(BT709_LUMA, clipped) > dot(BT709_LUMA, green) + 0.01);
+
+        // Desaturation keeps the luminance.
+        let desaturated = ToneMapper::default().map_gamut(green);
+        assert!(desaturated.iter().all(|&c| (0.0..=1.0).contains(&c)));
+        assert!((dot(BT709_LUMA, desaturated) - dot(BT709_LUMA, green)).abs() < 1e-4);
+
+        // In-gamut colors are not changed.
+        let color = [0.2, 0.5, 0.7];
+        assert_eq!(ToneMapper::default().map_gamut(color), color);
+    }
+
+    #[test]
+    fn invalid_parameters() {
+        let size = Resolution::from((4, 4));
+        let src_format = PixelFormat::from(DecodedFormat::I010);
+        let src = vec![0u8; src_format.packed_size(size)];
+        let tone_mapper = ToneMapper::default();
+
+        // 10-bit output.
+        let mut dst = vec![0u8; src_format.packed_size(size)];
+        assert!(tone_mapper
+            .tone_map(
+                &src,
+                &src_format.packed_layout(size),
+                &PQ_BT2020,
+                &mut dst,
+                &src_format.packed_layout(size),
+            )
+            .is_err());
+
+        // SDR input.
+        let dst_format = PixelFormat::from(DecodedFormat::I420);
+        let mut dst = vec![0u8; dst_format.packed_size(size)];
+        assert!(tone_mapper
+            .tone_map(
+                &src,
+                &src_format.packed_layout(size),
+                &ToneMapper::output_color(),
+                &mut dst,
+                &dst_format.packed_layout(size),
+            )
+            .is_err());
+    }
+}
@@ -0,0 +1,468 @@
+// Copyright 2023 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+//! Index of the random access points of a stream, for frame-accurate seeking.
+//!
+//! [`StreamIndex::build`] scans an H.264 or H.265 Annex B stream, or an IVF file containing VP8 or
+//! VP9, using only the parsers, and records where each access unit starts and whether decoding can
+//! start from it. A playback loop can then seek to any frame by feeding the decoder from the
+//! closest preceding sync point and dropping the frames before the target.
+//!
+//! Building the index requires scanning the whole stream, so it can be serialized with
+//! [`StreamIndex::to_bytes`] and loaded again with [`StreamIndex::from_bytes`].
+
+mod h264;
+mod h265;
+
+use std::io::Cursor;
+use std::ops::Range;
+
+use anyhow::anyhow;
+use bytes::Buf;
+
+use crate::codec::h264::nalu_reader::NaluReader;
+use crate::codec::vp8::parser::Parser as Vp8Parser;
+use crate::codec::vp9::parser::FrameType;
+use crate::codec::vp9::parser::Parser as Vp9Parser;
+use crate::utils::probe::probe;
+use crate::utils::probe::Codec;
+use crate::utils::IvfIterator;
+
+/// Identifies serialized indexes.
+const MAGIC: &[u8; 4] = b"CCIX";
+/// Version of the serialization format, to be increased on every change.
+const VERSION: u8 = 1;
+
+/// Payload type of the recovery point SEI message, in both H.264 and H.265.
+const SEI_RECOVERY_POINT: u32 = 6;
+
+/// How decoding can start from an access unit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RandomAccess {
+    /// H.264 or H.265 IDR picture: no later picture references the pictures before it.
+    Idr,
+    /// H.265 clean random access picture. The RASL pictures following it in decoding order cannot
+    /// be decoded when starting from it, and must be skipped.
+    Cra,
+    /// H.265 broken link access picture. Its RASL pictures are always skipped by the decoder.
+    Bla,
+    /// Picture with a recovery point SEI message. The pictures decoded from it are only correct
+    /// in content after `recovery_cnt`, which is `recovery_frame_cnt` (in frames) for H.264 and
+    /// `recovery_poc_cnt` (in picture order count units) for H.265.
+    RecoveryPoint { recovery_cnt: i32 },
+    /// VP8 or VP9 key frame.
+    KeyFrame,
+}
+
+/// An access unit of the stream, i.e. a picture for H.264 and H.265 (a single field for interlaced
+/// H.264 streams), or an IVF frame for VP8 and VP9.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IndexEntry {
+    /// Offset of the access unit in the stream. For Annex B streams, this is the offset of the
+    /// start code of its first NAL unit; for IVF files, the offset of the frame data, after the
+    /// frame header.
+    pub offset: u64,
+    /// Size of the access unit, in bytes.
+    pub size: u64,
+    /// Position of the access unit in decoding order.
+    pub decode_index: u64,
+    /// Picture order count of H.264 and H.265 pictures.
+    pub poc: Option<i32>,
+    /// Timestamp of IVF frames, in units of [`StreamIndex::time_base`].
+    pub timestamp: Option<u64>,
+    /// How decoding can start from this access unit, or `None` if it depends on earlier ones.
+    pub random_access: Option<RandomAccess>,
+}
+
+/// Index of the access units of a stream, in decoding order.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StreamIndex {
+    pub codec: Codec,
+    /// Time base of the timestamps, in seconds, as a (numerator, denominator) pair.
+    pub time_base: Option<(u32, u32)>,
+    pub entries: Vec<IndexEntry>,
+}
+
+/// Collects the access units of an Annex B stream as its NAL units are scanned.
+#[derive(Default)]
+struct AccessUnits {
+    entries: Vec<IndexEntry>,
+    /// Offset of the first NAL unit of the current access unit.
+    start: Option<usize>,
+    /// Whether the current access unit has VCL NAL units, i.e. has an entry already.
+    has_vcl: bool,
+    /// `recovery_cnt` of the recovery point SEI of the current access unit, if any.
+    recovery_cnt: Option<i32>,
+}
+
+impl AccessUnits {
+    /// Adds the NAL unit spanning `range` of the stream to the current access unit, or to a new
+    /// one if `starts_access_unit` is set.
+    fn push_nalu(&mut self, range: Range<usize>, starts_access_unit: bool) {
+        if starts_access_unit && self.has_vcl {
+            self.start = None;
+            self.has_vcl = false;
+            self.recovery_cnt = None;
+        }
+
+        let start = *self.start.get_or_insert(range.start);
+        if self.has_vcl {
+            if let Some(entry) = self.entries.last_mut() {
+                entry.size = (range.end - start) as u64;
+            }
+        }
+    }
+
+    /// Records the picture of the current access unit, whose first VCL NAL unit ends at `end`.
+    fn push_picture(&mut self, end: usize, poc: i32, random_access: Option<RandomAccess>) {
+        let start = self.start.unwrap_or_default();
+
+        self.entries.push(IndexEntry {
+            offset: start as u64,
+            size: (end - start) as u64,
+            decode_index: self.entries.len() as u64,
+            poc: Some(poc),
+            timestamp: None,
+            random_access: random_access.or(self
+                .recovery_cnt
+                .map(|recovery_cnt| RandomAccess::RecoveryPoint { recovery_cnt })),
+        });
+        self.has_vcl = true;
+    }
+}
+
+/// Returns a reader positioned at the payload of the first SEI message of type `payload_type` in
+/// `sei`, the SEI NAL unit without its header, if there is one.
+fn find_sei_message(sei: &[u8], payload_type: u32) -> anyhow::Result<Option<NaluReader<&[u8]>>> {
+    let mut r = NaluReader::new(sei);
+
+    // Both values are coded as a sequence of 0xff bytes followed by a last byte, all summed.
+    let read_value = |r: &mut NaluReader<&[u8]>| -> anyhow::Result<u32> {
+        let mut value = 0;
+        loop {
+            let byte: u32 = r.read_bits(8)?;
+            value += byte;
+            if byte != 0xff {
+                return Ok(value);
+            }
+        }
+    };
+
+    while r.has_more_rsbp_data() {
+        let type_ = read_value(&mut r)?;
+        let size = read_value(&mut r)?;
+
+        if type_ == payload_type {
+            return Ok(Some(r));
+        }
+
+        r.skip_bits(usize::try_from(size)? * 8)?;
+    }
+
+    Ok(None)
+}
+
+/// Indexes the frames of `ivf`, `is_key_frame` being called on each of them in order.
+fn index_ivf_frames<F>(mut ivf: IvfIterator, mut is_key_frame: F) -> anyhow::Result<Vec<IndexEntry>>
+where
+    F: FnMut(&[u8]) -> anyhow::Result<bool>,
+{
+    std::iter::from_fn(|| ivf.next_frame())
+        .enumerate()
+        .map(|(decode_index, frame)| {
+            Ok(IndexEntry {
+                offset: frame.offset as u64,
+                size: frame.data.len() as u64,
+                decode_index: decode_index as u64,
+                poc: None,
+                timestamp: Some(frame.pts),
+                random_access: is_key_frame(frame.data)?.then_some(RandomAccess::KeyFrame),
+            })
+        })
+        .collect()
+}
+
+fn index_ivf(data: &[u8]) -> anyhow::Result<StreamIndex> {
+    let ivf = IvfIterator::new(data);
+    let header = ivf.header()?;
+
+    let (codec, entries) = match &header.fourcc {
+        b"VP80" => {
+            let mut parser = Vp8Parser::default();
+            let entries =
+                index_ivf_frames(ivf, |frame| Ok(parser.parse_frame(frame)?.header.key_frame))?;
+
+            (Codec::Vp8, entries)
+        }
+        b"VP90" => {
+            let mut parser = Vp9Parser::default();
+            let entries = index_ivf_frames(ivf, |frame| {
+                Ok(parser
+                    .parse_chunk(frame)?
+                    .first()
+                    .is_some_and(|frame| frame.header.frame_type == FrameType::KeyFrame))
+            })?;
+
+            (Codec::Vp9, entries)
+        }
+        fourcc => return Err(anyhow!("unsupported IVF fourcc {:?}", fourcc)),
+    };
+
+    Ok(StreamIndex {
+        codec,
+        time_base: Some(header.time_base),
+        entries,
+    })
+}
+
+fn codec_to_u8(codec: Codec) -> u8 {
+    match codec {
+        Codec::H264 => 0,
+        Codec::H265 => 1,
+        Codec::Vp8 => 2,
+        Codec::Vp9 => 3,
+    }
+}
+
+fn codec_from_u8(value: u8) -> anyhow::Result<Codec> {
+    match value {
+        0 => Ok(Codec::H264),
+        1 => Ok(Codec::H265),
+        2 => Ok(Codec::Vp8),
+        3 => Ok(Codec::Vp9),
+        _ => Err(anyhow!("invalid codec {}", value)),
+    }
+}
+
+impl StreamIndex {
+    /// Builds the index of `data`, which must be a complete H.264 or H.265 Annex B stream, or an
+    /// IVF file containing VP8 or VP9.
+    pub fn build(data: &[u8]) -> anyhow::Result<Self> {
+        if data.starts_with(b"DKIF") {
+            return index_ivf(data);
+        }
+
+        let codec = probe(data)?.codec;
+        let entries = match codec {
+            Codec::H264 => h264::index(data)?,
+            Codec::H265 => h265::index(data)?,
+            Codec::Vp8 | Codec::Vp9 => {
+                return Err(anyhow!(
+                    "VP8 and VP9 streams must be in an IVF file to be indexed"
+                ))
+            }
+        };
+
+        Ok(Self {
+            codec,
+            time_base: None,
+            entries,
+        })
+    }
+
+    /// Returns the last sync point at or before the access unit at `decode_index`.
+    pub fn sync_point_before(&self, decode_index: u64) -> Option<&IndexEntry> {
+        self.entries
+            .iter()
+            .rev()
+            .filter(|entry| entry.decode_index <= decode_index)
+            .find(|entry| entry.random_access.is_some())
+    }
+
+    /// Returns the last sync point whose timestamp is at or before `timestamp`, for streams
+    /// with timestamps.
+    pub fn sync_point_before_timestamp(&self, timestamp: u64) -> Option<&IndexEntry> {
+        self.entries
+            .iter()
+            .rev()
+            .filter(|entry| entry.timestamp.is_some_and(|t| t <= timestamp))
+            .find(|entry| entry.random_access.is_some())
+    }
+
+    /// Serializes the index. All values are stored in little endian.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION);
+        out.push(codec_to_u8(self.codec));
+        let (num, den) = self.time_base.unwrap_or_default();
+        out.extend_from_slice(&num.to_le_bytes());
+        out.extend_from_slice(&den.to_le_bytes());
+        out.extend_from_slice(&(self.entries.len() as u64).to_le_bytes());
+
+        for entry in &self.entries {
+            out.extend_from_slice(&entry.offset.to_le_bytes());
+            out.extend_from_slice(&entry.size.to_le_bytes());
+            out.extend_from_slice(&entry.decode_index.to_le_bytes());
+
+            out.push(u8::from(entry.poc.is_some()));
+            out.extend_from_slice(&entry.poc.unwrap_or_default().to_le_bytes());
+            out.push(u8::from(entry.timestamp.is_some()));
+            out.extend_from_slice(&entry.timestamp.unwrap_or_default().to_le_bytes());
+
+            let (random_access, recovery_cnt) = match entry.random_access {
+                None => (0u8, 0),
+                Some(RandomAccess::Idr) => (1, 0),
+                Some(RandomAccess::Cra) => (2, 0),
+                Some(RandomAccess::Bla) => (3, 0),
+                Some(RandomAccess::RecoveryPoint { recovery_cnt }) => (4, recovery_cnt),
+                Some(RandomAccess::KeyFrame) => (5, 0),
+            };
+            out.push(random_access);
+            out.extend_from_slice(&recovery_cnt.to_le_bytes());
+        }
+
+        out
+    }
+
+    /// Deserializes an index produced by [`StreamIndex::to_bytes`].
+    pub fn from_bytes(data: &[u8]) -> anyhow::Result<Self> {
+        // Size of the fixed part of the header, and of each entry.
+        const HEADER_SIZE: usize = 22;
+        const ENTRY_SIZE: usize = 43;
+
+        let mut cursor = Cursor::new(data);
+        if cursor.remaining() < HEADER_SIZE || &data[..4] != MAGIC {
+            return Err(anyhow!("not a stream index"));
+        }
+        cursor.advance(4);
+
+        let version = cursor.get_u8();
+        if version != VERSION {
+            return Err(anyhow!("unsupported stream index version {}", version));
+        }
+
+        let codec = codec_from_u8(cursor.get_u8())?;
+        let time_base = match (cursor.get_u32_le(), cursor.get_u32_le()) {
+            (0, 0) => None,
+            time_base => Some(time_base),
+        };
+
+        let num_entries = usize::try_from(cursor.get_u64_le())?;
+        if cursor.remaining() != num_entries.saturating_mul(ENTRY_SIZE) {
+            return Err(anyhow!("stream index has an invalid size"));
+        }
+
+        let mut entries = Vec::with_capacity(num_entries);
+        for _ in 0..num_entries {
+            let offset = cursor.get_u64_le();
+            let size = cursor.get_u64_le();
+            let decode_index = cursor.get_u64_le();
+            let has_poc = cursor.get_u8() != 0;
+            let poc = cursor.get_i32_le();
+            let has_timestamp = cursor.get_u8() != 0;
+            let timestamp = cursor.get_u64_le();
+            let random_access = cursor.get_u8();
+            let recovery_cnt = cursor.get_i32_le();
+
+            entries.push(IndexEntry {
+                offset,
+                size,
+                decode_index,
+                poc: has_poc.then_some(poc),
+                timestamp: has_timestamp.then_some(timestamp),
+                random_access: match random_access {
+                    0 => None,
+                    1 => Some(RandomAccess::Idr),
+                    2 => Some(RandomAccess::Cra),
+                    3 => Some(RandomAccess::Bla),
+                    4 => Some(RandomAccess::RecoveryPoint { recovery_cnt }),
+                    5 => Some(RandomAccess::KeyFrame),
+                    _ => return Err(anyhow!("invalid random access type {}", random_access)),
+                },
+            });
+        }
+
+        Ok(Self {
+            codec,
+            time_base,
+            entries,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::IvfIterator;
+
+    const VP8_STREAM: &[u8] = include_bytes!("../codec/vp8/test_data/test-25fps.vp8");
+    const VP9_STREAM: &[u8] = include_bytes!("../codec/vp9/test_data/test-25fps.vp9");
+
+    /// Returns the decode indices of the sync points of `index`.
+    fn sync_points(index: &StreamIndex) -> Vec<u64> {
+        index
+            .entries
+            .iter()
+            .filter(|entry| entry.random_access == Some(RandomAccess::KeyFrame))
+            .map(|entry| entry.decode_index)
+            .collect()
+    }
+
+    #[test]
+    fn index_ivf() {
+        let index = StreamIndex::build(VP8_STREAM).unwrap();
+        assert_eq!(index.codec, Codec::Vp8);
+        assert_eq!(index.time_base, Some((2, 50)));
+        assert_eq!(index.entries.len(), 250);
+        assert_eq!(sync_points(&index), [0, 128]);
+        // The first frame follows the 32-byte file header and its 12-byte frame header.
+        assert_eq!(index.entries[0].offset, 44);
+        assert_eq!(index.entries[1].timestamp, Some(1));
+
+        let index = StreamIndex::build(VP9_STREAM).unwrap();
+        assert_eq!(index.codec, Codec::Vp9);
+        assert_eq!(index.time_base, Some((1, 1000)));
+        assert_eq!(index.entries.len(), 250);
+        assert_eq!(sync_points(&index), [0, 150]);
+
+        let frame = &index.entries[1];
+        assert_eq!(frame.timestamp, Some(40));
+        assert_eq!(
+            &VP9_STREAM[frame.offset as usize..][..frame.size as usize],
+            IvfIterator::new(VP9_STREAM).nth(1).unwrap()
+        );
+
+        assert_eq!(index.sync_point_before(149), Some(&index.entries[0]));
+        assert_eq!(index.sync_point_before(150), Some(&index.entries[150]));
+        assert_eq!(
+            index.sync_point_before_timestamp(5999),
+            Some(&index.entries[0])
+        );
+        assert_eq!(
+            index.sync_point_before_timestamp(6000),
+            Some(&index.entries[150])
+        );
+    }
+
+    #[test]
+    fn serialization() {
+        for stream in [
+            &include_bytes!("../codec/h264/test_data/test-25fps.h264")[..],
+            VP9_STREAM,
+        ] {
+            let mut index = StreamIndex::build(stream).unwrap();
+            index.entries[1].random_access = Some(RandomAccess::RecoveryPoint { recovery_cnt: -3 });
+
+            assert_eq!(StreamIndex::from_bytes(&index.to_bytes()).unwrap(), index);
+        }
+    }
+
+    #[test]
+    fn invalid_input() {
+        // Raw VP9 frames have no framing to index.
+        assert!(StreamIndex::build(IvfIterator::new(VP9_STREAM).next().unwrap()).is_err());
+        assert!(StreamIndex::build(b"not a video stream").is_err());
+
+        let bytes = StreamIndex::build(VP8_STREAM).unwrap().to_bytes();
+        assert!(StreamIndex::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(StreamIndex::from_bytes(&bytes[1..]).is_err());
+
+        let mut bytes = bytes;
+        // Unknown version.
+        bytes[4] = VERSION + 1;
+        assert!(StreamIndex::from_bytes(&bytes).is_err());
+    }
+}
@@ -0,0 +1,309 @@
+// Copyright 2023 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+//! Indexing of H.264 streams.
+
+use anyhow::anyhow;
+
+use crate::codec::h264::nalu::Header;
+use crate::codec::h264::parser::Nalu;
+use crate::codec::h264::parser::NaluType;
+use crate::codec::h264::parser::Parser;
+use crate::codec::h264::parser::SliceHeader;
+use crate::codec::h264::parser::Sps;
+use crate::utils::for_each_nalu;
+use crate::utils::index::find_sei_message;
+use crate::utils::index::AccessUnits;
+use crate::utils::index::IndexEntry;
+use crate::utils::index::RandomAccess;
+use crate::utils::index::SEI_RECOVERY_POINT;
+
+/// Variables of the picture order count decoding process (8.2.1) carried from one picture to the
+/// next.
+#[derive(Default)]
+struct PocState {
+    /// `PicOrderCntMsb` and `pic_order_cnt_lsb` of the previous reference picture.
+    prev_ref_msb: i32,
+    prev_ref_lsb: i32,
+    /// `frame_num` and `FrameNumOffset` of the previous picture.
+    prev_frame_num: i32,
+    prev_frame_num_offset: i32,
+}
+
+impl PocState {
+    /// Returns the picture order count of the picture whose first slice has `header`.
+    fn compute(
+        &mut self,
+        sps: &Sps,
+        header: &SliceHeader,
+        nal_ref_idc: u8,
+        idr: bool,
+    ) -> anyhow::Result<i32> {
+        let frame_num = i32::from(header.frame_num);
+        let frame_num_offset = if idr {
+            0
+        } else if self.prev_frame_num > frame_num {
+            self.prev_frame_num_offset + sps.max_frame_num() as i32
+        } else {
+            self.prev_frame_num_offset
+        };
+
+        let (top, bottom) = match sps.pic_order_cnt_type {
+            // 8.2.1.1
+            0 => {
+                if idr {
+                    self.prev_ref_msb = 0;
+                    self.prev_ref_lsb = 0;
+                }
+
+                let max_lsb = 1 << (sps.log2_max_pic_order_cnt_lsb_minus4 + 4);
+                let lsb = i32::from(header.pic_order_cnt_lsb);
+                let msb = if lsb < self.prev_ref_lsb && self.prev_ref_lsb - lsb >= max_lsb / 2 {
+                    self.prev_ref_msb + max_lsb
+                } else if lsb > self.prev_ref_lsb && lsb - self.prev_ref_lsb > max_lsb / 2 {
+                    self.prev_ref_msb - max_lsb
+                } else {
+                    self.prev_ref_msb
+                };
+
+                if nal_ref_idc != 0 {
+                    self.prev_ref_msb = msb;
+                    self.prev_ref_lsb = lsb;
+                }
+
+                // Fields only use the order count of their parity, which is msb + lsb for both.
+                let top = msb + lsb;
+                if header.field_pic_flag {
+                    (top, top)
+                } else {
+                    (top, top + header.delta_pic_order_cnt_bottom)
+                }
+            }
+            // 8.2.1.2
+            1 => {
+                let cycle_len = i32::from(sps.num_ref_frames_in_pic_order_cnt_cycle);
+                let mut abs_frame_num = if cycle_len != 0 {
+                    frame_num_offset + frame_num
+                } else {
+                    0
+                };
+                if nal_ref_idc == 0 && abs_frame_num > 0 {
+                    abs_frame_num -= 1;
+                }
+
+                let mut expected = 0;
+                if abs_frame_num > 0 {
+                    let cycle_cnt = (abs_frame_num - 1) / cycle_len;
+                    let frame_num_in_cycle = ((abs_frame_num - 1) % cycle_len) as usize;
+                    expected = cycle_cnt * sps.expected_delta_per_pic_order_cnt_cycle
+                        + sps.offset_for_ref_frame[..=frame_num_in_cycle]
+                            .iter()
+                            .sum::<i32>();
+                }
+                if nal_ref_idc == 0 {
+                    expected += sps.offset_for_non_ref_pic;
+                }
+
+                let top = expected + header.delta_pic_order_cnt[0];
+                let bottom = if header.field_pic_flag {
+                    expected + sps.offset_for_top_to_bottom_field + header.delta_pic_order_cnt[0]
+                } else {
+                    top + sps.offset_for_top_to_bottom_field + header.delta_pic_order_cnt[1]
+                };
+
+                (top, bottom)
+            }
+            // 8.2.1.3
+            2 => {
+                let poc = if idr {
+                    0
+                } else if nal_ref_idc == 0 {
+                    2 * (frame_num_offset + frame_num) - 1
+                } else {
+                    2 * (frame_num_offset + frame_num)
+                };
+
+                (poc, poc)
+            }
+            type_ => return Err(anyhow!("invalid pic_order_cnt_type {}", type_)),
+        };
+
+        let poc = match (header.field_pic_flag, header.bottom_field_flag) {
+            (false, _) => top.min(bottom),
+            (true, false) => top,
+            (true, true) => bottom,
+        };
+
+        let has_mmco_5 = header
+            .dec_ref_pic_marking
+            .inner
+            .iter()
+            .any(|m| m.memory_management_control_operation == 5);
+
+        if has_mmco_5 {
+            // The picture is considered to have a frame_num of 0 and a picture order count of 0
+            // once decoded, which only leaves its top field order count as a reference.
+            self.prev_frame_num = 0;
+            self.prev_frame_num_offset = 0;
+            self.prev_ref_msb = 0;
+            self.prev_ref_lsb = if header.bottom_field_flag {
+                0
+            } else {
+                top - poc
+            };
+        } else {
+            self.prev_frame_num = frame_num;
+            self.prev_frame_num_offset = frame_num_offset;
+        }
+
+        Ok(poc)
+    }
+}
+
+/// Whether `nalu` is the first NAL unit of a new access unit, assuming the current one has VCL NAL
+/// units (7.4.1.2.3). Arbitrary slice order is not supported.
+fn starts_access_unit(nalu: &Nalu<&[u8]>) -> bool {
+    match nalu.header().nalu_type() {
+        NaluType::Sei
+        | NaluType::Sps
+        | NaluType::Pps
+        | NaluType::AuDelimiter
+        | NaluType::PrefixUnit
+        | NaluType::SubsetSps
+        | NaluType::DepthSps => true,
+        NaluType::Slice | NaluType::SliceDpa | NaluType::SliceIdr => is_first_slice(nalu),
+        _ => false,
+    }
+}
+
+/// Whether the slice `nalu` has a `first_mb_in_slice` of 0, i.e. an Exp-Golomb code made of a
+/// single set bit.
+fn is_first_slice(nalu: &Nalu<&[u8]>) -> bool {
+    nalu.as_ref()
+        .get(nalu.header().len())
+        .is_some_and(|byte| byte & 0x80 != 0)
+}
+
+/// Returns the `recovery_frame_cnt` of the recovery point SEI message in `nalu`, if any.
+fn recovery_frame_cnt(nalu: &Nalu<&[u8]>) -> anyhow::Result<Option<i32>> {
+    match find_sei_message(&nalu.as_ref()[nalu.header().len()..], SEI_RECOVERY_POINT)? {
+        Some(mut r) => Ok(Some(r.read_ue()?)),
+        None => Ok(None),
+    }
+}
+
+/// Returns the access units of the H.264 Annex B stream `data`.
+pub(super) fn index(data: &[u8]) -> anyhow::Result<Vec<IndexEntry>> {
+    let mut parser = Parser::default();
+    let mut poc_state = PocState::default();
+    let mut access_units = AccessUnits::default();
+
+    for_each_nalu(data, |nalu: Nalu<&[u8]>| {
+        let range = nalu.sc_offset()..nalu.offset() + nalu.size();
+        access_units.push_nalu(range.clone(), starts_access_unit(&nalu));
+
+        match nalu.header().nalu_type() {
+            NaluType::Sps => {
+                parser.parse_sps(&nalu)?;
+            }
+            NaluType::Pps => {
+                parser.parse_pps(&nalu)?;
+            }
+            NaluType::Sei => {
+                if let Some(recovery_cnt) = recovery_frame_cnt(&nalu)? {
+                    access_units.recovery_cnt = Some(recovery_cnt);
+                }
+            }
+            NaluType::Slice | NaluType::SliceDpa | NaluType::SliceIdr if !access_units.has_vcl => {
+                let nal_ref_idc = nalu.header().ref_idc();
+                let idr = nalu.header().idr_pic_flag();
+
+                let slice = parser.parse_slice_header(nalu)?;
+                let header = slice.header();
+                let pps = parser
+                    .get_pps(header.pic_parameter_set_id)
+                    .ok_or_else(|| anyhow!("slice references an unknown PPS"))?;
+                let poc = poc_state.compute(&pps.sps, header, nal_ref_idc, idr)?;
+
+                access_units.push_picture(range.end, poc, idr.then_some(RandomAccess::Idr));
+            }
+            _ => (),
+        }
+
+        Ok(())
+    })?;
+
+    Ok(access_units.entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::index::RandomAccess;
+    use crate::utils::index::StreamIndex;
+    use crate::utils::probe::Codec;
+
+    #[test]
+    fn index_progressive() {
+        let stream = include_bytes!("../../codec/h264/test_data/test-25fps.h264");
+        let index = StreamIndex::build(stream).unwrap();
+
+        assert_eq!(index.codec, Codec::H264);
+        assert_eq!(index.time_base, None);
+        assert_eq!(index.entries.len(), 250);
+
+        // The access units cover the whole stream.
+        assert_eq!(index.entries[0].offset, 0);
+        for pair in index.entries.windows(2) {
+            assert_eq!(pair[0].offset + pair[0].size, pair[1].offset);
+        }
+        let last = index.entries.last().unwrap();
+        assert_eq!((last.offset + last.size) as usize, stream.len());
+
+        // I P B P B ...
+        let pocs = index.entries[..5]
+            .iter()
+            .map(|entry| entry.poc.unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(pocs, [0, 4, 2, 8, 6]);
+
+        let idrs = index
+            .entries
+            .iter()
+            .filter(|entry| entry.random_access == Some(RandomAccess::Idr))
+            .map(|entry| entry.decode_index)
+            .collect::<Vec<_>>();
+        assert_eq!(idrs.len(), 4);
+        assert_eq!(idrs[0], 0);
+        assert!(index
+            .entries
+            .iter()
+            .all(|entry| entry.random_access.is_none()
+                || entry.random_access == Some(RandomAccess::Idr)));
+
+        for entry in &index.entries {
+            let expected = idrs.iter().rev().find(|&&idr| idr <= entry.decode_index);
+            assert_eq!(
+                index
+                    .sync_point_before(entry.decode_index)
+                    .map(|sync_point| &sync_point.decode_index),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn index_interlaced() {
+        let stream = include_bytes!("../../codec/h264/test_data/test-25fps-interlaced.h264");
+        let index = StreamIndex::build(stream).unwrap();
+
+        assert_eq!(index.entries.len(), 250);
+        assert_eq!(index.entries[0].random_access, Some(RandomAccess::Idr));
+
+        let pocs = index.entries[..4]
+            .iter()
+            .map(|entry| entry.poc.unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(pocs, [0, 6, 2, 4]);
+    }
+}
@@ -0,0 +1,183 @@
+// Copyright 2023 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+//! Indexing of H.265 streams.
+
+use anyhow::anyhow;
+
+use crate::codec::h265::parser::Nalu;
+use crate::codec::h265::parser::NaluType;
+use crate::codec::h265::parser::Parser;
+use crate::utils::for_each_nalu;
+use crate::utils::index::find_sei_message;
+use crate::utils::index::AccessUnits;
+use crate::utils::index::IndexEntry;
+use crate::utils::index::RandomAccess;
+use crate::utils::index::SEI_RECOVERY_POINT;
+
+/// Whether `type_` is the type of a VCL NAL unit.
+fn is_vcl(type_: NaluType) -> bool {
+    (type_ as u32) < NaluType::VpsNut as u32
+}
+
+/// Whether `nalu` is the first NAL unit of a new access unit, assuming the current one has VCL NAL
+/// units (7.4.2.4.4).
+fn starts_access_unit(nalu: &Nalu<&[u8]>) -> bool {
+    match nalu.header().nalu_type() {
+        NaluType::VpsNut
+        | NaluType::SpsNut
+        | NaluType::PpsNut
+        | NaluType::AudNut
+        | NaluType::PrefixSeiNut
+        | NaluType::RsvNvcl41
+        | NaluType::RsvNvcl42
+        | NaluType::RsvNvcl43
+        | NaluType::RsvNvcl44 => true,
+        type_ if is_vcl(type_) => is_first_slice_segment(nalu),
+        _ => false,
+    }
+}
+
+/// Whether the slice segment `nalu` has `first_slice_segment_in_pic_flag` set. The flag is the
+/// first bit after the 2-byte NAL unit header.
+fn is_first_slice_segment(nalu: &Nalu<&[u8]>) -> bool {
+    nalu.as_ref().get(2).is_some_and(|byte| byte & 0x80 != 0)
+}
+
+/// Returns the `recovery_poc_cnt` of the recovery point SEI message in `nalu`, if any.
+fn recovery_poc_cnt(nalu: &Nalu<&[u8]>) -> anyhow::Result<Option<i32>> {
+    match find_sei_message(&nalu.as_ref()[2..], SEI_RECOVERY_POINT)? {
+        Some(mut r) => Ok(Some(r.read_se()?)),
+        None => Ok(None),
+    }
+}
+
+/// Returns the access units of the H.265 Annex B stream `data`.
+pub(super) fn index(data: &[u8]) -> anyhow::Result<Vec<IndexEntry>> {
+    let mut parser = Parser::default();
+    let mut access_units = AccessUnits::default();
+    // Picture order count of the previous picture with TemporalId 0 that is not a RASL, RADL or
+    // SLNR picture (8.3.1), or `None` at the start of a coded video sequence.
+    let mut prev_tid0_poc: Option<i32> = None;
+
+    for_each_nalu(data, |nalu: Nalu<&[u8]>| {
+        let range = nalu.sc_offset()..nalu.offset() + nalu.size();
+        access_units.push_nalu(range.clone(), starts_access_unit(&nalu));
+
+        // Only the base layer is indexed.
+        if nalu.header().layer_id() != 0 {
+            return Ok(());
+        }
+
+        let type_ = nalu.header().nalu_type();
+        match type_ {
+            NaluType::VpsNut => {
+                parser.parse_vps(&nalu)?;
+            }
+            NaluType::SpsNut => {
+                parser.parse_sps(&nalu)?;
+            }
+            NaluType::PpsNut => {
+                parser.parse_pps(&nalu)?;
+            }
+            NaluType::PrefixSeiNut => {
+                if let Some(recovery_cnt) = recovery_poc_cnt(&nalu)? {
+                    access_units.recovery_cnt = Some(recovery_cnt);
+                }
+            }
+            // The first picture after an end of sequence starts a new coded video sequence.
+            NaluType::EosNut => prev_tid0_poc = None,
+            _ if is_vcl(type_) && !access_units.has_vcl => {
+                let temporal_id = nalu.header().temporal_id_plus1().saturating_sub(1);
+
+                let slice = parser.parse_slice_header(nalu)?;
+                let header = slice.header();
+                let pps = parser
+                    .get_pps(header.pic_parameter_set_id)
+                    .ok_or_else(|| anyhow!("slice references an unknown PPS"))?;
+                let sps = parser
+                    .get_sps(pps.seq_parameter_set_id)
+                    .ok_or_else(|| anyhow!("PPS references an unknown SPS"))?;
+
+                // IRAP pictures with NoRaslOutputFlag set restart the picture order count.
+                let no_rasl_output = type_.is_idr() || type_.is_bla() || prev_tid0_poc.is_none();
+
+                let max_lsb = 1 << (sps.log2_max_pic_order_cnt_lsb_minus4 + 4);
+                let lsb = i32::from(header.pic_order_cnt_lsb);
+                let msb = if type_.is_irap() && no_rasl_output {
+                    0
+                } else {
+                    let prev_poc = prev_tid0_poc.unwrap_or_default();
+                    let prev_lsb = prev_poc & (max_lsb - 1);
+                    let prev_msb = prev_poc - prev_lsb;
+
+                    if lsb < prev_lsb && prev_lsb - lsb >= max_lsb / 2 {
+                        prev_msb + max_lsb
+                    } else if lsb > prev_lsb && lsb - prev_lsb > max_lsb / 2 {
+                        prev_msb - max_lsb
+                    } else {
+                        prev_msb
+                    }
+                };
+                let poc = msb + lsb;
+
+                if temporal_id == 0 && !type_.is_radl() && !type_.is_rasl() && !type_.is_slnr() {
+                    prev_tid0_poc = Some(poc);
+                }
+
+                let random_access = if type_.is_idr() {
+                    Some(RandomAccess::Idr)
+                } else if type_.is_bla() {
+                    Some(RandomAccess::Bla)
+                } else if type_.is_cra() {
+                    Some(RandomAccess::Cra)
+                } else {
+                    None
+                };
+
+                access_units.push_picture(range.end, poc, random_access);
+            }
+            _ => (),
+        }
+
+        Ok(())
+    })?;
+
+    Ok(access_units.entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::index::RandomAccess;
+    use crate::utils::index::StreamIndex;
+    use crate::utils::probe::Codec;
+
+    #[test]
+    fn index_stream() {
+        let stream = include_bytes!("../../codec/h265/test_data/test-25fps.h265");
+        let index = StreamIndex::build(stream).unwrap();
+
+        assert_eq!(index.codec, Codec::H265);
+        assert_eq!(index.entries.len(), 250);
+        assert_eq!(index.entries[0].random_access, Some(RandomAccess::Idr));
+        assert!(index.entries[1..]
+            .iter()
+            .all(|entry| entry.random_access.is_none()));
+
+        let last = index.entries.last().unwrap();
+        assert_eq!((last.offset + last.size) as usize, stream.len());
+
+        // The stream is a single coded video sequence, so each picture has a different POC.
+        let mut pocs = index
+            .entries
+            .iter()
+            .map(|entry| entry.poc.unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(pocs[..5], [0, 3, 2, 1, 8]);
+        pocs.sort();
+        assert!(pocs.iter().copied().eq(0..250));
+
+        assert_eq!(index.sync_point_before(249), Some(&index.entries[0]));
+    }
+}
@@ -0,0 +1,545 @@
+// Copyright 2023 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+//! Identification of encoded streams without decoding them.
+//!
+//! [`probe`] only runs the parsers on the beginning of a stream, so it can be used to check
+//! whether some content is supported, and by which backend, before creating a decoder.
+
+use std::io::Cursor;
+
+use anyhow::anyhow;
+
+use crate::codec::h264::parser::Nalu as H264Nalu;
+use crate::codec::h264::parser::NaluType as H264NaluType;
+use crate::codec::h264::parser::Parser as H264Parser;
+use crate::codec::h265::parser::Nalu as H265Nalu;
+use crate::codec::h265::parser::NaluType as H265NaluType;
+use crate::codec::h265::parser::Parser as H265Parser;
+use crate::codec::vp8::parser::Parser as Vp8Parser;
+use crate::codec::vp9::parser::FrameType;
+use crate::codec::vp9::parser::Parser as Vp9Parser;
+use crate::codec::vp9::parser::NUM_REF_FRAMES;
+use crate::utils::convert::rgb::ColorDescription;
+use crate::utils::IvfIterator;
+use crate::Resolution;
+
+/// Number of reference frames of VP8: last, golden and altref.
+const VP8_NUM_REF_FRAMES: usize = 3;
+
+/// Codec of a probed stream.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Codec {
+    H264,
+    H265,
+    Vp8,
+    Vp9,
+}
+
+/// Format in which the encoded stream is stored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Container {
+    /// NAL units separated by start codes (H.264 and H.265 Annex B).
+    AnnexB,
+    /// IVF file.
+    Ivf,
+    /// A single encoded VP8 frame or VP9 chunk.
+    RawFrame,
+}
+
+/// Chroma subsampling of a stream.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChromaFormat {
+    Monochrome,
+    Yuv420,
+    Yuv422,
+    Yuv440,
+    Yuv444,
+}
+
+/// Properties of a probed stream.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProbeResult {
+    pub codec: Codec,
+    pub container: Container,
+    /// `profile_idc` for H.264, `general_profile_idc` for H.265, the version for VP8 and the
+    /// profile for VP9.
+    pub profile: u8,
+    /// `level_idc` for H.264 and `general_level_idc` for H.265. VP8 and VP9 do not signal a
+    /// level.
+    pub level: Option<u8>,
+    /// Bit depth of the luma samples.
+    pub bit_depth: u8,
+    pub chroma_format: ChromaFormat,
+    /// Size of the decoded frames.
+    pub coded_resolution: Resolution,
+    /// Size of the part of the decoded frames meant to be displayed.
+    pub display_resolution: Resolution,
+    /// Whether the stream contains fields rather than only frames.
+    pub interlaced: bool,
+    /// Frame rate as a (numerator, denominator) pair, if signaled by the stream or its container.
+    pub frame_rate: Option<(u32, u32)>,
+    /// Shape of the samples as a (width, height) pair, if signaled by the stream.
+    pub sample_aspect_ratio: Option<(u32, u32)>,
+    /// Number of frames the decoder keeps for reference or reordering.
+    pub dpb_size: usize,
+    /// How the decoded samples are to be converted to RGB.
+    pub color: ColorDescription,
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// Returns the frame rate of a stream with `time_scale` ticks per second and
+/// `ticks_per_frame` ticks per frame, as a reduced fraction.
+fn frame_rate(time_scale: u64, ticks_per_frame: u64) -> Option<(u32, u32)> {
+    if time_scale == 0 || ticks_per_frame == 0 {
+        return None;
+    }
+
+    let gcd = gcd(time_scale, ticks_per_frame);
+    Some((
+        u32::try_from(time_scale / gcd).ok()?,
+        u32::try_from(ticks_per_frame / gcd).ok()?,
+    ))
+}
+
+/// Returns the sample aspect ratio of `aspect_ratio_idc` as per table E-1 of both H.264 and
+/// H.265, with `sar` being used for `Extended_SAR`.
+fn sample_aspect_ratio(aspect_ratio_idc: u32, sar: (u32, u32)) -> Option<(u32, u32)> {
+    const EXTENDED_SAR: u32 = 255;
+    const SAMPLE_ASPECT_RATIOS: [(u32, u32); 16] = [
+        (1, 1),
+        (12, 11),
+        (10, 11),
+        (16, 11),
+        (40, 33),
+        (24, 11),
+        (20, 11),
+        (32, 11),
+        (80, 33),
+        (18, 11),
+        (15, 11),
+        (64, 33),
+        (160, 99),
+        (4, 3),
+        (3, 2),
+        (2, 1),
+    ];
+
+    match aspect_ratio_idc {
+        EXTENDED_SAR if sar.0 != 0 && sar.1 != 0 => Some(sar),
+        // 0 is unspecified, and the other values are reserved.
+        idc => SAMPLE_ASPECT_RATIOS
+            .get(usize::try_from(idc).ok()?.checked_sub(1)?)
+            .copied(),
+    }
+}
+
+fn chroma_format_from_idc(chroma_format_idc: u8) -> anyhow::Result<ChromaFormat> {
+    match chroma_format_idc {
+        0 => Ok(ChromaFormat::Monochrome),
+        1 => Ok(ChromaFormat::Yuv420),
+        2 => Ok(ChromaFormat::Yuv422),
+        3 => Ok(ChromaFormat::Yuv444),
+        _ => Err(anyhow!("invalid chroma_format_idc {}", chroma_format_idc)),
+    }
+}
+
+/// Whether `data` starts with an Annex B start code.
+fn starts_with_start_code(data: &[u8]) -> bool {
+    data.starts_with(&[0x00, 0x00, 0x01]) || data.starts_with(&[0x00, 0x00, 0x00, 0x01])
+}
+
+fn probe_h264(data: &[u8]) -> anyhow::Result<ProbeResult> {
+    let mut cursor = Cursor::new(data);
+    let mut parser = H264Parser::default();
+
+    while let Ok(nalu) = H264Nalu::next(&mut cursor) {
+        if nalu.header().nalu_type() != H264NaluType::Sps {
+            continue;
+        }
+
+        let sps = parser.parse_sps(&nalu)?;
+        let visible = sps.visible_rectangle();
+        let vui = &sps.vui_parameters;
+        let frame_rate = if sps.vui_parameters_present_flag && vui.timing_info_present_flag {
+            // Each tick is a field (E.2.1).
+            frame_rate(
+                u64::from(vui.time_scale),
+                2 * u64::from(vui.num_units_in_tick),
+            )
+        } else {
+            None
+        };
+        let sample_aspect_ratio =
+            if sps.vui_parameters_present_flag && vui.aspect_ratio_info_present_flag {
+                sample_aspect_ratio(
+                    u32::from(vui.aspect_ratio_idc),
+                    (u32::from(vui.sar_width), u32::from(vui.sar_height)),
+                )
+            } else {
+                None
+            };
+
+        return Ok(ProbeResult {
+            codec: Codec::H264,
+            container: Container::AnnexB,
+            profile: sps.profile_idc,
+            level: Some(sps.level_idc as u8),
+            bit_depth: sps.bit_depth_luma_minus8 + 8,
+            chroma_format: chroma_format_from_idc(sps.chroma_format_idc)?,
+            coded_resolution: Resolution::from((sps.width, sps.height)),
+            display_resolution: Resolution::from((
+                visible.max.x - visible.min.x,
+                visible.max.y - visible.min.y,
+            )),
+            interlaced: !sps.frame_mbs_only_flag,
+            frame_rate,
+            sample_aspect_ratio,
+            dpb_size: sps.max_dpb_frames(),
+            color: ColorDescription::from_h264_sps(sps),
+        });
+    }
+
+    Err(anyhow!("no H.264 SPS found"))
+}
+
+fn probe_h265(data: &[u8]) -> anyhow::Result<ProbeResult> {
+    let mut cursor = Cursor::new(data);
+    let mut parser = H265Parser::default();
+    let mut vps_frame_rate = None;
+
+    while let Ok(nalu) = H265Nalu::next(&mut cursor) {
+        match nalu.header().nalu_type() {
+            H265NaluType::VpsNut => {
+                let vps = parser.parse_vps(&nalu)?;
+                if vps.timing_info_present_flag {
+                    vps_frame_rate =
+                        frame_rate(u64::from(vps.time_scale), u64::from(vps.num_units_in_tick));
+                }
+            }
+            H265NaluType::SpsNut => {
+                let sps = parser.parse_sps(&nalu)?;
+                let ptl = &sps.profile_tier_level;
+                let visible = sps.visible_rectangle();
+                let vui = &sps.vui_parameters;
+                let vui_present = sps.vui_parameters_present_flag;
+
+                let frame_rate = if vui_present && vui.timing_info_present_flag {
+                    frame_rate(u64::from(vui.time_scale), u64::from(vui.num_units_in_tick))
+                } else {
+                    vps_frame_rate
+                };
+                let sample_aspect_ratio = if vui_present && vui.aspect_ratio_info_present_flag {
+                    sample_aspect_ratio(vui.aspect_ratio_idc, (vui.sar_width, vui.sar_height))
+                } else {
+                    None
+                };
+
+                let highest_sub_layer = usize::from(sps.max_sub_layers_minus1);
+
+                return Ok(ProbeResult {
+                    codec: Codec::H265,
+                    container: Container::AnnexB,
+                    profile: ptl.general_profile_idc,
+                    level: Some(ptl.general_level_idc as u8),
+                    bit_depth: sps.bit_depth_luma_minus8 + 8,
+                    chroma_format: chroma_format_from_idc(sps.chroma_format_idc)?,
+                    coded_resolution: Resolution::from((
+                        u32::from(sps.width()),
+                        u32::from(sps.height()),
+                    )),
+                    display_resolution: Resolution::from((
+                        visible.max.x - visible.min.x,
+                        visible.max.y - visible.min.y,
+                    )),
+                    // Either the pictures are fields, or the frames are made of two interleaved
+                    // fields.
+                    interlaced: (vui_present && vui.field_seq_flag)
+                        || (ptl.general_interlaced_source_flag
+                            && !ptl.general_progressive_source_flag),
+                    frame_rate,
+                    sample_aspect_ratio,
+                    dpb_size: usize::from(sps.max_dec_pic_buffering_minus1[highest_sub_layer]) + 1,
+                    color: ColorDescription::from_h265_sps(sps),
+                });
+            }
+            _ => (),
+        }
+    }
+
+    Err(anyhow!("no H.265 SPS found"))
+}
+
+/// Probes an Annex B stream, which can be either H.264 or H.265.
+fn probe_annex_b(data: &[u8]) -> anyhow::Result<ProbeResult> {
+    // The NAL unit headers of both codecs can be mistaken for each other, so look for a
+    // parameter set that is valid with the 2-byte H.265 header first, as it is less likely to
+    // happen by chance, and fall back to H.264 if it cannot be parsed.
+    let looks_like_h265 = data
+        .windows(5)
+        .any(|w| w[..3] == [0x00, 0x00, 0x01] && matches!(w[3], 0x40 | 0x42) && w[4] == 0x01);
+
+    if looks_like_h265 {
+        if let Ok(result) = probe_h265(data) {
+            return Ok(result);
+        }
+    }
+
+    probe_h264(data).map_err(|e| anyhow!("unrecognized Annex B stream: {}", e))
+}
+
+/// Probes the encoded VP8 `frame`, which must be a key frame.
+fn probe_vp8(frame: &[u8], container: Container) -> anyhow::Result<Option<ProbeResult>> {
+    let header = Vp8Parser::default().parse_frame(frame)?.header;
+    if !header.key_frame {
+        return Ok(None);
+    }
+
+    let resolution = Resolution::from((u32::from(header.width), u32::from(header.height)));
+
+    Ok(Some(ProbeResult {
+        codec: Codec::Vp8,
+        container,
+        profile: header.version,
+        level: None,
+        bit_depth: 8,
+        chroma_format: ChromaFormat::Yuv420,
+        coded_resolution: resolution,
+        display_resolution: resolution,
+        interlaced: false,
+        frame_rate: None,
+        sample_aspect_ratio: None,
+        dpb_size: VP8_NUM_REF_FRAMES,
+        color: ColorDescription::vp8(),
+    }))
+}
+
+/// Probes the VP9 `chunk`, using its first frame carrying the color configuration, if any.
+fn probe_vp9(chunk: &[u8], container: Container) -> anyhow::Result<Option<ProbeResult>> {
+    let frames = Vp9Parser::default().parse_chunk(chunk)?;
+    let header = match frames
+        .iter()
+        .map(|frame| &frame.header)
+        .find(|header| header.frame_type == FrameType::KeyFrame || header.intra_only)
+    {
+        Some(header) => header,
+        None => return Ok(None),
+    };
+
+    let chroma_format = match (header.subsampling_x, header.subsampling_y) {
+        (true, true) => ChromaFormat::Yuv420,
+        (true, false) => ChromaFormat::Yuv422,
+        (false, true) => ChromaFormat::Yuv440,
+        (false, false) => ChromaFormat::Yuv444,
+    };
+
+    Ok(Some(ProbeResult {
+        codec: Codec::Vp9,
+        container,
+        profile: header.profile as u8,
+        level: None,
+        bit_depth: header.bit_depth as u8,
+        chroma_format,
+        coded_resolution: Resolution::from((header.width, header.height)),
+        display_resolution: Resolution::from((header.render_width, header.render_height)),
+        interlaced: false,
+        frame_rate: None,
+        sample_aspect_ratio: None,
+        dpb_size: NUM_REF_FRAMES,
+        color: ColorDescription::from_vp9_header(header),
+    }))
+}
+
+/// Probes an IVF file, whose frame rate is deduced from the time base and the timestamps of the
+/// first two frames.
+fn probe_ivf(data: &[u8]) -> anyhow::Result<ProbeResult> {
+    let mut ivf = IvfIterator::new(data);
+    let header = ivf.header()?;
+    let (time_base_num, time_base_den) = header.time_base;
+    let frames = std::iter::from_fn(|| ivf.next_frame()).collect::<Vec<_>>();
+
+    let frame_rate = match &frames[..] {
+        [first, second, ..] if second.pts > first.pts => frame_rate(
+            u64::from(time_base_den),
+            u64::from(time_base_num) * (second.pts - first.pts),
+        ),
+        _ => None,
+    };
+
+    for frame in frames {
+        let result = match &header.fourcc {
+            b"VP80" => probe_vp8(frame.data, Container::Ivf)?,
+            b"VP90" => probe_vp9(frame.data, Container::Ivf)?,
+            fourcc => return Err(anyhow!("unsupported IVF fourcc {:?}", fourcc)),
+        };
+
+        if let Some(result) = result {
+            return Ok(ProbeResult {
+                frame_rate,
+                ..result
+            });
+        }
+    }
+
+    Err(anyhow!("no key frame found in IVF file"))
+}
+
+/// Detects the format of the encoded stream `data` and returns its properties.
+///
+/// `data` can be an H.264 or H.265 Annex B stream, an IVF file containing VP8 or VP9, or a single
+/// VP8 key frame or VP9 chunk starting with a key or intra-only frame. Only the beginning of
+/// `data` needs to be available: the parameters are taken from the first H.264 or H.265 SPS, or
+/// from the first VP8 or VP9 key frame.
+pub fn probe(data: &[u8]) -> anyhow::Result<ProbeResult> {
+    if data.starts_with(b"DKIF") {
+        return probe_ivf(data);
+    }
+
+    if starts_with_start_code(data) {
+        return probe_annex_b(data);
+    }
+
+    // VP8 key frames have a start code after their 3-byte frame tag, whose bit 0 is 0.
+    if data.len() >= 10 && data[0] & 0x1 == 0 && data[3..6] == [0x9d, 0x01, 0x2a] {
+        return probe_vp8(data, Container::RawFrame)?
+            .ok_or_else(|| anyhow!("VP8 frame is not a key frame"));
+    }
+
+    // VP9 frames start with a 2-bit frame marker.
+    if data.first().is_some_and(|byte| byte >> 6 == 0b10) {
+        return probe_vp9(data, Container::RawFrame)?
+            .ok_or_else(|| anyhow!("VP9 chunk has no key frame"));
+    }
+
+    Err(anyhow!("unrecognized stream format"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::convert::rgb::MatrixCoefficients;
+
+    const H264_STREAM: &[u8] = include_bytes!("../codec/h264/test_data/test-25fps.h264");
+    const VP8_STREAM: &[u8] = include_bytes!("../codec/vp8/test_data/test-25fps.vp8");
+    const VP9_STREAM: &[u8] = include_bytes!("../codec/vp9/test_data/test-25fps.vp9");
+
+    /// Returns the first frame of the IVF file `data`.
+    fn first_ivf_frame(data: &[u8]) -> &[u8] {
+        IvfIterator::new(data).next().unwrap()
+    }
+
+    #[test]
+    fn probe_h264() {
+        let result = probe(H264_STREAM).unwrap();
+        assert_eq!(result.codec, Codec::H264);
+        assert_eq!(result.container, Container::AnnexB);
+        assert_eq!(result.profile, 77);
+        assert_eq!(result.level, Some(13));
+        assert_eq!(result.bit_depth, 8);
+        assert_eq!(result.chroma_format, ChromaFormat::Yuv420);
+        assert_eq!(result.coded_resolution, Resolution::from((320, 240)));
+        assert_eq!(result.display_resolution, Resolution::from((320, 240)));
+        assert!(!result.interlaced);
+        assert_eq!(result.frame_rate, None);
+        assert_eq!(result.color.matrix, MatrixCoefficients::Bt601);
+        assert!(!result.color.full_range);
+
+        let result = probe(include_bytes!(
+            "../codec/h264/test_data/test-25fps-interlaced.h264"
+        ))
+        .unwrap();
+        assert_eq!(result.profile, 100);
+        assert_eq!(result.level, Some(21));
+        assert_eq!(result.coded_resolution, Resolution::from((320, 256)));
+        assert_eq!(result.display_resolution, Resolution::from((320, 240)));
+        assert!(result.interlaced);
+        assert_eq!(result.frame_rate, Some((25, 1)));
+        assert_eq!(result.dpb_size, 4);
+    }
+
+    #[test]
+    fn sample_aspect_ratios() {
+        assert_eq!(sample_aspect_ratio(0, (0, 0)), None);
+        assert_eq!(sample_aspect_ratio(1, (0, 0)), Some((1, 1)));
+        assert_eq!(sample_aspect_ratio(13, (0, 0)), Some((160, 99)));
+        assert_eq!(sample_aspect_ratio(16, (0, 0)), Some((2, 1)));
+        assert_eq!(sample_aspect_ratio(17, (0, 0)), None);
+        assert_eq!(sample_aspect_ratio(255, (4, 3)), Some((4, 3)));
+        assert_eq!(sample_aspect_ratio(255, (0, 3)), None);
+    }
+
+    #[test]
+    fn probe_h265() {
+        let result = probe(include_bytes!("../codec/h265/test_data/test-25fps.h265")).unwrap();
+        assert_eq!(result.codec, Codec::H265);
+        assert_eq!(result.container, Container::AnnexB);
+        assert_eq!(result.profile, 1);
+        assert_eq!(result.level, Some(60));
+        assert_eq!(result.bit_depth, 8);
+        assert_eq!(result.chroma_format, ChromaFormat::Yuv420);
+        assert_eq!(result.coded_resolution, Resolution::from((320, 240)));
+        assert!(!result.interlaced);
+        assert_eq!(result.frame_rate, Some((25, 1)));
+        assert_eq!(result.dpb_size, 5);
+
+        let result = probe(include_bytes!("../codec/h265/test_data/bbb.h265")).unwrap();
+        assert_eq!(result.coded_resolution, Resolution::from((176, 144)));
+        assert_eq!(result.frame_rate, Some((60, 1)));
+    }
+
+    #[test]
+    fn probe_ivf() {
+        let result = probe(VP8_STREAM).unwrap();
+        assert_eq!(result.codec, Codec::Vp8);
+        assert_eq!(result.container, Container::Ivf);
+        assert_eq!(result.profile, 0);
+        assert_eq!(result.level, None);
+        assert_eq!(result.coded_resolution, Resolution::from((320, 240)));
+        assert_eq!(result.frame_rate, Some((25, 1)));
+        assert_eq!(result.dpb_size, 3);
+
+        let result = probe(VP9_STREAM).unwrap();
+        assert_eq!(result.codec, Codec::Vp9);
+        assert_eq!(result.container, Container::Ivf);
+        assert_eq!(result.profile, 0);
+        assert_eq!(result.bit_depth, 8);
+        assert_eq!(result.chroma_format, ChromaFormat::Yuv420);
+        assert_eq!(result.display_resolution, Resolution::from((320, 240)));
+        assert_eq!(result.frame_rate, Some((25, 1)));
+        assert_eq!(result.dpb_size, 8);
+    }
+
+    #[test]
+    fn probe_raw_frames() {
+        let result = probe(first_ivf_frame(VP8_STREAM)).unwrap();
+        assert_eq!(result.codec, Codec::Vp8);
+        assert_eq!(result.container, Container::RawFrame);
+        assert_eq!(result.coded_resolution, Resolution::from((320, 240)));
+        assert_eq!(result.frame_rate, None);
+
+        let result = probe(first_ivf_frame(VP9_STREAM)).unwrap();
+        assert_eq!(result.codec, Codec::Vp9);
+        assert_eq!(result.container, Container::RawFrame);
+        assert_eq!(result.coded_resolution, Resolution::from((320, 240)));
+    }
+
+    #[test]
+    fn invalid_streams() {
+        assert!(probe(&[]).is_err());
+        assert!(probe(b"not a video stream").is_err());
+        // An Annex B stream without parameter sets.
+        assert!(probe(&[0x00, 0x00, 0x00, 0x01, 0x09, 0xf0]).is_err());
+        // A truncated IVF file.
+        assert!(probe(&VP9_STREAM[..16]).is_err());
+        // A VP8 inter frame.
+        assert!(probe(&[0x01, 0x00, 0x00, 0x9d, 0x01, 0x2a, 0, 0, 0, 0]).is_err());
+    }
+}
@@ -0,0 +1,36 @@
+// Copyright 2023 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+//! Rewriters editing H.264 and H.265 Annex B streams without re-encoding them.
+//!
+//! The rewriters parse the parameter sets of the stream, let the client change the fields that do
+//! not affect how slices are parsed or decoded (VUI, DPB sizing, profile and level), and write them
+//! back using the synthesizers. They can also drop NAL units of a given type, e.g. SEI messages or
+//! filler data, and insert access unit delimiters. Slice NAL units are always copied as-is.
+//!
+//! Every NAL unit of the output is prefixed with a 4-byte start code.
+
+pub mod h264;
+pub mod h265;
+
+/// Start code written before each NAL unit, including the `zero_byte` so it is valid in front of
+/// parameter sets and the first NAL unit of an access unit.
+const START_CODE: [u8; 4] = [0x00, 0x00, 0x00, 0x01];
+
+/// Colour description to write into the video signal type of the VUI. The values are the code
+/// points of ITU-T H.273, which both H.264 and H.265 use.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ColourDescription {
+    pub colour_primaries: u8,
+    pub transfer_characteristics: u8,
+    pub matrix_coefficients: u8,
+    /// Whether the samples use the full range of values, instead of the studio swing.
+    pub full_range: bool,
+}
+
+/// Appends `nalu`, which does not include a start code, to `output`.
+fn push_nalu(output: &mut Vec<u8>, nalu: &[u8]) {
+    output.extend_from_slice(&START_CODE);
+    output.extend_from_slice(nalu);
+}
@@ -0,0 +1,441 @@
+// Copyright 2023 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+//! H.264 stream rewriter.
+
+use anyhow::anyhow;
+
+use crate::codec::h264::nalu::Header;
+use crate::codec::h264::parser::Nalu;
+use crate::codec::h264::parser::NaluType;
+use crate::codec::h264::parser::Parser;
+use crate::codec::h264::parser::Sps;
+use crate::codec::h264::synthesizer::Synthesizer;
+use crate::utils::for_each_nalu;
+use crate::utils::rewrite::push_nalu;
+use crate::utils::rewrite::ColourDescription;
+
+/// Access unit delimiter with `primary_pic_type` equal to 7, i.e. allowing every slice type, so it
+/// can be written before the slices of the access unit are known.
+const AUD_NALU: [u8; 2] = [0x09, 0xf0];
+
+/// An edit applied to each SPS of the stream.
+pub type SpsEdit = Box<dyn FnMut(&mut Sps) -> anyhow::Result<()>>;
+
+/// Rewrites H.264 Annex B streams, see the [module documentation](crate::utils::rewrite).
+pub struct H264Rewriter {
+    parser: Parser,
+    sps_edit: Option<SpsEdit>,
+    dropped_types: Vec<NaluType>,
+    insert_aud: bool,
+    /// Whether the current access unit has VCL NAL units, meaning that the next access unit
+    /// delimiter, parameter set, SEI or first slice of a picture starts a new one.
+    au_has_vcl: bool,
+}
+
+impl Default for H264Rewriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl H264Rewriter {
+    /// Creates a rewriter copying the stream unchanged.
+    pub fn new() -> Self {
+        Self {
+            parser: Default::default(),
+            sps_edit: None,
+            dropped_types: Vec::new(),
+            insert_aud: false,
+            au_has_vcl: true,
+        }
+    }
+
+    /// Sets the edit to apply to each SPS of the stream.
+    ///
+    /// Only the VUI, `level_idc` and the constraint flags can be changed, as the other fields are
+    /// needed to parse the slices. The rewriting fails if the edit changes anything else.
+    pub fn set_sps_edit(&mut self, edit: SpsEdit) {
+        self.sps_edit = Some(edit);
+    }
+
+    /// Drops the NAL units of type `type_` from the stream. The VCL NAL units and the parameter
+    /// sets are needed to decode the stream and cannot be dropped.
+    pub fn drop_nalu_type(&mut self, type_: NaluType) -> anyhow::Result<()> {
+        if matches!(
+            type_,
+            NaluType::Slice
+                | NaluType::SliceDpa
+                | NaluType::SliceDpb
+                | NaluType::SliceDpc
+                | NaluType::SliceIdr
+                | NaluType::Sps
+                | NaluType::Pps
+                | NaluType::SubsetSps
+        ) {
+            return Err(anyhow!("cannot drop NAL units of type {:?}", type_));
+        }
+
+        if !self.dropped_types.contains(&type_) {
+            self.dropped_types.push(type_);
+        }
+
+        Ok(())
+    }
+
+    /// Sets whether an access unit delimiter is inserted at the start of each access unit. The
+    /// delimiters already present in the stream are replaced.
+    pub fn set_insert_aud(&mut self, insert_aud: bool) {
+        self.insert_aud = insert_aud;
+    }
+
+    /// Whether `nalu` is the first NAL unit of a new access unit (7.4.1.2.3). Pictures are
+    /// assumed to start with the slice containing macroblock 0, i.e. arbitrary slice order is not
+    /// supported.
+    fn starts_access_unit(&self, nalu: &Nalu<&[u8]>) -> bool {
+        match nalu.header().nalu_type() {
+            NaluType::Sei
+            | NaluType::Sps
+            | NaluType::Pps
+            | NaluType::AuDelimiter
+            | NaluType::PrefixUnit
+            | NaluType::SubsetSps
+            | NaluType::DepthSps => self.au_has_vcl,
+            NaluType::Slice | NaluType::SliceDpa | NaluType::SliceIdr => {
+                // first_mb_in_slice is the first syntax element of the slice header, and is 0
+                // if and only if its Exp-Golomb code is a single set bit.
+                let first_mb_is_zero = nalu
+                    .as_ref()
+                    .get(nalu.header().len())
+                    .is_some_and(|byte| byte & 0x80 != 0);
+
+                self.au_has_vcl && first_mb_is_zero
+            }
+            _ => false,
+        }
+    }
+
+    /// Writes `sps_nalu` into `output` after applying the SPS edit, if any. Returns false if the
+    /// edit left the SPS unchanged, in which case nothing is written.
+    fn rewrite_sps(
+        &mut self,
+        sps_nalu: &Nalu<&[u8]>,
+        output: &mut Vec<u8>,
+    ) -> anyhow::Result<bool> {
+        let sps = self.parser.parse_sps(sps_nalu)?;
+        let edit = match &mut self.sps_edit {
+            Some(edit) => edit,
+            None => return Ok(false),
+        };
+
+        let mut edited = Sps::clone(sps);
+        edit(&mut edited)?;
+        if edited == **sps {
+            return Ok(false);
+        }
+
+        // Put back the fields that may be changed to check that nothing else was.
+        let mut check = edited.clone();
+        check.level_idc = sps.level_idc;
+        check.constraint_set0_flag = sps.constraint_set0_flag;
+        check.constraint_set1_flag = sps.constraint_set1_flag;
+        check.constraint_set2_flag = sps.constraint_set2_flag;
+        check.constraint_set3_flag = sps.constraint_set3_flag;
+        check.constraint_set4_flag = sps.constraint_set4_flag;
+        check.constraint_set5_flag = sps.constraint_set5_flag;
+        check.vui_parameters_present_flag = sps.vui_parameters_present_flag;
+        check.vui_parameters = sps.vui_parameters.clone();
+        if check != **sps {
+            return Err(anyhow!(
+                "SPS {} edit changes fields used to parse the slices",
+                sps.seq_parameter_set_id
+            ));
+        }
+
+        // The synthesizer writes the start code.
+        Synthesizer::<Sps, _>::synthesize(sps_nalu.header().ref_idc(), &edited, output, true)?;
+
+        Ok(true)
+    }
+
+    /// Rewrites the Annex B `data` and returns the result.
+    ///
+    /// The rewriter keeps track of the parameter sets and access units, so a stream must be
+    /// passed in decoding order across calls, which may be split at any NAL unit boundary.
+    pub fn rewrite(&mut self, data: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let mut output = Vec::with_capacity(data.len() + AUD_NALU.len() + 4);
+
+        for_each_nalu(data, |nalu: Nalu<&[u8]>| {
+            let type_ = nalu.header().nalu_type();
+
+            if self.starts_access_unit(&nalu) {
+                self.au_has_vcl = false;
+                if self.insert_aud {
+                    push_nalu(&mut output, &AUD_NALU);
+                }
+            }
+
+            if matches!(
+                type_,
+                NaluType::Slice
+                    | NaluType::SliceDpa
+                    | NaluType::SliceDpb
+                    | NaluType::SliceDpc
+                    | NaluType::SliceIdr
+            ) {
+                self.au_has_vcl = true;
+            }
+
+            if (self.insert_aud && type_ == NaluType::AuDelimiter)
+                || self.dropped_types.contains(&type_)
+            {
+                return Ok(());
+            }
+
+            if type_ == NaluType::Sps && self.rewrite_sps(&nalu, &mut output)? {
+                return Ok(());
+            }
+
+            push_nalu(&mut output, nalu.as_ref());
+            Ok(())
+        })?;
+
+        Ok(output)
+    }
+}
+
+/// Sets the `bitstream_restriction` of the VUI of `sps`, which lets the decoder output pictures
+/// as soon as `max_num_reorder_frames` allows instead of waiting for its DPB to fill up.
+///
+/// The other bitstream restriction fields keep their value if already present, or take their
+/// inferred value otherwise.
+pub fn set_bitstream_restriction(
+    sps: &mut Sps,
+    max_num_reorder_frames: u32,
+    max_dec_frame_buffering: u32,
+) -> anyhow::Result<()> {
+    if max_num_reorder_frames > max_dec_frame_buffering {
+        return Err(anyhow!(
+            "max_num_reorder_frames {} is larger than max_dec_frame_buffering {}",
+            max_num_reorder_frames,
+            max_dec_frame_buffering
+        ));
+    }
+
+    // E.2.1: max_dec_frame_buffering must be large enough for the reference frames.
+    if max_dec_frame_buffering < sps.max_num_ref_frames {
+        return Err(anyhow!(
+            "max_dec_frame_buffering {} is smaller than max_num_ref_frames {}",
+            max_dec_frame_buffering,
+            sps.max_num_ref_frames
+        ));
+    }
+
+    if !sps.vui_parameters_present_flag {
+        sps.vui_parameters_present_flag = true;
+        sps.vui_parameters = Default::default();
+    }
+
+    let vui = &mut sps.vui_parameters;
+    if !vui.bitstream_restriction_flag {
+        vui.bitstream_restriction_flag = true;
+        vui.motion_vectors_over_pic_boundaries_flag = true;
+        vui.max_bytes_per_pic_denom = 2;
+        vui.max_bits_per_mb_denom = 1;
+        vui.log2_max_mv_length_horizontal = 15;
+        vui.log2_max_mv_length_vertical = 15;
+    }
+
+    vui.max_num_reorder_frames = max_num_reorder_frames;
+    vui.max_dec_frame_buffering = max_dec_frame_buffering;
+
+    Ok(())
+}
+
+/// Sets the colour description of the VUI of `sps`.
+pub fn set_colour_description(sps: &mut Sps, colour: &ColourDescription) {
+    if !sps.vui_parameters_present_flag {
+        sps.vui_parameters_present_flag = true;
+        sps.vui_parameters = Default::default();
+    }
+
+    let vui = &mut sps.vui_parameters;
+    vui.video_signal_type_present_flag = true;
+    vui.video_full_range_flag = colour.full_range;
+    vui.colour_description_present_flag = true;
+    vui.colour_primaries = colour.colour_primaries;
+    vui.transfer_characteristics = colour.transfer_characteristics;
+    vui.matrix_coefficients = colour.matrix_coefficients;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::h264::parser::NaluHeader;
+    use crate::utils::annexb_nalus;
+    use crate::utils::NalIterator;
+
+    const STREAM: &[u8] = include_bytes!("../../codec/h264/test_data/test-25fps.h264");
+
+    /// Returns the type and content of the NAL units of `data`.
+    fn nalus(data: &[u8]) -> Vec<(NaluType, Vec<u8>)> {
+        annexb_nalus::<NaluHeader>(data)
+            .into_iter()
+            .map(|nalu| (NaluType::n(nalu[0] & 0x1f).unwrap(), nalu.to_vec()))
+            .collect()
+    }
+
+    /// Returns the SPSs of `data`.
+    fn spses(data: &[u8]) -> Vec<Sps> {
+        let mut parser = Parser::default();
+        let mut spses = Vec::new();
+        for_each_nalu(data, |nalu: Nalu<&[u8]>| {
+            if nalu.header().nalu_type() == NaluType::Sps {
+                spses.push(Sps::clone(parser.parse_sps(&nalu)?));
+            }
+            Ok(())
+        })
+        .unwrap();
+
+        spses
+    }
+
+    #[test]
+    fn passthrough() {
+        let mut rewriter = H264Rewriter::new();
+        let output = rewriter.rewrite(STREAM).unwrap();
+
+        assert_eq!(nalus(&output), nalus(STREAM));
+    }
+
+    #[test]
+    fn edit_sps() {
+        let colour = ColourDescription {
+            colour_primaries: 1,
+            transfer_characteristics: 1,
+            matrix_coefficients: 1,
+            full_range: true,
+        };
+        let edit = move |sps: &mut Sps| {
+            set_bitstream_restriction(sps, 0, sps.max_num_ref_frames)?;
+            set_colour_description(sps, &colour);
+            Ok(())
+        };
+
+        let mut rewriter = H264Rewriter::new();
+        rewriter.set_sps_edit(Box::new(edit));
+
+        // Feed the stream one NAL unit at a time to check that the state is kept across calls.
+        let mut output = Vec::new();
+        for nalu in NalIterator::<Nalu<&[u8]>>::new(STREAM) {
+            output.extend(rewriter.rewrite(nalu).unwrap());
+        }
+
+        // Only the SPSs have changed.
+        let original_nalus = nalus(STREAM);
+        let nalus = nalus(&output);
+        assert_eq!(nalus.len(), original_nalus.len());
+        for ((type_, nalu), (original_type, original_nalu)) in nalus.iter().zip(&original_nalus) {
+            assert_eq!(type_, original_type);
+            if *type_ != NaluType::Sps {
+                assert_eq!(nalu, original_nalu);
+            }
+        }
+
+        let original_spses = spses(STREAM);
+        let spses = spses(&output);
+        assert_eq!(spses.len(), original_spses.len());
+        for (sps, original) in spses.iter().zip(original_spses) {
+            let mut expected = original.clone();
+            edit(&mut expected).unwrap();
+            assert_eq!(*sps, expected);
+
+            assert!(!original.vui_parameters.bitstream_restriction_flag);
+            assert_eq!(sps.max_num_order_frames(), 0);
+            assert_eq!(sps.max_dpb_frames(), sps.max_num_ref_frames as usize);
+            assert!(sps.max_dpb_frames() < original.max_dpb_frames());
+        }
+    }
+
+    #[test]
+    fn drop_sei_and_insert_aud() {
+        let mut rewriter = H264Rewriter::new();
+        rewriter.drop_nalu_type(NaluType::Sei).unwrap();
+        rewriter.set_insert_aud(true);
+        let output = rewriter.rewrite(STREAM).unwrap();
+
+        let original_nalus = nalus(STREAM);
+        let nalus = nalus(&output);
+
+        // The stream has two slices per picture: each access unit starts with its SEI, which is
+        // now replaced by the delimiter.
+        let num_pictures = original_nalus
+            .iter()
+            .filter(|(type_, nalu)| {
+                matches!(type_, NaluType::Slice | NaluType::SliceIdr) && nalu[1] & 0x80 != 0
+            })
+            .count();
+        assert_eq!(num_pictures, 250);
+        assert!(!nalus.iter().any(|(type_, _)| *type_ == NaluType::Sei));
+        assert_eq!(
+            nalus
+                .iter()
+                .filter(|(type_, _)| *type_ == NaluType::AuDelimiter)
+                .count(),
+            num_pictures
+        );
+        assert_eq!(nalus[0].0, NaluType::AuDelimiter);
+
+        // Each delimiter comes right before the first NAL unit of the access unit.
+        let mut expected = Vec::new();
+        for (type_, nalu) in original_nalus {
+            match type_ {
+                NaluType::Sei => {
+                    if expected.last().is_none_or(|(type_, _)| {
+                        matches!(type_, NaluType::Slice | NaluType::SliceIdr)
+                    }) {
+                        expected.push((NaluType::AuDelimiter, AUD_NALU.to_vec()));
+                    }
+                }
+                _ => expected.push((type_, nalu)),
+            }
+        }
+        assert_eq!(nalus, expected);
+    }
+
+    #[test]
+    fn replace_aud() {
+        const STREAM: &[u8] = include_bytes!("../../codec/h264/test_data/64x64-I-P-B-P.h264");
+
+        let mut rewriter = H264Rewriter::new();
+        rewriter.set_insert_aud(true);
+        let output = rewriter.rewrite(STREAM).unwrap();
+
+        let types = |nalus: Vec<(NaluType, Vec<u8>)>| {
+            nalus
+                .into_iter()
+                .map(|(type_, _)| type_)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(types(nalus(&output)), types(nalus(STREAM)));
+    }
+
+    #[test]
+    fn invalid_edits() {
+        let mut rewriter = H264Rewriter::new();
+        assert!(rewriter.drop_nalu_type(NaluType::Slice).is_err());
+        assert!(rewriter.drop_nalu_type(NaluType::Pps).is_err());
+
+        rewriter.set_sps_edit(Box::new(|sps| {
+            sps.log2_max_frame_num_minus4 += 1;
+            Ok(())
+        }));
+        assert!(rewriter.rewrite(STREAM).is_err());
+
+        let mut sps = spses(STREAM).remove(0);
+        assert!(set_bitstream_restriction(&mut sps, 2, 1).is_err());
+        let max_num_ref_frames = sps.max_num_ref_frames;
+        assert!(set_bitstream_restriction(&mut sps, 0, max_num_ref_frames - 1).is_err());
+    }
+}
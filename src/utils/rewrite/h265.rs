@@ -0,0 +1,404 @@
+// Copyright 2023 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+//! H.265 stream rewriter.
+
+use anyhow::anyhow;
+
+use crate::codec::h265::parser::Nalu;
+use crate::codec::h265::parser::NaluType;
+use crate::codec::h265::parser::Parser;
+use crate::codec::h265::parser::Sps;
+use crate::codec::h265::synthesizer::Synthesizer;
+use crate::utils::for_each_nalu;
+use crate::utils::rewrite::push_nalu;
+use crate::utils::rewrite::ColourDescription;
+
+/// Payload of the access unit delimiters, with `pic_type` equal to 2, i.e. allowing every slice
+/// type, so it can be written before the slices of the access unit are known.
+const AUD_PAYLOAD: u8 = 0x50;
+
+/// An edit applied to each SPS of the stream.
+pub type SpsEdit = Box<dyn FnMut(&mut Sps) -> anyhow::Result<()>>;
+
+/// Whether `type_` is the type of a VCL NAL unit.
+fn is_vcl(type_: NaluType) -> bool {
+    (type_ as u32) < NaluType::VpsNut as u32
+}
+
+/// Rewrites H.265 Annex B streams, see the [module documentation](crate::utils::rewrite).
+pub struct H265Rewriter {
+    parser: Parser,
+    sps_edit: Option<SpsEdit>,
+    dropped_types: Vec<NaluType>,
+    insert_aud: bool,
+    /// Whether the current access unit has VCL NAL units, meaning that the next parameter set,
+    /// prefix SEI, access unit delimiter or first slice segment of a picture starts a new one.
+    au_has_vcl: bool,
+}
+
+impl Default for H265Rewriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl H265Rewriter {
+    /// Creates a rewriter copying the stream unchanged.
+    pub fn new() -> Self {
+        Self {
+            parser: Default::default(),
+            sps_edit: None,
+            dropped_types: Vec::new(),
+            insert_aud: false,
+            au_has_vcl: true,
+        }
+    }
+
+    /// Sets the edit to apply to each SPS of the stream.
+    ///
+    /// Only the VUI, the profile, tier and level, and the sub-layer ordering info (DPB size,
+    /// reordering and latency) can be changed, as the other fields are needed to parse the
+    /// slices. The rewriting fails if the edit changes anything else. The DPB sizes must not
+    /// exceed the ones of the VPS.
+    pub fn set_sps_edit(&mut self, edit: SpsEdit) {
+        self.sps_edit = Some(edit);
+    }
+
+    /// Drops the NAL units of type `type_` from the stream. The VCL NAL units and the parameter
+    /// sets are needed to decode the stream and cannot be dropped.
+    pub fn drop_nalu_type(&mut self, type_: NaluType) -> anyhow::Result<()> {
+        if is_vcl(type_)
+            || matches!(
+                type_,
+                NaluType::VpsNut | NaluType::SpsNut | NaluType::PpsNut
+            )
+        {
+            return Err(anyhow!("cannot drop NAL units of type {:?}", type_));
+        }
+
+        if !self.dropped_types.contains(&type_) {
+            self.dropped_types.push(type_);
+        }
+
+        Ok(())
+    }
+
+    /// Sets whether an access unit delimiter is inserted at the start of each access unit. The
+    /// delimiters already present in the stream are replaced.
+    pub fn set_insert_aud(&mut self, insert_aud: bool) {
+        self.insert_aud = insert_aud;
+    }
+
+    /// Whether `nalu` is the first NAL unit of a new access unit (7.4.2.4.4).
+    fn starts_access_unit(&self, nalu: &Nalu<&[u8]>) -> bool {
+        let type_ = nalu.header().nalu_type();
+
+        match type_ {
+            NaluType::VpsNut
+            | NaluType::SpsNut
+            | NaluType::PpsNut
+            | NaluType::AudNut
+            | NaluType::PrefixSeiNut
+            | NaluType::RsvNvcl41
+            | NaluType::RsvNvcl42
+            | NaluType::RsvNvcl43
+            | NaluType::RsvNvcl44 => self.au_has_vcl,
+            _ if is_vcl(type_) => {
+                // first_slice_segment_in_pic_flag is the first bit after the 2-byte NAL unit
+                // header.
+                let first_slice_segment = nalu.as_ref().get(2).is_some_and(|byte| byte & 0x80 != 0);
+
+                self.au_has_vcl && first_slice_segment
+            }
+            _ => false,
+        }
+    }
+
+    /// Writes `sps_nalu` into `output` after applying the SPS edit, if any. Returns false if the
+    /// edit left the SPS unchanged, in which case nothing is written.
+    fn rewrite_sps(
+        &mut self,
+        sps_nalu: &Nalu<&[u8]>,
+        output: &mut Vec<u8>,
+    ) -> anyhow::Result<bool> {
+        let sps = self.parser.parse_sps(sps_nalu)?;
+        let edit = match &mut self.sps_edit {
+            Some(edit) => edit,
+            None => return Ok(false),
+        };
+
+        let mut edited = sps.clone();
+        edit(&mut edited)?;
+        if edited == *sps {
+            return Ok(false);
+        }
+
+        // Put back the fields that may be changed to check that nothing else was.
+        let mut check = edited.clone();
+        check.profile_tier_level = sps.profile_tier_level.clone();
+        check.sub_layer_ordering_info_present_flag = sps.sub_layer_ordering_info_present_flag;
+        check.max_dec_pic_buffering_minus1 = sps.max_dec_pic_buffering_minus1;
+        check.max_num_reorder_pics = sps.max_num_reorder_pics;
+        check.max_latency_increase_plus1 = sps.max_latency_increase_plus1;
+        check.vui_parameters_present_flag = sps.vui_parameters_present_flag;
+        check.vui_parameters = sps.vui_parameters.clone();
+        if check != *sps {
+            return Err(anyhow!(
+                "SPS {} edit changes fields used to parse the slices",
+                sps.seq_parameter_set_id
+            ));
+        }
+
+        // The synthesizer writes the start code.
+        Synthesizer::<Sps, _>::synthesize(&edited, output, true)?;
+
+        Ok(true)
+    }
+
+    /// Rewrites the Annex B `data` and returns the result.
+    ///
+    /// The rewriter keeps track of the parameter sets and access units, so a stream must be
+    /// passed in decoding order across calls, which may be split at any NAL unit boundary.
+    pub fn rewrite(&mut self, data: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let mut output = Vec::with_capacity(data.len() + 7);
+
+        for_each_nalu(data, |nalu: Nalu<&[u8]>| {
+            let type_ = nalu.header().nalu_type();
+
+            if self.starts_access_unit(&nalu) {
+                self.au_has_vcl = false;
+                if self.insert_aud {
+                    // The delimiter has the temporal id of the access unit, which all of its NAL
+                    // units share except for the end of bitstream one.
+                    let aud = [
+                        (NaluType::AudNut as u8) << 1,
+                        nalu.header().temporal_id_plus1(),
+                        AUD_PAYLOAD,
+                    ];
+                    push_nalu(&mut output, &aud);
+                }
+            }
+
+            if is_vcl(type_) {
+                self.au_has_vcl = true;
+            }
+
+            if (self.insert_aud && type_ == NaluType::AudNut) || self.dropped_types.contains(&type_)
+            {
+                return Ok(());
+            }
+
+            if type_ == NaluType::SpsNut && self.rewrite_sps(&nalu, &mut output)? {
+                return Ok(());
+            }
+
+            push_nalu(&mut output, nalu.as_ref());
+            Ok(())
+        })?;
+
+        Ok(output)
+    }
+}
+
+/// Sets the DPB size and the number of reordered pictures of all the sub-layers of `sps`. Reducing
+/// them lets the decoder output pictures as soon as possible instead of waiting for its DPB to fill
+/// up.
+pub fn set_dpb_size(
+    sps: &mut Sps,
+    max_dec_pic_buffering_minus1: u8,
+    max_num_reorder_pics: u8,
+) -> anyhow::Result<()> {
+    if max_num_reorder_pics > max_dec_pic_buffering_minus1 {
+        return Err(anyhow!(
+            "max_num_reorder_pics {} is larger than max_dec_pic_buffering_minus1 {}",
+            max_num_reorder_pics,
+            max_dec_pic_buffering_minus1
+        ));
+    }
+
+    // 7.4.8: the short-term reference picture sets of the SPS must fit in the DPB. The ones
+    // signaled in the slice headers cannot be checked here.
+    let max_num_delta_pocs = sps
+        .short_term_ref_pic_set
+        .iter()
+        .map(|rps| rps.num_delta_pocs)
+        .max()
+        .unwrap_or(0);
+    if u32::from(max_dec_pic_buffering_minus1) < max_num_delta_pocs {
+        return Err(anyhow!(
+            "max_dec_pic_buffering_minus1 {} is smaller than the {} pictures of the reference picture sets",
+            max_dec_pic_buffering_minus1,
+            max_num_delta_pocs
+        ));
+    }
+
+    let first = if sps.sub_layer_ordering_info_present_flag {
+        0
+    } else {
+        sps.max_sub_layers_minus1
+    };
+
+    for i in usize::from(first)..=usize::from(sps.max_sub_layers_minus1) {
+        sps.max_dec_pic_buffering_minus1[i] = max_dec_pic_buffering_minus1;
+        sps.max_num_reorder_pics[i] = max_num_reorder_pics;
+    }
+
+    Ok(())
+}
+
+/// Sets the colour description of the VUI of `sps`.
+pub fn set_colour_description(sps: &mut Sps, colour: &ColourDescription) {
+    if !sps.vui_parameters_present_flag {
+        sps.vui_parameters_present_flag = true;
+        sps.vui_parameters = Default::default();
+    }
+
+    let vui = &mut sps.vui_parameters;
+    vui.video_signal_type_present_flag = true;
+    vui.video_full_range_flag = colour.full_range;
+    vui.colour_description_present_flag = true;
+    vui.colour_primaries = colour.colour_primaries.into();
+    vui.transfer_characteristics = colour.transfer_characteristics.into();
+    vui.matrix_coeffs = colour.matrix_coefficients.into();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::h265::parser::NaluHeader;
+    use crate::codec::h265::parser::ShortTermRefPicSet;
+    use crate::utils::annexb_nalus;
+    use crate::utils::NalIterator;
+
+    const STREAM: &[u8] = include_bytes!("../../codec/h265/test_data/bbb.h265");
+
+    /// Returns the type and content of the NAL units of `data`.
+    fn nalus(data: &[u8]) -> Vec<(NaluType, Vec<u8>)> {
+        annexb_nalus::<NaluHeader>(data)
+            .into_iter()
+            .map(|nalu| (NaluType::n((nalu[0] >> 1) & 0x3f).unwrap(), nalu.to_vec()))
+            .collect()
+    }
+
+    /// Returns the SPSs of `data`.
+    fn spses(data: &[u8]) -> Vec<Sps> {
+        let mut parser = Parser::default();
+        let mut spses = Vec::new();
+        for_each_nalu(data, |nalu: Nalu<&[u8]>| {
+            if nalu.header().nalu_type() == NaluType::SpsNut {
+                spses.push(parser.parse_sps(&nalu)?.clone());
+            }
+            Ok(())
+        })
+        .unwrap();
+
+        spses
+    }
+
+    #[test]
+    fn passthrough() {
+        let mut rewriter = H265Rewriter::new();
+        let output = rewriter.rewrite(STREAM).unwrap();
+
+        assert_eq!(nalus(&output), nalus(STREAM));
+    }
+
+    #[test]
+    fn edit_sps() {
+        let colour = ColourDescription {
+            colour_primaries: 9,
+            transfer_characteristics: 16,
+            matrix_coefficients: 9,
+            full_range: false,
+        };
+        let edit = move |sps: &mut Sps| {
+            set_dpb_size(sps, 1, 0)?;
+            set_colour_description(sps, &colour);
+            Ok(())
+        };
+
+        let mut rewriter = H265Rewriter::new();
+        rewriter.set_sps_edit(Box::new(edit));
+
+        // Feed the stream one NAL unit at a time to check that the state is kept across calls.
+        let mut output = Vec::new();
+        for nalu in NalIterator::<Nalu<&[u8]>>::new(STREAM) {
+            output.extend(rewriter.rewrite(nalu).unwrap());
+        }
+
+        // Only the SPS has changed.
+        let original_nalus = nalus(STREAM);
+        let nalus = nalus(&output);
+        assert_eq!(nalus.len(), original_nalus.len());
+        for ((type_, nalu), (original_type, original_nalu)) in nalus.iter().zip(&original_nalus) {
+            assert_eq!(type_, original_type);
+            if *type_ != NaluType::SpsNut {
+                assert_eq!(nalu, original_nalu);
+            }
+        }
+
+        let original_spses = spses(STREAM);
+        let spses = spses(&output);
+        assert_eq!(spses.len(), 1);
+        let mut expected = original_spses[0].clone();
+        assert!(expected.max_dec_pic_buffering_minus1[0] > 1);
+        edit(&mut expected).unwrap();
+        assert_eq!(spses[0], expected);
+    }
+
+    #[test]
+    fn drop_sei_and_insert_aud() {
+        let mut rewriter = H265Rewriter::new();
+        rewriter.drop_nalu_type(NaluType::PrefixSeiNut).unwrap();
+        rewriter.set_insert_aud(true);
+        let output = rewriter.rewrite(STREAM).unwrap();
+
+        // Each picture has a single slice segment, which starts an access unit unless it follows
+        // the parameter sets.
+        let aud = vec![0x46, 0x01, AUD_PAYLOAD];
+        let mut expected = vec![(NaluType::AudNut, aud.clone())];
+        for (type_, nalu) in nalus(STREAM) {
+            match type_ {
+                NaluType::PrefixSeiNut => (),
+                _ if is_vcl(type_) && is_vcl(expected.last().unwrap().0) => {
+                    expected.push((NaluType::AudNut, aud.clone()));
+                    expected.push((type_, nalu));
+                }
+                _ => expected.push((type_, nalu)),
+            }
+        }
+
+        assert_eq!(nalus(&output), expected);
+        assert_eq!(
+            expected
+                .iter()
+                .filter(|(type_, _)| *type_ == NaluType::AudNut)
+                .count(),
+            60
+        );
+    }
+
+    #[test]
+    fn invalid_edits() {
+        let mut rewriter = H265Rewriter::new();
+        assert!(rewriter.drop_nalu_type(NaluType::TrailR).is_err());
+        assert!(rewriter.drop_nalu_type(NaluType::SpsNut).is_err());
+
+        rewriter.set_sps_edit(Box::new(|sps| {
+            sps.log2_max_pic_order_cnt_lsb_minus4 += 1;
+            Ok(())
+        }));
+        assert!(rewriter.rewrite(STREAM).is_err());
+
+        let mut sps = spses(STREAM).remove(0);
+        assert!(set_dpb_size(&mut sps, 1, 2).is_err());
+        sps.short_term_ref_pic_set.push(ShortTermRefPicSet {
+            num_delta_pocs: 2,
+            ..Default::default()
+        });
+        assert!(set_dpb_size(&mut sps, 1, 0).is_err());
+        assert!(set_dpb_size(&mut sps, 2, 0).is_ok());
+    }
+}
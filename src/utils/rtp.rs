@@ -0,0 +1,567 @@
+// Copyright 2023 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+//! Depacketizers rebuilding encoded frames from RTP streams.
+//!
+//! [`Depacketizer`] takes care of the codec-independent part of the job: it parses RTP packets,
+//! puts them back in sequence number order, detects lost packets, and groups packets into frames
+//! using their timestamp and marker bit. The codec-specific payload formats implement
+//! [`PayloadFormat`] to turn the payloads of a frame into data that can be fed to the
+//! corresponding stateless decoder.
+//!
+//! Frames are always produced, even if some of their packets have been lost. In that case their
+//! `complete` member is unset, and the client can decide whether to decode them anyway or to skip
+//! until the next key frame.
+
+pub mod h264;
+pub mod h265;
+pub mod vp8;
+pub mod vp9;
+
+use std::collections::BTreeMap;
+use std::collections::VecDeque;
+
+use anyhow::anyhow;
+
+/// Default number of packets we keep waiting for a missing one before considering it lost.
+const DEFAULT_REORDER_WINDOW: usize = 64;
+
+/// A parsed RTP packet (RFC 3550 section 5.1).
+#[derive(Debug, PartialEq, Eq)]
+pub struct RtpPacket<'a> {
+    pub marker: bool,
+    pub payload_type: u8,
+    pub sequence_number: u16,
+    pub timestamp: u32,
+    pub ssrc: u32,
+    /// Payload of the packet, without header extension or padding.
+    pub payload: &'a [u8],
+}
+
+impl<'a> RtpPacket<'a> {
+    pub fn parse(data: &'a [u8]) -> anyhow::Result<Self> {
+        if data.len() < 12 {
+            return Err(anyhow!("RTP packet too short ({} bytes)", data.len()));
+        }
+
+        let version = data[0] >> 6;
+        if version != 2 {
+            return Err(anyhow!("unsupported RTP version {}", version));
+        }
+
+        let padding = data[0] & 0x20 != 0;
+        let extension = data[0] & 0x10 != 0;
+        let csrc_count = usize::from(data[0] & 0xf);
+
+        let mut start = 12 + 4 * csrc_count;
+        if extension {
+            let len = data
+                .get(start + 2..start + 4)
+                .map(|b| usize::from(u16::from_be_bytes([b[0], b[1]])))
+                .ok_or_else(|| anyhow!("truncated RTP header extension"))?;
+            start += 4 + 4 * len;
+        }
+
+        let mut end = data.len();
+        if padding {
+            let padding_len = usize::from(data[end - 1]);
+            end = end
+                .checked_sub(padding_len)
+                .ok_or_else(|| anyhow!("invalid RTP padding length {}", padding_len))?;
+        }
+
+        if start > end {
+            return Err(anyhow!("RTP header is larger than the packet"));
+        }
+
+        Ok(Self {
+            marker: data[1] & 0x80 != 0,
+            payload_type: data[1] & 0x7f,
+            sequence_number: u16::from_be_bytes([data[2], data[3]]),
+            timestamp: u32::from_be_bytes([data[4], data[5], data[6], data[7]]),
+            ssrc: u32::from_be_bytes([data[8], data[9], data[10], data[11]]),
+            payload: &data[start..end],
+        })
+    }
+}
+
+/// An encoded frame rebuilt from RTP packets.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Frame {
+    /// Data of the frame, in the format expected by the decoder.
+    pub data: Vec<u8>,
+    /// RTP timestamp of the frame.
+    pub timestamp: u32,
+    /// Whether all the packets of the frame have been received and could be depacketized.
+    pub complete: bool,
+}
+
+impl AsRef<[u8]> for Frame {
+    fn as_ref(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+/// Codec-specific part of a [`Depacketizer`].
+pub trait PayloadFormat {
+    /// Processes the payload of the next packet of the current frame. An error means that the
+    /// payload could not be used, e.g. because it is a fragment whose start has been lost, and
+    /// results in the frame being marked as incomplete.
+    fn depacketize(&mut self, payload: &[u8]) -> anyhow::Result<()>;
+    /// Signals that packets have been lost since the last call to `depacketize`, so any partially
+    /// received data must be dropped.
+    fn packets_lost(&mut self);
+    /// Returns the data of the current frame and whether it is complete from the point of view
+    /// of the payload format, and gets ready for the next frame.
+    fn finish_frame(&mut self) -> (Vec<u8>, bool);
+}
+
+/// A packet waiting in the reorder buffer.
+struct BufferedPacket {
+    marker: bool,
+    timestamp: u32,
+    payload: Vec<u8>,
+}
+
+/// The frame currently being rebuilt.
+struct CurrentFrame {
+    timestamp: u32,
+    complete: bool,
+}
+
+/// Rebuilds frames from RTP packets of a single stream.
+pub struct Depacketizer<F: PayloadFormat> {
+    format: F,
+    /// Packets waiting to be processed, by extended sequence number.
+    reorder_buffer: BTreeMap<u64, BufferedPacket>,
+    reorder_window: usize,
+    /// Highest extended sequence number received so far.
+    highest_seq: Option<u64>,
+    /// Extended sequence number of the next packet to process.
+    next_seq: Option<u64>,
+    current: Option<CurrentFrame>,
+    /// Whether packets have been lost since the last processed one.
+    loss: bool,
+    lost_packets: u64,
+    frames: VecDeque<Frame>,
+}
+
+impl<F: PayloadFormat> Depacketizer<F> {
+    pub fn new(format: F) -> Self {
+        Self {
+            format,
+            reorder_buffer: Default::default(),
+            reorder_window: DEFAULT_REORDER_WINDOW,
+            highest_seq: None,
+            next_seq: None,
+            current: None,
+            loss: false,
+            lost_packets: 0,
+            frames: Default::default(),
+        }
+    }
+
+    /// Sets the number of packets that can be received after a missing one before it is
+    /// considered lost. A larger value tolerates more reordering at the cost of latency when
+    /// packets are actually lost.
+    pub fn set_reorder_window(&mut self, packets: usize) {
+        self.reorder_window = packets;
+    }
+
+    /// Returns the payload format, e.g. to query stream information it has received in-band.
+    pub fn format(&self) -> &F {
+        &self.format
+    }
+
+    /// Returns the number of packets that have been lost so far.
+    pub fn lost_packets(&self) -> u64 {
+        self.lost_packets
+    }
+
+    /// Pushes a received RTP packet.
+    pub fn push(&mut self, packet: &[u8]) -> anyhow::Result<()> {
+        let packet = RtpPacket::parse(packet)?;
+
+        // Extend the sequence number to 64 bits to handle wraparound.
+        let seq = match self.highest_seq {
+            None => u64::from(packet.sequence_number),
+            Some(highest) => {
+                let delta = packet.sequence_number.wrapping_sub(highest as u16) as i16;
+                match highest.checked_add_signed(i64::from(delta)) {
+                    Some(seq) => seq,
+                    None => return Ok(()),
+                }
+            }
+        };
+
+        if self.next_seq.is_some_and(|next| seq < next) {
+            log::debug!("dropping late or duplicate RTP packet {}", seq);
+            return Ok(());
+        }
+
+        self.highest_seq = Some(self.highest_seq.map_or(seq, |h| h.max(seq)));
+        self.reorder_buffer.insert(
+            seq,
+            BufferedPacket {
+                marker: packet.marker,
+                timestamp: packet.timestamp,
+                payload: packet.payload.to_vec(),
+            },
+        );
+
+        self.process_buffered_packets(false);
+
+        Ok(())
+    }
+
+    /// Processes all the buffered packets regardless of any missing one, and emits the last
+    /// frame. To be called at the end of the stream.
+    pub fn flush(&mut self) {
+        self.process_buffered_packets(true);
+        self.finish_frame();
+    }
+
+    /// Returns the next rebuilt frame, if any.
+    pub fn next_frame(&mut self) -> Option<Frame> {
+        self.frames.pop_front()
+    }
+
+    fn process_buffered_packets(&mut self, flush: bool) {
+        while let Some(entry) = self.reorder_buffer.first_entry() {
+            let seq = *entry.key();
+            let next_seq = *self.next_seq.get_or_insert(seq);
+
+            if seq != next_seq {
+                if !flush && self.reorder_buffer.len() <= self.reorder_window {
+                    // Give the missing packet a chance to arrive.
+                    break;
+                }
+
+                log::warn!("lost {} RTP packets from {}", seq - next_seq, next_seq);
+                self.lost_packets += seq - next_seq;
+                self.loss = true;
+            }
+
+            let packet = self.reorder_buffer.remove(&seq).unwrap();
+            self.next_seq = Some(seq + 1);
+            self.process_packet(packet);
+        }
+    }
+
+    fn process_packet(&mut self, packet: BufferedPacket) {
+        if self.loss {
+            // The lost packets could have been part of the current frame.
+            if let Some(current) = &mut self.current {
+                current.complete = false;
+            }
+            self.format.packets_lost();
+        }
+
+        if self
+            .current
+            .as_ref()
+            .is_some_and(|c| c.timestamp != packet.timestamp)
+        {
+            self.finish_frame();
+        }
+
+        // If packets have been lost, they could also have been the beginning of the new frame.
+        let loss = std::mem::take(&mut self.loss);
+        let current = self.current.get_or_insert(CurrentFrame {
+            timestamp: packet.timestamp,
+            complete: !loss,
+        });
+
+        if let Err(e) = self.format.depacketize(&packet.payload) {
+            log::warn!("error while depacketizing RTP payload: {:#}", e);
+            current.complete = false;
+        }
+
+        if packet.marker {
+            self.finish_frame();
+        }
+    }
+
+    fn finish_frame(&mut self) {
+        let current = match self.current.take() {
+            Some(current) => current,
+            None => return,
+        };
+
+        let (data, complete) = self.format.finish_frame();
+        if data.is_empty() {
+            return;
+        }
+
+        self.frames.push_back(Frame {
+            data,
+            timestamp: current.timestamp,
+            complete: current.complete && complete,
+        });
+    }
+}
+
+/// A NAL unit of the H.264 or H.265 access unit being rebuilt.
+struct NalUnit {
+    data: Vec<u8>,
+    /// Decoding order number, if the packetization mode transmits it.
+    don: Option<u16>,
+}
+
+/// Splits a 16-bit size-prefixed unit at `offset` of `data`, returning the unit and the
+/// remaining data.
+fn split_u16_prefixed(data: &[u8], offset: usize) -> anyhow::Result<(&[u8], &[u8])> {
+    let size = data
+        .get(offset..offset + 2)
+        .map(|b| usize::from(u16::from_be_bytes([b[0], b[1]])))
+        .ok_or_else(|| anyhow!("truncated aggregation unit size"))?;
+    let start = offset + 2;
+
+    if data.len() < start + size {
+        return Err(anyhow!(
+            "aggregation unit of size {} exceeds the packet",
+            size
+        ));
+    }
+
+    Ok((&data[start..start + size], &data[start + size..]))
+}
+
+/// Turns the NAL units of an access unit into Annex B format, putting them in decoding order if
+/// they have a DON and inserting the out-of-band parameter sets if they have not been sent yet.
+fn finish_access_unit(
+    nalus: &mut Vec<NalUnit>,
+    parameter_sets: &mut Vec<Vec<u8>>,
+    complete: bool,
+) -> (Vec<u8>, bool) {
+    let mut nalus = std::mem::take(nalus);
+
+    if let Some(first_don) = nalus.first().and_then(|n| n.don) {
+        if nalus.iter().all(|n| n.don.is_some()) {
+            nalus.sort_by_key(|n| n.don.unwrap_or(0).wrapping_sub(first_don) as i16);
+        }
+    }
+
+    let mut data = nalus_to_annexb(&std::mem::take(parameter_sets));
+    for nalu in nalus {
+        data.extend_from_slice(&[0, 0, 0, 1]);
+        data.extend_from_slice(&nalu.data);
+    }
+
+    (data, complete)
+}
+
+/// Decodes a base64 string, as used in SDP parameters.
+fn decode_base64(s: &str) -> anyhow::Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(s.len() * 3 / 4);
+    let mut acc = 0u32;
+    let mut bits = 0;
+
+    for c in s.trim_end_matches('=').bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            _ => return Err(anyhow!("invalid base64 character {:?}", c as char)),
+        };
+
+        acc = (acc << 6) | u32::from(value);
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+    }
+
+    Ok(out)
+}
+
+/// Decodes a comma-separated list of base64-encoded NAL units, as found in the
+/// `sprop-parameter-sets` SDP parameter of H.264 or the `sprop-vps`/`sprop-sps`/`sprop-pps`
+/// parameters of H.265.
+pub fn parse_sprop_nalus(value: &str) -> anyhow::Result<Vec<Vec<u8>>> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(decode_base64)
+        .collect()
+}
+
+/// Returns `nalus` as an Annex B byte stream, which is the format expected by the parsers and
+/// decoders.
+pub fn nalus_to_annexb<T: AsRef<[u8]>>(nalus: &[T]) -> Vec<u8> {
+    let mut out = Vec::new();
+
+    for nalu in nalus {
+        out.extend_from_slice(&[0, 0, 0, 1]);
+        out.extend_from_slice(nalu.as_ref());
+    }
+
+    out
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Returns the RTP packets of a file in rtpdump format.
+    pub(crate) fn read_rtpdump(data: &[u8]) -> Vec<&[u8]> {
+        // Skip the text line and the binary file header.
+        let header_end = data.iter().position(|&b| b == b'\n').unwrap() + 1 + 16;
+        let mut data = &data[header_end..];
+        let mut packets = vec![];
+
+        while data.len() >= 8 {
+            let length = usize::from(u16::from_be_bytes([data[0], data[1]]));
+            let packet_len = usize::from(u16::from_be_bytes([data[2], data[3]]));
+            packets.push(&data[8..8 + packet_len]);
+            data = &data[length..];
+        }
+
+        packets
+    }
+
+    /// Builds a RTP packet with the given parameters.
+    pub(crate) fn rtp_packet(seq: u16, timestamp: u32, marker: bool, payload: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0x80, if marker { 0x80 | 96 } else { 96 }];
+        packet.extend_from_slice(&seq.to_be_bytes());
+        packet.extend_from_slice(&timestamp.to_be_bytes());
+        packet.extend_from_slice(&0x1234_5678u32.to_be_bytes());
+        packet.extend_from_slice(payload);
+        packet
+    }
+
+    /// Payload format that just concatenates the payloads of a frame.
+    #[derive(Default)]
+    struct RawPayload {
+        data: Vec<u8>,
+    }
+
+    impl PayloadFormat for RawPayload {
+        fn depacketize(&mut self, payload: &[u8]) -> anyhow::Result<()> {
+            self.data.extend_from_slice(payload);
+            Ok(())
+        }
+
+        fn packets_lost(&mut self) {}
+
+        fn finish_frame(&mut self) -> (Vec<u8>, bool) {
+            (std::mem::take(&mut self.data), true)
+        }
+    }
+
+    fn frames(depacketizer: &mut Depacketizer<RawPayload>) -> Vec<Frame> {
+        std::iter::from_fn(|| depacketizer.next_frame()).collect()
+    }
+
+    #[test]
+    fn parse_packet() {
+        // CSRC, header extension and padding.
+        let packet = [
+            0xb1, 0xe0, 0x12, 0x34, 0x00, 0x01, 0x00, 0x02, 0xde, 0xad, 0xbe, 0xef, 0x00, 0x00,
+            0x00, 0x01, 0xbe, 0xde, 0x00, 0x01, 0x10, 0x20, 0x30, 0x40, 0xaa, 0xbb, 0x00, 0x00,
+            0x03,
+        ];
+
+        let packet = RtpPacket::parse(&packet).unwrap();
+        assert!(packet.marker);
+        assert_eq!(packet.payload_type, 0x60);
+        assert_eq!(packet.sequence_number, 0x1234);
+        assert_eq!(packet.timestamp, 0x0001_0002);
+        assert_eq!(packet.ssrc, 0xdead_beef);
+        assert_eq!(packet.payload, &[0xaa, 0xbb]);
+
+        assert!(RtpPacket::parse(&[0x80; 11]).is_err());
+        assert!(RtpPacket::parse(&[0x40; 12]).is_err());
+    }
+
+    #[test]
+    fn reorder_and_wraparound() {
+        let mut depacketizer = Depacketizer::new(RawPayload::default());
+
+        let packets = [
+            rtp_packet(65534, 0, false, &[1]),
+            rtp_packet(0, 0, true, &[3]),
+            rtp_packet(65535, 0, false, &[2]),
+            rtp_packet(2, 10, true, &[5]),
+            rtp_packet(1, 10, false, &[4]),
+            // Duplicate.
+            rtp_packet(1, 10, false, &[4]),
+        ];
+        for packet in &packets {
+            depacketizer.push(packet).unwrap();
+        }
+        depacketizer.flush();
+
+        assert_eq!(
+            frames(&mut depacketizer),
+            vec![
+                Frame {
+                    data: vec![1, 2, 3],
+                    timestamp: 0,
+                    complete: true
+                },
+                Frame {
+                    data: vec![4, 5],
+                    timestamp: 10,
+                    complete: true
+                },
+            ]
+        );
+        assert_eq!(depacketizer.lost_packets(), 0);
+    }
+
+    #[test]
+    fn packet_loss() {
+        let mut depacketizer = Depacketizer::new(RawPayload::default());
+        depacketizer.set_reorder_window(2);
+
+        let packets = [
+            rtp_packet(10, 0, true, &[1]),
+            rtp_packet(11, 10, false, &[2]),
+            // 12 and 13 are lost, and were the end of the second frame and the beginning of the
+            // third one.
+            rtp_packet(14, 20, true, &[5]),
+            rtp_packet(15, 30, true, &[6]),
+            rtp_packet(16, 40, true, &[7]),
+        ];
+        for packet in &packets {
+            depacketizer.push(packet).unwrap();
+        }
+        depacketizer.flush();
+
+        assert_eq!(
+            frames(&mut depacketizer)
+                .into_iter()
+                .map(|f| (f.timestamp, f.complete))
+                .collect::<Vec<_>>(),
+            vec![(0, true), (10, false), (20, false), (30, true), (40, true)]
+        );
+        assert_eq!(depacketizer.lost_packets(), 2);
+
+        // Packet 12 arriving too late must be ignored.
+        depacketizer.push(&rtp_packet(12, 10, false, &[3])).unwrap();
+        depacketizer.flush();
+        assert_eq!(depacketizer.next_frame(), None);
+    }
+
+    #[test]
+    fn sprop() {
+        assert_eq!(
+            parse_sprop_nalus("Z0IACpZTBYmI,aMljiA==").unwrap(),
+            vec![
+                vec![0x67, 0x42, 0x00, 0x0a, 0x96, 0x53, 0x05, 0x89, 0x88],
+                vec![0x68, 0xc9, 0x63, 0x88]
+            ]
+        );
+        assert!(parse_sprop_nalus("Z0I*").is_err());
+        assert_eq!(
+            nalus_to_annexb(&[[0x67, 0x42], [0x68, 0xce]]),
+            vec![0, 0, 0, 1, 0x67, 0x42, 0, 0, 0, 1, 0x68, 0xce]
+        );
+    }
+}
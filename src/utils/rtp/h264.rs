@@ -0,0 +1,320 @@
+// Copyright 2023 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+//! H.264 RTP payload format (RFC 6184).
+//!
+//! Single NAL unit, STAP-A, MTAP16, MTAP24 and FU-A packets are supported, which covers the
+//! non-interleaved mode and the interleaved mode as long as FU-B packets are not used. In
+//! interleaved mode the NAL units of each access unit are put back into decoding order using the
+//! DON of the MTAP packets.
+
+use anyhow::anyhow;
+
+use crate::utils::rtp::finish_access_unit;
+use crate::utils::rtp::split_u16_prefixed;
+use crate::utils::rtp::Depacketizer;
+use crate::utils::rtp::NalUnit;
+use crate::utils::rtp::PayloadFormat;
+
+const NALU_TYPE_STAP_A: u8 = 24;
+const NALU_TYPE_MTAP16: u8 = 26;
+const NALU_TYPE_MTAP24: u8 = 27;
+const NALU_TYPE_FU_A: u8 = 28;
+
+/// A depacketizer producing Annex B H.264 access units.
+pub type H264Depacketizer = Depacketizer<H264Payload>;
+
+/// A fragmented NAL unit being reassembled from FU-A packets.
+struct Fragment {
+    data: Vec<u8>,
+}
+
+/// The H.264 payload format.
+#[derive(Default)]
+pub struct H264Payload {
+    /// Parameter sets to send before the first access unit.
+    parameter_sets: Vec<Vec<u8>>,
+    nalus: Vec<NalUnit>,
+    fragment: Option<Fragment>,
+    /// Whether the current access unit has lost a fragment.
+    incomplete: bool,
+}
+
+impl H264Payload {
+    /// Creates a new payload format, with the parameter sets given out-of-band (e.g. from the
+    /// `sprop-parameter-sets` SDP parameter) to insert before the first access unit.
+    pub fn new(parameter_sets: Vec<Vec<u8>>) -> Self {
+        Self {
+            parameter_sets,
+            ..Default::default()
+        }
+    }
+
+    fn push_nalu(&mut self, data: &[u8], don: Option<u16>) -> anyhow::Result<()> {
+        if data.is_empty() {
+            return Err(anyhow!("empty NAL unit"));
+        }
+
+        self.nalus.push(NalUnit {
+            data: data.to_vec(),
+            don,
+        });
+
+        Ok(())
+    }
+
+    fn depacketize_stap_a(&mut self, mut data: &[u8]) -> anyhow::Result<()> {
+        while !data.is_empty() {
+            let (nalu, rest) = split_u16_prefixed(data, 0)?;
+            self.push_nalu(nalu, None)?;
+            data = rest;
+        }
+
+        Ok(())
+    }
+
+    fn depacketize_mtap(&mut self, data: &[u8], ts_offset_len: usize) -> anyhow::Result<()> {
+        if data.len() < 2 {
+            return Err(anyhow!("truncated MTAP packet"));
+        }
+
+        let donb = u16::from_be_bytes([data[0], data[1]]);
+        let mut data = &data[2..];
+        while !data.is_empty() {
+            // Each NAL unit is preceded by its DOND and timestamp offset.
+            let (unit, rest) = split_u16_prefixed(data, 0)?;
+            let header_len = 1 + ts_offset_len;
+            if unit.len() <= header_len {
+                return Err(anyhow!("truncated MTAP NAL unit"));
+            }
+
+            let don = donb.wrapping_add(u16::from(unit[0]));
+            self.push_nalu(&unit[header_len..], Some(don))?;
+            data = rest;
+        }
+
+        Ok(())
+    }
+
+    fn depacketize_fu_a(&mut self, indicator: u8, data: &[u8]) -> anyhow::Result<()> {
+        let (&header, payload) = data
+            .split_first()
+            .ok_or_else(|| anyhow!("truncated FU-A packet"))?;
+        let start = header & 0x80 != 0;
+        let end = header & 0x40 != 0;
+
+        if start {
+            let res = match self.fragment.take() {
+                Some(_) => Err(anyhow!("FU-A start while previous NAL unit is incomplete")),
+                None => Ok(()),
+            };
+
+            let mut nalu = vec![(indicator & 0xe0) | (header & 0x1f)];
+            nalu.extend_from_slice(payload);
+            self.fragment = Some(Fragment { data: nalu });
+            res?;
+        } else {
+            match &mut self.fragment {
+                Some(fragment) => fragment.data.extend_from_slice(payload),
+                None => return Err(anyhow!("FU-A fragment without start")),
+            }
+        }
+
+        if end {
+            if let Some(fragment) = self.fragment.take() {
+                self.push_nalu(&fragment.data, None)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl PayloadFormat for H264Payload {
+    fn depacketize(&mut self, payload: &[u8]) -> anyhow::Result<()> {
+        let (&header, data) = payload
+            .split_first()
+            .ok_or_else(|| anyhow!("empty H.264 payload"))?;
+
+        let res = match header & 0x1f {
+            1..=23 => self.push_nalu(payload, None),
+            NALU_TYPE_STAP_A => self.depacketize_stap_a(data),
+            NALU_TYPE_MTAP16 => self.depacketize_mtap(data, 2),
+            NALU_TYPE_MTAP24 => self.depacketize_mtap(data, 3),
+            NALU_TYPE_FU_A => self.depacketize_fu_a(header, data),
+            type_ => Err(anyhow!("unsupported H.264 payload type {}", type_)),
+        };
+
+        if res.is_err() {
+            self.incomplete = true;
+        }
+
+        res
+    }
+
+    fn packets_lost(&mut self) {
+        self.fragment = None;
+    }
+
+    fn finish_frame(&mut self) -> (Vec<u8>, bool) {
+        finish_access_unit(
+            &mut self.nalus,
+            &mut self.parameter_sets,
+            self.fragment.take().is_none() && !std::mem::take(&mut self.incomplete),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::codec::h264::parser::Nalu;
+    use crate::codec::h264::parser::NaluHeader;
+    use crate::codec::h264::parser::Parser;
+    use crate::utils::annexb_nalus;
+    use crate::utils::rtp::parse_sprop_nalus;
+    use crate::utils::rtp::tests::read_rtpdump;
+    use crate::utils::rtp::tests::rtp_packet;
+    use crate::utils::rtp::Frame;
+
+    const STREAM: &[u8] = include_bytes!("../../codec/h264/test_data/64x64-I-P-B-P.h264");
+    const RTPDUMP: &[u8] = include_bytes!("test_data/64x64-I-P-B-P.h264.rtpdump");
+    const RTPDUMP_INTERLEAVED: &[u8] =
+        include_bytes!("test_data/64x64-I-P-B-P-interleaved.h264.rtpdump");
+
+    fn depacketize(packets: &[&[u8]], parameter_sets: Vec<Vec<u8>>) -> Vec<Frame> {
+        let mut depacketizer = H264Depacketizer::new(H264Payload::new(parameter_sets));
+        for packet in packets {
+            depacketizer.push(packet).unwrap();
+        }
+        depacketizer.flush();
+
+        std::iter::from_fn(|| depacketizer.next_frame()).collect()
+    }
+
+    fn check_stream(rtpdump: &[u8]) {
+        let frames = depacketize(&read_rtpdump(rtpdump), vec![]);
+
+        assert_eq!(frames.len(), 3);
+        for (i, frame) in frames.iter().enumerate() {
+            assert_eq!(frame.timestamp, 90000 + i as u32 * 3600);
+            assert!(frame.complete);
+        }
+
+        let data = frames.into_iter().map(|f| f.data).collect::<Vec<_>>();
+        assert_eq!(
+            annexb_nalus::<NaluHeader>(&data.concat()),
+            annexb_nalus::<NaluHeader>(STREAM)
+        );
+    }
+
+    #[test]
+    fn non_interleaved() {
+        check_stream(RTPDUMP);
+    }
+
+    #[test]
+    fn interleaved() {
+        check_stream(RTPDUMP_INTERLEAVED);
+    }
+
+    #[test]
+    fn reordered() {
+        let mut packets = read_rtpdump(RTPDUMP);
+        packets.swap(1, 3);
+        packets.swap(4, 5);
+        let frames = depacketize(&packets, vec![]);
+        assert!(frames.iter().all(|f| f.complete));
+        let data = frames.into_iter().map(|f| f.data).collect::<Vec<_>>();
+        assert_eq!(
+            annexb_nalus::<NaluHeader>(&data.concat()),
+            annexb_nalus::<NaluHeader>(STREAM)
+        );
+    }
+
+    #[test]
+    fn lost_fragment() {
+        let mut packets = read_rtpdump(RTPDUMP);
+        // Drop the second FU-A packet, which belongs to the SEI of the first access unit.
+        let index = packets
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| p[12] & 0x1f == NALU_TYPE_FU_A)
+            .nth(1)
+            .unwrap()
+            .0;
+        packets.remove(index);
+
+        let frames = depacketize(&packets, vec![]);
+        assert_eq!(frames.len(), 3);
+        assert!(!frames[0].complete);
+        assert!(frames[1].complete);
+        assert!(frames[2].complete);
+
+        // Only the fragmented SEI must have been dropped.
+        let types = annexb_nalus::<NaluHeader>(&frames[0].data)
+            .iter()
+            .map(|n| n[0] & 0x1f)
+            .collect::<Vec<_>>();
+        assert_eq!(types, vec![9, 7, 8, 5]);
+    }
+
+    #[test]
+    fn fu_a_without_start() {
+        let packets = [
+            rtp_packet(0, 0, false, &[0x7c, 0x45, 1, 2]),
+            rtp_packet(1, 0, true, &[0x7c, 0x05, 3, 4]),
+            rtp_packet(2, 10, false, &[0x7c, 0x81, 5]),
+            rtp_packet(3, 10, true, &[0x7c, 0x41, 6]),
+        ];
+        let packets = packets.iter().map(|p| p.as_slice()).collect::<Vec<_>>();
+
+        let frames = depacketize(&packets, vec![]);
+        // The first frame is entirely lost, the second one is complete.
+        assert_eq!(
+            frames,
+            vec![Frame {
+                data: vec![0, 0, 0, 1, 0x61, 5, 6],
+                timestamp: 10,
+                complete: true
+            }]
+        );
+    }
+
+    #[test]
+    fn sprop_parameter_sets() {
+        let stream_nalus = annexb_nalus::<NaluHeader>(STREAM);
+        let sps = stream_nalus.iter().find(|n| n[0] & 0x1f == 7).unwrap();
+        let pps = stream_nalus.iter().find(|n| n[0] & 0x1f == 8).unwrap();
+
+        // Remove the in-band parameter sets from the stream and provide them through SDP.
+        let sprop = "Z0LAFdkEJsBagwMDUoAAAAMAgAAAHkeLFyQ=,aMuMsg==";
+        let parameter_sets = parse_sprop_nalus(sprop).unwrap();
+        assert_eq!(parameter_sets, vec![*sps, *pps]);
+
+        let packets = [
+            rtp_packet(0, 0, false, &[0x09, 0xf0]),
+            rtp_packet(
+                1,
+                0,
+                true,
+                stream_nalus.iter().find(|n| n[0] & 0x1f == 5).unwrap(),
+            ),
+        ];
+        let packets = packets.iter().map(|p| p.as_slice()).collect::<Vec<_>>();
+        let frames = depacketize(&packets, parameter_sets);
+        assert_eq!(frames.len(), 1);
+
+        // The parser must be able to use the parameter sets of the first frame.
+        let mut parser = Parser::default();
+        let mut cursor = Cursor::new(frames[0].data.as_slice());
+        let nalu = Nalu::next(&mut cursor).unwrap();
+        let sps = parser.parse_sps(&nalu).unwrap();
+        assert_eq!(sps.pic_width_in_mbs_minus1, 3);
+        let nalu = Nalu::next(&mut cursor).unwrap();
+        parser.parse_pps(&nalu).unwrap();
+    }
+}
@@ -0,0 +1,377 @@
+// Copyright 2023 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+//! H.265 RTP payload format (RFC 7798).
+//!
+//! Single NAL unit, aggregation (AP), fragmentation (FU) and PACI packets are supported. When the
+//! stream uses a non-zero `sprop-max-don-diff`, the DONL and DOND fields are parsed and the NAL
+//! units of each access unit are put back into decoding order.
+
+use anyhow::anyhow;
+
+use crate::utils::rtp::finish_access_unit;
+use crate::utils::rtp::split_u16_prefixed;
+use crate::utils::rtp::Depacketizer;
+use crate::utils::rtp::NalUnit;
+use crate::utils::rtp::PayloadFormat;
+
+const NALU_TYPE_AP: u8 = 48;
+const NALU_TYPE_FU: u8 = 49;
+const NALU_TYPE_PACI: u8 = 50;
+
+/// A depacketizer producing Annex B H.265 access units.
+pub type H265Depacketizer = Depacketizer<H265Payload>;
+
+/// A fragmented NAL unit being reassembled from FU packets.
+struct Fragment {
+    data: Vec<u8>,
+    don: Option<u16>,
+}
+
+/// The H.265 payload format.
+#[derive(Default)]
+pub struct H265Payload {
+    /// Parameter sets to send before the first access unit.
+    parameter_sets: Vec<Vec<u8>>,
+    /// Whether packets carry DONL and DOND fields.
+    has_don: bool,
+    nalus: Vec<NalUnit>,
+    fragment: Option<Fragment>,
+    /// Whether the current access unit has lost a fragment.
+    incomplete: bool,
+}
+
+impl H265Payload {
+    /// Creates a new payload format, with the parameter sets given out-of-band (e.g. from the
+    /// `sprop-vps`, `sprop-sps` and `sprop-pps` SDP parameters) to insert before the first access
+    /// unit. `max_don_diff` is the value of the `sprop-max-don-diff` SDP parameter, or 0 if it is
+    /// absent.
+    pub fn new(parameter_sets: Vec<Vec<u8>>, max_don_diff: u32) -> Self {
+        Self {
+            parameter_sets,
+            has_don: max_don_diff > 0,
+            ..Default::default()
+        }
+    }
+
+    fn push_nalu(&mut self, data: Vec<u8>, don: Option<u16>) -> anyhow::Result<()> {
+        if data.len() < 2 {
+            return Err(anyhow!("NAL unit of {} bytes is too short", data.len()));
+        }
+
+        self.nalus.push(NalUnit { data, don });
+
+        Ok(())
+    }
+
+    /// Reads the DONL field at the start of `data` if the stream has one.
+    fn read_donl<'a>(&self, data: &'a [u8]) -> anyhow::Result<(Option<u16>, &'a [u8])> {
+        if !self.has_don {
+            return Ok((None, data));
+        }
+
+        match data {
+            [b0, b1, rest @ ..] => Ok((Some(u16::from_be_bytes([*b0, *b1])), rest)),
+            _ => Err(anyhow!("truncated DONL field")),
+        }
+    }
+
+    fn depacketize_single(&mut self, header: [u8; 2], data: &[u8]) -> anyhow::Result<()> {
+        let (don, data) = self.read_donl(data)?;
+        let mut nalu = header.to_vec();
+        nalu.extend_from_slice(data);
+        self.push_nalu(nalu, don)
+    }
+
+    fn depacketize_ap(&mut self, data: &[u8]) -> anyhow::Result<()> {
+        let (mut don, mut data) = self.read_donl(data)?;
+        let mut first = true;
+
+        while !data.is_empty() {
+            // All the units but the first one have a DOND field instead of a DONL.
+            let offset = if self.has_don && !first {
+                let dond = data[0];
+                don = don.map(|don| don.wrapping_add(u16::from(dond) + 1));
+                1
+            } else {
+                0
+            };
+
+            let (nalu, rest) = split_u16_prefixed(data, offset)?;
+            self.push_nalu(nalu.to_vec(), don)?;
+            data = rest;
+            first = false;
+        }
+
+        Ok(())
+    }
+
+    fn depacketize_fu(&mut self, header: [u8; 2], data: &[u8]) -> anyhow::Result<()> {
+        let (&fu_header, payload) = data
+            .split_first()
+            .ok_or_else(|| anyhow!("truncated FU packet"))?;
+        let start = fu_header & 0x80 != 0;
+        let end = fu_header & 0x40 != 0;
+
+        if start {
+            let res = match self.fragment.take() {
+                Some(_) => Err(anyhow!("FU start while previous NAL unit is incomplete")),
+                None => Ok(()),
+            };
+
+            let (don, payload) = self.read_donl(payload)?;
+            let mut nalu = vec![(header[0] & 0x81) | ((fu_header & 0x3f) << 1), header[1]];
+            nalu.extend_from_slice(payload);
+            self.fragment = Some(Fragment { data: nalu, don });
+            res?;
+        } else {
+            match &mut self.fragment {
+                Some(fragment) => fragment.data.extend_from_slice(payload),
+                None => return Err(anyhow!("FU fragment without start")),
+            }
+        }
+
+        if end {
+            if let Some(fragment) = self.fragment.take() {
+                self.push_nalu(fragment.data, fragment.don)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn depacketize_paci(&mut self, header: [u8; 2], data: &[u8]) -> anyhow::Result<()> {
+        if data.len() < 2 {
+            return Err(anyhow!("truncated PACI packet"));
+        }
+
+        let a = data[0] >> 7;
+        let ctype = (data[0] >> 1) & 0x3f;
+        let phs_size = usize::from(((data[0] & 0x1) << 4) | (data[1] >> 4));
+        if ctype == NALU_TYPE_PACI {
+            return Err(anyhow!("nested PACI packet"));
+        }
+
+        // The payload header extension structure is only meaningful to the sender, skip it.
+        let payload = data
+            .get(2 + phs_size..)
+            .ok_or_else(|| anyhow!("PACI header extension exceeds the packet"))?;
+        let header = [(a << 7) | (ctype << 1) | (header[0] & 0x1), header[1]];
+
+        self.depacketize_inner(header, payload)
+    }
+
+    fn depacketize_inner(&mut self, header: [u8; 2], data: &[u8]) -> anyhow::Result<()> {
+        match (header[0] >> 1) & 0x3f {
+            0..=47 => self.depacketize_single(header, data),
+            NALU_TYPE_AP => self.depacketize_ap(data),
+            NALU_TYPE_FU => self.depacketize_fu(header, data),
+            NALU_TYPE_PACI => self.depacketize_paci(header, data),
+            type_ => Err(anyhow!("unsupported H.265 payload type {}", type_)),
+        }
+    }
+}
+
+impl PayloadFormat for H265Payload {
+    fn depacketize(&mut self, payload: &[u8]) -> anyhow::Result<()> {
+        let res = match payload {
+            [b0, b1, data @ ..] => self.depacketize_inner([*b0, *b1], data),
+            _ => Err(anyhow!("H.265 payload too short")),
+        };
+
+        if res.is_err() {
+            self.incomplete = true;
+        }
+
+        res
+    }
+
+    fn packets_lost(&mut self) {
+        self.fragment = None;
+    }
+
+    fn finish_frame(&mut self) -> (Vec<u8>, bool) {
+        finish_access_unit(
+            &mut self.nalus,
+            &mut self.parameter_sets,
+            self.fragment.take().is_none() && !std::mem::take(&mut self.incomplete),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::codec::h265::parser::Nalu;
+    use crate::codec::h265::parser::NaluHeader;
+    use crate::codec::h265::parser::Parser;
+    use crate::utils::annexb_nalus;
+    use crate::utils::rtp::nalus_to_annexb;
+    use crate::utils::rtp::parse_sprop_nalus;
+    use crate::utils::rtp::tests::read_rtpdump;
+    use crate::utils::rtp::tests::rtp_packet;
+    use crate::utils::rtp::Frame;
+
+    const STREAM: &[u8] = include_bytes!("../../codec/h265/test_data/64x64-I-P-B-P.h265");
+    const RTPDUMP: &[u8] = include_bytes!("test_data/64x64-I-P-B-P.h265.rtpdump");
+
+    fn nalu_type(nalu: &[u8]) -> u8 {
+        (nalu[0] >> 1) & 0x3f
+    }
+
+    fn depacketize(packets: &[&[u8]], payload: H265Payload) -> Vec<Frame> {
+        let mut depacketizer = H265Depacketizer::new(payload);
+        for packet in packets {
+            depacketizer.push(packet).unwrap();
+        }
+        depacketizer.flush();
+
+        std::iter::from_fn(|| depacketizer.next_frame()).collect()
+    }
+
+    #[test]
+    fn stream() {
+        let frames = depacketize(&read_rtpdump(RTPDUMP), H265Payload::new(vec![], 0));
+
+        assert_eq!(frames.len(), 3);
+        for (i, frame) in frames.iter().enumerate() {
+            assert_eq!(frame.timestamp, 90000 + i as u32 * 3600);
+            assert!(frame.complete);
+        }
+
+        let data = frames.into_iter().map(|f| f.data).collect::<Vec<_>>();
+        assert_eq!(
+            annexb_nalus::<NaluHeader>(&data.concat()),
+            annexb_nalus::<NaluHeader>(STREAM)
+        );
+    }
+
+    #[test]
+    fn lost_fragment() {
+        let mut packets = read_rtpdump(RTPDUMP);
+        // Drop the second FU packet, which belongs to the SEI of the first access unit.
+        let index = packets
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| nalu_type(&p[12..]) == NALU_TYPE_FU)
+            .nth(1)
+            .unwrap()
+            .0;
+        packets.remove(index);
+
+        let frames = depacketize(&packets, H265Payload::new(vec![], 0));
+        assert_eq!(frames.len(), 3);
+        assert!(!frames[0].complete);
+        assert!(frames[1].complete);
+        assert!(frames[2].complete);
+
+        // Only the fragmented SEI must have been dropped.
+        let types = annexb_nalus::<NaluHeader>(&frames[0].data)
+            .iter()
+            .map(|n| nalu_type(n))
+            .collect::<Vec<_>>();
+        assert_eq!(types, vec![32, 33, 34, 20]);
+    }
+
+    #[test]
+    fn don() {
+        // Two NAL units sent in reverse decoding order: an AP with a DONL and a DOND, then a
+        // single NAL unit packet with a DONL.
+        let ap = [
+            (NALU_TYPE_AP << 1),
+            1,
+            0x00,
+            0x05, // DONL
+            0x00,
+            0x03,
+            0x02,
+            0x01,
+            0xaa, // NAL unit with DON 5
+            0x01, // DOND
+            0x00,
+            0x03,
+            0x02,
+            0x01,
+            0xbb, // NAL unit with DON 7
+        ];
+        let single = [0x02, 0x01, 0x00, 0x06, 0xcc];
+        let packets = [
+            rtp_packet(0, 0, false, &ap),
+            rtp_packet(1, 0, true, &single),
+        ];
+        let packets = packets.iter().map(|p| p.as_slice()).collect::<Vec<_>>();
+
+        let frames = depacketize(&packets, H265Payload::new(vec![], 2));
+        assert_eq!(frames.len(), 1);
+        assert_eq!(
+            annexb_nalus::<NaluHeader>(&frames[0].data),
+            vec![
+                vec![0x02, 0x01, 0xaa],
+                vec![0x02, 0x01, 0xcc],
+                vec![0x02, 0x01, 0xbb]
+            ]
+        );
+    }
+
+    #[test]
+    fn sprop_parameter_sets() {
+        let stream_nalus = annexb_nalus::<NaluHeader>(STREAM);
+        let parameter_sets = stream_nalus
+            .iter()
+            .filter(|n| (32..=34).contains(&nalu_type(n)))
+            .cloned()
+            .collect::<Vec<_>>();
+        assert_eq!(parameter_sets.len(), 3);
+
+        // Provide the parameter sets through the SDP parameters.
+        let sprop = parameter_sets
+            .iter()
+            .map(|ps| base64(ps))
+            .collect::<Vec<_>>()
+            .join(",");
+        let parameter_sets = parse_sprop_nalus(&sprop).unwrap();
+
+        let idr = stream_nalus.iter().find(|n| nalu_type(n) == 20).unwrap();
+        let packets = [rtp_packet(0, 0, true, idr)];
+        let packets = packets.iter().map(|p| p.as_slice()).collect::<Vec<_>>();
+        let frames = depacketize(&packets, H265Payload::new(parameter_sets.clone(), 0));
+        assert_eq!(frames.len(), 1);
+
+        let mut expected = nalus_to_annexb(&parameter_sets);
+        expected.extend(nalus_to_annexb(&[idr]));
+        assert_eq!(frames[0].data, expected);
+
+        // The parser must be able to use the parameter sets of the first frame.
+        let mut parser = Parser::default();
+        let mut cursor = Cursor::new(frames[0].data.as_slice());
+        parser.parse_vps(&Nalu::next(&mut cursor).unwrap()).unwrap();
+        let sps = parser.parse_sps(&Nalu::next(&mut cursor).unwrap()).unwrap();
+        assert_eq!(sps.pic_width_in_luma_samples(), 64);
+        parser.parse_pps(&Nalu::next(&mut cursor).unwrap()).unwrap();
+    }
+
+    /// Minimal base64 encoder, to build SDP parameters from the test stream.
+    fn base64(data: &[u8]) -> String {
+        const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+        let mut s = String::new();
+        for chunk in data.chunks(3) {
+            let n = chunk
+                .iter()
+                .enumerate()
+                .fold(0u32, |n, (i, &b)| n | (u32::from(b) << (16 - 8 * i)));
+            for i in 0..4 {
+                if i <= chunk.len() {
+                    s.push(char::from(ALPHABET[(n >> (18 - 6 * i)) as usize & 0x3f]));
+                } else {
+                    s.push('=');
+                }
+            }
+        }
+
+        s
+    }
+}
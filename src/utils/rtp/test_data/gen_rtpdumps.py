@@ -0,0 +1,269 @@
+#!/usr/bin/env python3
+# Copyright 2023 The ChromiumOS Authors
+# Use of this source code is governed by a BSD-style license that can be
+# found in the LICENSE file.
+
+"""Generates the rtpdump fixtures used by the RTP depacketizer tests.
+
+See README.md for a description of each file.
+"""
+
+import os
+import struct
+
+HERE = os.path.dirname(os.path.abspath(__file__))
+CODEC_DATA = os.path.join(HERE, "..", "..", "..", "codec")
+
+SSRC = 0x12345678
+PAYLOAD_TYPE = 96
+# Start close to the end of the sequence number space to exercise wraparound.
+FIRST_SEQUENCE_NUMBER = 65530
+
+
+def split_nalus(stream):
+    """Returns the NAL units of an Annex B stream, without their start code."""
+    nalus = []
+    i = stream.find(b"\x00\x00\x01")
+    while i >= 0:
+        start = i + 3
+        j = stream.find(b"\x00\x00\x01", start)
+        end = len(stream) if j < 0 else j
+        nalu = stream[start:end]
+        # Trailing zero of a 4-byte start code.
+        if j >= 0 and nalu.endswith(b"\x00"):
+            nalu = nalu[:-1]
+        nalus.append(nalu)
+        i = j
+    return nalus
+
+
+def split_access_units(nalus, is_vcl, starts_au):
+    aus = []
+    current = []
+    prev_vcl = False
+    for nalu in nalus:
+        vcl = is_vcl(nalu)
+        if current and prev_vcl and (not vcl or starts_au(nalu)):
+            aus.append(current)
+            current = []
+        current.append(nalu)
+        prev_vcl = vcl
+    aus.append(current)
+    return aus
+
+
+def h264_access_units():
+    with open(os.path.join(CODEC_DATA, "h264", "test_data", "64x64-I-P-B-P.h264"), "rb") as f:
+        nalus = split_nalus(f.read())
+    return split_access_units(
+        nalus, lambda n: (n[0] & 0x1F) in (1, 5), lambda n: n[1] & 0x80 != 0
+    )
+
+
+def h265_access_units():
+    with open(os.path.join(CODEC_DATA, "h265", "test_data", "64x64-I-P-B-P.h265"), "rb") as f:
+        nalus = split_nalus(f.read())
+    return split_access_units(
+        nalus, lambda n: (n[0] >> 1) & 0x3F < 32, lambda n: n[2] & 0x80 != 0
+    )
+
+
+class RtpDump:
+    def __init__(self):
+        self.seq = FIRST_SEQUENCE_NUMBER
+        self.packets = []
+
+    def add(self, payload, timestamp, marker):
+        header = struct.pack(
+            ">BBHII",
+            0x80,
+            (0x80 if marker else 0) | PAYLOAD_TYPE,
+            self.seq,
+            timestamp,
+            SSRC,
+        )
+        self.seq = (self.seq + 1) & 0xFFFF
+        self.packets.append(header + payload)
+
+    def write(self, name):
+        with open(os.path.join(HERE, name), "wb") as f:
+            f.write(b"#!rtpplay1.0 127.0.0.1/5004\n")
+            f.write(struct.pack(">IIIHH", 0, 0, 0x7F000001, 5004, 0))
+            for i, packet in enumerate(self.packets):
+                f.write(struct.pack(">HHI", len(packet) + 8, len(packet), i))
+                f.write(packet)
+
+
+def packetize(dump, aus, mtu, single, aggregate, fragment):
+    """Packetizes access units, aggregating small NAL units and fragmenting large ones."""
+    for i, au in enumerate(aus):
+        timestamp = 90000 + i * 3600
+        payloads = []
+        group = []
+        for nalu in au:
+            if len(nalu) > mtu:
+                if group:
+                    payloads.append(aggregate(group) if len(group) > 1 else single(group[0]))
+                    group = []
+                payloads.extend(fragment(nalu, mtu))
+            elif sum(len(n) + 2 for n in group + [nalu]) + 2 > mtu:
+                payloads.append(aggregate(group) if len(group) > 1 else single(group[0]))
+                group = [nalu]
+            else:
+                group.append(nalu)
+        if group:
+            payloads.append(aggregate(group) if len(group) > 1 else single(group[0]))
+
+        for j, payload in enumerate(payloads):
+            dump.add(payload, timestamp, j == len(payloads) - 1)
+
+
+def h264_stap_a(nalus):
+    nri = max(n[0] & 0x60 for n in nalus)
+    return bytes([nri | 24]) + b"".join(struct.pack(">H", len(n)) + n for n in nalus)
+
+
+def h264_fu_a(nalu, mtu):
+    indicator = (nalu[0] & 0xE0) | 28
+    data = nalu[1:]
+    chunks = [data[i : i + mtu] for i in range(0, len(data), mtu)]
+    for i, chunk in enumerate(chunks):
+        header = (0x80 if i == 0 else 0) | (0x40 if i == len(chunks) - 1 else 0) | (nalu[0] & 0x1F)
+        yield bytes([indicator, header]) + chunk
+
+
+def h264_mtap(aus, dump):
+    """Sends each access unit as a single MTAP packet, with its NAL units in reverse order."""
+    don = 0
+    for i, au in enumerate(aus):
+        timestamp = 90000 + i * 3600
+        mtap24 = i % 2 == 1
+        nri = max(n[0] & 0x60 for n in au)
+        payload = bytes([nri | (27 if mtap24 else 26)]) + struct.pack(">H", don)
+        for dond, nalu in reversed(list(enumerate(au))):
+            payload += struct.pack(">HB", len(nalu) + (4 if mtap24 else 3), dond)
+            payload += struct.pack(">I", 0)[1:] if mtap24 else struct.pack(">H", 0)
+            payload += nalu
+        dump.add(payload, timestamp, True)
+        don += len(au)
+
+
+def h265_ap(nalus):
+    layer_id = 0
+    tid = min(n[1] & 0x7 for n in nalus)
+    f = max(n[0] & 0x80 for n in nalus)
+    header = struct.pack(">BB", f | (48 << 1) | (layer_id >> 5), ((layer_id & 0x1F) << 3) | tid)
+    return header + b"".join(struct.pack(">H", len(n)) + n for n in nalus)
+
+
+def h265_fu(nalu, mtu):
+    header = bytes([(nalu[0] & 0x81) | (49 << 1), nalu[1]])
+    nal_type = (nalu[0] >> 1) & 0x3F
+    data = nalu[2:]
+    chunks = [data[i : i + mtu] for i in range(0, len(data), mtu)]
+    for i, chunk in enumerate(chunks):
+        fu_header = (0x80 if i == 0 else 0) | (0x40 if i == len(chunks) - 1 else 0) | nal_type
+        yield header + bytes([fu_header]) + chunk
+
+
+def h265_paci(nalu):
+    """Wraps a single NAL unit packet into a PACI packet with a 2-byte PHES."""
+    nal_type = (nalu[0] >> 1) & 0x3F
+    header = bytes([(50 << 1) | (nalu[0] & 0x01), nalu[1]])
+    # A = F bit, cType = type, PHSsize = 2, F0 = F1 = F2 = Y = 0.
+    paci = struct.pack(">H", ((nalu[0] >> 7) << 15) | (nal_type << 9) | (2 << 4))
+    return header + paci + b"\xaa\xbb" + nalu[2:]
+
+
+def ivf_frames(path, count):
+    """Returns the first `count` frames of an IVF file."""
+    with open(path, "rb") as f:
+        data = f.read()
+    pos = struct.unpack("<H", data[6:8])[0]
+    frames = []
+    while pos < len(data) and len(frames) < count:
+        size = struct.unpack("<I", data[pos : pos + 4])[0]
+        frames.append(data[pos + 12 : pos + 12 + size])
+        pos += 12 + size
+    return frames
+
+
+def vp8_packetize(dump, frames, mtu):
+    # 15-bit PictureID and TL0PICIDX both start close to their maximum to exercise wraparound.
+    for i, frame in enumerate(frames):
+        timestamp = 90000 + i * 3600
+        picture_id = (0x7FFE + i) & 0x7FFF
+        tl0picidx = (0xFF + i) & 0xFF
+        chunks = [frame[j : j + mtu] for j in range(0, len(frame), mtu)]
+        for j, chunk in enumerate(chunks):
+            # X=1, S on the first packet, PID=0.
+            descriptor = bytes([0x80 | (0x10 if j == 0 else 0)])
+            # I=1, L=1, T=1, then the PictureID, TL0PICIDX, and TID=0 with Y=1.
+            descriptor += bytes([0xE0]) + struct.pack(">H", 0x8000 | picture_id)
+            descriptor += bytes([tl0picidx, 0x20])
+            dump.add(descriptor + chunk, timestamp, j == len(chunks) - 1)
+
+
+def vp9_scalability_structure(width, height):
+    # N_S=0, Y=1, G=1, one picture group entry with TID=0, U=0, R=1 and P_DIFF=1.
+    return bytes([0x18]) + struct.pack(">HH", width, height) + bytes([1, 0x04, 1])
+
+
+def vp9_packetize(dump, frames, mtu):
+    for i, frame in enumerate(frames):
+        timestamp = 90000 + i * 3600
+        picture_id = (0x7FFE + i) & 0x7FFF
+        tl0picidx = (0xFF + i) & 0xFF
+        key_frame = frame[0] & 0x0C == 0
+        chunks = [frame[j : j + mtu] for j in range(0, len(frame), mtu)]
+        for j, chunk in enumerate(chunks):
+            first = j == 0
+            last = j == len(chunks) - 1
+            # I=1, P for inter frames, L=1, F=0, B/E on the first/last packet, V on the first
+            # packet of key frames.
+            flags = 0x80 | 0x20
+            flags |= 0 if key_frame else 0x40
+            flags |= 0x08 if first else 0
+            flags |= 0x04 if last else 0
+            flags |= 0x02 if first and key_frame else 0
+            descriptor = bytes([flags]) + struct.pack(">H", 0x8000 | picture_id)
+            # TID=0, U=0, SID=0, D=0, then TL0PICIDX.
+            descriptor += bytes([0, tl0picidx])
+            if first and key_frame:
+                descriptor += vp9_scalability_structure(320, 240)
+            dump.add(descriptor + chunk, timestamp, last)
+
+
+def main():
+    dump = RtpDump()
+    packetize(dump, h264_access_units(), 400, lambda n: n, h264_stap_a, h264_fu_a)
+    dump.write("64x64-I-P-B-P.h264.rtpdump")
+
+    dump = RtpDump()
+    h264_mtap(h264_access_units(), dump)
+    dump.write("64x64-I-P-B-P-interleaved.h264.rtpdump")
+
+    # Send the last NAL unit of the stream through PACI.
+    aus = h265_access_units()
+    last = aus[-1][-1]
+
+    def single(nalu):
+        return h265_paci(nalu) if nalu is last else nalu
+
+    dump = RtpDump()
+    packetize(dump, aus, 400, single, h265_ap, h265_fu)
+    dump.write("64x64-I-P-B-P.h265.rtpdump")
+
+    dump = RtpDump()
+    frames = ivf_frames(os.path.join(CODEC_DATA, "vp8", "test_data", "test-25fps.vp8"), 3)
+    vp8_packetize(dump, frames, 1200)
+    dump.write("test-25fps-3frames.vp8.rtpdump")
+
+    dump = RtpDump()
+    frames = ivf_frames(os.path.join(CODEC_DATA, "vp9", "test_data", "test-25fps.vp9"), 3)
+    vp9_packetize(dump, frames, 1200)
+    dump.write("test-25fps-3frames.vp9.rtpdump")
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,341 @@
+// Copyright 2023 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+//! VP8 RTP payload format (RFC 7741).
+//!
+//! Frames are rebuilt by concatenating the payloads of their packets. A frame is reported as
+//! incomplete if its first packet or any of its partitions is missing, or if the PictureID shows
+//! that a previous frame has been lost. Since the following frames may reference an incomplete
+//! frame, they are also reported as incomplete until the next key frame, unless the lost frame was
+//! marked as non-reference: the client should request a key frame from the sender when that
+//! happens.
+
+use anyhow::anyhow;
+
+use crate::utils::rtp::Depacketizer;
+use crate::utils::rtp::PayloadFormat;
+
+/// A depacketizer producing VP8 frames.
+pub type Vp8Depacketizer = Depacketizer<Vp8Payload>;
+
+/// The VP8 payload descriptor (RFC 7741 section 4.2).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Vp8PayloadDescriptor {
+    /// Whether the frame can be discarded without affecting other frames.
+    pub non_reference: bool,
+    /// Whether this packet starts a partition.
+    pub start_of_partition: bool,
+    /// Index of the partition the packet belongs to.
+    pub partition_index: u8,
+    /// PictureID of the frame, 7 or 15 bits.
+    pub picture_id: Option<u16>,
+    /// Running index of the base temporal layer frames.
+    pub tl0_pic_idx: Option<u8>,
+    /// Temporal layer of the frame.
+    pub tid: Option<u8>,
+    /// Whether the frame only depends on the base layer.
+    pub layer_sync: bool,
+    /// Running index of the key frames.
+    pub key_idx: Option<u8>,
+}
+
+impl Vp8PayloadDescriptor {
+    /// Parses the payload descriptor at the start of `payload`, and returns it along with the
+    /// VP8 data that follows it.
+    pub fn parse(payload: &[u8]) -> anyhow::Result<(Self, &[u8])> {
+        let truncated = || anyhow!("truncated VP8 payload descriptor");
+        let mut bytes = payload.iter().copied();
+        let mut next = || bytes.next().ok_or_else(truncated);
+
+        let b0 = next()?;
+        let mut descriptor = Self {
+            non_reference: b0 & 0x20 != 0,
+            start_of_partition: b0 & 0x10 != 0,
+            partition_index: b0 & 0x07,
+            ..Default::default()
+        };
+
+        // Extended control bits.
+        if b0 & 0x80 != 0 {
+            let x = next()?;
+
+            if x & 0x80 != 0 {
+                let p0 = next()?;
+                descriptor.picture_id = Some(if p0 & 0x80 != 0 {
+                    (u16::from(p0 & 0x7f) << 8) | u16::from(next()?)
+                } else {
+                    u16::from(p0)
+                });
+            }
+
+            if x & 0x40 != 0 {
+                descriptor.tl0_pic_idx = Some(next()?);
+            }
+
+            if x & 0x30 != 0 {
+                let b = next()?;
+                if x & 0x20 != 0 {
+                    descriptor.tid = Some(b >> 6);
+                    descriptor.layer_sync = b & 0x20 != 0;
+                }
+                if x & 0x10 != 0 {
+                    descriptor.key_idx = Some(b & 0x1f);
+                }
+            }
+        }
+
+        let len = payload.len() - bytes.len();
+        Ok((descriptor, &payload[len..]))
+    }
+
+    /// Whether this packet is the first one of a frame.
+    pub fn is_frame_start(&self) -> bool {
+        self.start_of_partition && self.partition_index == 0
+    }
+}
+
+/// The VP8 payload format.
+#[derive(Default)]
+pub struct Vp8Payload {
+    data: Vec<u8>,
+    /// Whether the first packet of the current frame has been received.
+    started: bool,
+    /// Whether the current frame is known to miss data.
+    incomplete: bool,
+    /// PictureID of the current frame.
+    picture_id: Option<u16>,
+    /// PictureID of the last frame.
+    last_picture_id: Option<u16>,
+    /// Whether the current frame is not used as reference by other frames.
+    non_reference: bool,
+    /// Whether a reference frame has been lost since the last key frame.
+    needs_key_frame: bool,
+}
+
+impl Vp8Payload {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Returns whether the PictureID of the current frame directly follows the last one.
+    fn picture_id_is_contiguous(&self) -> bool {
+        match (self.last_picture_id, self.picture_id) {
+            // The PictureID wraps around at the size of the field in use, 7 or 15 bits.
+            (Some(last), Some(current)) => {
+                current == (last + 1) & 0x7f || current == (last + 1) & 0x7fff
+            }
+            _ => true,
+        }
+    }
+
+    fn depacketize_packet(&mut self, payload: &[u8]) -> anyhow::Result<()> {
+        let (descriptor, data) = Vp8PayloadDescriptor::parse(payload)?;
+
+        if descriptor.is_frame_start() {
+            if !self.data.is_empty() {
+                self.data.clear();
+                return Err(anyhow!("VP8 frame start received in the middle of a frame"));
+            }
+
+            self.started = true;
+            self.picture_id = descriptor.picture_id;
+            self.non_reference = descriptor.non_reference;
+        } else if !self.started {
+            return Err(anyhow!(
+                "VP8 packet received without the start of its frame"
+            ));
+        }
+
+        self.data.extend_from_slice(data);
+
+        Ok(())
+    }
+}
+
+/// Returns whether `frame` contains at least its frame header and complete first partition.
+fn has_first_partition(frame: &[u8]) -> bool {
+    let Some(tag) = frame.get(0..3) else {
+        return false;
+    };
+
+    let key_frame = tag[0] & 0x1 == 0;
+    let first_part_size =
+        (u32::from(tag[0]) | u32::from(tag[1]) << 8 | u32::from(tag[2]) << 16) >> 5;
+    let header_size = if key_frame { 10 } else { 3 };
+
+    frame.len() >= header_size + first_part_size as usize
+}
+
+impl PayloadFormat for Vp8Payload {
+    fn depacketize(&mut self, payload: &[u8]) -> anyhow::Result<()> {
+        let res = self.depacketize_packet(payload);
+
+        if res.is_err() {
+            self.incomplete = true;
+        }
+
+        res
+    }
+
+    fn packets_lost(&mut self) {
+        // Partitions after the lost packets cannot be placed correctly, drop them.
+        self.started = false;
+        self.incomplete = true;
+    }
+
+    fn finish_frame(&mut self) -> (Vec<u8>, bool) {
+        let data = std::mem::take(&mut self.data);
+        let key_frame = data.first().is_some_and(|b| b & 0x1 == 0);
+
+        let mut complete = !std::mem::take(&mut self.incomplete);
+        if !has_first_partition(&data) {
+            log::debug!("VP8 frame is missing its first partition");
+            complete = false;
+        }
+        if !key_frame && !self.picture_id_is_contiguous() {
+            log::debug!(
+                "VP8 PictureID jumped from {:?} to {:?}",
+                self.last_picture_id,
+                self.picture_id
+            );
+            complete = false;
+        }
+
+        if !complete && !self.non_reference {
+            self.needs_key_frame = true;
+        } else if complete && key_frame {
+            self.needs_key_frame = false;
+        }
+        if self.needs_key_frame {
+            complete = false;
+        }
+
+        if !data.is_empty() {
+            self.last_picture_id = self.picture_id.take().or(self.last_picture_id);
+        }
+        self.started = false;
+        self.non_reference = false;
+
+        (data, complete)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::rtp::tests::read_rtpdump;
+    use crate::utils::rtp::tests::rtp_packet;
+    use crate::utils::rtp::Frame;
+    use crate::utils::IvfIterator;
+
+    const STREAM: &[u8] = include_bytes!("../../codec/vp8/test_data/test-25fps.vp8");
+    const RTPDUMP: &[u8] = include_bytes!("test_data/test-25fps-3frames.vp8.rtpdump");
+
+    fn depacketize(packets: &[&[u8]]) -> Vec<Frame> {
+        let mut depacketizer = Vp8Depacketizer::new(Vp8Payload::new());
+        for packet in packets {
+            depacketizer.push(packet).unwrap();
+        }
+        depacketizer.flush();
+
+        std::iter::from_fn(|| depacketizer.next_frame()).collect()
+    }
+
+    #[test]
+    fn parse_descriptor() {
+        // Minimal descriptor.
+        let (descriptor, data) = Vp8PayloadDescriptor::parse(&[0x10, 0xaa]).unwrap();
+        assert!(descriptor.is_frame_start());
+        assert_eq!(descriptor.picture_id, None);
+        assert_eq!(data, [0xaa]);
+
+        // All the optional fields, with a 15-bit PictureID.
+        let (descriptor, data) =
+            Vp8PayloadDescriptor::parse(&[0xa3, 0xf0, 0x81, 0x23, 0x45, 0x65, 0xaa]).unwrap();
+        assert_eq!(
+            descriptor,
+            Vp8PayloadDescriptor {
+                non_reference: true,
+                start_of_partition: false,
+                partition_index: 3,
+                picture_id: Some(0x123),
+                tl0_pic_idx: Some(0x45),
+                tid: Some(1),
+                layer_sync: true,
+                key_idx: Some(5),
+            }
+        );
+        assert_eq!(data, [0xaa]);
+
+        // 7-bit PictureID.
+        let (descriptor, _) = Vp8PayloadDescriptor::parse(&[0x90, 0x80, 0x12]).unwrap();
+        assert_eq!(descriptor.picture_id, Some(0x12));
+
+        assert!(Vp8PayloadDescriptor::parse(&[0x90, 0x80, 0x81]).is_err());
+    }
+
+    #[test]
+    fn stream() {
+        let frames = depacketize(&read_rtpdump(RTPDUMP));
+
+        assert_eq!(frames.len(), 3);
+        for (i, (frame, expected)) in frames.iter().zip(IvfIterator::new(STREAM)).enumerate() {
+            assert_eq!(frame.timestamp, 90000 + i as u32 * 3600);
+            assert!(frame.complete);
+            assert_eq!(frame.data, expected);
+        }
+    }
+
+    #[test]
+    fn lost_packet() {
+        // Drop the third packet of the key frame, which is in the middle of a partition.
+        let mut packets = read_rtpdump(RTPDUMP);
+        packets.remove(2);
+
+        // The following frames reference the key frame, so they cannot be decoded either.
+        let frames = depacketize(&packets);
+        assert_eq!(frames.len(), 3);
+        assert!(frames.iter().all(|f| !f.complete));
+    }
+
+    #[test]
+    fn lost_frame_start() {
+        let key_frame = IvfIterator::new(STREAM).next().unwrap();
+        let packets = [
+            // Continuation of a frame whose start has been lost before the first packet we
+            // received, so the depacketizer cannot see any sequence number gap.
+            rtp_packet(0, 0, true, &[0x80, 0x80, 0x01, 0xaa]),
+            // Next frame, which is a key frame and thus can be decoded.
+            rtp_packet(1, 10, true, &[&[0x90, 0x80, 0x02][..], key_frame].concat()),
+        ];
+        let packets = packets.iter().map(|p| p.as_slice()).collect::<Vec<_>>();
+
+        let frames = depacketize(&packets);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].data, key_frame);
+        assert!(frames[0].complete);
+    }
+
+    #[test]
+    fn picture_id_gap() {
+        let frame = IvfIterator::new(STREAM).nth(1).unwrap();
+        let packets = [
+            rtp_packet(0, 0, true, &[&[0x90, 0x80, 0x7f][..], frame].concat()),
+            // The 7-bit PictureID wraps around.
+            rtp_packet(1, 10, true, &[&[0x90, 0x80, 0x00][..], frame].concat()),
+            // A frame is missing.
+            rtp_packet(2, 20, true, &[&[0x90, 0x80, 0x02][..], frame].concat()),
+            // Non-reference frames do not prevent the following frames from being decoded, but
+            // the reference chain is already broken.
+            rtp_packet(3, 30, true, &[&[0xb0, 0x80, 0x03][..], frame].concat()),
+        ];
+        let packets = packets.iter().map(|p| p.as_slice()).collect::<Vec<_>>();
+
+        let frames = depacketize(&packets);
+        assert_eq!(
+            frames.iter().map(|f| f.complete).collect::<Vec<_>>(),
+            vec![true, true, false, false]
+        );
+    }
+}
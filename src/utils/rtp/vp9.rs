@@ -0,0 +1,504 @@
+// Copyright 2023 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+//! VP9 RTP payload format (RFC 9628).
+//!
+//! The layer frames of a picture are rebuilt from their packets, and packed into a superframe
+//! when the picture has more than one spatial layer so that the decoder receives all of them at
+//! once. A spatial layer that has lost packets is dropped along with the layers that depend on
+//! it, and the picture is reported as incomplete. So are all the following pictures until the
+//! next key picture, since they may reference the missing layers: the client should request a key
+//! frame from the sender when that happens.
+
+use anyhow::anyhow;
+
+use crate::codec::vp9::synthesizer::pack_superframe;
+use crate::utils::rtp::Depacketizer;
+use crate::utils::rtp::PayloadFormat;
+
+/// A depacketizer producing VP9 frames.
+pub type Vp9Depacketizer = Depacketizer<Vp9Payload>;
+
+/// Maximum number of spatial layers of a VP9 stream.
+const MAX_SPATIAL_LAYERS: usize = 8;
+
+/// An entry of the picture group description of a scalability structure.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Vp9PictureGroupEntry {
+    /// Temporal layer of the picture.
+    pub tid: u8,
+    /// Whether the picture is a switching up point.
+    pub switching_up_point: bool,
+    /// Differences between the PictureID of the picture and those of its references.
+    pub p_diffs: Vec<u8>,
+}
+
+/// The scalability structure of a VP9 stream (RFC 9628 section 4.2.1).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Vp9ScalabilityStructure {
+    /// Number of spatial layers.
+    pub num_spatial_layers: usize,
+    /// Resolution of each spatial layer, if signaled.
+    pub resolutions: Vec<(u16, u16)>,
+    /// Description of the pictures of a group of pictures, if signaled.
+    pub picture_group: Option<Vec<Vp9PictureGroupEntry>>,
+}
+
+/// The VP9 payload descriptor (RFC 9628 section 4.2).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Vp9PayloadDescriptor {
+    /// Whether the picture is predicted from previous pictures.
+    pub inter_picture_predicted: bool,
+    /// Whether the stream uses the flexible mode.
+    pub flexible_mode: bool,
+    /// Whether this packet starts a layer frame.
+    pub start_of_frame: bool,
+    /// Whether this packet ends a layer frame.
+    pub end_of_frame: bool,
+    /// Whether the frame is not used as reference by upper spatial layers.
+    pub not_reference_for_upper_layers: bool,
+    /// PictureID of the picture, 7 or 15 bits.
+    pub picture_id: Option<u16>,
+    /// Temporal layer of the frame.
+    pub tid: u8,
+    /// Whether the frame is a switching up point.
+    pub switching_up_point: bool,
+    /// Spatial layer of the frame.
+    pub sid: u8,
+    /// Whether the frame depends on the frame of the spatial layer below.
+    pub inter_layer_dependency: bool,
+    /// Running index of the base temporal layer pictures, in non-flexible mode.
+    pub tl0_pic_idx: Option<u8>,
+    /// Differences between the PictureID and those of the references, in flexible mode.
+    pub p_diffs: Vec<u8>,
+    /// Scalability structure, usually sent with key frames.
+    pub scalability_structure: Option<Vp9ScalabilityStructure>,
+}
+
+impl Vp9PayloadDescriptor {
+    /// Parses the payload descriptor at the start of `payload`, and returns it along with the
+    /// VP9 data that follows it.
+    pub fn parse(payload: &[u8]) -> anyhow::Result<(Self, &[u8])> {
+        let truncated = || anyhow!("truncated VP9 payload descriptor");
+        let mut bytes = payload.iter().copied();
+        let mut next = || bytes.next().ok_or_else(truncated);
+
+        let b0 = next()?;
+        let mut descriptor = Self {
+            inter_picture_predicted: b0 & 0x40 != 0,
+            flexible_mode: b0 & 0x10 != 0,
+            start_of_frame: b0 & 0x08 != 0,
+            end_of_frame: b0 & 0x04 != 0,
+            not_reference_for_upper_layers: b0 & 0x01 != 0,
+            ..Default::default()
+        };
+
+        if b0 & 0x80 != 0 {
+            let p0 = next()?;
+            descriptor.picture_id = Some(if p0 & 0x80 != 0 {
+                (u16::from(p0 & 0x7f) << 8) | u16::from(next()?)
+            } else {
+                u16::from(p0)
+            });
+        }
+
+        // Layer indices.
+        if b0 & 0x20 != 0 {
+            let l = next()?;
+            descriptor.tid = l >> 5;
+            descriptor.switching_up_point = l & 0x10 != 0;
+            descriptor.sid = (l >> 1) & 0x7;
+            descriptor.inter_layer_dependency = l & 0x1 != 0;
+
+            if !descriptor.flexible_mode {
+                descriptor.tl0_pic_idx = Some(next()?);
+            }
+        }
+
+        // Reference indices.
+        if descriptor.flexible_mode && descriptor.inter_picture_predicted {
+            loop {
+                let p = next()?;
+                descriptor.p_diffs.push(p >> 1);
+                if p & 0x1 == 0 {
+                    break;
+                }
+                if descriptor.p_diffs.len() == 3 {
+                    return Err(anyhow!("more than 3 reference indices in VP9 descriptor"));
+                }
+            }
+        }
+
+        if b0 & 0x02 != 0 {
+            let v = next()?;
+            let mut ss = Vp9ScalabilityStructure {
+                num_spatial_layers: usize::from(v >> 5) + 1,
+                ..Default::default()
+            };
+
+            if v & 0x10 != 0 {
+                for _ in 0..ss.num_spatial_layers {
+                    let width = u16::from_be_bytes([next()?, next()?]);
+                    let height = u16::from_be_bytes([next()?, next()?]);
+                    ss.resolutions.push((width, height));
+                }
+            }
+
+            if v & 0x08 != 0 {
+                let num_pictures = next()?;
+                let mut picture_group = Vec::with_capacity(usize::from(num_pictures));
+                for _ in 0..num_pictures {
+                    let g = next()?;
+                    let mut entry = Vp9PictureGroupEntry {
+                        tid: g >> 5,
+                        switching_up_point: g & 0x10 != 0,
+                        p_diffs: vec![],
+                    };
+                    for _ in 0..(g >> 2) & 0x3 {
+                        entry.p_diffs.push(next()?);
+                    }
+                    picture_group.push(entry);
+                }
+                ss.picture_group = Some(picture_group);
+            }
+
+            descriptor.scalability_structure = Some(ss);
+        }
+
+        let len = payload.len() - bytes.len();
+        Ok((descriptor, &payload[len..]))
+    }
+}
+
+/// A layer frame of the current picture.
+struct LayerFrame {
+    sid: u8,
+    inter_layer_dependency: bool,
+    data: Vec<u8>,
+    /// Whether the end of the layer frame has been received.
+    complete: bool,
+}
+
+/// The VP9 payload format.
+#[derive(Default)]
+pub struct Vp9Payload {
+    layers: Vec<LayerFrame>,
+    /// Whether the current picture is known to miss data.
+    incomplete: bool,
+    /// Whether the current picture is predicted from previous pictures, as signaled by the
+    /// descriptor of its base layer.
+    inter_picture_predicted: bool,
+    /// Whether a layer frame has been lost since the last key picture.
+    needs_key_picture: bool,
+    /// Last scalability structure received.
+    scalability_structure: Option<Vp9ScalabilityStructure>,
+}
+
+impl Vp9Payload {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Returns the last scalability structure received, if any.
+    pub fn scalability_structure(&self) -> Option<&Vp9ScalabilityStructure> {
+        self.scalability_structure.as_ref()
+    }
+
+    fn depacketize_packet(&mut self, payload: &[u8]) -> anyhow::Result<()> {
+        let (descriptor, data) = Vp9PayloadDescriptor::parse(payload)?;
+
+        if let Some(ss) = descriptor.scalability_structure {
+            self.scalability_structure = Some(ss);
+        }
+
+        if descriptor.start_of_frame {
+            if self.layers.last().is_some_and(|l| !l.complete) {
+                self.layers.pop();
+                self.incomplete = true;
+            }
+
+            if descriptor.sid == 0 {
+                self.inter_picture_predicted = descriptor.inter_picture_predicted;
+            }
+
+            self.layers.push(LayerFrame {
+                sid: descriptor.sid,
+                inter_layer_dependency: descriptor.inter_layer_dependency,
+                data: vec![],
+                complete: false,
+            });
+        }
+
+        let layer = match self.layers.last_mut() {
+            Some(layer) if !layer.complete && layer.sid == descriptor.sid => layer,
+            _ => {
+                return Err(anyhow!(
+                    "VP9 packet received without the start of its frame"
+                ))
+            }
+        };
+
+        layer.data.extend_from_slice(data);
+        layer.complete = descriptor.end_of_frame;
+
+        Ok(())
+    }
+}
+
+/// Splits `frame` into the frames it contains if it is a superframe (VP9 Annex B).
+fn split_superframe(frame: &[u8]) -> Vec<&[u8]> {
+    let Some(&marker) = frame.last() else {
+        return vec![frame];
+    };
+
+    if marker & 0xe0 != 0xc0 {
+        return vec![frame];
+    }
+
+    let frames_in_superframe = usize::from(marker & 0x7) + 1;
+    let bytes_per_framesize = usize::from((marker >> 3) & 0x3) + 1;
+    let index_size = 2 + frames_in_superframe * bytes_per_framesize;
+    if frame.len() < index_size || frame[frame.len() - index_size] != marker {
+        return vec![frame];
+    }
+
+    let index = &frame[frame.len() - index_size + 1..frame.len() - 1];
+    let mut frames = vec![];
+    let mut offset = 0;
+    for size in index.chunks(bytes_per_framesize) {
+        let size = size
+            .iter()
+            .rev()
+            .fold(0usize, |size, &b| (size << 8) | usize::from(b));
+        match frame.get(offset..offset + size) {
+            Some(f) => frames.push(f),
+            None => return vec![frame],
+        }
+        offset += size;
+    }
+
+    frames
+}
+
+impl PayloadFormat for Vp9Payload {
+    fn depacketize(&mut self, payload: &[u8]) -> anyhow::Result<()> {
+        let res = self.depacketize_packet(payload);
+
+        if res.is_err() {
+            self.incomplete = true;
+        }
+
+        res
+    }
+
+    fn packets_lost(&mut self) {
+        // The data of the layer frame being received cannot be used anymore.
+        if self.layers.last().is_some_and(|l| !l.complete) {
+            self.layers.pop();
+        }
+        self.incomplete = true;
+    }
+
+    fn finish_frame(&mut self) -> (Vec<u8>, bool) {
+        let mut complete = !std::mem::take(&mut self.incomplete);
+        let mut decodable = [false; MAX_SPATIAL_LAYERS];
+        let mut frames = vec![];
+
+        for layer in std::mem::take(&mut self.layers) {
+            let sid = usize::from(layer.sid);
+            let reference_ok =
+                !layer.inter_layer_dependency || sid.checked_sub(1).is_some_and(|s| decodable[s]);
+
+            if !layer.complete || !reference_ok {
+                log::debug!("dropping VP9 spatial layer {}", sid);
+                complete = false;
+                continue;
+            }
+
+            decodable[sid] = true;
+            frames.push(layer.data);
+        }
+
+        let key_picture = !std::mem::take(&mut self.inter_picture_predicted);
+        if !complete {
+            self.needs_key_picture = true;
+        } else if key_picture {
+            self.needs_key_picture = false;
+        }
+        if self.needs_key_picture {
+            complete = false;
+        }
+
+        // Layer frames may be superframes themselves, e.g. with a hidden frame.
+        let data = match frames.as_slice() {
+            [] => vec![],
+            [frame] => frame.clone(),
+            frames => {
+                let frames = frames
+                    .iter()
+                    .flat_map(|f| split_superframe(f))
+                    .collect::<Vec<_>>();
+                match pack_superframe(&frames) {
+                    Ok(data) => data,
+                    // Too many frames for a superframe, which a valid stream cannot have.
+                    Err(_) => {
+                        complete = false;
+                        vec![]
+                    }
+                }
+            }
+        };
+
+        (data, complete)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::rtp::tests::read_rtpdump;
+    use crate::utils::rtp::tests::rtp_packet;
+    use crate::utils::rtp::Frame;
+    use crate::utils::IvfIterator;
+
+    const STREAM: &[u8] = include_bytes!("../../codec/vp9/test_data/test-25fps.vp9");
+    const RTPDUMP: &[u8] = include_bytes!("test_data/test-25fps-3frames.vp9.rtpdump");
+
+    fn depacketize(packets: &[&[u8]]) -> Vec<Frame> {
+        let mut depacketizer = Vp9Depacketizer::new(Vp9Payload::new());
+        for packet in packets {
+            depacketizer.push(packet).unwrap();
+        }
+        depacketizer.flush();
+
+        std::iter::from_fn(|| depacketizer.next_frame()).collect()
+    }
+
+    #[test]
+    fn parse_descriptor() {
+        // Flexible mode with two references.
+        let (descriptor, data) =
+            Vp9PayloadDescriptor::parse(&[0xfc, 0x81, 0x23, 0x53, 0x03, 0x04, 0xaa]).unwrap();
+        assert_eq!(
+            descriptor,
+            Vp9PayloadDescriptor {
+                inter_picture_predicted: true,
+                flexible_mode: true,
+                start_of_frame: true,
+                end_of_frame: true,
+                picture_id: Some(0x123),
+                tid: 2,
+                switching_up_point: true,
+                sid: 1,
+                inter_layer_dependency: true,
+                p_diffs: vec![1, 2],
+                ..Default::default()
+            }
+        );
+        assert_eq!(data, [0xaa]);
+
+        // Scalability structure with two spatial layers and a picture group of one picture.
+        let (descriptor, data) = Vp9PayloadDescriptor::parse(&[
+            0x0a, 0x38, 0x00, 0xa0, 0x00, 0x78, 0x01, 0x40, 0x00, 0xf0, 0x01, 0x04, 0x01, 0xaa,
+        ])
+        .unwrap();
+        assert_eq!(
+            descriptor.scalability_structure,
+            Some(Vp9ScalabilityStructure {
+                num_spatial_layers: 2,
+                resolutions: vec![(160, 120), (320, 240)],
+                picture_group: Some(vec![Vp9PictureGroupEntry {
+                    tid: 0,
+                    switching_up_point: false,
+                    p_diffs: vec![1],
+                }]),
+            })
+        );
+        assert_eq!(data, [0xaa]);
+
+        // Non-flexible mode with truncated TL0PICIDX.
+        assert!(Vp9PayloadDescriptor::parse(&[0x28, 0x00]).is_err());
+    }
+
+    #[test]
+    fn stream() {
+        let mut depacketizer = Vp9Depacketizer::new(Vp9Payload::new());
+        for packet in read_rtpdump(RTPDUMP) {
+            depacketizer.push(packet).unwrap();
+        }
+        depacketizer.flush();
+        let frames = std::iter::from_fn(|| depacketizer.next_frame()).collect::<Vec<_>>();
+
+        assert_eq!(frames.len(), 3);
+        for (i, (frame, expected)) in frames.iter().zip(IvfIterator::new(STREAM)).enumerate() {
+            assert_eq!(frame.timestamp, 90000 + i as u32 * 3600);
+            assert!(frame.complete);
+            assert_eq!(frame.data, expected);
+        }
+
+        let ss = depacketizer.format().scalability_structure().unwrap();
+        assert_eq!(ss.resolutions, vec![(320, 240)]);
+    }
+
+    #[test]
+    fn lost_packet() {
+        // Drop the second packet of the key frame, which is then dropped entirely.
+        let mut packets = read_rtpdump(RTPDUMP);
+        packets.remove(1);
+
+        // The following frames cannot be decoded either.
+        let frames = depacketize(&packets);
+        assert_eq!(frames.len(), 2);
+        assert!(frames.iter().all(|f| !f.complete));
+    }
+
+    /// Returns the payload of a packet of a single-packet layer frame in flexible mode.
+    fn layer_packet(sid: u8, inter_layer_dependency: bool, data: &[u8]) -> Vec<u8> {
+        let l = (sid << 1) | u8::from(inter_layer_dependency);
+        [&[0x3c, l][..], data].concat()
+    }
+
+    #[test]
+    fn spatial_layers() {
+        let packets = [
+            rtp_packet(0, 0, false, &layer_packet(0, false, &[1, 2, 3])),
+            rtp_packet(1, 0, false, &layer_packet(1, true, &[4, 5])),
+            rtp_packet(2, 0, true, &layer_packet(2, true, &[6])),
+        ];
+        let packets = packets.iter().map(|p| p.as_slice()).collect::<Vec<_>>();
+
+        let frames = depacketize(&packets);
+        assert_eq!(frames.len(), 1);
+        assert!(frames[0].complete);
+        assert_eq!(
+            frames[0].data,
+            [1, 2, 3, 4, 5, 6, 0xc2, 3, 2, 1, 0xc2].to_vec()
+        );
+        assert_eq!(
+            split_superframe(&frames[0].data),
+            vec![&[1, 2, 3][..], &[4, 5][..], &[6][..]]
+        );
+    }
+
+    #[test]
+    fn missing_spatial_layer() {
+        // The second layer frame has lost its end, so it is dropped along with the third one,
+        // which depends on it. The fourth one only uses temporal prediction.
+        let packets = [
+            rtp_packet(0, 0, false, &layer_packet(0, false, &[1, 2, 3])),
+            rtp_packet(1, 0, false, &[0x38, 1 << 1 | 1, 4, 5]),
+            rtp_packet(2, 0, false, &layer_packet(2, true, &[6])),
+            rtp_packet(3, 0, true, &layer_packet(3, false, &[7])),
+        ];
+        let packets = packets.iter().map(|p| p.as_slice()).collect::<Vec<_>>();
+
+        let frames = depacketize(&packets);
+        assert_eq!(frames.len(), 1);
+        assert!(!frames[0].complete);
+        assert_eq!(
+            split_superframe(&frames[0].data),
+            vec![&[1, 2, 3][..], &[7][..]]
+        );
+    }
+}
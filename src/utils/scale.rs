@@ -0,0 +1,561 @@
+// Copyright 2023 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+//! Software scaling and cropping of decoded frames.
+//!
+//! [`scale`] resizes a rectangle of a planar or semi-planar frame into another frame of the same
+//! format, using one of the separable filters of [`Filter`]. Each plane is resampled on its own,
+//! so subsampled chroma planes, including those with an odd size, keep their position relative to
+//! the luma plane. When downscaling, the filters are widened to cover all the source samples and
+//! avoid aliasing.
+//!
+//! [`scale_readback`] reads a [`MappableHandle`] and scales it, which is convenient to produce
+//! previews and thumbnails of decoded frames.
+
+use std::f32::consts::PI;
+
+use anyhow::anyhow;
+
+use crate::codec::h264::parser::Point;
+use crate::codec::h264::parser::Rect;
+use crate::decoder::MappableHandle;
+use crate::utils::convert::chroma_size;
+use crate::utils::convert::layout_format;
+use crate::utils::convert::read;
+use crate::utils::convert::write;
+use crate::utils::convert::Packing;
+use crate::utils::convert::PixelFormat;
+use crate::utils::convert::Planar;
+use crate::DecodedFormat;
+use crate::FrameLayout;
+use crate::Resolution;
+
+/// Filters used to compute the scaled samples.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Filter {
+    /// Takes the closest source sample. Fast but blocky.
+    Nearest,
+    /// Linear interpolation between the two closest samples in each direction.
+    #[default]
+    Bilinear,
+    /// Cubic convolution over 4 samples in each direction (Keys, a = -0.5).
+    Bicubic,
+    /// Windowed sinc over 6 samples in each direction. Sharpest but slowest.
+    Lanczos3,
+}
+
+impl Filter {
+    /// Returns the distance from the center beyond which the kernel is zero.
+    fn support(self) -> f32 {
+        match self {
+            Self::Nearest => 0.5,
+            Self::Bilinear => 1.0,
+            Self::Bicubic => 2.0,
+            Self::Lanczos3 => 3.0,
+        }
+    }
+
+    /// Returns the value of the kernel at distance `x` from the center.
+    fn kernel(self, x: f32) -> f32 {
+        let x = x.abs();
+
+        match self {
+            Self::Nearest => 1.0,
+            Self::Bilinear => (1.0 - x).max(0.0),
+            Self::Bicubic => {
+                const A: f32 = -0.5;
+                if x < 1.0 {
+                    ((A + 2.0) * x - (A + 3.0)) * x * x + 1.0
+                } else if x < 2.0 {
+                    ((A * x - 5.0 * A) * x + 8.0 * A) * x - 4.0 * A
+                } else {
+                    0.0
+                }
+            }
+            Self::Lanczos3 => {
+                if x == 0.0 {
+                    1.0
+                } else if x < 3.0 {
+                    let pi_x = PI * x;
+                    3.0 * pi_x.sin() * (pi_x / 3.0).sin() / (pi_x * pi_x)
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+}
+
+/// Source samples contributing to a destination sample, and their weights.
+struct Contribution {
+    /// Index of the first source sample.
+    start: usize,
+    weights: Vec<f32>,
+}
+
+/// Returns the contributions of the `src_len` source samples to each of the `dst_len` destination
+/// samples, when the destination starts at `region_start` in the source and each of its samples
+/// covers `ratio` source samples.
+fn contributions(
+    filter: Filter,
+    src_len: usize,
+    region_start: f32,
+    ratio: f32,
+    dst_len: usize,
+) -> Vec<Contribution> {
+    // Widen the kernel when downscaling so that every source sample contributes.
+    let scale = ratio.max(1.0);
+    let support = filter.support() * scale;
+    let last = src_len as i64 - 1;
+
+    (0..dst_len)
+        .map(|i| {
+            // Position of the center of the destination sample in the source.
+            let center = region_start + (i as f32 + 0.5) * ratio - 0.5;
+
+            if filter == Filter::Nearest {
+                return Contribution {
+                    start: (center.round() as i64).clamp(0, last) as usize,
+                    weights: vec![1.0],
+                };
+            }
+
+            // Samples beyond the edges are replaced by the edge samples.
+            let first = (center - support).ceil() as i64;
+            let end = (center + support).floor() as i64;
+            let start = first.clamp(0, last);
+            let mut weights = vec![0.0; (end.clamp(0, last) - start + 1) as usize];
+
+            for j in first..=end {
+                let index = (j.clamp(0, last) - start) as usize;
+                weights[index] += filter.kernel((j as f32 - center) / scale);
+            }
+
+            let sum: f32 = weights.iter().sum();
+            weights.iter_mut().for_each(|w| *w /= sum);
+
+            Contribution {
+                start: start as usize,
+                weights,
+            }
+        })
+        .collect()
+}
+
+/// Resizes the `src_width`x`src_height` plane `src` into a `dst_width`x`dst_height` plane.
+/// `origin` is the position of the destination in the source, and `ratios` the number of source
+/// samples covered by each destination sample, horizontally and vertically.
+#[allow(clippy::too_many_arguments)]
+fn scale_plane(
+    src: &[u16],
+    src_width: usize,
+    src_height: usize,
+    origin: (f32, f32),
+    ratios: (f32, f32),
+    dst_width: usize,
+    dst_height: usize,
+    bit_depth: u8,
+    filter: Filter,
+) -> Vec<u16> {
+    let columns = contributions(filter, src_width, origin.0, ratios.0, dst_width);
+    let lines = contributions(filter, src_height, origin.1, ratios.1, dst_height);
+    let max = ((1u32 << bit_depth) - 1) as f32;
+
+    // Only filter the source lines that are used.
+    let first_line = lines.iter().map(|c| c.start).min().unwrap_or(0);
+    let end_line = lines
+        .iter()
+        .map(|c| c.start + c.weights.len())
+        .max()
+        .unwrap_or(0);
+
+    let horizontal = (first_line..end_line)
+        .map(|y| {
+            let line = &src[y * src_width..][..src_width];
+            columns
+                .iter()
+                .map(|c| {
+                    line[c.start..]
+                        .iter()
+                        .zip(&c.weights)
+                        .map(|(&sample, weight)| f32::from(sample) * weight)
+                        .sum::<f32>()
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    let mut dst = Vec::with_capacity(dst_width * dst_height);
+    for c in &lines {
+        for x in 0..dst_width {
+            let value: f32 = horizontal[c.start - first_line..]
+                .iter()
+                .zip(&c.weights)
+                .map(|(line, weight)| line[x] * weight)
+                .sum();
+            dst.push(value.round().clamp(0.0, max) as u16);
+        }
+    }
+
+    dst
+}
+
+/// Scales the `crop` rectangle of the frame in `src`, laid out according to `src_layout`, into
+/// `dst`, laid out according to `dst_layout`. The whole source frame is used if `crop` is `None`.
+///
+/// Both layouts must have the same planar or semi-planar format, and `crop` is given in luma
+/// samples.
+pub fn scale(
+    src: &[u8],
+    src_layout: &FrameLayout,
+    crop: Option<Rect<u32>>,
+    dst: &mut [u8],
+    dst_layout: &FrameLayout,
+    filter: Filter,
+) -> anyhow::Result<()> {
+    let format = layout_format(src_layout)?;
+    if layout_format(dst_layout)? != format {
+        return Err(anyhow!(
+            "cannot scale {} into {}",
+            src_layout.format.0,
+            dst_layout.format.0
+        ));
+    }
+
+    let info = format.info();
+    if !matches!(info.packing, Packing::Planar | Packing::SemiPlanar) {
+        return Err(anyhow!("{} frames cannot be scaled", src_layout.format.0));
+    }
+
+    let src_size = src_layout.size;
+    let crop = crop.unwrap_or(Rect {
+        min: Point { x: 0, y: 0 },
+        max: Point {
+            x: src_size.width,
+            y: src_size.height,
+        },
+    });
+    if crop.min.x >= crop.max.x
+        || crop.min.y >= crop.max.y
+        || crop.max.x > src_size.width
+        || crop.max.y > src_size.height
+    {
+        return Err(anyhow!(
+            "invalid crop rectangle {:?} for a {:?} frame",
+            crop,
+            src_size
+        ));
+    }
+
+    let dst_size = dst_layout.size;
+    if dst_size.width == 0 || dst_size.height == 0 {
+        return Err(anyhow!("cannot scale to an empty frame"));
+    }
+
+    let frame = read(
+        src,
+        src_layout,
+        format,
+        src_size.width as usize,
+        src_size.height as usize,
+    )?;
+    let (dst_width, dst_height) = (dst_size.width as usize, dst_size.height as usize);
+    let (chroma_width, chroma_height) = frame.chroma_size();
+    let (dst_chroma_width, dst_chroma_height) =
+        chroma_size(dst_width, dst_height, info.subsampling);
+
+    let ratios = (
+        (crop.max.x - crop.min.x) as f32 / dst_size.width as f32,
+        (crop.max.y - crop.min.y) as f32 / dst_size.height as f32,
+    );
+
+    let mut planes: [Vec<u16>; 3] = Default::default();
+    for (i, (src_plane, dst_plane)) in frame.planes.iter().zip(&mut planes).enumerate() {
+        let (src_width, src_height, dst_width, dst_height, (h_div, v_div)) = if i == 0 {
+            (frame.width, frame.height, dst_width, dst_height, (1, 1))
+        } else {
+            (
+                chroma_width,
+                chroma_height,
+                dst_chroma_width,
+                dst_chroma_height,
+                (
+                    if info.subsampling.0 { 2 } else { 1 },
+                    if info.subsampling.1 { 2 } else { 1 },
+                ),
+            )
+        };
+
+        // The last sample of odd-sized chroma planes is only half covered by the frame, so the
+        // ratios are derived from the luma plane.
+        let origin = (
+            crop.min.x as f32 / h_div as f32,
+            crop.min.y as f32 / v_div as f32,
+        );
+
+        *dst_plane = scale_plane(
+            src_plane,
+            src_width,
+            src_height,
+            origin,
+            ratios,
+            dst_width,
+            dst_height,
+            frame.bit_depth,
+            filter,
+        );
+    }
+
+    let scaled = Planar {
+        width: dst_width,
+        height: dst_height,
+        bit_depth: frame.bit_depth,
+        subsampling: frame.subsampling,
+        planes,
+    };
+
+    write(&scaled, dst, dst_layout, format)
+}
+
+/// Reads `handle`, which contains a `size` frame of `format`, and returns the `crop` rectangle of
+/// it scaled to `dst_size`, in the same format and without padding.
+pub fn scale_readback(
+    handle: &mut dyn MappableHandle,
+    format: DecodedFormat,
+    size: Resolution,
+    crop: Option<Rect<u32>>,
+    dst_size: Resolution,
+    filter: Filter,
+) -> anyhow::Result<Vec<u8>> {
+    let mut frame = vec![0u8; handle.image_size()];
+    handle.read(&mut frame)?;
+
+    let format = PixelFormat::from(format);
+    let mut scaled = vec![0u8; format.packed_size(dst_size)];
+    scale(
+        &frame,
+        &format.packed_layout(size),
+        crop,
+        &mut scaled,
+        &format.packed_layout(dst_size),
+        filter,
+    )?;
+
+    Ok(scaled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILTERS: [Filter; 4] = [
+        Filter::Nearest,
+        Filter::Bilinear,
+        Filter::Bicubic,
+        Filter::Lanczos3,
+    ];
+
+    fn rect(left: u32, top: u32, right: u32, bottom: u32) -> Rect<u32> {
+        Rect {
+            min: Point { x: left, y: top },
+            max: Point {
+                x: right,
+                y: bottom,
+            },
+        }
+    }
+
+    /// Scales the packed frame `src` of `format`.
+    fn scale_packed(
+        src: &[u8],
+        format: DecodedFormat,
+        size: (u32, u32),
+        crop: Option<Rect<u32>>,
+        dst_size: (u32, u32),
+        filter: Filter,
+    ) -> Vec<u8> {
+        let format = PixelFormat::from(format);
+        let dst_size = Resolution::from(dst_size);
+        let mut dst = vec![0u8; format.packed_size(dst_size)];
+        scale(
+            src,
+            &format.packed_layout(Resolution::from(size)),
+            crop,
+            &mut dst,
+            &format.packed_layout(dst_size),
+            filter,
+        )
+        .unwrap();
+
+        dst
+    }
+
+    #[test]
+    fn kernels() {
+        for filter in FILTERS {
+            assert_eq!(filter.kernel(0.0), 1.0, "{:?}", filter);
+        }
+
+        for filter in [Filter::Bilinear, Filter::Bicubic, Filter::Lanczos3] {
+            assert_eq!(filter.kernel(filter.support()), 0.0, "{:?}", filter);
+            assert!(filter.kernel(1.0).abs() < 1e-6, "{:?}", filter);
+        }
+    }
+
+    #[test]
+    fn identity() {
+        let size = (7, 5);
+        let format = DecodedFormat::I420;
+        let src = (0..PixelFormat::from(format).packed_size(Resolution::from(size)))
+            .map(|i| (i * 41 % 256) as u8)
+            .collect::<Vec<_>>();
+
+        // Scaling to the same size leaves the frame untouched.
+        for filter in FILTERS {
+            assert_eq!(
+                scale_packed(&src, format, size, None, size, filter),
+                src,
+                "{:?}",
+                filter
+            );
+        }
+    }
+
+    #[test]
+    fn crop() {
+        // 4x4 NV12 frame with 2x2 chroma.
+        #[rustfmt::skip]
+        let src = [
+            0, 1, 2, 3,
+            4, 5, 6, 7,
+            8, 9, 10, 11,
+            12, 13, 14, 15,
+            100, 200, 101, 201,
+            102, 202, 103, 203,
+        ];
+
+        for filter in FILTERS {
+            let dst = scale_packed(
+                &src,
+                DecodedFormat::NV12,
+                (4, 4),
+                Some(rect(2, 2, 4, 4)),
+                (2, 2),
+                filter,
+            );
+            assert_eq!(dst, [10, 11, 14, 15, 103, 203], "{:?}", filter);
+        }
+    }
+
+    #[test]
+    fn downscale() {
+        let format = DecodedFormat::I444;
+        // Columns alternating between 0 and 200.
+        let src = (0..4 * 4 * 3)
+            .map(|i| if i % 2 == 0 { 0 } else { 200 })
+            .collect::<Vec<_>>();
+
+        // Nearest picks one of the columns, the other filters average them, with some bias near
+        // the edges where the kernels are clamped.
+        let dst = scale_packed(&src, format, (4, 4), None, (2, 2), Filter::Nearest);
+        assert!(dst.iter().all(|&s| s == 0 || s == 200));
+        for filter in [Filter::Bilinear, Filter::Bicubic, Filter::Lanczos3] {
+            let dst = scale_packed(&src, format, (4, 4), None, (2, 2), filter);
+            assert!(
+                dst.iter().all(|&s| (60..=140).contains(&s)),
+                "{:?} {:?}",
+                filter,
+                dst
+            );
+        }
+    }
+
+    #[test]
+    fn upscale_16bit() {
+        let format = DecodedFormat::I010;
+        // 2x2 frame with a horizontal luma gradient and flat chroma.
+        let src = [0u16, 1000, 0, 1000, 512, 512]
+            .iter()
+            .flat_map(|s| s.to_le_bytes())
+            .collect::<Vec<_>>();
+
+        let dst = scale_packed(&src, format, (2, 2), None, (4, 4), Filter::Bilinear);
+        let samples = dst
+            .chunks(2)
+            .map(|s| u16::from_le_bytes([s[0], s[1]]))
+            .collect::<Vec<_>>();
+
+        // Each line of luma is interpolated, and chroma is 2x2.
+        assert_eq!(samples.len(), 16 + 2 * 4);
+        for line in samples[..16].chunks(4) {
+            assert_eq!(line, [0, 250, 750, 1000]);
+        }
+        assert!(samples[16..].iter().all(|&s| s == 512));
+
+        // Overshooting filters are clamped to the range of 10-bit samples.
+        let src = [0u16, 1023, 0, 1023, 512, 512]
+            .iter()
+            .flat_map(|s| s.to_le_bytes())
+            .collect::<Vec<_>>();
+        let dst = scale_packed(&src, format, (2, 2), None, (7, 2), Filter::Lanczos3);
+        assert!(dst
+            .chunks(2)
+            .all(|s| u16::from_le_bytes([s[0], s[1]]) <= 1023));
+    }
+
+    #[test]
+    fn odd_chroma_sizes() {
+        let format = DecodedFormat::I420;
+        let size = (5, 3);
+        let src = vec![128u8; PixelFormat::from(format).packed_size(Resolution::from(size))];
+
+        for filter in FILTERS {
+            for dst_size in [(3, 3), (9, 7), (1, 1)] {
+                let dst =
+                    scale_packed(&src, format, size, Some(rect(1, 1, 4, 3)), dst_size, filter);
+                assert!(dst.iter().all(|&s| s == 128), "{:?}", filter);
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_parameters() {
+        let size = Resolution::from((4, 4));
+        let format = PixelFormat::from(DecodedFormat::I420);
+        let layout = format.packed_layout(size);
+        let src = vec![0u8; format.packed_size(size)];
+        let mut dst = vec![0u8; format.packed_size(size)];
+
+        // Crop rectangles outside of the frame or empty.
+        for crop in [rect(0, 0, 5, 4), rect(2, 2, 2, 4)] {
+            assert!(scale(
+                &src,
+                &layout,
+                Some(crop),
+                &mut dst,
+                &layout,
+                Filter::Bilinear
+            )
+            .is_err());
+        }
+
+        // Different formats.
+        let nv12 = PixelFormat::from(DecodedFormat::NV12).packed_layout(size);
+        assert!(scale(&src, &layout, None, &mut dst, &nv12, Filter::Bilinear).is_err());
+
+        // Packed formats.
+        let y410 = PixelFormat::Y410.packed_layout(size);
+        let mut y410_frame = vec![0u8; PixelFormat::Y410.packed_size(size)];
+        let y410_src = y410_frame.clone();
+        assert!(scale(
+            &y410_src,
+            &y410,
+            None,
+            &mut y410_frame,
+            &y410,
+            Filter::Bilinear
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,548 @@
+// Copyright 2023 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+//! Reading and writing of YUV4MPEG2 (Y4M) files.
+//!
+//! A Y4M file starts with a header line giving the size, format and timing of the frames, followed
+//! by the frames themselves, each preceded by a `FRAME` line. Unlike raw YUV dumps, they can be
+//! played and compared without having to remember how they were produced.
+//!
+//! Frames are exchanged with the same layout as the one written by
+//! [`MappableHandle::read`](crate::decoder::MappableHandle::read), i.e.
+//! [`PixelFormat::packed_layout`].
+
+use std::fmt::Display;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Write;
+
+use anyhow::anyhow;
+use anyhow::Context;
+
+use crate::utils::convert::convert;
+use crate::utils::convert::Dither;
+use crate::utils::convert::PixelFormat;
+use crate::utils::probe::ProbeResult;
+use crate::DecodedFormat;
+use crate::Resolution;
+
+/// Magic string starting every Y4M file.
+const MAGIC: &str = "YUV4MPEG2";
+/// Tag starting the line preceding each frame.
+const FRAME_TAG: &str = "FRAME";
+/// Maximum length of the header and frame lines, to avoid reading whole invalid files in memory.
+const MAX_LINE_LENGTH: usize = 4096;
+/// Maximum width and height of the frames read from a file, so a header cannot make us allocate
+/// arbitrarily large frames. This is well above what hardware decoders support.
+const MAX_DIMENSION: u32 = 16384;
+
+/// How the lines of the frames are to be displayed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Interlacing {
+    #[default]
+    Progressive,
+    TopFieldFirst,
+    BottomFieldFirst,
+    /// The frames may be made of fields, whose order is not known.
+    Mixed,
+}
+
+impl Interlacing {
+    fn tag(self) -> char {
+        match self {
+            Interlacing::Progressive => 'p',
+            Interlacing::TopFieldFirst => 't',
+            Interlacing::BottomFieldFirst => 'b',
+            Interlacing::Mixed => 'm',
+        }
+    }
+
+    fn from_tag(tag: &str) -> anyhow::Result<Self> {
+        match tag {
+            // Unknown interlacing is most commonly progressive.
+            "p" | "?" => Ok(Interlacing::Progressive),
+            "t" => Ok(Interlacing::TopFieldFirst),
+            "b" => Ok(Interlacing::BottomFieldFirst),
+            "m" => Ok(Interlacing::Mixed),
+            _ => Err(anyhow!("invalid interlacing mode {:?}", tag)),
+        }
+    }
+}
+
+/// Returns the value of the `C` parameter for frames of `format`.
+fn colorspace(format: DecodedFormat) -> &'static str {
+    match format {
+        DecodedFormat::I420 | DecodedFormat::NV12 => "420jpeg",
+        DecodedFormat::I422 => "422",
+        DecodedFormat::I444 => "444",
+        DecodedFormat::I010 => "420p10",
+        DecodedFormat::I012 => "420p12",
+        DecodedFormat::I210 => "422p10",
+        DecodedFormat::I212 => "422p12",
+        DecodedFormat::I410 => "444p10",
+        DecodedFormat::I412 => "444p12",
+    }
+}
+
+/// Returns the format of the frames described by the `C` parameter `colorspace`.
+fn format_from_colorspace(colorspace: &str) -> anyhow::Result<DecodedFormat> {
+    // The 4:2:0 variants only differ by their chroma siting.
+    match colorspace {
+        "420" | "420jpeg" | "420mpeg2" | "420paldv" => Ok(DecodedFormat::I420),
+        "422" => Ok(DecodedFormat::I422),
+        "444" => Ok(DecodedFormat::I444),
+        "420p10" => Ok(DecodedFormat::I010),
+        "420p12" => Ok(DecodedFormat::I012),
+        "422p10" => Ok(DecodedFormat::I210),
+        "422p12" => Ok(DecodedFormat::I212),
+        "444p10" => Ok(DecodedFormat::I410),
+        "444p12" => Ok(DecodedFormat::I412),
+        _ => Err(anyhow!("unsupported colorspace {:?}", colorspace)),
+    }
+}
+
+/// Parses a `num:den` ratio, `0:0` meaning unknown.
+fn parse_ratio(value: &str) -> anyhow::Result<Option<(u32, u32)>> {
+    let (num, den) = value
+        .split_once(':')
+        .ok_or_else(|| anyhow!("invalid ratio {:?}", value))?;
+
+    match (num.parse()?, den.parse()?) {
+        (0, _) | (_, 0) => Ok(None),
+        ratio => Ok(Some(ratio)),
+    }
+}
+
+/// Stream parameters stored in the header of a Y4M file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Y4mHeader {
+    /// Format of the frames passed to [`Y4mWriter::write_frame`] or returned by
+    /// [`Y4mReader::read_frame`].
+    ///
+    /// Y4M only supports planar formats, so NV12 frames are written as I420 and read back as
+    /// such.
+    pub format: DecodedFormat,
+    /// Size of the frames, without any padding.
+    pub size: Resolution,
+    /// Frame rate as a (numerator, denominator) pair, if known.
+    pub frame_rate: Option<(u32, u32)>,
+    /// Shape of the samples as a (width, height) pair, if known.
+    pub sample_aspect_ratio: Option<(u32, u32)>,
+    pub interlacing: Interlacing,
+}
+
+impl Y4mHeader {
+    /// Returns a header for progressive frames of `format` and `size`, without timing or aspect
+    /// ratio information.
+    pub fn new(format: DecodedFormat, size: Resolution) -> Self {
+        Self {
+            format,
+            size,
+            frame_rate: None,
+            sample_aspect_ratio: None,
+            interlacing: Default::default(),
+        }
+    }
+
+    /// Returns a header for the stream described by `probe`, decoded into `format`.
+    pub fn from_probe(probe: &ProbeResult, format: DecodedFormat) -> Self {
+        Self {
+            format,
+            size: probe.display_resolution,
+            frame_rate: probe.frame_rate,
+            sample_aspect_ratio: probe.sample_aspect_ratio,
+            interlacing: if probe.interlaced {
+                Interlacing::Mixed
+            } else {
+                Interlacing::Progressive
+            },
+        }
+    }
+
+    /// Returns the size of the frames passed to [`Y4mWriter::write_frame`] or returned by
+    /// [`Y4mReader::read_frame`].
+    pub fn frame_size(&self) -> usize {
+        PixelFormat::from(self.format).packed_size(self.size)
+    }
+
+    /// Parses the header line `line`, without its terminating newline.
+    fn parse(line: &str) -> anyhow::Result<Self> {
+        let mut params = line.split(' ');
+        if params.next() != Some(MAGIC) {
+            return Err(anyhow!("not a Y4M file"));
+        }
+
+        let (mut width, mut height) = (None, None);
+        let mut header = Self::new(DecodedFormat::I420, Resolution::default());
+
+        for param in params.filter(|param| !param.is_empty()) {
+            // Parameters are made of a single letter followed by their value.
+            let (tag, value) = param
+                .split_at_checked(1)
+                .ok_or_else(|| anyhow!("invalid header parameter {:?}", param))?;
+            match tag {
+                "W" => width = Some(value.parse().context("invalid width")?),
+                "H" => height = Some(value.parse().context("invalid height")?),
+                "F" => header.frame_rate = parse_ratio(value)?,
+                "A" => header.sample_aspect_ratio = parse_ratio(value)?,
+                "I" => header.interlacing = Interlacing::from_tag(value)?,
+                "C" => header.format = format_from_colorspace(value)?,
+                // Application-specific parameters.
+                "X" => (),
+                _ => return Err(anyhow!("invalid header parameter {:?}", param)),
+            }
+        }
+
+        match (width, height) {
+            (Some(width), Some(height))
+                if (1..=MAX_DIMENSION).contains(&width)
+                    && (1..=MAX_DIMENSION).contains(&height) =>
+            {
+                header.size = Resolution { width, height };
+                Ok(header)
+            }
+            _ => Err(anyhow!("missing or invalid frame size")),
+        }
+    }
+}
+
+impl Display for Y4mHeader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (fps_num, fps_den) = self.frame_rate.unwrap_or((0, 0));
+        let (sar_num, sar_den) = self.sample_aspect_ratio.unwrap_or((0, 0));
+
+        write!(
+            f,
+            "{} W{} H{} F{}:{} I{} A{}:{} C{}",
+            MAGIC,
+            self.size.width,
+            self.size.height,
+            fps_num,
+            fps_den,
+            self.interlacing.tag(),
+            sar_num,
+            sar_den,
+            colorspace(self.format)
+        )
+    }
+}
+
+/// Writes frames into a Y4M file.
+pub struct Y4mWriter<W: Write> {
+    writer: W,
+    header: Y4mHeader,
+    /// Frames converted to a planar format before being written.
+    converted: Vec<u8>,
+}
+
+impl<W: Write> Y4mWriter<W> {
+    /// Writes the header of the file into `writer`, and returns a writer for its frames.
+    pub fn new(mut writer: W, header: Y4mHeader) -> anyhow::Result<Self> {
+        writeln!(writer, "{}", header)?;
+
+        Ok(Self {
+            writer,
+            header,
+            converted: Vec::new(),
+        })
+    }
+
+    /// Returns the header of the file.
+    pub fn header(&self) -> &Y4mHeader {
+        &self.header
+    }
+
+    /// Writes `frame`, which must be laid out as per [`PixelFormat::packed_layout`] for the format
+    /// and size of the header.
+    pub fn write_frame(&mut self, frame: &[u8]) -> anyhow::Result<()> {
+        let frame_size = self.header.frame_size();
+        let frame = frame
+            .get(..frame_size)
+            .ok_or_else(|| anyhow!("frame is {} bytes, expected {}", frame.len(), frame_size))?;
+
+        let frame = match self.header.format {
+            DecodedFormat::NV12 => {
+                let size = self.header.size;
+                let planar = PixelFormat::from(DecodedFormat::I420);
+                self.converted.resize(planar.packed_size(size), 0);
+                convert(
+                    frame,
+                    &PixelFormat::from(DecodedFormat::NV12).packed_layout(size),
+                    &mut self.converted,
+                    &planar.packed_layout(size),
+                    Dither::None,
+                )?;
+                &self.converted[..]
+            }
+            _ => frame,
+        };
+
+        writeln!(self.writer, "{}", FRAME_TAG)?;
+        self.writer.write_all(frame)?;
+
+        Ok(())
+    }
+
+    /// Flushes the file and returns the underlying writer.
+    pub fn into_inner(mut self) -> anyhow::Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Reads frames from a Y4M file.
+///
+/// The reader is accessed in small chunks, so it is best to use a
+/// [`BufReader`](std::io::BufReader).
+pub struct Y4mReader<R: Read> {
+    reader: R,
+    header: Y4mHeader,
+}
+
+impl<R: Read> Y4mReader<R> {
+    /// Reads the header of the Y4M file in `reader`, and returns a reader for its frames.
+    pub fn new(mut reader: R) -> anyhow::Result<Self> {
+        let line = read_line(&mut reader)?.ok_or_else(|| anyhow!("empty Y4M file"))?;
+        let header = Y4mHeader::parse(&line)?;
+
+        Ok(Self { reader, header })
+    }
+
+    /// Returns the header of the file.
+    pub fn header(&self) -> &Y4mHeader {
+        &self.header
+    }
+
+    /// Reads the next frame into `frame`, which is resized to [`Y4mHeader::frame_size`].
+    ///
+    /// Returns `false` if the end of the file has been reached.
+    pub fn read_frame_into(&mut self, frame: &mut Vec<u8>) -> anyhow::Result<bool> {
+        let line = match read_line(&mut self.reader)? {
+            Some(line) => line,
+            None => return Ok(false),
+        };
+
+        // Per-frame parameters may follow the tag, but they cannot change the frame size.
+        if line.split(' ').next() != Some(FRAME_TAG) {
+            return Err(anyhow!("invalid frame header {:?}", line));
+        }
+
+        // Only grow `frame` as data comes in, so truncated files do not allocate a whole frame.
+        let frame_size = self.header.frame_size();
+        frame.clear();
+        (&mut self.reader)
+            .take(frame_size as u64)
+            .read_to_end(frame)?;
+        if frame.len() != frame_size {
+            return Err(anyhow!(
+                "truncated Y4M frame: got {} bytes, expected {}",
+                frame.len(),
+                frame_size
+            ));
+        }
+
+        Ok(true)
+    }
+
+    /// Reads the next frame, or returns `None` if the end of the file has been reached.
+    pub fn read_frame(&mut self) -> anyhow::Result<Option<Vec<u8>>> {
+        let mut frame = Vec::new();
+        Ok(self.read_frame_into(&mut frame)?.then_some(frame))
+    }
+}
+
+impl<R: Read> Iterator for Y4mReader<R> {
+    type Item = anyhow::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_frame().transpose()
+    }
+}
+
+/// Reads a line from `reader`, without its terminating newline. Returns `None` if `reader` is at
+/// its end.
+fn read_line<R: Read>(reader: &mut R) -> anyhow::Result<Option<String>> {
+    let mut line = Vec::new();
+    let mut byte = [0u8];
+
+    loop {
+        match reader.read_exact(&mut byte) {
+            Ok(()) => (),
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof && line.is_empty() => return Ok(None),
+            Err(e) => return Err(e).context("truncated Y4M line"),
+        }
+
+        if byte[0] == b'\n' {
+            break;
+        }
+        if line.len() == MAX_LINE_LENGTH {
+            return Err(anyhow!("Y4M line is too long"));
+        }
+        line.push(byte[0]);
+    }
+
+    Ok(Some(String::from_utf8(line)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::utils::probe::probe;
+
+    /// Returns a frame of `format` and `size` filled with a different value in each plane.
+    fn test_frame(format: DecodedFormat, size: Resolution) -> Vec<u8> {
+        let layout = PixelFormat::from(format).packed_layout(size);
+        let mut frame = vec![0; PixelFormat::from(format).packed_size(size)];
+        for (i, plane) in layout.planes.iter().enumerate() {
+            frame[plane.offset..].fill(i as u8 + 1);
+        }
+
+        frame
+    }
+
+    #[test]
+    fn header() {
+        let header = Y4mHeader {
+            format: DecodedFormat::I210,
+            size: Resolution::from((320, 240)),
+            frame_rate: Some((30000, 1001)),
+            sample_aspect_ratio: Some((16, 11)),
+            interlacing: Interlacing::TopFieldFirst,
+        };
+        let line = header.to_string();
+        assert_eq!(line, "YUV4MPEG2 W320 H240 F30000:1001 It A16:11 C422p10");
+        assert_eq!(Y4mHeader::parse(&line).unwrap(), header);
+
+        let header = Y4mHeader::new(DecodedFormat::NV12, Resolution::from((64, 48)));
+        assert_eq!(
+            header.to_string(),
+            "YUV4MPEG2 W64 H48 F0:0 Ip A0:0 C420jpeg"
+        );
+
+        // Optional parameters can be omitted.
+        assert_eq!(
+            Y4mHeader::parse("YUV4MPEG2 W64 H48 I? XYSCSS=420JPEG").unwrap(),
+            Y4mHeader::new(DecodedFormat::I420, Resolution::from((64, 48)))
+        );
+        assert_eq!(
+            Y4mHeader::parse("YUV4MPEG2 W64 H48 C420mpeg2")
+                .unwrap()
+                .format,
+            DecodedFormat::I420
+        );
+
+        assert!(Y4mHeader::parse("YUV4MPEG W64 H48").is_err());
+        assert!(Y4mHeader::parse("YUV4MPEG2 W64").is_err());
+        assert!(Y4mHeader::parse("YUV4MPEG2 W64 H0").is_err());
+        assert!(Y4mHeader::parse("YUV4MPEG2 W64 H48 Cmono").is_err());
+        assert!(Y4mHeader::parse("YUV4MPEG2 W64 H48 F25").is_err());
+        assert!(Y4mHeader::parse("YUV4MPEG2 W64 H48 é").is_err());
+    }
+
+    #[test]
+    fn from_probe() {
+        let result = probe(include_bytes!(
+            "../codec/h264/test_data/test-25fps-interlaced.h264"
+        ))
+        .unwrap();
+        let header = Y4mHeader::from_probe(&result, DecodedFormat::I420);
+
+        assert_eq!(header.size, Resolution::from((320, 240)));
+        assert_eq!(header.frame_rate, Some((25, 1)));
+        assert_eq!(header.interlacing, Interlacing::Mixed);
+        assert_eq!(header.sample_aspect_ratio, None);
+
+        // The sample aspect ratio is recorded when the stream signals one.
+        let result = ProbeResult {
+            sample_aspect_ratio: Some((16, 11)),
+            ..result
+        };
+        let header = Y4mHeader::from_probe(&result, DecodedFormat::I420);
+        assert_eq!(header.sample_aspect_ratio, Some((16, 11)));
+    }
+
+    #[test]
+    fn write_and_read() {
+        let size = Resolution::from((34, 18));
+        for format in [
+            DecodedFormat::I420,
+            DecodedFormat::I422,
+            DecodedFormat::I444,
+            DecodedFormat::I010,
+            DecodedFormat::I212,
+            DecodedFormat::I412,
+        ] {
+            let frames = [
+                test_frame(format, size),
+                vec![0x7; PixelFormat::from(format).packed_size(size)],
+            ];
+            let mut header = Y4mHeader::new(format, size);
+            header.frame_rate = Some((25, 1));
+
+            let mut writer = Y4mWriter::new(Vec::new(), header.clone()).unwrap();
+            for frame in &frames {
+                writer.write_frame(frame).unwrap();
+            }
+            let file = writer.into_inner().unwrap();
+
+            let reader = Y4mReader::new(Cursor::new(file)).unwrap();
+            assert_eq!(reader.header(), &header);
+            let read_frames = reader.collect::<anyhow::Result<Vec<_>>>().unwrap();
+            assert_eq!(read_frames, frames, "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn write_nv12() {
+        let size = Resolution::from((16, 8));
+        let mut writer =
+            Y4mWriter::new(Vec::new(), Y4mHeader::new(DecodedFormat::NV12, size)).unwrap();
+
+        // Luma, then interleaved U and V samples.
+        let mut frame = vec![0x10; 16 * 8];
+        frame.extend([0x20, 0x30].repeat(8 * 4));
+        writer.write_frame(&frame).unwrap();
+        assert!(writer.write_frame(&frame[1..]).is_err());
+
+        let mut reader = Y4mReader::new(Cursor::new(writer.into_inner().unwrap())).unwrap();
+        assert_eq!(reader.header().format, DecodedFormat::I420);
+
+        let mut expected = vec![0x10; 16 * 8];
+        expected.extend([0x20; 8 * 4]);
+        expected.extend([0x30; 8 * 4]);
+        assert_eq!(reader.read_frame().unwrap(), Some(expected));
+        assert_eq!(reader.read_frame().unwrap(), None);
+    }
+
+    #[test]
+    fn read_errors() {
+        let mut file = b"YUV4MPEG2 W2 H2 C444\nFRAME Ixyz\n".to_vec();
+        file.extend([1; 12]);
+        file.extend(b"FRAME\n");
+        file.extend([2; 11]);
+
+        let mut reader = Y4mReader::new(Cursor::new(&file)).unwrap();
+        // Per-frame parameters are ignored.
+        assert_eq!(reader.read_frame().unwrap(), Some(vec![1; 12]));
+        assert!(reader.read_frame().is_err());
+
+        let file = b"YUV4MPEG2 W2 H2 C444\nFRAMES\n";
+        let mut reader = Y4mReader::new(Cursor::new(&file)).unwrap();
+        assert!(reader.read_frame().is_err());
+
+        assert!(Y4mReader::new(Cursor::new(b"")).is_err());
+        assert!(Y4mReader::new(Cursor::new(b"YUV4MPEG2 W2 H2")).is_err());
+        assert!(Y4mReader::new(Cursor::new(b"YUV4MPEG2 W4294967295 H2\n")).is_err());
+        assert!(Y4mReader::new(Cursor::new(b"YUV4MPEG2 W2 H16385\n")).is_err());
+
+        // The largest frames only fail once their data runs out.
+        let file = b"YUV4MPEG2 W16384 H16384 C444p12\nFRAME\n\0\0";
+        let mut reader = Y4mReader::new(Cursor::new(&file)).unwrap();
+        assert_eq!(reader.header().frame_size(), 16384 * 16384 * 3 * 2);
+        assert!(reader.read_frame().is_err());
+        assert!(Y4mReader::new(Cursor::new(vec![b'Y'; 2 * MAX_LINE_LENGTH])).is_err());
+    }
+}